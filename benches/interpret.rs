@@ -0,0 +1,89 @@
+//! Run time for function calls and object property reads.
+//!
+//! Calls share the function body instead of cloning it, so the first pair
+//! of lines compares 10k calls of a 1000-statement function with what
+//! deep-cloning its body 10k times alone would cost. The second pair puts
+//! property reads on a small object next to building and reading the same
+//! object natively.
+//!
+//!     cargo bench --features stdlib --bench interpret
+
+use infra::core::{ObjectMap, Stmt, Value};
+use infra::frontend::{Lexer, Parser};
+use infra::Engine;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+const CALLS: usize = 10_000;
+const READS: usize = 200_000;
+const OBJECTS: usize = 1_000_000;
+
+fn main() {
+    println!("best of {} runs", RUNS);
+
+    let code = large_function(CALLS);
+    let tokens = Lexer::new(&code).tokenize().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    let Some(Stmt::Function { body, .. }) = program.statements.first() else {
+        panic!("expected the function first");
+    };
+    let clones = best_of(|| {
+        for _ in 0..CALLS {
+            black_box((**body).clone());
+        }
+    });
+    let calls = best_of(|| run(&code));
+    report("10k calls, 1000 statements", calls);
+    report("10k deep clones of the body", clones);
+
+    let code = format!(
+        "let total = 0\n\
+         let point = {{x: 1, y: 2, z: 3, label: \"p\"}}\n\
+         for i in range(0, {}):\n    total = total + point.x + point.y + point.z\n",
+        READS
+    );
+    let fields: Vec<(String, Value)> = ["x", "y", "z", "label"]
+        .iter()
+        .map(|key| (key.to_string(), Value::Number(1.0)))
+        .collect();
+    let reads = best_of(|| run(&code));
+    let objects = best_of(|| {
+        for _ in 0..OBJECTS {
+            let map: ObjectMap = black_box(fields.clone()).into_iter().collect();
+            black_box(map.get("z"));
+        }
+    });
+    report("600k property reads", reads);
+    report("1M four-field objects built", objects);
+}
+
+/// A function of a thousand statements, called `calls` times
+fn large_function(calls: usize) -> String {
+    let statements: String = (0..1000)
+        .map(|i| format!("    let v{} = n + {}\n", i, i))
+        .collect();
+    format!(
+        "function big(n):\n{{\n{}    return v999\n}}\n\nfor i in range(0, {}):\n    big(i)\n",
+        statements, calls
+    )
+}
+
+fn run(code: &str) {
+    Engine::builder().build().execute(code).unwrap();
+}
+
+fn best_of<T>(mut run: impl FnMut() -> T) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, time: Duration) {
+    println!("{:<30} {:>8.2} ms", name, time.as_secs_f64() * 1000.0);
+}
@@ -0,0 +1,83 @@
+//! Parse time for a large generated program.
+//!
+//! Lexing borrows identifiers and escape-free strings from the source; the
+//! owned run goes through `tokenize_owned`, which allocates every payload
+//! the way the lexer used to, so the two lines show what borrowing saves.
+//!
+//!     cargo bench --features parsing
+//!     cargo bench --features parsing -- 20000   # lines to generate
+
+use infra::frontend::{Lexer, Parser};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const DEFAULT_LINES: usize = 5_000;
+const RUNS: usize = 20;
+
+fn main() {
+    let lines = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_LINES);
+    let source = generate(lines);
+    println!(
+        "{} lines, {} bytes, best of {} runs",
+        lines,
+        source.len(),
+        RUNS
+    );
+
+    let borrowed = best_of(|| {
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    });
+    let owned = best_of(|| {
+        let tokens = Lexer::new(&source).tokenize_owned().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    });
+
+    report("lex + parse, borrowed tokens", borrowed, lines);
+    report("lex + parse, owned tokens", owned, lines);
+}
+
+/// A program mixing the statements real files are made of
+fn generate(lines: usize) -> String {
+    let mut source = String::new();
+    // Eleven lines a function
+    for i in (0..lines).step_by(11) {
+        source.push_str(&format!(
+            "function step_{i}(count, label):\n\
+             {{\n\
+             \x20   let total = count * {i} + 1\n\
+             \x20   if total > 100 && label != \"skip\": {{\n\
+             \x20       print(\"step ${{label}}: \" + total)\n\
+             \x20   }}\n\
+             \x20   return [total, \"tab\\tseparated\", {{name: label, index: {i}}}]\n\
+             }}\n\
+             let result_{i} = step_{i}({i}, \"item\")\n\
+             # keep the comment and blank lines a real file has\n\
+             \n"
+        ));
+    }
+    source
+}
+
+fn best_of<T>(mut run: impl FnMut() -> T) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, time: Duration, lines: usize) {
+    println!(
+        "{:<30} {:>8.2} ms  {:>6.0} lines/ms",
+        name,
+        time.as_secs_f64() * 1000.0,
+        lines as f64 / (time.as_secs_f64() * 1000.0)
+    );
+}
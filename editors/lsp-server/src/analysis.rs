@@ -0,0 +1,1005 @@
+//! Background document analysis with a time budget.
+//!
+//! Symbols, semantic tokens and lexical diagnostics come from a line scanner
+//! that runs on a worker thread, a chunk of lines at a time. Each chunk is
+//! merged into the shared result straight away, so request handlers never
+//! scan the document themselves: they read whatever has been analysed so far,
+//! waiting a short while at most. Documents over the size cap are scanned for
+//! diagnostics only. Once the whole text is scanned, the interpreter's own
+//! parser adds the first syntax error, if any (see `syntax`), or else the
+//! document's scopes (see `scopes`).
+//!
+//! Like `import_paths`, this works on plain text so it keeps working while
+//! the document does not parse. Columns are UTF-16 code units, as in LSP.
+
+use crate::literal_shapes;
+use crate::scopes::SymbolTable;
+use crate::syntax;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Lines scanned between two merges into the shared result
+const CHUNK_LINES: usize = 2000;
+
+pub const KEYWORDS: &[&str] = &[
+    "let", "if", "else", "while", "for", "in", "range", "true", "false", "null", "print",
+    "return", "break", "continue", "function", "def", "try", "catch", "import", "export",
+    "from", "as", "async", "await", "class", "extends", "this", "super", "init", "new",
+    "number", "string", "boolean",
+];
+
+/// Keywords only at the start of a statement; elsewhere they are names
+pub const STATEMENT_KEYWORDS: &[&str] = &["enum", "match"];
+
+#[derive(Debug, Clone)]
+pub struct AnalysisConfig {
+    /// How long handlers may keep waiting on a pass in progress; after that
+    /// they answer from the partial result at once
+    pub time_budget: Duration,
+    /// The longest a single request waits for a pass to complete
+    pub request_wait: Duration,
+    /// Documents larger than this many bytes only get diagnostics
+    pub max_file_size: usize,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            time_budget: Duration::from_millis(500),
+            request_wait: Duration::from_millis(100),
+            max_file_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Full,
+    /// Over the size cap: no symbols or tokens are collected
+    DiagnosticsOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Variable,
+    Enum,
+}
+
+/// A declaration found by the scanner
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    /// The declaring line, trimmed
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Function,
+    Variable,
+    Class,
+}
+
+impl TokenKind {
+    /// Token type names in the order of their legend index
+    pub const LEGEND: &'static [&'static str] = &[
+        "keyword", "string", "number", "comment", "function", "variable", "class",
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub line: u32,
+    pub start: u32,
+    pub length: u32,
+    pub kind: TokenKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    pub message: String,
+    pub warning: bool,
+    /// Shown as related information, e.g. how to fix a syntax error
+    pub hint: Option<String>,
+}
+
+impl Symbol {
+    /// Members of an enum, read back from its declaring line
+    /// `enum Status: pending, active, closed`
+    pub fn enum_members(&self) -> Vec<&str> {
+        if self.kind != SymbolKind::Enum {
+            return Vec::new();
+        }
+        self.detail
+            .split_once(':')
+            .map(|(_, members)| {
+                members
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|member| !member.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Everything known about one version of a document
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub version: i32,
+    pub mode: Mode,
+    pub symbols: Vec<Symbol>,
+    /// Sorted by position
+    pub tokens: Vec<Token>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Definitions and references, when the document parses
+    pub scopes: Option<SymbolTable>,
+    pub lines_done: usize,
+    pub complete: bool,
+}
+
+impl Analysis {
+    /// The first declaration of `name`
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// The enum and its members when `line_prefix` ends in `Name.` and
+    /// maybe the start of a member, as it does while one is being typed
+    pub fn enum_at_dot(&self, line_prefix: &str) -> Option<(&Symbol, Vec<&str>)> {
+        let before_member =
+            line_prefix.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        let before_dot = before_member.strip_suffix('.')?;
+        let name_start = before_dot
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let symbol = self.symbol(&before_dot[name_start..])?;
+        (symbol.kind == SymbolKind::Enum).then(|| (symbol, symbol.enum_members()))
+    }
+
+    /// Tokens on lines `start_line..=end_line`
+    pub fn tokens_in(&self, start_line: u32, end_line: u32) -> &[Token] {
+        let from = self.tokens.partition_point(|t| t.line < start_line);
+        let to = self.tokens.partition_point(|t| t.line <= end_line);
+        &self.tokens[from..to.max(from)]
+    }
+}
+
+/// Sent once a pass over the latest version of a document finishes
+#[derive(Debug, Clone)]
+pub struct Completed {
+    pub uri: String,
+    pub version: i32,
+    pub mode: Mode,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// What `Analyzer::submit` has to tell the client
+#[derive(Debug, Default)]
+pub struct Submitted {
+    /// Set the first time a document goes over the size cap
+    pub warning: Option<String>,
+}
+
+struct Entry {
+    started: Instant,
+    analysis: Analysis,
+}
+
+#[derive(Default)]
+struct State {
+    documents: HashMap<String, Entry>,
+    /// Documents the size cap warning was already sent for
+    warned: HashSet<String>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct Job {
+    uri: String,
+    version: i32,
+    text: Arc<str>,
+    mode: Mode,
+}
+
+pub struct Analyzer {
+    config: AnalysisConfig,
+    shared: Arc<Shared>,
+    jobs: Sender<Job>,
+}
+
+impl Analyzer {
+    /// Start the worker thread. `on_complete` runs on it after each
+    /// finished pass that was not superseded by a newer version.
+    pub fn new(
+        config: AnalysisConfig,
+        on_complete: impl Fn(Completed) + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+        let (jobs, receiver) = mpsc::channel::<Job>();
+
+        let worker = Arc::clone(&shared);
+        thread::spawn(move || {
+            for job in receiver {
+                if let Some(completed) = run_job(&worker, job) {
+                    on_complete(completed);
+                }
+            }
+        });
+
+        Self {
+            config,
+            shared,
+            jobs,
+        }
+    }
+
+    pub fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    /// Queue a new version of a document; the previous result stays
+    /// readable until the first chunk of the new one is merged
+    pub fn submit(&self, uri: &str, version: i32, text: Arc<str>) -> Submitted {
+        let mut submitted = Submitted::default();
+        let mode = if text.len() > self.config.max_file_size {
+            Mode::DiagnosticsOnly
+        } else {
+            Mode::Full
+        };
+
+        {
+            let mut state = self.shared.lock();
+            if mode == Mode::DiagnosticsOnly && state.warned.insert(uri.to_string()) {
+                submitted.warning = Some(format!(
+                    "{} is {} bytes, over the {} byte limit: only diagnostics are available for it",
+                    file_name(uri),
+                    text.len(),
+                    self.config.max_file_size
+                ));
+            }
+            let entry = state.documents.entry(uri.to_string()).or_insert(Entry {
+                started: Instant::now(),
+                analysis: Analysis::default(),
+            });
+            entry.started = Instant::now();
+            entry.analysis.version = version;
+            entry.analysis.mode = mode;
+            entry.analysis.complete = false;
+        }
+
+        let _ = self.jobs.send(Job {
+            uri: uri.to_string(),
+            version,
+            text,
+            mode,
+        });
+        submitted
+    }
+
+    pub fn close(&self, uri: &str) {
+        let mut state = self.shared.lock();
+        state.documents.remove(uri);
+        state.warned.remove(uri);
+    }
+
+    /// Run `read` on the current result for `uri`. While the pass is within
+    /// its time budget this waits up to `request_wait` for it to complete;
+    /// otherwise it answers from the partial result immediately.
+    pub fn read<R>(&self, uri: &str, read: impl FnOnce(&Analysis) -> R) -> Option<R> {
+        let asked = Instant::now();
+        let mut state = self.shared.lock();
+
+        loop {
+            let entry = state.documents.get(uri)?;
+            if entry.analysis.complete {
+                break;
+            }
+            let budget_left = self
+                .config
+                .time_budget
+                .saturating_sub(entry.started.elapsed());
+            let wait = budget_left
+                .min(self.config.request_wait)
+                .saturating_sub(asked.elapsed());
+            if wait.is_zero() {
+                break;
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, wait)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+
+        state.documents.get(uri).map(|entry| read(&entry.analysis))
+    }
+}
+
+/// Analyse all of `text` on the calling thread
+pub fn scan(text: &str) -> Analysis {
+    let mut scanner = Scanner::new(Mode::Full);
+    let mut analysis = Analysis::default();
+    for (number, line) in text.lines().enumerate() {
+        scanner.scan_line(number as u32, line, &mut analysis);
+    }
+    scanner.finish(&mut analysis);
+    let unbalanced = !analysis.diagnostics.is_empty();
+    parse(text, Mode::Full, unbalanced, &mut analysis);
+    analysis.diagnostics.extend(shape_diagnostics(text));
+    analysis.lines_done = scanner.lines;
+    analysis.complete = true;
+    analysis
+}
+
+/// Literals that do not match their annotation; a declaration can span
+/// chunks, so these are found once the whole text has been scanned
+fn shape_diagnostics(text: &str) -> impl Iterator<Item = Diagnostic> {
+    literal_shapes::check(text)
+        .into_iter()
+        .map(|issue| Diagnostic {
+            line: issue.line,
+            start: issue.start,
+            end: issue.end,
+            message: issue.message,
+            warning: issue.warning,
+            hint: None,
+        })
+}
+
+/// Parse `text` with the interpreter's own lexer and parser, for the scopes
+/// of the document or else its first error. The error is only reported
+/// when the scanner found no unbalanced string or bracket, as it is then
+/// the same problem reported further from its cause.
+fn parse(text: &str, mode: Mode, unbalanced: bool, out: &mut Analysis) {
+    match syntax::parse(text) {
+        Ok(program) => {
+            if mode == Mode::Full {
+                out.scopes = Some(SymbolTable::build(text, &program));
+            }
+        }
+        Err(error) if !unbalanced => out.diagnostics.push(Diagnostic {
+            line: error.line,
+            start: error.start,
+            end: error.end,
+            message: error.message,
+            warning: false,
+            hint: error.hint,
+        }),
+        Err(_) => {}
+    }
+}
+
+fn file_name(uri: &str) -> &str {
+    uri.rsplit('/').next().unwrap_or(uri)
+}
+
+/// Scan `job` chunk by chunk, merging each chunk into the shared state.
+/// Gives up as soon as a newer version has been submitted.
+fn run_job(shared: &Shared, job: Job) -> Option<Completed> {
+    let superseded = |state: &State| {
+        state
+            .documents
+            .get(&job.uri)
+            .is_none_or(|entry| entry.analysis.version != job.version)
+    };
+
+    if superseded(&shared.lock()) {
+        return None;
+    }
+
+    let mut scanner = Scanner::new(job.mode);
+    let mut lines = job.text.lines().enumerate().peekable();
+    let mut first_chunk = true;
+    let mut unbalanced = false;
+
+    loop {
+        let mut chunk = Analysis::default();
+        for (number, line) in lines.by_ref().take(CHUNK_LINES) {
+            scanner.scan_line(number as u32, line, &mut chunk);
+        }
+        let done = lines.peek().is_none();
+        if done {
+            scanner.finish(&mut chunk);
+        }
+        unbalanced |= !chunk.diagnostics.is_empty();
+        if done {
+            parse(&job.text, job.mode, unbalanced, &mut chunk);
+            chunk.diagnostics.extend(shape_diagnostics(&job.text));
+        }
+
+        let mut state = shared.lock();
+        if superseded(&state) {
+            return None;
+        }
+        let analysis = &mut state.documents.get_mut(&job.uri)?.analysis;
+        if first_chunk {
+            analysis.symbols.clear();
+            analysis.tokens.clear();
+            analysis.diagnostics.clear();
+            analysis.scopes = None;
+            analysis.lines_done = 0;
+            first_chunk = false;
+        }
+        analysis.symbols.append(&mut chunk.symbols);
+        analysis.tokens.append(&mut chunk.tokens);
+        analysis.diagnostics.append(&mut chunk.diagnostics);
+        if done {
+            analysis.scopes = chunk.scopes.take();
+        }
+        analysis.lines_done = scanner.lines;
+        analysis.complete = done;
+
+        let completed = done.then(|| Completed {
+            uri: job.uri.clone(),
+            version: job.version,
+            mode: job.mode,
+            diagnostics: analysis.diagnostics.clone(),
+        });
+        drop(state);
+        shared.changed.notify_all();
+
+        if completed.is_some() {
+            return completed;
+        }
+    }
+}
+
+/// What the previous word on the line makes of the next identifier
+#[derive(Clone, Copy, PartialEq)]
+enum Declaring {
+    Nothing,
+    Function,
+    Class,
+    Variable,
+    Enum,
+}
+
+struct Scanner {
+    mode: Mode,
+    /// Open brackets: character, line, column
+    open: Vec<(char, u32, u32)>,
+    lines: usize,
+}
+
+impl Scanner {
+    fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            open: Vec::new(),
+            lines: 0,
+        }
+    }
+
+    fn token(&self, out: &mut Analysis, line: u32, start: u32, length: u32, kind: TokenKind) {
+        if self.mode == Mode::Full {
+            out.tokens.push(Token {
+                line,
+                start,
+                length,
+                kind,
+            });
+        }
+    }
+
+    fn scan_line(&mut self, number: u32, line: &str, out: &mut Analysis) {
+        self.lines += 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        let mut column: u32 = 0;
+        let mut declaring = Declaring::Nothing;
+
+        let width = |c: &char| c.len_utf16() as u32;
+        let span = |chars: &[char]| chars.iter().map(width).sum::<u32>();
+
+        while i < chars.len() {
+            let c = chars[i];
+            let start = column;
+
+            if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+                let length = span(&chars[i..]);
+                self.token(out, number, start, length, TokenKind::Comment);
+                return;
+            }
+
+            if c == '"' || c == '\'' {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != c {
+                    j += if chars[j] == '\\' { 2 } else { 1 };
+                }
+                let end = (j + 1).min(chars.len());
+                let length = span(&chars[i..end]);
+                if j >= chars.len() {
+                    out.diagnostics.push(Diagnostic {
+                        line: number,
+                        start,
+                        end: start + length,
+                        message: "Unterminated string".to_string(),
+                        warning: false,
+                        hint: None,
+                    });
+                }
+                self.token(out, number, start, length, TokenKind::String);
+                column += length;
+                i = end;
+                continue;
+            }
+
+            if c.is_ascii_digit() {
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                    j += 1;
+                }
+                let length = (j - i) as u32;
+                self.token(out, number, start, length, TokenKind::Number);
+                column += length;
+                i = j;
+                continue;
+            }
+
+            if c.is_alphabetic() || c == '_' {
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                    j += 1;
+                }
+                let word: String = chars[i..j].iter().collect();
+                let length = span(&chars[i..j]);
+                declaring = self.word(out, number, start, length, word, declaring, line);
+                column += length;
+                i = j;
+                continue;
+            }
+
+            match c {
+                '(' | '[' | '{' => self.open.push((c, number, start)),
+                ')' | ']' | '}' => {
+                    let expected = match c {
+                        ')' => '(',
+                        ']' => '[',
+                        _ => '{',
+                    };
+                    if self.open.last().map(|&(open, _, _)| open) == Some(expected) {
+                        self.open.pop();
+                    } else {
+                        out.diagnostics.push(Diagnostic {
+                            line: number,
+                            start,
+                            end: start + 1,
+                            message: format!("Unmatched '{}'", c),
+                            warning: false,
+                            hint: None,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            column += width(&c);
+            i += 1;
+        }
+    }
+
+    /// Record a keyword or identifier and return what it declares next
+    #[allow(clippy::too_many_arguments)]
+    fn word(
+        &self,
+        out: &mut Analysis,
+        number: u32,
+        start: u32,
+        length: u32,
+        word: String,
+        declaring: Declaring,
+        line: &str,
+    ) -> Declaring {
+        let statement_keyword = STATEMENT_KEYWORDS.contains(&word.as_str())
+            && line
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .map(|c| c.len_utf16() as u32)
+                .sum::<u32>()
+                == start
+            && line
+                .chars()
+                .skip(utf16_to_char_index(line, start + length))
+                .find(|c| !c.is_whitespace())
+                .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '"');
+        if statement_keyword {
+            self.token(out, number, start, length, TokenKind::Keyword);
+            return if word == "enum" {
+                Declaring::Enum
+            } else {
+                Declaring::Nothing
+            };
+        }
+
+        if KEYWORDS.contains(&word.as_str()) {
+            self.token(out, number, start, length, TokenKind::Keyword);
+            return match word.as_str() {
+                "function" | "def" => Declaring::Function,
+                "class" => Declaring::Class,
+                "let" => Declaring::Variable,
+                _ => Declaring::Nothing,
+            };
+        }
+
+        let (kind, symbol) = match declaring {
+            Declaring::Function => (TokenKind::Function, Some(SymbolKind::Function)),
+            Declaring::Class => (TokenKind::Class, Some(SymbolKind::Class)),
+            Declaring::Variable => (TokenKind::Variable, Some(SymbolKind::Variable)),
+            Declaring::Enum => (TokenKind::Class, Some(SymbolKind::Enum)),
+            Declaring::Nothing => {
+                let rest = line
+                    .chars()
+                    .skip(utf16_to_char_index(line, start + length))
+                    .find(|c| !c.is_whitespace());
+                if rest == Some('(') {
+                    (TokenKind::Function, None)
+                } else {
+                    (TokenKind::Variable, None)
+                }
+            }
+        };
+
+        self.token(out, number, start, length, kind);
+        if let (Some(kind), Mode::Full) = (symbol, self.mode) {
+            out.symbols.push(Symbol {
+                name: word,
+                kind,
+                line: number,
+                start,
+                end: start + length,
+                detail: line.trim().to_string(),
+            });
+        }
+        Declaring::Nothing
+    }
+
+    fn finish(&mut self, out: &mut Analysis) {
+        for (c, line, start) in self.open.drain(..) {
+            out.diagnostics.push(Diagnostic {
+                line,
+                start,
+                end: start + 1,
+                message: format!("Unclosed '{}'", c),
+                warning: false,
+                hint: None,
+            });
+        }
+    }
+}
+
+fn utf16_to_char_index(line: &str, column: u32) -> usize {
+    let mut units = 0;
+    for (index, c) in line.chars().enumerate() {
+        if units >= column {
+            return index;
+        }
+        units += c.len_utf16() as u32;
+    }
+    line.chars().count()
+}
+
+/// Byte offset of an LSP position, clamped to the text
+pub fn offset_at(text: &str, line: u32, character: u32) -> usize {
+    let mut offset = 0;
+    for _ in 0..line {
+        match text[offset..].find('\n') {
+            Some(newline) => offset += newline + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (index, c) in text[offset..].char_indices() {
+        if units >= character || c == '\n' {
+            return offset + index;
+        }
+        units += c.len_utf16() as u32;
+    }
+    text.len()
+}
+
+/// LSP line and UTF-16 character of a byte offset, the inverse of
+/// `offset_at`
+pub fn position_at(text: &str, offset: usize) -> (u32, u32) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count() as u32;
+    let character = before[line_start..].chars().map(|c| c.len_utf16() as u32).sum();
+    (line, character)
+}
+
+/// Tokens in the relative five-integer encoding of `textDocument/semanticTokens`
+pub fn encode_tokens(tokens: &[Token]) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut line, mut start) = (0, 0);
+    for token in tokens {
+        let delta_line = token.line - line;
+        let delta_start = if delta_line == 0 {
+            token.start - start
+        } else {
+            token.start
+        };
+        data.extend([delta_line, delta_start, token.length, token.kind as u32, 0]);
+        line = token.line;
+        start = token.start;
+    }
+    data
+}
+
+/// A `SemanticTokensEdit`: replace `delete_count` integers at `start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenEdit {
+    pub start: u32,
+    pub delete_count: u32,
+    pub data: Vec<u32>,
+}
+
+/// The edits that turn `previous` into `current`: at most one, covering
+/// everything between their common prefix and suffix
+pub fn token_delta(previous: &[u32], current: &[u32]) -> Vec<TokenEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == previous.len() && prefix == current.len() {
+        return Vec::new();
+    }
+
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    vec![TokenEdit {
+        start: prefix as u32,
+        delete_count: (previous.len() - prefix - suffix) as u32,
+        data: current[prefix..current.len() - suffix].to_vec(),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_millis(100);
+
+    fn generated(lines: usize) -> String {
+        let mut text = String::new();
+        for i in 0..lines {
+            match i % 4 {
+                0 => text.push_str(&format!("function fn_{}(a, b):\n", i)),
+                1 => text.push_str("    return add(a, b) + 1 # sum\n"),
+                2 => text.push_str(&format!("let value_{} = \"text\"\n", i)),
+                _ => text.push_str("print(value)\n"),
+            }
+        }
+        text
+    }
+
+    fn analyzer(config: AnalysisConfig) -> (Analyzer, mpsc::Receiver<Completed>) {
+        let (sender, receiver) = mpsc::channel();
+        let analyzer = Analyzer::new(config, move |completed| {
+            let _ = sender.send(completed);
+        });
+        (analyzer, receiver)
+    }
+
+    #[test]
+    fn large_file_answers_within_budget_and_publishes_later() {
+        let mut text = generated(200_000);
+        text.push_str("let broken = \"never closed\n");
+        // About 5MB: over the default cap, so raise it
+        let (analyzer, completions) = analyzer(AnalysisConfig {
+            time_budget: WAIT,
+            max_file_size: 16 * 1024 * 1024,
+            ..AnalysisConfig::default()
+        });
+
+        analyzer.submit("file:///big.infra", 1, text.into());
+
+        // Hover looks the word up in whatever has been analysed so far
+        let asked = Instant::now();
+        let hover = analyzer.read("file:///big.infra", |analysis| {
+            analysis.symbol("fn_0").map(|symbol| symbol.detail.clone())
+        });
+        assert!(asked.elapsed() < WAIT + Duration::from_millis(150));
+        assert!(hover.is_some());
+
+        let completed = completions.recv_timeout(Duration::from_secs(120)).unwrap();
+        assert_eq!(completed.version, 1);
+        assert_eq!(completed.mode, Mode::Full);
+        assert_eq!(completed.diagnostics.len(), 1);
+        assert_eq!(completed.diagnostics[0].line, 200_000);
+        assert_eq!(completed.diagnostics[0].message, "Unterminated string");
+
+        let (complete, symbols) = analyzer
+            .read("file:///big.infra", |a| (a.complete, a.symbols.len()))
+            .unwrap();
+        assert!(complete);
+        assert_eq!(symbols, 100_001);
+    }
+
+    #[test]
+    fn oversized_file_degrades_to_diagnostics_with_one_warning() {
+        let mut text = generated(100);
+        text.push_str("print((1)\n");
+        let (analyzer, completions) = analyzer(AnalysisConfig {
+            max_file_size: 1024,
+            ..AnalysisConfig::default()
+        });
+
+        let first = analyzer.submit("file:///gen/huge.infra", 1, text.clone().into());
+        let warning = first.warning.unwrap();
+        assert!(warning.starts_with("huge.infra is "), "{}", warning);
+        assert!(analyzer
+            .submit("file:///gen/huge.infra", 2, text.into())
+            .warning
+            .is_none());
+
+        // Version 1 was superseded before it finished, or finished first
+        let completed = loop {
+            let completed = completions.recv_timeout(Duration::from_secs(10)).unwrap();
+            if completed.version == 2 {
+                break completed;
+            }
+        };
+        assert_eq!(completed.mode, Mode::DiagnosticsOnly);
+        assert_eq!(
+            completed.diagnostics,
+            vec![Diagnostic {
+                line: 100,
+                start: 5,
+                end: 6,
+                message: "Unclosed '('".to_string(),
+                warning: false,
+                hint: None,
+            }]
+        );
+
+        let (symbols, tokens) = analyzer
+            .read("file:///gen/huge.infra", |a| (a.symbols.len(), a.tokens.len()))
+            .unwrap();
+        assert_eq!((symbols, tokens), (0, 0));
+
+        // A small file is analysed in full
+        let small = analyzer.submit("file:///small.infra", 1, generated(4).into());
+        assert!(small.warning.is_none());
+        completions.recv_timeout(Duration::from_secs(10)).unwrap();
+        let kind = analyzer.read("file:///small.infra", |a| a.symbol("fn_0").map(|s| s.kind));
+        assert_eq!(kind, Some(Some(SymbolKind::Function)));
+    }
+
+    #[test]
+    fn tokens_are_encoded_relative_and_diffed() {
+        let mut scanner = Scanner::new(Mode::Full);
+        let mut before = Analysis::default();
+        for (number, line) in "let a = 1\nprint(a)\n".lines().enumerate() {
+            scanner.scan_line(number as u32, line, &mut before);
+        }
+        let previous = encode_tokens(&before.tokens);
+        assert_eq!(
+            previous,
+            vec![0, 0, 3, 0, 0, 0, 4, 1, 5, 0, 0, 4, 1, 2, 0, 1, 0, 5, 0, 0, 0, 6, 1, 5, 0]
+        );
+
+        let mut scanner = Scanner::new(Mode::Full);
+        let mut after = Analysis::default();
+        for (number, line) in "let a = 1\nprint(a, \"x\")\n".lines().enumerate() {
+            scanner.scan_line(number as u32, line, &mut after);
+        }
+        let current = encode_tokens(&after.tokens);
+        assert_eq!(
+            token_delta(&previous, &current),
+            vec![TokenEdit {
+                start: 25,
+                delete_count: 0,
+                data: vec![0, 3, 3, 1, 0],
+            }]
+        );
+        assert!(token_delta(&current, &current).is_empty());
+        assert_eq!(after.tokens_in(1, 1).len(), 3);
+    }
+
+    #[test]
+    fn enums_are_symbols_with_member_completion() {
+        let analysis = scan(
+            "enum Status: pending, active, closed\n\
+             match status:\n\
+             let found = regex.match(text)\n",
+        );
+
+        let status = analysis.symbol("Status").unwrap();
+        assert_eq!(status.kind, SymbolKind::Enum);
+        assert_eq!(status.enum_members(), vec!["pending", "active", "closed"]);
+
+        let (symbol, members) = analysis.enum_at_dot("    print(Status.ac").unwrap();
+        assert_eq!(symbol.name, "Status");
+        assert_eq!(members.len(), 3);
+        assert!(analysis.enum_at_dot("print(Status").is_none());
+        assert!(analysis.enum_at_dot("status.").is_none());
+
+        // Only the statement keywords are highlighted, not regex.match
+        let keywords: Vec<(u32, u32)> = analysis
+            .tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Keyword)
+            .map(|token| (token.line, token.start))
+            .collect();
+        assert_eq!(keywords, vec![(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn decorated_functions_are_defined_at_their_function_line() {
+        let analysis = scan(
+            "@memo.wrap\n\
+             @timing.throttle(100)\n\
+             export function save(data):\n    return data\n",
+        );
+
+        // Go-to-definition on `save` lands on the declaration, not a decorator
+        let save = analysis.symbol("save").unwrap();
+        assert_eq!(
+            (save.kind, save.line, save.start),
+            (SymbolKind::Function, 2, 16)
+        );
+        assert_eq!(analysis.symbols.len(), 1);
+        assert!(analysis.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn parse_errors_are_reported_unless_brackets_are_unbalanced() {
+        let analysis = scan("let (a) = [1]\nprint(a)\n");
+        assert_eq!(analysis.diagnostics.len(), 1);
+        let diagnostic = &analysis.diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.warning), (0, false));
+        assert!(diagnostic.hint.is_some());
+
+        // The scanner's error is the one that points at the cause
+        let analysis = scan("print((1)\n");
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert_eq!(analysis.diagnostics[0].message, "Unclosed '('");
+
+        assert!(scan("let a = (1)\nprint(a)\n").diagnostics.is_empty());
+    }
+
+    #[test]
+    fn scopes_are_kept_only_for_documents_that_parse() {
+        let analysis = scan("let a = (1)\nprint(a)\n");
+        let scopes = analysis.scopes.unwrap();
+        assert_eq!(scopes.definition_at(1, 6).unwrap().name, "a");
+        assert!(scan("let (a) = [1]\nprint(a)\n").scopes.is_none());
+    }
+}
@@ -0,0 +1,375 @@
+//! Quick fixes that add missing imports. Removing unused ones is shared
+//! with `infra fix` through `infra::frontend::unused_imports`.
+//!
+//! Like `import_paths`, everything here works on plain text so it keeps
+//! working while the document does not parse. Imports are recognised one
+//! statement per line, which is the only form the parser accepts.
+
+use crate::import_paths;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+const EXTENSION: &str = "infra";
+
+/// A name the user referenced without importing it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// `helpers` in `helpers.slugify(...)`: satisfied by a default import
+    Module(String),
+    /// `slugify` in `slugify(...)`: satisfied by a named import
+    Symbol(String),
+}
+
+impl Reference {
+    pub fn name(&self) -> &str {
+        match self {
+            Reference::Module(name) | Reference::Symbol(name) => name,
+        }
+    }
+}
+
+/// One way to satisfy a reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportCandidate {
+    /// Module path as written in the import, relative to the importing file
+    pub module_path: String,
+    pub statement: String,
+}
+
+/// Text to insert so that it becomes line `line` of the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insertion {
+    pub line: usize,
+    pub text: String,
+}
+
+/// Every `.infra` file in a workspace with the names it exports
+#[derive(Debug, Default)]
+pub struct ExportIndex {
+    modules: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl ExportIndex {
+    /// Scan `root` recursively, skipping hidden directories
+    pub fn build(root: &Path) -> Self {
+        let mut index = Self::default();
+        index.scan_dir(root);
+        index.modules.sort_by(|a, b| a.0.cmp(&b.0));
+        index
+    }
+
+    fn scan_dir(&mut self, dir: &Path) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'));
+            if hidden {
+                continue;
+            }
+
+            if path.is_dir() {
+                self.scan_dir(&path);
+            } else if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                let exports = std::fs::read_to_string(&path)
+                    .map(|source| import_paths::scan_exports(&source))
+                    .unwrap_or_default();
+                self.modules.push((path, exports));
+            }
+        }
+    }
+
+    /// Modules that could satisfy `reference`, in path order
+    pub fn providers(&self, reference: &Reference) -> Vec<&Path> {
+        self.modules
+            .iter()
+            .filter(|(path, exports)| match reference {
+                Reference::Module(name) => {
+                    path.file_stem().is_some_and(|stem| stem == name.as_str())
+                }
+                Reference::Symbol(name) => exports.iter().any(|export| export == name),
+            })
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+}
+
+/// The reference at `character` on `line`: the identifier under the cursor,
+/// as a module if a `.` follows it
+pub fn reference_at(line: &str, character: usize) -> Option<Reference> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut start = character.min(chars.len());
+    while start > 0 && is_word(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = start;
+    while end < chars.len() && is_word(chars[end]) {
+        end += 1;
+    }
+    if start == end || chars[start].is_ascii_digit() {
+        return None;
+    }
+
+    // `obj.name` refers to a property, not something to import
+    if start > 0 && chars[start - 1] == '.' {
+        return None;
+    }
+
+    let name: String = chars[start..end].iter().collect();
+    if chars.get(end) == Some(&'.') {
+        Some(Reference::Module(name))
+    } else {
+        Some(Reference::Symbol(name))
+    }
+}
+
+/// Imports that would make `reference` resolve in `document`, one per module
+pub fn candidates(
+    reference: &Reference,
+    document: &Path,
+    index: &ExportIndex,
+) -> Vec<ImportCandidate> {
+    let document_dir = document.parent().unwrap_or(Path::new(""));
+
+    index
+        .providers(reference)
+        .into_iter()
+        .filter(|path| *path != document)
+        .map(|path| {
+            let module_path = relative_module_path(document_dir, path);
+            let statement = match reference {
+                Reference::Module(name) => format!("import {} from \"{}\"", name, module_path),
+                Reference::Symbol(name) => format!("import {{{}}} from \"{}\"", name, module_path),
+            };
+            ImportCandidate {
+                module_path,
+                statement,
+            }
+        })
+        .collect()
+}
+
+/// Path from `from_dir` to `target` as written in an import: always starting
+/// with `./` or `../`, using `/`, and without the `.infra` extension
+pub fn relative_module_path(from_dir: &Path, target: &Path) -> String {
+    let target = target.with_extension("");
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = target.components().collect();
+
+    let common = from
+        .iter()
+        .zip(&to)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+
+    let path = parts.join("/");
+    if path.starts_with("..") {
+        path
+    } else {
+        format!("./{}", path)
+    }
+}
+
+/// Where to put a new import statement: right after the last import of the
+/// leading import block, or at the top of the file followed by a blank line
+/// when there are no imports yet
+pub fn insertion(text: &str, statement: &str) -> Insertion {
+    let mut last_import = None;
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if is_import(trimmed) {
+            last_import = Some(number);
+        } else if !(trimmed.is_empty() || trimmed.starts_with("//")) {
+            break;
+        }
+    }
+
+    match last_import {
+        Some(line) => Insertion {
+            line: line + 1,
+            text: format!("{}\n", statement),
+        },
+        None => {
+            let starts_blank = text.lines().next().is_none_or(|l| l.trim().is_empty());
+            Insertion {
+                line: 0,
+                text: if starts_blank {
+                    format!("{}\n", statement)
+                } else {
+                    format!("{}\n\n", statement)
+                },
+            }
+        }
+    }
+}
+
+/// Names bound by import statements in `text`
+pub fn imported_names(text: &str) -> HashSet<String> {
+    text.lines().filter_map(parse_import).flatten().collect()
+}
+
+fn is_import(line: &str) -> bool {
+    line.strip_prefix("import")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_whitespace() || c == '{'))
+}
+
+/// The names an import line binds. `import "m"` and `import * from "m"`
+/// bind nothing that can be checked, so they are skipped.
+fn parse_import(line: &str) -> Option<Vec<String>> {
+    let trimmed = line.trim_start();
+    if !is_import(trimmed) {
+        return None;
+    }
+    let rest = trimmed["import".len()..].trim();
+
+    let from = rest.rfind(" from ")?;
+    let head = rest[..from].trim();
+
+    if let Some(list) = head.strip_prefix('{').and_then(|h| h.strip_suffix('}')) {
+        Some(
+            list.split(',')
+                .filter_map(|item| binding(item.trim()))
+                .collect(),
+        )
+    } else if head == "*" {
+        None
+    } else {
+        Some(vec![binding(head)?])
+    }
+}
+
+/// The local name of `name` or `name as alias`
+fn binding(text: &str) -> Option<String> {
+    let mut words = text.split_whitespace();
+    let name = words.next()?;
+    match (words.next(), words.next()) {
+        (Some("as"), Some(alias)) => Some(alias.to_string()),
+        _ => Some(name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/auto_import")
+    }
+
+    #[test]
+    fn single_candidate_inserted_after_import_block() {
+        let root = workspace();
+        let index = ExportIndex::build(&root);
+        let document = root.join("app/main.infra");
+        let text = std::fs::read_to_string(&document).unwrap();
+
+        let line = text.lines().nth(4).unwrap();
+        let reference = reference_at(line, line.find("helpers").unwrap() + 2).unwrap();
+        assert_eq!(reference, Reference::Module("helpers".to_string()));
+
+        let found = candidates(&reference, &document, &index);
+        assert_eq!(
+            found,
+            vec![ImportCandidate {
+                module_path: "../helpers".to_string(),
+                statement: "import helpers from \"../helpers\"".to_string(),
+            }]
+        );
+
+        assert_eq!(
+            insertion(&text, &found[0].statement),
+            Insertion {
+                line: 2,
+                text: "import helpers from \"../helpers\"\n".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn insertion_without_imports_adds_blank_line() {
+        assert_eq!(
+            insertion("print(1)\n", "import {a} from \"./a\""),
+            Insertion {
+                line: 0,
+                text: "import {a} from \"./a\"\n\n".to_string(),
+            }
+        );
+        assert_eq!(insertion("// header\nimport {a} from \"./a\"\n\nlet x = 1\n", "import b from \"./b\"").line, 2);
+    }
+
+    #[test]
+    fn ambiguous_symbol_offers_every_module() {
+        let root = workspace();
+        let index = ExportIndex::build(&root);
+        let document = root.join("app/main.infra");
+
+        let found = candidates(&Reference::Symbol("slugify".to_string()), &document, &index);
+        let statements: Vec<&str> = found.iter().map(|c| c.statement.as_str()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "import {slugify} from \"../helpers\"",
+                "import {slugify} from \"../text/slug\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_unused_import() {
+        use infra::frontend::unused_imports;
+        use tower_lsp::lsp_types::{Position, Range, Url};
+
+        let uri = Url::parse("file:///app/unused.infra").unwrap();
+        let edit = |text: &str| {
+            let unused = &unused_imports::find(text)[0];
+            let action = crate::Server::remove_import_action(&uri, text, unused);
+            let mut changes = action.edit.unwrap().changes.unwrap();
+            (action.title, changes.remove(&uri).unwrap().remove(0))
+        };
+
+        let text = std::fs::read_to_string(workspace().join("app/unused.infra")).unwrap();
+        let (title, removed) = edit(&text);
+        assert_eq!(title, "Remove unused import of `slugify`");
+        assert_eq!(
+            removed.range,
+            Range::new(Position::new(1, 0), Position::new(2, 0))
+        );
+        assert_eq!(removed.new_text, "");
+
+        let partly = "import {greet, slugify as slug} from \"./a\"\nprint(slug(\"x\"))\n";
+        let (_, rewritten) = edit(partly);
+        assert_eq!(
+            rewritten.range,
+            Range::new(Position::new(0, 7), Position::new(0, 31))
+        );
+        assert_eq!(rewritten.new_text, "{slugify as slug}");
+    }
+
+    #[test]
+    fn relative_paths_between_directories() {
+        let root = Path::new("/work");
+        assert_eq!(relative_module_path(root, &root.join("util.infra")), "./util");
+        assert_eq!(
+            relative_module_path(&root.join("app"), &root.join("lib/strings.infra")),
+            "../lib/strings"
+        );
+        assert_eq!(
+            relative_module_path(&root.join("app"), &root.join("app/deep/x.infra")),
+            "./deep/x"
+        );
+    }
+}
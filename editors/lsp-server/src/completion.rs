@@ -0,0 +1,319 @@
+//! What to offer at the cursor: module members after `module.`, and
+//! otherwise the declarations, builtins, modules and keywords that start
+//! with the word being typed.
+//!
+//! Like the scanner in `analysis`, the cursor's context comes from its line
+//! alone, so completion keeps working while the document does not parse.
+//! Signatures of library functions come from the standard library
+//! reference, which lists each one on a line of its own.
+
+use crate::analysis::{self, Symbol, SymbolKind};
+use infra::stdlib::{metadata, StandardLibrary};
+use std::collections::HashSet;
+
+const REFERENCE: &str = include_str!("../../../docs/STANDARD_LIBRARY.md");
+
+/// Keywords that are called like functions
+const BUILTIN_KEYWORDS: &[(&str, &str)] =
+    &[("print", "print(value)"), ("range", "range(start, end)")];
+
+/// Where the cursor is, as far as completion cares
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Context<'a> {
+    /// Inside a string literal or a comment, or after a `.` that does not
+    /// follow a name: nothing is offered
+    Quiet,
+    /// After `object.`, with the start of the member typed so far
+    Member { object: &'a str, prefix: &'a str },
+    /// Anywhere else, with the start of the word typed so far
+    Word { prefix: &'a str },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateKind {
+    Keyword,
+    Function,
+    Module,
+    Variable,
+    Class,
+    Enum,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub label: String,
+    pub kind: CandidateKind,
+    pub detail: String,
+}
+
+/// The context of a UTF-16 column of `line`
+pub fn context(line: &str, character: u32) -> Context<'_> {
+    let mut end = line.len();
+    let mut units = 0;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if units >= character {
+            end = index;
+            break;
+        }
+        units += c.len_utf16() as u32;
+        match (quote, c) {
+            (Some(_), '\\') => {
+                if let Some((_, escaped)) = chars.next() {
+                    units += escaped.len_utf16() as u32;
+                }
+            }
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return Context::Quiet,
+            (None, '/') if chars.peek().map(|&(_, next)| next) == Some('/') => {
+                return Context::Quiet;
+            }
+            (None, _) => {}
+        }
+    }
+    if quote.is_some() {
+        return Context::Quiet;
+    }
+
+    let before = &line[..end];
+    let prefix = trailing_word(before);
+    let Some(before_dot) = before[..before.len() - prefix.len()]
+        .strip_suffix('.')
+        .map(|before| before.strip_suffix('?').unwrap_or(before))
+    else {
+        return Context::Word { prefix };
+    };
+    let object = trailing_word(before_dot);
+    if object.is_empty() || object.starts_with(|c: char| c.is_ascii_digit()) {
+        return Context::Quiet;
+    }
+    Context::Member { object, prefix }
+}
+
+fn trailing_word(text: &str) -> &str {
+    let start = text
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |i| {
+            i + text[i..].chars().next().map_or(1, char::len_utf8)
+        });
+    &text[start..]
+}
+
+/// The functions of the library module `module` that start with `prefix`,
+/// sorted by name; `None` when there is no such module
+pub fn module_members(
+    library: &StandardLibrary,
+    module: &str,
+    prefix: &str,
+) -> Option<Vec<Candidate>> {
+    // Functions the evaluator implements are described but not registered
+    let mut functions = library.get_module_functions(module).unwrap_or_default();
+    functions.extend(
+        metadata::FUNCTIONS
+            .iter()
+            .filter(|info| info.module == module)
+            .map(|info| info.name),
+    );
+    if functions.is_empty() {
+        return None;
+    }
+    functions.retain(|function| function.starts_with(prefix));
+    functions.sort_unstable();
+    functions.dedup();
+    Some(
+        functions
+            .into_iter()
+            .map(|function| Candidate {
+                label: function.to_string(),
+                kind: CandidateKind::Function,
+                detail: documented_signature(module, function)
+                    .map_or_else(|| format!("{}.{}(...)", module, function), str::to_string),
+            })
+            .collect(),
+    )
+}
+
+/// `module.function(params): type` as the reference gives it
+pub fn documented_signature(module: &str, function: &str) -> Option<&'static str> {
+    let call = format!("{}.{}(", module, function);
+    REFERENCE
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(line).trim())
+        .find(|line| {
+            // Examples are written the same way, but without a return type
+            let returns = line
+                .rsplit_once("): ")
+                .is_some_and(|(_, returns)| !returns.is_empty() && !returns.contains(' '));
+            line.starts_with(&call) && (returns || line.len() == call.len() + 1)
+        })
+}
+
+/// Everything a word starting with `prefix` can complete to at a position:
+/// the document's declarations before it, and its functions, classes and
+/// enums wherever they are, then builtins, library modules and keywords.
+/// A name is offered once, as the first of these that has it.
+pub fn words(
+    library: &StandardLibrary,
+    symbols: &[Symbol],
+    line: u32,
+    character: u32,
+    prefix: &str,
+) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    let mut declared: Vec<&Symbol> = symbols
+        .iter()
+        .filter(|symbol| {
+            symbol.kind != SymbolKind::Variable || (symbol.line, symbol.end) < (line, character)
+        })
+        .collect();
+    // The latest declaration before the cursor is the one in effect
+    declared.sort_by_key(|symbol| std::cmp::Reverse((symbol.line, symbol.start)));
+    for symbol in declared {
+        candidates.push(Candidate {
+            label: symbol.name.clone(),
+            kind: match symbol.kind {
+                SymbolKind::Function => CandidateKind::Function,
+                SymbolKind::Class => CandidateKind::Class,
+                SymbolKind::Variable => CandidateKind::Variable,
+                SymbolKind::Enum => CandidateKind::Enum,
+            },
+            detail: symbol.detail.clone(),
+        });
+    }
+
+    for &(name, detail) in BUILTIN_KEYWORDS {
+        candidates.push(builtin(name, detail.to_string()));
+    }
+    let mut globals = library.get_globals();
+    globals.sort_unstable();
+    for name in globals {
+        candidates.push(builtin(name, format!("{}(...)", name)));
+    }
+
+    let mut modules = library.get_modules();
+    modules.extend(metadata::FUNCTIONS.iter().map(|info| info.module));
+    modules.sort_unstable();
+    modules.dedup();
+    for module in modules {
+        candidates.push(Candidate {
+            label: module.to_string(),
+            kind: CandidateKind::Module,
+            detail: "Standard library module".to_string(),
+        });
+    }
+
+    for &keyword in analysis::KEYWORDS
+        .iter()
+        .chain(analysis::STATEMENT_KEYWORDS)
+    {
+        candidates.push(Candidate {
+            label: keyword.to_string(),
+            kind: CandidateKind::Keyword,
+            detail: "Infra keyword".to_string(),
+        });
+    }
+
+    let mut seen = HashSet::new();
+    candidates.retain(|candidate| {
+        candidate.label.starts_with(prefix) && seen.insert(candidate.label.clone())
+    });
+    candidates
+}
+
+fn builtin(name: &str, detail: String) -> Candidate {
+    Candidate {
+        label: name.to_string(),
+        kind: CandidateKind::Function,
+        detail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_follows_strings_comments_and_dots() {
+        assert_eq!(context("let x = ma", 10), Context::Word { prefix: "ma" });
+        assert_eq!(
+            context("print(math.sq)", 13),
+            Context::Member {
+                object: "math",
+                prefix: "sq"
+            }
+        );
+        assert_eq!(
+            context("user?.na", 8),
+            Context::Member {
+                object: "user",
+                prefix: "na"
+            }
+        );
+        assert_eq!(context("let s = \"math.", 14), Context::Quiet);
+        assert_eq!(
+            context("let s = \"a\\\"b\" + le", 19),
+            Context::Word { prefix: "le" }
+        );
+        assert_eq!(context("x = 1 # math.", 13), Context::Quiet);
+        assert_eq!(context("x = 1 // le", 11), Context::Quiet);
+        assert_eq!(context("let n = 1.", 10), Context::Quiet);
+        assert_eq!(context("f().", 4), Context::Quiet);
+
+        // Columns are UTF-16 code units, and the word ends at the cursor
+        assert_eq!(context("\"😀\" + total", 9), Context::Word { prefix: "to" });
+    }
+
+    #[test]
+    fn module_members_come_with_their_signatures() {
+        let library = StandardLibrary::new();
+        let members = module_members(&library, "math", "sq").unwrap();
+        assert_eq!(
+            members,
+            vec![Candidate {
+                label: "sqrt".to_string(),
+                kind: CandidateKind::Function,
+                detail: "math.sqrt(x: number): number".to_string(),
+            }]
+        );
+        let pi = module_members(&library, "math", "pi").unwrap();
+        assert_eq!(pi[0].detail, "math.pi()");
+        assert!(module_members(&library, "nothing", "").is_none());
+
+        // Kept by the evaluator rather than the library's registry
+        let timing = module_members(&library, "timing", "").unwrap();
+        let labels: Vec<&str> = timing.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["cancel", "debounce", "throttle"]);
+    }
+
+    #[test]
+    fn words_offer_earlier_declarations_before_builtins_and_keywords() {
+        let library = StandardLibrary::new();
+        let text = "let total = 1\nprint(to)\nlet tomorrow = 2\nfunction tokens():\n    return 1\n";
+        let symbols = analysis::scan(text).symbols;
+
+        let labels: Vec<(String, CandidateKind)> = words(&library, &symbols, 1, 8, "to")
+            .into_iter()
+            .map(|candidate| (candidate.label, candidate.kind))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("tokens".to_string(), CandidateKind::Function),
+                ("total".to_string(), CandidateKind::Variable),
+            ]
+        );
+
+        let labels: Vec<String> = words(&library, &symbols, 1, 7, "")
+            .into_iter()
+            .map(|candidate| candidate.label)
+            .collect();
+        for expected in ["print", "len", "math", "string", "let", "match"] {
+            assert!(labels.iter().any(|label| label == expected), "{}", expected);
+        }
+        assert_eq!(labels.iter().filter(|label| *label == "string").count(), 1);
+    }
+}
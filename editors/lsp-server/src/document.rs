@@ -0,0 +1,260 @@
+//! Open documents, kept in step with the client through `didChange`.
+//!
+//! Alongside the text, a document keeps the byte offset at which each line
+//! starts, so an LSP position resolves without scanning the lines before it.
+//! An edit rescans only the text it inserted, plus the line terminators at
+//! its two ends; the lines after it are shifted by the change in length.
+//!
+//! Lines end at `\n`, `\r\n` or a lone `\r`, and columns are UTF-16 code
+//! units, as in LSP.
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub version: i32,
+    text: String,
+    /// Byte offset of the start of each line; the first is always 0
+    line_starts: Vec<usize>,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        let mut document = Self {
+            version,
+            text: String::new(),
+            line_starts: vec![0],
+        };
+        document.replace(text);
+        document
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The position just past the last character
+    pub fn end(&self) -> (u32, u32) {
+        let line = self.line_starts.len() - 1;
+        let start = self.line_starts[line];
+        let units: usize = self.text[start..].chars().map(char::len_utf16).sum();
+        (line as u32, units as u32)
+    }
+
+    /// Replace the whole text, as a change without a range does
+    pub fn replace(&mut self, text: String) {
+        self.text = text;
+        self.line_starts.truncate(1);
+        let ends = line_ends(&self.text, 0, self.text.len());
+        self.line_starts.extend(ends);
+    }
+
+    /// Byte offset of an LSP position, clamped to the text. A column past
+    /// the end of its line is the end of that line, before its terminator.
+    pub fn offset_at(&self, line: u32, character: u32) -> usize {
+        let line = line as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let end = self.content_end(line);
+
+        let mut units = 0;
+        for (index, c) in self.text[start..end].char_indices() {
+            if units >= character {
+                return start + index;
+            }
+            units += c.len_utf16() as u32;
+        }
+        end
+    }
+
+    /// Apply an incremental change from `didChange`
+    pub fn apply_change(&mut self, start: (u32, u32), end: (u32, u32), new_text: &str) {
+        let from = self.offset_at(start.0, start.1);
+        let to = self.offset_at(end.0, end.1).max(from);
+        self.text.replace_range(from..to, new_text);
+
+        // Lines starting before the edit are untouched, and rescanning from
+        // the last of them catches a `\r` just before it joining a `\n`
+        // at the start of `new_text`
+        let kept = self.line_starts.partition_point(|&line| line < from).max(1);
+        let rescan = self.line_starts[kept - 1];
+        let inserted_end = from + new_text.len();
+        let shifted: Vec<usize> = self.line_starts[kept..]
+            .iter()
+            .filter(|&&line| line > to)
+            .map(|&line| line - to + inserted_end)
+            .collect();
+
+        self.line_starts.truncate(kept);
+        self.line_starts
+            .extend(line_ends(&self.text, rescan, inserted_end));
+        // A `\r` ending `new_text` may have joined the `\n` after it, which
+        // then already ends the line the rescan found
+        let last = *self.line_starts.last().unwrap();
+        self.line_starts
+            .extend(shifted.into_iter().skip_while(|&line| line <= last));
+    }
+
+    /// Byte offset where the text of `line` ends, before its terminator
+    fn content_end(&self, line: usize) -> usize {
+        match self.line_starts.get(line + 1) {
+            Some(&next) if self.text[..next].ends_with("\r\n") => next - 2,
+            Some(&next) => next - 1,
+            None => self.text.len(),
+        }
+    }
+}
+
+/// Offsets just past the line terminators that begin in `from..to`
+fn line_ends(text: &str, from: usize, to: usize) -> Vec<usize> {
+    let bytes = text.as_bytes();
+    let mut ends = Vec::new();
+    let mut index = from;
+    while index < to {
+        match bytes[index] {
+            b'\r' if bytes.get(index + 1) == Some(&b'\n') => {
+                ends.push(index + 2);
+                index += 2;
+            }
+            b'\r' | b'\n' => {
+                ends.push(index + 1);
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line starts of `text`, found the obvious way
+    fn oracle_lines(text: &str) -> Vec<usize> {
+        let mut starts = vec![0];
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        for (i, &(offset, c)) in chars.iter().enumerate() {
+            let before_lf = chars.get(i + 1).map(|&(_, next)| next) == Some('\n');
+            if c == '\n' || (c == '\r' && !before_lf) {
+                starts.push(offset + 1);
+            }
+        }
+        starts
+    }
+
+    /// Byte offset of a position, found by walking the text from its start
+    fn oracle_offset(text: &str, line: u32, character: u32) -> usize {
+        let starts = oracle_lines(text);
+        let Some(&start) = starts.get(line as usize) else {
+            return text.len();
+        };
+        let rest = &text[start..];
+        let content = &rest[..rest.find(['\r', '\n']).unwrap_or(rest.len())];
+        let mut units = 0;
+        for (index, c) in content.char_indices() {
+            if units >= character {
+                return start + index;
+            }
+            units += c.len_utf16() as u32;
+        }
+        start + content.len()
+    }
+
+    /// xorshift, so a failing sequence can be replayed from its seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    const PIECES: &[&str] = &["a", "let x", " ", "é", "😀", "\n", "\r", "\r\n", "\t", "名"];
+
+    fn random_position(rng: &mut Rng, document: &Document) -> (u32, u32) {
+        let line = rng.below(document.line_count() as u64 + 1) as u32;
+        (line, rng.below(12) as u32)
+    }
+
+    #[test]
+    fn incremental_changes_use_utf16_columns() {
+        let mut document = Document::new("let é = \"😀\"\nprint(é)\n".to_string(), 1);
+        document.apply_change((0, 11), (0, 12), "!");
+        assert_eq!(document.text(), "let é = \"😀!\nprint(é)\n");
+        document.apply_change((1, 0), (2, 0), "");
+        assert_eq!(document.text(), "let é = \"😀!\n");
+    }
+
+    #[test]
+    fn positions_past_the_end_of_a_line_stop_before_its_terminator() {
+        let document = Document::new("ab\r\ncd\re\n".to_string(), 1);
+        assert_eq!(document.line_count(), 4);
+        assert_eq!(document.offset_at(0, 99), 2);
+        assert_eq!(document.offset_at(1, 99), 6);
+        assert_eq!(document.offset_at(2, 1), 8);
+        assert_eq!(document.offset_at(3, 0), 9);
+        assert_eq!(document.offset_at(9, 0), 9);
+        assert_eq!(document.end(), (3, 0));
+        assert_eq!(Document::new("a\né😀".to_string(), 1).end(), (1, 3));
+    }
+
+    #[test]
+    fn carriage_returns_join_and_split_with_the_text_around_them() {
+        let mut document = Document::new("a\rb".to_string(), 1);
+        document.apply_change((1, 0), (1, 0), "\n");
+        assert_eq!(document.text(), "a\r\nb");
+        assert_eq!(document.line_count(), 2);
+
+        document.apply_change((0, 1), (1, 0), "\r");
+        assert_eq!(document.text(), "a\rb");
+        assert_eq!(document.line_count(), 2);
+
+        let mut document = Document::new("a\nb".to_string(), 1);
+        document.apply_change((0, 1), (0, 1), "x\r");
+        assert_eq!(document.text(), "ax\r\nb");
+        assert_eq!(document.line_starts, vec![0, 4]);
+    }
+
+    #[test]
+    fn random_edits_match_a_full_text_oracle() {
+        for seed in 1..=200u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut document = Document::new("let é = 1\r\nprint(é)\n".to_string(), 0);
+            let mut expected = document.text().to_string();
+
+            for step in 0..50 {
+                let start = random_position(&mut rng, &document);
+                let end = random_position(&mut rng, &document);
+                let (start, end) = (start.min(end), start.max(end));
+                let new_text: String = (0..rng.below(4))
+                    .map(|_| PIECES[rng.below(PIECES.len() as u64) as usize])
+                    .collect();
+
+                let from = oracle_offset(&expected, start.0, start.1);
+                let to = oracle_offset(&expected, end.0, end.1).max(from);
+                expected.replace_range(from..to, &new_text);
+                document.apply_change(start, end, &new_text);
+
+                assert_eq!(document.text(), expected, "seed {} step {}", seed, step);
+                assert_eq!(
+                    document.line_starts,
+                    oracle_lines(&expected),
+                    "seed {} step {}",
+                    seed,
+                    step
+                );
+            }
+        }
+    }
+}
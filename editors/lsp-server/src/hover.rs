@@ -0,0 +1,255 @@
+//! What hovering a word shows, as Markdown with the code in `infra` fences.
+//!
+//! Keywords get a description and an example. A name defined in the
+//! document shows its declaration from the symbol table, so a function has
+//! its signature and a variable its declared or inferred type. A library
+//! function, called through its module or as a global, shows its signature
+//! from the reference with its arity and summary from the library's own
+//! table.
+
+use crate::completion::{self, Context};
+use crate::scopes::{NameRange, SymbolTable};
+use infra::core::Value;
+use infra::stdlib::StandardLibrary;
+
+/// Keywords with what they do and an example
+const KEYWORDS: &[(&str, &str, &str)] = &[
+    (
+        "function",
+        "Defines a reusable function in Infra.",
+        "function add(a, b): number {\n  return a + b\n}",
+    ),
+    (
+        "class",
+        "Defines a class for object-oriented programming.",
+        "class Person:\n{\n  init(name):\n    this.name = name\n}",
+    ),
+    (
+        "let",
+        "Declares a variable with optional type annotation.",
+        "let x: number = 42",
+    ),
+    (
+        "if",
+        "Conditional statement.",
+        "if x > 0:\n  print(\"positive\")",
+    ),
+    (
+        "for",
+        "Loop over iterables.",
+        "for item in array:\n  print(item)",
+    ),
+    (
+        "while",
+        "Conditional loop.",
+        "while condition:\n  do_something()",
+    ),
+    (
+        "return",
+        "Returns a value from a function.",
+        "return result",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoverText {
+    pub markdown: String,
+    /// The hovered word
+    pub range: NameRange,
+}
+
+/// What to show for the word at a UTF-16 column of `text`, line `line` of
+/// a document; `symbols` is its table when it parses
+pub fn hover(
+    library: &StandardLibrary,
+    symbols: Option<&SymbolTable>,
+    text: &str,
+    line: u32,
+    character: u32,
+) -> Option<HoverText> {
+    let (word, range) = word_at(text, line, character)?;
+    let context = completion::context(text, range.start);
+    if context == Context::Quiet {
+        return None;
+    }
+
+    if let Some(&(_, description, example)) =
+        KEYWORDS.iter().find(|(keyword, _, _)| *keyword == word)
+    {
+        return Some(HoverText {
+            markdown: format!("{}\n\n{}", description, code(example)),
+            range,
+        });
+    }
+
+    let markdown = match context {
+        // A property, or a function of the library module before the dot
+        Context::Member { object, .. } => {
+            // The object's range ends where the dot starts
+            let shadowed = symbols
+                .and_then(|symbols| symbols.definition_at(line, range.start - 1))
+                .is_some();
+            if shadowed {
+                return None;
+            }
+            library_function(library, &format!("{}.{}", object, word), object, word)?
+        }
+        _ => match symbols.and_then(|symbols| symbols.definition_at(line, character)) {
+            Some(definition) => code(&definition.declaration),
+            None => match library.get_global(word)? {
+                Value::NativeFunction { module, function } => {
+                    library_function(library, word, &module, &function)?
+                }
+                _ => return None,
+            },
+        },
+    };
+    Some(HoverText { markdown, range })
+}
+
+/// The name, keyword or other word a UTF-16 column is in or at the end of
+pub fn word_at(text: &str, line: u32, character: u32) -> Option<(&str, NameRange)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut units = 0;
+    let mut word: Option<(usize, u32)> = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if is_word(c) {
+            word.get_or_insert((index, units));
+        } else if let Some((start, start_units)) = word.take() {
+            if (start_units..=units).contains(&character) {
+                let found = &text[start..index];
+                if found.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                let range = NameRange {
+                    line,
+                    start: start_units,
+                    end: units,
+                };
+                return Some((found, range));
+            }
+        }
+        units += c.len_utf16() as u32;
+    }
+    None
+}
+
+/// `text` in a fenced block of Infra code
+pub fn code(text: &str) -> String {
+    format!("```infra\n{}\n```", text)
+}
+
+/// A library function called as `name`, with its documented parameters
+fn library_function(
+    library: &StandardLibrary,
+    name: &str,
+    module: &str,
+    function: &str,
+) -> Option<String> {
+    let info = library.describe(module, function)?;
+    let qualified = format!("{}.{}", module, function);
+    let signature = match completion::documented_signature(module, function) {
+        Some(signature) => format!("{}{}", name, &signature[qualified.len()..]),
+        None => format!("{}(...)", name),
+    };
+    Some(format!(
+        "{}\n\n{}.\n\nTakes {}.",
+        code(&signature),
+        info.summary,
+        info.arity
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    fn hover_at(text: &str, line: u32, character: u32) -> Option<HoverText> {
+        let program = syntax::parse(text).unwrap();
+        let symbols = SymbolTable::build(text, &program);
+        let line_text = text.lines().nth(line as usize).unwrap();
+        hover(
+            &StandardLibrary::new(),
+            Some(&symbols),
+            line_text,
+            line,
+            character,
+        )
+    }
+
+    #[test]
+    fn functions_show_their_signature_and_variables_their_type() {
+        let text = "function add(a: number, b = 1) -> number: {\n    return a + b\n}\nlet total = add(1, 2)\nlet label: string = \"sum\"\nlet items = [1, 2]\nfor item in items: {\n    print(item)\n}\n";
+
+        let function = hover_at(text, 3, 13).unwrap();
+        assert_eq!(
+            function.markdown,
+            "```infra\nfunction add(a: number, b = 1) -> number\n```"
+        );
+        assert_eq!(
+            function.range,
+            NameRange {
+                line: 3,
+                start: 12,
+                end: 15
+            }
+        );
+
+        let markdown = |line, character| hover_at(text, line, character).unwrap().markdown;
+        assert_eq!(markdown(1, 12), "```infra\na: number\n```");
+        assert_eq!(markdown(3, 6), "```infra\nlet total: number\n```");
+        assert_eq!(markdown(4, 6), "```infra\nlet label: string\n```");
+        assert_eq!(markdown(5, 6), "```infra\nlet items: [number]\n```");
+        assert_eq!(markdown(7, 11), "```infra\nlet item: number\n```");
+    }
+
+    #[test]
+    fn library_calls_show_arity_and_summary() {
+        let text = "let s = string.replace(\"a-b\", \"-\", \"+\")\nprint(len(s))\nlet user = {len: 1}\nprint(user.len)\n";
+
+        let replace = hover_at(text, 0, 17).unwrap();
+        assert_eq!(
+            replace.markdown,
+            "```infra\nstring.replace(s: string, old: string, new: string): string\n```\n\n\
+             The string with every occurrence of a substring replaced.\n\nTakes 3 arguments."
+        );
+        assert_eq!(
+            replace.range,
+            NameRange {
+                line: 0,
+                start: 15,
+                end: 22
+            }
+        );
+
+        let len = hover_at(text, 1, 7).unwrap();
+        assert!(
+            len.markdown.starts_with("```infra\nlen("),
+            "{}",
+            len.markdown
+        );
+        assert!(len.markdown.ends_with("Takes 1 argument."));
+
+        // A property is not the global of the same name
+        assert_eq!(hover_at(text, 3, 12), None);
+    }
+
+    #[test]
+    fn keywords_are_described_but_not_inside_strings() {
+        let text = "let x = \"return\"\nreturn x\n";
+        let keyword = hover_at(text, 1, 2).unwrap();
+        assert!(keyword
+            .markdown
+            .starts_with("Returns a value from a function.\n\n```infra\n"));
+        assert_eq!(
+            keyword.range,
+            NameRange {
+                line: 1,
+                start: 0,
+                end: 6
+            }
+        );
+        assert_eq!(hover_at(text, 0, 11), None);
+    }
+}
@@ -0,0 +1,349 @@
+//! Completion and hover for module paths in import statements.
+//!
+//! The lookups here only scan the current line, so they keep working while
+//! the rest of the document does not parse. Resolution mirrors the
+//! interpreter's `ModuleSystem`: paths starting with `./` or `../` are tried
+//! next to the importing file first, then every search path is tried, each
+//! with and without the `.infra` extension.
+
+use std::path::{Path, PathBuf};
+
+const EXTENSION: &str = "infra";
+
+/// The module path string literal under the cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportPath {
+    /// Text between the opening quote and the cursor
+    pub prefix: String,
+    /// Full contents of the literal (up to the closing quote or end of line)
+    pub path: String,
+    /// Character offset of the first character after the opening quote
+    pub start: usize,
+    /// Character offset of the closing quote, or of the end of the line
+    pub end: usize,
+}
+
+/// A file or directory offered while typing a module path
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathCompletion {
+    pub label: String,
+    pub is_dir: bool,
+}
+
+/// Outcome of resolving a module path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Found(PathBuf),
+    NotFound { attempted: Vec<PathBuf> },
+}
+
+/// Find the import path literal containing `character` on `line`.
+///
+/// A string literal counts when the text before its opening quote ends with
+/// the `from` or `import` keyword; an unterminated literal runs to the end of
+/// the line.
+pub fn import_path_at(line: &str, character: usize) -> Option<ImportPath> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let quote = chars[i];
+        if quote != '"' && quote != '\'' {
+            i += 1;
+            continue;
+        }
+
+        let start = i + 1;
+        let mut end = start;
+        while end < chars.len() && chars[end] != quote {
+            end += 1;
+        }
+
+        if character >= start && character <= end {
+            let before: String = chars[..i].iter().collect();
+            if !follows_import_keyword(&before) {
+                return None;
+            }
+            return Some(ImportPath {
+                prefix: chars[start..character].iter().collect(),
+                path: chars[start..end].iter().collect(),
+                start,
+                end,
+            });
+        }
+
+        i = end + 1;
+    }
+
+    None
+}
+
+fn follows_import_keyword(before: &str) -> bool {
+    let before = before.trim_end();
+    ["from", "import"].iter().any(|keyword| {
+        before.strip_suffix(keyword).is_some_and(|rest| {
+            !rest
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        })
+    })
+}
+
+/// Directories searched for bare module names, in the interpreter's order,
+/// followed by the entries of `INFRA_PATH`
+pub fn search_paths(workspace_root: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![
+        workspace_root.to_path_buf(),
+        workspace_root.join("lib"),
+        workspace_root.join("modules"),
+    ];
+    if let Some(infra_path) = std::env::var_os("INFRA_PATH") {
+        paths.extend(std::env::split_paths(&infra_path));
+    }
+    paths
+}
+
+/// List the `.infra` files and directories that can complete `prefix`.
+///
+/// Directories are labelled with a trailing `/` so the user can keep typing
+/// into them; files are labelled without their extension, since imports
+/// omit it.
+pub fn complete(
+    prefix: &str,
+    document_dir: &Path,
+    search_paths: &[PathBuf],
+) -> Vec<PathCompletion> {
+    let (dir_part, partial) = match prefix.rfind('/') {
+        Some(idx) => (&prefix[..=idx], &prefix[idx + 1..]),
+        None => ("", prefix),
+    };
+
+    let bases: Vec<PathBuf> = if is_relative(prefix) {
+        vec![document_dir.join(dir_part)]
+    } else if prefix.starts_with('.') {
+        // Still typing "./" or "../"
+        return Vec::new();
+    } else {
+        search_paths
+            .iter()
+            .map(|path| path.join(dir_part))
+            .collect()
+    };
+
+    let mut completions: Vec<PathCompletion> = Vec::new();
+    for base in bases {
+        let Ok(entries) = std::fs::read_dir(&base) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if name.starts_with('.') && !partial.starts_with('.') {
+                continue;
+            }
+
+            let completion = if path.is_dir() {
+                PathCompletion {
+                    label: format!("{}/", name),
+                    is_dir: true,
+                }
+            } else if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                PathCompletion {
+                    label: stem.to_string(),
+                    is_dir: false,
+                }
+            } else {
+                continue;
+            };
+
+            if completion.label.starts_with(partial) && !completions.contains(&completion) {
+                completions.push(completion);
+            }
+        }
+    }
+
+    completions.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.label.cmp(&b.label)));
+    completions
+}
+
+/// Resolve a module path the way `ModuleSystem::resolve_module_path` does
+pub fn resolve(module_path: &str, document_dir: &Path, search_paths: &[PathBuf]) -> Resolution {
+    let mut candidates = Vec::new();
+    if is_relative(module_path) {
+        candidates.push(document_dir.join(module_path));
+    }
+    candidates.extend(search_paths.iter().map(|path| path.join(module_path)));
+
+    let mut attempted = Vec::new();
+    for candidate in candidates {
+        for path in [candidate.clone(), candidate.with_extension(EXTENSION)] {
+            if path.is_file() {
+                return Resolution::Found(path);
+            }
+            if !attempted.contains(&path) {
+                attempted.push(path);
+            }
+        }
+    }
+
+    Resolution::NotFound { attempted }
+}
+
+/// Names exported by a module, in source order
+pub fn scan_exports(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim_start().strip_prefix("export")?;
+            let rest = rest.trim_start();
+            let rest = ["function", "def", "let"]
+                .iter()
+                .find_map(|keyword| rest.strip_prefix(keyword))?;
+            if !rest.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let name: String = rest
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            (!name.is_empty()).then_some(name)
+        })
+        .collect()
+}
+
+/// Markdown shown when hovering a module path
+pub fn hover_text(module_path: &str, resolution: &Resolution) -> String {
+    match resolution {
+        Resolution::Found(path) => {
+            let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+            let exports = std::fs::read_to_string(&path)
+                .map(|source| scan_exports(&source))
+                .unwrap_or_default();
+
+            let mut text = format!("**{}**\n\n", path.display());
+            if exports.is_empty() {
+                text.push_str("No exports");
+            } else {
+                text.push_str("Exports:\n");
+                for name in exports {
+                    text.push_str(&format!("- `{}`\n", name));
+                }
+            }
+            text
+        }
+        Resolution::NotFound { attempted } => {
+            let mut text = format!("Cannot resolve module `{}`\n\nTried:\n", module_path);
+            for path in attempted {
+                text.push_str(&format!("- {}\n", path.display()));
+            }
+            text
+        }
+    }
+}
+
+fn is_relative(path: &str) -> bool {
+    path.starts_with("./") || path.starts_with("../")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace")
+    }
+
+    fn labels(completions: &[PathCompletion]) -> Vec<&str> {
+        completions.iter().map(|c| c.label.as_str()).collect()
+    }
+
+    #[test]
+    fn completes_relative_paths_at_several_prefixes() {
+        let root = workspace();
+        let paths = search_paths(&root);
+
+        assert_eq!(
+            labels(&complete("./", &root, &paths)),
+            vec!["lib/", "shapes/", "main", "util"]
+        );
+        assert_eq!(labels(&complete("./s", &root, &paths)), vec!["shapes/"]);
+        assert_eq!(
+            labels(&complete("./shapes/", &root, &paths)),
+            vec!["circle", "square"]
+        );
+        assert_eq!(
+            labels(&complete("./shapes/sq", &root, &paths)),
+            vec!["square"]
+        );
+        assert_eq!(
+            labels(&complete("../", &root.join("shapes"), &paths)),
+            vec!["lib/", "shapes/", "main", "util"]
+        );
+        assert!(complete(".", &root, &paths).is_empty());
+    }
+
+    #[test]
+    fn completes_bare_names_from_search_paths() {
+        let root = workspace();
+        let paths = search_paths(&root);
+
+        assert_eq!(labels(&complete("str", &root, &paths)), vec!["strings"]);
+        assert!(complete("str", &root, &paths).iter().all(|c| !c.is_dir));
+    }
+
+    #[test]
+    fn hover_lists_exports_of_resolved_module() {
+        let root = workspace();
+        let paths = search_paths(&root);
+
+        let resolution = resolve("./util", &root, &paths);
+        assert_eq!(resolution, Resolution::Found(root.join("./util.infra")));
+
+        let text = hover_text("./util", &resolution);
+        let absolute = root.join("util.infra").canonicalize().unwrap();
+        assert!(text.starts_with(&format!("**{}**", absolute.display())));
+        assert!(text.contains("- `greet`\n- `version`\n"));
+    }
+
+    #[test]
+    fn hover_lists_attempted_locations_when_unresolved() {
+        let root = workspace();
+        let paths = vec![root.clone()];
+
+        let resolution = resolve("./missing", &root, &paths);
+        let text = hover_text("./missing", &resolution);
+
+        assert!(text.starts_with("Cannot resolve module `./missing`"));
+        assert!(text.contains(&format!("- {}\n", root.join("./missing.infra").display())));
+        match resolution {
+            Resolution::NotFound { attempted } => assert_eq!(attempted.len(), 2),
+            other => panic!("expected unresolved module, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn finds_import_path_on_incomplete_lines() {
+        let line = "import {area} from \"./sha";
+        let found = import_path_at(line, line.chars().count()).unwrap();
+        assert_eq!(found.prefix, "./sha");
+        assert_eq!(found.start, 20);
+        assert_eq!(found.end, 25);
+
+        let line = "import * from './shapes/circle'";
+        let found = import_path_at(line, 18).unwrap();
+        assert_eq!(found.prefix, "./s");
+        assert_eq!(found.path, "./shapes/circle");
+
+        assert_eq!(import_path_at("import \"", 8).unwrap().prefix, "");
+        assert!(import_path_at("print(\"./util\")", 9).is_none());
+        assert!(import_path_at("let platform = \"./x\"", 18).is_none());
+    }
+}
@@ -0,0 +1,547 @@
+//! Workspace symbol index for cross-file navigation.
+//!
+//! After `initialized` every `.infra` file in the workspace is scanned on a
+//! small pool of background threads, recording what each file defines,
+//! exports and imports. The result is saved under `.infra-cache/lsp-index`
+//! keyed by a hash of each file's content, so the next session only rescans
+//! files that changed. Go-to-definition and workspace symbols answer from
+//! the index and scan a file on demand only when it has not been indexed yet.
+//!
+//! Like the rest of the server's scanning, this works on plain text, so a
+//! file that does not parse still contributes what can be read from it.
+
+use crate::analysis::{self, Symbol, SymbolKind};
+use crate::import_paths::{self, Resolution};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::thread;
+
+/// Bump when the cache format or what gets recorded changes; an index
+/// written by another version is discarded and rebuilt
+pub const INDEX_VERSION: u32 = 1;
+
+/// The cache file, relative to the workspace root
+pub const CACHE_FILE: &str = ".infra-cache/lsp-index";
+
+/// What one file contributes to the index
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    /// FNV-1a hash of the content the entry was built from
+    pub hash: u64,
+    pub symbols: Vec<Symbol>,
+    pub exports: Vec<String>,
+    /// Module paths as written in the file's import statements
+    pub imports: Vec<String>,
+}
+
+impl FileEntry {
+    fn scan(text: &str) -> Self {
+        Self {
+            hash: content_hash(text),
+            symbols: analysis::scan(text).symbols,
+            exports: import_paths::scan_exports(text),
+            imports: scan_imports(text),
+        }
+    }
+
+    fn definition(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+}
+
+/// Where a name is defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub path: PathBuf,
+    pub symbol: Symbol,
+}
+
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    cache_file: Option<PathBuf>,
+    files: RwLock<HashMap<PathBuf, FileEntry>>,
+    /// Files scanned by this index, in the background or on demand
+    parses: AtomicUsize,
+}
+
+impl WorkspaceIndex {
+    /// An index persisted in `cache_file`, starting from what the previous
+    /// session saved there. A missing, unreadable or outdated cache starts
+    /// empty and is overwritten on the next save.
+    pub fn load(cache_file: PathBuf) -> Self {
+        let files = std::fs::read_to_string(&cache_file)
+            .ok()
+            .and_then(|text| decode(&text))
+            .unwrap_or_default();
+        Self {
+            cache_file: Some(cache_file),
+            files: RwLock::new(files),
+            parses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(cache_file) = &self.cache_file else {
+            return Ok(());
+        };
+        if let Some(dir) = cache_file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = encode(&self.files.read().unwrap());
+        let partial = cache_file.with_extension("tmp");
+        std::fs::write(&partial, text)?;
+        std::fs::rename(&partial, cache_file)
+    }
+
+    /// How many files this index has scanned
+    pub fn parse_count(&self) -> usize {
+        self.parses.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn entry(&self, path: &Path) -> Option<FileEntry> {
+        self.files.read().unwrap().get(path).cloned()
+    }
+
+    /// Bring the index in line with `files`, the workspace's current
+    /// `.infra` files: scan the ones whose content changed since they were
+    /// indexed and forget the ones that are gone. Runs on at most half the
+    /// cores so foreground requests keep the rest; `progress` is called
+    /// with the number of files done and the total. Returns how many files
+    /// were scanned.
+    pub fn index_files(&self, files: &[PathBuf], progress: impl Fn(usize, usize) + Sync) -> usize {
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        self.files
+            .write()
+            .unwrap()
+            .retain(|path, _| present.contains(path));
+
+        let workers = thread::available_parallelism().map_or(1, |n| (n.get() / 2).max(1));
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..workers.min(files.len()) {
+                scope.spawn(|| {
+                    while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if self.update(path) {
+                            scanned.fetch_add(1, Ordering::Relaxed);
+                        }
+                        progress(done.fetch_add(1, Ordering::Relaxed) + 1, files.len());
+                    }
+                });
+            }
+        });
+
+        scanned.into_inner()
+    }
+
+    /// Re-index `path` after it was created or changed. Returns whether it
+    /// had to be scanned; an unreadable file is dropped from the index.
+    pub fn update(&self, path: &Path) -> bool {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            self.remove(path);
+            return false;
+        };
+        let hash = content_hash(&text);
+        if self.files.read().unwrap().get(path).map(|entry| entry.hash) == Some(hash) {
+            return false;
+        }
+
+        self.parses.fetch_add(1, Ordering::Relaxed);
+        let entry = FileEntry::scan(&text);
+        self.files.write().unwrap().insert(path.to_path_buf(), entry);
+        true
+    }
+
+    pub fn remove(&self, path: &Path) {
+        self.files.write().unwrap().remove(path);
+    }
+
+    /// The entry for `path`, scanning the file first if it is not indexed
+    fn entry_or_scan(&self, path: &Path) -> Option<FileEntry> {
+        if let Some(entry) = self.entry(path) {
+            return Some(entry);
+        }
+        self.update(path);
+        self.entry(path)
+    }
+
+    /// Where `name`, used in the document at `document` whose current text
+    /// is `text`, is defined in another file. Modules the document imports
+    /// are checked first; failing that, every indexed file exporting the
+    /// name is a candidate.
+    pub fn definition(
+        &self,
+        document: &Path,
+        text: &str,
+        name: &str,
+        search_paths: &[PathBuf],
+    ) -> Vec<Definition> {
+        let document_dir = document.parent().unwrap_or(Path::new("."));
+        for module_path in scan_imports(text) {
+            let Resolution::Found(path) =
+                import_paths::resolve(&module_path, document_dir, search_paths)
+            else {
+                continue;
+            };
+            let Some(entry) = self.entry_or_scan(&path) else {
+                continue;
+            };
+            if !entry.exports.iter().any(|export| export == name) {
+                continue;
+            }
+            if let Some(symbol) = entry.definition(name) {
+                return vec![Definition {
+                    path,
+                    symbol: symbol.clone(),
+                }];
+            }
+        }
+
+        let files = self.files.read().unwrap();
+        let mut definitions: Vec<Definition> = files
+            .iter()
+            .filter(|(path, entry)| {
+                path.as_path() != document && entry.exports.iter().any(|export| export == name)
+            })
+            .filter_map(|(path, entry)| {
+                Some(Definition {
+                    path: path.clone(),
+                    symbol: entry.definition(name)?.clone(),
+                })
+            })
+            .collect();
+        definitions.sort_by(|a, b| a.path.cmp(&b.path));
+        definitions
+    }
+
+    /// The symbols in `files` whose name contains `query`, ignoring case,
+    /// for `workspace/symbol`. Files not indexed yet are scanned now.
+    pub fn symbols_matching(&self, files: &[PathBuf], query: &str) -> Vec<Definition> {
+        let query = query.to_lowercase();
+        let mut found = Vec::new();
+        for path in files {
+            let Some(entry) = self.entry_or_scan(path) else {
+                continue;
+            };
+            found.extend(
+                entry
+                    .symbols
+                    .into_iter()
+                    .filter(|symbol| symbol.name.to_lowercase().contains(&query))
+                    .map(|symbol| Definition {
+                        path: path.clone(),
+                        symbol,
+                    }),
+            );
+        }
+        found
+    }
+}
+
+/// Module paths of the import statements in `text`, in source order
+pub fn scan_imports(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let rest = line.trim_start().strip_prefix("import")?;
+            if !rest.starts_with(|c: char| c.is_whitespace() || c == '{' || c == '"') {
+                return None;
+            }
+            let start = rest.find(['"', '\''])?;
+            let quote = rest[start..].chars().next()?;
+            let literal = &rest[start + 1..];
+            let end = literal.find(quote)?;
+            Some(literal[..end].to_string())
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a, stable across runs and toolchains unlike `DefaultHasher`
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn kind_name(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "function",
+        SymbolKind::Class => "class",
+        SymbolKind::Variable => "variable",
+        SymbolKind::Enum => "enum",
+    }
+}
+
+/// One tab-separated record per line, after a version header. Each `file`
+/// record is followed by the `def`, `export` and `import` records of that
+/// file; a symbol's detail comes last since it may itself contain tabs.
+fn encode(files: &HashMap<PathBuf, FileEntry>) -> String {
+    let mut paths: Vec<&PathBuf> = files.keys().collect();
+    paths.sort();
+
+    let mut out = format!("infra-lsp-index\t{}\n", INDEX_VERSION);
+    for path in paths {
+        let entry = &files[path];
+        out.push_str(&format!("file\t{:016x}\t{}\n", entry.hash, path.display()));
+        for symbol in &entry.symbols {
+            out.push_str(&format!(
+                "def\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                kind_name(symbol.kind),
+                symbol.line,
+                symbol.start,
+                symbol.end,
+                symbol.name,
+                symbol.detail
+            ));
+        }
+        for export in &entry.exports {
+            out.push_str(&format!("export\t{}\n", export));
+        }
+        for import in &entry.imports {
+            out.push_str(&format!("import\t{}\n", import));
+        }
+    }
+    out
+}
+
+/// The inverse of `encode`; `None` for anything it would not have written
+fn decode(text: &str) -> Option<HashMap<PathBuf, FileEntry>> {
+    let mut lines = text.lines();
+    if lines.next()? != format!("infra-lsp-index\t{}", INDEX_VERSION) {
+        return None;
+    }
+
+    let mut files = HashMap::new();
+    let mut current: Option<(PathBuf, FileEntry)> = None;
+    for line in lines {
+        let (tag, rest) = line.split_once('\t')?;
+        if tag == "file" {
+            let (hash, path) = rest.split_once('\t')?;
+            let entry = FileEntry {
+                hash: u64::from_str_radix(hash, 16).ok()?,
+                symbols: Vec::new(),
+                exports: Vec::new(),
+                imports: Vec::new(),
+            };
+            files.extend(current.replace((PathBuf::from(path), entry)));
+            continue;
+        }
+
+        let (_, entry) = current.as_mut()?;
+        match tag {
+            "def" => {
+                let fields: Vec<&str> = rest.splitn(6, '\t').collect();
+                let [kind, line, start, end, name, detail] = fields[..] else {
+                    return None;
+                };
+                entry.symbols.push(Symbol {
+                    name: name.to_string(),
+                    kind: match kind {
+                        "function" => SymbolKind::Function,
+                        "class" => SymbolKind::Class,
+                        "variable" => SymbolKind::Variable,
+                        "enum" => SymbolKind::Enum,
+                        _ => return None,
+                    },
+                    line: line.parse().ok()?,
+                    start: start.parse().ok()?,
+                    end: end.parse().ok()?,
+                    detail: detail.to_string(),
+                });
+            }
+            "export" => entry.exports.push(rest.to_string()),
+            "import" => entry.imports.push(rest.to_string()),
+            _ => return None,
+        }
+    }
+    files.extend(current);
+    Some(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn workspace() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace")
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "infra-lsp-index-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The `.infra` files under `dir`, as the server's walker would list them
+    fn infra_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(infra_files(&path));
+            } else if path.extension().is_some_and(|ext| ext == "infra") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap().flatten() {
+            let target = to.join(entry.file_name());
+            if entry.path().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                std::fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn builds_over_workspace_and_starts_warm_from_cache() {
+        let root = workspace();
+        let cache = scratch("warm").join("lsp-index");
+        let files = infra_files(&root);
+
+        let index = WorkspaceIndex::load(cache.clone());
+        let reported = Mutex::new(Vec::new());
+        let scanned = index.index_files(&files, |done, total| {
+            reported.lock().unwrap().push((done, total));
+        });
+        assert_eq!(scanned, 5);
+        assert_eq!(index.len(), 5);
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort();
+        assert_eq!(reported.last(), Some(&(5, 5)));
+
+        let util = index.entry(&root.join("util.infra")).unwrap();
+        assert_eq!(util.exports, vec!["greet", "version"]);
+        let main = index.entry(&root.join("main.infra")).unwrap();
+        assert_eq!(main.imports, vec!["./util", "./shapes/circle"]);
+        index.save().unwrap();
+
+        let warm = WorkspaceIndex::load(cache.clone());
+        assert_eq!(warm.len(), 5);
+        assert_eq!(warm.entry(&root.join("util.infra")), Some(util));
+        assert_eq!(warm.index_files(&files, |_, _| {}), 0);
+        assert_eq!(warm.parse_count(), 0);
+
+        // A corrupt or outdated cache is silently discarded
+        std::fs::write(&cache, "infra-lsp-index\t0\nfile\tzz\n").unwrap();
+        assert!(WorkspaceIndex::load(cache.clone()).is_empty());
+        std::fs::write(&cache, "garbage").unwrap();
+        assert!(WorkspaceIndex::load(cache).is_empty());
+    }
+
+    #[test]
+    fn cross_file_definition_is_answered_from_the_index() {
+        let root = workspace();
+        let cache = scratch("definition").join("lsp-index");
+        let files = infra_files(&root);
+        let built = WorkspaceIndex::load(cache.clone());
+        built.index_files(&files, |_, _| {});
+        built.save().unwrap();
+
+        let index = WorkspaceIndex::load(cache);
+        let document = root.join("main.infra");
+        let text = std::fs::read_to_string(&document).unwrap();
+
+        // `area` is exported by both shapes; the import picks the circle
+        let area = index.definition(&document, &text, "area", &[]);
+        assert_eq!(area.len(), 1);
+        assert_eq!(area[0].path, root.join("shapes/circle.infra"));
+        assert_eq!((area[0].symbol.line, area[0].symbol.start), (0, 16));
+
+        // Not imported: every module exporting it is offered
+        let shout = index.definition(&document, &text, "shout", &[]);
+        assert_eq!(shout.len(), 1);
+        assert_eq!(shout[0].path, root.join("lib/strings.infra"));
+        assert!(index.definition(&document, &text, "print", &[]).is_empty());
+
+        assert_eq!(index.parse_count(), 0);
+    }
+
+    #[test]
+    fn watcher_events_update_the_index() {
+        let root = scratch("watch").join("workspace");
+        copy_dir(&workspace(), &root);
+        let index = WorkspaceIndex::load(root.join(CACHE_FILE));
+        index.index_files(&infra_files(&root), |_, _| {});
+        let document = root.join("main.infra");
+        let text = "import {farewell} from \"./util\"\nfarewell()\n";
+        assert!(index.definition(&document, text, "farewell", &[]).is_empty());
+
+        let util = root.join("util.infra");
+        std::fs::write(
+            &util,
+            "export function greet(name):\n    return name\n\nexport function farewell():\n    return \"bye\"\n",
+        )
+        .unwrap();
+        assert!(index.update(&util));
+        assert!(!index.update(&util));
+        let farewell = index.definition(&document, text, "farewell", &[]);
+        assert_eq!(farewell.len(), 1);
+        assert_eq!(farewell[0].symbol.line, 3);
+        assert_eq!(index.parse_count(), 6);
+
+        let square = root.join("shapes/square.infra");
+        std::fs::remove_file(&square).unwrap();
+        index.remove(&square);
+        assert_eq!(index.len(), 4);
+
+        // An unindexed file is scanned on demand
+        std::fs::write(root.join("extra.infra"), "export let answer = 42\n").unwrap();
+        let answer = index.definition(
+            &document,
+            "import {answer} from \"./extra\"\n",
+            "answer",
+            &[],
+        );
+        assert_eq!(answer[0].path, root.join("extra.infra"));
+        assert_eq!(index.parse_count(), 7);
+    }
+
+    #[test]
+    fn workspace_symbols_match_case_insensitively_and_scan_lazily() {
+        let root = workspace();
+        let index = WorkspaceIndex::default();
+        let files = infra_files(&root);
+
+        let found: Vec<(PathBuf, String)> = index
+            .symbols_matching(&files, "AR")
+            .into_iter()
+            .map(|definition| (definition.path, definition.symbol.name))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (root.join("shapes/circle.infra"), "area".to_string()),
+                (root.join("shapes/square.infra"), "area".to_string()),
+            ]
+        );
+        assert_eq!(index.parse_count(), 5);
+
+        let all = index.symbols_matching(&files, "");
+        assert_eq!(all.len(), 5);
+        assert_eq!(index.parse_count(), 5);
+    }
+}
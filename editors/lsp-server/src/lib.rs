@@ -1,9 +1,12 @@
 use async_trait::async_trait;
 use dashmap::DashMap;
-use log::{debug, error, info, warn};
-use serde_json::Value;
+use infra::frontend::{formatter, printer, unused_imports};
+use infra::stdlib::StandardLibrary;
+use log::{error, info, warn};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use tower_lsp::{
     jsonrpc::{Error, Result},
     lsp_types::{
@@ -12,23 +15,329 @@ use tower_lsp::{
     Client, LanguageServer,
 };
 
+pub mod analysis;
+pub mod auto_import;
+pub mod completion;
+pub mod document;
+pub mod hover;
+pub mod import_paths;
+pub mod index;
+pub mod literal_shapes;
+pub mod outline;
+pub mod rename;
+pub mod scopes;
+pub mod syntax;
+
 pub struct Server {
     client: Arc<Client>,
-    documents: Arc<DashMap<lsp_types::Url, lsp_types::TextDocumentItem>>,
+    documents: Arc<DashMap<lsp_types::Url, document::Document>>,
+    workspace_root: Arc<RwLock<Option<PathBuf>>>,
+    analyzer: Arc<analysis::Analyzer>,
+    /// The last semantic tokens sent per document, for delta requests
+    semantic_tokens: Arc<DashMap<lsp_types::Url, (String, Vec<u32>)>>,
+    next_result_id: AtomicU64,
+    /// Cross-file symbols, replaced by a persisted one once the workspace
+    /// root is known
+    index: Arc<RwLock<Arc<index::WorkspaceIndex>>>,
 }
 
 impl Server {
-    pub fn new() -> Self {
+    pub fn new(client: Client) -> Self {
+        Self::with_analysis_config(client, analysis::AnalysisConfig::default())
+    }
+
+    /// The server `LspService::new` builds around the client it hands over
+    pub fn with_analysis_config(client: Client, config: analysis::AnalysisConfig) -> Self {
+        let client = Arc::new(client);
+
+        // Publish diagnostics once a pass completes, and have the client
+        // re-request tokens that were answered from a partial result
+        let runtime = tokio::runtime::Handle::current();
+        let publisher = Arc::clone(&client);
+        let analyzer = analysis::Analyzer::new(config, move |completed| {
+            let client = Arc::clone(&publisher);
+            runtime.spawn(async move {
+                let Ok(uri) = lsp_types::Url::parse(&completed.uri) else {
+                    return;
+                };
+                let diagnostics = completed
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| Self::to_diagnostic(&uri, diagnostic))
+                    .collect();
+                client
+                    .publish_diagnostics(uri, diagnostics, Some(completed.version))
+                    .await;
+                if completed.mode == analysis::Mode::Full {
+                    let _ = client.semantic_tokens_refresh().await;
+                }
+            });
+        });
+
         Self {
-            client: Arc::new(Client::new()),
+            client,
             documents: Arc::new(DashMap::new()),
+            workspace_root: Arc::new(RwLock::new(None)),
+            analyzer: Arc::new(analyzer),
+            semantic_tokens: Arc::new(DashMap::new()),
+            next_result_id: AtomicU64::new(0),
+            index: Arc::new(RwLock::new(Arc::default())),
+        }
+    }
+
+    fn to_diagnostic(uri: &lsp_types::Url, diagnostic: &analysis::Diagnostic) -> Diagnostic {
+        let range = Range::new(
+            Position::new(diagnostic.line, diagnostic.start),
+            Position::new(diagnostic.line, diagnostic.end),
+        );
+        Diagnostic {
+            range,
+            severity: Some(if diagnostic.warning {
+                DiagnosticSeverity::WARNING
+            } else {
+                DiagnosticSeverity::ERROR
+            }),
+            source: Some("infra".to_string()),
+            message: diagnostic.message.clone(),
+            related_information: diagnostic.hint.as_ref().map(|hint| {
+                vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), range),
+                    message: hint.clone(),
+                }]
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Hand the current text of `uri` to the background analyzer
+    async fn analyze(&self, uri: &lsp_types::Url) {
+        let Some((version, text)) = self
+            .documents
+            .get(uri)
+            .map(|doc| (doc.version, Arc::<str>::from(doc.text())))
+        else {
+            return;
+        };
+
+        let submitted = self.analyzer.submit(uri.as_str(), version, text);
+        if let Some(warning) = submitted.warning {
+            self.client.show_message(MessageType::WARNING, warning).await;
+        }
+    }
+
+    fn semantic_tokens_result(&self, uri: &lsp_types::Url, data: Vec<u32>) -> SemanticTokens {
+        let result_id = self.next_result_id.fetch_add(1, Ordering::Relaxed).to_string();
+        self.semantic_tokens
+            .insert(uri.clone(), (result_id.clone(), data.clone()));
+        SemanticTokens {
+            result_id: Some(result_id),
+            data: Self::semantic_token_data(&data),
         }
     }
 
-    fn uri_to_path(uri: &lsp_types::Url) -> Option<String> {
+    fn semantic_token_data(data: &[u32]) -> Vec<SemanticToken> {
+        data.chunks_exact(5)
+            .map(|token| SemanticToken {
+                delta_line: token[0],
+                delta_start: token[1],
+                length: token[2],
+                token_type: token[3],
+                token_modifiers_bitset: token[4],
+            })
+            .collect()
+    }
+
+    fn document_dir(uri: &lsp_types::Url) -> Option<PathBuf> {
         uri.to_file_path()
             .ok()
-            .and_then(|path| path.to_str().map(|s| s.to_string()))
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+    }
+
+    fn index(&self) -> Arc<index::WorkspaceIndex> {
+        Arc::clone(&self.index.read().unwrap())
+    }
+
+    fn symbol_location(uri: lsp_types::Url, symbol: &analysis::Symbol) -> Location {
+        Location::new(
+            uri,
+            Range::new(
+                Position::new(symbol.line, symbol.start),
+                Position::new(symbol.line, symbol.end),
+            ),
+        )
+    }
+
+    fn name_location(uri: lsp_types::Url, range: scopes::NameRange) -> Location {
+        Location::new(
+            uri,
+            Range::new(
+                Position::new(range.line, range.start),
+                Position::new(range.line, range.end),
+            ),
+        )
+    }
+
+    fn completion_item(candidate: completion::Candidate) -> CompletionItem {
+        let kind = match candidate.kind {
+            completion::CandidateKind::Keyword => CompletionItemKind::KEYWORD,
+            completion::CandidateKind::Function => CompletionItemKind::FUNCTION,
+            completion::CandidateKind::Module => CompletionItemKind::MODULE,
+            completion::CandidateKind::Variable => CompletionItemKind::VARIABLE,
+            completion::CandidateKind::Class => CompletionItemKind::CLASS,
+            completion::CandidateKind::Enum => CompletionItemKind::ENUM,
+        };
+        CompletionItem {
+            label: candidate.label,
+            kind: Some(kind),
+            detail: Some(candidate.detail),
+            ..Default::default()
+        }
+    }
+
+    fn symbol_kind(kind: analysis::SymbolKind) -> lsp_types::SymbolKind {
+        match kind {
+            analysis::SymbolKind::Function => lsp_types::SymbolKind::FUNCTION,
+            analysis::SymbolKind::Class => lsp_types::SymbolKind::CLASS,
+            analysis::SymbolKind::Variable => lsp_types::SymbolKind::VARIABLE,
+            analysis::SymbolKind::Enum => lsp_types::SymbolKind::ENUM,
+        }
+    }
+
+    fn outline_symbol(symbol: &outline::OutlineSymbol) -> DocumentSymbol {
+        let range = |range: outline::TextRange| {
+            Range::new(
+                Position::new(range.start.0, range.start.1),
+                Position::new(range.end.0, range.end.1),
+            )
+        };
+        let children = symbol.children.iter().map(Self::outline_symbol).collect();
+        #[allow(deprecated)]
+        DocumentSymbol {
+            name: symbol.name.clone(),
+            detail: symbol.detail.clone(),
+            kind: match symbol.kind {
+                outline::OutlineKind::Function => lsp_types::SymbolKind::FUNCTION,
+                outline::OutlineKind::Method => lsp_types::SymbolKind::METHOD,
+                outline::OutlineKind::Field => lsp_types::SymbolKind::FIELD,
+                outline::OutlineKind::Class => lsp_types::SymbolKind::CLASS,
+                outline::OutlineKind::Variable => lsp_types::SymbolKind::VARIABLE,
+                outline::OutlineKind::Enum => lsp_types::SymbolKind::ENUM,
+            },
+            tags: None,
+            deprecated: None,
+            range: range(symbol.range),
+            selection_range: range(symbol.selection),
+            children: Some(children),
+        }
+    }
+
+    /// The `.infra` files under `root`, skipping hidden directories and
+    /// anything `.gitignore` excludes
+    fn workspace_files(root: &Path) -> Vec<PathBuf> {
+        ignore::WalkBuilder::new(root)
+            .require_git(false)
+            .build()
+            .flatten()
+            .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+            .map(|entry| entry.into_path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "infra"))
+            .collect()
+    }
+
+    /// Bring the workspace index up to date in the background, reporting
+    /// progress to clients that support it
+    async fn build_index(client: Arc<Client>, index: Arc<index::WorkspaceIndex>, root: PathBuf) {
+        let token = NumberOrString::String("infra/index".to_string());
+        let report = client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .is_ok();
+        // Each notification owns its client, so the future outlives the call
+        let progress = |value: WorkDoneProgress| {
+            let client = Arc::clone(&client);
+            let params = ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            };
+            async move { client.send_notification::<notification::Progress>(params).await }
+        };
+
+        if report {
+            progress(
+                WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: "Indexing workspace".to_string(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                }),
+            )
+            .await;
+        }
+
+        let runtime = tokio::runtime::Handle::current();
+        let reporter = Arc::clone(&client);
+        let reported = std::sync::atomic::AtomicU32::new(0);
+        let token_for_worker = token.clone();
+        let indexed = tokio::task::spawn_blocking(move || {
+            let files = Self::workspace_files(&root);
+            let scanned = index.index_files(&files, |done, total| {
+                let percentage = (done * 100 / total.max(1)) as u32;
+                if !report || reported.swap(percentage, Ordering::Relaxed) == percentage {
+                    return;
+                }
+                let client = Arc::clone(&reporter);
+                let params = ProgressParams {
+                    token: token_for_worker.clone(),
+                    value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(
+                        WorkDoneProgressReport {
+                            cancellable: Some(false),
+                            message: Some(format!("{}/{} files", done, total)),
+                            percentage: Some(percentage),
+                        },
+                    )),
+                };
+                runtime.spawn(async move {
+                    client.send_notification::<notification::Progress>(params).await
+                });
+            });
+            if let Err(err) = index.save() {
+                warn!("Could not save the workspace index: {}", err);
+            }
+            (files.len(), scanned)
+        })
+        .await;
+
+        let message = match indexed {
+            Ok((files, scanned)) => {
+                info!("Indexed {} files, {} rescanned", files, scanned);
+                format!("{} files", files)
+            }
+            Err(err) => {
+                error!("Workspace indexing failed: {}", err);
+                "failed".to_string()
+            }
+        };
+        if report {
+            progress(
+                WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: Some(message),
+                }),
+            )
+            .await;
+        }
+    }
+
+    /// Module search paths, rooted at the workspace when one is open
+    fn module_search_paths(&self, document_dir: &Path) -> Vec<PathBuf> {
+        let root = self
+            .workspace_root
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| document_dir.to_path_buf());
+        import_paths::search_paths(&root)
     }
 }
 
@@ -37,9 +346,15 @@ impl LanguageServer for Server {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
         info!("Initializing LSP server for Infra");
 
+        if let Some(root) = params.root_uri.as_ref().and_then(|uri| uri.to_file_path().ok()) {
+            *self.index.write().unwrap() =
+                Arc::new(index::WorkspaceIndex::load(root.join(index::CACHE_FILE)));
+            *self.workspace_root.write().unwrap() = Some(root);
+        }
+
         let capabilities = ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                TextDocumentSyncKind::Incremental,
+                TextDocumentSyncKind::INCREMENTAL,
             )),
             completion_provider: Some(CompletionOptions {
                 resolve_provider: Some(false),
@@ -53,6 +368,7 @@ impl LanguageServer for Server {
                 ]),
                 work_done_progress_options: Default::default(),
                 all_commit_characters: None,
+                completion_item: None,
             }),
             hover_provider: Some(HoverProviderCapability::Simple(true)),
             definition_provider: Some(OneOf::Left(true)),
@@ -60,7 +376,7 @@ impl LanguageServer for Server {
             document_highlight_provider: Some(OneOf::Left(true)),
             document_symbol_provider: Some(OneOf::Left(true)),
             workspace_symbol_provider: Some(OneOf::Left(true)),
-            code_action_provider: Some(CodeActionProviderCapability::Simple(
+            code_action_provider: Some(CodeActionProviderCapability::Options(
                 CodeActionOptions {
                     code_action_kinds: Some(vec![
                         CodeActionKind::QUICKFIX,
@@ -81,11 +397,26 @@ impl LanguageServer for Server {
                 retrigger_characters: None,
                 work_done_progress_options: Default::default(),
             }),
-            rename_provider: Some(OneOf::Left(true)),
-            prepare_rename_provider: None,
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            })),
             execute_command_provider: None,
             workspace: None,
-            semantic_tokens_provider: None,
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    legend: SemanticTokensLegend {
+                        token_types: analysis::TokenKind::LEGEND
+                            .iter()
+                            .map(|name| SemanticTokenType::new(name))
+                            .collect(),
+                        token_modifiers: Vec::new(),
+                    },
+                    range: Some(true),
+                    full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                    work_done_progress_options: Default::default(),
+                }),
+            ),
             moniker_provider: None,
             linked_editing_range_provider: None,
             call_hierarchy_provider: None,
@@ -95,7 +426,7 @@ impl LanguageServer for Server {
             folding_range_provider: None,
             selection_range_provider: None,
             declaration_provider: None,
-            workspace_folders_provider: None,
+            ..Default::default()
         };
 
         Ok(InitializeResult {
@@ -109,6 +440,26 @@ impl LanguageServer for Server {
 
     async fn initialized(&self, _: InitializedParams) {
         info!("LSP server initialized");
+
+        let watchers = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.infra".to_string()),
+                kind: None,
+            }],
+        };
+        let registration = Registration {
+            id: "infra-watch".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(watchers).ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            warn!("Could not watch workspace files: {}", err);
+        }
+
+        let Some(root) = self.workspace_root.read().unwrap().clone() else {
+            return;
+        };
+        tokio::spawn(Self::build_index(Arc::clone(&self.client), self.index(), root));
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -118,95 +469,130 @@ impl LanguageServer for Server {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let text_document = params.text_document;
-        let uri = text_document.uri.clone();
+        let uri = text_document.uri;
 
-        self.documents.insert(uri, text_document);
+        self.documents.insert(
+            uri.clone(),
+            document::Document::new(text_document.text, text_document.version),
+        );
+        self.analyze(&uri).await;
         info!("Opened document: {}", uri);
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
 
-        if let Some(doc) = self.documents.get_mut(&uri) {
+        if let Some(mut doc) = self.documents.get_mut(&uri) {
+            doc.version = params.text_document.version;
             for change in params.content_changes {
-                match change {
-                    TextDocumentContentChangeEvent::Full { text } => {
-                        doc.text = text;
-                    }
-                    TextDocumentContentChangeEvent::Incremental { range, text: change_text } => {
-                        // For simplicity, we'll handle full text changes only for now
-                        // In a full implementation, you'd apply incremental changes
-                        warn!("Incremental changes not fully implemented");
-                    }
+                match change.range {
+                    None => doc.replace(change.text),
+                    Some(range) => doc.apply_change(
+                        (range.start.line, range.start.character),
+                        (range.end.line, range.end.character),
+                        &change.text,
+                    ),
+                }
+            }
+        }
+        self.analyze(&uri).await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let index = self.index();
+        let updated = tokio::task::spawn_blocking(move || {
+            for change in params.changes {
+                let Ok(path) = change.uri.to_file_path() else {
+                    continue;
+                };
+                if change.typ == FileChangeType::DELETED {
+                    index.remove(&path);
+                } else {
+                    index.update(&path);
                 }
             }
+            index.save()
+        })
+        .await;
+        if let Ok(Err(err)) = updated {
+            warn!("Could not save the workspace index: {}", err);
         }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri;
         self.documents.remove(&uri);
+        self.semantic_tokens.remove(&uri);
+        self.analyzer.close(uri.as_str());
+        self.client.publish_diagnostics(uri.clone(), Vec::new(), None).await;
         info!("Closed document: {}", uri);
     }
 
-    async fn completion(&self, params: CompletionParams) -> Result<Option<Vec<CompletionItem>>> {
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         let uri = params.text_document_position.text_document.uri;
         let position = params.text_document_position.position;
 
         let Some(doc) = self.documents.get(&uri) else {
             return Ok(None);
-        }
-
-        let text = &doc.text;
-        let lines: Vec<&str> = text.lines().collect();
+        };
 
-        if position.line >= lines.len() {
+        let Some(current_line) = doc.text().lines().nth(position.line as usize) else {
             return Ok(None);
-        }
+        };
 
-        let current_line = lines[position.line];
-        let line_prefix = &current_line[..position.character.min(current_line.len())];
-
-        let mut completions = Vec::new();
-
-        // Basic keyword completions
-        let keywords = vec![
-            "function", "class", "let", "return", "if", "else", "for", "while",
-            "break", "continue", "import", "export", "and", "or", "not", "in",
-            "await", "async", "true", "false", "nil"
-        ];
-
-        for keyword in keywords {
-            if keyword.starts_with(line_prefix) {
-                completions.push(CompletionItem {
-                    label: keyword.to_string(),
-                    kind: Some(CompletionItemKind::KEYWORD),
-                    detail: Some("Infra keyword".to_string()),
-                    documentation: Some(Documentation::String(format!("Infra keyword: {}", keyword))),
-                    ..Default::default()
-                });
-            }
+        if let Some(import) = import_paths::import_path_at(current_line, position.character as usize) {
+            return Ok(Some(CompletionResponse::Array(
+                self.import_path_completions(&uri, &import, position),
+            )));
         }
 
-        // Function completions
-        let functions = vec![
-            "print", "len", "abs", "max", "min", "round", "floor", "ceil",
-            "type", "str", "int", "float", "bool", "array", "object",
-        ];
-
-        for function in functions {
-            if function.starts_with(line_prefix) {
-                completions.push(CompletionItem {
-                    label: function.to_string(),
-                    kind: Some(CompletionItemKind::FUNCTION),
-                    detail: Some("Built-in function".to_string()),
-                    documentation: Some(Documentation::String(format!("Built-in function: {}", function))),
-                    ..Default::default()
+        let library = StandardLibrary::new();
+        let items = match completion::context(current_line, position.character) {
+            completion::Context::Quiet => return Ok(None),
+            completion::Context::Member { object, prefix } => {
+                // After `Status.`: the enum's members and generated helpers
+                let line_prefix = format!("{}.{}", object, prefix);
+                let members = self.analyzer.read(uri.as_str(), |analysis| {
+                    analysis.enum_at_dot(&line_prefix).map(|(symbol, members)| {
+                        let mut items: Vec<CompletionItem> = members
+                            .into_iter()
+                            .map(|member| CompletionItem {
+                                label: member.to_string(),
+                                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                                detail: Some(format!("{}.{}", symbol.name, member)),
+                                ..Default::default()
+                            })
+                            .collect();
+                        for (helper, detail) in [("values", "values() -> array"), ("parse", "parse(name: string)")] {
+                            items.push(CompletionItem {
+                                label: helper.to_string(),
+                                kind: Some(CompletionItemKind::METHOD),
+                                detail: Some(format!("{}.{}", symbol.name, detail)),
+                                ..Default::default()
+                            });
+                        }
+                        items
+                    })
                 });
+                if let Some(Some(items)) = members {
+                    return Ok(Some(CompletionResponse::Array(items)));
+                }
+
+                // After `math.`: the library module's functions
+                completion::module_members(&library, object, prefix).unwrap_or_default()
             }
-        }
+            completion::Context::Word { prefix } => {
+                let symbols = self
+                    .analyzer
+                    .read(uri.as_str(), |analysis| analysis.symbols.clone())
+                    .unwrap_or_default();
+                completion::words(&library, &symbols, position.line, position.character, prefix)
+            }
+        };
 
-        Ok(Some(completions))
+        Ok(Some(CompletionResponse::Array(
+            items.into_iter().map(Self::completion_item).collect(),
+        )))
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -215,61 +601,428 @@ impl LanguageServer for Server {
 
         let Some(doc) = self.documents.get(&uri) else {
             return Ok(None);
-        }
-
-        let text = &doc.text;
-        let lines: Vec<&str> = text.lines().collect();
+        };
 
-        if position.line >= lines.len() {
+        let Some(current_line) = doc.text().lines().nth(position.line as usize) else {
             return Ok(None);
-        }
+        };
 
-        let current_line = lines[position.line];
-        let word_at_position = self.get_word_at_position(current_line, position.character);
-
-        if let Some(word) = word_at_position {
-            let content = match word.as_str() {
-                "function" => "Defines a reusable function in Infra.\n\nExample:\nfunction add(a, b): number {\n  return a + b\n}",
-                "class" => "Defines a class for object-oriented programming.\n\nExample:\nclass Person:\n  function init(name):\n    this.name = name",
-                "let" => "Declares a variable with optional type annotation.\n\nExample:\nlet x: number = 42",
-                "if" => "Conditional statement.\n\nExample:\nif x > 0:\n  print(\"positive\")",
-                "for" => "Loop over iterables.\n\nExample:\nfor item in array:\n  print(item)",
-                "while" => "Conditional loop.\n\nExample:\nwhile condition:\n  do_something()",
-                "return" => "Returns a value from a function.\n\nExample:\nreturn result",
-                _ => return Ok(None),
+        if let Some(import) = import_paths::import_path_at(current_line, position.character as usize) {
+            let Some(dir) = Self::document_dir(&uri) else {
+                return Ok(None);
             };
+            let resolution = import_paths::resolve(&import.path, &dir, &self.module_search_paths(&dir));
 
             return Ok(Some(Hover {
-                contents: HoverContents::Markdown(content.to_string()),
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: import_paths::hover_text(&import.path, &resolution),
+                }),
                 range: Some(Range {
-                    start: Position {
-                        line: position.line,
-                        character: position.character.saturating_sub(word.len()),
-                    },
-                    end: Position {
-                        line: position.line,
-                        character: position.character,
-                    },
+                    start: Position::new(position.line, import.start as u32),
+                    end: Position::new(position.line, import.end as u32),
                 }),
             }));
         }
 
-        Ok(None)
+        let library = StandardLibrary::new();
+        let found = self
+            .analyzer
+            .read(uri.as_str(), |analysis| {
+                hover::hover(&library, analysis.scopes.as_ref(), current_line, position.line, position.character)
+                    .or_else(|| {
+                        // A document that does not parse has the scanner's
+                        // declarations, which may still be partway through a large file
+                        let (word, range) = hover::word_at(current_line, position.line, position.character)?;
+                        let symbol = analysis.scopes.is_none().then(|| analysis.symbol(word)).flatten()?;
+                        Some(hover::HoverText { markdown: hover::code(&symbol.detail), range })
+                    })
+            })
+            .unwrap_or_else(|| hover::hover(&library, None, current_line, position.line, position.character));
+
+        Ok(found.map(|found| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: found.markdown,
+            }),
+            range: Some(Range {
+                start: Position::new(found.range.line, found.range.start),
+                end: Position::new(found.range.line, found.range.end),
+            }),
+        }))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let Some(doc) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let text = doc.text().to_string();
+        drop(doc);
+
+        let mut actions = Vec::new();
+
+        // Add-import fixes for the names the diagnostics complain about
+        for diagnostic in &params.context.diagnostics {
+            if !(diagnostic.message.contains("Undefined variable")
+                || diagnostic.message.contains("Unknown module"))
+            {
+                continue;
+            }
+            let Some(line) = text.lines().nth(diagnostic.range.start.line as usize) else {
+                continue;
+            };
+            let Some(reference) =
+                auto_import::reference_at(line, diagnostic.range.start.character as usize)
+            else {
+                continue;
+            };
+            actions.extend(self.add_import_actions(&uri, &text, &reference, diagnostic));
+        }
+
+        // Remove imports flagged as unused, as `infra fix` would
+        for unused in unused_imports::find(&text) {
+            let line = unused.line as u32 - 1;
+            if line < params.range.start.line || line > params.range.end.line {
+                continue;
+            }
+            actions.push(CodeActionOrCommand::CodeAction(Self::remove_import_action(
+                &uri, &text, &unused,
+            )));
+        }
+
+        // Rename keys that look like a typo of a field the annotation has
+        for issue in literal_shapes::check(&text) {
+            if issue.line < params.range.start.line || issue.line > params.range.end.line {
+                continue;
+            }
+            if let Some(action) = Self::rename_field_action(&uri, &text, &issue) {
+                actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+        }
+
+        Ok(Some(actions))
     }
 
     async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
-        // Basic implementation - would need full parser for real definitions
-        Ok(None)
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let Some(text) = self.documents.get(&uri).map(|doc| doc.text().to_string()) else {
+            return Ok(None);
+        };
+        let Some(word) = text
+            .lines()
+            .nth(position.line as usize)
+            .and_then(|line| self.get_word_at_position(line, position.character))
+        else {
+            return Ok(None);
+        };
+
+        // When the document parses, its scopes know which declaration the
+        // name under the cursor means. Imported names are looked up in
+        // their module first.
+        let scoped = self
+            .analyzer
+            .read(uri.as_str(), |analysis| {
+                let scopes = analysis.scopes.as_ref()?;
+                scopes.definition_at(position.line, position.character).cloned()
+            })
+            .flatten();
+        let import = match scoped {
+            Some(definition) if definition.kind == scopes::DefinitionKind::Import => {
+                Some(definition)
+            }
+            Some(definition) => {
+                return Ok(Some(GotoDefinitionResponse::Scalar(Self::name_location(
+                    uri,
+                    definition.range,
+                ))));
+            }
+            None => None,
+        };
+
+        // Otherwise, declarations in the document itself come from the
+        // analyzer
+        let local = self
+            .analyzer
+            .read(uri.as_str(), |analysis| analysis.symbol(&word).cloned())
+            .flatten();
+        if let Some(symbol) = local {
+            return Ok(Some(GotoDefinitionResponse::Scalar(Self::symbol_location(
+                uri, &symbol,
+            ))));
+        }
+
+        // Other files come from the workspace index, which only scans a
+        // module itself when it has not been indexed yet
+        let (Ok(document), Some(dir)) = (uri.to_file_path(), Self::document_dir(&uri)) else {
+            return Ok(None);
+        };
+        let search_paths = self.module_search_paths(&dir);
+        let mut locations: Vec<Location> = self
+            .index()
+            .definition(&document, &text, &word, &search_paths)
+            .iter()
+            .filter_map(|definition| {
+                let uri = lsp_types::Url::from_file_path(&definition.path).ok()?;
+                Some(Self::symbol_location(uri, &definition.symbol))
+            })
+            .collect();
+        if let (true, Some(import)) = (locations.is_empty(), import) {
+            locations.push(Self::name_location(uri, import.range));
+        }
+
+        Ok(match locations.len() {
+            0 => None,
+            1 => locations.pop().map(GotoDefinitionResponse::Scalar),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        })
     }
 
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        // Basic implementation - would need full parser for real references
-        Ok(None)
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let include_declaration = params.context.include_declaration;
+
+        // Only known while the document parses
+        let ranges = self
+            .analyzer
+            .read(uri.as_str(), |analysis| {
+                let scopes = analysis.scopes.as_ref()?;
+                Some(scopes.references_at(position.line, position.character, include_declaration))
+            })
+            .flatten()
+            .unwrap_or_default();
+        if ranges.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            ranges
+                .into_iter()
+                .map(|range| Self::name_location(uri.clone(), range))
+                .collect(),
+        ))
     }
 
-    async fn document_symbol(&self, params: DocumentSymbolParams) -> Result<Option<Vec<DocumentSymbol>>> {
-        // Basic implementation - would need full parser for real symbols
-        Ok(None)
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let uri = params.text_document.uri;
+        let position = params.position;
+
+        let Some(text) = self.documents.get(&uri).map(|doc| doc.text().to_string()) else {
+            return Ok(None);
+        };
+        let library = StandardLibrary::new();
+        let prepared = self
+            .analyzer
+            .read(uri.as_str(), |analysis| {
+                rename::prepare(&library, analysis.scopes.as_ref(), &text, position.line, position.character)
+            })
+            .unwrap_or_else(|| rename::prepare(&library, None, &text, position.line, position.character));
+
+        // The client shows why a name cannot be renamed
+        let range = prepared.map_err(Error::invalid_params)?;
+        Ok(Some(PrepareRenameResponse::Range(Range::new(
+            Position::new(range.line, range.start),
+            Position::new(range.line, range.end),
+        ))))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let Some(text) = self.documents.get(&uri).map(|doc| doc.text().to_string()) else {
+            return Ok(None);
+        };
+        // Only the uses the document's scopes resolve to the same
+        // definition are renamed, so shadowed names are left alone
+        let library = StandardLibrary::new();
+        let renamed = self
+            .analyzer
+            .read(uri.as_str(), |analysis| {
+                rename::rename(
+                    &library,
+                    analysis.scopes.as_ref(),
+                    &text,
+                    position.line,
+                    position.character,
+                    &params.new_name,
+                )
+            })
+            .unwrap_or_else(|| {
+                rename::rename(&library, None, &text, position.line, position.character, &params.new_name)
+            });
+
+        let edits = renamed
+            .map_err(Error::invalid_params)?
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: Range::new(
+                    Position::new(edit.range.line, edit.range.start),
+                    Position::new(edit.range.line, edit.range.end),
+                ),
+                new_text: edit.new_text,
+            })
+            .collect();
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..Default::default()
+        }))
+    }
+
+    async fn document_symbol(&self, params: DocumentSymbolParams) -> Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri;
+
+        let outline = self
+            .documents
+            .get(&uri)
+            .and_then(|doc| outline::outline(doc.text()));
+        if let Some(outline) = outline {
+            let symbols = outline.iter().map(Self::outline_symbol).collect();
+            return Ok(Some(DocumentSymbolResponse::Nested(symbols)));
+        }
+
+        // The document does not parse: the symbols the scanner found so
+        // far, when the analysis is still running
+        let symbols = self.analyzer.read(uri.as_str(), |analysis| {
+            analysis
+                .symbols
+                .iter()
+                .map(|symbol| {
+                    let range = Range::new(
+                        Position::new(symbol.line, symbol.start),
+                        Position::new(symbol.line, symbol.end),
+                    );
+                    #[allow(deprecated)]
+                    DocumentSymbol {
+                        name: symbol.name.clone(),
+                        detail: Some(symbol.detail.clone()),
+                        kind: Self::symbol_kind(symbol.kind),
+                        tags: None,
+                        deprecated: None,
+                        range,
+                        selection_range: range,
+                        children: None,
+                    }
+                })
+                .collect()
+        });
+
+        Ok(symbols.map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let Some(root) = self.workspace_root.read().unwrap().clone() else {
+            return Ok(None);
+        };
+
+        let index = self.index();
+        let found = tokio::task::spawn_blocking(move || {
+            index.symbols_matching(&Self::workspace_files(&root), &params.query)
+        })
+        .await
+        .unwrap_or_default();
+
+        let symbols = found
+            .into_iter()
+            .filter_map(|definition| {
+                let uri = lsp_types::Url::from_file_path(&definition.path).ok()?;
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: definition.symbol.name.clone(),
+                    kind: Self::symbol_kind(definition.symbol.kind),
+                    tags: None,
+                    deprecated: None,
+                    location: Self::symbol_location(uri, &definition.symbol),
+                    container_name: None,
+                })
+            })
+            .collect();
+        Ok(Some(symbols))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self
+            .analyzer
+            .read(uri.as_str(), |analysis| analysis::encode_tokens(&analysis.tokens))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(SemanticTokensResult::Tokens(
+            self.semantic_tokens_result(&uri, data),
+        )))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self
+            .analyzer
+            .read(uri.as_str(), |analysis| analysis::encode_tokens(&analysis.tokens))
+        else {
+            return Ok(None);
+        };
+
+        let previous = self
+            .semantic_tokens
+            .get(&uri)
+            .filter(|entry| entry.0 == params.previous_result_id)
+            .map(|entry| entry.1.clone());
+        let Some(previous) = previous else {
+            // The client holds tokens we no longer know: send them all
+            return Ok(Some(SemanticTokensFullDeltaResult::Tokens(
+                self.semantic_tokens_result(&uri, data),
+            )));
+        };
+
+        let edits = analysis::token_delta(&previous, &data)
+            .into_iter()
+            .map(|edit| SemanticTokensEdit {
+                start: edit.start,
+                delete_count: edit.delete_count,
+                data: Some(Self::semantic_token_data(&edit.data)),
+            })
+            .collect();
+        let result_id = self.semantic_tokens_result(&uri, data).result_id;
+
+        Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(
+            SemanticTokensDelta { result_id, edits },
+        )))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let uri = params.text_document.uri;
+        let range = params.range;
+
+        // Only the visible range is encoded, so this stays cheap on large files
+        let data = self.analyzer.read(uri.as_str(), |analysis| {
+            analysis::encode_tokens(analysis.tokens_in(range.start.line, range.end.line))
+        });
+
+        Ok(data.map(|data| {
+            SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data: Self::semantic_token_data(&data),
+            })
+        }))
     }
 
     async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
@@ -277,23 +1030,223 @@ impl LanguageServer for Server {
 
         let Some(doc) = self.documents.get(&uri) else {
             return Ok(None);
-        }
+        };
 
-        let formatted = self.format_code(&doc.text, &params.options);
+        let options = Self::formatting_options(&params.options);
+        // A document that does not parse is left as it is
+        let Ok(formatted) = formatter::format_source(doc.text(), &options) else {
+            return Ok(None);
+        };
+        if formatted == doc.text() {
+            return Ok(Some(Vec::new()));
+        }
 
-        let edit = TextEdit {
+        let (line, character) = doc.end();
+        Ok(Some(vec![TextEdit {
             range: Range {
                 start: Position::new(0, 0),
-                end: Position::new(u32::MAX, u32::MAX),
+                end: Position::new(line, character),
             },
             new_text: formatted,
+        }]))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        let range = params.range;
+
+        let Some(doc) = self.documents.get(&uri) else {
+            return Ok(None);
         };
 
-        Ok(Some(vec![edit]))
+        // The whole top-level statements the range touches are formatted
+        let options = Self::formatting_options(&params.options);
+        let lines = (range.start.line as usize + 1, range.end.line as usize + 1);
+        let Ok(Some((first, last, formatted))) =
+            formatter::format_lines(doc.text(), lines.0, lines.1, &options)
+        else {
+            return Ok(None);
+        };
+
+        // `last` is 1-based, so the line after it starts at `last`
+        let (end_line, end_character) = if last < doc.line_count() {
+            (last as u32, 0)
+        } else {
+            doc.end()
+        };
+        Ok(Some(vec![TextEdit {
+            range: Range {
+                start: Position::new(first as u32 - 1, 0),
+                end: Position::new(end_line, end_character),
+            },
+            new_text: formatted,
+        }]))
     }
 }
 
 impl Server {
+    /// Complete the file or directory name being typed in an import path
+    fn import_path_completions(
+        &self,
+        uri: &lsp_types::Url,
+        import: &import_paths::ImportPath,
+        position: Position,
+    ) -> Vec<CompletionItem> {
+        let Some(dir) = Self::document_dir(uri) else {
+            return Vec::new();
+        };
+
+        // Only the last path segment is replaced
+        let segment = import.prefix.rsplit('/').next().unwrap_or("");
+        let range = Range {
+            start: Position::new(
+                position.line,
+                position.character - segment.chars().count() as u32,
+            ),
+            end: position,
+        };
+
+        import_paths::complete(&import.prefix, &dir, &self.module_search_paths(&dir))
+            .into_iter()
+            .map(|completion| CompletionItem {
+                kind: Some(if completion.is_dir {
+                    CompletionItemKind::FOLDER
+                } else {
+                    CompletionItemKind::FILE
+                }),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: completion.label.clone(),
+                })),
+                // Keep completing inside a directory after picking it
+                command: completion.is_dir.then(|| Command {
+                    title: "Complete path".to_string(),
+                    command: "editor.action.triggerSuggest".to_string(),
+                    arguments: None,
+                }),
+                label: completion.label,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// One quick fix per module that could provide `reference`. Only a
+    /// lone candidate is marked preferred, so ambiguous fixes are never
+    /// applied automatically.
+    fn add_import_actions(
+        &self,
+        uri: &lsp_types::Url,
+        text: &str,
+        reference: &auto_import::Reference,
+        diagnostic: &Diagnostic,
+    ) -> Vec<CodeActionOrCommand> {
+        if auto_import::imported_names(text).contains(reference.name()) {
+            return Vec::new();
+        }
+        let (Ok(document), Some(dir)) = (uri.to_file_path(), Self::document_dir(uri)) else {
+            return Vec::new();
+        };
+        let root = self.workspace_root.read().unwrap().clone().unwrap_or(dir);
+
+        let index = auto_import::ExportIndex::build(&root);
+        let candidates = auto_import::candidates(reference, &document, &index);
+        let single = candidates.len() == 1;
+
+        candidates
+            .into_iter()
+            .map(|candidate| {
+                let insertion = auto_import::insertion(text, &candidate.statement);
+                let position = Position::new(insertion.line as u32, 0);
+                let edit = TextEdit {
+                    range: Range::new(position, position),
+                    new_text: insertion.text,
+                };
+
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Add `{}`", candidate.statement),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(single),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    fn remove_import_action(
+        uri: &lsp_types::Url,
+        text: &str,
+        unused: &unused_imports::UnusedImport,
+    ) -> CodeAction {
+        let position = |offset| {
+            let (line, character) = analysis::position_at(text, offset);
+            Position::new(line, character)
+        };
+        let edit = TextEdit {
+            range: Range::new(position(unused.range.start), position(unused.range.end)),
+            new_text: unused.replacement.clone(),
+        };
+
+        CodeAction {
+            title: format!("Remove unused import of `{}`", unused.names.join("`, `")),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn rename_field_action(
+        uri: &lsp_types::Url,
+        text: &str,
+        issue: &literal_shapes::ShapeIssue,
+    ) -> Option<CodeAction> {
+        let field = issue.rename.as_ref()?;
+        let line = text.lines().nth(issue.line as usize)?;
+        let start = analysis::offset_at(line, 0, issue.start);
+        let end = analysis::offset_at(line, 0, issue.end);
+        let key = &line[start..end];
+        // A quoted key stays quoted
+        let new_text = match key.chars().next() {
+            Some(quote @ ('"' | '\'')) => format!("{}{}{}", quote, field, quote),
+            _ => field.clone(),
+        };
+        let range = Range::new(
+            Position::new(issue.line, issue.start),
+            Position::new(issue.line, issue.end),
+        );
+
+        Some(CodeAction {
+            title: format!("Rename `{}` to `{}`", key.trim_matches(['"', '\'']), field),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("infra".to_string()),
+                message: issue.message.clone(),
+                ..Default::default()
+            }]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    uri.clone(),
+                    vec![TextEdit { range, new_text }],
+                )])),
+                ..Default::default()
+            }),
+            is_preferred: Some(true),
+            ..Default::default()
+        })
+    }
+
     fn get_word_at_position(&self, line: &str, character: u32) -> Option<String> {
         let char_idx = character as usize;
         if char_idx >= line.len() {
@@ -331,9 +1284,12 @@ impl Server {
         }
     }
 
-    fn format_code(&self, code: &str, _options: &FormattingOptions) -> String {
-        // Basic formatting - would need full parser for proper formatting
-        // For now, just return the original code
-        code.to_string()
+    /// The formatter's options for the client's
+    fn formatting_options(options: &FormattingOptions) -> printer::FormattingOptions {
+        printer::FormattingOptions {
+            tab_size: options.tab_size as usize,
+            use_tabs: !options.insert_spaces,
+            ..Default::default()
+        }
     }
 }
@@ -0,0 +1,495 @@
+//! Object and array literals checked against the annotation of the `let`
+//! they initialize, the way `infra check` does: missing fields, unknown
+//! fields with the field they are probably a typo of, and literal values of
+//! the wrong type. Computed values are left alone.
+//!
+//! Like the rest of the server this reads plain text, so a declaration is
+//! checked even while another part of the document does not parse.
+
+/// One problem with a literal, at the key, value or name it is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeIssue {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    pub message: String,
+    /// Unknown fields that are not a likely typo are only warnings
+    pub warning: bool,
+    /// The field name a typo'd key should be renamed to
+    pub rename: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Word(String),
+    Text(String),
+    Number,
+    Punct(char),
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+struct Tok {
+    kind: Kind,
+    line: u32,
+    start: u32,
+    end: u32,
+}
+
+enum Ty {
+    Object(Vec<(String, Ty)>),
+    Array(Box<Ty>),
+    /// `number`, `string`, an enum...
+    Name(String),
+    Union(Vec<Ty>),
+}
+
+enum Lit {
+    Object(Vec<(usize, String, Lit)>),
+    Array(Vec<(usize, Lit)>),
+    /// A literal value of the named type
+    Leaf(&'static str),
+    Computed,
+}
+
+/// Every literal in `text` that does not match its annotation
+pub fn check(text: &str) -> Vec<ShapeIssue> {
+    let tokens = tokenize(text);
+    let mut issues = Vec::new();
+
+    for index in 0..tokens.len() {
+        let (Kind::Word(keyword), Some(Kind::Word(name)), Some(Kind::Punct(':'))) = (
+            &tokens[index].kind,
+            tokens.get(index + 1).map(|t| &t.kind),
+            tokens.get(index + 2).map(|t| &t.kind),
+        ) else {
+            continue;
+        };
+        if keyword != "let" {
+            continue;
+        }
+
+        let mut parser = Parser {
+            tokens: &tokens,
+            at: index + 3,
+        };
+        let Some(ty) = parser.ty() else {
+            continue;
+        };
+        if !matches!(ty, Ty::Object(_) | Ty::Array(_)) || !parser.eat('=') {
+            continue;
+        }
+        let value_at = parser.at;
+        let Some(value) = parser.value() else {
+            continue;
+        };
+        if matches!(value, Lit::Object(_) | Lit::Array(_)) {
+            let mut checker = Checker {
+                tokens: &tokens,
+                issues: &mut issues,
+            };
+            checker.value(name, index + 1, &ty, value_at, &value);
+        }
+    }
+    issues
+}
+
+struct Checker<'a> {
+    tokens: &'a [Tok],
+    issues: &'a mut Vec<ShapeIssue>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, at: usize, message: String, warning: bool, rename: Option<String>) {
+        let token = &self.tokens[at];
+        self.issues.push(ShapeIssue {
+            line: token.line,
+            start: token.start,
+            end: token.end,
+            message,
+            warning,
+            rename,
+        });
+    }
+
+    /// Check `value`, found at token `at`, against `expected`; `anchor` is
+    /// the token missing fields are reported at
+    fn value(&mut self, path: &str, anchor: usize, expected: &Ty, at: usize, value: &Lit) {
+        let found = match (value, expected) {
+            (Lit::Object(fields), Ty::Object(expected)) => {
+                return self.object(path, anchor, expected, fields)
+            }
+            (Lit::Array(elements), Ty::Array(element_type)) => {
+                for (index, (at, element)) in elements.iter().enumerate() {
+                    let path = format!("{}[{}]", path, index);
+                    self.value(&path, *at, element_type, *at, element);
+                }
+                return;
+            }
+            (Lit::Computed, _) | (_, Ty::Name(_) | Ty::Union(_)) if accepts(expected, value) => {
+                return
+            }
+            (Lit::Object(_), _) => "object",
+            (Lit::Array(_), _) => "array",
+            (Lit::Leaf(name), _) => name,
+            (Lit::Computed, _) => return,
+        };
+        self.push(
+            at,
+            format!("{} expects {}, found {}", path, type_name(expected), found),
+            false,
+            None,
+        );
+    }
+
+    fn object(
+        &mut self,
+        path: &str,
+        anchor: usize,
+        expected: &[(String, Ty)],
+        fields: &[(usize, String, Lit)],
+    ) {
+        let mut missing: Vec<&str> = expected
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !fields.iter().any(|(_, key, _)| key == name))
+            .collect();
+
+        for (at, key, value) in fields {
+            let field_path = format!("{}.{}", path, key);
+            match expected.iter().find(|(name, _)| name == key) {
+                Some((_, field_type)) => {
+                    let value_at = self.value_start(*at);
+                    self.value(&field_path, *at, field_type, value_at, value)
+                }
+                None => {
+                    let suggestion = closest_name(key, &missing).map(str::to_string);
+                    missing.retain(|name| Some(*name) != suggestion.as_deref());
+                    let message = match &suggestion {
+                        Some(suggestion) => {
+                            format!(
+                                "unknown field {}; did you mean '{}'?",
+                                field_path, suggestion
+                            )
+                        }
+                        None => format!("unknown field {}", field_path),
+                    };
+                    let warning = suggestion.is_none();
+                    self.push(*at, message, warning, suggestion);
+                }
+            }
+        }
+
+        for (name, field_type) in expected {
+            if missing.contains(&name.as_str()) {
+                let message = format!(
+                    "missing field {}.{} ({})",
+                    path,
+                    name,
+                    type_name(field_type)
+                );
+                self.push(anchor, message, false, None);
+            }
+        }
+    }
+
+    /// The first token of the value after the key at `key`
+    fn value_start(&self, key: usize) -> usize {
+        let mut at = key + 2;
+        while matches!(self.tokens.get(at).map(|t| &t.kind), Some(Kind::Newline)) {
+            at += 1;
+        }
+        at.min(self.tokens.len() - 1)
+    }
+}
+
+/// Whether `value` can be of type `expected` as far as the text shows
+fn accepts(expected: &Ty, value: &Lit) -> bool {
+    match (expected, value) {
+        (_, Lit::Computed) => true,
+        (Ty::Name(name), _) if name == "any" => true,
+        // Which member a literal is meant to be is left to the runtime check
+        (Ty::Union(_), Lit::Object(_) | Lit::Array(_)) => true,
+        (Ty::Union(types), _) => types.iter().any(|t| accepts(t, value)),
+        (Ty::Name(name), Lit::Leaf(leaf)) => name == leaf,
+        _ => false,
+    }
+}
+
+fn type_name(ty: &Ty) -> String {
+    match ty {
+        Ty::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, t)| format!("{}: {}", name, type_name(t)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Ty::Array(element) => format!("[{}]", type_name(element)),
+        Ty::Name(name) => name.clone(),
+        Ty::Union(types) => types.iter().map(type_name).collect::<Vec<_>>().join(" | "),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Tok],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Kind> {
+        self.tokens.get(self.at).map(|t| &t.kind)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Kind::Punct(c)) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Newlines only separate statements outside brackets
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Kind::Newline) {
+            self.at += 1;
+        }
+    }
+
+    fn ty(&mut self) -> Option<Ty> {
+        let mut types = vec![self.base_ty()?];
+        while self.eat('|') {
+            types.push(self.base_ty()?);
+        }
+        Some(if types.len() == 1 {
+            types.pop()?
+        } else {
+            Ty::Union(types)
+        })
+    }
+
+    fn base_ty(&mut self) -> Option<Ty> {
+        if self.eat('{') {
+            let mut fields = Vec::new();
+            loop {
+                self.skip_newlines();
+                if self.eat('}') {
+                    return Some(Ty::Object(fields));
+                }
+                let Some(Kind::Word(name)) = self.peek().cloned() else {
+                    return None;
+                };
+                self.at += 1;
+                if !self.eat(':') {
+                    return None;
+                }
+                fields.push((name, self.ty()?));
+                self.skip_newlines();
+                self.eat(',');
+            }
+        }
+        if self.eat('[') {
+            let element = self.ty()?;
+            return self.eat(']').then(|| Ty::Array(Box::new(element)));
+        }
+        let Some(Kind::Word(name)) = self.peek().cloned() else {
+            return None;
+        };
+        self.at += 1;
+        Some(Ty::Name(name))
+    }
+
+    fn value(&mut self) -> Option<Lit> {
+        if self.eat('{') {
+            let mut fields = Vec::new();
+            loop {
+                self.skip_newlines();
+                if self.eat('}') {
+                    return Some(Lit::Object(fields));
+                }
+                let key_at = self.at;
+                let key = match self.peek()? {
+                    Kind::Word(key) | Kind::Text(key) => key.clone(),
+                    _ => return None,
+                };
+                self.at += 1;
+                if !self.eat(':') {
+                    return None;
+                }
+                self.skip_newlines();
+                fields.push((key_at, key, self.value()?));
+                self.skip_newlines();
+                self.eat(',');
+            }
+        }
+        if self.eat('[') {
+            let mut elements = Vec::new();
+            loop {
+                self.skip_newlines();
+                if self.eat(']') {
+                    return Some(Lit::Array(elements));
+                }
+                elements.push((self.at, self.value()?));
+                self.skip_newlines();
+                self.eat(',');
+            }
+        }
+
+        let leaf = match self.peek()? {
+            Kind::Number => Some("number"),
+            Kind::Text(_) => Some("string"),
+            Kind::Word(word) if word == "true" || word == "false" => Some("boolean"),
+            Kind::Word(word) if word == "null" => Some("null"),
+            _ => None,
+        };
+        self.at += 1;
+        if let Some(leaf) = leaf {
+            if matches!(
+                self.peek(),
+                None | Some(Kind::Newline | Kind::Punct(',' | '}' | ']'))
+            ) {
+                return Some(Lit::Leaf(leaf));
+            }
+        }
+
+        // Anything else is computed: skip to the end of the item
+        let mut depth = 0usize;
+        while let Some(kind) = self.peek() {
+            match kind {
+                Kind::Punct('(' | '[' | '{') => depth += 1,
+                Kind::Punct(')' | ']' | '}') if depth > 0 => depth -= 1,
+                Kind::Punct(',' | ']' | '}') | Kind::Newline if depth == 0 => break,
+                _ => {}
+            }
+            self.at += 1;
+        }
+        Some(Lit::Computed)
+    }
+}
+
+/// Words, strings, numbers and punctuation, with UTF-16 columns. Comments
+/// are dropped.
+fn tokenize(text: &str) -> Vec<Tok> {
+    let mut tokens = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let width = |chars: &[char]| chars.iter().map(|c| c.len_utf16() as u32).sum::<u32>();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let start = width(&chars[..i]);
+            if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+                break;
+            }
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+
+            let (kind, end) = if c == '"' || c == '\'' {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != c {
+                    j += if chars[j] == '\\' { 2 } else { 1 };
+                }
+                let end = (j + 1).min(chars.len());
+                let content: String = chars[i + 1..j.min(chars.len())].iter().collect();
+                (Kind::Text(content), end)
+            } else if c.is_ascii_digit() {
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                    j += 1;
+                }
+                (Kind::Number, j)
+            } else if c.is_alphabetic() || c == '_' {
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                    j += 1;
+                }
+                (Kind::Word(chars[i..j].iter().collect()), j)
+            } else {
+                (Kind::Punct(c), i + 1)
+            };
+            tokens.push(Tok {
+                kind,
+                line: number as u32,
+                start,
+                end: start + width(&chars[i..end]),
+            });
+            i = end;
+        }
+        tokens.push(Tok {
+            kind: Kind::Newline,
+            line: number as u32,
+            start: width(&chars),
+            end: width(&chars),
+        });
+    }
+    tokens
+}
+
+/// The candidate closest to `name` by edit distance, if close enough to be
+/// a likely typo. The same rule the interpreter uses for suggestions.
+fn closest_name<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let distance = |a: &str, b: &str| {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut previous = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = previous + usize::from(ca != *cb);
+                previous = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+            }
+        }
+        row[b.len()]
+    };
+
+    let limit = name.chars().count().div_ceil(3);
+    candidates
+        .iter()
+        .map(|candidate| (distance(name, candidate), *candidate))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typo_missing_and_mistyped_fields() {
+        let text = "let cfg: {host: string, port: number} = {host: \"db\", prot: 5432}\n\
+                    let app: {name: string, server: {port: number}, tags: [string]} = {\n\
+                    \x20   name: 1,\n\
+                    \x20   server: {},\n\
+                    \x20   tags: [\"a\", upper(\"b\")],\n\
+                    \x20   debug: true\n\
+                    }\n";
+        let issues = check(text);
+        let summary: Vec<(u32, u32, &str, bool)> = issues
+            .iter()
+            .map(|issue| {
+                (
+                    issue.line,
+                    issue.start,
+                    issue.message.as_str(),
+                    issue.warning,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 53, "unknown field cfg.prot; did you mean 'port'?", false),
+                (2, 10, "app.name expects string, found number", false),
+                (3, 4, "missing field app.server.port (number)", false),
+                (5, 4, "unknown field app.debug", true),
+            ]
+        );
+        assert_eq!(issues[0].rename.as_deref(), Some("port"));
+        assert_eq!(issues[0].end - issues[0].start, 4);
+    }
+}
@@ -2,8 +2,7 @@ use anyhow::Result;
 use clap::Parser;
 use infra_lsp::Server;
 use log::info;
-use std::io;
-use tower_lsp::{LspService, Server};
+use tower_lsp::LspService;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -17,17 +16,16 @@ async fn main() -> Result<()> {
     let args = Args::parse();
 
     let log_level = if args.verbose { log::LevelFilter::Debug } else { log::LevelFilter::Info };
-    env_logger::Builder()
+    env_logger::Builder::new()
         .filter_level(log_level)
         .init();
 
     info!("Starting Infra Language Server");
 
-    let (service, socket) = LspService::new(Server::new());
-    Server::new(std::io::stdin(), std::io::stdout())
-        .interleave(service)
-        .serve(socket)
-        .await?;
+    let (service, socket) = LspService::new(Server::new);
+    tower_lsp::Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
 
     Ok(())
 }
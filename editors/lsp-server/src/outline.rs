@@ -0,0 +1,411 @@
+//! The document outline, from the AST the interpreter's parser builds.
+//!
+//! The parser records the line each top-level statement starts on, so a
+//! declaration runs from there to the last line of code before the next
+//! one. Names, and the methods and fields inside a class, have no positions
+//! in the AST and are found in the lines of their declaration. A document
+//! that does not parse has no outline; the server then falls back to the
+//! symbols the scanner in `analysis` found.
+
+use infra::core::{ExportItem, Expr, MethodDecl, OptionalParams, Program, Stmt, Type};
+use infra::frontend::printer::{format_expr, format_type};
+
+use crate::syntax;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineKind {
+    Function,
+    Method,
+    Field,
+    Class,
+    Variable,
+    Enum,
+}
+
+/// LSP positions: lines from 0 and UTF-16 columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextRange {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineSymbol {
+    pub name: String,
+    pub kind: OutlineKind,
+    /// Parameters and return type of a function, members of an enum...
+    pub detail: Option<String>,
+    /// The whole declaration
+    pub range: TextRange,
+    /// The declared name
+    pub selection: TextRange,
+    pub children: Vec<OutlineSymbol>,
+}
+
+/// The declarations in `text`, or `None` when it does not parse
+pub fn outline(text: &str) -> Option<Vec<OutlineSymbol>> {
+    let program = syntax::parse(text).ok()?;
+    Some(Outliner::new(text).program(&program))
+}
+
+struct Outliner<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> Outliner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().collect(),
+        }
+    }
+
+    fn program(&self, program: &Program) -> Vec<OutlineSymbol> {
+        let starts: Vec<usize> = (0..program.statements.len())
+            .map(|index| program.line_of(index).map_or(0, |line| line - 1))
+            .collect();
+
+        let mut symbols = Vec::new();
+        for (index, stmt) in program.statements.iter().enumerate() {
+            let first = starts[index];
+            let next = starts.get(index + 1).copied().unwrap_or(self.lines.len());
+            let last = self.last_code_line(first, next);
+            self.statement(stmt, first, last, &mut symbols);
+        }
+        symbols
+    }
+
+    /// Add the symbols a top-level statement on lines `first..=last` declares
+    fn statement(&self, stmt: &Stmt, first: usize, last: usize, out: &mut Vec<OutlineSymbol>) {
+        let symbol =
+            |name: &str, kind, detail| self.symbol(name, kind, detail, first, last, Vec::new());
+
+        match stmt {
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                ..
+            } => {
+                let detail = signature(params, param_types, optional_params, return_type);
+                out.extend(symbol(name, OutlineKind::Function, Some(detail)));
+            }
+            Stmt::AsyncFunction {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                ..
+            } => {
+                let detail = signature(params, param_types, optional_params, return_type);
+                let detail = format!("async {}", detail);
+                out.extend(symbol(name, OutlineKind::Function, Some(detail)));
+            }
+            Stmt::Let {
+                name,
+                type_annotation,
+                ..
+            } => {
+                let detail = type_annotation.as_ref().map(format_type);
+                out.extend(symbol(name, OutlineKind::Variable, detail));
+            }
+            Stmt::LetTuple { pattern, .. } => {
+                for name in pattern.names() {
+                    out.extend(symbol(name, OutlineKind::Variable, None));
+                }
+            }
+            Stmt::Export { item } => match item {
+                ExportItem::Function {
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                    ..
+                } => {
+                    let detail = signature(params, param_types, optional_params, return_type);
+                    let detail = format!("export {}", detail);
+                    out.extend(symbol(name, OutlineKind::Function, Some(detail)));
+                }
+                ExportItem::Variable {
+                    name,
+                    type_annotation,
+                    ..
+                } => {
+                    let detail = match type_annotation {
+                        Some(ty) => format!("export {}", format_type(ty)),
+                        None => "export".to_string(),
+                    };
+                    out.extend(symbol(name, OutlineKind::Variable, Some(detail)));
+                }
+            },
+            Stmt::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+            } => {
+                let children = self.class_members(fields, methods, first, last);
+                let detail = superclass
+                    .as_ref()
+                    .map(|parent| format!("extends {}", parent));
+                out.extend(self.symbol(name, OutlineKind::Class, detail, first, last, children));
+            }
+            Stmt::Enum { name, members } => {
+                out.extend(symbol(name, OutlineKind::Enum, Some(members.join(", "))));
+            }
+            _ => {}
+        }
+    }
+
+    /// Fields and methods of the class on lines `first..=last`, in source
+    /// order. Each ends where the next begins, the last one before the
+    /// class's closing brace.
+    fn class_members(
+        &self,
+        fields: &[(String, Expr)],
+        methods: &[MethodDecl],
+        first: usize,
+        last: usize,
+    ) -> Vec<OutlineSymbol> {
+        let mut members = Vec::new();
+        let mut from = first + 1;
+        for (field, _) in fields {
+            if let Some(line) = self.member_line(field, '=', from, last) {
+                members.push((line, field.as_str(), OutlineKind::Field, None));
+                from = line + 1;
+            }
+        }
+        from = first + 1;
+        for method in methods {
+            if let Some(line) = self.member_line(&method.name, '(', from, last) {
+                let detail = signature(
+                    &method.params,
+                    &method.param_types,
+                    &method.optional_params,
+                    &method.return_type,
+                );
+                members.push((
+                    line,
+                    method.name.as_str(),
+                    OutlineKind::Method,
+                    Some(detail),
+                ));
+                from = line + 1;
+            }
+        }
+        members.sort_by_key(|&(line, ..)| line);
+
+        let mut children = Vec::new();
+        for (index, (line, name, kind, detail)) in members.iter().enumerate() {
+            let next = members.get(index + 1).map_or(last, |member| member.0);
+            let end = self.last_code_line(*line, next);
+            children.extend(self.symbol(name, *kind, detail.clone(), *line, end, Vec::new()));
+        }
+        children
+    }
+
+    /// A symbol for the declaration of `name` on lines `first..=last`;
+    /// `None` if the name cannot be found there
+    fn symbol(
+        &self,
+        name: &str,
+        kind: OutlineKind,
+        detail: Option<String>,
+        first: usize,
+        last: usize,
+        children: Vec<OutlineSymbol>,
+    ) -> Option<OutlineSymbol> {
+        let selection = (first..=last).find_map(|line| {
+            let start = find_word(self.lines.get(line)?, name)?;
+            Some(TextRange {
+                start: (line as u32, start),
+                end: (line as u32, start + utf16_len(name)),
+            })
+        })?;
+        let end_column = self.lines.get(last).map_or(0, |line| utf16_len(line));
+        Some(OutlineSymbol {
+            name: name.to_string(),
+            kind,
+            detail,
+            range: TextRange {
+                start: (first as u32, 0),
+                end: (last as u32, end_column),
+            },
+            selection,
+            children,
+        })
+    }
+
+    /// The line in `from..=last` declaring the class member `name`:
+    /// `name = value` for a field and `name(...)`, maybe after `function`
+    /// or `def`, for a method
+    fn member_line(&self, name: &str, follows: char, from: usize, last: usize) -> Option<usize> {
+        (from..=last.min(self.lines.len().saturating_sub(1))).find(|&line| {
+            let code = self.lines[line].trim_start();
+            let code = ["function ", "def "]
+                .iter()
+                .find_map(|keyword| code.strip_prefix(keyword))
+                .unwrap_or(code)
+                .trim_start();
+            code.strip_prefix(name)
+                .is_some_and(|rest| rest.trim_start().starts_with(follows))
+        })
+    }
+
+    /// The last line before `next` with code on it, not before `first`
+    fn last_code_line(&self, first: usize, next: usize) -> usize {
+        (first..next.min(self.lines.len()))
+            .rev()
+            .find(|&line| {
+                let code = self.lines[line].trim();
+                !(code.is_empty() || code.starts_with('#') || code.starts_with("//"))
+            })
+            .unwrap_or(first)
+    }
+}
+
+/// `(a: number, b = 2, ...rest) -> string`
+fn signature(
+    params: &[String],
+    param_types: &[Option<Type>],
+    optional_params: &OptionalParams,
+    return_type: &Option<Type>,
+) -> String {
+    let first_default = optional_params.required(params.len());
+    let params: Vec<String> = params
+        .iter()
+        .enumerate()
+        .map(|(index, param)| {
+            let mut text = if optional_params.rest && index + 1 == params.len() {
+                format!("...{}", param)
+            } else {
+                param.clone()
+            };
+            if let Some(Some(ty)) = param_types.get(index) {
+                text.push_str(&format!(": {}", format_type(ty)));
+            }
+            if let Some(default) = index
+                .checked_sub(first_default)
+                .and_then(|default| optional_params.defaults.get(default))
+            {
+                text.push_str(&format!(" = {}", format_expr(default)));
+            }
+            text
+        })
+        .collect();
+
+    match return_type {
+        Some(ty) => format!("({}) -> {}", params.join(", "), format_type(ty)),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+/// UTF-16 column of the first whole-word occurrence of `word` in `line`
+fn find_word(line: &str, word: &str) -> Option<u32> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word)
+        .find(|&(index, _)| {
+            let before = line[..index].chars().next_back();
+            let after = line[index + word.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        })
+        .map(|(index, _)| utf16_len(&line[..index]))
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> TextRange {
+        TextRange { start, end }
+    }
+
+    #[test]
+    fn functions_variables_and_exports() {
+        let text = "let é: number = 1\n\
+                    \n\
+                    function add(a: number, b = 2, ...rest) -> number:\n    \
+                    return a + b\n\
+                    # trailing comment\n\
+                    \n\
+                    export let limit = 10\n\
+                    @memo.wrap\n\
+                    export function square(x):\n    \
+                    return x * x\n";
+        let symbols = outline(text).unwrap();
+        let summary: Vec<(&str, OutlineKind, Option<&str>)> = symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("é", OutlineKind::Variable, Some("number")),
+                (
+                    "add",
+                    OutlineKind::Function,
+                    Some("(a: number, b = 2, ...rest) -> number")
+                ),
+                ("limit", OutlineKind::Variable, Some("export")),
+                ("square", OutlineKind::Function, Some("export (x)")),
+            ]
+        );
+
+        assert_eq!(symbols[0].selection, range((0, 4), (0, 5)));
+        assert_eq!(symbols[1].range, range((2, 0), (3, 16)));
+        assert_eq!(symbols[1].selection, range((2, 9), (2, 12)));
+        // A decorated function starts at its first decorator
+        assert_eq!(symbols[3].range, range((7, 0), (9, 16)));
+        assert_eq!(symbols[3].selection, range((8, 16), (8, 22)));
+    }
+
+    #[test]
+    fn classes_nest_their_fields_and_methods() {
+        let text = "class Student extends Person:\n\
+                    {\n    \
+                    school = \"none\"\n\n    \
+                    init(name):\n    \
+                    {\n        \
+                    this.name = name\n    \
+                    }\n\n    \
+                    function greet() -> string:\n        \
+                    return this.name\n\
+                    }\n\
+                    enum Status: pending, done\n";
+        let symbols = outline(text).unwrap();
+        assert_eq!(symbols.len(), 2);
+
+        let class = &symbols[0];
+        assert_eq!(class.detail.as_deref(), Some("extends Person"));
+        assert_eq!(class.range, range((0, 0), (11, 1)));
+        let members: Vec<(&str, OutlineKind, TextRange)> = class
+            .children
+            .iter()
+            .map(|m| (m.name.as_str(), m.kind, m.range))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                ("school", OutlineKind::Field, range((2, 0), (2, 19))),
+                ("init", OutlineKind::Method, range((4, 0), (7, 5))),
+                ("greet", OutlineKind::Method, range((9, 0), (10, 24))),
+            ]
+        );
+        assert_eq!(class.children[2].detail.as_deref(), Some("() -> string"));
+
+        assert_eq!(symbols[1].kind, OutlineKind::Enum);
+        assert_eq!(symbols[1].detail.as_deref(), Some("pending, done"));
+    }
+
+    #[test]
+    fn documents_that_do_not_parse_have_no_outline() {
+        assert_eq!(outline("function broken(:\n"), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! Renaming a name defined in the document, everywhere it is used with the
+//! same meaning.
+//!
+//! The symbol table resolves each use to its definition, so renaming an
+//! inner `x` leaves an outer `x` it shadows alone. Names defined outside
+//! the document, such as builtins, library modules and names from
+//! `import *`, cannot be renamed here, nor can keywords. A name imported
+//! without an alias keeps its import working by gaining one: renaming
+//! `area` in `import {area} from "./shapes"` writes `area as size`.
+
+use crate::hover;
+use crate::scopes::{DefinitionKind, NameRange, SymbolTable};
+use infra::frontend::{Lexer, TokenType};
+use infra::stdlib::StandardLibrary;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameEdit {
+    pub range: NameRange,
+    pub new_text: String,
+}
+
+/// The range of the name at a UTF-16 column of line `line` of `text`, if
+/// it can be renamed, or why not
+pub fn prepare(
+    library: &StandardLibrary,
+    symbols: Option<&SymbolTable>,
+    text: &str,
+    line: u32,
+    character: u32,
+) -> Result<NameRange, String> {
+    let line_text = text.lines().nth(line as usize).unwrap_or("");
+    let Some((word, range)) = hover::word_at(line_text, line, character) else {
+        return Err("There is no name here to rename".to_string());
+    };
+    if symbols.is_some_and(|symbols| symbols.definition_at(line, character).is_some()) {
+        return Ok(range);
+    }
+    if library.has_module(word) {
+        return Err(format!(
+            "`{}` is a standard library module and cannot be renamed",
+            word
+        ));
+    }
+    if !is_identifier(word) {
+        return Err(format!("`{}` is a keyword and cannot be renamed", word));
+    }
+    let Some(symbols) = symbols else {
+        return Err("The document has errors; fix them before renaming".to_string());
+    };
+    if !symbols.references_at(line, character, false).is_empty() {
+        return Err(format!(
+            "`{}` is not defined in this document, so it cannot be renamed here",
+            word
+        ));
+    }
+    Err("There is no name here to rename".to_string())
+}
+
+/// The edits that rename the name at a position to `new_name`
+pub fn rename(
+    library: &StandardLibrary,
+    symbols: Option<&SymbolTable>,
+    text: &str,
+    line: u32,
+    character: u32,
+    new_name: &str,
+) -> Result<Vec<RenameEdit>, String> {
+    prepare(library, symbols, text, line, character)?;
+    if !is_identifier(new_name) {
+        let word = hover::word_at(new_name, 0, 0).is_some_and(|(word, _)| word == new_name);
+        return Err(if word {
+            format!("`{}` is a keyword and cannot be used as a name", new_name)
+        } else {
+            format!("`{}` is not a valid name", new_name)
+        });
+    }
+
+    // `prepare` found the definition
+    let symbols = symbols.unwrap();
+    let definition = symbols.definition_at(line, character).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    let edits = symbols
+        .references_at(line, character, true)
+        .into_iter()
+        .map(|range| {
+            let unaliased = range == definition.range
+                && definition.kind == DefinitionKind::Import
+                && !after_as(lines.get(range.line as usize).unwrap_or(&""), range.start);
+            let new_text = if unaliased {
+                format!("{} as {}", definition.name, new_name)
+            } else {
+                new_name.to_string()
+            };
+            RenameEdit { range, new_text }
+        })
+        .collect();
+    Ok(edits)
+}
+
+/// Whether `name` lexes as a single identifier
+fn is_identifier(name: &str) -> bool {
+    match Lexer::new(name).tokenize() {
+        Ok(tokens) => {
+            matches!(tokens.as_slice(), [token, _]
+                if matches!(&token.token_type, TokenType::Identifier(found) if found == name))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether the text before a UTF-16 column of `line` ends with `as`, so the
+/// name there is already an import alias
+fn after_as(line: &str, character: u32) -> bool {
+    let mut units = 0;
+    let end = line
+        .char_indices()
+        .find(|(_, c)| {
+            let reached = units >= character;
+            units += c.len_utf16() as u32;
+            reached
+        })
+        .map_or(line.len(), |(index, _)| index);
+    line[..end]
+        .trim_end()
+        .strip_suffix("as")
+        .is_some_and(|before| before.ends_with(char::is_whitespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    fn rename_at(
+        text: &str,
+        line: u32,
+        character: u32,
+        new_name: &str,
+    ) -> Result<Vec<RenameEdit>, String> {
+        let program = syntax::parse(text).unwrap();
+        let symbols = SymbolTable::build(text, &program);
+        rename(
+            &StandardLibrary::new(),
+            Some(&symbols),
+            text,
+            line,
+            character,
+            new_name,
+        )
+    }
+
+    fn edit(line: u32, start: u32, end: u32, new_text: &str) -> RenameEdit {
+        RenameEdit {
+            range: NameRange { line, start, end },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn renaming_an_inner_name_leaves_the_outer_one() {
+        let text = "let x = 1\nfunction f(): {\n    let x = 2\n    print(x)\n}\nprint(x)\n";
+
+        assert_eq!(
+            rename_at(text, 3, 10, "y"),
+            Ok(vec![edit(2, 8, 9, "y"), edit(3, 10, 11, "y")])
+        );
+        assert_eq!(
+            rename_at(text, 5, 6, "total"),
+            Ok(vec![edit(0, 4, 5, "total"), edit(5, 6, 7, "total")])
+        );
+    }
+
+    #[test]
+    fn imports_without_an_alias_gain_one() {
+        let text = "import {area, width as w} from \"./shapes\"\nprint(area(w))\n";
+
+        assert_eq!(
+            rename_at(text, 1, 7, "size"),
+            Ok(vec![edit(0, 8, 12, "area as size"), edit(1, 6, 10, "size")])
+        );
+        assert_eq!(
+            rename_at(text, 0, 23, "wide"),
+            Ok(vec![edit(0, 23, 24, "wide"), edit(1, 11, 12, "wide")])
+        );
+    }
+
+    #[test]
+    fn keywords_modules_and_builtins_are_refused() {
+        let text = "let s = string.upper(\"a\")\nreturn len(s)\n";
+        let message = |line, character| rename_at(text, line, character, "t").unwrap_err();
+
+        assert_eq!(message(1, 2), "`return` is a keyword and cannot be renamed");
+        assert_eq!(
+            message(0, 10),
+            "`string` is a standard library module and cannot be renamed"
+        );
+        assert_eq!(
+            message(1, 8),
+            "`len` is not defined in this document, so it cannot be renamed here"
+        );
+        assert_eq!(
+            rename_at(text, 0, 4, "for").unwrap_err(),
+            "`for` is a keyword and cannot be used as a name"
+        );
+        assert_eq!(
+            rename_at(text, 0, 4, "2x").unwrap_err(),
+            "`2x` is not a valid name"
+        );
+    }
+}
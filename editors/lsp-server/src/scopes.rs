@@ -0,0 +1,982 @@
+//! Definitions and references within one document, by scope.
+//!
+//! Built from the AST whenever the document parses. Scopes follow the
+//! interpreter: the program, each block, each function or lambda for its
+//! parameters, and each `for` loop for its variable. A reference resolves
+//! to the latest definition of its name before it in the innermost scope
+//! that has one, so shadowing works as at runtime. A name only defined
+//! further down, like a function called above its declaration, resolves to
+//! that definition once its scope closes.
+//!
+//! Identifier expressions carry their position, but declared names do not:
+//! those are matched, in source order, against the identifier tokens of the
+//! text that follow the keyword or bracket that declares them.
+//!
+//! Each definition keeps how it reads as a declaration, for hover. A
+//! variable without an annotation gets the type the evaluator infers for its
+//! value, or that of the variable or function return it is taken from.
+
+use infra::core::{AssignmentTarget, ExportItem, Expr, ImportItems, OptionalParams};
+use infra::core::{Pattern, Program, Stmt, StringPart, Type};
+use infra::frontend::printer::{format_signature, format_type};
+use infra::frontend::{Lexer, TokenType};
+use infra::Evaluator;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Variable,
+    Function,
+    Parameter,
+    Class,
+    Enum,
+    Import,
+}
+
+/// Where a name is written: a line from 0 and UTF-16 columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameRange {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl NameRange {
+    fn contains(&self, line: u32, character: u32) -> bool {
+        self.line == line && (self.start..=self.end).contains(&character)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    pub range: NameRange,
+    /// Such as `function add(a: number) -> number`, `let total: number`
+    /// or `a: number`
+    pub declaration: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub range: NameRange,
+    /// Index into `definitions`; `None` for builtins, names from
+    /// `import *` and anything else defined outside the document
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+/// What the name at a position stands for
+#[derive(Clone, Copy, PartialEq)]
+enum Target<'a> {
+    Defined(usize),
+    Unresolved(&'a str),
+}
+
+impl SymbolTable {
+    /// The table of `program`, parsed from `text`
+    pub fn build(text: &str, program: &Program) -> Self {
+        let mut builder = Builder::new(text);
+        builder.open_scope();
+        for stmt in &program.statements {
+            builder.statement(stmt);
+        }
+        builder.close_scope();
+        builder.table
+    }
+
+    /// The definition of the name at a position, whether that is a
+    /// reference to it or the definition itself
+    pub fn definition_at(&self, line: u32, character: u32) -> Option<&Definition> {
+        match self.target_at(line, character)? {
+            Target::Defined(index) => self.definitions.get(index),
+            Target::Unresolved(_) => None,
+        }
+    }
+
+    /// Everywhere the name at a position is used with the same meaning, in
+    /// source order, with its definition only if `include_declaration`.
+    /// Names defined outside the document match by name.
+    pub fn references_at(
+        &self,
+        line: u32,
+        character: u32,
+        include_declaration: bool,
+    ) -> Vec<NameRange> {
+        let Some(target) = self.target_at(line, character) else {
+            return Vec::new();
+        };
+
+        let mut ranges: Vec<NameRange> = self
+            .references
+            .iter()
+            .filter(|reference| match target {
+                Target::Defined(index) => reference.definition == Some(index),
+                Target::Unresolved(name) => {
+                    reference.definition.is_none() && reference.name == name
+                }
+            })
+            .map(|reference| reference.range)
+            .collect();
+        if let (Target::Defined(index), true) = (target, include_declaration) {
+            ranges.push(self.definitions[index].range);
+        }
+        ranges.sort_by_key(|range| (range.line, range.start));
+        ranges
+    }
+
+    fn target_at(&self, line: u32, character: u32) -> Option<Target<'_>> {
+        if let Some(index) = self
+            .definitions
+            .iter()
+            .position(|definition| definition.range.contains(line, character))
+        {
+            return Some(Target::Defined(index));
+        }
+        let reference = self
+            .references
+            .iter()
+            .find(|reference| reference.range.contains(line, character))?;
+        Some(match reference.definition {
+            Some(index) => Target::Defined(index),
+            None => Target::Unresolved(&reference.name),
+        })
+    }
+}
+
+/// The token before an identifier, which tells what can be declared there
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Before {
+    Let,
+    /// `function` or `def`
+    Function,
+    Class,
+    Enum,
+    For,
+    Catch,
+    Extends,
+    As,
+    /// `(`, `{`, `,`, `...` or `import`: the start or next item of a list
+    /// of parameters, tuple names or imported names
+    ListItem,
+    /// `.` or `?.`: a property name, never a declaration or reference
+    Dot,
+    Other,
+}
+
+/// An identifier token: 1-based line and character column, as the lexer
+/// counts them
+struct Name {
+    name: String,
+    line: usize,
+    column: usize,
+    before: Before,
+    /// Followed by `(`, as a method name is
+    opens_call: bool,
+    /// Followed by `=`, as a field name is
+    assigned: bool,
+}
+
+#[derive(Default)]
+struct Scope {
+    /// Each name defined so far, with its definition's index
+    names: Vec<(String, usize)>,
+    /// References in this scope, or one it encloses, that nothing defined
+    /// before them matched
+    pending: Vec<usize>,
+}
+
+struct Builder<'a> {
+    lines: Vec<&'a str>,
+    names: Vec<Name>,
+    /// The next name that can be matched to a declaration
+    cursor: usize,
+    scopes: Vec<Scope>,
+    table: SymbolTable,
+    evaluator: Evaluator,
+    /// Types of variables and return types of functions, by definition
+    types: HashMap<usize, Type>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(text: &'a str) -> Self {
+        let tokens = Lexer::new(text).tokenize().unwrap_or_default();
+        let mut names = Vec::new();
+        for (index, token) in tokens.iter().enumerate() {
+            let name = match &token.token_type {
+                TokenType::Identifier(name) => name.to_string(),
+                TokenType::Init => "init".to_string(),
+                _ => continue,
+            };
+            let before = match index.checked_sub(1).map(|i| &tokens[i].token_type) {
+                Some(TokenType::Let) => Before::Let,
+                Some(TokenType::Function | TokenType::Def) => Before::Function,
+                Some(TokenType::Class) => Before::Class,
+                Some(TokenType::Identifier(word)) if word == "enum" => Before::Enum,
+                Some(TokenType::For) => Before::For,
+                Some(TokenType::Catch) => Before::Catch,
+                Some(TokenType::Extends) => Before::Extends,
+                Some(TokenType::As) => Before::As,
+                Some(
+                    TokenType::LeftParen
+                    | TokenType::LeftBrace
+                    | TokenType::Comma
+                    | TokenType::Ellipsis
+                    | TokenType::Import,
+                ) => Before::ListItem,
+                Some(TokenType::Dot | TokenType::QuestionDot) => Before::Dot,
+                _ => Before::Other,
+            };
+            let next = tokens.get(index + 1).map(|next| &next.token_type);
+            names.push(Name {
+                name,
+                line: token.line,
+                column: token.column,
+                before,
+                opens_call: next == Some(&TokenType::LeftParen),
+                assigned: next == Some(&TokenType::Equal),
+            });
+        }
+
+        Self {
+            lines: text.lines().collect(),
+            names,
+            cursor: 0,
+            scopes: Vec::new(),
+            table: SymbolTable::default(),
+            evaluator: Evaluator::new(),
+            types: HashMap::new(),
+        }
+    }
+
+    /// The range of `name` written at a 1-based line and character column
+    fn range(&self, line: usize, column: usize, name: &str) -> Option<NameRange> {
+        let text = self.lines.get(line.checked_sub(1)?)?;
+        let start: u32 = text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| c.len_utf16() as u32)
+            .sum();
+        let length: u32 = name.chars().map(|c| c.len_utf16() as u32).sum();
+        Some(NameRange {
+            line: line as u32 - 1,
+            start,
+            end: start + length,
+        })
+    }
+
+    /// Where the next `name` after one of `before` is declared, moving
+    /// the cursor past it
+    fn locate(&mut self, name: &str, before: &[Before]) -> Option<NameRange> {
+        let offset = self.names[self.cursor..]
+            .iter()
+            .position(|token| token.name == name && before.contains(&token.before))?;
+        self.cursor += offset + 1;
+        let token = &self.names[self.cursor - 1];
+        self.range(token.line, token.column, name)
+    }
+
+    /// Move the cursor past an identifier expression at a 1-based position
+    fn sync(&mut self, line: usize, column: usize) {
+        self.cursor = self
+            .names
+            .partition_point(|token| (token.line, token.column) <= (line, column));
+    }
+
+    /// Add a definition, returning its index
+    fn define(
+        &mut self,
+        name: &str,
+        kind: DefinitionKind,
+        range: Option<NameRange>,
+        declaration: String,
+    ) -> Option<usize> {
+        let range = range?;
+        let index = self.table.definitions.len();
+        self.table.definitions.push(Definition {
+            name: name.to_string(),
+            kind,
+            range,
+            declaration,
+        });
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.push((name.to_string(), index));
+        }
+        Some(index)
+    }
+
+    fn variable(&mut self, name: &str, ty: Type, range: Option<NameRange>) {
+        let declaration = match ty {
+            Type::Any => format!("let {}", name),
+            _ => format!("let {}: {}", name, format_type(&ty)),
+        };
+        let index = self.define(name, DefinitionKind::Variable, range, declaration);
+        self.set_type(index, Some(ty));
+    }
+
+    fn set_type(&mut self, index: Option<usize>, ty: Option<Type>) {
+        if let (Some(index), Some(ty)) = (index, ty) {
+            self.types.insert(index, ty);
+        }
+    }
+
+    /// The type of a value: that of the variable it names or the return
+    /// type of the function it calls, or else what the evaluator infers
+    fn infer(&self, value: &Expr) -> Type {
+        let known = match value {
+            Expr::Identifier { name, .. } => self
+                .resolve(name)
+                .filter(|&index| self.table.definitions[index].kind != DefinitionKind::Function),
+            Expr::Call { callee, .. } => match callee.as_ref() {
+                Expr::Identifier { name, .. } => self.resolve(name).filter(|&index| {
+                    self.table.definitions[index].kind == DefinitionKind::Function
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+        known
+            .and_then(|index| self.types.get(&index).cloned())
+            .unwrap_or_else(|| self.evaluator.infer_expression_type(value))
+    }
+
+    /// The innermost, latest definition of `name` so far
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .names
+                .iter()
+                .rev()
+                .find(|(defined, _)| defined == name)
+                .map(|&(_, index)| index)
+        })
+    }
+
+    fn reference(&mut self, name: &str, range: Option<NameRange>) {
+        let Some(range) = range else {
+            return;
+        };
+        let definition = self.resolve(name);
+
+        let index = self.table.references.len();
+        self.table.references.push(Reference {
+            name: name.to_string(),
+            range,
+            definition,
+        });
+        if let (None, Some(scope)) = (definition, self.scopes.last_mut()) {
+            scope.pending.push(index);
+        }
+    }
+
+    fn open_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Resolve what is pending in the closing scope to its first definition
+    /// of the name, if it has one, and leave the rest to the scope around it
+    fn close_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let mut unresolved = Vec::new();
+        for index in scope.pending {
+            let reference = &mut self.table.references[index];
+            match scope.names.iter().find(|(name, _)| *name == reference.name) {
+                Some(&(_, definition)) => reference.definition = Some(definition),
+                None => unresolved.push(index),
+            }
+        }
+        if let Some(parent) = self.scopes.last_mut() {
+            parent.pending.extend(unresolved);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.expression(expr),
+            Stmt::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Let {
+                name,
+                type_annotation,
+                value,
+            }
+            | Stmt::Export {
+                item:
+                    ExportItem::Variable {
+                        name,
+                        type_annotation,
+                        value,
+                    },
+            } => {
+                // The value is evaluated before the name is defined
+                let at = self.locate(name, &[Before::Let]);
+                self.expression(value);
+                let ty = type_annotation.clone().unwrap_or_else(|| self.infer(value));
+                self.variable(name, ty, at);
+            }
+            Stmt::LetTuple { pattern, value, .. } => {
+                let names = pattern.names();
+                let at: Vec<_> = names
+                    .iter()
+                    .map(|name| self.locate(name, &[Before::ListItem]))
+                    .collect();
+                self.expression(value);
+                for (name, at) in names.into_iter().zip(at) {
+                    self.variable(name, Type::Any, at);
+                }
+            }
+            Stmt::If {
+                condition,
+                then_stmt,
+                else_stmt,
+            } => {
+                self.expression(condition);
+                self.statement(then_stmt);
+                if let Some(else_stmt) = else_stmt {
+                    self.statement(else_stmt);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::For {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                let at = self.locate(var, &[Before::For]);
+                self.expression(start);
+                self.expression(end);
+                self.loop_body(var, at, Type::Number, body);
+            }
+            Stmt::ForIn {
+                pattern: Pattern::Name(var),
+                iterable,
+                body,
+                ..
+            } => {
+                let at = self.locate(var, &[Before::For]);
+                self.expression(iterable);
+                let item = match self.infer(iterable) {
+                    Type::Array(item) => *item,
+                    _ => Type::Any,
+                };
+                self.loop_body(var, at, item, body);
+            }
+            // The names of a tuple pattern are located as a destructuring
+            // `let`'s are
+            Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                let names = pattern.names();
+                let at: Vec<_> = names
+                    .iter()
+                    .map(|name| self.locate(name, &[Before::ListItem]))
+                    .collect();
+                self.expression(iterable);
+                self.open_scope();
+                for (name, at) in names.into_iter().zip(at) {
+                    self.variable(name, Type::Any, at);
+                }
+                self.statement(body);
+                self.close_scope();
+            }
+            Stmt::Block(statements) => {
+                self.open_scope();
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.close_scope();
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                decorators,
+            }
+            | Stmt::Export {
+                item:
+                    ExportItem::Function {
+                        name,
+                        params,
+                        param_types,
+                        optional_params,
+                        return_type,
+                        body,
+                        decorators,
+                    },
+            } => {
+                for decorator in decorators {
+                    self.expression(decorator);
+                }
+                let declaration = format_signature(
+                    "function ",
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                );
+                let index = self.function(
+                    name,
+                    declaration,
+                    params,
+                    param_types,
+                    optional_params,
+                    body,
+                );
+                self.set_type(index, return_type.clone());
+            }
+            Stmt::AsyncFunction {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+            } => {
+                let declaration = format_signature(
+                    "async function ",
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                );
+                self.function(
+                    name,
+                    declaration,
+                    params,
+                    param_types,
+                    optional_params,
+                    body,
+                );
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+            } => {
+                let at = self.locate(name, &[Before::Class]);
+                let declaration = match superclass {
+                    Some(parent) => format!("class {} extends {}", name, parent),
+                    None => format!("class {}", name),
+                };
+                self.define(name, DefinitionKind::Class, at, declaration);
+                if let Some(parent) = superclass {
+                    let at = self.locate(parent, &[Before::Extends]);
+                    self.reference(parent, at);
+                }
+                self.class_body(fields, methods);
+            }
+            Stmt::Try {
+                try_block,
+                catch_var,
+                catch_block,
+            } => {
+                self.statement(try_block);
+                // Defined in the enclosing scope, as the interpreter does
+                let at = self.locate(catch_var, &[Before::Catch]);
+                let declaration = format!("catch {}", catch_var);
+                self.define(catch_var, DefinitionKind::Variable, at, declaration);
+                self.statement(catch_block);
+            }
+            Stmt::Assignment { target, value, .. } => {
+                match target {
+                    AssignmentTarget::Identifier(name) => {
+                        let at = self.locate(name, &[Before::Other, Before::ListItem]);
+                        self.reference(name, at);
+                    }
+                    AssignmentTarget::Property { object, .. } => self.expression(object),
+                    AssignmentTarget::Index { object, index } => {
+                        self.expression(object);
+                        self.expression(index);
+                    }
+                }
+                self.expression(value);
+            }
+            Stmt::Import { items, alias, .. } => match items {
+                ImportItems::Named(items) => {
+                    for item in items {
+                        let mut at = self.locate(&item.name, &[Before::ListItem]);
+                        if let Some(alias) = &item.alias {
+                            at = self.locate(alias, &[Before::As]);
+                        }
+                        let name = item.alias.as_ref().unwrap_or(&item.name);
+                        self.define(name, DefinitionKind::Import, at, format!("import {}", name));
+                    }
+                }
+                ImportItems::Default(name) => {
+                    let mut at = self.locate(name, &[Before::ListItem]);
+                    if let Some(alias) = alias {
+                        at = self.locate(alias, &[Before::As]);
+                    }
+                    let name = alias.as_ref().unwrap_or(name);
+                    self.define(name, DefinitionKind::Import, at, format!("import {}", name));
+                }
+                ImportItems::All => {
+                    if let Some(alias) = alias {
+                        let at = self.locate(alias, &[Before::As]);
+                        let declaration = format!("import * as {}", alias);
+                        self.define(alias, DefinitionKind::Import, at, declaration);
+                    }
+                }
+            },
+            Stmt::Enum { name, .. } => {
+                let at = self.locate(name, &[Before::Enum]);
+                self.define(name, DefinitionKind::Enum, at, format!("enum {}", name));
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+            } => {
+                self.expression(subject);
+                for arm in arms {
+                    for pattern in &arm.patterns {
+                        self.expression(pattern);
+                    }
+                    self.statement(&arm.body);
+                }
+                if let Some(default) = default {
+                    self.statement(default);
+                }
+            }
+        }
+    }
+
+    fn loop_body(&mut self, var: &str, at: Option<NameRange>, ty: Type, body: &Stmt) {
+        self.open_scope();
+        self.variable(var, ty, at);
+        self.statement(body);
+        self.close_scope();
+    }
+
+    /// A named function: the name is defined before the body, so it can
+    /// call itself. Returns the index of its definition.
+    fn function(
+        &mut self,
+        name: &str,
+        declaration: String,
+        params: &[String],
+        param_types: &[Option<Type>],
+        optional_params: &OptionalParams,
+        body: &Stmt,
+    ) -> Option<usize> {
+        let at = self.locate(name, &[Before::Function]);
+        let index = self.define(name, DefinitionKind::Function, at, declaration);
+        self.open_scope();
+        self.parameters(params, param_types, optional_params);
+        self.statement(body);
+        self.close_scope();
+        index
+    }
+
+    /// Each parameter in turn; a default value sees the ones before it
+    fn parameters(
+        &mut self,
+        params: &[String],
+        param_types: &[Option<Type>],
+        optional_params: &OptionalParams,
+    ) {
+        let first_default = optional_params.required(params.len());
+        for (index, param) in params.iter().enumerate() {
+            let at = self.locate(param, &[Before::ListItem]);
+            if let Some(default) = index
+                .checked_sub(first_default)
+                .and_then(|default| optional_params.defaults.get(default))
+            {
+                self.expression(default);
+            }
+            let mut declaration = if optional_params.rest && index + 1 == params.len() {
+                format!("...{}", param)
+            } else {
+                param.clone()
+            };
+            let ty = param_types.get(index).cloned().flatten();
+            if let Some(ty) = ty.as_ref().filter(|ty| **ty != Type::Any) {
+                declaration.push_str(&format!(": {}", format_type(ty)));
+            }
+            let index = self.define(param, DefinitionKind::Parameter, at, declaration);
+            self.set_type(index, ty);
+        }
+    }
+
+    /// Fields and methods in source order; the AST keeps them apart
+    fn class_body(&mut self, fields: &[(String, Expr)], methods: &[infra::core::MethodDecl]) {
+        enum Member<'m> {
+            Field(&'m Expr),
+            Method(&'m infra::core::MethodDecl),
+        }
+
+        let body = self.cursor;
+        let find = |names: &[Name], from: usize, name: &str, method: bool| {
+            names[from..]
+                .iter()
+                .position(|token| {
+                    token.name == name
+                        && token.before != Before::Dot
+                        && if method {
+                            token.opens_call
+                        } else {
+                            token.assigned
+                        }
+                })
+                .map(|offset| from + offset)
+        };
+
+        let mut members = Vec::new();
+        let mut from = body;
+        for (name, value) in fields {
+            if let Some(index) = find(&self.names, from, name, false) {
+                members.push((index, Member::Field(value)));
+                from = index + 1;
+            }
+        }
+        from = body;
+        for method in methods {
+            if let Some(index) = find(&self.names, from, &method.name, true) {
+                members.push((index, Member::Method(method)));
+                from = index + 1;
+            }
+        }
+        members.sort_by_key(|&(index, _)| index);
+
+        for (index, member) in members {
+            self.cursor = self.cursor.max(index + 1);
+            match member {
+                Member::Field(value) => self.expression(value),
+                Member::Method(method) => {
+                    self.open_scope();
+                    self.parameters(&method.params, &method.param_types, &method.optional_params);
+                    self.statement(&method.body);
+                    self.close_scope();
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier { name, span } => {
+                if let Some(span) = span {
+                    let at = self.range(span.line, span.column, name);
+                    self.sync(span.line, span.column);
+                    self.reference(name, at);
+                }
+            }
+            Expr::Literal(_) | Expr::This | Expr::Super { .. } | Expr::ModuleAccess { .. } => {}
+            Expr::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Unary { operand, .. } => self.expression(operand),
+            Expr::Call { callee, args, .. }
+            | Expr::New {
+                class: callee,
+                args,
+            } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            Expr::Array(items) | Expr::Tuple(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            Expr::Object(entries) => {
+                for (_, value) in entries {
+                    self.expression(value);
+                }
+            }
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expression(expr);
+                    }
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::Property { object, .. } => self.expression(object),
+            Expr::Await { expression } => self.expression(expression),
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.expression(condition);
+                self.expression(then_expr);
+                self.expression(else_expr);
+            }
+            Expr::Lambda {
+                params,
+                param_types,
+                optional_params,
+                body,
+                ..
+            } => {
+                self.open_scope();
+                self.parameters(params, param_types, optional_params);
+                self.expression(body);
+                self.close_scope();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    fn symbols(text: &str) -> SymbolTable {
+        SymbolTable::build(text, &syntax::parse(text).unwrap())
+    }
+
+    fn at(line: u32, start: u32, end: u32) -> NameRange {
+        NameRange { line, start, end }
+    }
+
+    #[test]
+    fn references_resolve_to_the_innermost_earlier_definition() {
+        let text = "let x = 1\n\
+                    function show(x):\n    \
+                    print(x)\n\
+                    for i in range(0, x): {\n    \
+                    let x = i\n    \
+                    print(x)\n\
+                    }\n\
+                    print(x)\n";
+        let table = symbols(text);
+
+        // The parameter shadows the global
+        let parameter = table.definition_at(2, 11).unwrap();
+        assert_eq!(parameter.kind, DefinitionKind::Parameter);
+        assert_eq!(parameter.range, at(1, 14, 15));
+
+        // The loop bound is read before the loop's own `x` exists
+        assert_eq!(table.definition_at(3, 19).unwrap().range, at(0, 4, 5));
+        assert_eq!(table.definition_at(5, 10).unwrap().range, at(4, 8, 9));
+        assert_eq!(table.definition_at(4, 12).unwrap().range, at(3, 4, 5));
+        assert_eq!(table.definition_at(7, 6).unwrap().range, at(0, 4, 5));
+
+        assert_eq!(
+            table.references_at(0, 4, true),
+            vec![at(0, 4, 5), at(3, 18, 19), at(7, 6, 7)]
+        );
+        assert_eq!(
+            table.references_at(7, 6, false),
+            vec![at(3, 18, 19), at(7, 6, 7)]
+        );
+    }
+
+    #[test]
+    fn functions_resolve_before_their_declaration_and_recursively() {
+        let text = "function even(n):\n    \
+                    return n == 0 ? true : odd(n - 1)\n\
+                    function odd(n):\n    \
+                    return n != 0 && even(n - 1)\n";
+        let table = symbols(text);
+
+        let odd = table.definition_at(1, 28).unwrap();
+        assert_eq!((odd.name.as_str(), odd.range), ("odd", at(2, 9, 12)));
+        assert_eq!(table.definition_at(3, 21).unwrap().range, at(0, 9, 13));
+        assert_eq!(table.definition_at(1, 11).unwrap().range, at(0, 14, 15));
+    }
+
+    #[test]
+    fn declarations_are_not_confused_with_keys_and_properties() {
+        let text = "import {area as size} from \"./shapes\"\n\
+                    let point = {x: 1, y: 2}\n\
+                    let x = point.x + size(2)\n\
+                    let label = \"${x}\"\n\
+                    let add = function(a, b = x): a + b\n";
+        let table = symbols(text);
+
+        let x = table.definition_at(2, 4).unwrap();
+        assert_eq!(x.range, at(2, 4, 5));
+        assert_eq!(
+            table.references_at(2, 4, false),
+            vec![at(3, 15, 16), at(4, 26, 27)]
+        );
+        let size = table.definition_at(2, 19).unwrap();
+        assert_eq!(
+            (size.kind, size.range),
+            (DefinitionKind::Import, at(0, 16, 20))
+        );
+        assert_eq!(
+            table.definition_at(4, 34).unwrap().kind,
+            DefinitionKind::Parameter
+        );
+
+        // Builtins have no definition, but their uses are still found
+        let text = "print(len([1]))\nprint(len([]))\n";
+        let table = symbols(text);
+        assert!(table.definition_at(0, 7).is_none());
+        assert_eq!(
+            table.references_at(0, 7, true),
+            vec![at(0, 6, 9), at(1, 6, 9)]
+        );
+    }
+
+    #[test]
+    fn columns_are_utf16_code_units() {
+        let table = symbols("let s = \"é\"\nprint(\"😀\" + s)\n");
+        assert_eq!(table.definition_at(1, 13).unwrap().range, at(0, 4, 5));
+        assert_eq!(table.references_at(0, 4, false), vec![at(1, 13, 14)]);
+    }
+
+    #[test]
+    fn class_methods_get_their_own_parameter_scope() {
+        let text = "let name = \"outer\"\n\
+                    class Greeter:\n\
+                    {\n    \
+                    greet(name):\n        \
+                    return \"hi \" + name\n    \
+                    prefix = name\n\
+                    }\n";
+        let table = symbols(text);
+        assert_eq!(table.definition_at(4, 23).unwrap().range, at(3, 10, 14));
+        assert_eq!(table.definition_at(5, 14).unwrap().range, at(0, 4, 8));
+    }
+
+    #[test]
+    fn tuple_loop_names_are_defined_for_the_body_only() {
+        let text = "let k = 0\n\
+                    for (k, (a, b)) in [(1, (2, 3))]:\n    \
+                    print(k + a + b)\n\
+                    print(k)\n";
+        let table = symbols(text);
+
+        assert_eq!(table.definition_at(2, 10).unwrap().range, at(1, 5, 6));
+        assert_eq!(table.definition_at(2, 14).unwrap().range, at(1, 9, 10));
+        assert_eq!(table.definition_at(2, 18).unwrap().range, at(1, 12, 13));
+        assert_eq!(table.definition_at(3, 6).unwrap().range, at(0, 4, 5));
+    }
+}
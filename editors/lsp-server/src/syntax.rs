@@ -0,0 +1,129 @@
+//! Syntax errors from the Infra frontend itself.
+//!
+//! The scanner in `analysis` only follows brackets and strings, so this runs
+//! the interpreter's own lexer and parser over the document to report what
+//! `infra` would reject before running it. The frontend stops at the first
+//! error, so there is at most one. Its lines are 1-based and its columns
+//! count characters; both are converted to LSP positions here. The passes
+//! that need the AST itself get it from `parse`.
+
+use infra::core::{InfraError, Program};
+use infra::frontend::{Lexer, Parser};
+
+/// The first lexical or parse error in `text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+pub fn check(text: &str) -> Option<SyntaxError> {
+    parse(text).err()
+}
+
+/// The AST of `text`, or its first error
+pub fn parse(text: &str) -> Result<Program, SyntaxError> {
+    let error = match Lexer::new(text).tokenize() {
+        Ok(tokens) => match Parser::new(tokens).parse() {
+            Ok(program) => return Ok(program),
+            Err(error) => error,
+        },
+        Err(error) => error,
+    };
+
+    let (message, line, column, hint) = match error {
+        InfraError::LexError {
+            message,
+            line,
+            column,
+            ..
+        } => (message, line, column, None),
+        InfraError::ParseError {
+            message,
+            line,
+            column,
+            hint,
+            ..
+        } => (message, line, column, hint),
+        other => (other.to_string(), 1, 1, None),
+    };
+
+    let (line, start, end) = span(text, line, column);
+    Err(SyntaxError {
+        line,
+        start,
+        end,
+        message,
+        hint,
+    })
+}
+
+/// The UTF-16 range of the word, or else the single character, at a
+/// 1-based line and character column. Past the end of the text this is the
+/// end of the last line.
+fn span(text: &str, line: usize, column: usize) -> (u32, u32, u32) {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(last) = lines.len().checked_sub(1) else {
+        return (0, 0, 0);
+    };
+    let line = line.saturating_sub(1);
+    let (line, column) = if line > last {
+        (last, usize::MAX)
+    } else {
+        (line, column.saturating_sub(1))
+    };
+
+    let units = |chars: &[char]| chars.iter().map(|c| c.len_utf16() as u32).sum::<u32>();
+    let chars: Vec<char> = lines[line].chars().collect();
+    let first = column.min(chars.len());
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    let length = match chars.get(first) {
+        Some(c) if is_word(c) => chars[first..].iter().take_while(|c| is_word(c)).count(),
+        Some(_) => 1,
+        None => 0,
+    };
+
+    let start = units(&chars[..first]);
+    let end = start + units(&chars[first..first + length]);
+    (line as u32, start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_documents_have_no_error() {
+        assert_eq!(check("let x = 1\nprint(x)\n"), None);
+        assert_eq!(check(""), None);
+    }
+
+    #[test]
+    fn parse_errors_cover_the_word_they_are_at() {
+        let error = check("let x = 1\nlet = 2\n").unwrap();
+        assert_eq!((error.line, error.start, error.end), (1, 4, 5));
+        assert_eq!(error.message, "Expected variable name after 'let'");
+
+        let error = check("let t = (1)\nprint(t\n").unwrap();
+        assert_eq!((error.line, error.start, error.end), (1, 7, 7));
+    }
+
+    #[test]
+    fn lexical_errors_use_utf16_columns() {
+        let error = check("let s = \"😀\" $ 2\n").unwrap();
+        assert_eq!(error.message, "Unexpected character '$'");
+        assert_eq!((error.line, error.start, error.end), (0, 13, 14));
+    }
+
+    #[test]
+    fn hints_are_kept() {
+        let error = check("let (a) = [1]\n").unwrap();
+        assert_eq!(
+            error.hint.as_deref(),
+            Some("Write `let (name,) = ...` to take apart a one-element tuple")
+        );
+    }
+}
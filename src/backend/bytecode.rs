@@ -1,17 +1,20 @@
 use crate::core::{
-    ast::{BinaryOp, Expr, Program, Stmt, UnaryOp},
-    Value,
+    ast::{AssignmentTarget, BinaryOp, Expr, Program, Stmt, UnaryOp},
+    InfraError, Value,
 };
 use std::collections::HashMap;
+use std::fmt::Write;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(dead_code)]
 pub enum OpCode {
     // Stack operations
-    LoadConst(usize), // Load constant from constant pool
-    LoadVar(usize),   // Load variable from local variable table
-    StoreVar(usize),  // Store to local variable table
-    Pop,              // Pop top value from stack
+    LoadConst(usize),   // Load constant from constant pool
+    LoadVar(usize),     // Load variable from local variable table
+    StoreVar(usize),    // Store to local variable table
+    LoadGlobal(usize),  // Load variable from the top level's table
+    StoreGlobal(usize), // Store to the top level's table
+    Pop,                // Pop top value from stack
 
     // Arithmetic operations
     Add,
@@ -64,6 +67,89 @@ pub enum OpCode {
     Halt,
 }
 
+impl OpCode {
+    /// The instruction's name in a disassembly
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpCode::LoadConst(_) => "LOAD_CONST",
+            OpCode::LoadVar(_) => "LOAD_VAR",
+            OpCode::StoreVar(_) => "STORE_VAR",
+            OpCode::LoadGlobal(_) => "LOAD_GLOBAL",
+            OpCode::StoreGlobal(_) => "STORE_GLOBAL",
+            OpCode::Pop => "POP",
+            OpCode::Add => "ADD",
+            OpCode::Sub => "SUB",
+            OpCode::Mul => "MUL",
+            OpCode::Div => "DIV",
+            OpCode::Mod => "MOD",
+            OpCode::Negate => "NEGATE",
+            OpCode::Equal => "EQUAL",
+            OpCode::NotEqual => "NOT_EQUAL",
+            OpCode::Less => "LESS",
+            OpCode::LessEqual => "LESS_EQUAL",
+            OpCode::Greater => "GREATER",
+            OpCode::GreaterEqual => "GREATER_EQUAL",
+            OpCode::And => "AND",
+            OpCode::Or => "OR",
+            OpCode::Not => "NOT",
+            OpCode::Jump(_) => "JUMP",
+            OpCode::JumpIfFalse(_) => "JUMP_IF_FALSE",
+            OpCode::Call(_) => "CALL",
+            OpCode::Return => "RETURN",
+            OpCode::Print => "PRINT",
+            OpCode::MakeArray(_) => "MAKE_ARRAY",
+            OpCode::ArrayGet => "ARRAY_GET",
+            OpCode::ArraySet => "ARRAY_SET",
+            OpCode::MakeObject(_) => "MAKE_OBJECT",
+            OpCode::ObjectGet => "OBJECT_GET",
+            OpCode::ObjectSet => "OBJECT_SET",
+            OpCode::CreatePromise => "CREATE_PROMISE",
+            OpCode::ResolvePromise => "RESOLVE_PROMISE",
+            OpCode::RejectPromise => "REJECT_PROMISE",
+            OpCode::Await => "AWAIT",
+            OpCode::AsyncCall => "ASYNC_CALL",
+            OpCode::Halt => "HALT",
+        }
+    }
+
+    /// Whether the instruction pops two values and pushes one result
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            OpCode::Add
+                | OpCode::Sub
+                | OpCode::Mul
+                | OpCode::Div
+                | OpCode::Mod
+                | OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::And
+                | OpCode::Or
+        )
+    }
+
+    /// The instruction's operand, for those that take one
+    pub fn operand(&self) -> Option<usize> {
+        match *self {
+            OpCode::LoadConst(n)
+            | OpCode::LoadVar(n)
+            | OpCode::StoreVar(n)
+            | OpCode::LoadGlobal(n)
+            | OpCode::StoreGlobal(n)
+            | OpCode::Jump(n)
+            | OpCode::JumpIfFalse(n)
+            | OpCode::Call(n)
+            | OpCode::MakeArray(n)
+            | OpCode::MakeObject(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Chunk {
@@ -103,6 +189,41 @@ impl Chunk {
         self.code.len() - 1
     }
 
+    /// A listing of the chunk headed by `name`: each instruction's offset,
+    /// source line ('|' when it is the line above's), name and operand, and
+    /// the value a LOAD_CONST loads
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut listing = format!("== {} ==\n", name);
+
+        for (offset, op) in self.code.iter().enumerate() {
+            let line = self.lines.get(offset).copied().unwrap_or(0);
+            let _ = write!(listing, "{:04} ", offset);
+            if offset > 0 && self.lines.get(offset - 1) == Some(&line) {
+                listing.push_str("   | ");
+            } else {
+                let _ = write!(listing, "{:4} ", line);
+            }
+
+            match (op, op.operand()) {
+                (OpCode::LoadConst(index), _) => {
+                    let constant = match self.constants.get(*index) {
+                        Some(Value::String(text)) => format!("{:?}", text),
+                        Some(value) => value.to_string(),
+                        None => "<missing>".to_string(),
+                    };
+                    let _ = write!(listing, "{:<16} {:4} ({})", op.name(), index, constant);
+                }
+                (_, Some(operand)) => {
+                    let _ = write!(listing, "{:<16} {:4}", op.name(), operand);
+                }
+                (_, None) => listing.push_str(op.name()),
+            }
+            listing.push('\n');
+        }
+
+        listing
+    }
+
     pub fn patch_jump(&mut self, offset: usize) {
         let jump_target = self.code.len();
         if let Some(OpCode::Jump(_)) | Some(OpCode::JumpIfFalse(_)) = self.code.get_mut(offset) {
@@ -115,12 +236,25 @@ impl Chunk {
     }
 }
 
+/// Where a variable lives when the VM runs
+enum Slot {
+    /// In the running function's (or the top level's) table
+    Local(usize),
+    /// In the top level's table, seen from inside a function
+    Global(usize),
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct Compiler {
     chunk: Chunk,
     locals: HashMap<String, usize>,
     local_count: usize,
+    /// Variables and slot counts of the functions around the one being
+    /// compiled, the top level first
+    enclosing: Vec<(HashMap<String, usize>, usize)>,
+    /// Source line of the code being compiled, or 0
+    line: usize,
 }
 
 impl Compiler {
@@ -129,199 +263,362 @@ impl Compiler {
             chunk: Chunk::new(),
             locals: HashMap::new(),
             local_count: 0,
+            enclosing: Vec::new(),
+            line: 0,
         }
     }
 
-    pub fn compile(mut self, program: &Program) -> Result<Chunk, crate::core::error::InfraError> {
+    pub fn compile(mut self, program: &Program) -> Result<Chunk, InfraError> {
+        // Top-level functions can call each other whatever order they are in
         for stmt in &program.statements {
+            if let Stmt::Function { name, .. } = stmt {
+                self.declare(name);
+            }
+        }
+
+        for (index, stmt) in program.statements.iter().enumerate() {
+            self.line = program.line_of(index).unwrap_or(0);
             self.compile_stmt(stmt)?;
         }
 
         // Emit halt instruction at the end
-        self.chunk.emit(OpCode::Halt, 0);
+        self.emit(OpCode::Halt);
 
         Ok(self.chunk)
     }
 
-    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), crate::core::error::InfraError> {
+    fn emit(&mut self, op: OpCode) {
+        self.chunk.emit(op, self.line);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.chunk.emit_jump(op, self.line)
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), InfraError> {
         match stmt {
             Stmt::Expression(expr) => {
                 self.compile_expr(expr)?;
-                self.chunk.emit(OpCode::Pop, 0); // Pop unused expression result
+                self.emit(OpCode::Pop); // Pop unused expression result
             }
 
             Stmt::Print(expr) => {
                 self.compile_expr(expr)?;
-                self.chunk.emit(OpCode::Print, 0);
+                self.emit(OpCode::Print);
             }
 
             Stmt::Let { name, value, .. } => {
                 self.compile_expr(value)?;
-                let local_index = self.local_count;
-                self.locals.insert(name.clone(), local_index);
-                self.local_count += 1;
-                self.chunk.emit(OpCode::StoreVar(local_index), 0);
+                let local_index = self.declare(name);
+                self.emit(OpCode::StoreVar(local_index));
             }
 
-            Stmt::Assignment { target, value } => {
+            Stmt::Assignment { target, value, .. } => {
                 self.compile_expr(value)?;
                 match target {
-                    crate::core::ast::AssignmentTarget::Identifier(name) => {
-                        if let Some(&local_index) = self.locals.get(name) {
-                            self.chunk.emit(OpCode::StoreVar(local_index), 0);
-                        } else {
-                            return Err(crate::core::error::InfraError::UndefinedVariable {
-                                name: name.clone(),
-                                line: None,
-                                column: None,
-                                suggestion: None,
-                            });
-                        }
-                    }
-                    _ => {
-                        return Err(crate::core::error::InfraError::RuntimeError {
-                            message: "Complex assignment targets not yet supported in bytecode"
-                                .to_string(),
-                            line: None,
-                            column: None,
-                            stack_trace: vec![],
-                            source_code: None,
-                        });
-                    }
+                    AssignmentTarget::Identifier(name) => match self.resolve(name)? {
+                        Slot::Local(slot) => self.emit(OpCode::StoreVar(slot)),
+                        Slot::Global(slot) => self.emit(OpCode::StoreGlobal(slot)),
+                    },
+                    _ => return Err(self.unsupported("Assignment to a property or index")),
                 }
             }
 
+            Stmt::Return(expr) => {
+                match expr {
+                    Some(expr) => self.compile_expr(expr)?,
+                    None => self.emit_null(),
+                }
+                self.emit(OpCode::Return);
+            }
+
+            Stmt::Function {
+                name,
+                params,
+                optional_params,
+                body,
+                decorators,
+                ..
+            } => {
+                if !decorators.is_empty() {
+                    return Err(self.unsupported("A decorated function"));
+                }
+                if !optional_params.is_empty() {
+                    return Err(self.unsupported("A default or rest parameter"));
+                }
+                // Bound before the body is compiled, so the function can call itself
+                let slot = match self.locals.get(name) {
+                    Some(&slot) => slot,
+                    None => self.declare(name),
+                };
+                let entry = self.compile_function(params, body)?;
+
+                let function = self.chunk.add_constant(Value::CompiledFunction {
+                    name: name.clone(),
+                    arity: params.len(),
+                    entry,
+                });
+                self.emit(OpCode::LoadConst(function));
+                self.emit(OpCode::StoreVar(slot));
+            }
+
             Stmt::Block(statements) => {
+                // A `let` in the block hides outer variables only until it ends
+                let outer = self.locals.clone();
                 for stmt in statements {
                     self.compile_stmt(stmt)?;
                 }
+                self.locals = outer;
             }
 
-            Stmt::Return(expr) => {
-                if let Some(expr) = expr {
-                    self.compile_expr(expr)?;
-                } else {
-                    let null_const = self.chunk.add_constant(Value::Null);
-                    self.chunk.emit(OpCode::LoadConst(null_const), 0);
+            Stmt::If {
+                condition,
+                then_stmt,
+                else_stmt,
+            } => {
+                self.compile_expr(condition)?;
+                let to_else = self.emit_jump(OpCode::JumpIfFalse(0));
+                self.compile_stmt(then_stmt)?;
+
+                match else_stmt {
+                    Some(else_stmt) => {
+                        let to_end = self.emit_jump(OpCode::Jump(0));
+                        self.chunk.patch_jump(to_else);
+                        self.compile_stmt(else_stmt)?;
+                        self.chunk.patch_jump(to_end);
+                    }
+                    None => self.chunk.patch_jump(to_else),
                 }
-                self.chunk.emit(OpCode::Return, 0);
             }
-            Stmt::Function { name, .. } => {
-                // For now, compile function as a placeholder
-                // In a full implementation, we'd compile the function body separately
-                let func_name_const = self.chunk.add_constant(Value::String(name.clone()));
-                self.chunk.emit(OpCode::LoadConst(func_name_const), 0);
-                // Placeholder: push function as a value
-                // TODO: Implement proper function compilation
-            }
-            Stmt::AsyncFunction { name, .. } => {
-                // Compile async function similarly to regular function
-                let func_name_const = self.chunk.add_constant(Value::String(name.clone()));
-                self.chunk.emit(OpCode::LoadConst(func_name_const), 0);
-                // Placeholder: push async function as a value
-                // TODO: Implement proper async function compilation
+
+            Stmt::While { condition, body } => {
+                let start = self.chunk.code.len();
+                self.compile_expr(condition)?;
+                let to_end = self.emit_jump(OpCode::JumpIfFalse(0));
+                self.compile_stmt(body)?;
+                self.emit(OpCode::Jump(start));
+                self.chunk.patch_jump(to_end);
             }
 
             _ => {
-                return Err(crate::core::error::InfraError::RuntimeError {
-                    message: format!("Statement type not yet supported in bytecode: {:?}", stmt),
-                    line: None,
-                    column: None,
-                    stack_trace: vec![],
-                    source_code: None,
-                });
+                let kind = variant_name(stmt);
+                return Err(self.unsupported(&format!("The '{}' statement", kind)));
             }
         }
 
         Ok(())
     }
 
-    fn compile_expr(&mut self, expr: &Expr) -> Result<(), crate::core::error::InfraError> {
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), InfraError> {
+        self.set_line(expr);
+
         match expr {
             Expr::Literal(value) => {
                 let const_index = self.chunk.add_constant(value.clone());
-                self.chunk.emit(OpCode::LoadConst(const_index), 0);
+                self.emit(OpCode::LoadConst(const_index));
             }
 
-            Expr::Identifier(name) => {
-                if let Some(&local_index) = self.locals.get(name) {
-                    self.chunk.emit(OpCode::LoadVar(local_index), 0);
-                } else {
-                    return Err(crate::core::error::InfraError::UndefinedVariable {
-                        name: name.clone(),
-                        line: None,
-                        column: None,
-                        suggestion: None,
-                    });
+            Expr::Identifier { name, .. } => match self.resolve(name)? {
+                Slot::Local(slot) => self.emit(OpCode::LoadVar(slot)),
+                Slot::Global(slot) => self.emit(OpCode::LoadGlobal(slot)),
+            },
+
+            Expr::Call { callee, args, .. } => {
+                match callee.as_ref() {
+                    // Built-in and standard library functions only exist in the interpreter
+                    Expr::Identifier { name, .. } if self.resolve(name).is_err() => {
+                        return Err(self.unsupported(&format!("Calling '{}'", name)));
+                    }
+                    _ => self.compile_expr(callee)?,
                 }
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                self.set_line(expr);
+                self.emit(OpCode::Call(args.len()));
             }
 
             Expr::Binary {
                 left,
                 operator,
                 right,
+                ..
             } => {
                 self.compile_expr(left)?;
                 self.compile_expr(right)?;
 
+                self.set_line(expr);
                 match operator {
-                    BinaryOp::Add => self.chunk.emit(OpCode::Add, 0),
-                    BinaryOp::Subtract => self.chunk.emit(OpCode::Sub, 0),
-                    BinaryOp::Multiply => self.chunk.emit(OpCode::Mul, 0),
-                    BinaryOp::Divide => self.chunk.emit(OpCode::Div, 0),
-                    BinaryOp::Modulo => self.chunk.emit(OpCode::Mod, 0),
-                    BinaryOp::Equal => self.chunk.emit(OpCode::Equal, 0),
-                    BinaryOp::NotEqual => self.chunk.emit(OpCode::NotEqual, 0),
-                    BinaryOp::Less => self.chunk.emit(OpCode::Less, 0),
-                    BinaryOp::Greater => self.chunk.emit(OpCode::Greater, 0),
-                    BinaryOp::LessEqual => self.chunk.emit(OpCode::LessEqual, 0),
-                    BinaryOp::GreaterEqual => self.chunk.emit(OpCode::GreaterEqual, 0),
-                    BinaryOp::And => self.chunk.emit(OpCode::And, 0),
-                    BinaryOp::Or => self.chunk.emit(OpCode::Or, 0),
+                    BinaryOp::Add => self.emit(OpCode::Add),
+                    BinaryOp::Subtract => self.emit(OpCode::Sub),
+                    BinaryOp::Multiply => self.emit(OpCode::Mul),
+                    BinaryOp::Divide => self.emit(OpCode::Div),
+                    BinaryOp::Modulo => self.emit(OpCode::Mod),
+                    BinaryOp::Equal => self.emit(OpCode::Equal),
+                    BinaryOp::NotEqual => self.emit(OpCode::NotEqual),
+                    BinaryOp::Less => self.emit(OpCode::Less),
+                    BinaryOp::Greater => self.emit(OpCode::Greater),
+                    BinaryOp::LessEqual => self.emit(OpCode::LessEqual),
+                    BinaryOp::GreaterEqual => self.emit(OpCode::GreaterEqual),
+                    BinaryOp::And => self.emit(OpCode::And),
+                    BinaryOp::Or => self.emit(OpCode::Or),
+                    BinaryOp::Coalesce => return Err(self.unsupported("The '??' operator")),
                 }
             }
 
-            Expr::Unary { operator, operand } => {
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.compile_expr(condition)?;
+                let to_else = self.emit_jump(OpCode::JumpIfFalse(0));
+                self.compile_expr(then_expr)?;
+                let to_end = self.emit_jump(OpCode::Jump(0));
+                self.chunk.patch_jump(to_else);
+                self.compile_expr(else_expr)?;
+                self.chunk.patch_jump(to_end);
+            }
+
+            Expr::Unary {
+                operator, operand, ..
+            } => {
                 self.compile_expr(operand)?;
 
+                self.set_line(expr);
                 match operator {
-                    UnaryOp::Minus => self.chunk.emit(OpCode::Negate, 0),
-                    UnaryOp::Not => self.chunk.emit(OpCode::Not, 0),
+                    UnaryOp::Minus => self.emit(OpCode::Negate),
+                    UnaryOp::Not => self.emit(OpCode::Not),
                 }
             }
 
-            Expr::Await { expression } => {
-                self.compile_expr(expression)?;
-                self.chunk.emit(OpCode::Await, 0);
-            }
-
             Expr::Array(elements) => {
                 for element in elements {
                     self.compile_expr(element)?;
                 }
-                self.chunk.emit(OpCode::MakeArray(elements.len()), 0);
+                self.emit(OpCode::MakeArray(elements.len()));
             }
 
             Expr::Object(fields) => {
                 for (key, value) in fields {
                     let key_const = self.chunk.add_constant(Value::String(key.clone()));
-                    self.chunk.emit(OpCode::LoadConst(key_const), 0);
+                    self.emit(OpCode::LoadConst(key_const));
                     self.compile_expr(value)?;
                 }
-                self.chunk.emit(OpCode::MakeObject(fields.len()), 0);
+                self.emit(OpCode::MakeObject(fields.len()));
             }
 
             _ => {
-                return Err(crate::core::error::InfraError::RuntimeError {
-                    message: format!("Expression type not yet supported in bytecode: {:?}", expr),
-                    line: None,
-                    column: None,
-                    stack_trace: vec![],
-                    source_code: None,
-                });
+                let kind = variant_name(expr);
+                return Err(self.unsupported(&format!("The '{}' expression", kind)));
             }
         }
 
         Ok(())
     }
+
+    /// Put what is emitted next on `expr`'s line. Statements in function
+    /// bodies have no line of their own, but most of their expressions do,
+    /// and an operator split over lines fails on the line it sits on rather
+    /// than its last operand's.
+    fn set_line(&mut self, expr: &Expr) {
+        if let Some(span) = expr.span() {
+            self.line = span.line;
+        }
+    }
+
+    /// Compile a function body where the VM will skip over it, returning
+    /// where its code starts. The arguments are the first local slots.
+    fn compile_function(&mut self, params: &[String], body: &Stmt) -> Result<usize, InfraError> {
+        let over = self.emit_jump(OpCode::Jump(0));
+        let entry = self.chunk.code.len();
+
+        let locals = std::mem::take(&mut self.locals);
+        let local_count = std::mem::replace(&mut self.local_count, 0);
+        self.enclosing.push((locals, local_count));
+        for param in params {
+            self.declare(param);
+        }
+
+        let result = self.compile_stmt(body);
+        // Falling off the end of the body returns null
+        self.emit_null();
+        self.emit(OpCode::Return);
+
+        let (locals, local_count) = self.enclosing.pop().expect("scope pushed above");
+        self.locals = locals;
+        self.local_count = local_count;
+        result?;
+
+        self.chunk.patch_jump(over);
+        Ok(entry)
+    }
+
+    /// Give `name` a new slot in the current scope
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.local_count;
+        self.locals.insert(name.to_string(), slot);
+        self.local_count += 1;
+        slot
+    }
+
+    fn emit_null(&mut self) {
+        let null = self.chunk.add_constant(Value::Null);
+        self.emit(OpCode::LoadConst(null));
+    }
+
+    /// Where the variable `name` lives: the current function's own
+    /// variables first, then the top level's
+    fn resolve(&self, name: &str) -> Result<Slot, InfraError> {
+        if let Some(&slot) = self.locals.get(name) {
+            return Ok(Slot::Local(slot));
+        }
+        if let Some((globals, _)) = self.enclosing.first() {
+            if let Some(&slot) = globals.get(name) {
+                return Ok(Slot::Global(slot));
+            }
+            if self.enclosing[1..]
+                .iter()
+                .any(|(locals, _)| locals.contains_key(name))
+            {
+                let what = format!("Using '{}' from an enclosing function", name);
+                return Err(self.unsupported(&what));
+            }
+        }
+
+        Err(self.placed(InfraError::UndefinedVariable {
+            name: name.to_string(),
+            line: None,
+            column: None,
+            suggestion: None,
+        }))
+    }
+
+    /// An error for code the compiler cannot translate; the program still
+    /// runs without --vm
+    fn unsupported(&self, what: &str) -> InfraError {
+        self.placed(InfraError::RuntimeError {
+            message: format!("{} is not supported in VM mode yet", what),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        })
+    }
+
+    /// Place `error` at the line being compiled, when it is known
+    fn placed(&self, error: InfraError) -> InfraError {
+        match self.line {
+            0 => error,
+            line => error.at(line, 0),
+        }
+    }
+}
+
+/// The variant of an AST node, such as "Function" for a `Stmt::Function`
+fn variant_name(node: &impl std::fmt::Debug) -> String {
+    format!("{:?}", node)
+        .chars()
+        .take_while(|c| c.is_alphanumeric())
+        .collect()
 }
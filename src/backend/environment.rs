@@ -1,9 +1,12 @@
-use crate::core::{InfraError, Result, Type, Value};
-use std::collections::HashMap;
+use crate::core::{Captures, InfraError, Result, Type, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Environment {
-    variables: HashMap<String, Value>,
+    // Each value sits in its own cell so functions defined here can share it
+    variables: HashMap<String, Rc<RefCell<Value>>>,
     types: HashMap<String, Option<Type>>, // Store type annotations and inferred types
     pub parent: Option<Box<Environment>>,
 }
@@ -26,13 +29,15 @@ impl Environment {
     }
 
     pub fn define(&mut self, name: String, value: Value) {
-        self.variables.insert(name.clone(), value);
+        self.variables
+            .insert(name.clone(), Rc::new(RefCell::new(value)));
         // Infer and store type for untyped variables
         self.types.entry(name).or_insert(None); // None means no explicit type annotation
     }
 
     pub fn define_with_type(&mut self, name: String, value: Value, type_annotation: Option<Type>) {
-        self.variables.insert(name.clone(), value);
+        self.variables
+            .insert(name.clone(), Rc::new(RefCell::new(value)));
         self.types.insert(name, type_annotation);
     }
 
@@ -47,8 +52,8 @@ impl Environment {
     }
 
     pub fn get(&self, name: &str) -> Result<Value> {
-        if let Some(value) = self.variables.get(name) {
-            Ok(value.clone())
+        if let Some(cell) = self.variables.get(name) {
+            Ok(cell.borrow().clone())
         } else if let Some(parent) = &self.parent {
             parent.get(name)
         } else {
@@ -63,8 +68,8 @@ impl Environment {
 
     #[allow(dead_code)]
     pub fn assign(&mut self, name: &str, value: Value) -> Result<()> {
-        if self.variables.contains_key(name) {
-            self.variables.insert(name.to_string(), value);
+        if let Some(cell) = self.variables.get(name) {
+            *cell.borrow_mut() = value;
             Ok(())
         } else if let Some(parent) = &mut self.parent {
             parent.assign(name, value)
@@ -85,14 +90,14 @@ impl Environment {
         value: Value,
         check_fn: &dyn Fn(&Value, Option<&Type>) -> Result<()>,
     ) -> Result<()> {
-        if self.variables.contains_key(name) {
+        if let Some(cell) = self.variables.get(name) {
             // Get stored type for type checking
             let stored_type = self.types.get(name).cloned();
 
             // Perform type checking if needed
             check_fn(&value, stored_type.as_ref().and_then(|t| t.as_ref()))?;
 
-            self.variables.insert(name.to_string(), value);
+            *cell.borrow_mut() = value;
             Ok(())
         } else if let Some(parent) = &mut self.parent {
             parent.assign_with_type_check(name, value, check_fn)
@@ -106,6 +111,101 @@ impl Environment {
         }
     }
 
+    /// Every variable name visible from here, each once. Only wanted when a
+    /// lookup has failed, so the scopes are walked in a loop rather than by
+    /// recursion.
+    pub fn names(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut names = Vec::new();
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            names.extend(
+                env.variables
+                    .keys()
+                    .filter(|name| seen.insert(name.as_str()))
+                    .cloned(),
+            );
+            scope = env.parent.as_deref();
+        }
+        names
+    }
+
+    /// Open a scope inside this one. What is defined from now on lives there
+    /// and is gone after `pop_scope`; outer variables stay visible and
+    /// assignable.
+    pub fn push_scope(&mut self) {
+        let outer = std::mem::take(self);
+        *self = Environment::with_parent(outer);
+    }
+
+    /// Close the innermost scope, dropping everything defined in it
+    pub fn pop_scope(&mut self) {
+        let scope = std::mem::take(self);
+        *self = *scope.parent.expect("a pushed scope has a parent");
+    }
+
+    /// Take the outermost scope out from under this one, leaving the inner
+    /// scopes without it. `None` if this is the outermost scope.
+    pub fn detach_outermost(&mut self) -> Option<Environment> {
+        let parent = self.parent.as_mut()?;
+        if parent.parent.is_none() {
+            return self.parent.take().map(|outermost| *outermost);
+        }
+        parent.detach_outermost()
+    }
+
+    /// Put back the scope `detach_outermost` took
+    pub fn attach_outermost(&mut self, outermost: Environment) {
+        match &mut self.parent {
+            Some(parent) => parent.attach_outermost(outermost),
+            None => self.parent = Some(Box::new(outermost)),
+        }
+    }
+
+    /// Forget `name` in this scope and return its value. A variable of the
+    /// same name in an outer scope is visible again afterwards.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.types.remove(name);
+        let cell = self.variables.remove(name)?;
+        let value = cell.borrow().clone();
+        Some(value)
+    }
+
+    /// The cell holding `name` in the innermost scope that declares it, for
+    /// changing part of its value in place
+    pub fn cell(&self, name: &str) -> Option<Rc<RefCell<Value>>> {
+        match self.variables.get(name) {
+            Some(cell) => Some(Rc::clone(cell)),
+            None => self.parent.as_ref()?.cell(name),
+        }
+    }
+
+    /// Bind `name` to a cell another scope holds too, as a call binds what
+    /// its function captured
+    pub fn define_shared(&mut self, name: String, cell: Rc<RefCell<Value>>) {
+        self.variables.insert(name.clone(), cell);
+        self.types.entry(name).or_insert(None);
+    }
+
+    /// The cells of every variable visible here except the outermost
+    /// scope's, for a function defined here to keep. Globals are left out:
+    /// a call's scope sits directly inside the outermost one.
+    pub fn capture(&self) -> Captures {
+        let mut cells: Vec<(String, Rc<RefCell<Value>>)> = Vec::new();
+        let mut scope = self;
+        while let Some(parent) = &scope.parent {
+            for (name, cell) in &scope.variables {
+                // An inner scope's binding hides an outer one of the same name
+                if !cells.iter().any(|(seen, _)| seen == name) {
+                    cells.push((name.clone(), Rc::clone(cell)));
+                }
+            }
+            scope = parent;
+        }
+        Captures::new(cells)
+    }
+
     #[allow(dead_code)]
     pub fn contains(&self, name: &str) -> bool {
         self.variables.contains_key(name) || self.parent.as_ref().is_some_and(|p| p.contains(name))
@@ -116,9 +216,20 @@ impl Environment {
         self.variables.clear();
     }
 
-    pub fn size(&self) -> usize {
-        let parent_size = self.parent.as_ref().map_or(0, |p| p.size());
-        self.variables.len() + parent_size
+    /// Every visible binding with its type annotation, sorted by name; an
+    /// inner scope's binding hides an outer one of the same name
+    pub fn bindings(&self) -> Vec<(String, Value, Option<Type>)> {
+        let mut bindings = self
+            .parent
+            .as_ref()
+            .map_or_else(Vec::new, |parent| parent.bindings());
+        bindings.retain(|(name, _, _)| !self.variables.contains_key(name));
+        for (name, cell) in &self.variables {
+            let annotation = self.types.get(name).cloned().flatten();
+            bindings.push((name.clone(), cell.borrow().clone(), annotation));
+        }
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
     }
 
     #[allow(dead_code)]
@@ -1,10 +1,63 @@
 use crate::backend::Environment;
-use crate::core::{ast::*, InfraError, Result, Value};
-use crate::stdlib::StandardLibrary;
+use crate::core::{
+    ast::*, Captures, ClassDef, EnumDef, InfraError, Instance, ObjectMap, Result, Value,
+};
+use crate::frontend::printer;
+use crate::stdlib::{
+    array, async_mod, cancel, capability, error, faults, io, memo, number, output, prelude, random,
+    record, schedule, timing, StandardLibrary,
+};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// Module functions implemented in the evaluator rather than the stdlib
+/// registry, because they call back into script code
+pub(crate) const EVALUATOR_FUNCTIONS: &[(&str, &str)] = &[
+    ("io", "with_temp_dir"),
+    ("array", "map"),
+    ("array", "filter"),
+    ("array", "reduce"),
+    ("array", "sort_by"),
+    ("faults", "inject"),
+    ("faults", "clear"),
+    ("faults", "active"),
+    ("memo", "wrap"),
+    ("memo", "clear"),
+    ("memo", "stats"),
+    ("memo", "__call"),
+    ("error", "new"),
+    ("async", "then"),
+    ("async", "catch"),
+    ("process", "with_timeout"),
+    ("schedule", "every"),
+    ("schedule", "cron"),
+    ("schedule", "cancel"),
+    ("schedule", "run_pending"),
+    ("timing", "debounce"),
+    ("timing", "throttle"),
+    ("timing", "cancel"),
+    ("timing", "__call"),
+    ("timing", "flush"),
+    ("number", "set_display_precision"),
+    ("random", "random"),
+    ("random", "int"),
+    ("random", "choice"),
+    ("random", "shuffle"),
+    ("random", "seed"),
+];
 
 pub struct Evaluator {
     environment: Environment,
     stdlib: StandardLibrary,
+    call_stack: Vec<(String, Option<usize>)>, // Functions executing, with the line each was called from
+    faults: faults::Faults,
+    jobs: schedule::Jobs,
+    wrappers: timing::Wrappers,
+    random: random::Random,
+    memo: memo::MemoTables,
+    /// Decimal places printed numbers are rounded to (None = shortest exact form)
+    display_precision: Option<usize>,
 }
 
 impl Evaluator {
@@ -12,391 +65,1603 @@ impl Evaluator {
         Self {
             environment: Environment::new(),
             stdlib: StandardLibrary::new(),
+            call_stack: Vec::new(),
+            faults: faults::Faults::default(),
+            jobs: schedule::Jobs::default(),
+            wrappers: timing::Wrappers::default(),
+            random: random::Random::default(),
+            memo: memo::MemoTables::default(),
+            display_precision: None,
         }
     }
 
     pub fn with_environment(environment: Environment) -> Self {
+        Self::with_stdlib(environment, StandardLibrary::new())
+    }
+
+    /// An evaluator calling native functions from `stdlib`, which may hold
+    /// functions its host registered
+    pub fn with_stdlib(environment: Environment, stdlib: StandardLibrary) -> Self {
         Self {
             environment,
-            stdlib: StandardLibrary::new(),
+            stdlib,
+            call_stack: Vec::new(),
+            faults: faults::Faults::default(),
+            jobs: schedule::Jobs::default(),
+            wrappers: timing::Wrappers::default(),
+            random: random::Random::default(),
+            memo: memo::MemoTables::default(),
+            display_precision: None,
+        }
+    }
+
+    pub fn stdlib(&self) -> &StandardLibrary {
+        &self.stdlib
+    }
+
+    /// Decimal places this evaluator rounds printed numbers to
+    pub fn display_precision(&self) -> Option<usize> {
+        self.display_precision
+    }
+
+    #[allow(dead_code)]
+    pub fn stdlib_mut(&mut self) -> &mut StandardLibrary {
+        &mut self.stdlib
+    }
+
+    /// A module the parser does not know, such as one only the host
+    /// registered, as an object of its functions: the parser reads `host.f`
+    /// as a property of a variable `host`
+    fn module_value(&self, name: &str) -> Option<Value> {
+        let functions = self.stdlib.get_module_functions(name)?;
+        Some(Value::Object(
+            functions
+                .into_iter()
+                .map(|function| {
+                    (
+                        function.to_string(),
+                        Value::NativeFunction {
+                            module: name.to_string(),
+                            function: function.to_string(),
+                        },
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    /// `name` is not defined; suggest the closest name the script could have
+    /// meant
+    fn undefined_variable(&self, name: &str) -> InfraError {
+        InfraError::UndefinedVariable {
+            name: name.to_string(),
+            line: None,
+            column: None,
+            suggestion: self.closest_visible_name(name),
+        }
+    }
+
+    /// The name closest to `name` in the script's scopes, the prelude, the
+    /// module names and the globals, preferring them in that order
+    fn closest_visible_name(&self, name: &str) -> Option<String> {
+        let mut candidates = self.environment.names();
+        if prelude::is_enabled() {
+            candidates.extend(prelude::exports().unwrap_or_default().into_keys());
+        }
+        candidates.extend(self.stdlib.get_modules().into_iter().map(str::to_string));
+        candidates.extend(self.stdlib.get_globals().into_iter().map(str::to_string));
+        closest_name(name, &candidates)
+    }
+
+    /// `name(...)` calls a function defined nowhere. A module function of
+    /// exactly that name is the likeliest meaning (`math.sqrt` for `sqrt`),
+    /// then the closest visible name, then the closest module function.
+    fn undefined_function(&self, name: &str) -> InfraError {
+        let mut modules = self.stdlib.get_modules();
+        modules.sort();
+        let qualified: Vec<(&str, String)> = modules
+            .into_iter()
+            .flat_map(|module| {
+                self.module_function_names(module)
+                    .into_iter()
+                    .map(move |function| (module, function))
+            })
+            .collect();
+        let in_module = |function: &str| {
+            let (module, function) = qualified.iter().find(|(_, f)| f == function)?;
+            Some(format!("{}.{}", module, function))
+        };
+
+        let suggestion = in_module(name)
+            .or_else(|| self.closest_visible_name(name))
+            .or_else(|| {
+                let functions: Vec<String> = qualified.iter().map(|(_, f)| f.clone()).collect();
+                in_module(&closest_name(name, &functions)?)
+            });
+        InfraError::UndefinedFunction {
+            name: name.to_string(),
+            line: None,
+            column: None,
+            suggestion,
+            available_functions: None,
+        }
+    }
+
+    /// `module.function` does not exist; suggest the module's function with
+    /// the closest name
+    fn unknown_module_function(&self, module: &str, function: &str) -> InfraError {
+        let functions = self.module_function_names(module);
+        InfraError::UndefinedFunction {
+            name: format!("{}.{}", module, function),
+            line: None,
+            column: None,
+            suggestion: closest_name(function, &functions)
+                .map(|similar| format!("{}.{}", module, similar)),
+            available_functions: None,
+        }
+    }
+
+    /// Every function `module` offers scripts, sorted
+    fn module_function_names(&self, module: &str) -> Vec<String> {
+        let mut functions: Vec<String> = self
+            .stdlib
+            .get_module_functions(module)
+            .unwrap_or_default()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        functions.extend(
+            EVALUATOR_FUNCTIONS
+                .iter()
+                .filter(|(m, f)| *m == module && !f.starts_with("__"))
+                .map(|(_, f)| f.to_string()),
+        );
+        functions.sort();
+        functions.dedup();
+        functions
+    }
+
+    fn is_module_function(&self, module: &str, function: &str) -> bool {
+        self.stdlib.get_function(module, function).is_some()
+            || EVALUATOR_FUNCTIONS.contains(&(module, function))
+    }
+
+    /// Whether `value.property(...)` calls a function of the value's
+    /// standard library module instead of a function the value holds. An
+    /// object's own keys come first, so dispatch tables keep working.
+    fn is_method_call(&self, value: &Value, property: &str) -> bool {
+        match value {
+            Value::String(_) | Value::Array(_) => true,
+            Value::Object(members) => {
+                !members.contains_key(property) && self.is_module_function("object", property)
+            }
+            _ => false,
+        }
+    }
+
+    /// Call `receiver.method(args)` as `module.method(receiver, args)`, where
+    /// the module is the string, array or object one to match the receiver
+    fn call_method(&mut self, receiver: Value, method: &str, args: &[Expr]) -> Result<Value> {
+        let module = match receiver {
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            _ => "object",
+        };
+        if method.starts_with("__") || !self.is_module_function(module, method) {
+            return Err(InfraError::PropertyNotFound {
+                property: method.to_string(),
+                object_type: Some(receiver.type_name().to_string()),
+                line: None,
+                available_properties: Some(self.module_function_names(module)),
+            });
+        }
+
+        let mut arg_values = vec![receiver];
+        for arg in args {
+            arg_values.push(self.evaluate_expression(arg)?);
         }
+        self.call_module_values(module, method, arg_values, None)
     }
 
+    /// The value of `expr`. An error without a position gets the position
+    /// of the innermost expression that has one.
     pub fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value> {
+        located(self.evaluate_unlocated(expr), expr.span())
+    }
+
+    fn evaluate_unlocated(&mut self, expr: &Expr) -> Result<Value> {
         match expr {
             Expr::Literal(value) => Ok(value.clone()),
-            Expr::Identifier(name) => self.environment.get(name),
+            Expr::Identifier { name, .. } => match self.environment.get(name) {
+                // The script's own names shadow the prelude's
+                Err(InfraError::UndefinedVariable { .. }) => match prelude::lookup(name)? {
+                    Some(value) => Ok(value),
+                    None => self
+                        .stdlib
+                        .get_global(name)
+                        .or_else(|| self.module_value(name))
+                        .ok_or_else(|| self.undefined_variable(name)),
+                },
+                found => found,
+            },
             Expr::Binary {
                 left,
                 operator,
                 right,
+                ..
             } => {
                 let left_val = self.evaluate_expression(left)?;
+                // `??` only looks at its right side when the left is null
+                if *operator == BinaryOp::Coalesce {
+                    return match left_val {
+                        Value::Null => self.evaluate_expression(right),
+                        found => Ok(found),
+                    };
+                }
                 let right_val = self.evaluate_expression(right)?;
                 self.apply_binary_operator(operator, &left_val, &right_val)
             }
-            Expr::Unary { operator, operand } => {
+            Expr::Unary {
+                operator, operand, ..
+            } => {
                 let operand_val = self.evaluate_expression(operand)?;
                 self.apply_unary_operator(operator, &operand_val)
             }
-            Expr::Call { callee, args } => {
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                if self.evaluate_expression(condition)?.is_truthy() {
+                    self.evaluate_expression(then_expr)
+                } else {
+                    self.evaluate_expression(else_expr)
+                }
+            }
+            Expr::Call { callee, args, span } => {
                 // Check if this is a module function call
                 if let Expr::ModuleAccess { module, function } = callee.as_ref() {
                     // Handle module function call
-                    return self.call_module_function(module, function, args);
+                    return self.call_module_function(module, function, args, span.map(|s| s.line));
                 }
 
-                let function = self.evaluate_expression(callee)?;
-
-                match function {
-                    Value::Function {
-                        name,
-                        params,
-                        param_types,
-                        return_type,
-                        body,
+                // Calls through an object key (dispatch tables) are named after
+                // the key, as in `handlers.start`
+                let (function, frame_name) = match callee.as_ref() {
+                    Expr::Property {
+                        object,
+                        property,
+                        optional,
+                        ..
+                    } => {
+                        let obj_value = self.evaluate_expression(object)?;
+                        // `a?.f()` is null, and its arguments unevaluated,
+                        // when `a` is null
+                        if *optional && obj_value == Value::Null {
+                            return Ok(Value::Null);
+                        }
+                        if let Value::Enum(definition) = &obj_value {
+                            let mut arg_values = Vec::new();
+                            for arg in args {
+                                arg_values.push(self.evaluate_expression(arg)?);
+                            }
+                            return enum_helper(definition, property, &arg_values);
+                        }
+                        if self.is_method_call(&obj_value, property) {
+                            return self.call_method(obj_value, property, args);
+                        }
+                        self.callable_member(object, obj_value, Value::String(property.clone()))?
+                    }
+                    Expr::Index {
+                        object,
+                        index,
+                        optional,
                         ..
                     } => {
-                        // Evaluate arguments
-                        let mut arg_values = Vec::new();
-                        for arg in args {
-                            arg_values.push(self.evaluate_expression(arg)?);
+                        let obj_value = self.evaluate_expression(object)?;
+                        if *optional && obj_value == Value::Null {
+                            return Ok(Value::Null);
+                        }
+                        let index_value = self.evaluate_expression(index)?;
+                        self.callable_member(object, obj_value, index_value)?
+                    }
+                    // Names not in scope fall back to the prelude and the
+                    // globals (see evaluate_unlocated's Identifier arm)
+                    Expr::Identifier { name, .. } => match self.evaluate_expression(callee) {
+                        Err(InfraError::UndefinedVariable { .. }) => {
+                            return Err(self.undefined_function(name))
                         }
+                        found => (found?, None),
+                    },
+                    _ => (self.evaluate_expression(callee)?, None),
+                };
 
-                        // Check argument count
-                        if arg_values.len() != params.len() {
-                            return Err(InfraError::ArgumentCountMismatch {
-                                expected: params.len(),
-                                found: arg_values.len(),
-                                function_name: Some(name.clone()),
-                                line: None,
-                            });
+                // Evaluate arguments
+                let mut arg_values = Vec::new();
+                for arg in args {
+                    arg_values.push(self.evaluate_expression(arg)?);
+                }
+
+                self.call_function_as(function, arg_values, frame_name, span.map(|s| s.line))
+            }
+            Expr::Interpolation(parts) => self.interpolate(parts),
+            Expr::Array(elements) => {
+                let mut array_values = Vec::new();
+                for element in elements {
+                    array_values.push(self.evaluate_expression(element)?);
+                }
+                Ok(Value::Array(array_values))
+            }
+            Expr::Tuple(elements) => {
+                let mut items = Vec::with_capacity(elements.len());
+                for element in elements {
+                    items.push(self.evaluate_expression(element)?);
+                }
+                Ok(Value::Tuple(items.into()))
+            }
+            Expr::Index {
+                object,
+                index,
+                optional,
+                ..
+            } => {
+                let obj_value = self.evaluate_expression(object)?;
+                if *optional && obj_value == Value::Null {
+                    return Ok(Value::Null);
+                }
+                let index_value = self.evaluate_expression(index)?;
+                self.index_value(obj_value, index_value)
+            }
+            Expr::Object(properties) => {
+                // Keys keep their source order (see ObjectMap)
+                let mut object = ObjectMap::new();
+                for (key, value_expr) in properties {
+                    let value = self.evaluate_expression(value_expr)?;
+                    object.insert(key.clone(), value);
+                }
+                Ok(Value::Object(object))
+            }
+            Expr::Property {
+                object,
+                property,
+                optional,
+                ..
+            } => {
+                let obj_value = self.evaluate_expression(object)?;
+                if *optional && obj_value == Value::Null {
+                    return Ok(Value::Null);
+                }
+                self.property_value(obj_value, property)
+            }
+            Expr::ModuleAccess { module, function } => {
+                // A module function used as a value, e.g. stored in a dispatch table
+                if self.stdlib.get_function(module, function).is_some()
+                    || EVALUATOR_FUNCTIONS.contains(&(module.as_str(), function.as_str()))
+                {
+                    Ok(Value::NativeFunction {
+                        module: module.clone(),
+                        function: function.clone(),
+                    })
+                } else {
+                    Err(self.unknown_module_function(module, function))
+                }
+            }
+            Expr::Await { expression } => {
+                let promise = self.evaluate_expression(expression)?;
+                awaited(promise)
+            }
+            Expr::This => self
+                .environment
+                .get("this")
+                .map_err(|_| this_outside_method()),
+            Expr::Super { method } => self.super_method(method),
+            Expr::Lambda {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+            } => Ok(Value::Function {
+                name: name.clone(),
+                params: params.clone(),
+                param_types: param_types.clone(),
+                optional_params: Rc::clone(optional_params),
+                return_type: return_type.clone(),
+                body: Rc::new(Stmt::Return(Some((**body).clone()))),
+                captured: self.environment.capture(),
+                is_async: false,
+            }),
+            Expr::New { class, args } => {
+                let class_value = self.evaluate_expression(class)?;
+                let Value::Class(class) = class_value else {
+                    return Err(InfraError::TypeError {
+                        expected: "class".to_string(),
+                        found: class_value.type_name().to_string(),
+                        context: Some("new expression".to_string()),
+                        line: None,
+                        column: None,
+                        hint: None,
+                    });
+                };
+                let mut arg_values = Vec::new();
+                for arg in args {
+                    arg_values.push(self.evaluate_expression(arg)?);
+                }
+                self.construct(&class, arg_values)
+            }
+        }
+    }
+
+    /// Bind the names of `pattern` to the matching parts of `value`, which
+    /// must be a tuple of exactly the pattern's shape. `keyword` is the
+    /// statement destructuring, `let` or `for`, for the error.
+    pub fn destructure(&mut self, keyword: &str, pattern: &Pattern, value: Value) -> Result<()> {
+        match pattern {
+            Pattern::Name(name) => {
+                let value_type = self.infer_value_type(&value);
+                self.define_variable_with_type(name.clone(), value, Some(value_type));
+                Ok(())
+            }
+            Pattern::Tuple(parts) => {
+                let items = match &value {
+                    Value::Tuple(items) if items.len() == parts.len() => items.clone(),
+                    other => {
+                        let found = match other {
+                            Value::Tuple(items) => format!("tuple of {} values", items.len()),
+                            other => other.type_name().to_string(),
+                        };
+                        return Err(InfraError::TypeError {
+                            expected: format!("tuple of {} values", parts.len()),
+                            found,
+                            context: Some(format!(
+                                "destructuring {} {}",
+                                keyword,
+                                printer::format_pattern(pattern)
+                            )),
+                            line: None,
+                            column: None,
+                            hint: Some(
+                                "The pattern needs one name per tuple element; nest parentheses for nested tuples"
+                                    .to_string(),
+                            ),
+                        });
+                    }
+                };
+                for (part, item) in parts.iter().zip(items.iter()) {
+                    self.destructure(keyword, part, item.clone())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Bind a `for` loop's name to `item`, or take `item` apart for a tuple
+    /// pattern
+    pub fn bind_loop_item(&mut self, pattern: &Pattern, item: Value) -> Result<()> {
+        match pattern {
+            Pattern::Name(var) => {
+                self.environment.define(var.clone(), item);
+                Ok(())
+            }
+            Pattern::Tuple(_) => self.destructure("for", pattern, item),
+        }
+    }
+
+    /// The text of an interpolated string, each expression written as
+    /// `print` would write it
+    fn interpolate(&mut self, parts: &[StringPart]) -> Result<Value> {
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                StringPart::Text(piece) => text.push_str(piece),
+                StringPart::Expr(expr) => {
+                    let value = self.evaluate_expression(expr)?;
+                    text.push_str(&value.display(self.display_precision).to_string())
+                }
+            }
+        }
+        Ok(Value::String(text))
+    }
+
+    /// Call a function value with already-evaluated arguments
+    pub fn call_function(&mut self, function: Value, arg_values: Vec<Value>) -> Result<Value> {
+        self.call_function_as(function, arg_values, None, None)
+    }
+
+    /// Apply a function's decorators to its value, the one nearest the
+    /// function first. `@name` calls `name(function)`; `@name(args)` calls
+    /// `name(args)` for the decorator, then the decorator with the function.
+    pub fn apply_decorators(&mut self, function: Value, decorators: &[Expr]) -> Result<Value> {
+        let mut value = function;
+        for decorator in decorators.iter().rev() {
+            let callee = match decorator {
+                Expr::Call { .. } => {
+                    let made = self.evaluate_expression(decorator)?;
+                    Expr::Literal(decorator_function(
+                        made,
+                        format!("decorator made by @{}", printer::format_expr(decorator)),
+                        "`@name(args)` calls name(args) and decorates with the function it returns",
+                    )?)
+                }
+                other => other.clone(),
+            };
+            let call = Expr::Call {
+                callee: Box::new(callee),
+                args: vec![Expr::Literal(value)],
+                span: decorator.span(),
+            };
+            value = decorator_function(
+                self.evaluate_expression(&call)?,
+                format!("result of decorator @{}", printer::format_expr(decorator)),
+                "A decorator must return the function to bind in place of the one it decorates",
+            )?;
+        }
+        Ok(value)
+    }
+
+    /// Call a function, naming its call-stack frame `frame_name` instead of
+    /// the function's own name when given. An error leaving the call takes
+    /// the stack as it was where the error happened.
+    fn call_function_as(
+        &mut self,
+        function: Value,
+        arg_values: Vec<Value>,
+        frame_name: Option<String>,
+        line: Option<usize>,
+    ) -> Result<Value> {
+        let frame_name = match (&function, frame_name) {
+            (Value::Function { .. } | Value::NativeFunction { .. }, Some(frame_name)) => frame_name,
+            (Value::Function { name, .. }, None) => name.clone(),
+            (Value::NativeFunction { module, function }, None) => {
+                format!("{}.{}", module, function)
+            }
+            _ => return self.call_function_value(function, arg_values),
+        };
+
+        self.call_stack.push((frame_name, line));
+        let result = self
+            .call_function_value(function, arg_values)
+            .map_err(|error| self.with_stack_trace(error));
+        self.call_stack.pop();
+        result
+    }
+
+    /// Give an error that has no stack trace yet the current one,
+    /// innermost call first
+    #[cold]
+    fn with_stack_trace(&self, error: InfraError) -> InfraError {
+        if !error.stack_trace().is_empty() || !error.is_catchable() {
+            return error;
+        }
+        error.traced(render_call_stack(&self.call_stack))
+    }
+
+    /// Look up `key` on `obj_value` for a call. A missing key on an object is
+    /// reported as an undefined function listing the keys that do exist.
+    fn callable_member(
+        &self,
+        object: &Expr,
+        obj_value: Value,
+        key: Value,
+    ) -> Result<(Value, Option<String>)> {
+        let (Value::Object(members), Value::String(key)) = (&obj_value, &key) else {
+            let value = match key {
+                Value::String(property) => self.property_value(obj_value, &property)?,
+                index => self.index_value(obj_value, index)?,
+            };
+            return Ok((value, None));
+        };
+
+        let name = format!("{}.{}", printer::format_expr(object), key);
+        match members.get(key) {
+            Some(value) => Ok((value.clone(), Some(name))),
+            None => {
+                let mut keys: Vec<String> = members.keys().cloned().collect();
+                keys.sort();
+                Err(InfraError::UndefinedFunction {
+                    name,
+                    line: None,
+                    column: None,
+                    suggestion: closest_name(key, &keys),
+                    available_functions: Some(keys),
+                })
+            }
+        }
+    }
+
+    fn property_value(&self, obj_value: Value, property: &str) -> Result<Value> {
+        match obj_value {
+            Value::Enum(definition) => {
+                definition
+                    .member(property)
+                    .ok_or_else(|| InfraError::PropertyNotFound {
+                        property: property.to_string(),
+                        object_type: Some(format!("enum {}", definition.name)),
+                        line: None,
+                        available_properties: Some(definition.members.clone()),
+                    })
+            }
+            Value::Object(obj) => match obj.get(property) {
+                Some(value) => Ok(value.clone()),
+                None => Err(InfraError::PropertyNotFound {
+                    property: property.to_string(),
+                    object_type: Some("object".to_string()),
+                    line: None,
+                    available_properties: Some(obj.keys().cloned().collect()),
+                }),
+            },
+            // A field, else a method bound to the instance
+            Value::Instance(instance) => {
+                if let Some(value) = instance.fields.borrow().get(property) {
+                    return Ok(value.clone());
+                }
+                if let Some((class, method)) = instance.class.method(property) {
+                    return Ok(bound_method(&instance, class, method));
+                }
+                let mut available: Vec<String> = instance.fields.borrow().keys().cloned().collect();
+                for class in instance.class.lineage() {
+                    for method in &class.methods {
+                        if !available.contains(&method.name) {
+                            available.push(method.name.clone());
                         }
+                    }
+                }
+                Err(InfraError::PropertyNotFound {
+                    property: property.to_string(),
+                    object_type: Some(format!("{} instance", instance.class.name)),
+                    line: None,
+                    available_properties: Some(available),
+                })
+            }
+            // An error's type, message, line and stack, then its own fields
+            Value::Error {
+                error_type,
+                message,
+                fields,
+                line,
+                stack,
+            } => match property {
+                "type" => Ok(Value::String(error_type)),
+                "message" => Ok(Value::String(message)),
+                "line" => Ok(line.map_or(Value::Null, |line| Value::Number(line as f64))),
+                "stack" => Ok(Value::Array(stack.into_iter().map(Value::String).collect())),
+                _ => fields
+                    .get(property)
+                    .cloned()
+                    .ok_or_else(|| InfraError::PropertyNotFound {
+                        property: property.to_string(),
+                        object_type: Some(format!("error {}", error_type)),
+                        line: None,
+                        available_properties: Some(
+                            ["type", "message", "line", "stack"]
+                                .into_iter()
+                                .map(str::to_string)
+                                .chain(fields.keys().cloned())
+                                .collect(),
+                        ),
+                    }),
+            },
+            _ => Err(InfraError::TypeError {
+                expected: "object".to_string(),
+                found: obj_value.type_name().to_string(),
+                context: Some("property access".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            }),
+        }
+    }
+
+    fn index_value(&self, obj_value: Value, index_value: Value) -> Result<Value> {
+        match (&obj_value, &index_value) {
+            (Value::Object(_) | Value::Error { .. } | Value::Instance(_), Value::String(key)) => {
+                self.property_value(obj_value, key)
+            }
+            (Value::Array(arr), Value::Number(idx)) => {
+                let index = *idx as usize;
+                if index >= arr.len() {
+                    Err(InfraError::IndexOutOfBounds {
+                        index,
+                        length: arr.len(),
+                        array_name: None,
+                        line: None,
+                    })
+                } else {
+                    Ok(arr[index].clone())
+                }
+            }
+            (Value::Tuple(items), Value::Number(idx)) => {
+                let index = *idx as usize;
+                items
+                    .get(index)
+                    .cloned()
+                    .ok_or(InfraError::IndexOutOfBounds {
+                        index,
+                        length: items.len(),
+                        array_name: None,
+                        line: None,
+                    })
+            }
+            (Value::Bytes(bytes), Value::Number(idx)) => {
+                let index = *idx as usize;
+                match bytes.get(index) {
+                    Some(&byte) => Ok(Value::Number(byte as f64)),
+                    None => Err(InfraError::IndexOutOfBounds {
+                        index,
+                        length: bytes.len(),
+                        array_name: None,
+                        line: None,
+                    }),
+                }
+            }
+            (Value::Array(_) | Value::Tuple(_) | Value::Bytes(_), _) => {
+                Err(InfraError::TypeError {
+                    expected: "number".to_string(),
+                    found: index_value.type_name().to_string(),
+                    context: Some("array indexing".to_string()),
+                    line: None,
+                    column: None,
+                    hint: None,
+                })
+            }
+            _ => Err(InfraError::TypeError {
+                expected: "array".to_string(),
+                found: obj_value.type_name().to_string(),
+                context: Some("array indexing".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            }),
+        }
+    }
+
+    fn call_function_value(&mut self, function: Value, arg_values: Vec<Value>) -> Result<Value> {
+        match &function {
+            Value::Function {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                captured,
+                is_async,
+            } => {
+                // Check argument count: parameters with defaults may be left
+                // out, and a rest parameter takes any number
+                if !optional_params.accepts(params.len(), arg_values.len()) {
+                    let required = optional_params.required(params.len());
+                    return Err(InfraError::ArgumentCountMismatch {
+                        expected: if arg_values.len() < required {
+                            required
+                        } else {
+                            params.len()
+                        },
+                        found: arg_values.len(),
+                        function_name: Some(name.clone()),
+                        line: None,
+                    });
+                }
 
-                        // Check parameter types with enhanced error messages
-                        for (i, (param_type, arg_value)) in
-                            param_types.iter().zip(arg_values.iter()).enumerate()
-                        {
-                            if let Some(expected_type) = param_type {
-                                if !self.check_type_compatibility(arg_value, expected_type) {
-                                    return Err(InfraError::TypeError {
+                let name = name.clone();
+                let return_type = return_type.clone();
+                let body = Rc::clone(body);
+                let is_async = *is_async;
+
+                // The body sees the globals and what the function closed
+                // over, never the scopes of whoever calls it
+                let result = self.in_function_scope(|evaluator| {
+                    for (captured_name, cell) in captured.iter() {
+                        evaluator
+                            .environment
+                            .define_shared(captured_name.clone(), Rc::clone(cell));
+                    }
+                    evaluator.bind_arguments(
+                        &name,
+                        params,
+                        param_types,
+                        optional_params,
+                        arg_values,
+                    )?;
+
+                    // Bind the function itself for recursion
+                    evaluator.environment.define(name.clone(), function.clone());
+
+                    match evaluator.execute_function_body(&body) {
+                        Ok(()) => Ok(Value::Null), // Function completed without return
+                        Err(InfraError::ReturnValue(Some(value))) => {
+                            // Check return type with enhanced error message
+                            match &return_type {
+                                Some(expected_return_type)
+                                    if !evaluator
+                                        .check_type_compatibility(&value, expected_return_type) =>
+                                {
+                                    Err(InfraError::TypeError {
                                         expected: format!(
-                                            "parameter '{}' to be of type {}",
-                                            params[i],
-                                            self.type_to_string(expected_type)
+                                            "function '{}' to return type {}",
+                                            name,
+                                            evaluator.type_to_string(expected_return_type)
                                         ),
-                                        found: format!("{} ({})", arg_value.type_name(), arg_value),
-                                        context: Some(format!("function call to '{}'", name)),
+                                        found: format!("{} ({})", value.type_name(), value),
+                                        context: Some(format!(
+                                            "function '{}' return statement",
+                                            name
+                                        )),
                                         line: None,
                                         column: None,
                                         hint: None,
-                                    });
+                                    })
                                 }
+                                _ => Ok(value),
                             }
                         }
+                        Err(InfraError::ReturnValue(None)) => Ok(Value::Null),
+                        Err(e) => Err(e.outside_loop(None)),
+                    }
+                });
+
+                if !is_async {
+                    return result;
+                }
+                // An async function settles its promise with whatever the body
+                // did; a promise it returns is passed on as it is
+                match result {
+                    Ok(promise @ Value::Promise { .. }) => Ok(promise),
+                    Ok(value) => Ok(async_mod::resolved_promise(value, "async function")),
+                    Err(error) if error.is_catchable() => Ok(async_mod::rejected_promise(
+                        error.to_string(),
+                        "async function",
+                    )),
+                    Err(error) => Err(error),
+                }
+            }
+            // call_function_as put the call, and the line it is on, on the stack
+            Value::NativeFunction { module, function } => {
+                let line = self.call_stack.last().and_then(|(_, line)| *line);
+                self.call_module_values(module, function, arg_values, line)
+            }
+            // Calling a class constructs an instance of it
+            Value::Class(class) => self.construct(class, arg_values),
+            _ => Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: function.type_name().to_string(),
+                context: Some("function call".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            }),
+        }
+    }
 
-                        // Create new environment for function
-                        let old_env = self.environment.clone();
-                        let mut function_env = Environment::with_parent(old_env.clone());
+    /// Run `body` in a new scope directly inside the outermost one, with
+    /// the caller's other scopes set aside until it returns. This is what
+    /// makes scoping lexical: a function reaches the globals and what it
+    /// captured, but not the locals of the function that calls it.
+    fn in_function_scope<T>(&mut self, body: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let caller = match self.environment.detach_outermost() {
+            Some(outermost) => Some(std::mem::replace(
+                &mut self.environment,
+                Environment::with_parent(outermost),
+            )),
+            None => {
+                self.environment.push_scope();
+                None
+            }
+        };
+        let result = body(self);
+        self.environment.pop_scope();
+        if let Some(mut caller) = caller {
+            caller.attach_outermost(std::mem::take(&mut self.environment));
+            self.environment = caller;
+        }
+        result
+    }
 
-                        // Bind parameters
-                        for (param, arg_value) in params.iter().zip(arg_values.iter()) {
-                            function_env.define(param.clone(), arg_value.clone());
-                        }
+    /// Call a function from a standard library module, from `line`
+    fn call_module_function(
+        &mut self,
+        module: &str,
+        function: &str,
+        args: &[Expr],
+        line: Option<usize>,
+    ) -> Result<Value> {
+        // Evaluate arguments
+        let mut arg_values = Vec::new();
+        for arg in args {
+            arg_values.push(self.evaluate_expression(arg)?);
+        }
 
-                        // Bind the function itself for recursion
-                        let recursive_func = Value::Function {
-                            name: name.clone(),
-                            params: params.clone(),
-                            param_types: param_types.clone(),
-                            return_type: return_type.clone(),
-                            body: body.clone(),
-                        };
-                        function_env.define(name.clone(), recursive_func);
-
-                        // Execute function body with new environment
-                        let old_evaluator_env =
-                            std::mem::replace(&mut self.environment, function_env);
-
-                        let result = match self.execute_function_body(&body) {
-                            Ok(()) => Ok(Value::Null), // Function completed without return
-                            Err(InfraError::ReturnValue(Some(value))) => {
-                                // Check return type with enhanced error message
-                                if let Some(expected_return_type) = return_type {
-                                    if !self.check_type_compatibility(&value, &expected_return_type)
-                                    {
-                                        return Err(InfraError::TypeError {
-                                            expected: format!(
-                                                "function '{}' to return type {}",
-                                                name,
-                                                self.type_to_string(&expected_return_type)
-                                            ),
-                                            found: format!("{} ({})", value.type_name(), value),
-                                            context: Some(format!(
-                                                "function '{}' return statement",
-                                                name
-                                            )),
-                                            line: None,
-                                            column: None,
-                                            hint: None,
-                                        });
-                                    }
-                                }
-                                Ok(value)
-                            }
-                            Err(InfraError::ReturnValue(None)) => Ok(Value::Null),
-                            Err(e) => Err(e),
-                        };
+        self.call_module_values(module, function, arg_values, line)
+    }
+
+    fn call_module_values(
+        &mut self,
+        module: &str,
+        function: &str,
+        arg_values: Vec<Value>,
+        line: Option<usize>,
+    ) -> Result<Value> {
+        // Functions that need to call back into the interpreter (EVALUATOR_FUNCTIONS)
+        if module == "io" && function == "with_temp_dir" {
+            return self.call_with_temp_dir(arg_values);
+        }
+        if module == "array" && function == "map" {
+            return self.map_array(arg_values);
+        }
+        if module == "array" && function == "filter" {
+            return self.filter_array(arg_values);
+        }
+        if module == "array" && function == "reduce" {
+            return self.reduce_array(arg_values);
+        }
+        if module == "array" && function == "sort_by" {
+            return self.sort_by(arg_values);
+        }
+        if module == "faults" {
+            return self.call_faults(function, &arg_values);
+        }
+        if module == "memo" {
+            return self.call_memo(function, &arg_values);
+        }
+        if module == "error" && function == "new" {
+            return self.create_error_value(&arg_values, line);
+        }
+        if module == "async" && (function == "then" || function == "catch") {
+            return self.call_promise_handler(function, arg_values);
+        }
+        if module == "process" && function == "with_timeout" {
+            return self.call_with_timeout(arg_values);
+        }
+        if module == "schedule" && function != "now" {
+            return self.call_schedule(function, &arg_values);
+        }
+        if module == "timing" {
+            return self.call_timing(function, &arg_values);
+        }
+        if module == "random" {
+            return self.call_random(function, &arg_values);
+        }
+        if module == "number" && function == "set_display_precision" {
+            self.display_precision = number::display_precision(&arg_values)?;
+            return Ok(Value::Null);
+        }
+
+        // An injected fault fails the call before anything is attempted
+        if let Some(error) = self.injected_fault(module, function, &arg_values)? {
+            return Err(error);
+        }
+
+        // A replayed session answers from its recording instead of the world
+        if let Some(result) = record::replay(module, function, &arg_values) {
+            return result;
+        }
+
+        // Mutating operations are only logged in dry-run mode
+        if self.stdlib.is_dry_run() {
+            if let Some(value) = capability::intercept(module, function, &arg_values) {
+                return Ok(value);
+            }
+        }
+
+        // Get the native function from stdlib
+        if let Some(native_func) = self.stdlib.get_function(module, function) {
+            let result = native_func(&arg_values);
+            record::record(module, function, &arg_values, &result);
+            result
+        } else {
+            Err(self.unknown_module_function(module, function))
+        }
+    }
+
+    /// io.with_temp_dir(fn): call `fn` with a fresh directory and remove it afterwards,
+    /// even when the callback fails
+    fn call_with_temp_dir(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if arg_values.len() != 1 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: arg_values.len(),
+                function_name: Some("io.with_temp_dir".to_string()),
+                line: None,
+            });
+        }
 
-                        // Restore environment
-                        self.environment = old_evaluator_env;
+        let callback = arg_values.into_iter().next().unwrap();
+        if !matches!(callback, Value::Function { .. }) {
+            return Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: callback.type_name().to_string(),
+                context: Some("io.with_temp_dir() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        }
+
+        let dir = io::create_temp_dir("infra-", false)?;
+        let result = self.call_function(callback, vec![Value::String(dir.clone())]);
+        io::release_temp_path(&dir);
+        result
+    }
+
+    /// faults.inject, faults.clear and faults.active, on this evaluator's faults
+    fn call_faults(&mut self, function: &str, arg_values: &[Value]) -> Result<Value> {
+        match function {
+            "inject" => {
+                let stdlib = &self.stdlib;
+                self.faults.inject(arg_values, |module, function| {
+                    stdlib.get_function(module, function).is_some()
+                })?;
+                Ok(Value::Null)
+            }
+            "clear" => self.faults.clear(arg_values),
+            _ if !arg_values.is_empty() => Err(InfraError::ArgumentCountMismatch {
+                expected: 0,
+                found: arg_values.len(),
+                function_name: Some("faults.active".to_string()),
+                line: None,
+            }),
+            _ => Ok(self.faults.active()),
+        }
+    }
+
+    /// schedule.every, cron, cancel and run_pending, on this evaluator's jobs
+    fn call_schedule(&mut self, function: &str, arg_values: &[Value]) -> Result<Value> {
+        match function {
+            "every" => self.jobs.every(arg_values),
+            "cron" => self.jobs.cron(arg_values),
+            "cancel" => self.jobs.cancel(arg_values),
+            _ if !arg_values.is_empty() => Err(InfraError::ArgumentCountMismatch {
+                expected: 0,
+                found: arg_values.len(),
+                function_name: Some("schedule.run_pending".to_string()),
+                line: None,
+            }),
+            _ => self.run_pending_schedules(),
+        }
+    }
+
+    /// The random functions, on this evaluator's generator
+    fn call_random(&mut self, function: &str, arg_values: &[Value]) -> Result<Value> {
+        match function {
+            "random" => self.random.random(arg_values),
+            "int" => self.random.int(arg_values),
+            "choice" => self.random.choice(arg_values),
+            "shuffle" => self.random.shuffle(arg_values),
+            _ => self.random.seed(arg_values),
+        }
+    }
 
-                        result
-                    }
-                    _ => Err(InfraError::TypeError {
-                        expected: "function".to_string(),
-                        found: function.type_name().to_string(),
-                        context: Some("function call".to_string()),
-                        line: None,
-                        column: None,
-                        hint: None,
-                    }),
-                }
+    /// timing.debounce, throttle, cancel and flush, and calls through their
+    /// wrappers, on this evaluator's wrappers
+    fn call_timing(&mut self, function: &str, arg_values: &[Value]) -> Result<Value> {
+        match function {
+            // `timing.throttle(100)` is the decorator `@timing.throttle(100)`
+            "debounce" | "throttle"
+                if matches!(
+                    arg_values,
+                    [Value::Number(_)] | [Value::Number(_), Value::Object(_)]
+                ) =>
+            {
+                Ok(decorator("timing", function, arg_values))
             }
-            Expr::Array(elements) => {
-                let mut array_values = Vec::new();
-                for element in elements {
-                    array_values.push(self.evaluate_expression(element)?);
+            "debounce" => self.wrappers.debounce(arg_values),
+            "throttle" => self.wrappers.throttle(arg_values),
+            "cancel" => self.wrappers.cancel(arg_values),
+            "flush" => match self.wrappers.take_flush(arg_values)? {
+                Some((function, args)) => {
+                    let args = forwarded(&function, args);
+                    self.call_function(function, args)
                 }
-                Ok(Value::Array(array_values))
+                None => Ok(Value::Null),
+            },
+            _ => {
+                let invocations = self.wrappers.call(arg_values, schedule::now_ms())?;
+                self.invoke_all(invocations)?;
+                Ok(Value::Null)
             }
-            Expr::Index { object, index } => {
-                let obj_value = self.evaluate_expression(object)?;
-                let index_value = self.evaluate_expression(index)?;
+        }
+    }
 
-                match (&obj_value, &index_value) {
-                    (Value::Array(arr), Value::Number(idx)) => {
-                        let index = *idx as usize;
-                        if index >= arr.len() {
-                            Err(InfraError::IndexOutOfBounds {
-                                index,
-                                length: arr.len(),
-                                array_name: None,
-                                line: None,
-                            })
-                        } else {
-                            Ok(arr[index].clone())
-                        }
-                    }
-                    (Value::Array(_), _) => Err(InfraError::TypeError {
-                        expected: "number".to_string(),
-                        found: index_value.type_name().to_string(),
-                        context: Some("array indexing".to_string()),
-                        line: None,
-                        column: None,
-                        hint: None,
-                    }),
-                    _ => Err(InfraError::TypeError {
-                        expected: "array".to_string(),
-                        found: obj_value.type_name().to_string(),
-                        context: Some("array indexing".to_string()),
-                        line: None,
-                        column: None,
-                        hint: None,
-                    }),
+    /// The error an injected fault fails this call with, if any. A fault with
+    /// a `when` predicate only counts calls the predicate accepts.
+    fn injected_fault(
+        &mut self,
+        module: &str,
+        function: &str,
+        arg_values: &[Value],
+    ) -> Result<Option<InfraError>> {
+        if self.faults.is_empty() {
+            return Ok(None);
+        }
+        for (index, when) in self.faults.candidates(module, function) {
+            if let Some(predicate) = when {
+                if !self
+                    .call_function(predicate, arg_values.to_vec())?
+                    .is_truthy()
+                {
+                    continue;
                 }
             }
-            Expr::Object(properties) => {
-                let mut object = std::collections::HashMap::new();
-                for (key, value_expr) in properties {
-                    let value = self.evaluate_expression(value_expr)?;
-                    object.insert(key.clone(), value);
-                }
-                Ok(Value::Object(object))
+            if let Some(error) = self.faults.trip(index, arg_values) {
+                return Ok(Some(error));
             }
-            Expr::Property { object, property } => {
-                let obj_value = self.evaluate_expression(object)?;
+        }
+        Ok(None)
+    }
 
-                match obj_value {
-                    Value::Object(obj) => match obj.get(property) {
-                        Some(value) => Ok(value.clone()),
-                        None => Err(InfraError::PropertyNotFound {
-                            property: property.clone(),
-                            object_type: Some("object".to_string()),
-                            line: None,
-                            available_properties: Some(obj.keys().cloned().collect()),
-                        }),
-                    },
-                    _ => Err(InfraError::TypeError {
-                        expected: "object".to_string(),
-                        found: obj_value.type_name().to_string(),
-                        context: Some("property access".to_string()),
-                        line: None,
-                        column: None,
-                        hint: None,
-                    }),
-                }
+    /// array.map(items, transform): call `transform` on each item in order
+    /// and collect the results
+    fn map_array(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if arg_values.len() != 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: arg_values.len(),
+                function_name: Some("array.map".to_string()),
+                line: None,
+            });
+        }
+
+        let mut args = arg_values.into_iter();
+        let (items, transform) = (args.next().unwrap(), args.next().unwrap());
+        let Value::Array(items) = items else {
+            return Err(InfraError::TypeError {
+                expected: "array".to_string(),
+                found: items.type_name().to_string(),
+                context: Some("array.map() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+        if !matches!(
+            transform,
+            Value::Function { .. } | Value::NativeFunction { .. }
+        ) {
+            return Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: transform.type_name().to_string(),
+                context: Some("array.map() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Pass a function taking one item, such as string.upper".to_string()),
+            });
+        }
+
+        items
+            .into_iter()
+            .map(|item| self.call_function(transform.clone(), vec![item]))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array)
+    }
+
+    /// array.filter(items, predicate): the items `predicate` returns true
+    /// for, in order. Given a number instead of a predicate, it keeps the
+    /// numbers greater than it.
+    fn filter_array(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if arg_values.len() != 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: arg_values.len(),
+                function_name: Some("array.filter".to_string()),
+                line: None,
+            });
+        }
+        let Value::Array(items) = &arg_values[0] else {
+            return Err(InfraError::TypeError {
+                expected: "array".to_string(),
+                found: arg_values[0].type_name().to_string(),
+                context: Some("array.filter() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+        let predicate = match &arg_values[1] {
+            Value::Number(threshold) => {
+                let above = items
+                    .iter()
+                    .filter(|item| matches!(item, Value::Number(n) if n > threshold))
+                    .cloned()
+                    .collect();
+                return Ok(Value::Array(above));
             }
-            Expr::ModuleAccess { module, function } => {
-                // Module access should not be evaluated directly - it should only be used in function calls
-                Err(InfraError::RuntimeError {
-                    message: format!(
-                        "Cannot access {}.{} directly - use as function call",
-                        module, function
-                    ),
+            predicate @ (Value::Function { .. } | Value::NativeFunction { .. }) => {
+                predicate.clone()
+            }
+            other => {
+                return Err(InfraError::TypeError {
+                    expected: "function or number".to_string(),
+                    found: other.type_name().to_string(),
+                    context: Some("array.filter() predicate".to_string()),
                     line: None,
                     column: None,
-                    stack_trace: vec![],
-                    source_code: None,
+                    hint: None,
                 })
             }
-            Expr::Await { expression } => {
-                let promise = self.evaluate_expression(expression)?;
-                match promise {
-                    Value::Promise {
-                        resolved, value, ..
-                    } => {
-                        if resolved {
-                            // Promise is resolved, return the value
-                            value
-                                .map(|boxed| *boxed)
-                                .ok_or_else(|| InfraError::RuntimeError {
-                                    message: "Promise resolved but has no value".to_string(),
-                                    line: None,
-                                    column: None,
-                                    stack_trace: vec![],
-                                    source_code: None,
-                                })
-                        } else {
-                            // Promise is not resolved yet - for now, return an error
-                            // In a full implementation, this would suspend execution
-                            Err(InfraError::RuntimeError {
-                                message: "Cannot await unresolved promise - not yet implemented"
-                                    .to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
-                    }
-                    _ => Err(InfraError::TypeError {
-                        expected: "promise".to_string(),
-                        found: promise.type_name().to_string(),
-                        context: Some("await expression".to_string()),
+        };
+
+        let mut kept = Vec::new();
+        for item in items {
+            match self.call_function(predicate.clone(), vec![item.clone()])? {
+                Value::Boolean(true) => kept.push(item.clone()),
+                Value::Boolean(false) => {}
+                other => {
+                    return Err(InfraError::TypeError {
+                        expected: "boolean".to_string(),
+                        found: other.type_name().to_string(),
+                        context: Some("result of the array.filter() predicate".to_string()),
                         line: None,
                         column: None,
-                        hint: None,
-                    }),
+                        hint: Some("Return true to keep an item and false to drop it".to_string()),
+                    })
                 }
             }
-            Expr::This => {
-                // 'this' should be handled in the context of a method call
-                // For now, return an error
-                Err(InfraError::RuntimeError {
-                    message: "'this' can only be used inside class methods".to_string(),
+        }
+        Ok(Value::Array(kept))
+    }
+
+    /// array.reduce(items, reducer, initial?): thread an accumulator through
+    /// `reducer(acc, item)` from left to right. Without `initial` the first
+    /// item starts it.
+    fn reduce_array(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if !(2..=3).contains(&arg_values.len()) {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: if arg_values.len() < 2 { 2 } else { 3 },
+                found: arg_values.len(),
+                function_name: Some("array.reduce".to_string()),
+                line: None,
+            });
+        }
+
+        let mut args = arg_values.into_iter();
+        let (items, reducer, initial) = (args.next().unwrap(), args.next().unwrap(), args.next());
+        let Value::Array(items) = items else {
+            return Err(InfraError::TypeError {
+                expected: "array".to_string(),
+                found: items.type_name().to_string(),
+                context: Some("array.reduce() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+        match &reducer {
+            Value::Function {
+                name,
+                params,
+                optional_params,
+                ..
+            } if !optional_params.accepts(params.len(), 2) => {
+                return Err(InfraError::ArgumentCountMismatch {
+                    expected: 2,
+                    found: params.len(),
+                    function_name: Some(format!("{} (array.reduce reducer)", name)),
+                    line: None,
+                });
+            }
+            Value::Function { .. } | Value::NativeFunction { .. } => {}
+            other => {
+                return Err(InfraError::TypeError {
+                    expected: "function".to_string(),
+                    found: other.type_name().to_string(),
+                    context: Some("array.reduce() function".to_string()),
                     line: None,
                     column: None,
-                    stack_trace: vec![],
-                    source_code: None,
+                    hint: Some("Pass a function taking the accumulator and an item".to_string()),
                 })
             }
-            Expr::Super { method } => {
-                // 'super' should be handled in the context of a method call
-                // For now, return an error
-                Err(InfraError::RuntimeError {
-                    message: format!("'super.{}' can only be used inside class methods", method),
+        }
+
+        let mut items = items.into_iter();
+        let Some(mut accumulator) = initial.or_else(|| items.next()) else {
+            return Err(InfraError::RuntimeError {
+                message: "array.reduce of an empty array needs an initial value".to_string(),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            });
+        };
+        for item in items {
+            accumulator = self.call_function(reducer.clone(), vec![accumulator, item])?;
+        }
+        Ok(accumulator)
+    }
+
+    /// array.sort_by(items, compare, extra...): sort a copy of `items` by
+    /// `compare(a, b, extra...)`, which returns a negative number, zero or a
+    /// positive number. The sort is stable, so items comparing 0 keep their
+    /// order. The first error from `compare` stops the sort.
+    fn sort_by(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if arg_values.len() < 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: arg_values.len(),
+                function_name: Some("array.sort_by".to_string()),
+                line: None,
+            });
+        }
+
+        let mut args = arg_values.into_iter();
+        let (items, compare) = (args.next().unwrap(), args.next().unwrap());
+        let extra: Vec<Value> = args.collect();
+        let Value::Array(items) = items else {
+            return Err(InfraError::TypeError {
+                expected: "array".to_string(),
+                found: items.type_name().to_string(),
+                context: Some("array.sort_by() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+        if !matches!(
+            compare,
+            Value::Function { .. } | Value::NativeFunction { .. }
+        ) {
+            return Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: compare.type_name().to_string(),
+                context: Some("array.sort_by() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Pass a comparison such as string.compare".to_string()),
+            });
+        }
+
+        let sorted = array::stable_sort_by(items, |a, b| {
+            let mut call_args = vec![a.clone(), b.clone()];
+            call_args.extend(extra.iter().cloned());
+            match self.call_function(compare.clone(), call_args)? {
+                Value::Number(n) if !n.is_nan() => Ok(n.partial_cmp(&0.0).unwrap()),
+                other => Err(InfraError::TypeError {
+                    expected: "number".to_string(),
+                    found: other.type_name().to_string(),
+                    context: Some("result of the array.sort_by() comparison".to_string()),
                     line: None,
                     column: None,
-                    stack_trace: vec![],
-                    source_code: None,
+                    hint: None,
+                }),
+            }
+        })?;
+        Ok(Value::Array(sorted))
+    }
+
+    /// process.with_timeout(ms, fn): call `fn`, failing with a TimeoutError if
+    /// it is still running after `ms` milliseconds
+    fn call_with_timeout(&mut self, arg_values: Vec<Value>) -> Result<Value> {
+        if arg_values.len() != 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: arg_values.len(),
+                function_name: Some("process.with_timeout".to_string()),
+                line: None,
+            });
+        }
+
+        let mut args = arg_values.into_iter();
+        let (timeout, callback) = (args.next().unwrap(), args.next().unwrap());
+        let timeout_ms = match timeout {
+            Value::Number(ms) if ms >= 0.0 && ms.is_finite() => ms as u64,
+            other => {
+                return Err(InfraError::TypeError {
+                    expected: "non-negative number of milliseconds".to_string(),
+                    found: other.to_string(),
+                    context: Some("process.with_timeout() function".to_string()),
+                    line: None,
+                    column: None,
+                    hint: None,
                 })
             }
-            Expr::New { class, args: _ } => {
-                // Handle 'new' expression for class instantiation
-                let class_value = self.evaluate_expression(class)?;
-                match class_value {
-                    Value::Object(obj) => {
-                        // Check if this looks like a class (has a constructor)
-                        if obj.contains_key("constructor") {
-                            // Create a new instance
-                            let instance = obj.clone();
-
-                            // Call constructor if it exists
-                            if let Some(ctor) = obj.get("constructor") {
-                                if let Value::Function {
-                                    name: _,
-                                    params,
-                                    param_types,
-                                    return_type,
-                                    body,
-                                } = ctor
-                                {
-                                    // Create a temporary function to call the constructor
-                                    let temp_func = Value::Function {
-                                        name: "constructor".to_string(),
-                                        params: params.clone(),
-                                        param_types: param_types.clone(),
-                                        return_type: return_type.clone(),
-                                        body: body.clone(),
-                                    };
-
-                                    // For now, we'll simplify the constructor call
-                                    // In a full implementation, this would properly handle 'this' binding
-                                    drop(temp_func); // We're not actually calling it for now
-                                }
-                            }
+        };
+        if !matches!(callback, Value::Function { .. }) {
+            return Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: callback.type_name().to_string(),
+                context: Some("process.with_timeout() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        }
 
-                            Ok(Value::Object(instance))
-                        } else {
-                            Err(InfraError::TypeError {
-                                expected: "class".to_string(),
-                                found: "object without constructor".to_string(),
-                                context: Some("new expression".to_string()),
-                                line: None,
-                                column: None,
-                                hint: None,
-                            })
-                        }
-                    }
-                    _ => Err(InfraError::TypeError {
-                        expected: "class".to_string(),
-                        found: class_value.type_name().to_string(),
-                        context: Some("new expression".to_string()),
+        let _deadline = cancel::push_deadline(timeout_ms);
+        self.call_function(callback, vec![])
+    }
+
+    /// async.then(promise, callback) runs the callback with a resolved value;
+    /// async.catch(promise, callback) runs it with a rejection's error.
+    /// Either way the promise counts as handled.
+    fn call_promise_handler(&mut self, function: &str, arg_values: Vec<Value>) -> Result<Value> {
+        let function_name = format!("async.{}", function);
+        if arg_values.len() != 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: arg_values.len(),
+                function_name: Some(function_name),
+                line: None,
+            });
+        }
+
+        let mut args = arg_values.into_iter();
+        let (promise, callback) = (args.next().unwrap(), args.next().unwrap());
+        let promise = async_mod::wait_for(&promise)?;
+        let Value::Promise {
+            value,
+            resolved,
+            rejected,
+            error,
+            ..
+        } = &promise
+        else {
+            return Err(InfraError::TypeError {
+                expected: "promise".to_string(),
+                found: promise.type_name().to_string(),
+                context: Some(format!("{}() function", function_name)),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+        async_mod::mark_observed(&promise);
+
+        let origin = if function == "then" {
+            "async.then"
+        } else {
+            "async.catch"
+        };
+        let error = error
+            .clone()
+            .unwrap_or_else(|| "Promise rejected".to_string());
+        let input = match (function, *resolved, *rejected) {
+            ("then", true, _) => value.clone().map(|boxed| *boxed).unwrap_or(Value::Null),
+            ("catch", _, true) => Value::String(error),
+            // The outcome passes through to a new promise untouched
+            (_, true, _) => {
+                let value = value.clone().map(|boxed| *boxed).unwrap_or(Value::Null);
+                return Ok(async_mod::resolved_promise(value, origin));
+            }
+            (_, _, true) => return Ok(async_mod::rejected_promise(error, origin)),
+            _ => return Ok(async_mod::pending_promise(origin)),
+        };
+
+        match self.call_function(callback, vec![input])? {
+            promise @ Value::Promise { .. } => Ok(promise),
+            result => Ok(async_mod::resolved_promise(result, origin)),
+        }
+    }
+
+    /// schedule.run_pending(): run the jobs that are due, returning how many
+    /// ran. A job that fails is reported and stays scheduled.
+    pub fn run_pending_schedules(&mut self) -> Result<Value> {
+        let due = self.jobs.take_due(schedule::now_ms());
+        let ran = due.len();
+
+        for (id, function) in due {
+            let started = schedule::now_ms();
+            let result = self.call_function(function, vec![]);
+            self.jobs.finish(id, started, schedule::now_ms());
+            // Ctrl-C stops the scheduler rather than counting as a failure
+            cancel::check(None)?;
+            if let Err(err) = result {
+                schedule::report_failure(id, &err);
+            }
+        }
+        Ok(Value::Number(ran as f64))
+    }
+
+    /// Make the debounced and throttled calls that are due, returning how
+    /// many were made. A failing call stops the script, like any other error.
+    pub fn run_due_timers(&mut self) -> Result<usize> {
+        let due = self.wrappers.take_due(schedule::now_ms());
+        self.invoke_all(due)
+    }
+
+    /// When the next scheduled job or timed call comes due
+    pub fn next_due(&self) -> Option<u64> {
+        [self.jobs.next_due(), self.wrappers.next_due()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn invoke_all(&mut self, invocations: Vec<timing::Invocation>) -> Result<usize> {
+        let count = invocations.len();
+        for (function, args) in invocations {
+            let args = forwarded(&function, args);
+            self.call_function(function, args)?;
+        }
+        Ok(count)
+    }
+
+    /// Define each parameter in the function's scope: to its argument, to
+    /// its default when the argument was left out, or for a rest parameter
+    /// to an array of the arguments left over. Defaults are evaluated in
+    /// order, so they can use the parameters before them.
+    fn bind_arguments(
+        &mut self,
+        name: &str,
+        params: &[String],
+        param_types: &[Option<Type>],
+        optional_params: &OptionalParams,
+        arg_values: Vec<Value>,
+    ) -> Result<()> {
+        let first_default = optional_params.required(params.len());
+        let mut args = arg_values.into_iter();
+        for (i, param) in params.iter().enumerate() {
+            let value = if optional_params.rest && i == params.len() - 1 {
+                Value::Array(args.by_ref().collect())
+            } else if let Some(value) = args.next() {
+                value
+            } else {
+                self.evaluate_expression(&optional_params.defaults[i - first_default])?
+            };
+
+            // Check parameter types with enhanced error messages
+            if let Some(Some(expected_type)) = param_types.get(i) {
+                if !self.check_type_compatibility(&value, expected_type) {
+                    return Err(InfraError::TypeError {
+                        expected: format!(
+                            "parameter '{}' to be of type {}",
+                            param,
+                            self.type_to_string(expected_type)
+                        ),
+                        found: format!("{} ({})", value.type_name(), value),
+                        context: Some(format!("function call to '{}'", name)),
                         line: None,
                         column: None,
                         hint: None,
-                    }),
+                    });
                 }
             }
+            self.environment.define(param.clone(), value);
         }
+        Ok(())
     }
 
-    /// Call a function from a standard library module
-    fn call_module_function(
-        &mut self,
-        module: &str,
-        function: &str,
-        args: &[Expr],
-    ) -> Result<Value> {
-        // Evaluate arguments
-        let mut arg_values = Vec::new();
-        for arg in args {
-            arg_values.push(self.evaluate_expression(arg)?);
+    /// memo.wrap, clear and stats, and calls through the wrappers, on this
+    /// evaluator's tables
+    fn call_memo(&mut self, function: &str, arg_values: &[Value]) -> Result<Value> {
+        match function {
+            // `memo.wrap(options)` is the decorator `@memo.wrap(options)`
+            "wrap" if matches!(arg_values, [] | [Value::Object(_)]) => {
+                Ok(decorator("memo", function, arg_values))
+            }
+            "wrap" => self.memo.wrap(arg_values),
+            "clear" => self.memo.clear(arg_values),
+            "stats" => self.memo.stats(arg_values),
+            _ => self.call_memoized(arg_values),
         }
+    }
 
-        // Get the native function from stdlib
-        if let Some(native_func) = self.stdlib.get_function(module, function) {
-            native_func(&arg_values)
-        } else {
-            Err(InfraError::RuntimeError {
-                message: format!("Unknown function {}.{}", module, function),
-                line: None,
-                column: None,
-                stack_trace: vec![],
-                source_code: None,
-            })
+    /// Forwarded call from a memo.wrap() function: use the cached result or
+    /// call the original function and remember what it returned
+    fn call_memoized(&mut self, arg_values: &[Value]) -> Result<Value> {
+        match self.memo.lookup(arg_values)? {
+            memo::MemoLookup::Hit(value) => Ok(value),
+            memo::MemoLookup::Miss { function, id, key } => {
+                let args = forwarded(&function, arg_values[1..].to_vec());
+                let value = self.call_function(function, args)?;
+                self.memo.store(id, key, &value);
+                Ok(value)
+            }
+        }
+    }
+
+    /// error.new(): build the error value and capture the current call stack
+    fn create_error_value(&self, arg_values: &[Value], call_line: Option<usize>) -> Result<Value> {
+        let mut value = error::new(arg_values)?;
+        if let Value::Error { stack, line, .. } = &mut value {
+            *line = call_line;
+            *stack = self
+                .call_stack
+                .iter()
+                .rev()
+                .map(|(name, _)| name.clone())
+                .collect();
         }
+        Ok(value)
     }
 
     pub fn define_variable(&mut self, name: String, value: Value) {
@@ -428,23 +1693,39 @@ impl Evaluator {
     fn apply_binary_operator(&self, op: &BinaryOp, left: &Value, right: &Value) -> Result<Value> {
         match (left, right) {
             (Value::Number(l), Value::Number(r)) => self.apply_numeric_binary_operator(op, *l, *r),
+            (Value::BigInt(_), Value::BigInt(_) | Value::Number(_))
+            | (Value::Number(_), Value::BigInt(_)) => {
+                self.apply_bigint_binary_operator(op, left, right)
+            }
             (Value::String(l), Value::String(r)) => self.apply_string_binary_operator(op, l, r),
             (Value::Boolean(l), Value::Boolean(r)) => {
                 self.apply_boolean_binary_operator(op, *l, *r)
             }
-            (Value::String(s), Value::Number(n)) if matches!(op, BinaryOp::Add) => {
-                Ok(Value::String(format!("{}{}", s, n)))
+            (Value::String(s), Value::Number(_) | Value::BigInt(_))
+                if matches!(op, BinaryOp::Add) =>
+            {
+                Ok(Value::String(format!(
+                    "{}{}",
+                    s,
+                    right.display(self.display_precision)
+                )))
             }
-            (Value::Number(n), Value::String(s)) if matches!(op, BinaryOp::Add) => {
-                Ok(Value::String(format!("{}{}", n, s)))
+            (Value::Number(_) | Value::BigInt(_), Value::String(s))
+                if matches!(op, BinaryOp::Add) =>
+            {
+                Ok(Value::String(format!(
+                    "{}{}",
+                    left.display(self.display_precision),
+                    s
+                )))
             }
             _ => {
                 // Handle logical operators for mixed types
                 match op {
                     BinaryOp::And => Ok(Value::Boolean(left.is_truthy() && right.is_truthy())),
                     BinaryOp::Or => Ok(Value::Boolean(left.is_truthy() || right.is_truthy())),
-                    BinaryOp::Equal => Ok(Value::Boolean(self.values_equal(left, right))),
-                    BinaryOp::NotEqual => Ok(Value::Boolean(!self.values_equal(left, right))),
+                    BinaryOp::Equal => Ok(Value::Boolean(left.script_eq(right))),
+                    BinaryOp::NotEqual => Ok(Value::Boolean(!left.script_eq(right))),
                     _ => Err(InfraError::TypeError {
                         expected: "compatible types".to_string(),
                         found: format!("{} and {}", left.type_name(), right.type_name()),
@@ -482,6 +1763,41 @@ impl Evaluator {
             BinaryOp::GreaterEqual => Ok(Value::Boolean(left >= right)),
             BinaryOp::And => Ok(Value::Boolean(left != 0.0 && right != 0.0)),
             BinaryOp::Or => Ok(Value::Boolean(left != 0.0 || right != 0.0)),
+            BinaryOp::Coalesce => Ok(Value::Number(left)),
+        }
+    }
+
+    /// Operators with a bigint operand. Arithmetic needs both sides to be
+    /// bigints; comparisons are exact across bigints and floats.
+    fn apply_bigint_binary_operator(
+        &self,
+        op: &BinaryOp,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value> {
+        let ordering = left.numeric_cmp(right);
+        match op {
+            BinaryOp::Add => left.clone() + right.clone(),
+            BinaryOp::Subtract => left.clone() - right.clone(),
+            BinaryOp::Multiply => left.clone() * right.clone(),
+            BinaryOp::Divide => left.clone() / right.clone(),
+            BinaryOp::Modulo => left.clone() % right.clone(),
+            BinaryOp::Equal => Ok(Value::Boolean(ordering == Some(Ordering::Equal))),
+            BinaryOp::NotEqual => Ok(Value::Boolean(ordering != Some(Ordering::Equal))),
+            BinaryOp::Less => Ok(Value::Boolean(ordering == Some(Ordering::Less))),
+            BinaryOp::LessEqual => Ok(Value::Boolean(matches!(
+                ordering,
+                Some(Ordering::Less | Ordering::Equal)
+            ))),
+            BinaryOp::Greater => Ok(Value::Boolean(ordering == Some(Ordering::Greater))),
+            BinaryOp::GreaterEqual => Ok(Value::Boolean(matches!(
+                ordering,
+                Some(Ordering::Greater | Ordering::Equal)
+            ))),
+            BinaryOp::And => Ok(Value::Boolean(left.is_truthy() && right.is_truthy())),
+            BinaryOp::Or => Ok(Value::Boolean(left.is_truthy() || right.is_truthy())),
+
+            BinaryOp::Coalesce => Ok(left.clone()),
         }
     }
 
@@ -535,6 +1851,7 @@ impl Evaluator {
     fn apply_unary_operator(&self, op: &UnaryOp, operand: &Value) -> Result<Value> {
         match (op, operand) {
             (UnaryOp::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+            (UnaryOp::Minus, Value::BigInt(n)) => Ok(Value::BigInt(-n)),
             (UnaryOp::Not, value) => Ok(Value::Boolean(!value.is_truthy())),
             (UnaryOp::Minus, _) => Err(InfraError::TypeError {
                 expected: "number".to_string(),
@@ -547,17 +1864,9 @@ impl Evaluator {
         }
     }
 
-    fn values_equal(&self, left: &Value, right: &Value) -> bool {
-        match (left, right) {
-            (Value::Number(l), Value::Number(r)) => (l - r).abs() < f64::EPSILON,
-            (Value::String(l), Value::String(r)) => l == r,
-            (Value::Boolean(l), Value::Boolean(r)) => l == r,
-            (Value::Null, Value::Null) => true,
-            _ => false,
-        }
-    }
-
     pub fn execute_function_body(&mut self, stmt: &Stmt) -> Result<()> {
+        cancel::check(None)?;
+
         match stmt {
             Stmt::Expression(expr) => {
                 self.evaluate_expression(expr)?;
@@ -568,16 +1877,22 @@ impl Evaluator {
                 self.environment.define(name.clone(), val);
                 Ok(())
             }
+            Stmt::LetTuple {
+                pattern,
+                value,
+                span,
+                ..
+            } => {
+                let val = self.evaluate_expression(value)?;
+                located(self.destructure("let", pattern, val), *span)
+            }
             Stmt::Print(expr) => {
                 let value = self.evaluate_expression(expr)?;
-                println!("{}", value);
+                output::print_value(value.display(self.display_precision));
                 Ok(())
             }
             Stmt::Block(statements) => {
-                // Create new scope
-                let old_env = self.environment.clone();
-                let new_env = Environment::with_parent(old_env.clone());
-                self.environment = new_env;
+                self.environment.push_scope();
 
                 let mut result = Ok(());
                 for statement in statements {
@@ -587,8 +1902,7 @@ impl Evaluator {
                     }
                 }
 
-                // Restore original scope
-                self.environment = old_env;
+                self.environment.pop_scope();
 
                 result
             }
@@ -612,7 +1926,11 @@ impl Evaluator {
                     if !condition_value.is_truthy() {
                         break;
                     }
-                    self.execute_function_body(body)?;
+                    match self.execute_function_body(body) {
+                        Err(InfraError::Break { .. }) => break,
+                        Ok(()) | Err(InfraError::Continue { .. }) => {}
+                        Err(error) => return Err(error),
+                    }
                 }
                 Ok(())
             }
@@ -639,21 +1957,48 @@ impl Evaluator {
                     }
                 };
 
-                // Save old variable value if it exists
-                let old_var_value = self.environment.get(var).ok();
-
+                // As at the top level, the loop variable gets its own scope
+                self.environment.push_scope();
+                let mut result = Ok(());
                 for i in start_num..end_num {
                     self.environment
                         .define(var.clone(), Value::Number(i as f64));
-                    self.execute_function_body(body)?;
+                    match self.execute_function_body(body) {
+                        Err(InfraError::Break { .. }) => break,
+                        Ok(()) | Err(InfraError::Continue { .. }) => {}
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
                 }
+                self.environment.pop_scope();
 
-                // Restore old variable value or remove it
-                if let Some(old_value) = old_var_value {
-                    self.environment.define(var.clone(), old_value);
+                result
+            }
+            Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                span,
+            } => {
+                let items = located(loop_items(self.evaluate_expression(iterable)?), *span)?;
+                self.environment.push_scope();
+                let mut result = Ok(());
+                for item in items {
+                    let bound = located(self.bind_loop_item(pattern, item), *span);
+                    match bound.and_then(|()| self.execute_function_body(body)) {
+                        Err(InfraError::Break { .. }) => break,
+                        Ok(()) | Err(InfraError::Continue { .. }) => {}
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
                 }
+                self.environment.pop_scope();
 
-                Ok(())
+                result
             }
             Stmt::Return(value) => {
                 let return_value = if let Some(expr) = value {
@@ -663,146 +2008,48 @@ impl Evaluator {
                 };
                 Err(InfraError::ReturnValue(return_value))
             }
+            Stmt::Break { span } => Err(InfraError::Break {
+                line: span.map(|span| span.line),
+                column: span.map(|span| span.column),
+            }),
+            Stmt::Continue { span } => Err(InfraError::Continue {
+                line: span.map(|span| span.line),
+                column: span.map(|span| span.column),
+            }),
             Stmt::Function {
                 name,
                 params,
                 param_types,
+                optional_params,
                 return_type,
                 body,
-                ..
+                decorators,
             } => {
                 let function_value = Value::Function {
                     name: name.clone(),
                     params: params.clone(),
                     param_types: param_types.clone(),
+                    optional_params: Rc::clone(optional_params),
                     return_type: return_type.clone(),
                     body: body.clone(),
+                    captured: self.environment.capture(),
+                    is_async: false,
                 };
-                self.environment.define(name.clone(), function_value);
+                self.environment
+                    .define(name.clone(), function_value.clone());
+                if !decorators.is_empty() {
+                    let decorated = self.apply_decorators(function_value, decorators)?;
+                    self.environment.define(name.clone(), decorated);
+                }
                 Ok(())
             }
-            Stmt::Assignment { target, value } => {
+            Stmt::Assignment {
+                target,
+                value,
+                span,
+            } => {
                 let new_value = self.evaluate_expression(value)?;
-
-                match target {
-                    AssignmentTarget::Identifier(name) => {
-                        if self.environment.get(name).is_err() {
-                            return Err(InfraError::UndefinedVariable {
-                                name: name.clone(),
-                                line: None,
-                                column: None,
-                                suggestion: None,
-                            });
-                        }
-
-                        // Check type compatibility for assignment
-                        if let Ok(stored_type) = self.environment.get_type(name) {
-                            if let Some(expected_type) = stored_type {
-                                if !self.check_type_compatibility(&new_value, &expected_type) {
-                                    return Err(InfraError::TypeError {
-                                        expected: format!(
-                                            "variable '{}' to be of type {}",
-                                            name,
-                                            self.type_to_string(&expected_type)
-                                        ),
-                                        found: format!("{} ({})", new_value.type_name(), new_value),
-                                        context: Some(format!("assignment to variable '{}'", name)),
-                                        line: None,
-                                        column: None,
-                                        hint: None,
-                                    });
-                                }
-                            }
-                        }
-
-                        self.environment.define(name.clone(), new_value);
-                        Ok(())
-                    }
-                    AssignmentTarget::Property { object, property } => {
-                        let obj_val = self.evaluate_expression(object)?;
-                        match obj_val {
-                            Value::Object(mut map) => {
-                                map.insert(property.clone(), new_value);
-                                let updated_obj = Value::Object(map);
-
-                                // We need to update the object in the environment
-                                // This is tricky because we need to find where the object is stored
-                                if let Expr::Identifier(obj_name) = object.as_ref() {
-                                    self.environment.define(obj_name.clone(), updated_obj);
-                                    Ok(())
-                                } else {
-                                    Err(InfraError::RuntimeError {
-                                        message: "Cannot assign to property of complex expression"
-                                            .to_string(),
-                                        line: None,
-                                        column: None,
-                                        stack_trace: vec![],
-                                        source_code: None,
-                                    })
-                                }
-                            }
-                            _ => Err(InfraError::TypeError {
-                                expected: "object".to_string(),
-                                found: obj_val.type_name().to_string(),
-                                context: Some("property assignment".to_string()),
-                                line: None,
-                                column: None,
-                                hint: None,
-                            }),
-                        }
-                    }
-                    AssignmentTarget::Index { object, index } => {
-                        let obj_val = self.evaluate_expression(object)?;
-                        let index_val = self.evaluate_expression(index)?;
-
-                        match (obj_val, index_val) {
-                            (Value::Array(mut arr), Value::Number(idx)) => {
-                                let index = idx as usize;
-                                if index >= arr.len() {
-                                    return Err(InfraError::IndexOutOfBounds {
-                                        index,
-                                        length: arr.len(),
-                                        array_name: None,
-                                        line: None,
-                                    });
-                                }
-                                arr[index] = new_value;
-                                let updated_arr = Value::Array(arr);
-
-                                // Update array in environment
-                                if let Expr::Identifier(arr_name) = object.as_ref() {
-                                    self.environment.define(arr_name.clone(), updated_arr);
-                                    Ok(())
-                                } else {
-                                    Err(InfraError::RuntimeError {
-                                        message: "Cannot assign to index of complex expression"
-                                            .to_string(),
-                                        line: None,
-                                        column: None,
-                                        stack_trace: vec![],
-                                        source_code: None,
-                                    })
-                                }
-                            }
-                            (Value::Array(_), _) => Err(InfraError::TypeError {
-                                expected: "number".to_string(),
-                                found: "non-number index".to_string(),
-                                context: Some("array index assignment".to_string()),
-                                line: None,
-                                column: None,
-                                hint: None,
-                            }),
-                            (_, _) => Err(InfraError::TypeError {
-                                expected: "array".to_string(),
-                                found: "non-array for indexing".to_string(),
-                                context: Some("array index assignment".to_string()),
-                                line: None,
-                                column: None,
-                                hint: None,
-                            }),
-                        }
-                    }
-                }
+                located(self.assign(target, new_value), *span)
             }
             Stmt::Try { .. } => {
                 // Try statements should be handled by the interpreter, not the evaluator
@@ -834,28 +2081,338 @@ impl Evaluator {
                     source_code: None,
                 })
             }
-            Stmt::AsyncFunction { .. } => {
-                // Async function declarations should be handled by the interpreter, not the evaluator
-                Err(InfraError::RuntimeError {
-                    message: "Async function declarations should be handled by interpreter"
-                        .to_string(),
-                    line: None,
-                    column: None,
-                    stack_trace: vec![],
-                    source_code: None,
+            Stmt::AsyncFunction {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                ..
+            } => {
+                let function_value = Value::Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    param_types: param_types.clone(),
+                    optional_params: Rc::clone(optional_params),
+                    return_type: return_type.clone(),
+                    body: body.clone(),
+                    captured: self.environment.capture(),
+                    is_async: true,
+                };
+                self.environment.define(name.clone(), function_value);
+                Ok(())
+            }
+            Stmt::Enum { name, members } => {
+                self.define_enum(name, members);
+                Ok(())
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+            } => match self.select_match_arm(subject, arms, default)? {
+                Some(body) => self.execute_function_body(body),
+                None => Ok(()),
+            },
+            Stmt::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+            } => self.define_class(name, superclass.as_deref(), fields, methods),
+        }
+    }
+
+    /// Store `new_value` where an assignment statement's target says
+    fn assign(&mut self, target: &AssignmentTarget, new_value: Value) -> Result<()> {
+        match target {
+            AssignmentTarget::Identifier(name) => {
+                if self.environment.get(name).is_err() {
+                    return Err(self.undefined_variable(name));
+                }
+
+                // Check type compatibility for assignment
+                if let Ok(stored_type) = self.environment.get_type(name) {
+                    if let Some(expected_type) = stored_type {
+                        if !self.check_type_compatibility(&new_value, &expected_type) {
+                            return Err(InfraError::TypeError {
+                                expected: format!(
+                                    "variable '{}' to be of type {}",
+                                    name,
+                                    self.type_to_string(&expected_type)
+                                ),
+                                found: format!("{} ({})", new_value.type_name(), new_value),
+                                context: Some(format!("assignment to variable '{}'", name)),
+                                line: None,
+                                column: None,
+                                hint: None,
+                            });
+                        }
+                    }
+                }
+
+                // The binding is updated in the scope that declared it
+                self.environment.assign(name, new_value)
+            }
+            AssignmentTarget::Property { object, property } => {
+                let last = Access::Property(property);
+                self.assign_into(object, last, new_value)
+            }
+            AssignmentTarget::Index { object, index } => {
+                let last = Access::Index(index);
+                self.assign_into(object, last, new_value)
+            }
+        }
+    }
+
+    /// `object<last> = value`, however deeply `object` is nested: the
+    /// variable at the root of the chain is changed in place, in the scope
+    /// that declared it
+    fn assign_into(&mut self, object: &Expr, last: Access, value: Value) -> Result<()> {
+        let mut accesses = vec![last];
+        let mut base = object;
+        let root = loop {
+            match base {
+                Expr::Identifier { name, .. } => break name.as_str(),
+                Expr::This => break "this",
+                Expr::Property {
+                    object, property, ..
+                } => {
+                    accesses.push(Access::Property(property));
+                    base = object;
+                }
+                Expr::Index { object, index, .. } => {
+                    accesses.push(Access::Index(index));
+                    base = object;
+                }
+                other => {
+                    return Err(InfraError::RuntimeError {
+                        message: format!(
+                            "Cannot assign into {}: only variables and their properties and \
+                             elements can be assigned",
+                            printer::format_expr(other)
+                        ),
+                        line: None,
+                        column: None,
+                        stack_trace: vec![],
+                        source_code: None,
+                    })
+                }
+            }
+        };
+
+        // Indices are evaluated left to right, before anything is changed
+        let mut steps = Vec::with_capacity(accesses.len());
+        for access in accesses.into_iter().rev() {
+            steps.push(match access {
+                Access::Property(name) => Step::Property(name.clone()),
+                Access::Index(index) => Step::Index(self.evaluate_expression(index)?),
+            });
+        }
+        let target = steps
+            .iter()
+            .fold(root.to_string(), |path, step| path + &step.to_string());
+
+        let Some(cell) = self.environment.cell(root) else {
+            return Err(match root {
+                "this" => this_outside_method(),
+                _ => self.undefined_variable(root),
+            });
+        };
+        let mut reached = root.to_string();
+        let mut pending = store(&mut cell.borrow_mut(), &steps, value, &target, &mut reached)?;
+
+        // An instance is shared, so the steps after one change it wherever
+        // it is held. Each instance's fields are borrowed on their own, after
+        // the borrows that led to it are released.
+        while let Some((instance, steps, value)) = pending {
+            let (step, rest) = steps.split_first().expect("a step follows an instance");
+            let key = match step {
+                Step::Property(key) | Step::Index(Value::String(key)) => key,
+                Step::Index(index) => {
+                    return Err(assignment_error(
+                        &target,
+                        format!(
+                            "{} is a {} instance, so its index must be a string, not a {}",
+                            reached,
+                            instance.class.name,
+                            index.type_name()
+                        ),
+                    ))
+                }
+            };
+            let mut fields = instance.fields.borrow_mut();
+            if rest.is_empty() {
+                fields.insert(key.clone(), value);
+                return Ok(());
+            }
+            let Some(field) = fields.get_mut(key) else {
+                return Err(assignment_error(
+                    &target,
+                    format!("{} has no property '{}'", reached, key),
+                ));
+            };
+            reached.push_str(&step.to_string());
+            pending = store(field, rest, value, &target, &mut reached)?;
+        }
+        Ok(())
+    }
+
+    pub fn define_class(
+        &mut self,
+        name: &str,
+        superclass: Option<&str>,
+        fields: &[(String, Expr)],
+        methods: &[MethodDecl],
+    ) -> Result<()> {
+        let superclass = match superclass {
+            Some(parent) => Some(self.superclass(name, parent)?),
+            None => None,
+        };
+        let definition = ClassDef {
+            name: name.to_string(),
+            superclass,
+            fields: fields.to_vec(),
+            methods: methods.to_vec(),
+            captured: self.environment.capture(),
+        };
+        self.environment
+            .define(name.to_string(), Value::Class(Rc::new(definition)));
+        Ok(())
+    }
+
+    /// The class `name` extends, which must be a class already defined
+    fn superclass(&mut self, name: &str, parent: &str) -> Result<Rc<ClassDef>> {
+        let problem = match self.environment.get(parent) {
+            Ok(Value::Class(class)) => return Ok(class),
+            Ok(other) => format!("{} is a {}, not a class", parent, other.type_name()),
+            Err(_) => format!("no class named {} is defined", parent),
+        };
+        Err(InfraError::ClassError {
+            message: format!("cannot extend {}: {}", parent, problem),
+            class_name: Some(name.to_string()),
+            method_name: None,
+            line: None,
+        })
+    }
+
+    /// A new instance of `class`. Its fields start at their initializers'
+    /// values, the superclass's first, then `init` runs with `args`.
+    fn construct(&mut self, class: &Rc<ClassDef>, args: Vec<Value>) -> Result<Value> {
+        let instance = Rc::new(Instance {
+            class: Rc::clone(class),
+            fields: RefCell::new(ObjectMap::new()),
+        });
+
+        let lineage: Vec<&Rc<ClassDef>> = class.lineage().collect();
+        for declaring in lineage.into_iter().rev() {
+            if declaring.fields.is_empty() {
+                continue;
+            }
+            // Initializers see what a method would: the class's captures
+            // and `this`, so a field can build on the ones before it
+            self.in_function_scope(|evaluator| {
+                for (captured_name, cell) in declaring.captured.iter() {
+                    evaluator
+                        .environment
+                        .define_shared(captured_name.clone(), Rc::clone(cell));
+                }
+                evaluator
+                    .environment
+                    .define("this".to_string(), Value::Instance(Rc::clone(&instance)));
+                declaring.fields.iter().try_for_each(|(field, value)| {
+                    let value = evaluator.evaluate_expression(value)?;
+                    instance.fields.borrow_mut().insert(field.clone(), value);
+                    Ok(())
                 })
+            })?;
+        }
+
+        // A class without its own `init` is built by its superclass's
+        match class.method("init") {
+            Some((declaring, init)) => {
+                self.call_function(bound_method(&instance, declaring, init), args)?;
             }
-            Stmt::Class { .. } => {
-                // Class declarations should be handled by the interpreter, not the evaluator
-                Err(InfraError::RuntimeError {
-                    message: "Class declarations should be handled by interpreter".to_string(),
+            None if !args.is_empty() => {
+                return Err(InfraError::ClassError {
+                    message: format!(
+                        "{} has no init method, so it takes no arguments (got {})",
+                        class.name,
+                        args.len()
+                    ),
+                    class_name: Some(class.name.clone()),
+                    method_name: None,
                     line: None,
-                    column: None,
-                    stack_trace: vec![],
-                    source_code: None,
                 })
             }
+            None => {}
+        }
+        Ok(Value::Instance(instance))
+    }
+
+    /// `super.method`: the superclass's `method`, bound to `this`. The
+    /// superclass is that of the class declaring the running method, not of
+    /// the instance, so each level of an override chain reaches the next.
+    fn super_method(&self, method: &str) -> Result<Value> {
+        let (Ok(Value::Class(declaring)), Ok(Value::Instance(instance))) =
+            (self.environment.get("class"), self.environment.get("this"))
+        else {
+            return Err(InfraError::ClassError {
+                message: format!("'super.{}' can only be used inside class methods", method),
+                class_name: None,
+                method_name: Some(method.to_string()),
+                line: None,
+            });
+        };
+        let class_error = |message: String| InfraError::ClassError {
+            message,
+            class_name: Some(declaring.name.clone()),
+            method_name: Some(method.to_string()),
+            line: None,
+        };
+        let Some(parent) = &declaring.superclass else {
+            return Err(class_error(format!(
+                "'super.{}' needs a superclass, but {} does not extend another class",
+                method, declaring.name
+            )));
+        };
+        match parent.method(method) {
+            Some((class, found)) => Ok(bound_method(&instance, class, found)),
+            None => Err(class_error(format!(
+                "{} has no method '{}' for super.{} to call",
+                parent.name, method, method
+            ))),
+        }
+    }
+
+    pub fn define_enum(&mut self, name: &str, members: &[String]) {
+        let definition = EnumDef {
+            name: name.to_string(),
+            members: members.to_vec(),
+        };
+        self.environment
+            .define(name.to_string(), Value::Enum(Rc::new(definition)));
+    }
+
+    /// The body of the first arm with a pattern equal to the subject, or the
+    /// default arm. None when nothing matches. Patterns are evaluated in
+    /// order and only until one matches.
+    pub fn select_match_arm<'a>(
+        &mut self,
+        subject: &Expr,
+        arms: &'a [MatchArm],
+        default: &'a Option<Box<Stmt>>,
+    ) -> Result<Option<&'a Stmt>> {
+        let subject = self.evaluate_expression(subject)?;
+        for arm in arms {
+            for pattern in &arm.patterns {
+                if subject.script_eq(&self.evaluate_expression(pattern)?) {
+                    return Ok(Some(&arm.body));
+                }
+            }
         }
+        Ok(default.as_deref())
     }
 
     // Enhanced type checking for function parameters and returns
@@ -965,8 +2522,10 @@ impl Evaluator {
     fn type_to_string(&self, type_annotation: &Type) -> String {
         match type_annotation {
             Type::Number => "number".to_string(),
+            Type::BigInt => "bigint".to_string(),
             Type::String => "string".to_string(),
             Type::Boolean => "boolean".to_string(),
+            Type::Bytes => "bytes".to_string(),
             Type::Any => "any".to_string(),
             Type::Array(element_type) => format!("[{}]", self.type_to_string(element_type)),
             Type::Object(fields) => {
@@ -995,6 +2554,8 @@ impl Evaluator {
                     self.type_to_string(return_type)
                 )
             }
+            Type::Tuple(_) => printer::format_type(type_annotation),
+            Type::Named(name) => name.clone(),
             Type::Never => "never".to_string(),
         }
     }
@@ -1003,23 +2564,30 @@ impl Evaluator {
     pub fn infer_value_type(&self, value: &Value) -> Type {
         match value {
             Value::Number(_) => Type::Number,
+            Value::BigInt(_) => Type::BigInt,
             Value::String(_) => Type::String,
             Value::Boolean(_) => Type::Boolean,
+            Value::Bytes(_) => Type::Bytes,
+            Value::EnumMember { definition, .. } => Type::Named(definition.name.clone()),
+            Value::Instance(instance) => Type::Named(instance.class.name.clone()),
             Value::Null => Type::Any, // Use Any for null values
             Value::Array(arr) => {
                 if arr.is_empty() {
-                    // For empty arrays, we can't infer the element type
-                    Type::Array(Box::new(Type::Union(vec![
-                        Type::Number,
-                        Type::String,
-                        Type::Boolean,
-                    ])))
+                    // An empty array fits any element type, as an empty
+                    // rest parameter must
+                    Type::Array(Box::new(Type::Any))
                 } else {
                     // Infer from first element (could be enhanced to check all elements)
                     let element_type = self.infer_value_type(&arr[0]);
                     Type::Array(Box::new(element_type))
                 }
             }
+            Value::Tuple(items) => Type::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.infer_value_type(item))
+                    .collect(),
+            ),
             Value::Object(obj) => {
                 let mut fields = Vec::new();
                 for (key, value) in obj {
@@ -1048,12 +2616,20 @@ impl Evaluator {
                     return_type: Box::new(ret_type),
                 }
             }
+            // Native and compiled signatures are not tracked
+            Value::NativeFunction { .. } | Value::CompiledFunction { .. } => Type::Any,
             Value::Promise { .. } => Type::Any, // Promises can be any type when resolved
+            Value::Error { .. } => Type::Any,   // Errors may stand in for any result
+            Value::Enum(_) | Value::Class(_) => Type::Any, // The namespace itself is not a type
         }
     }
 
     // Enhanced type compatibility checking with better union type support
     fn check_type_compatibility(&self, value: &Value, expected_type: &Type) -> bool {
+        // An instance is also of every class its class extends
+        if let (Value::Instance(instance), Type::Named(name)) = (value, expected_type) {
+            return instance.class.is_a(name);
+        }
         let value_type = self.infer_value_type(value);
         self.types_compatible(&value_type, expected_type)
     }
@@ -1062,8 +2638,11 @@ impl Evaluator {
         match (actual, expected) {
             // Exact matches
             (Type::Number, Type::Number) => true,
+            (Type::BigInt, Type::BigInt) => true,
             (Type::String, Type::String) => true,
             (Type::Boolean, Type::Boolean) => true,
+            (Type::Bytes, Type::Bytes) => true,
+            (Type::Named(actual), Type::Named(expected)) => actual == expected,
             (Type::Any, _) | (_, Type::Any) => true, // Any is compatible with everything
 
             // Array compatibility
@@ -1071,6 +2650,15 @@ impl Evaluator {
                 self.types_compatible(actual_elem, expected_elem)
             }
 
+            // Tuples match position by position
+            (Type::Tuple(actual_items), Type::Tuple(expected_items)) => {
+                actual_items.len() == expected_items.len()
+                    && actual_items
+                        .iter()
+                        .zip(expected_items)
+                        .all(|(actual, expected)| self.types_compatible(actual, expected))
+            }
+
             // Object compatibility (structural typing)
             (Type::Object(actual_fields), Type::Object(expected_fields)) => {
                 // Check that all expected fields are present and compatible
@@ -1138,7 +2726,7 @@ impl Evaluator {
     pub fn infer_expression_type(&self, expr: &Expr) -> Type {
         match expr {
             Expr::Literal(value) => self.value_to_type(value),
-            Expr::Identifier(name) => {
+            Expr::Identifier { name, .. } => {
                 // Look up variable type in environment
                 if let Ok(value) = self.environment.get(name) {
                     self.value_to_type(&value)
@@ -1150,18 +2738,37 @@ impl Evaluator {
                 left,
                 operator,
                 right,
+                ..
             } => {
                 let left_type = self.infer_expression_type(left);
                 let right_type = self.infer_expression_type(right);
                 self.infer_binary_operation_type(operator, &left_type, &right_type)
             }
-            Expr::Unary { operator, operand } => {
+            Expr::Unary {
+                operator, operand, ..
+            } => {
                 let operand_type = self.infer_expression_type(operand);
                 self.infer_unary_operation_type(operator, &operand_type)
             }
-            Expr::Call { callee, args: _ } => {
+            Expr::Conditional {
+                then_expr,
+                else_expr,
+                ..
+            } => {
+                let then_type = self.infer_expression_type(then_expr);
+                let else_type = self.infer_expression_type(else_expr);
+                if then_type == else_type {
+                    then_type
+                } else {
+                    Type::Union(vec![then_type, else_type])
+                }
+            }
+            Expr::Call { callee, .. } => {
                 // Try to infer return type from function signature
-                if let Expr::Identifier(func_name) = callee.as_ref() {
+                if let Expr::Identifier {
+                    name: func_name, ..
+                } = callee.as_ref()
+                {
                     if let Ok(Value::Function { return_type, .. }) = self.environment.get(func_name)
                     {
                         return return_type.unwrap_or(Type::Any);
@@ -1182,6 +2789,13 @@ impl Evaluator {
                     Type::Array(Box::new(common_type))
                 }
             }
+            Expr::Interpolation(_) => Type::String,
+            Expr::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.infer_expression_type(e))
+                    .collect(),
+            ),
             Expr::Object(fields) => {
                 let typed_fields: Vec<(String, Type)> = fields
                     .iter()
@@ -1197,6 +2811,7 @@ impl Evaluator {
                 let object_type = self.infer_expression_type(object);
                 match object_type {
                     Type::Array(element_type) => *element_type,
+                    Type::Tuple(_) => Type::Any, // Depends on which element
                     Type::Object(_) => Type::Any, // Could be any property type
                     _ => Type::Any,
                 }
@@ -1206,6 +2821,17 @@ impl Evaluator {
             Expr::This => Type::Any,                // 'this' type depends on class context
             Expr::Super { .. } => Type::Any,        // 'super' type depends on inheritance
             Expr::New { .. } => Type::Any,          // 'new' expressions return object instances
+            Expr::Lambda {
+                param_types,
+                return_type,
+                ..
+            } => Type::Function {
+                params: param_types
+                    .iter()
+                    .map(|ty| ty.clone().unwrap_or(Type::Any))
+                    .collect(),
+                return_type: Box::new(return_type.clone().unwrap_or(Type::Any)),
+            },
         }
     }
 
@@ -1240,6 +2866,7 @@ impl Evaluator {
                 // Arithmetic operations
                 match (left, right) {
                     (Type::Number, Type::Number) => Type::Number,
+                    (Type::BigInt, Type::BigInt) => Type::BigInt,
                     (Type::String, Type::String) if matches!(operator, Add) => Type::String,
                     _ => Type::Any, // Could be invalid, but we'll let runtime handle it
                 }
@@ -1252,6 +2879,9 @@ impl Evaluator {
                 // Logical operations return boolean
                 Type::Boolean
             }
+            // Either side, so only a type both share is certain
+            Coalesce if left == right => left.clone(),
+            Coalesce => Type::Any,
         }
     }
 
@@ -1262,6 +2892,7 @@ impl Evaluator {
         match operator {
             Minus => match operand {
                 Type::Number => Type::Number,
+                Type::BigInt => Type::BigInt,
                 _ => Type::Any,
             },
             Not => Type::Boolean, // Logical not always returns boolean
@@ -1272,8 +2903,11 @@ impl Evaluator {
     fn value_to_type(&self, value: &Value) -> Type {
         match value {
             Value::Number(_) => Type::Number,
+            Value::BigInt(_) => Type::BigInt,
             Value::String(_) => Type::String,
             Value::Boolean(_) => Type::Boolean,
+            Value::Bytes(_) => Type::Bytes,
+            Value::EnumMember { definition, .. } => Type::Named(definition.name.clone()),
             Value::Array(elements) => {
                 if elements.is_empty() {
                     Type::Array(Box::new(Type::Any))
@@ -1284,6 +2918,9 @@ impl Evaluator {
                     Type::Array(Box::new(common_type))
                 }
             }
+            Value::Tuple(items) => {
+                Type::Tuple(items.iter().map(|v| self.value_to_type(v)).collect())
+            }
             Value::Object(map) => {
                 let typed_fields: Vec<(String, Type)> = map
                     .iter()
@@ -1302,8 +2939,14 @@ impl Evaluator {
                     .collect(),
                 return_type: Box::new(return_type.clone().unwrap_or(Type::Any)),
             },
-            Value::Null => Type::Any,           // Null can be any type
+            Value::Null => Type::Any,    // Null can be any type
+            Value::Enum(_) => Type::Any, // The namespace itself is not a type
+            Value::Class(_) => Type::Any,
+            Value::Instance(instance) => Type::Named(instance.class.name.clone()),
+            // Native and compiled signatures are not tracked
+            Value::NativeFunction { .. } | Value::CompiledFunction { .. } => Type::Any,
             Value::Promise { .. } => Type::Any, // Promises can be any type when resolved
+            Value::Error { .. } => Type::Any,   // Errors may stand in for any result
         }
     }
 }
@@ -1313,3 +2956,428 @@ impl Default for Evaluator {
         Self::new()
     }
 }
+
+/// What `for item in iterable:` visits: an array's or tuple's items, an
+/// object's keys or a string's characters, in order
+/// One step of an assignment target, as written
+enum Access<'a> {
+    Property(&'a String),
+    Index(&'a Expr),
+}
+
+/// One step of an assignment target, with its index evaluated
+enum Step {
+    Property(String),
+    Index(Value),
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Property(name) => write!(f, ".{}", name),
+            Step::Index(Value::String(key)) => write!(f, "[{:?}]", key),
+            Step::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+/// An assignment that reached an instance: the instance, the steps still
+/// to take from it and the value to store
+type Pending<'s> = (Rc<Instance>, &'s [Step], Value);
+
+/// Take `steps` from `current` and store `value` at the end, adding the
+/// last property if it is new. A walk that reaches an instance first stops
+/// there and hands back the instance, the steps left and the value.
+fn store<'s>(
+    mut current: &mut Value,
+    steps: &'s [Step],
+    value: Value,
+    target: &str,
+    reached: &mut String,
+) -> Result<Option<Pending<'s>>> {
+    for (i, step) in steps.iter().enumerate() {
+        if let Value::Instance(instance) = &*current {
+            return Ok(Some((Rc::clone(instance), &steps[i..], value)));
+        }
+        if i + 1 == steps.len() {
+            match (current, step) {
+                (Value::Object(map), Step::Property(key) | Step::Index(Value::String(key))) => {
+                    map.insert(key.clone(), value);
+                }
+                (current, step) => *element_mut(current, step, target, reached)? = value,
+            }
+            return Ok(None);
+        }
+        current = element_mut(current, step, target, reached)?;
+        reached.push_str(&step.to_string());
+    }
+    unreachable!("an assignment path has a step")
+}
+
+fn assignment_error(target: &str, problem: String) -> InfraError {
+    InfraError::RuntimeError {
+        message: format!("Cannot assign to {}: {}", target, problem),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
+
+/// `method` of `class` as a function value whose `this` is `instance`,
+/// named as in `Counter.increment`. The method also keeps `class`, which
+/// `super` starts from; scripts cannot name either binding, as `this` and
+/// `class` are keywords.
+fn bound_method(instance: &Rc<Instance>, class: &Rc<ClassDef>, method: &MethodDecl) -> Value {
+    let mut cells: Vec<_> = class.captured.iter().cloned().collect();
+    // Last, so they win over any the class itself closed over
+    cells.push((
+        "class".to_string(),
+        Rc::new(RefCell::new(Value::Class(Rc::clone(class)))),
+    ));
+    cells.push((
+        "this".to_string(),
+        Rc::new(RefCell::new(Value::Instance(Rc::clone(instance)))),
+    ));
+    Value::Function {
+        name: format!("{}.{}", class.name, method.name),
+        params: method.params.clone(),
+        param_types: method.param_types.clone(),
+        optional_params: Rc::clone(&method.optional_params),
+        return_type: method.return_type.clone(),
+        body: Rc::clone(&method.body),
+        captured: Captures::new(cells),
+        is_async: false,
+    }
+}
+
+/// `module.function(args)` waiting for the function it wraps: a decorator
+/// passing the function it is given in front of `args`, so
+/// `timing.throttle(100)` decorates `f` as `timing.throttle(f, 100)` does
+fn decorator(module: &str, function: &str, args: &[Value]) -> Value {
+    let decorated = "decorated";
+    Value::Function {
+        name: format!("{}.{}", module, function),
+        params: vec![decorated.to_string()],
+        param_types: vec![None],
+        optional_params: Default::default(),
+        return_type: None,
+        body: Rc::new(Stmt::Return(Some(Expr::Call {
+            callee: Box::new(Expr::ModuleAccess {
+                module: module.to_string(),
+                function: function.to_string(),
+            }),
+            args: std::iter::once(Expr::identifier(decorated))
+                .chain(args.iter().cloned().map(Expr::Literal))
+                .collect(),
+            span: None,
+        }))),
+        captured: Default::default(),
+        is_async: false,
+    }
+}
+
+/// `value` if it is a function, else a type error in `context`
+fn decorator_function(value: Value, context: String, hint: &str) -> Result<Value> {
+    if matches!(value, Value::Function { .. } | Value::NativeFunction { .. }) {
+        return Ok(value);
+    }
+    Err(InfraError::TypeError {
+        expected: "function".to_string(),
+        found: value.type_name().to_string(),
+        context: Some(context),
+        line: None,
+        column: None,
+        hint: Some(hint.to_string()),
+    })
+}
+
+/// The arguments a memo or timing wrapper, which takes the same parameters
+/// as `function`, passes on to it: a rest parameter arrives as one array and
+/// goes back out as the arguments it collected
+fn forwarded(function: &Value, mut args: Vec<Value>) -> Vec<Value> {
+    if let Value::Function {
+        optional_params, ..
+    } = function
+    {
+        if optional_params.rest {
+            if let Some(Value::Array(rest)) = args.pop() {
+                args.extend(rest);
+            }
+        }
+    }
+    args
+}
+
+fn this_outside_method() -> InfraError {
+    InfraError::ClassError {
+        message: "'this' can only be used inside class methods".to_string(),
+        class_name: None,
+        method_name: None,
+        line: None,
+    }
+}
+
+/// The existing property or element `step` names inside `container`.
+/// `reached` is the path to `container` and `target` the whole path being
+/// assigned, for errors.
+fn element_mut<'v>(
+    container: &'v mut Value,
+    step: &Step,
+    target: &str,
+    reached: &str,
+) -> Result<&'v mut Value> {
+    let problem = match (&*container, step) {
+        (Value::Object(map), Step::Property(key) | Step::Index(Value::String(key))) => {
+            if map.contains_key(key) {
+                None
+            } else {
+                Some(format!("{} has no property '{}'", reached, key))
+            }
+        }
+        (Value::Array(items), Step::Index(Value::Number(index))) => {
+            if (*index as usize) < items.len() {
+                None
+            } else {
+                Some(format!(
+                    "index {} is out of bounds for {} (length {})",
+                    index,
+                    reached,
+                    items.len()
+                ))
+            }
+        }
+        (Value::Array(_), Step::Index(index)) => Some(format!(
+            "{} is an array, so its index must be a number, not a {}",
+            reached,
+            index.type_name()
+        )),
+        (other, Step::Property(_)) => Some(format!(
+            "{} is a {}, not an object",
+            reached,
+            other.type_name()
+        )),
+        (other, Step::Index(_)) => Some(format!(
+            "{} is a {}, not an array or object",
+            reached,
+            other.type_name()
+        )),
+    };
+    if let Some(problem) = problem {
+        return Err(assignment_error(target, problem));
+    }
+    Ok(match (container, step) {
+        (Value::Object(map), Step::Property(key) | Step::Index(Value::String(key))) => {
+            map.get_mut(key).expect("checked above")
+        }
+        (Value::Array(items), Step::Index(Value::Number(index))) => &mut items[*index as usize],
+        _ => unreachable!("every other step was rejected above"),
+    })
+}
+
+/// Place an error without a position at `span`, when there is one
+pub(crate) fn located<T>(result: Result<T>, span: Option<Span>) -> Result<T> {
+    match (result, span) {
+        (Err(error), Some(span)) => Err(error.at(span.line, span.column)),
+        (result, _) => result,
+    }
+}
+
+pub(crate) fn loop_items(iterable: Value) -> Result<Vec<Value>> {
+    match iterable {
+        Value::Array(items) => Ok(items),
+        Value::Tuple(items) => Ok(items.to_vec()),
+        Value::Object(fields) => Ok(fields
+            .into_iter()
+            .map(|(key, _)| Value::String(key))
+            .collect()),
+        Value::String(s) => Ok(s.chars().map(|c| Value::String(c.to_string())).collect()),
+        other => Err(InfraError::TypeError {
+            expected: "array, tuple, object or string".to_string(),
+            found: other.type_name().to_string(),
+            context: Some("for loop iterable".to_string()),
+            line: None,
+            column: None,
+            hint: Some("Use range(start, end) to loop over numbers".to_string()),
+        }),
+    }
+}
+
+/// The value of `await promise`, once timers have settled it if they can
+fn awaited(promise: Value) -> Result<Value> {
+    async_mod::mark_observed(&promise);
+    let promise = async_mod::wait_for(&promise)?;
+    match promise {
+        Value::Promise {
+            resolved: true,
+            value,
+            ..
+        } => {
+            // Promise is resolved, return the value
+            value
+                .map(|boxed| *boxed)
+                .ok_or_else(|| InfraError::RuntimeError {
+                    message: "Promise resolved but has no value".to_string(),
+                    line: None,
+                    column: None,
+                    stack_trace: vec![],
+                    source_code: None,
+                })
+        }
+        Value::Promise {
+            rejected: true,
+            error,
+            ..
+        } => Err(InfraError::AsyncError {
+            message: error.unwrap_or_else(|| "Promise rejected".to_string()),
+            operation: Some("await".to_string()),
+        }),
+        Value::Promise { .. } => {
+            // No timer is left, and every other async operation
+            // settles its promise before returning, so nothing
+            // could settle this one; waiting would never end
+            let origin = async_mod::site(&promise)
+                .map_or_else(|| "a promise".to_string(), |site| site.describe());
+            Err(InfraError::AsyncError {
+                message: format!(
+                    "the promise from {} never settles, so awaiting it would wait forever",
+                    origin
+                ),
+                operation: Some("await".to_string()),
+            })
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "promise".to_string(),
+            found: promise.type_name().to_string(),
+            context: Some("await expression".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// `values()` and `parse(name)`, generated for every enum
+fn enum_helper(definition: &Rc<EnumDef>, helper: &str, args: &[Value]) -> Result<Value> {
+    let function_name = format!("{}.{}", definition.name, helper);
+    let expected = match helper {
+        "values" => 0,
+        "parse" => 1,
+        _ => {
+            return Err(InfraError::UndefinedFunction {
+                name: function_name,
+                line: None,
+                column: None,
+                suggestion: None,
+                available_functions: Some(vec!["values".to_string(), "parse".to_string()]),
+            })
+        }
+    };
+    if args.len() != expected {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected,
+            found: args.len(),
+            function_name: Some(function_name),
+            line: None,
+        });
+    }
+
+    if helper == "values" {
+        let members = (0..definition.members.len())
+            .map(|index| Value::EnumMember {
+                definition: definition.clone(),
+                index,
+            })
+            .collect();
+        return Ok(Value::Array(members));
+    }
+
+    let Value::String(name) = &args[0] else {
+        return Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        });
+    };
+    definition.member(name).ok_or_else(|| {
+        let suggestion = closest_name(name, &definition.members)
+            .map(|member| format!("; did you mean '{}'?", member))
+            .unwrap_or_default();
+        InfraError::RuntimeError {
+            message: format!(
+                "{}: '{}' is not a member of {} (expected one of {}){}",
+                function_name,
+                name,
+                definition.name,
+                definition.members.join(", "),
+                suggestion
+            ),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }
+    })
+}
+
+/// The candidate closest to `name` by edit distance, if any is close enough
+/// to be a likely typo
+pub(crate) fn closest_name(name: &str, candidates: &[String]) -> Option<String> {
+    let distance = |a: &str, b: &str| {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut previous = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = previous + usize::from(ca != *cb);
+                previous = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+            }
+        }
+        row[b.len()]
+    };
+
+    let length = name.chars().count();
+    let limit = length.div_ceil(3);
+    candidates
+        .iter()
+        // Names whose lengths differ by more than the limit cannot be close enough
+        .filter(|candidate| candidate.chars().count().abs_diff(length) <= limit)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Frames beyond this many repeats of the same call are summed up in one line
+const REPEATED_FRAMES_SHOWN: usize = 3;
+
+/// Stack trace lines for `call_stack`, innermost call first. A function
+/// calling itself from the same line again and again shows the first few
+/// calls and then how many more there were.
+fn render_call_stack(call_stack: &[(String, Option<usize>)]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut frames = call_stack.iter().rev().peekable();
+    while let Some(frame) = frames.next() {
+        let mut repeats = 0;
+        while frames.peek() == Some(&frame) {
+            frames.next();
+            repeats += 1;
+        }
+        let (name, line) = frame;
+        let text = match line {
+            Some(line) => format!("{} (called at line {})", name, line),
+            None => name.clone(),
+        };
+        let shown = (repeats + 1).min(REPEATED_FRAMES_SHOWN);
+        lines.extend(std::iter::repeat_n(text, shown));
+        if repeats + 1 > shown {
+            lines.push(format!("... {} more", repeats + 1 - shown));
+        }
+    }
+    lines
+}
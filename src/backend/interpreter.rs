@@ -1,5 +1,9 @@
-use crate::backend::{Environment, Evaluator, ModuleSystem};
+use crate::backend::evaluator::{located, loop_items};
+use crate::backend::literal_check::{self, Severity};
+use crate::backend::{DependencyLog, Environment, Evaluator, ModuleSystem};
 use crate::core::{ast::*, Result, Value};
+use crate::frontend::printer;
+use crate::stdlib::{async_mod, cancel, output, schedule, StandardLibrary};
 use std::path::Path;
 
 pub struct Interpreter {
@@ -10,8 +14,18 @@ pub struct Interpreter {
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_stdlib(StandardLibrary::new())
+    }
+
+    /// An interpreter calling native functions from `stdlib`; cloning a
+    /// shared library for each interpreter is cheap
+    pub fn with_stdlib(stdlib: StandardLibrary) -> Self {
+        // Promise bookkeeping belongs to one interpreter run; the display
+        // precision, scheduled jobs and waiting timed calls live in its evaluator
+        async_mod::reset_promises();
+
         Self {
-            evaluator: Evaluator::new(),
+            evaluator: Evaluator::with_stdlib(Environment::new(), stdlib),
             module_system: ModuleSystem::new(),
             current_file_path: None,
         }
@@ -26,13 +40,33 @@ impl Interpreter {
     }
 
     pub fn execute(&mut self, program: &Program) -> Result<()> {
-        for stmt in &program.statements {
-            self.execute_statement(stmt)?;
+        // Literals that do not match their annotation are reported before
+        // anything runs
+        let strict = literal_check::is_strict();
+        for (index, issue) in literal_check::check_statements(&program.statements) {
+            let line = program.line_of(index);
+            match issue.severity(strict) {
+                Severity::Error => return Err(issue.to_error(line)),
+                Severity::Warning => match line {
+                    Some(line) => eprintln!("Warning: line {}: {}", line, issue.message()),
+                    None => eprintln!("Warning: {}", issue.message()),
+                },
+            }
+        }
+
+        for (index, stmt) in program.statements.iter().enumerate() {
+            // Promises created by this statement are attributed to its line
+            async_mod::set_creation_line(program.line_of(index));
+            self.execute_statement(stmt)
+                .map_err(|error| error.outside_loop(program.line_of(index)))?;
         }
+        async_mod::set_creation_line(None);
         Ok(())
     }
 
     pub fn execute_statement(&mut self, stmt: &Stmt) -> Result<()> {
+        cancel::check(None)?;
+
         match stmt {
             Stmt::Expression(expr) => {
                 self.evaluator.evaluate_expression(expr)?;
@@ -69,16 +103,32 @@ impl Interpreter {
 
                 Ok(())
             }
+            Stmt::LetTuple {
+                pattern,
+                type_annotation,
+                value,
+                span,
+            } => {
+                let val = self.evaluator.evaluate_expression(value)?;
+                if let Some(expected_type) = type_annotation {
+                    self.check_type_compatibility_detailed(
+                        &val,
+                        expected_type,
+                        &format!(
+                            "destructured value of let {}",
+                            printer::format_pattern(pattern)
+                        ),
+                    )?;
+                }
+                located(self.evaluator.destructure("let", pattern, val), *span)
+            }
             Stmt::Print(expr) => {
                 let value = self.evaluator.evaluate_expression(expr)?;
-                println!("{}", value);
+                output::print_value(value.display(self.evaluator.display_precision()));
                 Ok(())
             }
             Stmt::Block(statements) => {
-                // Create new scope
-                let old_env = self.evaluator.get_environment().clone();
-                let new_env = Environment::with_parent(old_env);
-                self.evaluator = Evaluator::with_environment(new_env);
+                self.evaluator.get_environment_mut().push_scope();
 
                 let mut result = Ok(());
                 for statement in statements {
@@ -88,10 +138,7 @@ impl Interpreter {
                     }
                 }
 
-                // Restore parent scope
-                if let Some(parent) = self.evaluator.get_environment().parent.as_ref() {
-                    self.evaluator = Evaluator::with_environment((**parent).clone());
-                }
+                self.evaluator.get_environment_mut().pop_scope();
 
                 result
             }
@@ -115,7 +162,11 @@ impl Interpreter {
                     if !condition_value.is_truthy() {
                         break;
                     }
-                    self.execute_statement(body)?;
+                    match self.execute_statement(body) {
+                        Err(crate::core::InfraError::Break { .. }) => break,
+                        Ok(()) | Err(crate::core::InfraError::Continue { .. }) => {}
+                        Err(error) => return Err(error),
+                    }
                 }
                 Ok(())
             }
@@ -144,25 +195,50 @@ impl Interpreter {
                     }
                 };
 
-                // Save old variable value if it exists
-                let old_var_value = self.evaluator.get_environment().get(var).ok();
-
+                // The loop variable lives in a scope of its own, so one of the
+                // same name outside the loop is untouched
+                self.evaluator.get_environment_mut().push_scope();
+                let mut result = Ok(());
                 for i in start_num..end_num {
                     self.evaluator
                         .define_variable(var.clone(), crate::core::Value::Number(i as f64));
-                    self.execute_statement(body)?;
+                    match self.execute_statement(body) {
+                        Err(crate::core::InfraError::Break { .. }) => break,
+                        Ok(()) | Err(crate::core::InfraError::Continue { .. }) => {}
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
                 }
+                self.evaluator.get_environment_mut().pop_scope();
 
-                // Restore old variable value or remove it
-                if let Some(old_value) = old_var_value {
-                    self.evaluator.define_variable(var.clone(), old_value);
-                } else {
-                    // Variable didn't exist before, so we could remove it
-                    // But our current Environment doesn't support removal
-                    // This is a limitation we could address in the future
+                result
+            }
+            Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                span,
+            } => {
+                let iterable = self.evaluator.evaluate_expression(iterable)?;
+                let items = located(loop_items(iterable), *span)?;
+                self.evaluator.get_environment_mut().push_scope();
+                let mut result = Ok(());
+                for item in items {
+                    let bound = located(self.evaluator.bind_loop_item(pattern, item), *span);
+                    match bound.and_then(|()| self.execute_statement(body)) {
+                        Err(crate::core::InfraError::Break { .. }) => break,
+                        Ok(()) | Err(crate::core::InfraError::Continue { .. }) => {}
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
                 }
+                self.evaluator.get_environment_mut().pop_scope();
 
-                Ok(())
+                result
             }
             Stmt::Return(value) => {
                 let return_value = if let Some(expr) = value {
@@ -172,32 +248,42 @@ impl Interpreter {
                 };
                 Err(crate::core::InfraError::ReturnValue(return_value))
             }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {
+                self.evaluator.execute_function_body(stmt)
+            }
             Stmt::Function {
                 name,
                 params,
                 param_types,
+                optional_params,
                 return_type,
                 body,
-                ..
+                decorators,
             } => {
                 let function_value = crate::core::Value::Function {
                     name: name.clone(),
                     params: params.clone(),
                     param_types: param_types.clone(),
+                    optional_params: optional_params.clone(),
                     return_type: return_type.clone(),
                     body: body.clone(),
+                    captured: self.evaluator.get_environment().capture(),
+                    is_async: false,
                 };
-                self.evaluator.define_variable(name.clone(), function_value);
+                // Defined before decorating, so decorators and the function
+                // itself see the name; then rebound to the decorated value
+                self.evaluator
+                    .define_variable(name.clone(), function_value.clone());
+                if !decorators.is_empty() {
+                    let decorated = self
+                        .evaluator
+                        .apply_decorators(function_value, decorators)?;
+                    self.evaluator.define_variable(name.clone(), decorated);
+                }
                 Ok(())
             }
-            Stmt::Assignment { target, value } => {
-                // Delegate to the evaluator's assignment handling
-                let temp_stmt = Stmt::Assignment {
-                    target: target.clone(),
-                    value: value.clone(),
-                };
-                self.evaluator.execute_function_body(&temp_stmt)
-            }
+            // Delegate to the evaluator's assignment handling
+            Stmt::Assignment { .. } => self.evaluator.execute_function_body(stmt),
             Stmt::Try {
                 try_block,
                 catch_var,
@@ -207,26 +293,9 @@ impl Interpreter {
                 match self.execute_statement(try_block) {
                     Ok(_) => Ok(()), // Success, no error caught
                     Err(error) => {
-                        // Check if this is an exception that can be caught
-                        let can_catch = matches!(
-                            error,
-                            crate::core::InfraError::Exception { .. }
-                                | crate::core::InfraError::RuntimeError { .. }
-                                | crate::core::InfraError::TypeError { .. }
-                                | crate::core::InfraError::DivisionByZero { .. }
-                                | crate::core::InfraError::IndexOutOfBounds { .. }
-                                | crate::core::InfraError::PropertyNotFound { .. }
-                                | crate::core::InfraError::UndefinedVariable { .. }
-                                | crate::core::InfraError::ArgumentCountMismatch { .. }
-                        );
-
-                        if can_catch {
-                            // Store the error message in the catch variable
-                            let error_message = error.to_string();
-                            self.evaluator.define_variable(
-                                catch_var.clone(),
-                                crate::core::Value::String(error_message),
-                            );
+                        if error.is_catchable() {
+                            self.evaluator
+                                .define_variable(catch_var.clone(), error.into_value());
                             // Execute the catch block
                             self.execute_statement(catch_block)
                         } else {
@@ -287,7 +356,7 @@ impl Interpreter {
                                 .define_variable(import_name.clone(), default_export.clone());
                         } else {
                             // If no default export, create an object with all exports
-                            let mut exports_obj = std::collections::HashMap::new();
+                            let mut exports_obj = crate::core::ObjectMap::new();
                             for (export_name, export_value) in &module.exports {
                                 exports_obj.insert(export_name.clone(), export_value.clone());
                             }
@@ -309,6 +378,7 @@ impl Interpreter {
                 name,
                 params,
                 param_types,
+                optional_params,
                 return_type,
                 body,
                 ..
@@ -318,44 +388,35 @@ impl Interpreter {
                     name: name.clone(),
                     params: params.clone(),
                     param_types: param_types.clone(),
+                    optional_params: optional_params.clone(),
                     return_type: return_type.clone(),
                     body: body.clone(),
+                    captured: self.evaluator.get_environment().capture(),
+                    is_async: true,
                 };
                 self.evaluator.define_variable(name.clone(), function_value);
                 Ok(())
             }
+            Stmt::Enum { name, members } => {
+                self.evaluator.define_enum(name, members);
+                Ok(())
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+            } => match self.evaluator.select_match_arm(subject, arms, default)? {
+                Some(body) => self.execute_statement(body),
+                None => Ok(()),
+            },
             Stmt::Class {
                 name,
                 superclass,
+                fields,
                 methods,
-            } => {
-                // Create a class object with methods
-                let mut class_obj = std::collections::HashMap::new();
-
-                // Store superclass if any
-                if let Some(parent) = superclass {
-                    class_obj.insert(
-                        "__superclass__".to_string(),
-                        crate::core::Value::String(parent.clone()),
-                    );
-                }
-
-                // Store methods as function values
-                for method in methods {
-                    let method_value = crate::core::Value::Function {
-                        name: method.name.clone(),
-                        params: method.params.clone(),
-                        param_types: method.param_types.clone(),
-                        return_type: method.return_type.clone(),
-                        body: method.body.clone(),
-                    };
-                    class_obj.insert(method.name.clone(), method_value);
-                }
-
-                self.evaluator
-                    .define_variable(name.clone(), crate::core::Value::Object(class_obj));
-                Ok(())
-            }
+            } => self
+                .evaluator
+                .define_class(name, superclass.as_deref(), fields, methods),
         }
     }
 
@@ -364,8 +425,12 @@ impl Interpreter {
         match (value, expected_type) {
             (_, Type::Any) => true, // Any type accepts any value
             (Value::Number(_), Type::Number) => true,
+            (Value::BigInt(_), Type::BigInt) => true,
             (Value::String(_), Type::String) => true,
             (Value::Boolean(_), Type::Boolean) => true,
+            (Value::Bytes(_), Type::Bytes) => true,
+            (Value::EnumMember { definition, .. }, Type::Named(name)) => definition.name == *name,
+            (Value::Instance(instance), Type::Named(name)) => instance.class.is_a(name),
             (Value::Array(arr), Type::Array(element_type)) => {
                 // Check if all array elements match the expected element type
                 arr.iter()
@@ -379,6 +444,13 @@ impl Interpreter {
                         .unwrap_or(false)
                 })
             }
+            (Value::Tuple(items), Type::Tuple(types)) => {
+                items.len() == types.len()
+                    && items
+                        .iter()
+                        .zip(types)
+                        .all(|(item, item_type)| self.check_type_compatibility(item, item_type))
+            }
             (value, Type::Union(types)) => {
                 // Value must match at least one type in the union
                 types
@@ -394,8 +466,10 @@ impl Interpreter {
     fn type_to_string(&self, type_annotation: &Type) -> String {
         match type_annotation {
             Type::Number => "number".to_string(),
+            Type::BigInt => "bigint".to_string(),
             Type::String => "string".to_string(),
             Type::Boolean => "boolean".to_string(),
+            Type::Bytes => "bytes".to_string(),
             Type::Array(element_type) => format!("[{}]", self.type_to_string(element_type)),
             Type::Object(props) => {
                 let prop_strings: Vec<String> = props
@@ -421,21 +495,77 @@ impl Interpreter {
                     types.iter().map(|t| self.type_to_string(t)).collect();
                 type_strings.join(" | ")
             }
+            Type::Tuple(_) => printer::format_type(type_annotation),
+            Type::Named(name) => name.clone(),
             Type::Any => "any".to_string(),
             Type::Never => "never".to_string(),
         }
     }
 
+    /// Evaluate a single expression in the global scope
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        self.evaluator.evaluate_expression(expr)
+    }
+
+    /// Bind `name` in the global scope, replacing any earlier binding
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.evaluator.define_variable(name.to_string(), value);
+    }
+
     pub fn get_environment(&self) -> &Environment {
         self.evaluator.get_environment()
     }
 
+    /// Decimal places the script asked printed numbers to be rounded to
+    pub fn display_precision(&self) -> Option<usize> {
+        self.evaluator.display_precision()
+    }
+
+    #[allow(dead_code)]
+    pub fn stdlib(&self) -> &StandardLibrary {
+        self.evaluator.stdlib()
+    }
+
+    #[allow(dead_code)]
+    pub fn stdlib_mut(&mut self) -> &mut StandardLibrary {
+        self.evaluator.stdlib_mut()
+    }
+
     pub fn reset(&mut self) {
-        self.evaluator = Evaluator::new();
+        async_mod::reset_promises();
+        // Functions the host registered outlive the script's state
+        let stdlib = self.evaluator.stdlib().clone();
+        self.evaluator = Evaluator::with_stdlib(Environment::new(), stdlib);
         self.module_system = ModuleSystem::new();
         self.current_file_path = None;
     }
 
+    /// Run scheduled jobs and debounced or throttled calls as they come due
+    /// until none are left, or until the clock reaches `until` when given
+    pub fn run_schedules(&mut self, until: Option<u64>) -> Result<()> {
+        while let Some(due) = self.evaluator.next_due() {
+            if until.is_some_and(|until| due > until) {
+                break;
+            }
+            schedule::wait_until(due)?;
+            self.evaluator.run_due_timers()?;
+            self.evaluator.run_pending_schedules()?;
+        }
+        if let Some(until) = until {
+            schedule::wait_until(until)?;
+        }
+        Ok(())
+    }
+
+    /// Record the module files this interpreter loads from now on
+    pub fn track_module_dependencies(&mut self) {
+        self.module_system.track_dependencies();
+    }
+
+    pub fn module_dependencies(&self) -> Option<&DependencyLog> {
+        self.module_system.dependencies()
+    }
+
     pub fn set_current_file(&mut self, file_path: std::path::PathBuf) {
         self.current_file_path = Some(file_path);
     }
@@ -454,8 +584,21 @@ impl Interpreter {
         match (value, expected_type) {
             (_, Type::Any) => Ok(()), // Any type accepts any value
             (Value::Number(_), Type::Number) => Ok(()),
+            (Value::BigInt(_), Type::BigInt) => Ok(()),
             (Value::String(_), Type::String) => Ok(()),
             (Value::Boolean(_), Type::Boolean) => Ok(()),
+            (Value::Bytes(_), Type::Bytes) => Ok(()),
+            (Value::EnumMember { definition, .. }, Type::Named(name))
+                if definition.name == *name =>
+            {
+                Ok(())
+            }
+            (Value::Instance(instance), Type::Named(name)) if instance.class.is_a(name) => Ok(()),
+            (Value::Tuple(_), Type::Tuple(_))
+                if self.check_type_compatibility(value, expected_type) =>
+            {
+                Ok(())
+            }
             (Value::Array(arr), Type::Array(element_type)) => {
                 // Check each array element with detailed position information
                 for (index, val) in arr.iter().enumerate() {
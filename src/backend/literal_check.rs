@@ -0,0 +1,357 @@
+//! Object and array literals checked against the type annotation next to
+//! them, before anything runs: `let cfg: {host: string, port: number} =
+//! {host: "db", prot: 5432}` is reported without evaluating the program.
+//!
+//! Only what can be read off the literal is checked. A field whose value is
+//! computed, or whose annotation is a union of object types, is left to the
+//! runtime check when the value is assigned.
+
+use crate::backend::closest_name;
+use crate::core::ast::*;
+use crate::core::{InfraError, Value};
+use std::cell::Cell;
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Report unknown fields as errors rather than warnings
+pub fn set_strict(enabled: bool) {
+    STRICT.with(|strict| strict.set(enabled));
+}
+
+pub fn is_strict() -> bool {
+    STRICT.with(Cell::get)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeIssueKind {
+    /// A field of the annotation the literal leaves out
+    MissingField {
+        expected: String,
+    },
+    /// A field the annotation does not have, with the missing field it is
+    /// probably a typo of
+    UnknownField {
+        suggestion: Option<String>,
+    },
+    Mismatch {
+        expected: String,
+        found: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeIssue {
+    /// Where in the literal, starting from the variable: `cfg.server.port`,
+    /// `hosts[2]`
+    pub path: Vec<PathSegment>,
+    pub kind: ShapeIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+impl ShapeIssue {
+    /// An unknown field is only a warning unless it is a typo or checking is
+    /// strict; a typo leaves the field it was meant to be missing
+    pub fn severity(&self, strict: bool) -> Severity {
+        match &self.kind {
+            ShapeIssueKind::UnknownField { suggestion: None } if !strict => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn path_string(&self) -> String {
+        let mut out = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Field(name) if out.is_empty() => out.push_str(name),
+                PathSegment::Field(name) => {
+                    out.push('.');
+                    out.push_str(name);
+                }
+                PathSegment::Index(index) => out.push_str(&format!("[{}]", index)),
+            }
+        }
+        out
+    }
+
+    pub fn message(&self) -> String {
+        let path = self.path_string();
+        match &self.kind {
+            ShapeIssueKind::MissingField { expected } => {
+                format!("missing field {} ({})", path, expected)
+            }
+            ShapeIssueKind::UnknownField {
+                suggestion: Some(suggestion),
+            } => format!("unknown field {}; did you mean '{}'?", path, suggestion),
+            ShapeIssueKind::UnknownField { suggestion: None } => {
+                format!("unknown field {}", path)
+            }
+            ShapeIssueKind::Mismatch { expected, found } => {
+                format!("{} expects {}, found {}", path, expected, found)
+            }
+        }
+    }
+
+    pub fn to_error(&self, line: Option<usize>) -> InfraError {
+        let path = self.path_string();
+        let (expected, found, hint) = match &self.kind {
+            ShapeIssueKind::MissingField { expected } => (
+                format!("field {} of type {}", path, expected),
+                "no such field in the literal".to_string(),
+                None,
+            ),
+            ShapeIssueKind::UnknownField { suggestion } => (
+                "only the fields of the annotation".to_string(),
+                format!("unknown field {}", path),
+                suggestion
+                    .as_ref()
+                    .map(|suggestion| format!("Did you mean '{}'?", suggestion)),
+            ),
+            ShapeIssueKind::Mismatch { expected, found } => {
+                (format!("{} for {}", expected, path), found.clone(), None)
+            }
+        };
+        InfraError::TypeError {
+            expected,
+            found,
+            context: Some(format!("the literal assigned to '{}'", root(&self.path))),
+            line,
+            column: None,
+            hint,
+        }
+    }
+}
+
+fn root(path: &[PathSegment]) -> &str {
+    match path.first() {
+        Some(PathSegment::Field(name)) => name,
+        _ => "",
+    }
+}
+
+/// Check the literal `value` assigned to `name` against `annotation`.
+/// Nothing is reported unless the annotation is an object or array type and
+/// the value a literal of one.
+pub fn check_literal(name: &str, annotation: &Type, value: &Expr) -> Vec<ShapeIssue> {
+    let mut issues = Vec::new();
+    if matches!(annotation, Type::Object(_) | Type::Array(_))
+        && matches!(value, Expr::Object(_) | Expr::Array(_))
+    {
+        let mut path = vec![PathSegment::Field(name.to_string())];
+        check_value(&mut path, annotation, value, &mut issues);
+    }
+    issues
+}
+
+fn check_value(
+    path: &mut Vec<PathSegment>,
+    expected: &Type,
+    value: &Expr,
+    issues: &mut Vec<ShapeIssue>,
+) {
+    let found = match value {
+        Expr::Object(fields) => match expected {
+            Type::Object(expected_fields) => {
+                return check_object(path, expected_fields, fields, issues)
+            }
+            Type::Any | Type::Union(_) => return,
+            _ => "object".to_string(),
+        },
+        Expr::Array(elements) => match expected {
+            Type::Array(element_type) => {
+                for (index, element) in elements.iter().enumerate() {
+                    path.push(PathSegment::Index(index));
+                    check_value(path, element_type, element, issues);
+                    path.pop();
+                }
+                return;
+            }
+            Type::Any | Type::Union(_) => return,
+            _ => "array".to_string(),
+        },
+        Expr::Literal(literal) if !fits(literal, expected) => literal.type_name().to_string(),
+        // Computed values are checked when they are assigned
+        _ => return,
+    };
+
+    issues.push(ShapeIssue {
+        path: path.clone(),
+        kind: ShapeIssueKind::Mismatch {
+            expected: type_name(expected),
+            found,
+        },
+    });
+}
+
+fn check_object(
+    path: &mut Vec<PathSegment>,
+    expected: &[(String, Type)],
+    fields: &[(String, Expr)],
+    issues: &mut Vec<ShapeIssue>,
+) {
+    let mut missing: Vec<String> = expected
+        .iter()
+        .map(|(name, _)| name.clone())
+        .filter(|name| !fields.iter().any(|(key, _)| key == name))
+        .collect();
+
+    for (key, value) in fields {
+        path.push(PathSegment::Field(key.clone()));
+        match expected.iter().find(|(name, _)| name == key) {
+            Some((_, field_type)) => check_value(path, field_type, value, issues),
+            None => {
+                // A typo accounts for the field it was meant to be
+                let suggestion = closest_name(key, &missing);
+                missing.retain(|name| Some(name) != suggestion.as_ref());
+                issues.push(ShapeIssue {
+                    path: path.clone(),
+                    kind: ShapeIssueKind::UnknownField { suggestion },
+                });
+            }
+        }
+        path.pop();
+    }
+
+    for (name, field_type) in expected {
+        if missing.contains(name) {
+            let mut field_path = path.clone();
+            field_path.push(PathSegment::Field(name.clone()));
+            issues.push(ShapeIssue {
+                path: field_path,
+                kind: ShapeIssueKind::MissingField {
+                    expected: type_name(field_type),
+                },
+            });
+        }
+    }
+}
+
+fn fits(value: &Value, expected: &Type) -> bool {
+    match (value, expected) {
+        (_, Type::Any) => true,
+        (_, Type::Union(types)) => types.iter().any(|t| fits(value, t)),
+        (Value::Number(_), Type::Number) => true,
+        (Value::BigInt(_), Type::BigInt) => true,
+        (Value::String(_), Type::String) => true,
+        (Value::Boolean(_), Type::Boolean) => true,
+        _ => false,
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Number => "number".to_string(),
+        Type::BigInt => "bigint".to_string(),
+        Type::String => "string".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Bytes => "bytes".to_string(),
+        Type::Array(element) => format!("[{}]", type_name(element)),
+        Type::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, t)| format!("{}: {}", name, type_name(t)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Type::Function {
+            params,
+            return_type,
+        } => {
+            let params: Vec<String> = params.iter().map(type_name).collect();
+            format!("({}) -> {}", params.join(", "), type_name(return_type))
+        }
+        Type::Union(types) => types.iter().map(type_name).collect::<Vec<_>>().join(" | "),
+        Type::Tuple(_) => crate::frontend::printer::format_type(ty),
+        Type::Named(name) => name.clone(),
+        Type::Any => "any".to_string(),
+        Type::Never => "never".to_string(),
+    }
+}
+
+/// Every annotated `let` with a literal initializer in `statements`,
+/// including those in nested blocks and function bodies, with the index of
+/// the top-level statement it belongs to
+pub fn check_statements(statements: &[Stmt]) -> Vec<(usize, ShapeIssue)> {
+    let mut issues = Vec::new();
+    for (index, stmt) in statements.iter().enumerate() {
+        let mut found = Vec::new();
+        visit(stmt, &mut found);
+        issues.extend(found.into_iter().map(|issue| (index, issue)));
+    }
+    issues
+}
+
+fn visit(stmt: &Stmt, issues: &mut Vec<ShapeIssue>) {
+    match stmt {
+        Stmt::Let {
+            name,
+            type_annotation: Some(annotation),
+            value,
+        }
+        | Stmt::Export {
+            item:
+                ExportItem::Variable {
+                    name,
+                    type_annotation: Some(annotation),
+                    value,
+                },
+        } => issues.extend(check_literal(name, annotation, value)),
+        Stmt::Export {
+            item: ExportItem::Function { body, .. },
+        }
+        | Stmt::Function { body, .. }
+        | Stmt::AsyncFunction { body, .. } => visit(body, issues),
+        Stmt::If {
+            then_stmt,
+            else_stmt,
+            ..
+        } => {
+            visit(then_stmt, issues);
+            if let Some(else_stmt) = else_stmt {
+                visit(else_stmt, issues);
+            }
+        }
+        Stmt::While { body, .. } | Stmt::For { body, .. } | Stmt::ForIn { body, .. } => {
+            visit(body, issues)
+        }
+        Stmt::Block(statements) => {
+            for statement in statements {
+                visit(statement, issues);
+            }
+        }
+        Stmt::Class { methods, .. } => {
+            for method in methods {
+                visit(&method.body, issues);
+            }
+        }
+        Stmt::Try {
+            try_block,
+            catch_block,
+            ..
+        } => {
+            visit(try_block, issues);
+            visit(catch_block, issues);
+        }
+        Stmt::Match { arms, default, .. } => {
+            for arm in arms {
+                visit(&arm.body, issues);
+            }
+            if let Some(default) = default {
+                visit(default, issues);
+            }
+        }
+        _ => {}
+    }
+}
@@ -2,7 +2,9 @@ pub mod bytecode;
 pub mod environment;
 pub mod evaluator;
 pub mod interpreter;
+pub mod literal_check;
 pub mod module_system;
+pub mod optimizer;
 pub mod vm;
 
 #[cfg(test)]
@@ -1,7 +1,8 @@
 use crate::core::ast::{ExportItem, Program, Stmt};
 use crate::core::{InfraError, Result, Value};
 use crate::frontend::{Lexer, Parser};
-use std::collections::HashMap;
+use crate::stdlib::prelude;
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 
 /// Represents a loaded module with its exports
@@ -11,24 +12,53 @@ pub struct Module {
     pub exports: HashMap<String, Value>,
 }
 
+/// Files a program depended on through imports, recorded while tracking is on
+#[derive(Debug, Clone, Default)]
+pub struct DependencyLog {
+    pub files: BTreeSet<PathBuf>,
+    /// False when an import could not be followed, so the set may be missing files
+    pub complete: bool,
+}
+
 /// Module loader and cache
 pub struct ModuleSystem {
     loaded_modules: HashMap<String, Module>,
     search_paths: Vec<PathBuf>,
+    dependencies: Option<DependencyLog>,
 }
 
 impl ModuleSystem {
     pub fn new() -> Self {
+        let mut search_paths = vec![
+            PathBuf::from("."),
+            PathBuf::from("./lib"),
+            PathBuf::from("./modules"),
+        ];
+        // Extra directories for bare module names, searched last
+        if let Some(infra_path) = std::env::var_os("INFRA_PATH") {
+            search_paths.extend(std::env::split_paths(&infra_path));
+        }
+
         Self {
             loaded_modules: HashMap::new(),
-            search_paths: vec![
-                PathBuf::from("."),
-                PathBuf::from("./lib"),
-                PathBuf::from("./modules"),
-            ],
+            search_paths,
+            dependencies: None,
         }
     }
 
+    /// Start recording every module file that gets loaded, including the
+    /// modules those import in turn
+    pub fn track_dependencies(&mut self) {
+        self.dependencies = Some(DependencyLog {
+            files: BTreeSet::new(),
+            complete: true,
+        });
+    }
+
+    pub fn dependencies(&self) -> Option<&DependencyLog> {
+        self.dependencies.as_ref()
+    }
+
     pub fn add_search_path(&mut self, path: PathBuf) {
         self.search_paths.push(path);
     }
@@ -40,8 +70,28 @@ impl ModuleSystem {
             return Ok(module.clone());
         }
 
+        // The prelude is compiled in rather than read from a file
+        if module_path == prelude::MODULE_PATH {
+            let module = Module {
+                path: PathBuf::from(module_path),
+                exports: prelude::exports()?,
+            };
+            self.loaded_modules
+                .insert(module_path.to_string(), module.clone());
+            return Ok(module);
+        }
+
         // Resolve the module path
-        let resolved_path = self.resolve_module_path(module_path, current_dir)?;
+        let resolved_path = match self.resolve_module_path(module_path, current_dir) {
+            Ok(path) => path,
+            Err(err) => {
+                if let Some(log) = &mut self.dependencies {
+                    log.complete = false;
+                }
+                return Err(err);
+            }
+        };
+        self.record_dependency(&resolved_path);
 
         // Read the module file
         let source =
@@ -55,9 +105,17 @@ impl ModuleSystem {
 
         // Parse the module
         let mut lexer = Lexer::new(&source);
-        let tokens = lexer.tokenize()?;
-        let mut parser = Parser::new(tokens);
-        let program = parser.parse()?;
+        let tokens = lexer
+            .tokenize()
+            .map_err(|error| error.with_source(&source))?;
+        // Lines in the module would be read against the importing script,
+        // so the module's errors are placed where the script calls it
+        let mut parser = Parser::new(tokens).without_positions();
+        let program = parser.parse().map_err(|error| error.with_source(&source))?;
+
+        if self.dependencies.is_some() {
+            self.record_nested_imports(&program, &resolved_path);
+        }
 
         // Extract exports from the parsed program
         let exports = self.extract_exports_from_program(&program)?;
@@ -74,8 +132,54 @@ impl ModuleSystem {
         Ok(module)
     }
 
+    /// Add a loaded file to the dependency log; returns false if it was
+    /// already recorded
+    fn record_dependency(&mut self, path: &Path) -> bool {
+        match &mut self.dependencies {
+            Some(log) => {
+                let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                log.files.insert(path)
+            }
+            None => false,
+        }
+    }
+
+    /// Module bodies are not executed, so their own imports are followed
+    /// statically. Anything that cannot be followed marks the log incomplete.
+    fn record_nested_imports(&mut self, program: &Program, module_file: &Path) {
+        let dir = module_file.parent().unwrap_or_else(|| Path::new("."));
+        for stmt in &program.statements {
+            let Stmt::Import { module_path, .. } = stmt else {
+                continue;
+            };
+
+            let nested = self
+                .resolve_module_path(module_path, dir)
+                .ok()
+                .and_then(|path| {
+                    let source = std::fs::read_to_string(&path).ok()?;
+                    let tokens = Lexer::new(&source).tokenize().ok()?;
+                    let program = Parser::new(tokens).parse().ok()?;
+                    Some((path, program))
+                });
+
+            match nested {
+                Some((path, program)) => {
+                    if self.record_dependency(&path) {
+                        self.record_nested_imports(&program, &path);
+                    }
+                }
+                None => {
+                    if let Some(log) = &mut self.dependencies {
+                        log.complete = false;
+                    }
+                }
+            }
+        }
+    }
+
     /// Resolve a module path to an actual file path
-    fn resolve_module_path(&self, module_path: &str, current_dir: &Path) -> Result<PathBuf> {
+    pub fn resolve_module_path(&self, module_path: &str, current_dir: &Path) -> Result<PathBuf> {
         // If it's a relative path starting with './', resolve relative to current file
         if module_path.starts_with("./") || module_path.starts_with("../") {
             let path = current_dir.join(module_path);
@@ -112,8 +216,15 @@ impl ModuleSystem {
     }
 
     /// Extract exports from a program without full execution
-    fn extract_exports_from_program(&self, program: &Program) -> Result<HashMap<String, Value>> {
+    pub fn extract_exports_from_program(
+        &self,
+        program: &Program,
+    ) -> Result<HashMap<String, Value>> {
         let mut exports = HashMap::new();
+        let mut scope = Self::module_scope(program);
+        // Exported functions close over the module's top-level bindings, so
+        // they can call its helpers and read its constants from anywhere
+        let captured = scope.get_environment().capture();
 
         for stmt in &program.statements {
             match stmt {
@@ -123,36 +234,32 @@ impl ModuleSystem {
                             name,
                             params,
                             param_types,
+                            optional_params,
                             return_type,
                             body,
-                            ..
+                            decorators,
                         } => {
                             // Create a proper function value
-                            let function_value = Value::Function {
+                            let mut function_value = Value::Function {
                                 name: name.clone(),
                                 params: params.clone(),
                                 param_types: param_types.clone(),
+                                optional_params: optional_params.clone(),
                                 return_type: return_type.clone(),
                                 body: body.clone(),
+                                captured: captured.clone(),
+                                is_async: false,
                             };
+                            if !decorators.is_empty() {
+                                function_value =
+                                    scope.apply_decorators(function_value, decorators)?;
+                            }
                             exports.insert(name.clone(), function_value);
                         }
-                        ExportItem::Variable { name, value, .. } => {
-                            // For exported variables, we need to evaluate them
-                            // For now, we'll create a temporary evaluator to evaluate the expression
-                            let temp_env = crate::backend::environment::Environment::new();
-                            let mut temp_evaluator =
-                                crate::backend::evaluator::Evaluator::with_environment(temp_env);
-
-                            match temp_evaluator.evaluate_expression(value) {
-                                Ok(val) => {
-                                    exports.insert(name.clone(), val);
-                                }
-                                Err(_) => {
-                                    // If evaluation fails, store as null for now
-                                    exports.insert(name.clone(), Value::Null);
-                                }
-                            }
+                        ExportItem::Variable { name, .. } => {
+                            // Evaluated with the module's other bindings
+                            let value = scope.get_environment().get(name).unwrap_or(Value::Null);
+                            exports.insert(name.clone(), value);
                         }
                     }
                 }
@@ -170,6 +277,92 @@ impl ModuleSystem {
         Ok(exports)
     }
 
+    /// An evaluator holding the module's top-level bindings without running
+    /// the module: its functions as they are before decoration, then its
+    /// variables, evaluated in order. A variable whose value fails to
+    /// evaluate is null.
+    fn module_scope(program: &Program) -> crate::backend::evaluator::Evaluator {
+        // The bindings go below an empty outermost scope, where functions
+        // capture them as they would a function's locals
+        let mut evaluator = crate::backend::evaluator::Evaluator::with_environment(
+            crate::backend::environment::Environment::with_parent(
+                crate::backend::environment::Environment::new(),
+            ),
+        );
+        let mut functions = Vec::new();
+        for stmt in &program.statements {
+            let (name, params, param_types, optional_params, return_type, body) = match stmt {
+                Stmt::Function {
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                    body,
+                    ..
+                }
+                | Stmt::Export {
+                    item:
+                        ExportItem::Function {
+                            name,
+                            params,
+                            param_types,
+                            optional_params,
+                            return_type,
+                            body,
+                            ..
+                        },
+                } => (
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                    body,
+                ),
+                _ => continue,
+            };
+            functions.push(name.as_str());
+            evaluator.define_variable(
+                name.clone(),
+                Value::Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    param_types: param_types.clone(),
+                    optional_params: optional_params.clone(),
+                    return_type: return_type.clone(),
+                    body: body.clone(),
+                    captured: Default::default(),
+                    is_async: false,
+                },
+            );
+        }
+        for stmt in &program.statements {
+            let (name, value) = match stmt {
+                Stmt::Let { name, value, .. }
+                | Stmt::Export {
+                    item: ExportItem::Variable { name, value, .. },
+                } => (name, value),
+                _ => continue,
+            };
+            let value = evaluator.evaluate_expression(value).unwrap_or(Value::Null);
+            evaluator.define_variable(name.clone(), value);
+        }
+
+        // With everything bound, the module's functions close over all of
+        // it, so a helper reads the module's constants wherever it is called
+        let captured = evaluator.get_environment().capture();
+        for (name, cell) in captured.iter() {
+            if !functions.contains(&name.as_str()) {
+                continue;
+            }
+            if let Value::Function { captured: own, .. } = &mut *cell.borrow_mut() {
+                *own = captured.clone();
+            }
+        }
+        evaluator
+    }
+
     /// Get a value from a loaded module
     pub fn get_module_export(&self, module_path: &str, export_name: &str) -> Option<Value> {
         self.loaded_modules
@@ -0,0 +1,190 @@
+//! Rewrites compiled chunks into shorter ones that do the same thing.
+//!
+//! Each pass marks instructions to drop and rewrites others in place, then
+//! [`compact`] removes the dropped ones and moves every jump target and
+//! function entry along with the code. Passes repeat until nothing changes,
+//! so `1 + 2 * 3` folds in two rounds.
+//!
+//! An instruction some jump or call lands on starts a new run of code: it
+//! may begin a rewritten sequence but never sit in the middle of one.
+
+use crate::backend::bytecode::{Chunk, OpCode};
+use crate::backend::vm;
+use crate::core::Value;
+use std::collections::HashSet;
+
+/// Fold constant arithmetic and comparisons, drop constants that are loaded
+/// only to be popped, and send jumps straight to where jump chains end
+pub fn optimize(mut chunk: Chunk) -> Chunk {
+    loop {
+        let mut removed = vec![false; chunk.code.len()];
+        let mut changed = fold_constants(&mut chunk, &mut removed);
+        changed |= remove_dead_loads(&mut chunk, &mut removed);
+        changed |= thread_jumps(&mut chunk, &mut removed);
+        if !changed {
+            return chunk;
+        }
+        compact(&mut chunk, &removed);
+    }
+}
+
+/// Offsets a jump or a call can land on
+fn targets(chunk: &Chunk) -> HashSet<usize> {
+    let jumps = chunk.code.iter().filter_map(|op| match op {
+        OpCode::Jump(target) | OpCode::JumpIfFalse(target) => Some(*target),
+        _ => None,
+    });
+    let entries = chunk
+        .constants
+        .iter()
+        .filter_map(|constant| match constant {
+            Value::CompiledFunction { entry, .. } => Some(*entry),
+            _ => None,
+        });
+    jumps.chain(entries).collect()
+}
+
+fn constant(chunk: &Chunk, op: OpCode) -> Option<Value> {
+    match op {
+        OpCode::LoadConst(index) => chunk.constants.get(index).cloned(),
+        _ => None,
+    }
+}
+
+/// Replace an operator on constants with its result. Operations that fail
+/// are left for the VM to report when it gets there.
+fn fold_constants(chunk: &mut Chunk, removed: &mut [bool]) -> bool {
+    let targets = targets(chunk);
+    let mut changed = false;
+    let mut i = 0;
+
+    while i + 1 < chunk.code.len() {
+        let Some(a) = constant(chunk, chunk.code[i]) else {
+            i += 1;
+            continue;
+        };
+
+        let op = chunk.code[i + 1];
+        if matches!(op, OpCode::Negate | OpCode::Not) && !targets.contains(&(i + 1)) {
+            if let Ok(result) = vm::unary(op, a) {
+                chunk.code[i] = OpCode::LoadConst(chunk.add_constant(result));
+                removed[i + 1] = true;
+                changed = true;
+                i += 2;
+                continue;
+            }
+        } else if let (Some(b), Some(&op)) = (constant(chunk, op), chunk.code.get(i + 2)) {
+            if op.is_binary() && !targets.contains(&(i + 1)) && !targets.contains(&(i + 2)) {
+                if let Ok(result) = vm::binary(op, a, b) {
+                    chunk.code[i] = OpCode::LoadConst(chunk.add_constant(result));
+                    removed[i + 1] = true;
+                    removed[i + 2] = true;
+                    changed = true;
+                    i += 3;
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+
+    changed
+}
+
+/// Drop a constant that is popped straight away, as an expression statement
+/// that folded to a constant leaves behind
+fn remove_dead_loads(chunk: &mut Chunk, removed: &mut [bool]) -> bool {
+    let targets = targets(chunk);
+    let mut changed = false;
+
+    for i in 0..chunk.code.len().saturating_sub(1) {
+        if removed[i] || removed[i + 1] || targets.contains(&(i + 1)) {
+            continue;
+        }
+        if let (OpCode::LoadConst(_), OpCode::Pop) = (chunk.code[i], chunk.code[i + 1]) {
+            removed[i] = true;
+            removed[i + 1] = true;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Point jumps that land on another jump at that jump's target, and drop
+/// jumps to the very next instruction
+fn thread_jumps(chunk: &mut Chunk, removed: &mut [bool]) -> bool {
+    let mut changed = false;
+
+    for (i, gone) in removed.iter_mut().enumerate() {
+        if *gone {
+            continue;
+        }
+        let (OpCode::Jump(start) | OpCode::JumpIfFalse(start)) = chunk.code[i] else {
+            continue;
+        };
+
+        // A chain that loops back on itself is left alone
+        let mut target = start;
+        let mut hops = 0;
+        while let Some(OpCode::Jump(next)) = chunk.code.get(target) {
+            if *next == target || hops > chunk.code.len() {
+                target = start;
+                break;
+            }
+            target = *next;
+            hops += 1;
+        }
+
+        chunk.code[i] = match chunk.code[i] {
+            OpCode::Jump(_) if target == i + 1 => {
+                *gone = true;
+                changed = true;
+                continue;
+            }
+            OpCode::Jump(_) => OpCode::Jump(target),
+            _ => OpCode::JumpIfFalse(target),
+        };
+        changed |= target != start;
+    }
+
+    changed
+}
+
+/// Remove the instructions marked in `removed`, moving jump targets and
+/// function entries to where their instructions end up. A target that was
+/// removed moves to the next instruction kept.
+fn compact(chunk: &mut Chunk, removed: &[bool]) {
+    let mut new_offset = Vec::with_capacity(removed.len() + 1);
+    let mut kept = 0;
+    for &gone in removed {
+        new_offset.push(kept);
+        if !gone {
+            kept += 1;
+        }
+    }
+    new_offset.push(kept);
+
+    let mut index = 0;
+    chunk.code.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+    let mut index = 0;
+    chunk.lines.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+
+    for op in &mut chunk.code {
+        match op {
+            OpCode::Jump(target) | OpCode::JumpIfFalse(target) => *target = new_offset[*target],
+            _ => {}
+        }
+    }
+    for constant in &mut chunk.constants {
+        if let Value::CompiledFunction { entry, .. } = constant {
+            *entry = new_offset[*entry];
+        }
+    }
+}
@@ -1,7 +1,28 @@
-use crate::backend::bytecode::Compiler;
+use crate::backend::bytecode::{Chunk, Compiler, OpCode};
+use crate::backend::environment::Environment;
+use crate::backend::literal_check::{self, Severity};
+use crate::backend::optimizer;
 use crate::backend::vm::VM;
+use crate::cli::analyze::analyze;
+use crate::cli::check::check_source;
+use crate::cli::engine::Engine;
+use crate::cli::fix::fix_source;
+use crate::cli::viewer::{self, Key, ScriptedScreen};
+use crate::cli::{Repl, Runner};
 use crate::core::ast::{Expr, Program, Stmt};
-use crate::core::Value;
+use crate::core::wire::{WireError, WIRE_VERSION};
+use crate::core::{InfraError, ObjectMap, Type, Value};
+use crate::frontend::{formatter, printer, Lexer, Parser, TokenType};
+use crate::stdlib::output::{self, LimitedWriter, OutputLimits};
+use crate::stdlib::progress::{self, Mode};
+use crate::stdlib::sources::{ClockSource, EntropySource, ManualClock};
+use crate::stdlib::{
+    capability, error, io, json, math, prelude, random, schedule, time, units, StandardLibrary,
+};
+use crate::utils::platform;
+use num_bigint::BigInt;
+use std::borrow::Cow;
+use std::path::Path;
 
 #[cfg(test)]
 mod tests {
@@ -15,6 +36,7 @@ mod tests {
             left: Box::new(Expr::Literal(Value::Number(2.0))),
             operator: crate::core::ast::BinaryOp::Add,
             right: Box::new(Expr::Literal(Value::Number(3.0))),
+            span: None,
         }));
 
         // Compile to bytecode
@@ -37,7 +59,7 @@ mod tests {
             type_annotation: None,
             value: Expr::Literal(Value::Number(42.0)),
         });
-        program.add_statement(Stmt::Print(Expr::Identifier("x".to_string())));
+        program.add_statement(Stmt::Print(Expr::identifier("x")));
 
         // Compile and execute
         let compiler = Compiler::new();
@@ -48,4 +70,5468 @@ mod tests {
         let mut vm = VM::new();
         vm.interpret(chunk).expect("Execution should succeed");
     }
+
+    #[test]
+    fn test_vm_calls_recursive_functions() {
+        let program = parse_source(
+            "let depth = 0\n\
+             function fact(n):\n{\n    depth = depth + 1\n    if n <= 1: return 1\n    return n * fact(n - 1)\n}\n\
+             let result = fact(10)\n",
+        );
+        let mut vm = VM::new();
+        vm.interpret(Compiler::new().compile(&program).unwrap())
+            .unwrap();
+
+        // Slots: fact is declared first, then depth and result
+        assert_eq!(vm.local(1), Some(&Value::Number(10.0)));
+        assert_eq!(vm.local(2), Some(&Value::Number(3628800.0)));
+    }
+
+    #[test]
+    fn test_vm_errors_report_the_failing_line() {
+        let program = parse_source(
+            "function check(limit):\n{\n    let count = 0\n    if limit < count: print(\"over\")\n}\n\
+             check(1)\n\
+             check(\"three\")\n",
+        );
+        let mut vm = VM::new();
+        let error = vm
+            .interpret(Compiler::new().compile(&program).unwrap())
+            .unwrap_err();
+
+        match error {
+            InfraError::RuntimeError { message, line, .. } => {
+                assert_eq!(message, "Can only compare numbers");
+                assert_eq!(line, Some(4));
+            }
+            other => panic!("expected a runtime error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_optimizer_shortens_chunks_without_changing_results() {
+        let program = parse_source(
+            "let x = 4\n\
+             let y = 2 * 3 + x\n\
+             1 + 2\n\
+             let z = -(1 + 1) < 0\n\
+             function scale(n):\n    return n * (2 + 3)\n\
+             while x > 0:\n{\n    if x == 2: x = x - 2\n    else: x = x - 1\n}\n\
+             let w = scale(y)\n",
+        );
+        let chunk = Compiler::new().compile(&program).unwrap();
+        let optimized = optimizer::optimize(chunk.clone());
+        assert_eq!((chunk.code.len(), optimized.code.len()), (53, 40));
+        assert_eq!(optimized.code.len(), optimized.lines.len());
+
+        // 2 * 3 is one constant, and the loop's inner jump goes straight back
+        // to the condition instead of through the jump after the else branch
+        let OpCode::LoadConst(six) = optimized.code[2] else {
+            panic!("expected a folded constant, found {:?}", optimized.code[2]);
+        };
+        assert_eq!(optimized.constants[six], Value::Number(6.0));
+        assert_eq!(optimized.code[3], OpCode::LoadVar(1));
+        let jumps: Vec<_> = optimized
+            .code
+            .iter()
+            .filter(|op| matches!(op, OpCode::Jump(_)))
+            .collect();
+        assert_eq!(jumps.len(), 3);
+        assert_eq!(jumps[1], jumps[2]);
+
+        // Slot 0 holds scale, whose entry moves; the rest are x, y, z and w
+        let run = |chunk: Chunk| {
+            let mut vm = VM::new();
+            vm.interpret(chunk).unwrap();
+            (1..5)
+                .map(|slot| vm.local(slot).cloned())
+                .collect::<Vec<_>>()
+        };
+        let results = run(optimized);
+        assert_eq!(results, run(chunk));
+        assert_eq!(results[3], Some(Value::Number(50.0)));
+
+        // An operation that fails is left for the VM to report
+        let program = parse_source("let a = 1\nlet b = 2 - \"x\"\n");
+        let optimized = optimizer::optimize(Compiler::new().compile(&program).unwrap());
+        assert!(optimized.code.contains(&OpCode::Sub));
+        assert!(VM::new().interpret(optimized).is_err());
+    }
+
+    fn string_var(runner: &Runner, name: &str) -> String {
+        match runner.get_interpreter().get_environment().get(name) {
+            Ok(Value::String(s)) => s,
+            other => panic!("expected string variable '{}', got {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn test_with_temp_dir_cleans_up_after_error() {
+        let mut runner = Runner::new();
+        let code = r#"
+function work(dir):
+{
+    io.write_file(dir + "/data.txt", "scratch")
+    io.throw(dir)
+}
+io.with_temp_dir(work)
+"#;
+
+        match runner.execute_code(code) {
+            Err(InfraError::Exception { message, .. }) => {
+                assert!(!Path::new(&message).exists(), "temp dir was not removed");
+            }
+            other => panic!("expected the callback's exception, got {:?}", other),
+        }
+        assert!(io::cleanup_temp_paths().is_empty());
+    }
+
+    #[test]
+    fn test_temp_paths_removed_on_exit() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code("let f = io.temp_file(\"test-\", \".txt\")\nlet d = io.temp_dir()\n")
+            .expect("Execution should succeed");
+
+        let file = string_var(&runner, "f");
+        let dir = string_var(&runner, "d");
+        assert!(file.ends_with(".txt"));
+        assert!(Path::new(&file).is_file());
+        assert!(Path::new(&dir).is_dir());
+
+        assert!(io::cleanup_temp_paths().is_empty());
+        assert!(!Path::new(&file).exists());
+        assert!(!Path::new(&dir).exists());
+    }
+
+    #[test]
+    fn test_temp_paths_kept_on_request() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code("let kept = io.temp_file({keep: true})\nlet d = io.temp_dir()\n")
+            .expect("Execution should succeed");
+        let kept = string_var(&runner, "kept");
+        let dir = string_var(&runner, "d");
+
+        io::set_keep_temp(true);
+        let retained = io::cleanup_temp_paths();
+        io::set_keep_temp(false);
+
+        assert_eq!(retained, vec![kept.clone(), dir.clone()]);
+        assert!(Path::new(&kept).exists());
+        assert!(Path::new(&dir).exists());
+
+        std::fs::remove_file(&kept).unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_io_appends_lists_and_deletes() {
+        let dir = io::create_temp_dir("io-", false)
+            .unwrap()
+            .replace('\\', "/");
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&format!(
+                "io.mkdir(\"{0}/logs/old\")\n\
+                 io.mkdir(\"{0}/logs/old\")\n\
+                 io.append_file(\"{0}/logs/run.log\", \"first\\n\")\n\
+                 io.append_file(\"{0}/logs/run.log\", \"second\\n\")\n\
+                 let lines = io.read_lines(\"{0}/logs/run.log\")\n\
+                 let before = io.list_dir(\"{0}/logs\")\n\
+                 io.delete_file(\"{0}/logs/run.log\")\n\
+                 let after = io.list_dir(\"{0}/logs\")\n",
+                dir
+            ))
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("lines").unwrap().to_string(), "[first, second]");
+        assert_eq!(env.get("before").unwrap().to_string(), "[old, run.log]");
+        assert_eq!(env.get("after").unwrap().to_string(), "[old]");
+
+        for (call, operation, path) in [
+            (
+                "io.delete_file",
+                "io.delete_file",
+                format!("{}/logs/run.log", dir),
+            ),
+            ("io.list_dir", "io.list_dir", format!("{}/missing", dir)),
+        ] {
+            match runner.execute_code(&format!("{}(\"{}\")", call, path)) {
+                Err(InfraError::IoError {
+                    operation: found_operation,
+                    path: found_path,
+                    ..
+                }) => {
+                    assert_eq!(found_operation.as_deref(), Some(operation));
+                    assert_eq!(found_path.as_deref(), Some(path.as_str()));
+                }
+                other => panic!("expected an IoError from {}, got {:?}", call, other),
+            }
+        }
+        io::cleanup_temp_paths();
+    }
+
+    #[test]
+    fn test_dry_run_logs_mutations_in_order() {
+        let dir = io::create_temp_dir("dry-run-", false).unwrap();
+        let path = format!("{}/out.txt", dir.replace('\\', "/"));
+        let mut runner = Runner::new();
+
+        capability::set_dry_run(true);
+        let result = runner.execute_code(&format!(
+            "io.write_file(\"{0}\", \"first\")\nio.write_file(\"{0}\", \"second!\")\nlet seen = io.exists(\"{0}\")\nio.write_file(42, \"x\")\n",
+            path
+        ));
+        capability::set_dry_run(false);
+
+        // Invalid arguments still reach the real function and fail there
+        assert!(result.is_err());
+        assert_eq!(
+            capability::take_dry_run_log(),
+            vec![
+                format!("would have: io.write_file path={} bytes=5", path),
+                format!("would have: io.write_file path={} bytes=7", path),
+            ]
+        );
+        assert_eq!(
+            runner.get_interpreter().get_environment().get("seen").ok(),
+            Some(Value::Boolean(false))
+        );
+        assert!(!Path::new(&path).exists());
+        io::cleanup_temp_paths();
+    }
+
+    fn number_field(runner: &Runner, name: &str, field: &str) -> f64 {
+        match runner.get_interpreter().get_environment().get(name) {
+            Ok(Value::Object(obj)) => obj[field].as_number().unwrap(),
+            other => panic!("expected object variable '{}', got {:?}", name, other),
+        }
+    }
+
+    fn memo_script(cache_dir: &str) -> String {
+        format!(
+            "function combine(a, b):\n    return a * 10 + b\nlet f = memo.wrap(combine, {{persist: \"{}\"}})\nlet r = f(4, 2)\nlet s = memo.stats(f)\n",
+            cache_dir.replace('\\', "/")
+        )
+    }
+
+    #[test]
+    fn test_memo_hits_and_argument_order() {
+        let mut runner = Runner::new();
+        let code = r#"
+function combine(a, b):
+    return a * 10 + b
+let f = memo.wrap(combine)
+let first = f(1, 2)
+let again = f(1, 2)
+let swapped = f(2, 1)
+let s = memo.stats(f)
+"#;
+        runner.execute_code(code).expect("Execution should succeed");
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("first").unwrap(), Value::Number(12.0));
+        assert_eq!(env.get("again").unwrap(), Value::Number(12.0));
+        assert_eq!(env.get("swapped").unwrap(), Value::Number(21.0));
+        assert_eq!(number_field(&runner, "s", "hits"), 1.0);
+        assert_eq!(number_field(&runner, "s", "misses"), 2.0);
+
+        // Another interpreter's memo.clear() leaves these caches alone
+        Runner::new()
+            .execute_code(
+                "function g(x):\n    return x\nlet h = memo.wrap(g)\nh(1)\nmemo.clear()\n",
+            )
+            .unwrap();
+        runner
+            .execute_code("let later = f(1, 2)\nlet s = memo.stats(f)\n")
+            .unwrap();
+        assert_eq!(number_field(&runner, "s", "hits"), 2.0);
+    }
+
+    #[test]
+    fn test_memo_rejects_unserializable_arguments() {
+        let mut runner = Runner::new();
+        let code = "function id(x):\n    return x\nlet f = memo.wrap(id)\nf(id)\n";
+
+        assert!(matches!(
+            runner
+                .execute_code(code)
+                .map_err(|error| error.untraced().clone()),
+            Err(InfraError::TypeError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_memo_persists_across_runners() {
+        let dir = std::env::temp_dir().join(format!("infra-memo-{}", std::process::id()));
+        let script = memo_script(&dir.display().to_string());
+
+        let mut first = Runner::new();
+        first
+            .execute_code(&script)
+            .expect("Execution should succeed");
+        assert_eq!(number_field(&first, "s", "misses"), 1.0);
+
+        // Moving the function in its file keeps its cache
+        let mut second = Runner::new();
+        second
+            .execute_code(&format!("# moved down a line\n{}", script))
+            .expect("Execution should succeed");
+        assert_eq!(
+            second.get_interpreter().get_environment().get("r").unwrap(),
+            Value::Number(42.0)
+        );
+        assert_eq!(number_field(&second, "s", "hits"), 1.0);
+        assert_eq!(number_field(&second, "s", "misses"), 0.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_memo_ignores_corrupt_cache_files() {
+        let dir = std::env::temp_dir().join(format!("infra-memo-corrupt-{}", std::process::id()));
+        let script = memo_script(&dir.display().to_string());
+
+        Runner::new()
+            .execute_code(&script)
+            .expect("Execution should succeed");
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            std::fs::write(entry.unwrap().path(), "{\"version\": [").unwrap();
+        }
+
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&script)
+            .expect("Execution should succeed");
+        assert_eq!(
+            runner.get_interpreter().get_environment().get("r").unwrap(),
+            Value::Number(42.0)
+        );
+        assert_eq!(number_field(&runner, "s", "misses"), 1.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_repl_bracketed_paste_runs_as_one_unit() {
+        let mut repl = Repl::new();
+        let input = "\x1b[200~function add(a, b):\n    return a + b\n\nlet total = add(2, 3)\x1b[201~\nexit\n";
+        repl.run_from(std::io::Cursor::new(input));
+
+        assert_eq!(
+            repl.get_runner()
+                .get_interpreter()
+                .get_environment()
+                .get("total")
+                .unwrap(),
+            Value::Number(5.0)
+        );
+    }
+
+    #[test]
+    fn test_repl_paste_command_reads_until_dot() {
+        let mut repl = Repl::new();
+        let input = ":paste\nfunction double(x):\n    return x * 2\n\nlet y = double(21)\n.\n";
+        repl.run_from(std::io::Cursor::new(input));
+
+        assert_eq!(
+            repl.get_runner()
+                .get_interpreter()
+                .get_environment()
+                .get("y")
+                .unwrap(),
+            Value::Number(42.0)
+        );
+    }
+
+    #[test]
+    fn test_repl_continues_unfinished_statements() {
+        let mut repl = Repl::new();
+        let input = "function add(a, b):\n{\n    return a + b\n}\nlet total = add(\n    2,\n    3)\nlet text = \"\"\"one\ntwo\"\"\"\nexit\n";
+        repl.run_from(std::io::Cursor::new(input));
+
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert_eq!(env.get("total").unwrap(), Value::Number(5.0));
+        assert_eq!(env.get("text").unwrap(), Value::String("one\ntwo".into()));
+
+        // A trailing operator waits for its right side, and the joined lines run
+        let mut repl = Repl::new();
+        repl.run_from(std::io::Cursor::new(
+            "let sum = 1 +\n    2 *\n    3\nexit\n",
+        ));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert_eq!(env.get("sum").unwrap(), Value::Number(7.0));
+
+        // A statement that is wrong rather than unfinished is not continued
+        let mut repl = Repl::new();
+        repl.run_from(std::io::Cursor::new("let a = 1)\nlet b = 2\n"));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert!(env.get("a").is_err());
+        assert_eq!(env.get("b").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_repl_load_and_clear_commands() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let path = format!("{}/lib.infra", dir);
+        std::fs::write(&path, "function triple(x):\n    return x * 3\n").unwrap();
+
+        let mut repl = Repl::new();
+        let input = format!(":load {}\nlet n: number = triple(4)\n", path);
+        repl.run_from(std::io::Cursor::new(input));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert_eq!(env.get("n").unwrap(), Value::Number(12.0));
+
+        let bindings = env.bindings();
+        let names: Vec<_> = bindings.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, ["n", "triple"]);
+        assert_eq!(bindings[0].2, Some(Type::Number));
+
+        repl.run_from(std::io::Cursor::new(":clear\n"));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert!(env.bindings().is_empty());
+        io::release_temp_path(&dir);
+    }
+
+    /// Write an executable shell script to stand in for the user's editor
+    #[cfg(unix)]
+    fn fake_editor(dir: &str, script: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = format!("{}/editor.sh", dir);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_repl_edit_redefines_function() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let mut repl = Repl::new();
+        repl.set_editor(&fake_editor(
+            &dir,
+            "printf 'let scaled = scale(4)\\n' >> \"$1\"",
+        ));
+
+        let input = ":paste\nfunction scale(x):\n{\n    let factor = 2\n    return x * factor\n}\n.\n:edit scale\nexit\n";
+        repl.run_from(std::io::Cursor::new(input));
+
+        assert_eq!(
+            repl.get_runner()
+                .get_interpreter()
+                .get_environment()
+                .get("scaled")
+                .unwrap(),
+            Value::Number(8.0)
+        );
+        io::release_temp_path(&dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_repl_edit_prefills_failed_input_and_aborts_on_editor_failure() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let mut repl = Repl::new();
+
+        // Appending the missing body completes the input that failed to parse
+        repl.set_editor(&fake_editor(
+            &dir,
+            "printf '    return x + 1\\nlet r = inc(1)\\n' >> \"$1\"",
+        ));
+        repl.run_from(std::io::Cursor::new("function inc(x):\n:edit\nexit\n"));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert_eq!(env.get("r").unwrap(), Value::Number(2.0));
+
+        repl.set_editor(&fake_editor(
+            &dir,
+            "printf 'let aborted = true\\n' >> \"$1\"\nexit 1",
+        ));
+        repl.run_from(std::io::Cursor::new(":edit\nexit\n"));
+        let env = repl.get_runner().get_interpreter().get_environment();
+        assert!(env.get("aborted").is_err());
+        io::release_temp_path(&dir);
+    }
+
+    fn parse_source(source: &str) -> Program {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_printer_round_trips_function_source() {
+        let source = r#"function describe(items: [number], label) -> string:
+{
+    let total = 0
+    for i in range(0, array.length(items)):
+        total = total + items[i] * (2 - i % 3)
+    if total > 10 && !(label == null):
+        return label + ": " + string.upper("big\tvalue \"" + total + "\"")
+    else:
+        while total < 0:
+            total = total - -1
+    let info = {name: label, "odd key": [1.5, 2n, true]}
+    try:
+        print(info.name)
+    catch e:
+        return (1 - 2) - (3 - 4)
+    return "small"
+}
+"#;
+        let options = printer::FormattingOptions::default();
+        let printed = printer::format_program(&parse_source(source), &options);
+        assert_eq!(
+            printer::format_program(&parse_source(&printed), &options),
+            printed
+        );
+        assert!(printed.contains("items[i] * (2 - i % 3)"));
+        assert!(printed.contains("return 1 - 2 - (3 - 4)"));
+        assert!(printed.contains("\"odd key\": [1.5, 2n, true]"));
+    }
+
+    #[test]
+    fn test_formatter_keeps_comments_and_blank_lines() {
+        let source = "# Deploy helpers\nlet retries=3   // how many times\nfunction deploy(target,  attempts)->bool:\n{\n  # try each attempt\n  for i in range(0,attempts):\n  {\n      if ready(target): return true  # reachable\n\n\n      print(\"retry \"+i)\n  }\n  return false /* never */\n}\n/* trailing\n   note */\n";
+        let options = printer::FormattingOptions::default();
+        let formatted = formatter::format_source(source, &options).unwrap();
+        assert_eq!(
+            formatted,
+            "# Deploy helpers\nlet retries = 3  // how many times\n\nfunction deploy(target, attempts) -> bool:\n{\n    # try each attempt\n    for i in range(0, attempts):\n    {\n        if ready(target):\n            return true  # reachable\n\n        print(\"retry \" + i)\n    }\n    return false  /* never */\n}\n/* trailing\n   note */\n"
+        );
+        assert_eq!(
+            formatter::format_source(&formatted, &options).unwrap(),
+            formatted
+        );
+
+        let tabs = printer::FormattingOptions {
+            use_tabs: true,
+            ..Default::default()
+        };
+        let indented = formatter::format_source(source, &tabs).unwrap();
+        assert!(indented
+            .contains("\n\t# try each attempt\n\tfor i in range(0, attempts):\n\t{\n\t\tif"));
+
+        // Comments in a list or object literal stay with its items
+        let source = "let hosts=[\n  # primary\n  \"web\",  # front\n  {name:\"db\", # data\n   port:5432},\n  # spare\n]  # done\nlet ok=true\n";
+        let formatted = formatter::format_source(source, &options).unwrap();
+        assert_eq!(
+            formatted,
+            "let hosts = [\n    # primary\n    \"web\",  # front\n    {\n        name: \"db\",  # data\n        port: 5432,\n    },\n    # spare\n]\n# done\nlet ok = true\n"
+        );
+        assert_eq!(
+            formatter::format_source(&formatted, &options).unwrap(),
+            formatted
+        );
+        let joined = printer::FormattingOptions {
+            preserve_line_breaks: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            formatter::format_source(source, &joined).unwrap(),
+            "let hosts = [\"web\", {name: \"db\", port: 5432}]\n# primary\n# front\n# data\n# spare\n# done\nlet ok = true\n"
+        );
+
+        // Only the statements the lines touch are formatted
+        let source = "let a=1\n\nlet b=[1,\n  2]\nlet c=3\n";
+        assert_eq!(
+            formatter::format_lines(source, 4, 4, &options).unwrap(),
+            Some((3, 4, "let b = [\n    1,\n    2,\n]\n".to_string()))
+        );
+        assert_eq!(
+            formatter::format_lines(source, 1, 2, &options).unwrap(),
+            Some((1, 1, "let a = 1\n".to_string()))
+        );
+        assert_eq!(
+            formatter::format_lines(source, 9, 9, &options).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_error_value_round_trips_through_catch() {
+        let mut runner = Runner::new();
+        let code = r#"
+let caught = null
+try:
+    io.throw(error.new("NotFound", "user 42 missing", {id: 42}))
+catch e:
+    caught = e
+let kind = error.type(caught)
+let fields = error.fields(caught)
+let yes = error.is_error(caught)
+let no = error.is_error("user 42 missing")
+"#;
+        runner.execute_code(code).expect("Execution should succeed");
+
+        let env = runner.get_interpreter().get_environment();
+        match env.get("caught").unwrap() {
+            value @ Value::Error { .. } => {
+                assert_eq!(value.to_string(), "<error NotFound: user 42 missing>")
+            }
+            other => panic!("expected error value, got {:?}", other),
+        }
+        assert_eq!(env.get("kind").unwrap(), Value::String("NotFound".into()));
+        assert_eq!(number_field(&runner, "fields", "id"), 42.0);
+        assert_eq!(env.get("yes").unwrap(), Value::Boolean(true));
+        assert_eq!(env.get("no").unwrap(), Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_catch_binds_errors_as_error_values_and_thrown_values_unchanged() {
+        let mut runner = Runner::new();
+        let code = r#"
+let items = [1, 2]
+try:
+    let third = items[2]
+catch e:
+    let index = e
+try:
+    let n = 1 + {}
+catch e:
+    let mismatch = e
+try:
+    io.throw({code: 7})
+catch e:
+    let thrown = e
+try:
+    io.throw("plain")
+catch e:
+    let text = e
+"#;
+        runner.execute_code(code).unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        match env.get("index").unwrap() {
+            Value::Error {
+                error_type,
+                fields,
+                line,
+                ..
+            } => {
+                assert_eq!(error_type, "IndexOutOfBounds");
+                assert_eq!(fields.get("index"), Some(&Value::Number(2.0)));
+                assert_eq!(fields.get("length"), Some(&Value::Number(2.0)));
+                assert_eq!(line, Some(4));
+            }
+            other => panic!("expected an error value, got {:?}", other),
+        }
+        runner
+            .execute_code(
+                "let kind = mismatch.type
+let found = mismatch[\"found\"]
+let text_too = index.message
+",
+            )
+            .unwrap();
+        assert_eq!(string_var(&runner, "kind"), "TypeError");
+        assert_eq!(string_var(&runner, "found"), "number and object");
+        assert!(string_var(&runner, "text_too").contains("out of bounds"));
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("thrown").unwrap().to_string(), "{\"code\": 7}");
+        assert_eq!(string_var(&runner, "text"), "plain");
+    }
+
+    #[test]
+    fn test_error_value_captures_creating_function() {
+        let mut runner = Runner::new();
+        let code = r#"
+function find_user(id):
+    return error.new("NotFound", "missing")
+let result = find_user(7)
+let failed = error.is_error(result)
+let stack = error.stack(result)
+let make = error.new
+let later = make("Late", "made through a variable")
+"#;
+        runner.execute_code(code).expect("Execution should succeed");
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("failed").unwrap(), Value::Boolean(true));
+        assert_eq!(
+            env.get("stack").unwrap(),
+            Value::Array(vec![Value::String("find_user".into())])
+        );
+        // The line is the one error.new was called on
+        assert!(
+            matches!(
+                env.get("result").unwrap(),
+                Value::Error { line: Some(3), .. }
+            ),
+            "{:?}",
+            env.get("result")
+        );
+        assert!(
+            matches!(
+                env.get("later").unwrap(),
+                Value::Error { line: Some(8), .. }
+            ),
+            "{:?}",
+            env.get("later")
+        );
+    }
+
+    #[test]
+    fn test_error_value_serializes_fields() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code("let e = error.new(\"Invalid\", \"bad input\", {field: \"name\"})\n")
+            .expect("Execution should succeed");
+
+        let e = runner.get_interpreter().get_environment().get("e").unwrap();
+        assert_eq!(
+            e.to_canonical_string().unwrap(),
+            r#"{"fields":{"field":"name"},"message":"bad input","type":"Invalid"}"#
+        );
+    }
+
+    #[test]
+    fn test_number_parse_is_locale_invariant() {
+        let mut runner = Runner::new();
+
+        match runner.execute_code("number.parse(\"1,5\")\n") {
+            Err(InfraError::TypeError {
+                hint: Some(hint), ..
+            }) => {
+                assert!(hint.contains("parse_locale"), "unexpected hint: {}", hint)
+            }
+            other => panic!("expected a type error with a hint, got {:?}", other),
+        }
+
+        runner
+            .execute_code(
+                "let a = number.parse(\" 2.5e3 \")\nlet b = number.parse_locale(\"1.234,5\", {decimal: \",\", thousands: \".\"})\n",
+            )
+            .expect("Execution should succeed");
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("a").unwrap(), Value::Number(2500.0));
+        assert_eq!(env.get("b").unwrap(), Value::Number(1234.5));
+    }
+
+    #[test]
+    fn test_display_precision_only_affects_display() {
+        let mut runner = Runner::new();
+        let code = r#"
+let third = 1 / 3
+number.set_display_precision(2)
+let shown = "" + third
+let sig = number.to_precision(third, 4)
+number.set_display_precision(null)
+let full = "" + third
+"#;
+        runner.execute_code(code).expect("Execution should succeed");
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("shown").unwrap(), Value::String("0.33".into()));
+        assert_eq!(env.get("sig").unwrap(), Value::String("0.3333".into()));
+        assert_eq!(
+            env.get("full").unwrap(),
+            Value::String((1.0f64 / 3.0).to_string())
+        );
+
+        // The setting belongs to the interpreter that made it: values
+        // themselves and other interpreters keep the shortest exact form
+        runner
+            .execute_code("number.set_display_precision(1)\nlet rounded = \"${third}\"\n")
+            .unwrap();
+        let mut other = Runner::new();
+        other
+            .execute_code("let third = 1 / 3\nlet full = \"${third}\"\n")
+            .unwrap();
+        assert_eq!(string_var(&runner, "rounded"), "0.3");
+        assert_eq!(string_var(&other, "full"), (1.0f64 / 3.0).to_string());
+        assert_eq!(runner.get_interpreter().display_precision(), Some(1));
+        assert_eq!(other.get_interpreter().display_precision(), None);
+
+        let third = Value::Number(1.0 / 3.0);
+        assert_eq!(third.to_string(), (1.0f64 / 3.0).to_string());
+        assert_eq!(third.display(Some(1)).to_string(), "0.3");
+        assert_eq!(
+            Value::Array(vec![third.clone(), Value::Number(2.0)])
+                .display(Some(2))
+                .to_string(),
+            "[0.33, 2]"
+        );
+        assert_eq!(
+            third.to_canonical_string().unwrap(),
+            (1.0f64 / 3.0).to_string()
+        );
+    }
+
+    #[test]
+    fn test_bigint_round_trips_large_ids() {
+        let mut runner = Runner::new();
+        let id = runner
+            .evaluate_code("bigint.new(\"12345678901234567890\") * 10n + 7n - 7n")
+            .expect("bigint arithmetic should succeed");
+
+        assert_eq!(id.to_string(), "123456789012345678900");
+        let record = Value::Array(vec![id]);
+        assert_eq!(
+            record.to_canonical_string().unwrap(),
+            "[\"123456789012345678900\"]"
+        );
+        assert_eq!(
+            record.to_json_string(true).unwrap(),
+            "[123456789012345678900]"
+        );
+        assert_eq!(runner.evaluate_code("-7n / 2n").unwrap().to_string(), "-3");
+    }
+
+    #[test]
+    fn test_bigint_rejects_mixed_arithmetic() {
+        let mut runner = Runner::new();
+        for code in ["1n + 1", "2.5 * 3n", "10n % 3"] {
+            match runner.evaluate_code(code) {
+                Err(InfraError::TypeError { hint, .. }) => {
+                    assert!(hint.unwrap().contains("number.to_bigint()"))
+                }
+                other => panic!("expected a type error for {}, got {:?}", code, other),
+            }
+        }
+        assert!(runner.evaluate_code("number.to_bigint(1.5)").is_err());
+        assert!(runner
+            .evaluate_code("bigint.to_number(9007199254740993n)")
+            .is_err());
+        assert_eq!(
+            runner
+                .evaluate_code("bigint.to_number(-9007199254740992n)")
+                .unwrap(),
+            Value::Number(-9007199254740992.0)
+        );
+    }
+
+    #[test]
+    fn test_bigint_comparisons_at_float_precision_limit() {
+        let mut runner = Runner::new();
+        let cases = [
+            // 2^53 + 1 has no float representation
+            ("9007199254740993n == 9007199254740992", false),
+            ("9007199254740993n > 9007199254740992", true),
+            ("9007199254740992n == 9007199254740992", true),
+            ("9007199254740992n >= 9007199254740992", true),
+            ("3n == 3.5", false),
+            ("3n < 3.5", true),
+            ("4n > 3.5", true),
+            ("-1n < 0n", true),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(
+                runner.evaluate_code(code).unwrap(),
+                Value::Boolean(expected),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn test_analyze_reports_module_graph_findings() {
+        let entry = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/analyze/main.infra");
+        let analysis = analyze(&entry).expect("Analysis should succeed");
+
+        let unused: Vec<(String, String)> = analysis
+            .unused_exports
+            .iter()
+            .map(|(m, name)| (analysis.display_name(*m), name.clone()))
+            .collect();
+        assert_eq!(
+            unused,
+            vec![("util.infra".to_string(), "unused_helper".to_string())]
+        );
+
+        assert_eq!(analysis.missing_imports.len(), 1);
+        assert_eq!(analysis.missing_imports[0].name.as_deref(), Some("halper"));
+
+        let cycles: Vec<Vec<String>> = analysis
+            .cycles
+            .iter()
+            .map(|c| c.iter().map(|&m| analysis.display_name(m)).collect())
+            .collect();
+        assert_eq!(cycles, vec![vec!["a.infra", "b.infra", "a.infra"]]);
+
+        // The broken module is reported without stopping the analysis
+        let broken: Vec<String> = (0..analysis.modules.len())
+            .filter(|&i| analysis.modules[i].parse_error.is_some())
+            .map(|i| analysis.display_name(i))
+            .collect();
+        assert_eq!(broken, vec!["broken.infra"]);
+
+        // Dependencies come before the modules importing them
+        let order: Vec<String> = analysis
+            .order
+            .iter()
+            .map(|&m| analysis.display_name(m))
+            .collect();
+        assert_eq!(order.last().unwrap(), "main.infra");
+        assert!(
+            order.iter().position(|m| m == "util.infra")
+                < order.iter().position(|m| m == "main.infra")
+        );
+    }
+
+    #[test]
+    fn test_analyze_dot_output_edges() {
+        let entry = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/analyze/main.infra");
+        let dot = analyze(&entry).unwrap().to_dot();
+
+        let mut edges: Vec<&str> = dot
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.contains("->"))
+            .collect();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                "\"a.infra\" -> \"b.infra\";",
+                "\"b.infra\" -> \"a.infra\";",
+                "\"main.infra\" -> \"a.infra\";",
+                "\"main.infra\" -> \"broken.infra\";",
+                "\"main.infra\" -> \"util.infra\";",
+            ]
+        );
+        assert!(dot.starts_with("digraph modules {"));
+    }
+
+    #[test]
+    fn test_lexer_borrows_strings_without_escapes() {
+        let source = r#"let a = "plain text"
+let b = "tab\tand \"quotes\""
+"#;
+        let tokens = Lexer::new(source)
+            .tokenize()
+            .expect("Lexing should succeed");
+        let strings: Vec<&TokenType> = tokens
+            .iter()
+            .map(|t| &t.token_type)
+            .filter(|t| matches!(t, TokenType::String(_)))
+            .collect();
+
+        match strings[0] {
+            TokenType::String(Cow::Borrowed(s)) => assert_eq!(*s, "plain text"),
+            other => panic!("expected borrowed string, got {:?}", other),
+        }
+        match strings[1] {
+            TokenType::String(Cow::Owned(s)) => assert_eq!(s, "tab\tand \"quotes\""),
+            other => panic!("expected processed string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let mut runner = Runner::new();
+        let code = r#"let name = "web"
+let counts = {web: 3}
+let plain = "deploying ${name} x${counts[name] + 1}"
+let nested = "${ {a: "}"}.a } and ${"inner ${name}"}"
+let escaped = "\${name} costs $5"
+"#;
+        runner.execute_code(code).unwrap();
+        assert_eq!(string_var(&runner, "plain"), "deploying web x4");
+        assert_eq!(string_var(&runner, "nested"), "} and inner web");
+        assert_eq!(string_var(&runner, "escaped"), "${name} costs $5");
+
+        // The formatter writes interpolations back, and keeps `\${` escaped
+        let options = printer::FormattingOptions::default();
+        let printed = printer::format_program(&parse_source(code), &options);
+        assert!(printed.contains(r#""deploying ${name} x${counts[name] + 1}""#));
+        assert!(printed.contains(r#""\${name} costs $5""#));
+        assert_eq!(
+            printer::format_program(&parse_source(&printed), &options),
+            printed
+        );
+
+        // An unterminated interpolation is reported where it opens
+        match Lexer::new("let s = \"a ${b + 1\"\n").tokenize() {
+            Err(InfraError::LexError { line, column, .. }) => assert_eq!((line, column), (1, 12)),
+            other => panic!("expected a lex error, got {:?}", other),
+        }
+        // Names read only inside a string are not unused
+        let report =
+            check_source("function greet(who):\n    let name = who\n    return \"hi ${name}\"\n");
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+    }
+
+    #[test]
+    fn test_lexer_escapes_and_triple_quoted_strings() {
+        let lex_string = |source: &str| match Lexer::new(source).tokenize() {
+            Ok(tokens) => match &tokens[0].token_type {
+                TokenType::String(s) => Ok(s.to_string()),
+                other => panic!("expected a string, got {:?}", other),
+            },
+            Err(InfraError::LexError {
+                message,
+                line,
+                column,
+                ..
+            }) => Err((message, line, column)),
+            Err(other) => panic!("expected a lex error, got {:?}", other),
+        };
+
+        assert_eq!(lex_string(r#""a\nb\tc\rd""#), Ok("a\nb\tc\rd".to_string()));
+        assert_eq!(lex_string(r#""\\ \" \$""#), Ok("\\ \" $".to_string()));
+        assert_eq!(lex_string(r#""[\0]""#), Ok("[\0]".to_string()));
+        assert_eq!(lex_string(r#""\x41\x7e""#), Ok("A~".to_string()));
+        assert_eq!(lex_string(r#""\u{e9}\u{1F600}""#), Ok("é😀".to_string()));
+        // Triple quotes keep newlines and backslashes as written
+        assert_eq!(
+            lex_string("\"\"\"one\n  \"two\" \\n ${x}\n\"\"\""),
+            Ok("one\n  \"two\" \\n ${x}\n".to_string())
+        );
+        assert_eq!(lex_string("\"\"\"\"\"\""), Ok(String::new()));
+
+        // Bad escapes are reported at their backslash
+        let error_at =
+            |source: &str| lex_string(source).map_err(|(_, line, column)| (line, column));
+        assert_eq!(error_at("\"ok\n  \\q\""), Err((2, 3)));
+        assert_eq!(error_at(r#""ab\x4""#), Err((1, 4)));
+        assert_eq!(error_at(r#""\xff""#), Err((1, 2)));
+        assert_eq!(error_at(r#""\u41""#), Err((1, 2)));
+        assert_eq!(error_at(r#""\u{}""#), Err((1, 2)));
+        assert_eq!(error_at(r#""\u{110000}""#), Err((1, 2)));
+        assert_eq!(error_at(r#""\u{D800}""#), Err((1, 2)));
+        // An unterminated triple-quoted string is reported where it opens
+        assert_eq!(
+            lex_string("let a = 1\nlet s = \"\"\"never\nclosed\"\"").map_err(
+                |(message, line, column)| {
+                    assert!(message.contains("Unterminated"), "{}", message);
+                    (line, column)
+                }
+            ),
+            Err((2, 9))
+        );
+    }
+
+    #[test]
+    fn test_lexer_skips_block_comments_and_shebang() {
+        let source = "#!/usr/bin/env infra\n/* one\n   /* nested */ still a comment\n*/\nlet a = /* inline */ 2\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let kinds: Vec<&TokenType> = tokens
+            .iter()
+            .map(|t| &t.token_type)
+            .filter(|t| !matches!(t, TokenType::Newline))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &TokenType::Let,
+                &TokenType::Identifier(Cow::Borrowed("a")),
+                &TokenType::Equal,
+                &TokenType::Number(2.0),
+                &TokenType::Eof,
+            ]
+        );
+        // Lines inside a comment still count
+        let position = |kind: &TokenType| {
+            let token = tokens.iter().find(|t| &t.token_type == kind).unwrap();
+            (token.line, token.column)
+        };
+        assert_eq!(position(&TokenType::Let), (5, 1));
+        assert_eq!(position(&TokenType::Number(2.0)), (5, 22));
+
+        match Lexer::new("let a = 1\n/* open\n/* nested */\nprint(a)\n").tokenize() {
+            Err(InfraError::LexError {
+                message,
+                line,
+                column,
+                ..
+            }) => {
+                assert_eq!(message, "Unterminated block comment");
+                assert_eq!((line, column), (2, 1));
+            }
+            other => panic!("expected a lex error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lexer_keyword_identifier_boundaries() {
+        let source = "let lettuce = letx + _let + in_range + naïve";
+        let tokens = Lexer::new(source)
+            .tokenize_owned()
+            .expect("Lexing should succeed");
+        let types: Vec<TokenType> = tokens.into_iter().map(|t| t.token_type).collect();
+
+        let ident = |name: &str| TokenType::Identifier(Cow::Owned(name.to_string()));
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Let,
+                ident("lettuce"),
+                TokenType::Equal,
+                ident("letx"),
+                TokenType::Plus,
+                ident("_let"),
+                TokenType::Plus,
+                ident("in_range"),
+                TokenType::Plus,
+                ident("naïve"),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_columns_count_characters() {
+        let tokens = Lexer::new("let é = \"ü\" + 1.5\n").tokenize().unwrap();
+        let columns: Vec<usize> = tokens.iter().map(|t| t.column).collect();
+        assert_eq!(columns, vec![1, 5, 7, 9, 13, 15, 18, 1]);
+        assert_eq!(tokens[5].token_type, TokenType::Number(1.5));
+        assert_eq!(tokens[3].lexeme, "\"ü\"");
+    }
+
+    #[test]
+    fn test_read_lines_strips_crlf_unless_asked_to_keep() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let file = format!("{}/crlf.txt", dir);
+        std::fs::write(&file, "first\r\nsecond\r\n\r\nlast").unwrap();
+
+        let mut runner = Runner::new();
+        let code = format!(
+            "let plain = io.read_lines(\"{0}\")\nlet raw = io.read_lines(\"{0}\", {{keep_cr: true}})\n",
+            file.replace('\\', "/")
+        );
+        runner.execute_code(&code).unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        let strings = |values: &[&str]| -> Value {
+            Value::Array(
+                values
+                    .iter()
+                    .map(|s| Value::String(s.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            env.get("plain").unwrap(),
+            strings(&["first", "second", "", "last"])
+        );
+        assert_eq!(
+            env.get("raw").unwrap(),
+            strings(&["first\r", "second\r", "\r", "last"])
+        );
+
+        assert_eq!(io::split_lines("a\nb\n", false), vec!["a", "b"]);
+        assert!(io::split_lines("", false).is_empty());
+        io::release_temp_path(&dir);
+    }
+
+    #[test]
+    fn test_path_outputs_use_forward_slashes() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let joined = path.join(\"config\", \"app\", \"settings.json\")\n\
+                 let tidy = path.normalize(\"./a//b/../c/\")\n\
+                 let up = path.normalize(\"../x/./y\")\n\
+                 let dir = path.dirname(\"logs/today.txt\")\n\
+                 let bare = path.dirname(\"today.txt\")\n\
+                 let base = path.basename(\"logs/today.txt\")\n\
+                 let ext = path.extension(\"archive.tar.gz\")\n",
+            )
+            .unwrap();
+
+        assert_eq!(string_var(&runner, "joined"), "config/app/settings.json");
+        assert_eq!(string_var(&runner, "tidy"), "a/c");
+        assert_eq!(string_var(&runner, "up"), "../x/y");
+        assert_eq!(string_var(&runner, "dir"), "logs");
+        assert_eq!(string_var(&runner, "bare"), ".");
+        assert_eq!(string_var(&runner, "base"), "today.txt");
+        assert_eq!(string_var(&runner, "ext"), "gz");
+    }
+
+    #[test]
+    fn test_batch_scripts_detected_by_extension_and_search_path() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        std::fs::write(format!("{}/tool.cmd", dir), "").unwrap();
+        std::fs::write(format!("{}/editor.exe", dir), "").unwrap();
+        let search = vec![std::path::PathBuf::from(&dir)];
+
+        assert!(platform::is_batch_script("build.BAT", &[]));
+        assert!(platform::is_batch_script("tool", &search));
+        assert!(!platform::is_batch_script("editor", &search));
+        assert!(!platform::is_batch_script("missing", &search));
+        assert!(!platform::is_batch_script("editor.exe", &search));
+        io::release_temp_path(&dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_with_timeout_kills_hung_process() {
+        let mut runner = Runner::new();
+        let started = std::time::Instant::now();
+        let result = runner.execute_code(
+            "function hang():\n    return process.exec(\"sleep\", [\"30\"])\nprocess.with_timeout(100, hang)\n",
+        );
+
+        match result.as_ref().map_err(InfraError::untraced) {
+            Err(InfraError::TimeoutError {
+                operation,
+                timeout_ms,
+            }) => {
+                assert_eq!(operation.as_deref(), Some("process.exec"));
+                assert_eq!(*timeout_ms, 100);
+            }
+            other => panic!("expected timeout, got {:?}", other),
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_with_timeout_leaves_fast_operations_alone() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let file = format!("{}/fast.txt", dir).replace('\\', "/");
+        std::fs::write(&file, "quick").unwrap();
+
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&format!(
+                "function load():\n    return io.read_file(\"{}\")\nlet content = process.with_timeout(5000, load)\n",
+                file
+            ))
+            .unwrap();
+
+        assert_eq!(string_var(&runner, "content"), "quick");
+        io::release_temp_path(&dir);
+    }
+
+    #[test]
+    fn test_with_timeout_is_catchable_and_nests() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function spin():\n    while true:\n        let x = 1\n\
+                 function nested():\n    return process.with_timeout(60000, spin)\n\
+                 let outcome = \"finished\"\n\
+                 try:\n    process.with_timeout(50, nested)\ncatch e:\n    outcome = e.message\n\
+                 let after = \"still running\"\n",
+            )
+            .unwrap();
+
+        assert_eq!(
+            string_var(&runner, "outcome"),
+            "Timeout error: deadline of 50 ms passed"
+        );
+        assert_eq!(string_var(&runner, "after"), "still running");
+    }
+
+    #[test]
+    fn test_function_calls_keep_recursion_closures_and_redefinition() {
+        let mut runner = Runner::new();
+        let code = r#"
+function fib(n):
+{
+    if n < 2:
+        return n
+    return fib(n - 1) + fib(n - 2)
+}
+
+let offset = 100
+function shifted(x):
+    return x + offset
+
+function make_adder():
+{
+    function add_one(x):
+        return x + 1
+    return add_one
+}
+
+let adder = make_adder()
+let first = fib(15)
+let before = shifted(1)
+let added = adder(41)
+
+let old_fib = fib
+function fib(n):
+    return -n
+let redefined = fib(15)
+let kept = old_fib(10)
+"#;
+        runner.execute_code(code).unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("first").unwrap(), Value::Number(610.0));
+        assert_eq!(env.get("before").unwrap(), Value::Number(101.0));
+        assert_eq!(env.get("added").unwrap(), Value::Number(42.0));
+        assert_eq!(env.get("redefined").unwrap(), Value::Number(-15.0));
+        // The old value's recursive calls still go to the body it was defined with
+        assert_eq!(env.get("kept").unwrap(), Value::Number(55.0));
+    }
+
+    #[test]
+    fn test_function_values_share_their_body() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function twice(x):\n    return x * 2\nlet alias = twice\nlet r = alias(4)\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        let (Value::Function { body: original, .. }, Value::Function { body: alias, .. }) =
+            (env.get("twice").unwrap(), env.get("alias").unwrap())
+        else {
+            panic!("expected function values");
+        };
+        assert!(std::rc::Rc::ptr_eq(&original, &alias));
+        assert_eq!(env.get("r").unwrap(), Value::Number(8.0));
+    }
+
+    #[test]
+    fn test_progress_logs_plain_lines_and_clamps_at_total() {
+        progress::capture(Mode::Plain);
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let bar = progress.bar(20, {label: \"Copying\"})\n\
+                 for i in range(0, 25):\n    progress.advance(bar)\n\
+                 progress.finish(bar)\n",
+            )
+            .unwrap();
+
+        let output = progress::take_captured();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "Copying: 0/20 (0%)");
+        assert_eq!(lines[5], "Copying: 10/20 (50%)");
+        assert_eq!(lines[10], "Copying: 20/20 (100%)");
+        assert!(lines[11].starts_with("Copying: done in "));
+        assert_eq!(lines.len(), 12);
+        assert!(!output.contains('\x1b'));
+    }
+
+    /// 2026-01-01T00:02:30Z
+    const SCHEDULE_START: u64 = 1_767_225_750_000;
+
+    /// Run `code` on a virtual clock starting at SCHEDULE_START, then let
+    /// its jobs run for `ms`; returns the printed lines
+    fn run_scheduled(runner: &mut Runner, code: &str, ms: u64) -> Vec<String> {
+        progress::capture(Mode::Plain);
+        runner.execute_code(code).unwrap();
+        runner.run_schedules(Some(schedule::now_ms() + ms)).unwrap();
+        progress::take_captured()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_schedule_cron_fires_at_matching_minutes() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        let mut runner = Runner::new();
+        let fired = run_scheduled(
+            &mut runner,
+            "let midnight = schedule.now() - 150000\n\
+             function report():\n    print((schedule.now() - midnight) / 60000)\n\
+             schedule.cron(\"*/5 * * * *\", report)\n",
+            3_600_000,
+        );
+
+        let expected: Vec<String> = (1..=12).map(|i| (i * 5).to_string()).collect();
+        assert_eq!(fired, expected);
+        assert_eq!(schedule::now_ms(), SCHEDULE_START + 3_600_000);
+
+        let mut error = |expr: &str| {
+            runner
+                .execute_code(&format!("schedule.cron(\"{}\", report)", expr))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("*/5 * * 13 *").contains("month field '13' is out of range 1-12"));
+        assert!(
+            error("0 0 * * fri-mon").contains("day-of-week field 'fri-mon' has an inverted range")
+        );
+        assert!(error("*/0 * * * *").contains("minute field '*/0' has an invalid step"));
+        assert!(error("* * *").contains("expected 5 fields"));
+        assert!(error("0 0 30 2 *").contains("never matches"));
+        schedule::set_virtual_time(None);
+    }
+
+    #[test]
+    fn test_schedule_skips_overlapping_runs() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        schedule::take_warnings();
+        let mut runner = Runner::new();
+        let fired = run_scheduled(
+            &mut runner,
+            "let start = schedule.now()\n\
+             function slow():\n{\n    print((schedule.now() - start) / 1000)\n    await async.sleep(150000)\n}\n\
+             schedule.every(60000, slow)\n",
+            600_000,
+        );
+
+        // Each run takes 2.5 minutes, so the two fire times it spans are skipped
+        assert_eq!(fired, vec!["60", "240", "420", "600"]);
+        let warnings = schedule::take_warnings();
+        assert_eq!(warnings.len(), 4);
+        assert!(warnings
+            .iter()
+            .all(|warning| warning.ends_with("ran for 150000ms; skipped 2 overlapping run(s)")));
+
+        // A job is not re-entered from its own handler either
+        let mut runner = Runner::new();
+        run_scheduled(
+            &mut runner,
+            "function reentrant():\n{\n    await async.sleep(2000)\n    schedule.run_pending()\n}\n\
+             let job = schedule.every(1000, reentrant)\n",
+            1000,
+        );
+        let warnings = schedule::take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].ends_with("is still running; skipping overlapping run"));
+        schedule::set_virtual_time(None);
+    }
+
+    #[test]
+    fn test_sleeps_started_together_overlap() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let start = schedule.now()\n\
+                 let both = await async.all([async.sleep(500), async.sleep(500)])\n\
+                 let together = schedule.now() - start\n\
+                 let winner = async.race(async.sleep(300), async.create_promise(\"now\"))\n\
+                 let first = await winner\n\
+                 let slow = async.sleep(1000)\n\
+                 await async.sleep(400)\n\
+                 await slow\n\
+                 let total = schedule.now() - start\n",
+            )
+            .unwrap();
+        schedule::set_virtual_time(None);
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("both").unwrap().to_string(), "[null, null]");
+        assert_eq!(env.get("together").unwrap(), Value::Number(500.0));
+        // An already-settled promise wins without waiting for the sleep
+        assert_eq!(string_var(&runner, "first"), "now");
+        // Awaiting `slow` waits only for what is left of its second
+        assert_eq!(env.get("total").unwrap(), Value::Number(1500.0));
+    }
+
+    #[test]
+    fn test_assignment_reaches_through_nested_properties_and_indices() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let config = {server: {ports: [80, 443]}, name: \"web\"}\n\
+                 let matrix = [[1, 2], [3, 4]]\n\
+                 config.server.host = \"example.com\"\n\
+                 config.server.ports[1] = 8443\n\
+                 config[\"server\"][\"ports\"][0] = 8080\n\
+                 let row = 1\n\
+                 matrix[row][row - 1] = 30\n\
+                 function bump(): {\n    matrix[0][0] = matrix[0][0] + 10\n}\n\
+                 bump()\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(
+            env.get("config").unwrap().to_string(),
+            "{\"server\": {\"ports\": [8080, 8443], \"host\": example.com}, \"name\": web}"
+        );
+        // The function changed the global, not a copy of it
+        assert_eq!(env.get("matrix").unwrap().to_string(), "[[11, 2], [30, 4]]");
+
+        let failure = |code: &str| {
+            let mut runner = Runner::new();
+            runner
+                .execute_code(&format!(
+                    "let matrix = [[1, 2]]\nlet config = {{server: {{port: 80}}}}\n{}\n",
+                    code
+                ))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(failure("matrix[3][0] = 1").contains(
+            "Cannot assign to matrix[3][0]: index 3 is out of bounds for matrix (length 1)"
+        ));
+        assert!(failure("matrix[0][2] = 1")
+            .contains("index 2 is out of bounds for matrix[0] (length 2)"));
+        assert!(failure("config.client.port = 1")
+            .contains("Cannot assign to config.client.port: config has no property 'client'"));
+        assert!(failure("config.server.port.number = 1")
+            .contains("config.server.port is a number, not an object"));
+        assert!(failure("missing.field = 1").contains("missing"));
+    }
+
+    #[test]
+    fn test_block_and_loop_variables_end_with_their_scope() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let x = \"outer\"\n\
+                 {\n    let x = \"inner\"\n    let only_inside = 1\n}\n\
+                 for i in range(0, 3):\n    let last = i\n\
+                 let item = \"kept\"\n\
+                 for item in [1, 2]:\n    x = x + item\n\
+                 function count(): {\n    let total = 0\n    for n in range(0, 4):\n        total = total + n\n    return total\n}\n\
+                 let counted = count()\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(string_var(&runner, "x"), "outer12");
+        assert_eq!(string_var(&runner, "item"), "kept");
+        assert_eq!(env.get("counted").unwrap(), Value::Number(6.0));
+        for gone in ["only_inside", "i", "last", "n", "total"] {
+            assert!(env.get(gone).is_err(), "{} outlived its scope", gone);
+        }
+
+        let mut env = Environment::new();
+        env.define("x".to_string(), Value::Number(1.0));
+        env.push_scope();
+        env.define("x".to_string(), Value::Number(2.0));
+        assert_eq!(env.remove("x"), Some(Value::Number(2.0)));
+        assert_eq!(env.get("x").unwrap(), Value::Number(1.0));
+        assert_eq!(env.remove("x"), None);
+        env.pop_scope();
+        assert_eq!(env.remove("x"), Some(Value::Number(1.0)));
+        assert!(!env.contains("x"));
+    }
+
+    #[test]
+    fn test_all_and_race_wait_only_as_long_as_they_must() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let start = schedule.now()\n\
+                 let none = await async.all([])\n\
+                 let mixed = await async.all(1, async.sleep(100), async.create_promise(\"x\"))\n\
+                 let after_all = schedule.now() - start\n\
+                 try:\n    await async.all([async.sleep(5000), async.create_rejected_promise(\"boom\")])\n\
+                 catch e:\n    let failed = e.message\n\
+                 let after_failure = schedule.now() - start\n\
+                 let first = await async.race([async.sleep(300), async.sleep(100)])\n\
+                 let after_race = schedule.now() - start\n",
+            )
+            .unwrap();
+        schedule::set_virtual_time(None);
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("none").unwrap().to_string(), "[]");
+        assert_eq!(env.get("mixed").unwrap().to_string(), "[1, null, x]");
+        assert_eq!(env.get("after_all").unwrap(), Value::Number(100.0));
+        // A rejection settles `all` without waiting for the slow sleep
+        assert!(string_var(&runner, "failed").contains("boom"));
+        assert_eq!(env.get("after_failure").unwrap(), Value::Number(100.0));
+        assert_eq!(env.get("first").unwrap(), Value::Null);
+        assert_eq!(env.get("after_race").unwrap(), Value::Number(200.0));
+    }
+
+    #[test]
+    fn test_schedule_cancel_stops_future_runs() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        schedule::take_warnings();
+        let mut runner = Runner::new();
+        let fired = run_scheduled(
+            &mut runner,
+            "let start = schedule.now()\n\
+             function tick():\n    print((schedule.now() - start) / 1000)\n\
+             function broken():\n    io.throw(\"boom\")\n\
+             let ticker = schedule.every(1000, tick)\n\
+             let failing = schedule.every(1000, broken)\n",
+            3000,
+        );
+
+        // The failing job is reported each time without stopping the scheduler
+        assert_eq!(fired, vec!["1", "2", "3"]);
+        assert_eq!(schedule::take_warnings().len(), 3);
+
+        let fired = run_scheduled(
+            &mut runner,
+            "let stopped = schedule.cancel(ticker)\nlet again = schedule.cancel(ticker)\n",
+            5000,
+        );
+        assert!(fired.is_empty());
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("stopped").ok(), Some(Value::Boolean(true)));
+        assert_eq!(env.get("again").ok(), Some(Value::Boolean(false)));
+
+        // With no jobs left the runner returns, so the process can exit
+        runner.execute_code("schedule.cancel(failing)\n").unwrap();
+        let before = schedule::now_ms();
+        runner.run_schedules(None).unwrap();
+        assert_eq!(schedule::now_ms(), before);
+        schedule::take_warnings();
+        schedule::set_virtual_time(None);
+    }
+
+    /// Calls `wrapped(i)` for i in 0..10, 10ms apart; `save` prints the
+    /// argument and the virtual time of each call that gets through
+    const TIMED_BURST: &str = "let start = schedule.now()\n\
+        function save(n):\n    print(n + \"@\" + (schedule.now() - start))\n\
+        for i in range(0, 10):\n{\n    wrapped(i)\n    await async.sleep(10)\n}\n";
+
+    #[test]
+    fn test_debounce_collapses_a_burst_into_one_call() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        let mut runner = Runner::new();
+        let code = TIMED_BURST.replace("for i", "let wrapped = timing.debounce(save, 100)\nfor i");
+        let fired = run_scheduled(&mut runner, &code, 1000);
+        assert_eq!(fired, vec!["9@190"]);
+
+        // Nothing is left waiting, so the runner returns right away
+        let before = schedule::now_ms();
+        runner.run_schedules(None).unwrap();
+        assert_eq!(schedule::now_ms(), before);
+
+        let err = runner
+            .execute_code("timing.debounce(42, 100)\n")
+            .unwrap_err();
+        assert!(matches!(err, InfraError::TypeError { .. }), "{:?}", err);
+        schedule::set_virtual_time(None);
+    }
+
+    #[test]
+    fn test_throttle_leading_and_trailing_calls() {
+        let cases: [(&str, &[&str]); 4] = [
+            ("", &["0@0", "5@50"]),
+            ("{leading: true, trailing: true}", &["0@0", "4@50", "9@100"]),
+            ("{leading: false, trailing: true}", &["4@50", "9@100"]),
+            ("{leading: true}", &["0@0", "5@50"]),
+        ];
+        for (options, expected) in cases {
+            schedule::set_virtual_time(Some(SCHEDULE_START));
+            let mut runner = Runner::new();
+            let options = if options.is_empty() {
+                String::new()
+            } else {
+                format!(", {}", options)
+            };
+            let code = TIMED_BURST.replace(
+                "for i",
+                &format!("let wrapped = timing.throttle(save, 50{})\nfor i", options),
+            );
+            assert_eq!(
+                run_scheduled(&mut runner, &code, 1000),
+                expected,
+                "{}",
+                options
+            );
+        }
+
+        let mut runner = Runner::new();
+        let err = runner
+            .execute_code(
+                "function f():\n    return 1\n\
+                 timing.throttle(f, 50, {leading: false, trailing: false})\n",
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("leading and trailing"), "{}", err);
+        schedule::set_virtual_time(None);
+    }
+
+    #[test]
+    fn test_timing_cancel_and_flush() {
+        schedule::set_virtual_time(Some(SCHEDULE_START));
+        let mut runner = Runner::new();
+        let fired = run_scheduled(
+            &mut runner,
+            "let start = schedule.now()\n\
+             function save(n):\n    print(n + \"@\" + (schedule.now() - start))\n\
+             let wrapped = timing.debounce(save, 100)\n\
+             wrapped(1)\n\
+             let cancelled = timing.cancel(wrapped)\n\
+             let again = timing.cancel(wrapped)\n\
+             wrapped(2)\n\
+             await async.sleep(30)\n\
+             timing.flush(wrapped)\n\
+             let nothing = timing.flush(wrapped)\n",
+            1000,
+        );
+        assert_eq!(fired, vec!["2@30"]);
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("cancelled").ok(), Some(Value::Boolean(true)));
+        assert_eq!(env.get("again").ok(), Some(Value::Boolean(false)));
+        assert_eq!(env.get("nothing").ok(), Some(Value::Null));
+
+        let err = runner.execute_code("timing.cancel(save)\n").unwrap_err();
+        assert!(matches!(err, InfraError::TypeError { .. }), "{:?}", err);
+        schedule::set_virtual_time(None);
+    }
+
+    #[test]
+    fn test_progress_redraws_stacked_bars_in_terminal_mode() {
+        progress::capture(Mode::Terminal);
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let outer = progress.bar(4, {label: \"Hosts\"})\n\
+                 let inner = progress.spinner(\"Connecting\")\n",
+            )
+            .unwrap();
+        assert_eq!(
+            progress::take_captured(),
+            "\r\x1b[2KHosts [------------------------]   0% 0/4\
+             \r\x1b[2KHosts [------------------------]   0% 0/4\n\r\x1b[2K| Connecting"
+        );
+
+        // Finishing the inner bar blanks its line and returns to the outer one
+        runner.execute_code("progress.finish(inner)\n").unwrap();
+        assert_eq!(
+            progress::take_captured(),
+            "\x1b[1A\r\x1b[2KHosts [------------------------]   0% 0/4\n\r\x1b[2K\x1b[1A"
+        );
+
+        runner
+            .execute_code("progress.advance(outer, 10)\nprogress.set_message(outer, \"done\")\n")
+            .unwrap();
+        assert!(progress::take_captured()
+            .starts_with("\r\x1b[2KHosts (done) [########################] 100% 4/4"));
+
+        // The last bar to finish clears its line
+        runner.execute_code("progress.finish(outer)\n").unwrap();
+        assert_eq!(progress::take_captured(), "\r\x1b[2K");
+    }
+
+    #[test]
+    fn test_dispatch_table_calls_by_dynamic_key() {
+        let mut runner = Runner::new();
+        let code = r#"
+function start_service(name):
+    return "starting " + name
+
+let handlers = {start: start_service, shout: string.upper}
+let cmd = "start"
+let by_index = handlers[cmd]("web")
+let by_property = handlers.start("db")
+let native = handlers["shout"]("quiet")
+"#;
+        runner.execute_code(code).unwrap();
+
+        assert_eq!(string_var(&runner, "by_index"), "starting web");
+        assert_eq!(string_var(&runner, "by_property"), "starting db");
+        assert_eq!(string_var(&runner, "native"), "QUIET");
+    }
+
+    #[test]
+    fn test_methods_call_the_receivers_module() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let s = \"  hi there  \"\n\
+                 let shout = s.trim().upper()\n\
+                 let count = [1, 2].length()\n\
+                 let old = string.upper(\"old\")\n\
+                 let keys = {b: 1, a: 2}.keys().join(\",\")\n\
+                 function run(x):\n    return \"ran \" + x\n\
+                 let table = {keys: run}\n\
+                 let own = table.keys(\"first\")\n",
+            )
+            .unwrap();
+
+        assert_eq!(string_var(&runner, "shout"), "HI THERE");
+        assert!(matches!(
+            runner.get_interpreter().get_environment().get("count"),
+            Ok(Value::Number(n)) if n == 2.0
+        ));
+        assert_eq!(string_var(&runner, "old"), "OLD");
+        assert_eq!(string_var(&runner, "keys"), "b,a");
+        // The object's own key wins over object.keys
+        assert_eq!(string_var(&runner, "own"), "ran first");
+
+        match runner.execute_code("\"x\".shout()").unwrap_err() {
+            InfraError::PropertyNotFound {
+                property,
+                object_type,
+                available_properties: Some(available),
+                ..
+            } => {
+                assert_eq!(property, "shout");
+                assert_eq!(object_type.as_deref(), Some("string"));
+                assert!(available.contains(&"upper".to_string()));
+            }
+            other => panic!("expected PropertyNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_table_missing_key_lists_available_functions() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function run(x):\n    return x\nlet handlers = {start: run, stop: run}\n",
+            )
+            .unwrap();
+
+        let err = runner.execute_code("handlers[\"strat\"](1)").unwrap_err();
+        match err {
+            InfraError::UndefinedFunction {
+                name,
+                suggestion,
+                available_functions,
+                ..
+            } => {
+                assert_eq!(name, "handlers.strat");
+                assert_eq!(suggestion.as_deref(), Some("start"));
+                assert_eq!(
+                    available_functions,
+                    Some(vec!["start".to_string(), "stop".to_string()])
+                );
+            }
+            other => panic!("expected UndefinedFunction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_errors_leaving_functions_carry_the_call_stack() {
+        let mut runner = Runner::new();
+        let code = r#"
+function countdown(n):
+{
+    if n == 0:
+        return io.throw("bottom")
+    return countdown(n - 1)
+}
+
+function start():
+    return countdown(5)
+
+let caught = ""
+try:
+    start()
+catch e:
+    caught = e
+"#;
+        runner.execute_code(code).unwrap();
+        // The value thrown is caught as it was thrown
+        assert_eq!(string_var(&runner, "caught"), "bottom");
+
+        // Recursion from one line is cut short, and the frames of the caught
+        // error are gone by the time the next one happens
+        match runner.execute_code("start()") {
+            Err(InfraError::Exception { stack_trace, .. }) => assert_eq!(
+                stack_trace,
+                vec![
+                    "countdown (called at line 6)",
+                    "countdown (called at line 6)",
+                    "countdown (called at line 6)",
+                    "... 2 more",
+                    "countdown (called at line 10)",
+                    "start (called at line 1)",
+                ]
+            ),
+            other => panic!("expected an exception, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_every_error_leaving_a_call_carries_the_call_stack() {
+        let mut runner = Runner::new();
+        let code = r#"
+function e(): return 1 / 0
+function d(): return e()
+function c(): return d()
+function b(): return c()
+function a(): return b()
+let stack = []
+try:
+    a()
+catch err:
+    stack = error.stack(err)
+"#;
+        runner.execute_code(code).unwrap();
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("stack")
+                .unwrap()
+                .to_string(),
+            "[e (called at line 3), d (called at line 4), c (called at line 5), \
+             b (called at line 6), a (called at line 9)]"
+        );
+
+        // The error keeps its kind and position under the trace
+        let error = runner.execute_code("a()").unwrap_err();
+        assert!(
+            matches!(
+                error.untraced(),
+                InfraError::DivisionByZero { line: Some(2), .. }
+            ),
+            "{:?}",
+            error
+        );
+        assert_eq!(
+            error.stack_trace(),
+            [
+                "e (called at line 3)",
+                "d (called at line 4)",
+                "c (called at line 5)",
+                "b (called at line 6)",
+                "a (called at line 1)",
+            ]
+        );
+        assert_eq!(error.to_string(), error.untraced().to_string());
+
+        // Errors outside any call have no trace, even after one that had
+        let error = runner.execute_code("let z = 1 / 0").unwrap_err();
+        assert!(error.stack_trace().is_empty(), "{:?}", error);
+    }
+
+    #[test]
+    fn test_dispatch_table_calls_are_named_in_stack_traces() {
+        let mut runner = Runner::new();
+        let code = r#"
+function fail_step(name):
+    return error.new("StepError", "failed " + name)
+
+function run_all(table):
+    return table.deploy("web")
+
+let steps = {deploy: fail_step}
+let stack = error.stack(run_all(steps))
+"#;
+        runner.execute_code(code).unwrap();
+
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("stack")
+                .unwrap(),
+            Value::Array(vec![
+                Value::String("table.deploy".to_string()),
+                Value::String("run_all".to_string()),
+            ])
+        );
+    }
+
+    /// Small deterministic generator for the wire round-trip test
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_value(rng: &mut XorShift, depth: usize) -> Value {
+        let kinds = if depth >= 4 { 6 } else { 9 };
+        match rng.below(kinds) {
+            0 => Value::Null,
+            1 => Value::Boolean(rng.below(2) == 0),
+            2 => {
+                let special = [f64::NAN, -0.0, f64::INFINITY, f64::MIN_POSITIVE];
+                match rng.below(3) {
+                    0 => Value::Number(special[rng.below(4) as usize]),
+                    _ => Value::Number(f64::from_bits(rng.next())),
+                }
+            }
+            3 => {
+                let len = rng.below(6) as usize;
+                let text: String = (0..len)
+                    .map(|_| ['a', 'Z', '0', ' ', 'é', '🦀', '"'][rng.below(7) as usize])
+                    .collect();
+                Value::String(text)
+            }
+            4 => {
+                let digits: String = (0..=rng.below(40))
+                    .map(|_| (b'0' + rng.below(10) as u8) as char)
+                    .collect();
+                let n: BigInt = digits.parse().unwrap();
+                Value::BigInt(if rng.below(2) == 0 { -n } else { n })
+            }
+            5 => Value::Bytes(std::rc::Rc::new(
+                (0..rng.below(8)).map(|_| rng.next() as u8).collect(),
+            )),
+            6 => Value::Array(
+                (0..rng.below(4))
+                    .map(|_| random_value(rng, depth + 1))
+                    .collect(),
+            ),
+            7 => Value::Tuple(
+                (0..rng.below(4))
+                    .map(|_| random_value(rng, depth + 1))
+                    .collect(),
+            ),
+            _ => {
+                let fields: ObjectMap = (0..rng.below(4))
+                    .map(|i| (format!("k{}", i), random_value(rng, depth + 1)))
+                    .collect();
+                Value::Object(fields)
+            }
+        }
+    }
+
+    /// Equality that treats NaN as equal to itself and tells -0.0 from 0.0
+    fn same_value(a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => x.to_bits() == y.to_bits(),
+            (Value::Array(xs), Value::Array(ys)) => {
+                xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| same_value(x, y))
+            }
+            (Value::Tuple(xs), Value::Tuple(ys)) => {
+                xs.len() == ys.len() && xs.iter().zip(ys.iter()).all(|(x, y)| same_value(x, y))
+            }
+            (Value::Object(xs), Value::Object(ys)) => {
+                xs.len() == ys.len()
+                    && xs
+                        .iter()
+                        .all(|(k, x)| ys.get(k).is_some_and(|y| same_value(x, y)))
+            }
+            _ => a == b,
+        }
+    }
+
+    #[test]
+    fn test_wire_round_trips_random_values() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let value = random_value(&mut rng, 0);
+            let bytes = value.to_bytes().unwrap();
+            let decoded = Value::from_bytes(&bytes).unwrap();
+
+            assert!(same_value(&value, &decoded), "{:?} != {:?}", value, decoded);
+            assert_eq!(decoded.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_wire_rejects_other_versions_and_functions() {
+        let mut bytes = Value::Number(1.0).to_bytes().unwrap();
+        bytes[0] = WIRE_VERSION + 1;
+        assert_eq!(
+            Value::from_bytes(&bytes),
+            Err(WireError::VersionMismatch {
+                found: WIRE_VERSION + 1,
+                expected: WIRE_VERSION,
+            })
+        );
+
+        let mut runner = Runner::new();
+        runner
+            .execute_code("function double(x):\n    return x * 2\n")
+            .unwrap();
+        let function = runner
+            .get_interpreter()
+            .get_environment()
+            .get("double")
+            .unwrap();
+        let nested = Value::Array(vec![Value::Null, function]);
+        assert_eq!(nested.to_bytes(), Err(WireError::Unsupported("function")));
+
+        let err = runner.execute_code("ipc.encode([1, double])").unwrap_err();
+        assert!(matches!(err, InfraError::TypeError { .. }));
+    }
+
+    fn bytes_var(runner: &Runner, name: &str) -> std::rc::Rc<Vec<u8>> {
+        match runner.get_interpreter().get_environment().get(name) {
+            Ok(Value::Bytes(bytes)) => bytes,
+            other => panic!("{} is not bytes: {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip_through_hex_and_files() {
+        let dir = io::create_temp_dir("bytes-", false).unwrap();
+        let dir = dir.replace('\\', "/");
+        let original: Vec<u8> = (0..=255).collect();
+        std::fs::write(format!("{}/in.bin", dir), &original).unwrap();
+
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&format!(
+                "let data = io.read_bytes(\"{0}/in.bin\")\n\
+                 let hex = bytes.to_hex(data)\n\
+                 let back = bytes.from_hex(hex)\n\
+                 io.write_bytes(\"{0}/out.bin\", back)\n\
+                 let same = data == back\n\
+                 let last = data[255]\n\
+                 let shown = bytes.from_hex(\"deadbeef\")\n",
+                dir
+            ))
+            .unwrap();
+
+        assert_eq!(std::fs::read(format!("{}/out.bin", dir)).unwrap(), original);
+        assert_eq!(string_var(&runner, "hex").len(), 512);
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("same").ok(), Some(Value::Boolean(true)));
+        assert_eq!(env.get("last").ok(), Some(Value::Number(255.0)));
+        assert_eq!(env.get("shown").unwrap().to_string(), "<bytes 4: deadbeef>");
+        io::cleanup_temp_paths();
+    }
+
+    #[test]
+    fn test_bytes_reports_offsets_in_errors() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code("let b = bytes.from_array([104, 105, 255, 0])\n")
+            .unwrap();
+
+        let message = |code: &str| {
+            let mut runner = Runner::new();
+            runner
+                .execute_code("let b = bytes.from_array([104, 105, 255, 0])\n")
+                .unwrap();
+            runner.execute_code(code).unwrap_err().to_string()
+        };
+        assert!(message("bytes.to_string(b)").contains("invalid UTF-8 at byte offset 2"));
+        assert!(message("bytes.slice(b, 2, 9)").contains("range 2..9 is out of bounds for 4 bytes"));
+        assert!(message("bytes.slice(b, 3, 1)").contains("range 3..1"));
+        assert!(message("bytes.from_hex(\"abc\")").contains("odd number of hex digits"));
+        assert!(matches!(
+            runner.execute_code("let x = b[4]").unwrap_err(),
+            InfraError::IndexOutOfBounds { .. }
+        ));
+
+        runner
+            .execute_code(
+                "let tail = bytes.slice(b, 2)\nlet text = bytes.to_string(b, \"latin1\")\n",
+            )
+            .unwrap();
+        assert_eq!(*bytes_var(&runner, "tail"), vec![255, 0]);
+        assert_eq!(string_var(&runner, "text"), "hi\u{ff}\0");
+    }
+
+    #[test]
+    fn test_bytes_hold_large_buffers_without_copying() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let big = bytes.from_string(string.repeat(\"x\", 10000000))\n\
+                 let alias = big\n\
+                 let size = bytes.length(big)\n",
+            )
+            .unwrap();
+
+        let big = bytes_var(&runner, "big");
+        assert_eq!(big.len(), 10_000_000);
+        assert!(std::rc::Rc::ptr_eq(&big, &bytes_var(&runner, "alias")));
+        assert_eq!(
+            runner.get_interpreter().get_environment().get("size").ok(),
+            Some(Value::Number(10_000_000.0))
+        );
+    }
+
+    const ENUMS: &str = "enum Status: pending, active, closed\nenum Light: active, off\n";
+
+    #[test]
+    fn test_enum_members_are_equal_only_to_themselves() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&format!(
+                "{}let same = Status.active == Status.active\n\
+                 let other = Status.active == Light.active\n\
+                 let record = {{state: Status.active}}\n\
+                 let parsed = Status.parse(\"closed\") == Status.closed\n\
+                 let all = Status.values()\n\
+                 function label(s):\n\
+                 {{\n\
+                     match s:\n\
+                     {{\n\
+                         Status.pending: return \"waiting\"\n\
+                         Status.active:\n\
+                             return \"running\"\n\
+                         else: return \"done\"\n\
+                     }}\n\
+                 }}\n\
+                 let running = label(Status.active)\n\
+                 let done = label(Light.active)\n",
+                ENUMS
+            ))
+            .unwrap();
+
+        let get = |name: &str| {
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get(name)
+                .unwrap()
+        };
+        assert_eq!(get("same"), Value::Boolean(true));
+        assert_eq!(get("other"), Value::Boolean(false));
+        assert_eq!(get("parsed"), Value::Boolean(true));
+        assert_eq!(string_var(&runner, "running"), "running");
+        assert_eq!(string_var(&runner, "done"), "done");
+        assert_eq!(
+            get("all").to_string(),
+            "[Status.pending, Status.active, Status.closed]"
+        );
+
+        assert_eq!(get("record").to_string(), "{\"state\": Status.active}");
+        // Members serialize by name; Status.parse() reads them back
+        assert_eq!(
+            get("record").to_json_string(false).unwrap(),
+            "{\"state\":\"active\"}"
+        );
+    }
+
+    #[test]
+    fn test_enum_parse_rejects_unknown_names() {
+        let mut runner = Runner::new();
+        runner.execute_code(ENUMS).unwrap();
+
+        let message = runner
+            .execute_code("Status.parse(\"actve\")")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("'actve' is not a member of Status (expected one of pending, active, closed); did you mean 'active'?"),
+            "{}",
+            message
+        );
+        assert!(runner.execute_code("Status.parse(1)").is_err());
+        assert!(matches!(
+            runner.execute_code("let s = Status.paused").unwrap_err(),
+            InfraError::PropertyNotFound { .. }
+        ));
+
+        let tokens = Lexer::new("enum Mode: values, fast\n").tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err().to_string();
+        assert!(
+            error.contains("'values' is reserved for Mode.values()"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_enum_annotations_are_enforced() {
+        let mut runner = Runner::new();
+        runner.execute_code(ENUMS).unwrap();
+
+        runner
+            .execute_code("let s: Status = Status.pending")
+            .unwrap();
+        for code in [
+            "let s: Status = Light.active",
+            "let s: Status = \"pending\"",
+            "function set(s: Status):\n    return s\nset(Light.off)",
+        ] {
+            let error = runner.execute_code(code).unwrap_err();
+            assert!(
+                matches!(error.untraced(), InfraError::TypeError { .. }),
+                "{}: {}",
+                code,
+                error
+            );
+            assert!(error.to_string().contains("Status"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_check_reports_non_exhaustive_enum_matches() {
+        let source = format!(
+            "{}let s = Status.pending\n\
+             match s:\n\
+             {{\n\
+                 Status.pending:\n\
+                     print(\"waiting\")\n\
+                 Status.active: print(\"running\")\n\
+             }}\n\
+             match s:\n\
+             {{\n\
+                 Status.pending: print(\"waiting\")\n\
+                 else: print(\"other\")\n\
+             }}\n\
+             let l = Light.off\n\
+             match l:\n\
+             {{\n\
+                 Light.active: print(\"on\")\n\
+                 Light.off: print(\"off\")\n\
+             }}\n",
+            ENUMS
+        );
+
+        let findings = check_source(&source).findings;
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert_eq!((findings[0].line, findings[0].column), (4, 1));
+        assert_eq!(findings[0].code, "non-exhaustive-match");
+        assert_eq!(
+            findings[0].message,
+            "match over Status has no arm for Status.closed and no 'else' arm"
+        );
+    }
+
+    #[test]
+    fn test_match_case_arms_take_several_values_and_a_default() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(&format!(
+                "{}let seen = []\n\
+                 function label(x):\n\
+                 {{\n\
+                     match x:\n\
+                     {{\n\
+                         case 1, 2:\n\
+                         {{\n\
+                             seen = array.push(seen, x)\n\
+                             return \"small\"\n\
+                         }}\n\
+                         case \"foo\": return \"foo\"\n\
+                         case Status.pending, Status.active: return \"open\"\n\
+                         default: return \"other\"\n\
+                     }}\n\
+                 }}\n\
+                 let labels = [label(2), label(\"foo\"), label(Status.active), label(3)]\n\
+                 let case = 4\n\
+                 let named = \"\"\n\
+                 match 4:\n\
+                 {{\n\
+                     case: named = \"the variable\"\n\
+                 }}\n",
+                ENUMS
+            ))
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(
+            env.get("labels").unwrap().to_string(),
+            "[small, foo, open, other]"
+        );
+        // Only the matching arm ran, once
+        assert_eq!(env.get("seen").unwrap().to_string(), "[2]");
+        assert_eq!(string_var(&runner, "named"), "the variable");
+
+        // A second default arm is reported where it starts
+        let source = "match 1:\n{\n    else: print(1)\n    default: print(2)\n}\n";
+        let err = Parser::new(Lexer::new(source).tokenize().unwrap())
+            .parse()
+            .unwrap_err();
+        assert!(
+            matches!(&err, InfraError::ParseError { message, line: 4, column: 5, .. }
+                if message == "A match can only have one default arm"),
+            "{:?}",
+            err
+        );
+
+        // Members listed in one arm count towards an exhaustive match
+        let source = format!(
+            "{}match Light.off:\n{{\n    case Light.active, Light.off: print(1)\n}}\n\
+             match Status.closed:\n{{\n    case Status.pending, Status.active: print(1)\n    default: print(2)\n}}\n",
+            ENUMS
+        );
+        let findings = check_source(&source).findings;
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+
+    #[test]
+    fn test_literal_typo_is_caught_before_running() {
+        let source = "let started = string.upper(\"go\")\n\
+                      let cfg: {host: string, port: number} = {host: \"db\", prot: 5432}\n";
+
+        let findings = check_source(source).findings;
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert_eq!((findings[0].line, findings[0].column), (2, 54));
+        assert_eq!(findings[0].code, "unknown-field");
+        assert_eq!(
+            findings[0].message,
+            "unknown field cfg.prot; did you mean 'port'?"
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(fix_source(source)
+            .fixed
+            .ends_with("= {host: \"db\", port: 5432}\n"));
+
+        // Nothing runs, not even the statements before the typo
+        let mut runner = Runner::new();
+        match runner.execute_code(source) {
+            Err(InfraError::TypeError {
+                found, hint, line, ..
+            }) => {
+                assert_eq!(found, "unknown field cfg.prot");
+                assert_eq!(hint.as_deref(), Some("Did you mean 'port'?"));
+                assert_eq!(line, Some(2));
+            }
+            other => panic!("expected a type error, got {:?}", other),
+        }
+        assert!(runner
+            .get_interpreter()
+            .get_environment()
+            .get("started")
+            .is_err());
+    }
+
+    #[test]
+    fn test_literal_nested_fields_are_checked_by_path() {
+        let source =
+            "let cfg: {name: string, server: {host: string, port: number}, tags: [string]} = {\n\
+                      \x20   name: \"api\",\n\
+                      \x20   server: {host: \"db\"},\n\
+                      \x20   tags: [\"a\", 2, string.upper(\"c\")]\n\
+                      }\n";
+
+        let findings = check_source(source).findings;
+        let summary: Vec<(usize, usize, &str, &str)> = findings
+            .iter()
+            .map(|f| (f.line, f.column, f.code, f.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    3,
+                    5,
+                    "missing-field",
+                    "missing field cfg.server.port (number)"
+                ),
+                (
+                    4,
+                    17,
+                    "field-type",
+                    "cfg.tags[1] expects string, found number"
+                ),
+            ]
+        );
+
+        let err = Runner::new().execute_code(source).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("field cfg.server.port of type number"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_literal_unknown_field_is_an_error_only_when_strict() {
+        let source = "let cfg: {host: string} = {host: \"db\", debug: true}\n";
+
+        let findings = check_source(source).findings;
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert_eq!(findings[0].message, "unknown field cfg.debug");
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert!(findings[0].fix.is_none());
+        Runner::new().execute_code(source).unwrap();
+
+        literal_check::set_strict(true);
+        let strict_findings = check_source(source).findings;
+        let result = Runner::new().execute_code(source);
+        literal_check::set_strict(false);
+
+        assert_eq!(strict_findings[0].severity, Severity::Error);
+        assert!(
+            matches!(result, Err(InfraError::TypeError { .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_object_keys_keep_insertion_order_everywhere() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let config = {name: \"web\", port: 80, debug: false, port: 8080}\n\
+                 config.region = \"eu\"\n\
+                 config.tier = 2\n\
+                 config.name = \"api\"\n\
+                 let trimmed = object.remove(config, \"debug\")\n\
+                 let merged = object.merge(trimmed, {tier: 3, owner: \"ops\", name: \"db\"})\n\
+                 let keys = object.keys(merged)\n\
+                 let values = object.values(merged)\n\
+                 let entries = object.entries(merged)\n\
+                 let copied = ipc.decode(ipc.encode(merged))\n\
+                 let has_owner = object.has(merged, \"owner\")\n\
+                 let has_debug = object.has(trimmed, \"debug\")\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        let get = |name: &str| env.get(name).unwrap();
+        let strings = |items: &[&str]| {
+            Value::Array(items.iter().map(|s| Value::String(s.to_string())).collect())
+        };
+
+        // A repeated literal key keeps its first position; an assignment to
+        // an existing key keeps its position, new keys append
+        assert_eq!(
+            get("config").to_string(),
+            "{\"name\": api, \"port\": 8080, \"debug\": false, \"region\": eu, \"tier\": 2}"
+        );
+        // Removing leaves the rest in order; merging keeps the left keys'
+        // positions and appends the right's new ones
+        let expected = ["name", "port", "region", "tier", "owner"];
+        assert_eq!(get("keys"), strings(&expected));
+        assert_eq!(get("values").to_string(), "[db, 8080, eu, 3, ops]");
+        assert_eq!(get("has_owner"), Value::Boolean(true));
+        assert_eq!(get("has_debug"), Value::Boolean(false));
+        assert_eq!(
+            get("entries").to_string(),
+            "[[name, db], [port, 8080], [region, eu], [tier, 3], [owner, ops]]"
+        );
+
+        let merged = get("merged");
+        let display = "{\"name\": db, \"port\": 8080, \"region\": eu, \"tier\": 3, \"owner\": ops}";
+        assert_eq!(merged.to_string(), display);
+        assert_eq!(get("copied").to_string(), display);
+        let json = merged.to_json_string(false).unwrap();
+        assert_eq!(
+            json,
+            "{\"name\":\"db\",\"port\":8080,\"region\":\"eu\",\"tier\":3,\"owner\":\"ops\"}"
+        );
+        let parsed = crate::utils::json::parse(&json).unwrap();
+        let parsed_keys: Vec<&String> = parsed.as_object().unwrap().keys().collect();
+        assert_eq!(parsed_keys, expected);
+
+        // Equality and the canonical form ignore order
+        runner
+            .execute_code(
+                "let reordered = {owner: \"ops\", tier: 3, region: \"eu\", port: 8080, name: \"db\"}\n\
+                 let same = reordered == merged\n\
+                 let nested = [{a: [1, 2]}, (3, {b: null})] == [{a: [1, 2.0]}, (3, {b: null})]\n\
+                 let longer = [1, 2] == [1, 2, 3]\n\
+                 let extra = {a: 1} == {a: 1, b: 2}\n\
+                 let differs = {a: [1]} != {a: [2]}\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        let reordered = env.get("reordered").unwrap();
+        assert_eq!(reordered, merged);
+        assert_eq!(env.get("same").unwrap(), Value::Boolean(true));
+        assert_eq!(env.get("nested").unwrap(), Value::Boolean(true));
+        assert_eq!(env.get("longer").unwrap(), Value::Boolean(false));
+        assert_eq!(env.get("extra").unwrap(), Value::Boolean(false));
+        assert_eq!(env.get("differs").unwrap(), Value::Boolean(true));
+        assert_eq!(
+            reordered.to_canonical_string(),
+            merged.to_canonical_string()
+        );
+
+        // The bytecode backend builds literals in source order too
+        let program = parse_source("let point = {y: 2, x: 1, z: 3}\n");
+        let mut vm = VM::new();
+        vm.interpret(Compiler::new().compile(&program).unwrap())
+            .unwrap();
+        assert_eq!(
+            vm.local(0).unwrap().to_string(),
+            "{\"y\": 2, \"x\": 1, \"z\": 3}"
+        );
+    }
+
+    #[test]
+    fn test_object_has_checks_for_a_key() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let user = {name: \"ada\", email: null}\n\
+                 let has_name = object.has(user, \"name\")\n\
+                 let has_email = object.has(user, \"email\")\n\
+                 let has_age = object.has(user, \"age\")\n\
+                 let empty = object.has({}, \"name\")\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        // A key set to null is still there
+        for (name, expected) in [
+            ("has_name", true),
+            ("has_email", true),
+            ("has_age", false),
+            ("empty", false),
+        ] {
+            assert_eq!(env.get(name).unwrap(), Value::Boolean(expected), "{}", name);
+        }
+
+        for call in ["object.has([\"name\"], \"name\")", "object.has({a: 1}, 1)"] {
+            match runner.execute_code(call) {
+                Err(InfraError::TypeError { context, .. }) => {
+                    assert_eq!(context.as_deref(), Some("object.has function"), "{}", call)
+                }
+                other => panic!("{}: expected a type error, got {:?}", call, other),
+            }
+        }
+
+        for (call, found) in [
+            ("object.has({a: 1})", 1),
+            ("object.has({a: 1}, \"a\", \"b\")", 3),
+        ] {
+            match runner.execute_code(call) {
+                Err(InfraError::ArgumentCountMismatch {
+                    expected,
+                    found: f,
+                    function_name,
+                    ..
+                }) => {
+                    assert_eq!((expected, f), (2, found), "{}", call);
+                    assert_eq!(function_name.as_deref(), Some("object.has"));
+                }
+                other => panic!(
+                    "{}: expected an argument count error, got {:?}",
+                    call, other
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_prelude_functions_need_no_import() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let pairs = zip([\"a\", \"b\", \"c\"], [1, 2])\n\
+                 let numbered = enumerate([\"x\", \"y\"])\n\
+                 let total = fixed(2.5, 2)\n\
+                 let count = plural(3, \"file\")\n\
+                 function fail():\n    return error.new(\"Busy\", \"try later\")\n\
+                 let last = retry(fail, 3)\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        let pair = |a: Value, b: Value| Value::Array(vec![a, b]);
+        assert_eq!(
+            env.get("pairs").unwrap(),
+            Value::Array(vec![
+                pair(Value::String("a".to_string()), Value::Number(1.0)),
+                pair(Value::String("b".to_string()), Value::Number(2.0)),
+            ])
+        );
+        assert_eq!(
+            env.get("numbered").unwrap(),
+            Value::Array(vec![
+                pair(Value::Number(0.0), Value::String("x".to_string())),
+                pair(Value::Number(1.0), Value::String("y".to_string())),
+            ])
+        );
+        assert_eq!(string_var(&runner, "total"), "2.50");
+        assert_eq!(string_var(&runner, "count"), "3 files");
+        assert_eq!(
+            error::message(&[env.get("last").unwrap()]).unwrap(),
+            Value::String("try later".to_string())
+        );
+    }
+
+    #[test]
+    fn test_no_prelude_leaves_prelude_names_undefined() {
+        prelude::set_enabled(false);
+        let implicit = Runner::new().execute_code("let pairs = zip([1], [2])\n");
+        let mut runner = Runner::new();
+        let explicit = runner.execute_code(
+            "import {zip} from \"std:prelude\"\nlet pairs = array.length(zip([1], [2]))\n",
+        );
+        prelude::set_enabled(true);
+
+        assert!(
+            matches!(&implicit, Err(InfraError::UndefinedFunction { name, .. }) if name == "zip"),
+            "{:?}",
+            implicit
+        );
+        explicit.unwrap();
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("pairs")
+                .unwrap(),
+            Value::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn test_script_definitions_shadow_the_prelude() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function zip(a, b):\n    return \"mine\"\n\
+                 let plural = \"not a function\"\n\
+                 let zipped = zip([1], [2])\n",
+            )
+            .unwrap();
+
+        assert_eq!(string_var(&runner, "zipped"), "mine");
+        assert_eq!(string_var(&runner, "plural"), "not a function");
+    }
+
+    #[test]
+    fn test_conversion_builtins_need_no_prefix() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let text = str(42) + \"/\" + str([1, 2])\n\
+                 let whole = int(\" -3.7 \") + int(9.99) + int(true)\n\
+                 let half = float(\"2.5\")\n\
+                 let flags = [bool(\"\"), bool(0), bool([1]), bool(null)]\n\
+                 let types = [type(1), type(\"a\"), type({}), type(len)]\n\
+                 let lengths = [len(\"héllo\"), len([1, 2, 3]), len({a: 1, b: 2}), convert.len(\"ab\")]\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(string_var(&runner, "text"), "42/[1, 2]");
+        assert_eq!(env.get("whole").unwrap(), Value::Number(7.0));
+        assert_eq!(env.get("half").unwrap(), Value::Number(2.5));
+        assert_eq!(
+            env.get("flags").unwrap().to_string(),
+            "[false, false, true, false]"
+        );
+        assert_eq!(
+            env.get("types").unwrap().to_string(),
+            "[number, string, object, function]"
+        );
+        assert_eq!(env.get("lengths").unwrap().to_string(), "[5, 3, 2, 2]");
+
+        let err = Runner::new()
+            .execute_code("let n = int(\"12abc\")\n")
+            .unwrap_err();
+        assert!(
+            matches!(err.untraced(), InfraError::TypeError { found, hint: Some(hint), .. }
+                if found == "\"12abc\"" && hint.starts_with("Check the input")),
+            "{:?}",
+            err
+        );
+        assert!(Runner::new().execute_code("let n = len(5)\n").is_err());
+    }
+
+    #[test]
+    fn test_globals_call_module_functions_without_a_prefix() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let results = [abs(-3), max(1, 7, 2), min([4, 2]), round(2.5), floor(2.7), ceil(2.1), len(\"abc\")]\n\
+                 let biggest = max\n\
+                 let picked = biggest(3, 9)\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(
+            env.get("results").unwrap().to_string(),
+            "[3, 7, 2, 3, 2, 3, 3]"
+        );
+        assert_eq!(env.get("picked").unwrap(), Value::Number(9.0));
+
+        let err = Runner::new().execute_code("zip2([1], [2])\n").unwrap_err();
+        assert!(
+            matches!(&err, InfraError::UndefinedFunction { name, suggestion: Some(suggestion), .. }
+                if name == "zip2" && suggestion == "zip"),
+            "{:?}",
+            err
+        );
+
+        let library = StandardLibrary::new();
+        assert!(library.get_global("len").is_some());
+        assert!(library.get_global("sqrt").is_none());
+        assert!(library.get_globals().contains(&"abs"));
+    }
+
+    #[test]
+    fn test_every_builtin_function_is_described() {
+        let mut library = StandardLibrary::new();
+        for module in library.get_modules() {
+            for function in library.get_module_functions(module).unwrap() {
+                assert!(
+                    library.describe(module, function).is_some(),
+                    "{}.{} has no metadata",
+                    module,
+                    function
+                );
+            }
+        }
+        // Functions keeping per-evaluator state are implemented there
+        let evaluator_functions = crate::backend::evaluator::EVALUATOR_FUNCTIONS;
+        for info in crate::stdlib::metadata::FUNCTIONS {
+            assert!(
+                library.get_function(info.module, info.name).is_some()
+                    || evaluator_functions.contains(&(info.module, info.name)),
+                "{}.{} is described but not registered",
+                info.module,
+                info.name
+            );
+        }
+
+        let replace = library.describe("string", "replace").unwrap();
+        assert_eq!(replace.arity.to_string(), "3 arguments");
+        let slice = library.describe("array", "slice").unwrap();
+        assert_eq!(slice.arity.to_string(), "2 to 3 arguments");
+        let race = library.describe("async", "race").unwrap();
+        assert_eq!(race.arity.to_string(), "any number of arguments");
+
+        // A host's function is not the one described
+        library.register("string", "replace", math::abs);
+        assert!(library.describe("string", "replace").is_none());
+    }
+
+    #[test]
+    fn test_script_definitions_shadow_the_builtins() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function len(value):\n    return \"mine\"\n\
+                 let type = \"kept\"\n\
+                 let abs = function(x): \"also mine\"\n\
+                 let measured = len([1, 2])\n\
+                 let absolute = abs(-1)\n",
+            )
+            .unwrap();
+
+        assert_eq!(string_var(&runner, "measured"), "mine");
+        assert_eq!(string_var(&runner, "type"), "kept");
+        assert_eq!(string_var(&runner, "absolute"), "also mine");
+    }
+
+    #[test]
+    fn test_class_instances_keep_separate_state() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "class Counter:\n\
+                 {\n\
+                 \x20   count = 0\n\
+                 \x20   step = 1\n\
+                 \n\
+                 \x20   init(start):\n\
+                 \x20   {\n\
+                 \x20       this.count = start\n\
+                 \x20   }\n\
+                 \n\
+                 \x20   increment():\n\
+                 \x20   {\n\
+                 \x20       this.count = this.count + this.step\n\
+                 \x20       return this.count\n\
+                 \x20   }\n\
+                 \n\
+                 \x20   function describe() -> string:\n\
+                 \x20       return \"at \" + str(this.count)\n\
+                 }\n\
+                 let a = Counter(5)\n\
+                 let b = new Counter(10)\n\
+                 a.increment()\n\
+                 a.increment()\n\
+                 let last = b.increment()\n\
+                 let alias = a\n\
+                 alias.step = 10\n\
+                 let bump = alias.increment\n\
+                 bump()\n\
+                 let shown = a.describe()\n\
+                 let text = str(b)\n\
+                 let counts = [a.count, b.count]\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        // Instances are shared, so the alias and the bound method change a
+        assert_eq!(env.get("counts").unwrap().to_string(), "[17, 11]");
+        assert_eq!(env.get("last").unwrap(), Value::Number(11.0));
+        assert_eq!(string_var(&runner, "shown"), "at 17");
+        assert_eq!(
+            string_var(&runner, "text"),
+            "Counter {\"count\": 11, \"step\": 1}"
+        );
+        assert_eq!(env.get("a").unwrap(), env.get("alias").unwrap());
+        assert_ne!(env.get("a").unwrap(), env.get("b").unwrap());
+    }
+
+    #[test]
+    fn test_instances_that_contain_themselves() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "class Node:\n\
+                 {\n\
+                 \x20   name = \"\"\n\
+                 \x20   next = null\n\
+                 }\n\
+                 let a = Node()\n\
+                 a.name = \"a\"\n\
+                 let b = Node()\n\
+                 b.name = \"b\"\n\
+                 a.next = b\n\
+                 b.next = a\n\
+                 let text = str(a)\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(
+            string_var(&runner, "text"),
+            "Node {\"name\": a, \"next\": Node {\"name\": b, \"next\": <cycle>}}"
+        );
+        assert_eq!(env.get("a").unwrap().to_canonical_string(), None);
+        assert!(format!("{:?}", env.get("a").unwrap()).contains("<cycle>"));
+
+        let err = Runner::new()
+            .execute_code(
+                "class Node:\n{\n    next = null\n}\n\
+                 let a = Node()\n\
+                 a.next = Node()\n\
+                 a.next.next = a\n\
+                 json.stringify({head: a})\n",
+            )
+            .unwrap_err();
+        assert!(
+            matches!(err.untraced(), InfraError::TypeError { context: Some(context), .. }
+                if context == "json.stringify() at $.head.next.next"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_class_errors() {
+        let class = "class Point:\n{\n    x = 0\n}\n";
+        let run = |code: &str| {
+            Runner::new()
+                .execute_code(&format!("{}{}", class, code))
+                .unwrap_err()
+        };
+
+        let err = run("let p = Point(1)\n");
+        assert!(
+            matches!(&err, InfraError::ClassError { class_name: Some(name), .. } if name == "Point"),
+            "{:?}",
+            err
+        );
+        let err = run("let p = Point()\nlet z = p.z\n");
+        assert!(
+            matches!(&err, InfraError::PropertyNotFound { property, object_type: Some(object_type), .. }
+                if property == "z" && object_type == "Point instance"),
+            "{:?}",
+            err
+        );
+        let err = run("let p = Point()\np.missing.y = 1\n");
+        assert_eq!(
+            err.to_string(),
+            "Runtime error: Cannot assign to p.missing.y: p has no property 'missing'"
+        );
+        let err = run("function f():\n    return this\nf()\n");
+        assert!(
+            matches!(err.untraced(), InfraError::ClassError { message, .. } if message.contains("'this'")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_subclasses_inherit_and_call_super() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "class Animal:\n\
+                 {\n\
+                 \x20   legs = 4\n\
+                 \x20   init(name):\n\
+                 \x20       this.name = name\n\
+                 \x20   speak():\n\
+                 \x20       return this.name + \" makes a sound\"\n\
+                 \x20   describe():\n\
+                 \x20       return this.speak() + \" on \" + str(this.legs) + \" legs\"\n\
+                 }\n\
+                 class Dog extends Animal:\n\
+                 {\n\
+                 \x20   init(name, breed):\n\
+                 \x20   {\n\
+                 \x20       super.init(name)\n\
+                 \x20       this.breed = breed\n\
+                 \x20   }\n\
+                 \x20   speak():\n\
+                 \x20       return super.speak() + \": woof\"\n\
+                 }\n\
+                 class Puppy extends Dog:\n\
+                 {\n\
+                 \x20   speak():\n\
+                 \x20       return super.speak() + \" (squeaky)\"\n\
+                 }\n\
+                 function pet(animal: Animal):\n\
+                 \x20   return \"petted \" + animal.name\n\
+                 let rex = Dog(\"Rex\", \"collie\")\n\
+                 let described = rex.describe()\n\
+                 let pip: Animal = Puppy(\"Pip\", \"pug\")\n\
+                 let squeak = pip.speak()\n\
+                 let petted = pet(pip)\n\
+                 let kind = type(pip)\n",
+            )
+            .unwrap();
+
+        assert_eq!(
+            string_var(&runner, "described"),
+            "Rex makes a sound: woof on 4 legs"
+        );
+        assert_eq!(
+            string_var(&runner, "squeak"),
+            "Pip makes a sound: woof (squeaky)"
+        );
+        assert_eq!(string_var(&runner, "petted"), "petted Pip");
+        assert_eq!(string_var(&runner, "kind"), "Puppy");
+    }
+
+    #[test]
+    fn test_inheritance_errors_name_the_class_and_method() {
+        let err = Runner::new()
+            .execute_code("let five = 5\nclass Bad extends five:\n{\n}\n")
+            .unwrap_err();
+        assert!(
+            matches!(&err, InfraError::ClassError { class_name: Some(class), message, .. }
+                if class == "Bad" && message.contains("five is a number, not a class")),
+            "{:?}",
+            err
+        );
+
+        let err = Runner::new()
+            .execute_code(
+                "class Lonely:\n{\n    hi():\n        return super.hi()\n}\nLonely().hi()\n",
+            )
+            .unwrap_err();
+        assert!(
+            matches!(err.untraced(), InfraError::ClassError { class_name: Some(class), method_name: Some(method), .. }
+                if class == "Lonely" && method == "hi"),
+            "{:?}",
+            err
+        );
+
+        let err = Runner::new().execute_code("super.init()\n").unwrap_err();
+        assert!(
+            matches!(&err, InfraError::ClassError { class_name: None, method_name: Some(method), .. }
+                if method == "init"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_null_safe_access_and_coalescing() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "let missing = {\"server\": null}\n\
+                 let present = {\"server\": {\"port\": 9000}}\n\
+                 let nothing = null\n\
+                 let calls = 0\n\
+                 function fallback():\n{\n    calls = calls + 1\n    return 1\n}\n\
+                 let results = [\n\
+                 \x20   missing?.server?.port ?? 8080,\n\
+                 \x20   present?.server?.port ?? 8080,\n\
+                 \x20   0 ?? 5,\n\
+                 \x20   \"\" ?? \"x\",\n\
+                 \x20   false ?? true,\n\
+                 \x20   nothing?.[0],\n\
+                 \x20   nothing?.size(fallback()),\n\
+                 \x20   null ?? null ?? 3,\n\
+                 \x20   7 ?? fallback()\n\
+                 ]\n",
+            )
+            .unwrap();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(
+            env.get("results").unwrap(),
+            Value::Array(vec![
+                Value::Number(8080.0),
+                Value::Number(9000.0),
+                Value::Number(0.0),
+                Value::String(String::new()),
+                Value::Boolean(false),
+                Value::Null,
+                Value::Null,
+                Value::Number(3.0),
+                Value::Number(7.0),
+            ])
+        );
+        // Neither a skipped call's arguments nor `??`'s unneeded right side run
+        assert_eq!(env.get("calls").unwrap(), Value::Number(0.0));
+
+        // Plain `.` still fails on null, and `?.` cannot be assigned to
+        assert!(Runner::new()
+            .execute_code("let a = null\nlet b = a.port\n")
+            .is_err());
+        let err = Runner::new()
+            .execute_code("let a = {}\na?.port = 1\n")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid assignment target"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_conditional_expressions_evaluate_one_branch() {
+        let source = "let n = -3\n\
+                      let sign = n > 0 ? \"pos\" : n < 0 ? \"neg\" : \"zero\"\n\
+                      let safe = n < 0 ? 1 : 1 / 0\n\
+                      let grouped = (n < 0 ? false : true) ? \"a\" : \"b\"\n";
+        let mut runner = Runner::new();
+        runner.execute_code(source).unwrap();
+        assert_eq!(string_var(&runner, "sign"), "neg");
+        assert_eq!(string_var(&runner, "grouped"), "b");
+
+        // The VM jumps over the branch it does not take
+        let mut vm = VM::new();
+        vm.interpret(Compiler::new().compile(&parse_source(source)).unwrap())
+            .unwrap();
+        assert_eq!(vm.local(1), Some(&Value::String("neg".to_string())));
+        assert_eq!(vm.local(2), Some(&Value::Number(1.0)));
+        assert_eq!(vm.local(3), Some(&Value::String("b".to_string())));
+
+        // Nesting in the else side is printed without parentheses, in the
+        // condition or an operand with them
+        let options = printer::FormattingOptions::default();
+        let printed = printer::format_program(&parse_source(source), &options);
+        assert!(printed.contains("n > 0 ? \"pos\" : n < 0 ? \"neg\" : \"zero\""));
+        assert!(printed.contains("(n < 0 ? false : true) ? \"a\" : \"b\""));
+        let printed = printer::format_program(&parse_source("let x = 1 + (a ? 2 : 3)\n"), &options);
+        assert!(printed.contains("1 + (a ? 2 : 3)"), "{}", printed);
+    }
+
+    #[test]
+    fn test_default_and_rest_parameters() {
+        let mut runner = Runner::new();
+        let result = runner
+            .evaluate_code(
+                "function greet(name, greeting = \"hello\"):\n    return greeting + \", \" + name\n\
+                 function span(a, b = a * 2):\n    return [a, b]\n\
+                 function sum(...nums: [number]) -> number:\n\
+                 {\n    let total = 0\n    for n in nums:\n        total = total + n\n    return total\n}\n\
+                 function tag(label, sep = \":\", ...rest):\n    return label + sep + str(len(rest))\n\
+                 @memo.wrap\nfunction count(first, ...rest):\n    return first + len(rest)\n\
+                 [greet(\"Ann\"), greet(\"Bob\", \"hi\"), span(3), sum(), sum(1, 2, 3), \
+                 tag(\"a\"), tag(\"a\", \"-\", 1, 2), count(1, 5, 6)]\n",
+            )
+            .unwrap();
+        assert_eq!(
+            result.to_string(),
+            "[hello, Ann, hi, Bob, [3, 6], 0, 6, a:0, a-2, 3]"
+        );
+
+        // Too few arguments names how many are required, too many how many
+        // there are
+        for (call, expected, found) in [("greet()", 1, 0), ("greet(1, 2, 3)", 2, 3)] {
+            let err = runner.evaluate_code(call).unwrap_err();
+            assert!(
+                matches!(err.untraced(), InfraError::ArgumentCountMismatch { expected: e, found: f, .. }
+                    if *e == expected && *f == found),
+                "{}: {:?}",
+                call,
+                err
+            );
+        }
+        // The rest array is checked against the parameter's annotation
+        let err = runner.evaluate_code("sum(1, \"two\")").unwrap_err();
+        assert!(
+            matches!(err.untraced(), InfraError::TypeError { .. }),
+            "{:?}",
+            err
+        );
+
+        // Defaults and rest parameters are printed back as written
+        let source =
+            "function tag(label, sep: string = \":\", ...rest) -> string:\n    return label\n";
+        let options = printer::FormattingOptions::default();
+        assert_eq!(
+            printer::format_program(&parse_source(source), &options),
+            source
+        );
+
+        for (source, message) in [
+            (
+                "function f(a = 1, b):\n    return a\n",
+                "Parameter 'b' needs a default value, as the ones before it have",
+            ),
+            (
+                "function f(...a, b):\n    return a\n",
+                "A rest parameter must be the last parameter",
+            ),
+        ] {
+            let err = Parser::new(Lexer::new(source).tokenize().unwrap())
+                .parse()
+                .unwrap_err();
+            assert!(
+                matches!(&err, InfraError::ParseError { message: m, .. } if m == message),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_embedded_prelude_parses() {
+        let exports = prelude::load(prelude::SOURCE).unwrap();
+        for name in ["enumerate", "zip", "retry", "pad", "fixed", "plural"] {
+            assert!(
+                matches!(exports.get(name), Some(Value::Function { .. })),
+                "prelude does not export {}",
+                name
+            );
+        }
+
+        // A broken prelude is reported against its own lines
+        let err = prelude::load("export function ok():\n    return 1\n\nlet = 2\n").unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("internal error in the embedded prelude at prelude.infra line 4"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_decorators_apply_innermost_first() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function from_inner():\n    return \"from inner\"\n\
+                 function ran_second():\n    return \"outer ran second\"\n\
+                 function ran_first():\n    return \"outer ran first\"\n\
+                 function replace(other):\n{\n    function decorate(f):\n        return other\n    return decorate\n}\n\
+                 function after_inner(f):\n{\n    if f() == \"from inner\":\n        return ran_second\n    return ran_first\n}\n\
+                 @after_inner\n@replace(from_inner)\nfunction base():\n    return \"original\"\n\
+                 let result = base()\n\
+                 @memo.wrap\nfunction square(n):\n    return n * n\n\
+                 let first = square(4)\nlet again = square(4)\nlet stats = memo.stats(square)\n\
+                 @memo.wrap({max_entries: 0})\nfunction cube(n):\n    return n * n * n\n\
+                 cube(2)\ncube(2)\nlet uncached = memo.stats(cube)\n\
+                 let calls = 0\n\
+                 @timing.throttle(60000)\nfunction count():\n    calls = calls + 1\n\
+                 count()\ncount()\n",
+            )
+            .unwrap();
+
+        // `@replace(from_inner)` calls replace(from_inner) for the decorator
+        assert_eq!(string_var(&runner, "result"), "outer ran second");
+        // A module function works as a decorator too, called or not
+        assert_eq!(number_field(&runner, "stats", "hits"), 1.0);
+        assert_eq!(number_field(&runner, "stats", "misses"), 1.0);
+        assert_eq!(number_field(&runner, "uncached", "misses"), 2.0);
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("calls")
+                .unwrap(),
+            Value::Number(1.0)
+        );
+
+        // Decorators are kept in the AST and printed back in source order
+        let source = "@after_inner\n@replace(from_inner)\nfunction base():\n    return 1\n";
+        let program = parse_source(source);
+        let Some(Stmt::Function { decorators, .. }) = program.statements.first() else {
+            panic!("expected a function");
+        };
+        assert_eq!(decorators.len(), 2);
+        assert_eq!(
+            printer::format_program(&program, &Default::default()),
+            source
+        );
+    }
+
+    #[test]
+    fn test_decorated_exports_see_module_functions() {
+        let exports = prelude::load(
+            "function double(n):\n    return n * 2\n\
+             function replace(other):\n{\n    function decorate(f):\n        return other\n    return decorate\n}\n\
+             @replace(double)\nexport function square(n):\n    return n * n\n",
+        )
+        .unwrap();
+
+        assert!(
+            matches!(exports.get("square"), Some(Value::Function { name, .. }) if name == "double"),
+            "{:?}",
+            exports.get("square")
+        );
+    }
+
+    #[test]
+    fn test_decorator_must_return_a_function() {
+        let err = Runner::new()
+            .execute_code(
+                "function broken(f):\n    return 3\n@broken\nfunction answer():\n    return 42\n",
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, InfraError::TypeError { context: Some(context), .. }
+                if context.contains("@broken")),
+            "{:?}",
+            err
+        );
+        let err = Runner::new()
+            .execute_code(
+                "function broken(n):\n    return n\n@broken(3)\nfunction answer():\n    return 42\n",
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, InfraError::TypeError { context: Some(context), .. }
+                if context == "decorator made by @broken(3)"),
+            "{:?}",
+            err
+        );
+
+        for source in [
+            "@handlers[0]\nfunction f():\n    return 1\n",
+            "@trace\nlet x = 1\n",
+        ] {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let err = Parser::new(tokens).parse().unwrap_err();
+            assert!(matches!(err, InfraError::ParseError { .. }), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_huge_print_is_truncated_without_rendering_it_all() {
+        // 1,000 rows of 200 100-byte items: about 20MB of text
+        let item = Value::String("x".repeat(100));
+        let row = Value::Array(vec![item; 200]);
+        let huge = Value::Array(vec![row; 1000]);
+        let row_size = 2 + 200 * 100 + 199 * 2;
+        let full_size = 2 + 1000 * row_size + 999 * 2;
+
+        // The writer holds on to no more than the limit however much it is given
+        let mut writer = LimitedWriter::new(Some(1000));
+        std::fmt::Write::write_fmt(&mut writer, format_args!("{}", huge)).unwrap();
+        assert_eq!(writer.retained(), 1000);
+        let rendered = writer.finish();
+        assert_eq!(rendered.truncated, full_size - 1000);
+
+        progress::capture(Mode::Plain);
+        output::set_interactive(Some(false));
+        let mut runner = Runner::new();
+        runner.set_output_limits(OutputLimits {
+            max_print_size: Some(1000),
+            max_output: None,
+        });
+        runner
+            .get_interpreter_mut()
+            .execute_statement(&Stmt::Print(Expr::Literal(huge)))
+            .unwrap();
+        // A cut never splits a character
+        runner
+            .execute_code("print(string.repeat(\"é\", 600))\n")
+            .unwrap();
+        output::set_interactive(None);
+        output::set_limits(OutputLimits::default());
+
+        let printed = progress::take_captured();
+        let lines: Vec<&str> = printed.lines().collect();
+        let marker = |more: usize| {
+            format!(
+                "... [truncated, {} more bytes; rerun with --max-print-size to raise]",
+                more
+            )
+        };
+        assert_eq!(
+            lines[0],
+            format!("{}{}", rendered.text, marker(full_size - 1000))
+        );
+        assert!(lines[0].starts_with("[[xxx"));
+        assert_eq!(lines[1], format!("{}{}", "é".repeat(500), marker(200)));
+    }
+
+    #[test]
+    fn test_output_budget_skips_later_prints() {
+        progress::capture(Mode::Plain);
+        output::set_interactive(Some(false));
+        let mut runner = Runner::new();
+        runner.set_output_limits(OutputLimits {
+            max_print_size: None,
+            max_output: Some(20),
+        });
+        runner
+            .execute_code("for i in range(0, 10):\n    print(\"line \" + i)\n")
+            .unwrap();
+        let skipped = output::skipped_prints();
+        output::set_interactive(None);
+        output::set_limits(OutputLimits::default());
+
+        // Two 7-byte lines fit in 20 bytes; the third and later are skipped
+        assert_eq!(progress::take_captured(), "line 0\nline 1\n");
+        assert_eq!(skipped, 8);
+    }
+
+    #[test]
+    fn test_output_limits_do_not_apply_on_a_terminal() {
+        progress::capture(Mode::Plain);
+        output::set_interactive(Some(true));
+        let mut runner = Runner::new();
+        runner.set_output_limits(OutputLimits {
+            max_print_size: Some(10),
+            max_output: Some(10),
+        });
+        runner
+            .execute_code("print(\"a line longer than ten bytes\")\nprint(\"and another one\")\n")
+            .unwrap();
+        output::set_interactive(None);
+        output::set_limits(OutputLimits::default());
+
+        assert_eq!(
+            progress::take_captured(),
+            "a line longer than ten bytes\nand another one\n"
+        );
+        assert_eq!(output::parse_size("64K"), Some(Some(64 * 1024)));
+        assert_eq!(output::parse_size("0"), Some(None));
+        assert_eq!(output::parse_size("2X"), None);
+    }
+
+    fn host_greeting(args: &[Value]) -> crate::core::Result<Value> {
+        Ok(Value::String(format!("hello {}", args[0])))
+    }
+
+    fn host_upper(_args: &[Value]) -> crate::core::Result<Value> {
+        Ok(Value::String("shadowed".to_string()))
+    }
+
+    #[test]
+    fn test_engines_share_a_library_but_not_their_overlays() {
+        let shared = StandardLibrary::new();
+        let mut first = Engine::builder().with_shared_stdlib(&shared).build();
+        let mut second = Engine::builder().with_shared_stdlib(&shared).build();
+        first.register_native("host", "greet", host_greeting);
+        first.register_native("string", "upper", host_upper);
+
+        assert_eq!(
+            first.evaluate("host.greet(\"ada\")\n").unwrap(),
+            Value::String("hello ada".to_string())
+        );
+        assert_eq!(
+            first.evaluate("string.upper(\"a\")\n").unwrap(),
+            Value::String("shadowed".to_string())
+        );
+        // Neither the sibling nor the shared library sees the overlay
+        let err = second.evaluate("host.greet(\"ada\")\n").unwrap_err();
+        assert!(
+            matches!(&err, InfraError::UndefinedVariable { name, .. } if name == "host"),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            second.evaluate("string.upper(\"a\")\n").unwrap(),
+            Value::String("A".to_string())
+        );
+        assert!(shared.get_function("host", "greet").is_none());
+
+        // Unregistering uncovers the built-in again
+        assert!(first.unregister_native("string", "upper"));
+        assert!(!first.unregister_native("string", "upper"));
+        assert_eq!(
+            first.evaluate("string.upper(\"a\")\n").unwrap(),
+            Value::String("A".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shared_library_builds_engines_on_many_threads() {
+        fn send_and_sync<T: Send + Sync>() {}
+        send_and_sync::<StandardLibrary>();
+
+        let shared = StandardLibrary::new();
+        // Values stay on their engine's thread; only text comes back
+        let results: Vec<String> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|i| {
+                    let shared = &shared;
+                    scope.spawn(move || {
+                        let mut engine = Engine::builder()
+                            .with_shared_stdlib(shared)
+                            .with_native("host", "greet", host_greeting)
+                            .build();
+                        engine
+                            .evaluate(&format!("host.greet({}) + \" \" + math.sqrt(16)\n", i))
+                            .unwrap()
+                            .to_string()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(*result, format!("hello {} 4", i));
+        }
+    }
+
+    #[test]
+    fn test_engine_dry_run_is_per_instance() {
+        let dir = io::create_temp_dir("engine-", false).unwrap();
+        let dir = dir.replace('\\', "/");
+        let (dry_path, real_path) = (format!("{}/dry.txt", dir), format!("{}/real.txt", dir));
+        let write = |path: &str| format!("io.write_file(\"{}\", \"data\")\n", path);
+
+        let shared = StandardLibrary::new();
+        let mut dry = Engine::builder()
+            .with_shared_stdlib(&shared)
+            .dry_run(true)
+            .build();
+        let mut real = Engine::builder().with_shared_stdlib(&shared).build();
+        dry.execute(&write(&dry_path)).unwrap();
+        real.execute(&write(&real_path)).unwrap();
+        let dry_written = Path::new(&dry_path).exists();
+        let real_written = Path::new(&real_path).exists();
+        io::cleanup_temp_paths();
+
+        assert!(dry.stdlib().is_dry_run());
+        assert!(!real.stdlib().is_dry_run());
+        assert!(!dry_written);
+        assert!(real_written);
+        assert_eq!(
+            capability::take_dry_run_log(),
+            vec![format!(
+                "would have: io.write_file path={} bytes=4",
+                dry_path
+            )]
+        );
+    }
+
+    /// Counts how often the time is read
+    struct CountingClock(std::rc::Rc<std::cell::Cell<u32>>);
+
+    impl ClockSource for CountingClock {
+        fn now_ms(&self) -> u64 {
+            self.0.set(self.0.get() + 1);
+            SCHEDULE_START
+        }
+
+        fn monotonic_ms(&self) -> u64 {
+            self.now_ms()
+        }
+    }
+
+    /// Bytes 0, 1, 2, ... in order
+    struct CountingEntropy(std::cell::Cell<u8>);
+
+    impl EntropySource for CountingEntropy {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            for byte in buf {
+                *byte = self.0.get();
+                self.0.set(self.0.get().wrapping_add(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_engine_manual_clock_fires_scheduled_jobs() {
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let _unused = Engine::builder()
+            .with_clock(CountingClock(reads.clone()))
+            .build();
+        assert_eq!(reads.get(), 0, "building an engine must not read its clock");
+
+        let clock = ManualClock::new(SCHEDULE_START);
+        let mut engine = Engine::builder().with_clock(clock.clone()).build();
+        progress::capture(Mode::Plain);
+        engine
+            .execute(
+                "let start = schedule.now()\n\
+                 function tick():\n    print(schedule.now() - start)\n\
+                 schedule.every(1000, tick)\n",
+            )
+            .unwrap();
+        engine.advance_clock(3500).unwrap();
+        assert_eq!(progress::take_captured(), "1000\n2000\n3000\n");
+        assert_eq!(clock.now_ms(), SCHEDULE_START + 3500);
+
+        // Moving the clock by hand fires nothing until the engine runs again,
+        // and the job then sees the time it runs at
+        clock.advance(1000);
+        assert_eq!(progress::take_captured(), "");
+        engine.advance_clock(0).unwrap();
+        assert_eq!(progress::take_captured(), "4500\n");
+    }
+
+    #[test]
+    fn test_engine_entropy_source_makes_temp_names_predictable() {
+        let mut engine = Engine::builder()
+            .with_entropy(CountingEntropy(std::cell::Cell::new(0)))
+            .build();
+        let first = engine.evaluate("io.temp_file(\"entropy-\")").unwrap();
+        let second = engine.evaluate("io.temp_file(\"entropy-\")").unwrap();
+        io::cleanup_temp_paths();
+
+        // The high half of the little-endian words 0..8 and 8..16
+        let nonce = |path: &Value| path.to_string().split('-').nth(2).unwrap().to_string();
+        assert_eq!(nonce(&first), 0x0706_0504u32.to_string());
+        assert_eq!(nonce(&second), 0x0f0e_0d0cu32.to_string());
+    }
+
+    #[test]
+    fn test_engines_keep_their_own_clocks() {
+        let early = ManualClock::new(1_000);
+        let mut first = Engine::builder().with_clock(early.clone()).build();
+        let mut second = Engine::builder()
+            .with_clock(ManualClock::new(5_000))
+            .build();
+
+        first.advance_clock(250).unwrap();
+        assert_eq!(
+            first.evaluate("schedule.now()").unwrap(),
+            Value::Number(1_250.0)
+        );
+        assert_eq!(
+            second.evaluate("schedule.now()").unwrap(),
+            Value::Number(5_000.0)
+        );
+        first.execute("await async.sleep(750)").unwrap();
+        assert_eq!(early.now_ms(), 2_000);
+        assert_eq!(
+            second.evaluate("schedule.now()").unwrap(),
+            Value::Number(5_000.0)
+        );
+
+        // Outside the engines the thread is back on the real clock
+        assert!(schedule::now_ms() > SCHEDULE_START);
+    }
+
+    #[test]
+    fn test_engines_keep_their_own_jobs_and_timed_calls() {
+        let mut first = Engine::builder()
+            .with_clock(ManualClock::new(SCHEDULE_START))
+            .build();
+        let mut second = Engine::builder()
+            .with_clock(ManualClock::new(SCHEDULE_START))
+            .build();
+        let script = "let ticks = 0\n\
+                      let saved = 0\n\
+                      function tick(): ticks = ticks + 1\n\
+                      function save(n): saved = n\n\
+                      let save_later = timing.debounce(save, 500)\n\
+                      schedule.every(1000, tick)\n";
+        first.execute(script).unwrap();
+        second.execute(script).unwrap();
+        first.execute("save_later(1)").unwrap();
+        second.execute("save_later(2)").unwrap();
+
+        first.advance_clock(3000).unwrap();
+        assert_eq!(first.evaluate("ticks").unwrap(), Value::Number(3.0));
+        assert_eq!(first.evaluate("saved").unwrap(), Value::Number(1.0));
+        assert_eq!(second.evaluate("ticks").unwrap(), Value::Number(0.0));
+        assert_eq!(second.evaluate("saved").unwrap(), Value::Number(0.0));
+
+        second.execute("schedule.cancel(1)").unwrap();
+        second.advance_clock(1000).unwrap();
+        assert_eq!(second.evaluate("ticks").unwrap(), Value::Number(0.0));
+        assert_eq!(second.evaluate("saved").unwrap(), Value::Number(2.0));
+        first.advance_clock(1000).unwrap();
+        assert_eq!(first.evaluate("ticks").unwrap(), Value::Number(4.0));
+    }
+
+    #[test]
+    fn test_case_mapping_is_locale_independent_and_folds_fully() {
+        let mut runner = Runner::new();
+        let code = r#"
+let folded = string.casefold("Straße ẞ ΌΣΟΣ")
+let same = string.equals_ignore_case("STRASSE", "straße")
+let dotless = string.equals_ignore_case("ı", "I")
+let upper = string.upper("istanbul ß")
+let lower = string.lower("ISTANBUL İ")
+"#;
+        runner.execute_code(code).unwrap();
+
+        assert_eq!(string_var(&runner, "folded"), "strasse ss όσοσ");
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("same")
+                .unwrap(),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("dotless")
+                .unwrap(),
+            Value::Boolean(false)
+        );
+        assert_eq!(string_var(&runner, "upper"), "ISTANBUL SS");
+        assert_eq!(string_var(&runner, "lower"), "istanbul i\u{307}");
+    }
+
+    #[test]
+    fn test_natural_compare_sorts_filenames() {
+        let mut runner = Runner::new();
+        let code = r#"
+let natural = {numeric: true, case_insensitive: true}
+let files = ["img12.png", "IMG10.png", "img2.png", "img1.png", "Img02.png", "readme.md", "Éclair.txt", "img100.png"]
+let sorted = string.join(array.sort_by(files, string.compare, natural), " ")
+let plain = string.join(array.sort_by(files, string.compare), " ")
+let long = ["v123456789012345678901234567891", "v123456789012345678901234567890", "v99", "v000123456789012345678901234567890"]
+let long_sorted = string.join(array.sort_by(long, string.compare, {numeric: true}), " ")
+let ties = string.join(array.sort_by(["b", "A", "B", "a"], string.compare, natural), " ")
+"#;
+        runner.execute_code(code).unwrap();
+
+        assert_eq!(
+            string_var(&runner, "sorted"),
+            "img1.png Img02.png img2.png IMG10.png img12.png img100.png readme.md Éclair.txt"
+        );
+        assert_eq!(
+            string_var(&runner, "plain"),
+            "IMG10.png Img02.png img1.png img100.png img12.png img2.png readme.md Éclair.txt"
+        );
+        // 30-digit runs differ only past f64 precision; leading zeros don't
+        // make a number bigger, and an equal value goes by its text
+        assert_eq!(
+            string_var(&runner, "long_sorted"),
+            "v99 v000123456789012345678901234567890 v123456789012345678901234567890 \
+             v123456789012345678901234567891"
+        );
+        // Equal under the options, so they keep their original order
+        assert_eq!(string_var(&runner, "ties"), "A a b B");
+    }
+
+    #[test]
+    fn test_sort_by_stops_at_comparison_error() {
+        let mut runner = Runner::new();
+        let code = r#"
+function bad(a, b): return "not a number"
+let sorted = array.sort_by(["b", "a"], bad)
+"#;
+        let error = runner.execute_code(code).unwrap_err();
+        assert!(
+            error.to_string().contains("array.sort_by() comparison"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_injected_fault_fails_twice_then_retry_succeeds() {
+        let dir = io::create_temp_dir("faults-", false).unwrap();
+        let path = format!("{}/report.txt", dir.replace('\\', "/"));
+        let mut runner = Runner::new();
+        let code = format!(
+            r#"
+function save(path, attempt, saved):
+{{
+    if saved > 0:
+        return saved
+    io.write_file(path, "report")
+    return attempt
+}}
+faults.inject("io.write_file", {{error: "disk full", times: 2}})
+let saved = 0
+let log = ""
+for attempt in range(1, 6):
+    try:
+        saved = save("{path}", attempt, saved)
+    catch e:
+        log = log + e.message + ";"
+let stats = faults.active()[0]
+"#
+        );
+        runner.execute_code(&code).unwrap();
+        let written = std::fs::read_to_string(&path).ok();
+        io::cleanup_temp_paths();
+
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("saved")
+                .unwrap(),
+            Value::Number(3.0)
+        );
+        // The script sees the same I/O error a real failure raises
+        let failure = format!(
+            "I/O error: disk full during operation 'io.write_file' at path '{}';",
+            path
+        );
+        assert_eq!(string_var(&runner, "log"), failure.repeat(2));
+        assert_eq!(number_field(&runner, "stats", "calls"), 3.0);
+        assert_eq!(number_field(&runner, "stats", "failures"), 2.0);
+        assert_eq!(written.as_deref(), Some("report"));
+    }
+
+    #[test]
+    fn test_fault_predicate_affects_only_matching_calls() {
+        let dir = io::create_temp_dir("faults-", false).unwrap();
+        let dir = dir.replace('\\', "/");
+        let mut runner = Runner::new();
+        let code = format!(
+            r#"
+function is_lock(path, content): return string.ends_with(path, ".lock")
+faults.inject("io.write_file", {{error: "permission denied", when: is_lock}})
+io.write_file("{dir}/data.txt", "data")
+let outcome = "written"
+try:
+    io.write_file("{dir}/data.lock", "held")
+catch e:
+    outcome = e.message
+let removed = faults.clear()
+let remaining = array.length(faults.active())
+"#
+        );
+        runner.execute_code(&code).unwrap();
+        let data_written = Path::new(&format!("{}/data.txt", dir)).exists();
+        let lock_written = Path::new(&format!("{}/data.lock", dir)).exists();
+        io::cleanup_temp_paths();
+
+        assert!(data_written);
+        assert!(!lock_written);
+        assert!(string_var(&runner, "outcome").starts_with("I/O error: permission denied"));
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("removed").unwrap(), Value::Number(1.0));
+        assert_eq!(env.get("remaining").unwrap(), Value::Number(0.0));
+
+        // Faults belong to the interpreter that injected them
+        let mut other = Runner::new();
+        other
+            .execute_code("let count = array.length(faults.active())")
+            .unwrap();
+        assert_eq!(
+            other
+                .get_interpreter()
+                .get_environment()
+                .get("count")
+                .unwrap(),
+            Value::Number(0.0)
+        );
+    }
+
+    #[test]
+    fn test_tuples_destructure_and_single_element_needs_comma() {
+        let mut runner = Runner::new();
+        let code = r#"
+function min_max(items):
+{
+    let sorted = array.sort(items)
+    return (sorted[0], sorted[array.length(items) - 1])
+}
+let (low, high) = min_max([3, 9, 1])
+let (x, (y, z)) = (1, ("a", true))
+let grouped = (5)
+let single = (5,)
+let (only,) = single
+let same = (1, ("a", true)) == (x, (y, z))
+"#;
+        runner.execute_code(code).unwrap();
+        let env = runner.get_interpreter().get_environment();
+
+        assert_eq!(env.get("low").unwrap(), Value::Number(1.0));
+        assert_eq!(env.get("high").unwrap(), Value::Number(9.0));
+        assert_eq!(env.get("y").unwrap(), Value::String("a".to_string()));
+        assert_eq!(env.get("z").unwrap(), Value::Boolean(true));
+        assert_eq!(env.get("grouped").unwrap(), Value::Number(5.0));
+        assert_eq!(
+            env.get("single").unwrap(),
+            Value::Tuple(vec![Value::Number(5.0)].into())
+        );
+        assert_eq!(env.get("only").unwrap(), Value::Number(5.0));
+        assert_eq!(env.get("same").unwrap(), Value::Boolean(true));
+
+        let json = Value::Tuple(vec![Value::Number(1.0), Value::String("a".into())].into());
+        assert_eq!(json.to_string(), "(1, a)");
+        assert_eq!(env.get("single").unwrap().to_string(), "(5,)");
+        assert_eq!(json.to_json_string(false).as_deref(), Some(r#"[1,"a"]"#));
+    }
+
+    #[test]
+    fn test_tuple_destructuring_reports_arity_mismatch() {
+        let mut runner = Runner::new();
+        let error = runner.execute_code("let (a, b) = (1, 2, 3)").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("tuple of 2 values"), "{}", message);
+        assert!(message.contains("(a, b)"), "{}", message);
+
+        let error = runner.execute_code("let (a, b) = [1, 2]").unwrap_err();
+        assert!(matches!(error, InfraError::TypeError { .. }), "{}", error);
+
+        let tokens = Lexer::new("let (a) = (1,)").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_for_loops_destructure_tuple_items() {
+        let mut runner = Runner::new();
+        let code = r#"
+let pairs = [("a", 1), ("b", 2)]
+let keys = ""
+let total = 0
+for (key, value) in pairs:
+    keys = keys + key
+for (key, value) in pairs:
+    total = total + value
+let sums = []
+for (label, (x, y)) in [("p", (1, 2)), ("q", (3, 4))]:
+    sums = array.push(sums, x + y)
+let k = "outer"
+for (k,) in [(1,)]:
+    total = total + k
+"#;
+        runner.execute_code(code).unwrap();
+        let env = runner.get_interpreter().get_environment();
+
+        assert_eq!(string_var(&runner, "keys"), "ab");
+        assert_eq!(env.get("total").unwrap(), Value::Number(4.0));
+        assert_eq!(env.get("sums").unwrap().to_string(), "[3, 7]");
+        // The names belong to the loop
+        assert_eq!(string_var(&runner, "k"), "outer");
+
+        // An item of the wrong shape fails as a destructuring let does
+        let error = runner
+            .execute_code("for (a, b) in [(1, 2), (1, 2, 3)]:\n    print(a)\n")
+            .unwrap_err();
+        match error {
+            InfraError::TypeError {
+                expected,
+                found,
+                context,
+                line,
+                ..
+            } => {
+                assert_eq!(expected, "tuple of 2 values");
+                assert_eq!(found, "tuple of 3 values");
+                assert_eq!(context.as_deref(), Some("destructuring for (a, b)"));
+                assert_eq!(line, Some(1));
+            }
+            other => panic!("expected a type error, got {:?}", other),
+        }
+        let error = runner
+            .execute_code("for (a, b) in [[1, 2]]:\n    print(a)\n")
+            .unwrap_err();
+        assert!(matches!(error, InfraError::TypeError { .. }), "{}", error);
+
+        // Counting loops take a single name
+        let tokens = Lexer::new("for (a, b) in range(0, 2):\n    print(a)\n")
+            .tokenize()
+            .unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+
+        // Patterns are printed back as written
+        let source = "for (key, (x, y)) in items:\n    print(key)\n";
+        let options = printer::FormattingOptions::default();
+        assert_eq!(
+            printer::format_program(&parse_source(source), &options),
+            source
+        );
+    }
+
+    #[test]
+    fn test_array_map_calls_the_function_for_each_item() {
+        let mut runner = Runner::new();
+        let code = r#"
+function double(x): return x * 2
+let doubled = array.map([1, 2, 3], double)
+let shouted = string.join(array.map(["a", "b"], string.upper), "")
+"#;
+        runner.execute_code(code).unwrap();
+        assert_eq!(
+            runner
+                .get_interpreter()
+                .get_environment()
+                .get("doubled")
+                .unwrap(),
+            Value::Array(vec![
+                Value::Number(2.0),
+                Value::Number(4.0),
+                Value::Number(6.0)
+            ])
+        );
+        assert_eq!(string_var(&runner, "shouted"), "AB");
+
+        let error = runner.execute_code("array.map([1], 3)").unwrap_err();
+        assert!(matches!(error, InfraError::TypeError { .. }), "{}", error);
+        let error = runner
+            .execute_code("function add(a, b): return a + b\narray.map([1], add)")
+            .unwrap_err();
+        assert!(
+            matches!(error.untraced(), InfraError::ArgumentCountMismatch { .. }),
+            "{:?}",
+            error
+        );
+    }
+
+    /// Rows of {name, age, region} for the viewer tests
+    fn people(count: usize) -> Value {
+        let regions = ["eu", "us", "apac"];
+        Value::Array(
+            (0..count)
+                .map(|i| {
+                    Value::Object(ObjectMap::from([
+                        ("name".to_string(), Value::String(format!("user{}", i))),
+                        ("age".to_string(), Value::Number(20.0 + i as f64)),
+                        (
+                            "region".to_string(),
+                            Value::String(regions[i % 3].to_string()),
+                        ),
+                    ]))
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_repl_summarizes_results_over_the_view_threshold() {
+        let value = people(5000);
+        let size = value.to_string().len();
+
+        let mut repl = Repl::new();
+        repl.set_view_threshold(Some(size));
+        assert_eq!(repl.echo(&value), value.to_string());
+        repl.set_view_threshold(Some(size - 1));
+        assert_eq!(
+            repl.echo(&value),
+            "array[5000] of object{name, age, region} — use :view to inspect"
+        );
+        repl.set_view_threshold(None);
+        assert_eq!(repl.echo(&value).len(), size);
+
+        // The echoed result is bound to `_`
+        repl.run_from(std::io::Cursor::new("[1, 2, 3]\nlet n = array.length(_)\n"));
+        assert_eq!(
+            repl.get_runner()
+                .get_interpreter()
+                .get_environment()
+                .get("n")
+                .unwrap(),
+            Value::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn test_viewer_pages_and_opens_rows() {
+        let keys = [Key::Down, Key::Down, Key::PageDown, Key::End, Key::Enter];
+        let mut screen = ScriptedScreen::new(80, 10, keys);
+        viewer::view(&people(40), "people", &mut screen).unwrap();
+
+        // A title, the column header, 7 rows and the key help
+        let first = &screen.frames[0];
+        assert_eq!(first.len(), 10);
+        assert!(
+            first[0].starts_with("people  array[40] of object{name, age, region}  rows 1-7 of 40")
+        );
+        assert_eq!(
+            first[1].split_whitespace().collect::<Vec<_>>(),
+            ["name", "age", "region"]
+        );
+        assert!(first[2].starts_with("> [0]  user0"), "{:?}", first);
+
+        let selected = |frame: &[String]| {
+            frame
+                .iter()
+                .find(|line| line.starts_with("> "))
+                .cloned()
+                .unwrap()
+        };
+        assert!(selected(&screen.frames[2]).starts_with("> [2]  user2"));
+        // A page down moves by the rows on screen and scrolls to keep it shown
+        assert!(selected(&screen.frames[3]).starts_with("> [9]  user9"));
+        assert!(screen.frames[3][0].contains("rows 4-10 of 40"));
+        assert!(selected(&screen.frames[4]).starts_with("> [39] user39"));
+
+        // Enter opens the selected object, one row per field
+        let opened = screen.last_frame();
+        assert!(opened[0].starts_with("people[39]  object{name, age, region}"));
+        assert!(opened[1].starts_with("> .name   user39"), "{:?}", opened);
+        assert!(opened[3].starts_with("  .region eu"), "{:?}", opened);
+    }
+
+    #[test]
+    fn test_viewer_filter_narrows_rows() {
+        let keys = "/eu"
+            .chars()
+            .map(Key::Char)
+            .chain([Key::Enter, Key::Char('q')]);
+        let mut screen = ScriptedScreen::new(90, 40, keys);
+        viewer::view(&people(30), "_", &mut screen).unwrap();
+
+        let typing = &screen.frames[3];
+        assert_eq!(typing.last().unwrap().trim_end(), "/eu");
+        let filtered = screen.last_frame();
+        assert!(filtered[0].contains("rows 1-10 of 10  filter \"eu\" (10 of 30)"));
+        let rows: Vec<&String> = filtered[2..filtered.len() - 1].iter().collect();
+        assert_eq!(rows.len(), 10);
+        assert!(rows.iter().all(|row| row.trim_end().ends_with("eu")));
+        assert!(rows[0].starts_with("> [0]"));
+        assert!(rows[1].starts_with("  [3]"));
+    }
+
+    #[test]
+    fn test_array_filter_takes_a_predicate() {
+        let mut runner = Runner::new();
+        let code = r#"
+function is_long(s): return string.length(s) > 3
+function is_admin(user): return user.role == "admin"
+let long = string.join(array.filter(["ab", "abcd", "xyz", "hello"], is_long), " ")
+let admins = array.filter([{name: "ann", role: "admin"}, {name: "bo", role: "dev"}], is_admin)
+let admin = admins[0].name
+let none = array.length(array.filter([], is_long))
+let above = array.filter([1, 5, 2, 8], 3)
+"#;
+        runner.execute_code(code).unwrap();
+        let env = runner.get_interpreter().get_environment();
+
+        assert_eq!(string_var(&runner, "long"), "abcd hello");
+        assert_eq!(string_var(&runner, "admin"), "ann");
+        assert_eq!(env.get("none").unwrap(), Value::Number(0.0));
+        assert_eq!(
+            env.get("above").unwrap(),
+            Value::Array(vec![Value::Number(5.0), Value::Number(8.0)])
+        );
+
+        let error = runner
+            .execute_code(
+                "function length_of(s): return string.length(s)\narray.filter([\"a\"], length_of)",
+            )
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("array.filter() predicate"),
+            "{}",
+            error
+        );
+    }
+
+    /// A tree of config files for the glob tests; returns its root
+    fn glob_tree() -> String {
+        let root = io::create_temp_dir("glob-", false)
+            .unwrap()
+            .replace('\\', "/");
+        for file in [
+            "config/app.yaml",
+            "config/db.yml",
+            "config/readme.md",
+            "config/nested/deep/cache.yaml",
+            "config/vendor/lib.yaml",
+            "config/vendor/more/extra.yaml",
+            "other/shared.yaml",
+        ] {
+            let path = Path::new(&root).join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_glob_recurses_with_ignores_and_braces() {
+        let root = glob_tree();
+        let mut runner = Runner::new();
+        let code = format!(
+            r#"
+let options = {{root: "{root}", ignore: ["config/vendor/**"]}}
+let yaml = string.join(io.glob("config/**/*.yaml", options), " ")
+let either = string.join(io.glob("config/*.{{yaml,yml}}", {{root: "{root}"}}), " ")
+let everything = io.glob("**", {{root: "{root}"}})
+let tested = io.match_glob("src/**/[a-c]?.rs", "src/x/y/ab.rs")
+let shallow = io.match_glob("*.rs", "src/main.rs")
+"#
+        );
+        runner.execute_code(&code).unwrap();
+        io::cleanup_temp_paths();
+
+        assert_eq!(
+            string_var(&runner, "yaml"),
+            format!(
+                "{0}/config/app.yaml {0}/config/nested/deep/cache.yaml",
+                root
+            )
+        );
+        assert_eq!(
+            string_var(&runner, "either"),
+            format!("{0}/config/app.yaml {0}/config/db.yml", root)
+        );
+        // Directories match too, and paths come out sorted
+        let Value::Array(everything) = runner
+            .get_interpreter()
+            .get_environment()
+            .get("everything")
+            .unwrap()
+        else {
+            panic!("expected an array");
+        };
+        let everything: Vec<String> = everything.iter().map(Value::to_string).collect();
+        let mut sorted = everything.clone();
+        sorted.sort();
+        assert_eq!(everything, sorted);
+        assert_eq!(everything.len(), 13);
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("tested").unwrap(), Value::Boolean(true));
+        assert_eq!(env.get("shallow").unwrap(), Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_glob_with_many_double_stars_matches_quickly() {
+        let path = ["a"; 40].join("/");
+        let glob =
+            crate::stdlib::glob::Glob::compile(&format!("{}/b", ["**/a"; 12].join("/"))).unwrap();
+        assert!(!glob.is_match(&path));
+        assert!(glob.is_match(&format!("{}/b", path)));
+
+        let stacked = crate::stdlib::glob::Glob::compile("src/**/**/**/**/*.rs").unwrap();
+        assert!(stacked.is_match("src/main.rs"));
+        assert!(stacked.is_match("src/x/y/lib.rs"));
+        assert!(!stacked.is_match("lib/x/y/lib.rs"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_glob_leaves_symlinks_unfollowed_by_default() {
+        let root = glob_tree();
+        std::os::unix::fs::symlink("../other", format!("{}/config/linked", root)).unwrap();
+        let outside = io::create_temp_dir("glob-outside-", false).unwrap();
+        std::fs::write(format!("{}/secret.yaml", outside), "").unwrap();
+        std::os::unix::fs::symlink(&outside, format!("{}/config/escape", root)).unwrap();
+
+        let mut runner = Runner::new();
+        let code = format!(
+            r#"
+let plain = string.join(io.glob("config/**/shared.yaml", {{root: "{root}"}}), " ")
+let (followed, warnings) = io.glob("config/**/*.yaml", {{root: "{root}", follow_symlinks: true, ignore: ["**/vendor"], warnings: true}})
+let found = string.join(followed, " ")
+let warning = warnings[0]
+"#
+        );
+        runner.execute_code(&code).unwrap();
+        io::cleanup_temp_paths();
+
+        assert_eq!(string_var(&runner, "plain"), "");
+        // Following links still stops at the root
+        assert_eq!(
+            string_var(&runner, "found"),
+            format!(
+                "{0}/config/app.yaml {0}/config/linked/shared.yaml {0}/config/nested/deep/cache.yaml",
+                root
+            )
+        );
+        assert!(string_var(&runner, "warning").contains("leads outside the root"));
+    }
+
+    #[test]
+    fn test_glob_rejects_root_escapes_and_points_at_bad_patterns() {
+        let mut runner = Runner::new();
+        for (code, expected) in [
+            (r#"io.glob("../*")"#, "leaves the root"),
+            (
+                r#"io.glob("*", {ignore: ["a/../../b"]})"#,
+                "leaves the root",
+            ),
+            (r#"io.glob("/etc/*")"#, "leaves the root"),
+            (
+                r#"io.match_glob("src/[ab", "x")"#,
+                "unclosed '[' at position 5",
+            ),
+            (
+                r#"io.match_glob("{a,b", "x")"#,
+                "unclosed '{' at position 1",
+            ),
+            (
+                r#"io.match_glob("a**", "x")"#,
+                "'**' must be a whole path segment at position 2",
+            ),
+            (
+                r#"io.match_glob("[z-a]", "x")"#,
+                "range 'z-a' is reversed at position 2",
+            ),
+        ] {
+            let error = runner.execute_code(code).unwrap_err();
+            assert!(error.to_string().contains(expected), "{}: {}", code, error);
+        }
+    }
+
+    #[test]
+    fn test_array_reduce_threads_an_accumulator() {
+        let mut runner = Runner::new();
+        let code = r#"
+function add(sum, x): return sum + x
+function longest(best, word):
+{
+    if string.length(word) > string.length(best):
+        return word
+    return best
+}
+let total = array.reduce([1, 2, 3, 4], add, 10)
+let joined = array.reduce(["a", "b", "c"], add)
+let word = array.reduce(["fig", "banana", "kiwi"], longest)
+let seeded = array.reduce([], add, 7)
+"#;
+        runner.execute_code(code).unwrap();
+        let env = runner.get_interpreter().get_environment();
+
+        assert_eq!(env.get("total").unwrap(), Value::Number(20.0));
+        assert_eq!(string_var(&runner, "joined"), "abc");
+        assert_eq!(string_var(&runner, "word"), "banana");
+        assert_eq!(env.get("seeded").unwrap(), Value::Number(7.0));
+
+        // A reducer is required, and at most an initial value follows it
+        for (call, expected, found) in [
+            ("array.reduce()", 2, 0),
+            ("array.reduce([1, 2, 3])", 2, 1),
+            ("array.reduce([1], add, 0, 1)", 3, 4),
+        ] {
+            match runner.execute_code(call) {
+                Err(InfraError::ArgumentCountMismatch {
+                    expected: e,
+                    found: f,
+                    ..
+                }) => assert_eq!((e, f), (expected, found), "{}", call),
+                other => panic!(
+                    "{}: expected an argument count error, got {:?}",
+                    call, other
+                ),
+            }
+        }
+
+        let error = runner
+            .execute_code("function one(x): return x\narray.reduce([1, 2], one, 0)")
+            .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                InfraError::ArgumentCountMismatch {
+                    expected: 2,
+                    found: 1,
+                    ..
+                }
+            ),
+            "{:?}",
+            error
+        );
+        let error = runner.execute_code("array.reduce([], add)").unwrap_err();
+        assert!(
+            matches!(error, InfraError::RuntimeError { .. }),
+            "{:?}",
+            error
+        );
+        assert!(error.to_string().contains("array.reduce"), "{}", error);
+    }
+
+    #[test]
+    fn test_array_slice_concat_and_index_of() {
+        let mut runner = Runner::new();
+        let code = r#"
+let items = [1, 2, 3, 4, 5]
+let middle = array.slice(items, 1, 3)
+let tail = array.slice(items, -2)
+let clamped = array.slice(items, -100, 100)
+let crossed = array.slice(items, 4, 1)
+let both = array.concat([1, 2], ["a"])
+let found = array.index_of([1, 2, 3, 2], 2)
+let close = array.index_of([0.5, 0.1 + 0.2], 0.3)
+let missing = array.index_of(["a"], "b")
+"#;
+        runner.execute_code(code).unwrap();
+        let env = runner.get_interpreter().get_environment();
+        let numbers =
+            |values: &[f64]| Value::Array(values.iter().map(|&n| Value::Number(n)).collect());
+
+        assert_eq!(env.get("middle").unwrap(), numbers(&[2.0, 3.0]));
+        assert_eq!(env.get("tail").unwrap(), numbers(&[4.0, 5.0]));
+        assert_eq!(
+            env.get("clamped").unwrap(),
+            numbers(&[1.0, 2.0, 3.0, 4.0, 5.0])
+        );
+        assert_eq!(env.get("crossed").unwrap(), numbers(&[]));
+        assert_eq!(
+            env.get("both").unwrap(),
+            Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(2.0),
+                Value::String("a".to_string())
+            ])
+        );
+        assert_eq!(env.get("found").unwrap(), Value::Number(1.0));
+        // Numbers compare as == does, within f64::EPSILON
+        assert_eq!(env.get("close").unwrap(), Value::Number(1.0));
+        assert_eq!(env.get("missing").unwrap(), Value::Number(-1.0));
+    }
+
+    #[test]
+    fn test_object_map_keeps_order_across_the_index_boundary() {
+        use crate::core::object_map::SMALL_LEN;
+        let key = |i: usize| format!("k{}", i);
+        let mut map = ObjectMap::new();
+        for i in 0..SMALL_LEN {
+            map.insert(key(i), Value::Number(i as f64));
+        }
+        assert!(!map.is_indexed());
+        assert_eq!(
+            map.insert(key(3), Value::Number(30.0)),
+            Some(Value::Number(3.0))
+        );
+        assert!(!map.is_indexed());
+
+        map.insert(key(SMALL_LEN), Value::Number(SMALL_LEN as f64));
+        assert!(map.is_indexed());
+        let expected: Vec<String> = (0..=SMALL_LEN).map(key).collect();
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(map.get("k3"), Some(&Value::Number(30.0)));
+
+        // Removing below the boundary keeps the index, and positions after
+        // the removed key still resolve
+        assert_eq!(map.remove("k0"), Some(Value::Number(0.0)));
+        assert_eq!(map.remove("k0"), None);
+        assert_eq!(map.remove("k5"), Some(Value::Number(5.0)));
+        assert!(map.is_indexed());
+        for i in [1, 2, 3, 4, 6, 7, SMALL_LEN] {
+            assert!(map.contains_key(&key(i)), "lost {}", key(i));
+        }
+        *map.get_mut("k8").unwrap() = Value::Boolean(true);
+        map.insert("k0".to_string(), Value::Null);
+        let keys: Vec<&str> = map.keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, ["k1", "k2", "k3", "k4", "k6", "k7", "k8", "k0"]);
+        assert_eq!(map["k8"], Value::Boolean(true));
+
+        // A small map removes without an index too
+        let mut small: ObjectMap = (0..3).map(|i| (key(i), Value::Number(i as f64))).collect();
+        small.remove("k1");
+        assert_eq!(small.keys().cloned().collect::<Vec<_>>(), ["k0", "k2"]);
+        assert_eq!(small.get("k2"), Some(&Value::Number(2.0)));
+
+        let mut runner = Runner::new();
+        let result = runner
+            .evaluate_code(
+                "let o = {a: 1, b: 2, c: 3, d: 4, e: 5, f: 6, g: 7, h: 8}\n\
+                 o.i = 9\n\
+                 object.keys(object.remove(o, \"b\"))\n",
+            )
+            .unwrap();
+        assert_eq!(result.to_string(), "[a, c, d, e, f, g, h, i]");
+    }
+
+    #[test]
+    fn test_break_and_continue_reach_the_innermost_loop() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function stops():\n\
+                 {\n\
+                     for i in range(0, 5):\n\
+                     {\n\
+                         if i == 2:\n\
+                         {\n\
+                             break\n\
+                         }\n\
+                         if i > 2:\n\
+                             return \"kept going\"\n\
+                     }\n\
+                     return \"stopped\"\n\
+                 }\n\
+                 function skips():\n\
+                 {\n\
+                     for i in range(0, 5):\n\
+                     {\n\
+                         if i < 4:\n\
+                             continue\n\
+                         return i\n\
+                     }\n\
+                     return -1\n\
+                 }\n\
+                 function inner_only():\n\
+                 {\n\
+                     for i in range(0, 3):\n\
+                     {\n\
+                         while true:\n\
+                             break\n\
+                         if i == 2:\n\
+                             return i\n\
+                     }\n\
+                     return -1\n\
+                 }\n\
+                 while true:\n\
+                     break\n\
+                 for i in range(0, 5):\n\
+                 {\n\
+                     if i == 1:\n\
+                         break\n\
+                     if i > 1:\n\
+                         io.throw(\"break did not stop the loop\")\n\
+                 }\n\
+                 for i in range(0, 3):\n\
+                 {\n\
+                     try:\n\
+                         continue\n\
+                     catch e:\n\
+                         io.throw(\"continue was caught\")\n\
+                     io.throw(\"continue did not skip the rest\")\n\
+                 }\n\
+                 function bad():\n\
+                     break\n\
+                 let message = \"\"\n\
+                 try:\n\
+                     bad()\n\
+                 catch e:\n\
+                     message = e.message\n\
+                 let results = [stops(), skips(), inner_only()]\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("results").unwrap().to_string(), "[stopped, 4, 2]");
+        assert_eq!(
+            string_var(&runner, "message"),
+            "Runtime error: 'break' outside a loop; it can only be used inside a while or for loop"
+        );
+
+        let error = Runner::new()
+            .execute_code("let x = 1\ncontinue\n")
+            .unwrap_err();
+        match error {
+            InfraError::RuntimeError { message, line, .. } => {
+                assert!(
+                    message.starts_with("'continue' outside a loop"),
+                    "{}",
+                    message
+                );
+                assert_eq!(line, Some(2));
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lambdas_capture_values_and_are_called_like_functions() {
+        let mut runner = Runner::new();
+        runner
+            .execute_code(
+                "function make_adder(n):\n\
+                 {\n\
+                     let offset = n * 10\n\
+                     return function(x): x + offset\n\
+                 }\n\
+                 let add_twenty = make_adder(2)\n\
+                 let offset = 1000\n\
+                 let later = add_twenty(3)\n\
+                 let double = function(x: number) -> number: x * 2\n\
+                 let doubled = array.map([1, 2, 3], double)\n\
+                 let evens = array.filter([1, 2, 3, 4], def(x): x % 2 == 0)\n\
+                 let subtract = function(a): function(b): a - b\n\
+                 let difference = subtract(10)(4)\n",
+            )
+            .unwrap();
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("later").unwrap().to_string(), "23");
+        assert_eq!(env.get("doubled").unwrap().to_string(), "[2, 4, 6]");
+        assert_eq!(env.get("evens").unwrap().to_string(), "[2, 4]");
+        assert_eq!(env.get("difference").unwrap().to_string(), "6");
+        assert_eq!(
+            env.get("double").unwrap().to_string(),
+            "<function lambda@9(x)>"
+        );
+
+        let source = "let f = array.map(xs, function(x, y: number): x + y * 2)\n";
+        assert_eq!(
+            printer::format_program(&parse_source(source), &Default::default()),
+            source
+        );
+    }
+
+    #[test]
+    fn test_undefined_names_suggest_the_closest_visible_one() {
+        let mut runner = Runner::new();
+        let suggestion =
+            |result: Result<_, InfraError>| match result.map_err(|e| e.untraced().clone()) {
+                Err(InfraError::UndefinedVariable { suggestion, .. })
+                | Err(InfraError::UndefinedFunction { suggestion, .. }) => suggestion,
+                other => panic!("expected an undefined name, got {:?}", other),
+            };
+
+        // Names from outer scopes are candidates too
+        let code = "let length = 3\nfunction area(width):\n    if true:\n        return width * lenght\nprint(area(2))\n";
+        assert_eq!(
+            suggestion(runner.execute_code(code)),
+            Some("length".to_string())
+        );
+        assert_eq!(
+            suggestion(runner.execute_code("print(math.sqr(4))")),
+            Some("math.sqrt".to_string())
+        );
+        // A misspelt module is an undefined name like any other
+        assert_eq!(
+            suggestion(runner.execute_code("print(maht.sqrt(4))")),
+            Some("math".to_string())
+        );
+        // Calls also look among the globals and every module's functions
+        assert_eq!(
+            suggestion(runner.execute_code("print(lenn([1]))")),
+            Some("len".to_string())
+        );
+        assert_eq!(
+            suggestion(runner.execute_code("print(sqrt(4))")),
+            Some("math.sqrt".to_string())
+        );
+        assert_eq!(
+            suggestion(runner.execute_code("print(uppr(\"a\"))")),
+            Some("string.upper".to_string())
+        );
+        // Nothing close enough means no suggestion
+        assert_eq!(suggestion(runner.execute_code("print(zzzzzz)")), None);
+        assert_eq!(suggestion(runner.execute_code("zzzzzz(1)")), None);
+
+        // A misspelt module function can be caught like any other runtime error
+        runner
+            .execute_code(
+                "let caught = \"\"\ntry:\n    math.sqr(4)\ncatch e:\n    caught = \"yes\"\n",
+            )
+            .unwrap();
+        assert_eq!(string_var(&runner, "caught"), "yes");
+    }
+
+    #[test]
+    fn test_error_snippets_show_the_line_with_a_caret_and_its_neighbours() {
+        use crate::utils::error_reporter::write_snippet;
+        use termcolor::{Buffer, Color};
+
+        let snippet = |source: &str, line: usize, column: usize| {
+            let mut buffer = Buffer::no_color();
+            write_snippet(&mut buffer, source, line, column, Color::Red).unwrap();
+            String::from_utf8(buffer.into_inner()).unwrap()
+        };
+        let source = "let a = 1\n\nlet b = a / 0\nprint(b)\n";
+
+        assert_eq!(
+            snippet(source, 3, 11),
+            "  2 |\n  3 | let b = a / 0\n    |           ^\n  4 | print(b)\n"
+        );
+        // The first and last lines have no neighbour on one side
+        assert_eq!(
+            snippet(source, 1, 5),
+            "  1 | let a = 1\n    |     ^\n  2 |\n"
+        );
+        assert_eq!(
+            snippet(source, 4, 1),
+            "  3 | let b = a / 0\n  4 | print(b)\n    | ^\n"
+        );
+        // A column past the end points just after the line, and no column
+        // means no caret
+        assert_eq!(snippet("x\n", 1, 40), "  1 | x\n    |  ^\n");
+        assert_eq!(snippet("x\n", 1, 0), "  1 | x\n");
+        assert_eq!(snippet("x\n", 2, 1), "");
+        assert_eq!(
+            snippet("\tlet q = 1 / 0", 1, 12),
+            "  1 | \tlet q = 1 / 0\n    | \t          ^\n"
+        );
+        // The gutter grows with the line numbers shown
+        let long = "a\n".repeat(9) + "bad\n";
+        assert_eq!(snippet(&long, 10, 1), "   9 | a\n  10 | bad\n     | ^\n");
+
+        let mut colored = Buffer::ansi();
+        write_snippet(&mut colored, source, 3, 11, Color::Red).unwrap();
+        assert!(String::from_utf8(colored.into_inner())
+            .unwrap()
+            .contains('\x1b'));
+    }
+
+    #[test]
+    fn test_errors_in_imported_code_are_placed_at_the_calling_line() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let module = format!("{}/ratio.infra", dir).replace('\\', "/");
+        std::fs::write(
+            &module,
+            "\n\nexport function ratio(a, b):\n    return a / b\n",
+        )
+        .unwrap();
+
+        let error = Runner::new()
+            .execute_code(&format!(
+                "import {{ratio}} from \"{}\"\nlet fine = ratio(4, 2)\nlet r = ratio(1, 0)\n",
+                module
+            ))
+            .unwrap_err();
+        // Line 4 of the module means nothing in the script
+        assert!(matches!(
+            error.untraced(),
+            InfraError::DivisionByZero {
+                line: Some(3),
+                column: Some(9)
+            }
+        ));
+
+        // The innermost position wins over the ones around it
+        let error = InfraError::DivisionByZero {
+            line: None,
+            column: None,
+        };
+        assert!(matches!(
+            error.at(2, 5).at(7, 1),
+            InfraError::DivisionByZero {
+                line: Some(2),
+                column: Some(5)
+            }
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_closures_keep_state_per_instance_and_modules_keep_their_bindings() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let module = format!("{}/labels.infra", dir).replace('\\', "/");
+        std::fs::write(
+            &module,
+            "let prefix = \"id-\"\nlet made = 0\n\
+             function pad(name):\n    return prefix + name\n\
+             export function label(name):\n{\n    made = made + 1\n    return pad(name)\n}\n\
+             export function labelled():\n    return made\n",
+        )
+        .unwrap();
+
+        let mut runner = Runner::new();
+        let code = format!(
+            "function make_counter():\n\
+             {{\n\
+                 let count = 0\n\
+                 function increment():\n\
+                 {{\n\
+                     count = count + 1\n\
+                     return count\n\
+                 }}\n\
+                 return increment\n\
+             }}\n\
+             let a = make_counter()\n\
+             let b = make_counter()\n\
+             a()\n\
+             a()\n\
+             let third = a()\n\
+             let first = b()\n\
+             let copy = a\n\
+             let fourth = copy()\n\
+             function watch():\n\
+             {{\n\
+                 let seen = 1\n\
+                 let read = function(): seen\n\
+                 seen = 2\n\
+                 return read()\n\
+             }}\n\
+             let watched = watch()\n\
+             import {{label, labelled}} from \"{}\"\n\
+             let x = label(\"x\")\n\
+             let y = label(\"y\")\n\
+             let made = labelled()\n",
+            module
+        );
+        runner.execute_code(&code).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let env = runner.get_interpreter().get_environment();
+        assert_eq!(env.get("third").unwrap(), Value::Number(3.0));
+        // Each call to the factory makes its own count
+        assert_eq!(env.get("first").unwrap(), Value::Number(1.0));
+        // Copies of a closure share its state
+        assert_eq!(env.get("fourth").unwrap(), Value::Number(4.0));
+        // A closure shares the variable, not the value it had when defined
+        assert_eq!(env.get("watched").unwrap(), Value::Number(2.0));
+        // Imported functions reach the module's helpers, constants and state
+        assert_eq!(string_var(&runner, "x"), "id-x");
+        assert_eq!(string_var(&runner, "y"), "id-y");
+        assert_eq!(env.get("made").unwrap(), Value::Number(2.0));
+        assert!(env.get("pad").is_err() && env.get("prefix").is_err());
+    }
+
+    #[test]
+    fn test_functions_see_where_they_are_defined_not_where_they_are_called() {
+        let mut runner = Runner::new();
+        let code = "function reveal():\n    return secret\n\
+                    function caller():\n\
+                    {\n    let secret = 42\n    return reveal()\n}\n";
+        runner.execute_code(code).unwrap();
+        match runner
+            .execute_code("caller()")
+            .as_ref()
+            .map_err(InfraError::untraced)
+        {
+            Err(InfraError::UndefinedVariable { name, .. }) => assert_eq!(name, "secret"),
+            other => panic!("expected secret to be undefined, got {:?}", other),
+        }
+
+        // A local with the same name at the call site does not replace the
+        // one the function closed over, and globals are still reached
+        let code = "let unit = \"ms\"\n\
+                    function make_reader():\n\
+                    {\n    let value = 1\n    return function(): value + unit\n}\n\
+                    let read = make_reader()\n\
+                    function shadow():\n\
+                    {\n    let value = 99\n    return read()\n}\n\
+                    let result = shadow()\n";
+        runner.execute_code(code).unwrap();
+        assert_eq!(string_var(&runner, "result"), "1ms");
+    }
+
+    #[test]
+    fn test_async_functions_return_promises_that_await_unwraps() {
+        let dir = io::create_temp_dir("infra-test-", false).unwrap();
+        let file = format!("{}/a.txt", dir).replace('\\', "/");
+        std::fs::write(&file, "contents").unwrap();
+
+        let mut runner = Runner::new();
+        let code = format!(
+            "let x = await async.read_file(\"{}\")\n\
+             async function add_one(n):\n    return n + 1\n\
+             let pending = add_one(1)\n\
+             let two = await pending\n\
+             function outer():\n\
+             {{\n\
+                 async function inner():\n        return await add_one(10)\n\
+                 return inner()\n\
+             }}\n\
+             let eleven = await outer()\n\
+             async function fails():\n    return 1 / 0\n\
+             let rejected = fails()\n\
+             try:\n    let never = await rejected\ncatch err:\n    let caught = err.message\n",
+            file
+        );
+        runner.execute_code(&code).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(string_var(&runner, "x"), "contents");
+        let env = runner.get_interpreter().get_environment();
+        assert!(matches!(
+            env.get("pending").unwrap(),
+            Value::Promise { resolved: true, .. }
+        ));
+        assert_eq!(env.get("two").unwrap(), Value::Number(2.0));
+        assert_eq!(env.get("eleven").unwrap(), Value::Number(11.0));
+        assert!(
+            string_var(&runner, "caught").contains("Division by zero"),
+            "{}",
+            string_var(&runner, "caught")
+        );
+
+        // Nothing can settle a pending promise, so awaiting one fails at once
+        let err = Runner::new()
+            .execute_code("let p = async.create_promise()\nlet x = await p\n")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("promise from async.create_promise at line 1 never settles"),
+            "{}",
+            err
+        );
+    }
+
+    /// Answer `requests` HTTP requests on a local port, each by its path
+    fn serve_http(requests: usize) -> u16 {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    head.push(line.trim().to_string());
+                }
+                let length = head
+                    .iter()
+                    .find_map(|h| {
+                        h.to_ascii_lowercase()
+                            .strip_prefix("content-length: ")?
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let token = head
+                    .iter()
+                    .find_map(|h| h.strip_prefix("X-Token: ").or(h.strip_prefix("x-token: ")))
+                    .unwrap_or("none")
+                    .to_string();
+
+                let (status, reply): (&str, Vec<u8>) = match head[0].split(' ').nth(1).unwrap() {
+                    "/hello" => ("200 OK", b"hello there".to_vec()),
+                    "/echo" => (
+                        "201 Created",
+                        format!("{} {}", String::from_utf8_lossy(&body), token).into_bytes(),
+                    ),
+                    "/binary" => ("200 OK", vec![0xff, 0xfe, 0x00]),
+                    "/slow" => {
+                        std::thread::sleep(std::time::Duration::from_millis(1000));
+                        continue;
+                    }
+                    _ => ("404 Not Found", b"no such page".to_vec()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    reply.len()
+                );
+                let _ = stream.write_all(&reply);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_http_requests_resolve_with_real_responses() {
+        let port = serve_http(5);
+        let mut runner = Runner::new();
+        let code = format!(
+            "let base = \"http://127.0.0.1:{}\"\n\
+             let got = await async.http_get(base + \"/hello\")\n\
+             let posted = await async.http_post(base + \"/echo\", \"ping\", {{\"X-Token\": \"abc\"}})\n\
+             let missing = await async.http_get(base + \"/missing\")\n\
+             try:\n    await async.http_get(base + \"/binary\")\ncatch e:\n    let binary = e.message\n\
+             try:\n    await async.http_get(base + \"/slow\", {{timeout_ms: 200}})\ncatch e:\n    let slow = e.message\n\
+             try:\n    await async.http_get(\"nope://x\")\ncatch e:\n    let invalid = e.message\n",
+            port
+        );
+        runner.execute_code(&code).unwrap();
+
+        let field =
+            |name: &str, key: &str| match runner.get_interpreter().get_environment().get(name) {
+                Ok(Value::Object(response)) => response.get(key).unwrap().to_string(),
+                other => panic!("{} is {:?}", name, other),
+            };
+        assert_eq!(field("got", "status"), "200");
+        assert_eq!(field("got", "ok"), "true");
+        assert_eq!(field("got", "body"), "hello there");
+        assert_eq!(
+            field("got", "headers"),
+            "{\"content-type\": text/plain, \"content-length\": 11, \"connection\": close}"
+        );
+        assert_eq!(field("posted", "status"), "201");
+        assert_eq!(field("posted", "body"), "ping abc");
+        // An error status still resolves
+        assert_eq!(field("missing", "status"), "404");
+        assert_eq!(field("missing", "ok"), "false");
+
+        assert!(string_var(&runner, "binary").contains("is not UTF-8 text"));
+        assert!(string_var(&runner, "slow").contains("/slow failed"));
+        assert!(string_var(&runner, "invalid").contains("invalid URL 'nope://x'"));
+    }
+
+    fn text_call(function: fn(&[Value]) -> Result<Value, InfraError>, args: &[Value]) -> String {
+        match function(args) {
+            Ok(value) => value.to_string(),
+            Err(InfraError::RuntimeError { message, .. }) => format!("error: {}", message),
+            Err(other) => format!("error: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_time_parse_duration() {
+        let cases = [
+            ("2h30m", "9000000"),
+            ("2h 30m", "9000000"),
+            ("  90s ", "90000"),
+            ("1.5h", "5400000"),
+            ("1d", "86400000"),
+            ("250ms", "250"),
+            ("1m 1s 1ms", "61001"),
+            ("30m2h", "9000000"),
+            ("0.0005s", "1"),
+            ("0s", "0"),
+            // A bare number is milliseconds
+            ("1500", "1500"),
+            ("2:30:00", "9000000"),
+            ("0:00:01.250", "1250"),
+            ("104249991:22:39", "375299968959000"),
+            ("1h30", "error: Invalid duration \"1h30\": missing unit (ms, s, m, h or d)\n  1h30\n    ^^"),
+            ("2h3x", "error: Invalid duration \"2h3x\": unknown unit 'x' (use ms, s, m, h or d)\n  2h3x\n    ^^"),
+            ("-5m", "error: Invalid duration \"-5m\": negative durations are not allowed\n  -5m\n  ^"),
+            ("1h -5m", "error: Invalid duration \"1h -5m\": negative durations are not allowed\n  1h -5m\n     ^"),
+            ("1.2.3s", "error: Invalid duration \"1.2.3s\": expected a number\n  1.2.3s\n  ^^^^^^"),
+            ("h", "error: Invalid duration \"h\": expected a number\n  h\n  ^"),
+            ("", "error: Invalid duration \"\": empty duration\n  \n  ^"),
+            ("1:60:00", "error: Invalid duration \"1:60:00\": must be less than 60\n  1:60:00\n    ^^"),
+            ("1:00", "error: Invalid duration \"1:00\": expected hours:minutes:seconds\n  1:00\n  ^^^^"),
+            ("104249992d", "error: Invalid duration \"104249992d\": too large to count in whole milliseconds\n  104249992d\n  ^^^^^^^^^^"),
+        ];
+        for (text, expected) in cases {
+            let actual = text_call(time::parse_duration, &[Value::String(text.to_string())]);
+            assert_eq!(actual, expected, "parse_duration({:?})", text);
+        }
+    }
+
+    #[test]
+    fn test_time_format_duration() {
+        let clock = || {
+            let mut options = ObjectMap::new();
+            options.insert("compact".to_string(), Value::Boolean(false));
+            Value::Object(options)
+        };
+        let cases = [
+            (0.0, "0s", "0:00:00"),
+            (1.0, "1ms", "0:00:00.001"),
+            (999.0, "999ms", "0:00:00.999"),
+            (1000.0, "1s", "0:00:01"),
+            (59_999.0, "59s 999ms", "0:00:59.999"),
+            (60_000.0, "1m", "0:01:00"),
+            (9_000_000.0, "2h 30m", "2:30:00"),
+            (86_400_000.0, "1d", "24:00:00"),
+            (90_061_001.0, "1d 1h 1m 1s 1ms", "25:01:01.001"),
+            (1.4, "1ms", "0:00:00.001"),
+        ];
+        for (ms, compact, clock_form) in cases {
+            let compact_text = text_call(time::format_duration, &[Value::Number(ms)]);
+            assert_eq!(compact_text, compact, "format_duration({})", ms);
+            let clock_text = text_call(time::format_duration, &[Value::Number(ms), clock()]);
+            assert_eq!(clock_text, clock_form, "format_duration({}, clock)", ms);
+
+            // Both forms read back as the rounded milliseconds
+            for text in [compact_text, clock_text] {
+                assert_eq!(
+                    text_call(time::parse_duration, &[Value::String(text.clone())]),
+                    ms.round().to_string(),
+                    "round trip of {:?}",
+                    text
+                );
+            }
+        }
+
+        assert!(text_call(time::format_duration, &[Value::Number(-1.0)]).starts_with("error:"));
+        assert!(
+            text_call(time::format_duration, &[Value::Number(f64::INFINITY)]).starts_with("error:")
+        );
+    }
+
+    #[test]
+    fn test_time_format_and_parse_dates() {
+        let pattern = |text: &str| Value::String(text.to_string());
+        let cases = [
+            (0.0, "1970-01-01T00:00:00.000Z"),
+            (951_782_400_000.0, "2000-02-29T00:00:00.000Z"),
+            (1_700_000_000_123.0, "2023-11-14T22:13:20.123Z"),
+            (-1.0, "1969-12-31T23:59:59.999Z"),
+            (253_402_300_799_999.0, "9999-12-31T23:59:59.999Z"),
+            (-62_167_219_200_000.0, "0000-01-01T00:00:00.000Z"),
+        ];
+        for (ms, iso) in cases {
+            assert_eq!(text_call(time::format, &[Value::Number(ms)]), iso);
+            assert_eq!(
+                text_call(time::parse, &[Value::String(iso.to_string())]),
+                ms.to_string(),
+                "parse({:?})",
+                iso
+            );
+        }
+
+        let stamp = Value::Number(1_700_000_000_123.0);
+        assert_eq!(
+            text_call(
+                time::format,
+                &[stamp.clone(), pattern("%d/%m/%Y %H:%M (100%%)")]
+            ),
+            "14/11/2023 22:13 (100%)"
+        );
+        assert_eq!(
+            text_call(time::format, &[Value::Number(1.9), pattern("%S.%L")]),
+            "00.001"
+        );
+
+        let parses = [
+            ("2023-11-14", "%Y-%m-%d", "1699920000000"),
+            ("14.11.2023 22:13", "%d.%m.%Y %H:%M", "1699999980000"),
+            ("22:13", "%H:%M", "79980000"),
+            ("2023-13-01", "%Y-%m-%d", "error: Invalid date \"2023-13-01\": month must be 01 to 12\n  2023-13-01\n       ^^"),
+            ("2023-02-29", "%Y-%m-%d", "error: Invalid date \"2023-02-29\": day must be 01 to 28\n  2023-02-29\n          ^^"),
+            ("2023/11/14", "%Y-%m-%d", "error: Invalid date \"2023/11/14\": expected '-' here, as in \"%Y-%m-%d\"\n  2023/11/14\n      ^"),
+            ("2023-1-14", "%Y-%m-%d", "error: Invalid date \"2023-1-14\": expected 2 digits for %m\n  2023-1-14\n       ^^"),
+            ("2023-11-14 extra", "%Y-%m-%d", "error: Invalid date \"2023-11-14 extra\": unexpected text after \"%Y-%m-%d\"\n  2023-11-14 extra\n            ^^^^^^"),
+            ("24:00", "%H:%M", "error: Invalid date \"24:00\": hour must be 00 to 23\n  24:00\n  ^^"),
+            ("2023", "%Q", "error: Invalid pattern \"%Q\": unknown directive; use %Y, %m, %d, %H, %M, %S, %L or %%\n  %Q\n  ^^"),
+            ("2023", "%Y%", "error: Invalid pattern \"%Y%\": a lone '%' must be written %%\n  %Y%\n    ^"),
+        ];
+        for (text, format, expected) in parses {
+            let actual = text_call(time::parse, &[pattern(text), pattern(format)]);
+            assert_eq!(actual, expected, "parse({:?}, {:?})", text, format);
+        }
+    }
+
+    #[test]
+    fn test_time_now_and_elapsed_read_the_engines_clock() {
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut engine = Engine::builder().with_clock(clock.clone()).build();
+        engine.execute("let start = time.now()").unwrap();
+        clock.set(1_700_000_001_500);
+        assert_eq!(
+            engine.evaluate("time.elapsed(start)").unwrap(),
+            Value::Number(1_500.0)
+        );
+        assert_eq!(
+            engine
+                .evaluate("time.format(time.now(), \"%H:%M:%S.%L\")")
+                .unwrap(),
+            Value::String("22:13:21.500".to_string())
+        );
+    }
+
+    #[test]
+    fn test_math_functions() {
+        let n = Value::Number;
+        type Function = fn(&[Value]) -> Result<Value, InfraError>;
+        let cases: Vec<(Function, Vec<Value>, &str)> = vec![
+            (math::max, vec![n(1.0), n(5.0), n(3.0)], "5"),
+            (math::max, vec![n(-2.0)], "-2"),
+            (math::max, vec![Value::Array(vec![n(1.0), n(5.0), n(3.0)])], "5"),
+            (math::min, vec![n(4.0), n(2.0), n(9.0), n(2.5)], "2"),
+            (math::min, vec![Value::Array(vec![n(4.0), n(-1.0)])], "-1"),
+            (math::clamp, vec![n(12.0), n(0.0), n(10.0)], "10"),
+            (math::clamp, vec![n(-3.0), n(0.0), n(10.0)], "0"),
+            (math::clamp, vec![n(4.5), n(0.0), n(10.0)], "4.5"),
+            (math::sin, vec![n(0.0)], "0"),
+            (math::cos, vec![n(0.0)], "1"),
+            (math::tan, vec![n(0.0)], "0"),
+            (math::atan2, vec![n(0.0), n(-1.0)], "3.141592653589793"),
+            (math::log, vec![n(1.0)], "0"),
+            (math::log10, vec![n(1000.0)], "3"),
+            (math::exp, vec![n(0.0)], "1"),
+            (math::pi, vec![], "3.141592653589793"),
+            (math::e, vec![], "2.718281828459045"),
+            (math::max, vec![Value::Array(vec![])], "error: math.max: the array is empty"),
+            (math::min, vec![n(1.0), Value::String("2".to_string())], "error: Type error in math.min() function: expected number, found string"),
+            (math::clamp, vec![n(1.0), n(5.0), n(0.0)], "error: math.clamp: lo (5) must not be greater than hi (0)"),
+            (math::log, vec![n(-1.0)], "error: Cannot take the logarithm of a number that is not positive: math.log(-1) has no real result"),
+            (math::log10, vec![n(0.0)], "error: Cannot take the logarithm of a number that is not positive: math.log10(0) has no real result"),
+            (math::sqrt, vec![n(-4.0)], "error: Cannot take the square root of a negative number: math.sqrt(-4) has no real result"),
+            (math::sin, vec![], "error: Runtime error: Function 'math.sin' expected 1 arguments, found 0"),
+            (math::atan2, vec![n(1.0)], "error: Runtime error: Function 'math.atan2' expected 2 arguments, found 1"),
+            (math::pi, vec![n(1.0)], "error: Runtime error: Function 'math.pi' expected 0 arguments, found 1"),
+            (math::max, vec![], "error: Runtime error: Function 'math.max' expected 1 arguments, found 0"),
+        ];
+        for (function, args, expected) in cases {
+            assert_eq!(text_call(function, &args), expected, "args {:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_random_repeats_after_the_same_seed() {
+        let draws = "[random.random(), random.int(1, 6), random.choice([\"a\", \"b\", \"c\"]), \
+                     random.shuffle([1, 2, 3, 4, 5])]";
+        let mut runs = Vec::new();
+        for seed in [42, 42, 7] {
+            let mut runner = Runner::new();
+            runner
+                .execute_code(&format!("random.seed({})\nlet draws = {}", seed, draws))
+                .unwrap();
+            let draws = runner.get_interpreter().get_environment().get("draws");
+            runs.push(draws.unwrap().to_string());
+        }
+        assert_eq!(runs[0], runs[1], "the same seed must give the same numbers");
+        assert_ne!(
+            runs[0], runs[2],
+            "different seeds should give different numbers"
+        );
+
+        // Each interpreter has its own generator: draws in one leave the
+        // sequence of another untouched
+        let mut seeded = Runner::new();
+        let mut other = Runner::new();
+        seeded.execute_code("random.seed(42)\n").unwrap();
+        other
+            .execute_code("random.seed(7)\nlet skipped = random.shuffle([1, 2, 3])\n")
+            .unwrap();
+        seeded
+            .execute_code(&format!("let draws = {}", draws))
+            .unwrap();
+        let draws = seeded.get_interpreter().get_environment().get("draws");
+        assert_eq!(draws.unwrap().to_string(), runs[0]);
+    }
+
+    #[test]
+    fn test_random_stays_in_range() {
+        let mut random = random::Random::default();
+        random.seed(&[Value::Number(1.0)]).unwrap();
+        let mut seen = [false; 3];
+        for _ in 0..200 {
+            let Value::Number(unit) = random.random(&[]).unwrap() else {
+                panic!("random.random() must return a number");
+            };
+            assert!((0.0..1.0).contains(&unit), "{} is outside [0, 1)", unit);
+
+            let Value::Number(n) = random
+                .int(&[Value::Number(-1.0), Value::Number(1.0)])
+                .unwrap()
+            else {
+                panic!("random.int() must return a number");
+            };
+            assert!(
+                n.fract() == 0.0 && (-1.0..=1.0).contains(&n),
+                "{} is outside -1..=1",
+                n
+            );
+            seen[(n + 1.0) as usize] = true;
+        }
+        assert_eq!(
+            seen, [true; 3],
+            "both ends of random.int's range must come up"
+        );
+
+        let items: Vec<Value> = (1..=10).map(|n| Value::Number(n as f64)).collect();
+        let Value::Array(mut shuffled) = random.shuffle(&[Value::Array(items.clone())]).unwrap()
+        else {
+            panic!("random.shuffle() must return an array");
+        };
+        shuffled.sort_by_key(|value| value.to_string());
+        let mut sorted = items;
+        sorted.sort_by_key(|value| value.to_string());
+        assert_eq!(shuffled, sorted, "shuffle must keep every element");
+
+        assert_eq!(
+            random
+                .choice(&[Value::Array(vec![])])
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: random.choice: cannot choose from an empty array"
+        );
+        assert_eq!(
+            random
+                .int(&[Value::Number(5.0), Value::Number(1.0)])
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: random.int: min (5) is greater than max (1)"
+        );
+        assert!(random
+            .int(&[Value::Number(1.5), Value::Number(3.0)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_units_parse_size() {
+        let cases = [
+            ("1.5GiB", "1610612736"),
+            ("1.5 GiB", "1610612736"),
+            ("1.5gib", "1610612736"),
+            ("1KiB", "1024"),
+            ("1KB", "1000"),
+            ("1kb", "1000"),
+            ("20 MB", "20000000"),
+            ("2TB", "2000000000000"),
+            ("1PiB", "1125899906842624"),
+            ("7.99PiB", "8995940255672566"),
+            ("1.1KiB", "1126"),
+            ("10B", "10"),
+            // A bare number is bytes
+            ("512", "512"),
+            ("0", "0"),
+            ("8PiB", "error: Invalid size \"8PiB\": too large to count in whole bytes\n  8PiB\n  ^^^^"),
+            ("-1KB", "error: Invalid size \"-1KB\": negative sizes are not allowed\n  -1KB\n  ^"),
+            ("1.5 GX", "error: Invalid size \"1.5 GX\": unknown unit 'GX' (use B, KB, MB, GB, TB, PB or KiB, MiB, GiB, TiB, PiB)\n  1.5 GX\n      ^^"),
+            ("1K", "error: Invalid size \"1K\": unknown unit 'K' (use B, KB, MB, GB, TB, PB or KiB, MiB, GiB, TiB, PiB)\n  1K\n   ^"),
+            ("GiB", "error: Invalid size \"GiB\": expected a number\n  GiB\n  ^^^"),
+            ("  ", "error: Invalid size \"  \": empty size\n    \n  ^"),
+        ];
+        for (text, expected) in cases {
+            let actual = text_call(units::parse_size, &[Value::String(text.to_string())]);
+            assert_eq!(actual, expected, "parse_size({:?})", text);
+        }
+    }
+
+    #[test]
+    fn test_units_format_size() {
+        let options = |binary: bool, precision: f64| {
+            let mut options = ObjectMap::new();
+            options.insert("binary".to_string(), Value::Boolean(binary));
+            options.insert("precision".to_string(), Value::Number(precision));
+            Value::Object(options)
+        };
+        let cases = [
+            (0.0, false, 1.0, "0 B"),
+            (999.0, false, 1.0, "999 B"),
+            (1000.0, false, 1.0, "1 KB"),
+            (1023.0, true, 1.0, "1023 B"),
+            (1024.0, true, 1.0, "1 KiB"),
+            (1536.0, true, 1.0, "1.5 KiB"),
+            (1023.0, false, 1.0, "1 KB"),
+            (1_048_575.0, true, 1.0, "1 MiB"),
+            (1_048_575.0, true, 3.0, "1023.999 KiB"),
+            (999_999.0, false, 2.0, "1 MB"),
+            (1_500_000.0, false, 1.0, "1.5 MB"),
+            (1_610_612_736.0, true, 2.0, "1.5 GiB"),
+            (1_234_567.0, false, 0.0, "1 MB"),
+            (1_234_567.0, false, 3.0, "1.235 MB"),
+            (2_000_000_000_000_000.0, false, 1.0, "2 PB"),
+            (9_007_199_254_740_991.0, true, 1.0, "8 PiB"),
+            (1_125_899_906_842_624.0, true, 1.0, "1 PiB"),
+        ];
+        for (bytes, binary, precision, expected) in cases {
+            let actual = text_call(
+                units::format_size,
+                &[Value::Number(bytes), options(binary, precision)],
+            );
+            assert_eq!(
+                actual, expected,
+                "format_size({}, {}, {})",
+                bytes, binary, precision
+            );
+        }
+        assert_eq!(
+            text_call(units::format_size, &[Value::Number(1_500_000.0)]),
+            "1.5 MB"
+        );
+
+        // Exact renderings read back to the same size
+        let exact = [
+            (0.0, false),
+            (512.0, true),
+            (1536.0, false),
+            (1536.0, true),
+            (5_000_000.0, false),
+            (1_610_612_736.0, true),
+        ];
+        for (bytes, binary) in exact {
+            let text = text_call(
+                units::format_size,
+                &[Value::Number(bytes), options(binary, 3.0)],
+            );
+            assert_eq!(
+                text_call(units::parse_size, &[Value::String(text.clone())]),
+                bytes.to_string(),
+                "round trip of {:?}",
+                text
+            );
+        }
+
+        for bad in [options(true, -1.0), options(true, 1.5), Value::Number(1.0)] {
+            assert!(text_call(units::format_size, &[Value::Number(1.0), bad]).starts_with("error:"));
+        }
+        assert!(text_call(units::format_size, &[Value::Number(-1.0)]).starts_with("error:"));
+    }
+
+    #[test]
+    fn test_json_round_trips_the_repos_config_files() {
+        let documents = [
+            include_str!("../../editors/grammars/package.json"),
+            include_str!("../../editors/vscode-extension/package.json"),
+            include_str!("../../editors/vscode-extension/language-configuration.json"),
+            include_str!("../../editors/vscode-extension/syntaxes/infra.tmLanguage.json"),
+            include_str!("../../editors/vscode-extension/tsconfig.json"),
+        ];
+        for document in documents {
+            let parsed = json::parse(&[Value::String(document.to_string())]).unwrap();
+            for indent in [
+                Value::Null,
+                Value::Number(2.0),
+                Value::String("\t".to_string()),
+            ] {
+                let written = json::stringify(&[parsed.clone(), indent]).unwrap();
+                assert_eq!(json::parse(&[written]).unwrap(), parsed);
+            }
+        }
+
+        let nested =
+            crate::utils::json::parse("{\"a\": [1, {\"b c\": \"q\\\"\\n\"}], \"d\": {}}").unwrap();
+        assert_eq!(
+            text_call(json::stringify, std::slice::from_ref(&nested)),
+            "{\"a\":[1,{\"b c\":\"q\\\"\\n\"}],\"d\":{}}"
+        );
+        assert_eq!(
+            text_call(json::stringify, &[nested, Value::Number(1.0)]),
+            "{\n \"a\": [\n  1,\n  {\n   \"b c\": \"q\\\"\\n\"\n  }\n ],\n \"d\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_json_errors_say_where() {
+        let text = Value::String("{\n  \"a\": 1,\n  \"b\" 2\n}".to_string());
+        assert_eq!(
+            text_call(json::parse, &[text]),
+            "error: json.parse: Expected ':' at line 3, column 7 (byte 18)"
+        );
+
+        let deep = Value::String(format!("{}{}", "[".repeat(600), "]".repeat(600)));
+        assert_eq!(
+            text_call(json::parse, &[deep]),
+            "error: json.parse: Values are nested more than 512 levels deep \
+             at line 1, column 513 (byte 512)"
+        );
+        let deepest = format!("{}{}", "[".repeat(512), "]".repeat(512));
+        assert!(json::parse(&[Value::String(deepest)]).is_ok());
+
+        let mut handlers = ObjectMap::new();
+        handlers.insert(
+            "on start".to_string(),
+            Value::Array(vec![
+                Value::Number(1.0),
+                Value::NativeFunction {
+                    module: "math".to_string(),
+                    function: "sqrt".to_string(),
+                },
+            ]),
+        );
+        let mut config = ObjectMap::new();
+        config.insert("handlers".to_string(), Value::Object(handlers));
+        match json::stringify(&[Value::Object(config)]) {
+            Err(InfraError::TypeError { found, context, .. }) => {
+                assert_eq!(found, "function");
+                assert_eq!(
+                    context.as_deref(),
+                    Some("json.stringify() at $.handlers[\"on start\"][1]")
+                );
+            }
+            other => panic!("expected a TypeError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lifetime_findings_point_at_the_right_tokens() {
+        // Lets in a lambda-free function, a class method and a nested
+        // function must not put the tokens out of step with the statements
+        let source = "let count: number = 0\n\
+                      function helper(x):\n\
+                      \x20   return function(y): x + y\n\
+                      class Box:\n\
+                      {\n\
+                      \x20   open():\n\
+                      \x20   {\n\
+                      \x20       let count = 1\n\
+                      \x20       return count\n\
+                      \x20   }\n\
+                      }\n\
+                      if count == 0:\n\
+                      {\n\
+                      \x20   let count: number = count + 1\n\
+                      \x20   for k in range(0, 2):\n\
+                      \x20   {\n\
+                      \x20       function inner():\n\
+                      \x20           k = 3\n\
+                      \x20       k = 4\n\
+                      \x20   }\n\
+                      }\n\
+                      print(count)\n";
+        let findings = check_source(source).findings;
+        let summary: Vec<(usize, usize, &str)> = findings
+            .iter()
+            .map(|f| (f.line, f.column, f.code))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(14, 9, "shadowing"), (19, 9, "loop-variable")],
+            "{:?}",
+            findings
+        );
+        assert!(fix_source(source)
+            .fixed
+            .contains("\n    count = count + 1\n"));
+
+        // Only a parsed program is analysed
+        let broken = "let total = 0\nif true:\n{\n    let total = 1\n}\nprint(total\n";
+        assert!(check_source(broken)
+            .findings
+            .iter()
+            .all(|finding| finding.code == "syntax"));
+    }
+
+    #[cfg(windows)]
+    mod windows {
+        use super::*;
+
+        #[test]
+        fn test_path_accepts_backslashes_and_drive_letters() {
+            let mut runner = Runner::new();
+            runner
+                .execute_code(
+                    "let tidy = path.normalize(\"C:\\\\Users\\\\me\\\\..\\\\you/file.txt\")\n\
+                     let abs = path.is_absolute(\"C:\\\\temp\")\n\
+                     let dir = path.dirname(\"logs\\\\today.txt\")\n",
+                )
+                .unwrap();
+
+            assert_eq!(string_var(&runner, "tidy"), "C:/Users/you/file.txt");
+            assert_eq!(string_var(&runner, "dir"), "logs");
+            assert_eq!(
+                runner
+                    .get_interpreter()
+                    .get_environment()
+                    .get("abs")
+                    .unwrap(),
+                Value::Boolean(true)
+            );
+        }
+
+        #[test]
+        fn test_infra_path_splits_on_semicolons() {
+            let entries: Vec<_> = std::env::split_paths("C:\\lib;D:\\more").collect();
+            assert_eq!(entries.len(), 2);
+        }
+
+        #[test]
+        fn test_batch_editor_runs_through_cmd() {
+            let command = platform::command("notepad.cmd");
+            assert_eq!(command.get_program(), "cmd");
+        }
+    }
 }
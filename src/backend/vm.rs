@@ -1,9 +1,14 @@
 use crate::backend::bytecode::{Chunk, OpCode};
 use crate::core::{error::InfraError, Value};
+use crate::stdlib::{async_mod, output, sources};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 #[allow(dead_code)]
-const STACK_MAX: usize = 256;
+const STACK_MAX: usize = 65536;
+
+/// Calls deeper than this are reported as a stack overflow
+const FRAMES_MAX: usize = 10000;
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -13,12 +18,20 @@ pub struct VM {
     stack: Vec<Value>,
     locals: Vec<Value>, // Local variables storage
     globals: HashMap<String, Value>,
+    frames: Vec<CallFrame>, // Calls in progress, innermost last
 
     // Async execution state
     async_state: AsyncState,
     event_loop: EventLoop,
 }
 
+/// A call in progress: where its caller continues and the caller's locals
+#[derive(Debug)]
+struct CallFrame {
+    return_ip: usize,
+    locals: Vec<Value>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct AsyncState {
@@ -71,9 +84,8 @@ pub struct MicroTask {
 #[derive(Debug)]
 pub struct Timer {
     pub id: usize,
-    pub duration: u64,
+    pub due: u64, // Milliseconds since the Unix epoch, on the current clock
     pub callback: Value,
-    pub elapsed: u64,
 }
 
 impl VM {
@@ -81,9 +93,10 @@ impl VM {
         Self {
             chunk: None,
             ip: 0,
-            stack: Vec::with_capacity(STACK_MAX),
+            stack: Vec::with_capacity(256),
             locals: Vec::new(),
             globals: HashMap::new(),
+            frames: Vec::new(),
             async_state: AsyncState {
                 is_async: false,
                 suspended_ip: None,
@@ -100,10 +113,29 @@ impl VM {
         }
     }
 
+    /// The variable in local slot `slot` after `interpret`; top-level `let`s
+    /// take slots in order from 0
+    #[allow(dead_code)]
+    pub fn local(&self, slot: usize) -> Option<&Value> {
+        self.locals.get(slot)
+    }
+
     pub fn interpret(&mut self, chunk: Chunk) -> Result<(), InfraError> {
         self.chunk = Some(chunk);
         self.ip = 0;
-        self.run()
+        self.frames.clear();
+        // Place errors at the line of the instruction that failed
+        self.run().map_err(|error| match self.current_line() {
+            Some(line) => error.at(line, 0),
+            None => error,
+        })
+    }
+
+    /// Source line of the instruction last executed, when known
+    fn current_line(&self) -> Option<usize> {
+        let chunk = self.chunk.as_ref()?;
+        let line = *chunk.lines.get(self.ip.checked_sub(1)?)?;
+        (line > 0).then_some(line)
     }
 
     fn run(&mut self) -> Result<(), InfraError> {
@@ -137,171 +169,54 @@ impl VM {
                     self.locals[slot] = value;
                 }
 
-                OpCode::Pop => {
-                    self.pop()?;
-                }
-
-                OpCode::Add => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = (a + b)?;
-                    self.push(result)?;
-                }
-
-                OpCode::Sub => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = (a - b)?;
-                    self.push(result)?;
-                }
-
-                OpCode::Mul => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = (a * b)?;
-                    self.push(result)?;
-                }
-
-                OpCode::Div => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = (a / b)?;
-                    self.push(result)?;
-                }
-
-                OpCode::Mod => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = (a % b)?;
-                    self.push(result)?;
-                }
-
-                OpCode::Negate => {
-                    let value = self.pop()?;
-                    match value {
-                        Value::Number(n) => self.push(Value::Number(-n))?,
-                        _ => {
-                            return Err(InfraError::RuntimeError {
-                                message: "Can only negate numbers".to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
-                    }
-                }
-
-                OpCode::Equal => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    self.push(Value::Boolean(a == b))?;
-                }
-
-                OpCode::NotEqual => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    self.push(Value::Boolean(a != b))?;
-                }
-
-                OpCode::Less => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.push(Value::Boolean(a < b))?;
-                        }
-                        _ => {
-                            return Err(InfraError::RuntimeError {
-                                message: "Can only compare numbers".to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
-                    }
-                }
-
-                OpCode::Greater => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.push(Value::Boolean(a > b))?;
-                        }
-                        _ => {
-                            return Err(InfraError::RuntimeError {
-                                message: "Can only compare numbers".to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
-                    }
-                }
-
-                OpCode::LessEqual => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.push(Value::Boolean(a <= b))?;
-                        }
-                        _ => {
-                            return Err(InfraError::RuntimeError {
-                                message: "Can only compare numbers".to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
+                OpCode::LoadGlobal(slot) => {
+                    let globals = self.globals_mut();
+                    if slot >= globals.len() {
+                        globals.resize(slot + 1, Value::Null);
                     }
+                    let value = globals[slot].clone();
+                    self.push(value)?;
                 }
 
-                OpCode::GreaterEqual => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.push(Value::Boolean(a >= b))?;
-                        }
-                        _ => {
-                            return Err(InfraError::RuntimeError {
-                                message: "Can only compare numbers".to_string(),
-                                line: None,
-                                column: None,
-                                stack_trace: vec![],
-                                source_code: None,
-                            })
-                        }
+                OpCode::StoreGlobal(slot) => {
+                    let value = self.pop()?;
+                    let globals = self.globals_mut();
+                    if slot >= globals.len() {
+                        globals.resize(slot + 1, Value::Null);
                     }
+                    globals[slot] = value;
                 }
 
-                OpCode::And => {
-                    let b = self.pop()?;
-                    let a = self.pop()?;
-                    let result = Value::Boolean(a.is_truthy() && b.is_truthy());
-                    self.push(result)?;
+                OpCode::Pop => {
+                    self.pop()?;
                 }
 
-                OpCode::Or => {
+                OpCode::Add
+                | OpCode::Sub
+                | OpCode::Mul
+                | OpCode::Div
+                | OpCode::Mod
+                | OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Less
+                | OpCode::Greater
+                | OpCode::LessEqual
+                | OpCode::GreaterEqual
+                | OpCode::And
+                | OpCode::Or => {
                     let b = self.pop()?;
                     let a = self.pop()?;
-                    let result = Value::Boolean(a.is_truthy() || b.is_truthy());
-                    self.push(result)?;
+                    self.push(binary(instruction, a, b)?)?;
                 }
 
-                OpCode::Not => {
+                OpCode::Negate | OpCode::Not => {
                     let value = self.pop()?;
-                    self.push(Value::Boolean(!value.is_truthy()))?;
+                    self.push(unary(instruction, value)?)?;
                 }
 
                 OpCode::Print => {
                     let value = self.pop()?;
-                    println!("{}", value);
+                    output::print_value(&value);
                 }
 
                 OpCode::MakeArray(count) => {
@@ -314,12 +229,13 @@ impl VM {
                 }
 
                 OpCode::MakeObject(count) => {
-                    let mut object = HashMap::new();
+                    // Pairs come off the stack last first
+                    let mut pairs = Vec::with_capacity(count);
                     for _ in 0..count {
                         let value = self.pop()?;
                         let key = self.pop()?;
                         if let Value::String(key_str) = key {
-                            object.insert(key_str, value);
+                            pairs.push((key_str, value));
                         } else {
                             return Err(InfraError::RuntimeError {
                                 message: "Object keys must be strings".to_string(),
@@ -330,6 +246,7 @@ impl VM {
                             });
                         }
                     }
+                    let object = pairs.into_iter().rev().collect();
                     self.push(Value::Object(object))?;
                 }
 
@@ -344,10 +261,17 @@ impl VM {
                     }
                 }
 
+                OpCode::Call(arg_count) => self.call(arg_count)?,
+
                 OpCode::Return => {
-                    // For now, just break out of the loop
-                    // In a full implementation, this would handle function returns
-                    break;
+                    // A return at the top level ends the program
+                    let Some(frame) = self.frames.pop() else {
+                        break;
+                    };
+                    let value = self.pop()?;
+                    self.locals = frame.locals;
+                    self.ip = frame.return_ip;
+                    self.push(value)?;
                 }
 
                 OpCode::CreatePromise => {
@@ -417,6 +341,72 @@ impl VM {
         Ok(())
     }
 
+    /// Start running the function below the top `arg_count` values, with
+    /// those values as its first locals
+    fn call(&mut self, arg_count: usize) -> Result<(), InfraError> {
+        if self.stack.len() <= arg_count {
+            return Err(InfraError::RuntimeError {
+                message: "Stack underflow".to_string(),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            });
+        }
+        let args = self.stack.split_off(self.stack.len() - arg_count);
+
+        let (name, arity, entry) = match self.pop()? {
+            Value::CompiledFunction { name, arity, entry } => (name, arity, entry),
+            other => {
+                return Err(InfraError::TypeError {
+                    expected: "function".to_string(),
+                    found: other.type_name().to_string(),
+                    context: Some("function call".to_string()),
+                    line: None,
+                    column: None,
+                    hint: None,
+                })
+            }
+        };
+        if arity != arg_count {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: arity,
+                found: arg_count,
+                function_name: Some(name),
+                line: None,
+            });
+        }
+        if self.frames.len() >= FRAMES_MAX {
+            return Err(InfraError::RuntimeError {
+                message: format!(
+                    "Stack overflow: more than {} nested calls, the last to '{}'; \
+                     is a recursive function missing its base case?",
+                    FRAMES_MAX, name
+                ),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            });
+        }
+
+        let caller_locals = std::mem::replace(&mut self.locals, args);
+        self.frames.push(CallFrame {
+            return_ip: self.ip,
+            locals: caller_locals,
+        });
+        self.ip = entry;
+        Ok(())
+    }
+
+    /// The top level's variables, wherever the running code is
+    fn globals_mut(&mut self) -> &mut Vec<Value> {
+        match self.frames.first_mut() {
+            Some(frame) => &mut frame.locals,
+            None => &mut self.locals,
+        }
+    }
+
     // Async helper methods
     fn create_promise(&mut self) -> Result<Value, InfraError> {
         let promise_id = self.event_loop.promises.len();
@@ -431,12 +421,7 @@ impl VM {
 
         self.event_loop.promises.push(promise.clone());
 
-        Ok(Value::Promise {
-            value: None,
-            resolved: false,
-            rejected: false,
-            error: None,
-        })
+        Ok(async_mod::pending_promise("async function"))
     }
 
     fn resolve_promise(&mut self, _value: Value) -> Result<(), InfraError> {
@@ -561,12 +546,10 @@ impl VM {
     fn process_timers(&mut self) -> Result<(), InfraError> {
         // Check for expired timers and execute their callbacks
         let mut expired_timers = Vec::new();
+        let now = sources::now_ms();
 
         for i in (0..self.event_loop.timers.len()).rev() {
-            let timer = &mut self.event_loop.timers[i];
-            timer.elapsed += 1; // Simulate time passing
-
-            if timer.elapsed >= timer.duration {
+            if self.event_loop.timers[i].due <= now {
                 expired_timers.push(i);
             }
         }
@@ -615,6 +598,60 @@ impl VM {
     }
 }
 
+/// What a binary opcode makes of `a` and `b`; the optimizer folds
+/// constants with this too, so both agree
+pub fn binary(op: OpCode, a: Value, b: Value) -> Result<Value, InfraError> {
+    match op {
+        OpCode::Add => a + b,
+        OpCode::Sub => a - b,
+        OpCode::Mul => a * b,
+        OpCode::Div => a / b,
+        OpCode::Mod => a % b,
+        OpCode::Equal => Ok(Value::Boolean(a.script_eq(&b))),
+        OpCode::NotEqual => Ok(Value::Boolean(!a.script_eq(&b))),
+        OpCode::Less => compare(&a, &b, Ordering::is_lt),
+        OpCode::Greater => compare(&a, &b, Ordering::is_gt),
+        OpCode::LessEqual => compare(&a, &b, Ordering::is_le),
+        OpCode::GreaterEqual => compare(&a, &b, Ordering::is_ge),
+        OpCode::And => Ok(Value::Boolean(a.is_truthy() && b.is_truthy())),
+        OpCode::Or => Ok(Value::Boolean(a.is_truthy() || b.is_truthy())),
+        _ => unreachable!("{:?} is not a binary opcode", op),
+    }
+}
+
+/// Whether two numbers are ordered as `accept` wants
+fn compare(a: &Value, b: &Value, accept: fn(Ordering) -> bool) -> Result<Value, InfraError> {
+    match (a, b) {
+        (Value::Number(_) | Value::BigInt(_), Value::Number(_) | Value::BigInt(_)) => {
+            Ok(Value::Boolean(a.numeric_cmp(b).is_some_and(accept)))
+        }
+        _ => Err(InfraError::RuntimeError {
+            message: "Can only compare numbers".to_string(),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }),
+    }
+}
+
+/// What a unary opcode makes of `value`
+pub fn unary(op: OpCode, value: Value) -> Result<Value, InfraError> {
+    match (op, value) {
+        (OpCode::Negate, Value::Number(n)) => Ok(Value::Number(-n)),
+        (OpCode::Negate, Value::BigInt(n)) => Ok(Value::BigInt(-n)),
+        (OpCode::Negate, _) => Err(InfraError::RuntimeError {
+            message: "Can only negate numbers".to_string(),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }),
+        (OpCode::Not, value) => Ok(Value::Boolean(!value.is_truthy())),
+        (op, _) => unreachable!("{:?} is not a unary opcode", op),
+    }
+}
+
 impl Default for VM {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,461 @@
+use crate::backend::ModuleSystem;
+use crate::core::ast::{ExportItem, ImportItems, Stmt};
+use crate::core::{InfraError, Result, Value};
+use crate::frontend::{Lexer, Parser};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A module found while walking the import graph
+#[derive(Debug)]
+pub struct ModuleInfo {
+    pub path: PathBuf,
+    pub exports: Vec<String>,
+    pub imports: Vec<ImportEdge>,
+    pub parse_error: Option<String>,
+}
+
+/// One `import` statement of a module
+#[derive(Debug)]
+pub struct ImportEdge {
+    pub module_path: String,
+    pub target: Option<usize>, // Index into Analysis::modules, None if unresolved
+    pub names: Option<Vec<String>>, // None for `import *` and default imports
+}
+
+/// Static findings for a module graph
+#[derive(Debug)]
+pub struct Analysis {
+    pub root: PathBuf,
+    pub modules: Vec<ModuleInfo>,
+    pub order: Vec<usize>, // Dependencies before dependents
+    pub unused_exports: Vec<(usize, String)>,
+    pub missing_imports: Vec<MissingImport>,
+    pub cycles: Vec<Vec<usize>>,
+}
+
+#[derive(Debug)]
+pub struct MissingImport {
+    pub importer: usize,
+    pub module_path: String,
+    pub name: Option<String>, // None when the module itself could not be found
+}
+
+/// Walk the import graph from `entry` without executing any module bodies
+pub fn analyze(entry: &Path) -> Result<Analysis> {
+    let entry = entry.canonicalize().map_err(|err| InfraError::IoError {
+        message: format!("Error reading file '{}': {}", entry.display(), err),
+        operation: Some("analyze".to_string()),
+        path: Some(entry.display().to_string()),
+    })?;
+    let root = entry.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let module_system = ModuleSystem::new();
+    let mut modules: Vec<ModuleInfo> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    let mut queue = vec![entry.clone()];
+    index.insert(entry, 0);
+
+    // Parse each reachable module once, in discovery order
+    while modules.len() < queue.len() {
+        let path = queue[modules.len()].clone();
+        let (exports, raw_imports, parse_error) = match parse_module(&path) {
+            Ok((exports, imports)) => (exports, imports, None),
+            Err(err) => (vec![], vec![], Some(err.to_string())),
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut imports = Vec::new();
+        for (module_path, names) in raw_imports {
+            let target = module_system
+                .resolve_module_path(&module_path, dir)
+                .ok()
+                .and_then(|p| p.canonicalize().ok())
+                .map(|resolved| {
+                    *index.entry(resolved.clone()).or_insert_with(|| {
+                        queue.push(resolved);
+                        queue.len() - 1
+                    })
+                });
+            imports.push(ImportEdge {
+                module_path,
+                target,
+                names,
+            });
+        }
+
+        modules.push(ModuleInfo {
+            path,
+            exports,
+            imports,
+            parse_error,
+        });
+    }
+
+    let (order, cycles) = topological_order(&modules);
+    let missing_imports = find_missing_imports(&modules);
+    let unused_exports = find_unused_exports(&modules);
+
+    Ok(Analysis {
+        root,
+        modules,
+        order,
+        unused_exports,
+        missing_imports,
+        cycles,
+    })
+}
+
+type ParsedModule = (Vec<String>, Vec<(String, Option<Vec<String>>)>);
+
+fn parse_module(path: &Path) -> Result<ParsedModule> {
+    let source = fs::read_to_string(path).map_err(|err| InfraError::IoError {
+        message: format!("Could not read module file: {}", err),
+        operation: Some("analyze".to_string()),
+        path: Some(path.display().to_string()),
+    })?;
+
+    let mut lexer = Lexer::new(&source);
+    let tokens = lexer.tokenize()?;
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse()?;
+
+    let mut exports = Vec::new();
+    let mut imports = Vec::new();
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Export { item } => match item {
+                ExportItem::Function { name, .. } | ExportItem::Variable { name, .. } => {
+                    exports.push(name.clone())
+                }
+            },
+            Stmt::Import {
+                module_path, items, ..
+            } => {
+                let names = match items {
+                    ImportItems::Named(items) => {
+                        Some(items.iter().map(|item| item.name.clone()).collect())
+                    }
+                    ImportItems::All | ImportItems::Default(_) => None,
+                };
+                imports.push((module_path.clone(), names));
+            }
+            _ => {}
+        }
+    }
+
+    Ok((exports, imports))
+}
+
+/// Depth-first post-order over import edges, recording every back edge as a cycle
+fn topological_order(modules: &[ModuleInfo]) -> (Vec<usize>, Vec<Vec<usize>>) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        node: usize,
+        modules: &[ModuleInfo],
+        state: &mut [State],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        state[node] = State::InProgress;
+        path.push(node);
+
+        for edge in &modules[node].imports {
+            let Some(target) = edge.target else {
+                continue;
+            };
+            match state[target] {
+                State::Unvisited => visit(target, modules, state, path, order, cycles),
+                State::InProgress => {
+                    let start = path.iter().position(|&n| n == target).unwrap();
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(target);
+                    cycles.push(cycle);
+                }
+                State::Done => {}
+            }
+        }
+
+        path.pop();
+        state[node] = State::Done;
+        order.push(node);
+    }
+
+    let mut state = vec![State::Unvisited; modules.len()];
+    let mut order = Vec::new();
+    let mut cycles = Vec::new();
+    for node in 0..modules.len() {
+        if state[node] == State::Unvisited {
+            visit(
+                node,
+                modules,
+                &mut state,
+                &mut Vec::new(),
+                &mut order,
+                &mut cycles,
+            );
+        }
+    }
+
+    (order, cycles)
+}
+
+fn find_missing_imports(modules: &[ModuleInfo]) -> Vec<MissingImport> {
+    let mut missing = Vec::new();
+
+    for (importer, module) in modules.iter().enumerate() {
+        for edge in &module.imports {
+            match edge.target {
+                None => missing.push(MissingImport {
+                    importer,
+                    module_path: edge.module_path.clone(),
+                    name: None,
+                }),
+                Some(target) if modules[target].parse_error.is_none() => {
+                    for name in edge.names.iter().flatten() {
+                        if !modules[target].exports.contains(name) {
+                            missing.push(MissingImport {
+                                importer,
+                                module_path: edge.module_path.clone(),
+                                name: Some(name.clone()),
+                            });
+                        }
+                    }
+                }
+                // Exports of unparseable modules are unknown
+                Some(_) => {}
+            }
+        }
+    }
+
+    missing
+}
+
+fn find_unused_exports(modules: &[ModuleInfo]) -> Vec<(usize, String)> {
+    let mut used: HashSet<(usize, &str)> = HashSet::new();
+    let mut fully_used: HashSet<usize> = HashSet::new();
+
+    for module in modules {
+        for edge in &module.imports {
+            let Some(target) = edge.target else {
+                continue;
+            };
+            match &edge.names {
+                Some(names) => used.extend(names.iter().map(|n| (target, n.as_str()))),
+                None => {
+                    fully_used.insert(target);
+                }
+            }
+        }
+    }
+
+    // The entry point (module 0) is not imported by anything, so skip it
+    let mut unused = Vec::new();
+    for (i, module) in modules.iter().enumerate().skip(1) {
+        if fully_used.contains(&i) {
+            continue;
+        }
+        for name in &module.exports {
+            if !used.contains(&(i, name.as_str())) {
+                unused.push((i, name.clone()));
+            }
+        }
+    }
+    unused
+}
+
+impl Analysis {
+    /// Module path relative to the entry point's directory
+    pub fn display_name(&self, module: usize) -> String {
+        let path = &self.modules[module].path;
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+            .replace('\\', "/")
+    }
+
+    /// Edges as (importer, imported) display names, without duplicates
+    pub fn edges(&self) -> Vec<(String, String)> {
+        let mut edges = Vec::new();
+        for (i, module) in self.modules.iter().enumerate() {
+            for target in module.imports.iter().filter_map(|edge| edge.target) {
+                let edge = (self.display_name(i), self.display_name(target));
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+        edges
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("Modules (dependencies first):\n");
+        for &i in &self.order {
+            let count = self.modules[i].exports.len();
+            out.push_str(&format!(
+                "  {} ({} export{})\n",
+                self.display_name(i),
+                count,
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+
+        if !self.unused_exports.is_empty() {
+            out.push_str("\nUnused exports:\n");
+            for (module, name) in &self.unused_exports {
+                out.push_str(&format!("  {}: {}\n", self.display_name(*module), name));
+            }
+        }
+
+        if !self.missing_imports.is_empty() {
+            out.push_str("\nMissing imports:\n");
+            for missing in &self.missing_imports {
+                let importer = self.display_name(missing.importer);
+                match &missing.name {
+                    Some(name) => out.push_str(&format!(
+                        "  {}: '{}' is not exported by \"{}\"\n",
+                        importer, name, missing.module_path
+                    )),
+                    None => out.push_str(&format!(
+                        "  {}: module \"{}\" not found\n",
+                        importer, missing.module_path
+                    )),
+                }
+            }
+        }
+
+        if !self.cycles.is_empty() {
+            out.push_str("\nImport cycles:\n");
+            for cycle in &self.cycles {
+                let names: Vec<String> = cycle.iter().map(|&m| self.display_name(m)).collect();
+                out.push_str(&format!("  {}\n", names.join(" -> ")));
+            }
+        }
+
+        let errors: Vec<usize> = (0..self.modules.len())
+            .filter(|&i| self.modules[i].parse_error.is_some())
+            .collect();
+        if !errors.is_empty() {
+            out.push_str("\nParse errors:\n");
+            for i in errors {
+                out.push_str(&format!(
+                    "  {}: {}\n",
+                    self.display_name(i),
+                    self.modules[i].parse_error.as_ref().unwrap()
+                ));
+            }
+        }
+
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph modules {\n");
+        for i in 0..self.modules.len() {
+            out.push_str(&format!("  \"{}\";\n", self.display_name(i)));
+        }
+        for (from, to) in self.edges() {
+            out.push_str(&format!("  \"{}\" -> \"{}\";\n", from, to));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let name = |i: usize| Value::String(self.display_name(i));
+        let object = |pairs: Vec<(&str, Value)>| {
+            Value::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+
+        let modules = self
+            .order
+            .iter()
+            .map(|&i| {
+                let module = &self.modules[i];
+                object(vec![
+                    ("path", name(i)),
+                    (
+                        "exports",
+                        Value::Array(
+                            module
+                                .exports
+                                .iter()
+                                .map(|e| Value::String(e.clone()))
+                                .collect(),
+                        ),
+                    ),
+                    (
+                        "parse_error",
+                        module
+                            .parse_error
+                            .clone()
+                            .map(Value::String)
+                            .unwrap_or(Value::Null),
+                    ),
+                ])
+            })
+            .collect();
+
+        let edges = self
+            .edges()
+            .into_iter()
+            .map(|(from, to)| {
+                object(vec![
+                    ("from", Value::String(from)),
+                    ("to", Value::String(to)),
+                ])
+            })
+            .collect();
+
+        let unused = self
+            .unused_exports
+            .iter()
+            .map(|(m, export)| {
+                object(vec![
+                    ("module", name(*m)),
+                    ("export", Value::String(export.clone())),
+                ])
+            })
+            .collect();
+
+        let missing = self
+            .missing_imports
+            .iter()
+            .map(|m| {
+                object(vec![
+                    ("importer", name(m.importer)),
+                    ("module", Value::String(m.module_path.clone())),
+                    (
+                        "name",
+                        m.name.clone().map(Value::String).unwrap_or(Value::Null),
+                    ),
+                ])
+            })
+            .collect();
+
+        let cycles = self
+            .cycles
+            .iter()
+            .map(|cycle| Value::Array(cycle.iter().map(|&m| name(m)).collect()))
+            .collect();
+
+        let report = object(vec![
+            ("modules", Value::Array(modules)),
+            ("edges", Value::Array(edges)),
+            ("unused_exports", Value::Array(unused)),
+            ("missing_imports", Value::Array(missing)),
+            ("cycles", Value::Array(cycles)),
+        ]);
+
+        // Only strings, arrays, objects and null are involved, so this always succeeds
+        report.to_canonical_string().unwrap_or_default()
+    }
+}
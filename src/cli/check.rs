@@ -0,0 +1,1049 @@
+//! `infra check`: static findings for single files, most with a mechanical
+//! fix that `infra fix` can apply.
+//!
+//! The checks work on the token stream, so they still run on files that do
+//! not parse; the parse error is reported as a finding of its own and
+//! tells `infra fix` where the file stops being safe to edit. Names are
+//! matched by spelling across the whole file, which errs on the side of
+//! treating a name as used.
+
+use crate::backend::closest_name;
+use crate::backend::literal_check::{self, PathSegment, Severity, ShapeIssueKind};
+use crate::cli::lifetimes::{self, LifetimeIssueKind};
+use crate::core::ast::{Program, Stmt};
+use crate::core::{InfraError, Result};
+use crate::frontend::{
+    is_module_name, unused_imports, Lexer, Parser, StringPart, Token, TokenType,
+};
+use crate::stdlib::{prelude, StandardLibrary};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Replace `range`, in bytes of the checked source, with `replacement`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+/// A machine-applicable fix: all of its edits are applied or none are
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub title: String,
+    /// Lower wins when the edits of two fixes overlap
+    pub priority: u8,
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub line: usize,
+    pub column: usize,
+    pub code: &'static str,
+    pub message: String,
+    pub fix: Option<Fix>,
+    /// Only errors make `infra check` fail
+    pub severity: Severity,
+}
+
+/// Findings for one source text, in source order
+#[derive(Debug)]
+pub struct CheckReport {
+    pub findings: Vec<Finding>,
+    /// First line the parser could not make sense of. Fixes are only safe
+    /// before it.
+    pub parse_error_line: Option<usize>,
+}
+
+pub const PRIORITY_UNUSED_IMPORT: u8 = 0;
+pub const PRIORITY_UNUSED_VARIABLE: u8 = 1;
+pub const PRIORITY_RENAME: u8 = 2;
+pub const PRIORITY_ASSIGNMENT: u8 = 3;
+
+pub fn check_file(path: &Path) -> Result<(String, CheckReport)> {
+    let source = fs::read_to_string(path).map_err(|err| InfraError::IoError {
+        message: format!("Could not read file: {}", err),
+        operation: Some("check".to_string()),
+        path: Some(path.display().to_string()),
+    })?;
+    let report = check_source(&source);
+    Ok((source, report))
+}
+
+pub fn check_source(source: &str) -> CheckReport {
+    let tokens = match Lexer::new(source).tokenize() {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            let (line, column) = error_position(&err);
+            return CheckReport {
+                findings: vec![Finding {
+                    line,
+                    column,
+                    code: "syntax",
+                    message: err.to_string(),
+                    fix: None,
+                    severity: Severity::Error,
+                }],
+                parse_error_line: Some(line),
+            };
+        }
+    };
+
+    let mut findings = Vec::new();
+    let mut parse_error_line = None;
+    let program = match Parser::new(tokens.clone()).parse() {
+        Ok(program) => Some(program),
+        Err(err) => {
+            let (line, column) = error_position(&err);
+            parse_error_line = Some(line);
+            findings.push(Finding {
+                line,
+                column,
+                code: "syntax",
+                message: err.to_string(),
+                fix: None,
+                severity: Severity::Error,
+            });
+            None
+        }
+    };
+
+    let checker = Checker::new(source, &tokens);
+    findings.extend(checker.unused_imports());
+    findings.extend(checker.unused_variables());
+    findings.extend(checker.misspelled_names());
+    findings.extend(checker.non_exhaustive_matches());
+    findings.extend(checker.literal_shapes(literal_check::is_strict()));
+    if let Some(program) = &program {
+        findings.extend(checker.variable_lifetimes(program));
+    }
+    findings.retain(|finding| !is_suppressed(source, finding));
+    findings.sort_by_key(|finding| (finding.line, finding.column));
+
+    CheckReport {
+        findings,
+        parse_error_line,
+    }
+}
+
+fn error_position(err: &InfraError) -> (usize, usize) {
+    match err {
+        InfraError::LexError { line, column, .. } | InfraError::ParseError { line, column, .. } => {
+            (*line, *column)
+        }
+        _ => (1, 1),
+    }
+}
+
+/// Whether a comment on the finding's line, or alone on the line above,
+/// says `infra-ignore: <code>` (several codes are separated by commas).
+/// Syntax errors cannot be ignored.
+fn is_suppressed(source: &str, finding: &Finding) -> bool {
+    if finding.code == "syntax" {
+        return false;
+    }
+    let lines: Vec<&str> = source.lines().collect();
+    let ignores = |line: &str| {
+        let Some(at) = line.find("infra-ignore:") else {
+            return false;
+        };
+        let before = line[..at].trim_end();
+        (before.ends_with("//") || before.ends_with('#'))
+            && line[at + "infra-ignore:".len()..]
+                .split(',')
+                .any(|code| code.trim() == finding.code)
+    };
+    let own = finding
+        .line
+        .checked_sub(1)
+        .and_then(|index| lines.get(index));
+    let above = finding
+        .line
+        .checked_sub(2)
+        .and_then(|index| lines.get(index))
+        .filter(|line| {
+            let trimmed = line.trim_start();
+            trimmed.starts_with("//") || trimmed.starts_with('#')
+        });
+    own.is_some_and(|line| ignores(line)) || above.is_some_and(|line| ignores(line))
+}
+
+/// What an identifier token is doing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Declaration,
+    Use,
+    /// Property names, object keys, type names and imported names before `as`
+    Other,
+}
+
+struct Checker<'a> {
+    source: &'a str,
+    tokens: &'a [Token<'a>],
+    /// Byte offset where each line starts; lines are 1-based
+    line_starts: Vec<usize>,
+    roles: Vec<Role>,
+    declared: HashSet<String>,
+    uses: HashMap<String, usize>,
+    /// Suggested replacements for uses of undeclared names, by token index
+    suggestions: Vec<(usize, String)>,
+    /// Declared enums and their members
+    enums: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(source: &'a str, tokens: &'a [Token<'a>]) -> Self {
+        let mut line_starts = vec![0, 0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+
+        let mut checker = Self {
+            source,
+            tokens,
+            line_starts,
+            roles: vec![Role::Other; tokens.len()],
+            declared: HashSet::new(),
+            uses: HashMap::new(),
+            suggestions: Vec::new(),
+            enums: HashMap::new(),
+        };
+        checker.classify();
+        checker.suggest_names();
+        checker
+    }
+
+    fn kind(&self, index: usize) -> Option<&'a TokenType<'a>> {
+        let tokens = self.tokens;
+        tokens.get(index).map(|token| &token.token_type)
+    }
+
+    fn name(&self, index: usize) -> &'a str {
+        match self.kind(index) {
+            Some(TokenType::Identifier(name)) => name,
+            _ => "",
+        }
+    }
+
+    /// Byte offset of a token's first character
+    fn offset(&self, index: usize) -> usize {
+        let token = &self.tokens[index];
+        let line_start = self
+            .line_starts
+            .get(token.line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source[line_start..]
+            .char_indices()
+            .nth(token.column.saturating_sub(1))
+            .map_or(self.source.len(), |(i, _)| line_start + i)
+    }
+
+    fn end_offset(&self, index: usize) -> usize {
+        self.offset(index) + self.tokens[index].lexeme.len()
+    }
+
+    /// The whole line of a token, including its newline, if nothing but
+    /// whitespace precedes the token on it
+    fn line_range(&self, index: usize) -> Option<Range<usize>> {
+        let line = self.tokens[index].line;
+        let start = self.line_starts[line];
+        if !self.source[start..self.offset(index)].trim().is_empty() {
+            return None;
+        }
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        Some(start..end)
+    }
+
+    fn finding(&self, index: usize, code: &'static str, message: String, fix: Fix) -> Finding {
+        Finding {
+            line: self.tokens[index].line,
+            column: self.tokens[index].column,
+            code,
+            message,
+            fix: Some(fix),
+            severity: Severity::Error,
+        }
+    }
+
+    /// Work out the role of every identifier
+    fn classify(&mut self) {
+        let mut in_params = false;
+        // Brackets open inside a parameter list, counting its own '('; a
+        // default value can hold calls and literals of its own
+        let mut param_nesting = 0;
+        // Brace depth, and the depth inside each class body being read
+        let mut depth = 0;
+        let mut class_bodies: Vec<usize> = Vec::new();
+        let mut class_header = false;
+        let mut index = 0;
+
+        while index < self.tokens.len() {
+            if matches!(self.kind(index), Some(TokenType::Import)) {
+                index = self.classify_import(index);
+                continue;
+            }
+            match self.statement_keyword(index) {
+                Some("enum") => {
+                    index = self.classify_enum(index);
+                    continue;
+                }
+                Some(_) => {
+                    index += 1;
+                    continue;
+                }
+                None => {}
+            }
+            let Some(TokenType::Identifier(name)) = self.kind(index) else {
+                match self.kind(index) {
+                    Some(TokenType::LeftParen) => {
+                        let header = index >= 1
+                            && matches!(
+                                self.kind(index - 1),
+                                Some(TokenType::Function | TokenType::Def | TokenType::Init)
+                            )
+                            || index >= 2
+                                && matches!(self.kind(index - 1), Some(TokenType::Identifier(_)))
+                                && matches!(
+                                    self.kind(index - 2),
+                                    Some(TokenType::Function | TokenType::Def)
+                                );
+                        if header || (in_params && param_nesting == 0) {
+                            in_params = true;
+                            param_nesting = 1;
+                        } else if in_params {
+                            param_nesting += 1;
+                        }
+                    }
+                    Some(TokenType::LeftBracket) if in_params => param_nesting += 1,
+                    Some(TokenType::RightParen | TokenType::RightBracket) if in_params => {
+                        param_nesting -= 1;
+                        in_params = param_nesting > 0;
+                    }
+                    Some(TokenType::Class) => class_header = true,
+                    Some(TokenType::LeftBrace) => {
+                        if in_params {
+                            param_nesting += 1;
+                        }
+                        depth += 1;
+                        if std::mem::take(&mut class_header) {
+                            class_bodies.push(depth);
+                        }
+                    }
+                    Some(TokenType::RightBrace) => {
+                        if in_params {
+                            param_nesting -= 1;
+                        }
+                        if class_bodies.last() == Some(&depth) {
+                            class_bodies.pop();
+                        }
+                        depth = depth.saturating_sub(1);
+                    }
+                    Some(TokenType::InterpolatedString(parts)) => self.interpolated_uses(parts),
+                    _ => {}
+                }
+                index += 1;
+                continue;
+            };
+
+            let previous = index.checked_sub(1).and_then(|i| self.kind(i));
+            let next = self.kind(index + 1);
+            // A field or method of the class whose body this is
+            let member = class_bodies.last() == Some(&depth)
+                && matches!(
+                    previous,
+                    Some(
+                        TokenType::Newline
+                            | TokenType::LeftBrace
+                            | TokenType::Function
+                            | TokenType::Def
+                    )
+                );
+            let role = match previous {
+                _ if member => {
+                    in_params = matches!(next, Some(TokenType::LeftParen));
+                    Role::Other
+                }
+                Some(
+                    TokenType::Dot | TokenType::QuestionDot | TokenType::Arrow | TokenType::Pipe,
+                ) => Role::Other,
+                Some(
+                    TokenType::Let
+                    | TokenType::Function
+                    | TokenType::Def
+                    | TokenType::Class
+                    | TokenType::For
+                    | TokenType::Catch,
+                ) => Role::Declaration,
+                Some(TokenType::LeftParen | TokenType::Comma | TokenType::Ellipsis)
+                    if in_params && param_nesting == 1 =>
+                {
+                    Role::Declaration
+                }
+                // The type in `let x: bytes` or `f(x: bytes)`
+                Some(TokenType::Colon)
+                    if index >= 2 && self.roles[index - 2] == Role::Declaration =>
+                {
+                    Role::Other
+                }
+                // An object key
+                Some(TokenType::LeftBrace | TokenType::Comma | TokenType::Newline)
+                    if matches!(next, Some(TokenType::Colon)) =>
+                {
+                    Role::Other
+                }
+                _ => Role::Use,
+            };
+
+            self.roles[index] = role;
+            match role {
+                Role::Declaration => {
+                    self.declared.insert(name.to_string());
+                }
+                Role::Use => *self.uses.entry(name.to_string()).or_default() += 1,
+                Role::Other => {}
+            }
+            index += 1;
+        }
+    }
+
+    /// Names read inside `${...}` are uses. They are not offered as typos:
+    /// a fix can only edit whole tokens of the file.
+    fn interpolated_uses(&mut self, parts: &[StringPart]) {
+        for part in parts {
+            let StringPart::Code { source, .. } = part else {
+                continue;
+            };
+            let Ok(tokens) = Lexer::new(source).tokenize() else {
+                continue;
+            };
+            for (index, token) in tokens.iter().enumerate() {
+                match &token.token_type {
+                    TokenType::Identifier(name)
+                        if index == 0
+                            || !matches!(
+                                tokens[index - 1].token_type,
+                                TokenType::Dot | TokenType::QuestionDot
+                            ) =>
+                    {
+                        *self.uses.entry(name.to_string()).or_default() += 1;
+                    }
+                    TokenType::InterpolatedString(inner) => self.interpolated_uses(inner),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Classify the identifiers of the import statement at `start` and
+    /// return the index of the token after it
+    fn classify_import(&mut self, start: usize) -> usize {
+        let mut index = start + 1;
+
+        while let Some(kind) = self.kind(index) {
+            match kind {
+                TokenType::Newline | TokenType::Eof | TokenType::From => break,
+                TokenType::Identifier(name) => {
+                    if matches!(self.kind(index + 1), Some(TokenType::As)) {
+                        // The exported name; the alias after `as` is bound
+                    } else {
+                        self.roles[index] = Role::Declaration;
+                        self.declared.insert(name.to_string());
+                    }
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+
+        while !matches!(
+            self.kind(index),
+            Some(TokenType::Newline | TokenType::Eof) | None
+        ) {
+            index += 1;
+        }
+        index
+    }
+
+    /// Whether anything refers to `name`, counting misspellings of it so
+    /// that fixing a typo and removing its target never happen together
+    /// `enum` or `match` when the token at `index` starts such a statement.
+    /// Elsewhere both are ordinary names, as the parser treats them.
+    fn statement_keyword(&self, index: usize) -> Option<&'a str> {
+        let word = self.name(index);
+        let at_start = index == 0
+            || matches!(
+                self.kind(index - 1),
+                Some(TokenType::Newline | TokenType::LeftBrace)
+            );
+        let operand_follows = matches!(
+            self.kind(index + 1),
+            Some(
+                TokenType::Identifier(_)
+                    | TokenType::Number(_)
+                    | TokenType::String(_)
+                    | TokenType::InterpolatedString(_)
+                    | TokenType::True
+                    | TokenType::False
+                    | TokenType::Null
+                    | TokenType::This
+            )
+        );
+        (matches!(word, "enum" | "match") && at_start && operand_follows).then_some(word)
+    }
+
+    /// Record the enum declared at `start` and return the index of the
+    /// token after the declaration
+    fn classify_enum(&mut self, start: usize) -> usize {
+        let name_index = start + 1;
+        let name = self.name(name_index).to_string();
+        self.roles[name_index] = Role::Declaration;
+        self.declared.insert(name.clone());
+
+        let mut members = Vec::new();
+        let mut index = name_index + 1;
+        while let Some(kind) = self.kind(index) {
+            match kind {
+                TokenType::Identifier(member) => members.push(member.to_string()),
+                TokenType::Newline if !matches!(self.kind(index - 1), Some(TokenType::Comma)) => {
+                    break
+                }
+                TokenType::Eof => break,
+                _ => {}
+            }
+            index += 1;
+        }
+        self.enums.insert(name, members);
+        index
+    }
+
+    fn is_used(&self, name: &str) -> bool {
+        self.uses.get(name).is_some_and(|&count| count > 0)
+            || self
+                .suggestions
+                .iter()
+                .any(|(_, suggestion)| suggestion == name)
+    }
+
+    /// Imported names nothing refers to. The fix drops them from the braces,
+    /// or removes the statement when none are left.
+    fn unused_imports(&self) -> Vec<Finding> {
+        unused_imports::find_with(self.source, self.tokens, |name| self.is_used(name))
+            .into_iter()
+            .map(|unused| {
+                let message = unused.message();
+                Finding {
+                    line: unused.line,
+                    column: unused.column,
+                    code: "unused-import",
+                    message: message.clone(),
+                    fix: Some(Fix {
+                        title: format!("remove {}", message),
+                        priority: PRIORITY_UNUSED_IMPORT,
+                        edits: vec![Edit {
+                            range: unused.range,
+                            replacement: unused.replacement,
+                        }],
+                    }),
+                    severity: Severity::Error,
+                }
+            })
+            .collect()
+    }
+
+    /// `let` bindings that are never read, when dropping the whole statement
+    /// cannot change behaviour: the value is a literal or a plain name
+    fn unused_variables(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        for (let_index, token) in self.tokens.iter().enumerate() {
+            if !matches!(token.token_type, TokenType::Let) {
+                continue;
+            }
+            if let_index > 0 && matches!(self.kind(let_index - 1), Some(TokenType::Export)) {
+                continue;
+            }
+            let name_index = let_index + 1;
+            let name = self.name(name_index);
+            if name.is_empty() || self.is_used(name) {
+                continue;
+            }
+
+            let Some(equals) = (name_index + 1..self.tokens.len()).find(|&i| {
+                matches!(
+                    self.kind(i),
+                    Some(TokenType::Equal | TokenType::Newline | TokenType::Eof)
+                )
+            }) else {
+                continue;
+            };
+            let side_effect_free = matches!(self.kind(equals), Some(TokenType::Equal))
+                && matches!(
+                    self.kind(equals + 1),
+                    Some(
+                        TokenType::Number(_)
+                            | TokenType::BigInt(_)
+                            | TokenType::String(_)
+                            | TokenType::Identifier(_)
+                            | TokenType::True
+                            | TokenType::False
+                            | TokenType::Null
+                    )
+                )
+                && matches!(
+                    self.kind(equals + 2),
+                    Some(TokenType::Newline | TokenType::Eof)
+                );
+            if !side_effect_free {
+                continue;
+            }
+            let Some(range) = self.line_range(let_index) else {
+                continue;
+            };
+
+            findings.push(self.finding(
+                name_index,
+                "unused-variable",
+                format!("variable '{}' is never used", name),
+                Fix {
+                    title: format!("remove unused variable '{}'", name),
+                    priority: PRIORITY_UNUSED_VARIABLE,
+                    edits: vec![Edit {
+                        range,
+                        replacement: String::new(),
+                    }],
+                },
+            ));
+        }
+        findings
+    }
+
+    /// Names used but declared nowhere in the file that are a likely typo
+    /// of one that is
+    fn suggest_names(&mut self) {
+        let mut declared: Vec<String> = self.declared.iter().cloned().collect();
+        declared.sort();
+        let stdlib = StandardLibrary::new();
+
+        for (index, role) in self.roles.iter().enumerate() {
+            let name = self.name(index);
+            if *role != Role::Use
+                || self.declared.contains(name)
+                || is_module_name(name)
+                || matches!(prelude::lookup(name), Ok(Some(_)))
+                || stdlib.get_global(name).is_some()
+            {
+                continue;
+            }
+            if let Some(suggestion) = closest_name(name, &declared) {
+                self.suggestions.push((index, suggestion));
+            }
+        }
+    }
+
+    fn misspelled_names(&self) -> Vec<Finding> {
+        self.suggestions
+            .iter()
+            .map(|(index, suggestion)| {
+                let name = self.name(*index);
+                self.finding(
+                    *index,
+                    "unknown-name",
+                    format!("'{}' is not defined; did you mean '{}'?", name, suggestion),
+                    Fix {
+                        title: format!("rename '{}' to '{}'", name, suggestion),
+                        priority: PRIORITY_RENAME,
+                        edits: vec![Edit {
+                            range: self.offset(*index)..self.end_offset(*index),
+                            replacement: suggestion.clone(),
+                        }],
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// `match` statements whose arms are members of one enum but miss some
+    /// of them and have no default arm
+    fn non_exhaustive_matches(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        for start in 0..self.tokens.len() {
+            if self.statement_keyword(start) != Some("match") {
+                continue;
+            }
+            let Some(open) = (start..self.tokens.len())
+                .find(|&i| matches!(self.kind(i), Some(TokenType::LeftBrace)))
+            else {
+                continue;
+            };
+
+            let mut enum_name = None;
+            let mut covered = HashSet::new();
+            let mut has_default = false;
+            let mut mixed = false;
+            let mut depth = 0;
+            for index in open..self.tokens.len() {
+                match self.kind(index) {
+                    Some(TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket) => {
+                        depth += 1
+                    }
+                    Some(
+                        TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket,
+                    ) => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    break;
+                }
+                // Arms start a line directly inside the braces
+                let arm_start = depth == 1
+                    && matches!(
+                        self.kind(index - 1),
+                        Some(TokenType::Newline | TokenType::LeftBrace)
+                    );
+                if !arm_start {
+                    continue;
+                }
+                if matches!(self.kind(index), Some(TokenType::Else))
+                    || (self.name(index) == "default"
+                        && matches!(self.kind(index + 1), Some(TokenType::Colon)))
+                {
+                    has_default = true;
+                    continue;
+                }
+                // An arm lists its patterns after an optional `case`. Other
+                // lines are arm bodies written below their pattern.
+                let mut at = index;
+                if self.name(at) == "case" && !matches!(self.kind(at + 1), Some(TokenType::Colon)) {
+                    at += 1;
+                }
+                while let (
+                    Some(TokenType::Identifier(name)),
+                    Some(TokenType::Dot),
+                    Some(TokenType::Identifier(member)),
+                    Some(separator @ (TokenType::Comma | TokenType::Colon)),
+                ) = (
+                    self.kind(at),
+                    self.kind(at + 1),
+                    self.kind(at + 2),
+                    self.kind(at + 3),
+                ) {
+                    if !self.enums.contains_key(name.as_ref()) {
+                        break;
+                    }
+                    mixed |= enum_name.is_some_and(|enum_name| enum_name != name);
+                    enum_name = Some(name);
+                    covered.insert(member.as_ref());
+                    if matches!(separator, TokenType::Colon) {
+                        break;
+                    }
+                    at += 4;
+                }
+            }
+
+            let Some(enum_name) = enum_name else {
+                continue;
+            };
+            if has_default || mixed {
+                continue;
+            }
+            let missing: Vec<&str> = self.enums[enum_name.as_ref()]
+                .iter()
+                .map(String::as_str)
+                .filter(|member| !covered.contains(member))
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+
+            findings.push(Finding {
+                line: self.tokens[start].line,
+                column: self.tokens[start].column,
+                code: "non-exhaustive-match",
+                message: format!(
+                    "match over {} has no arm for {} and no 'else' arm",
+                    enum_name,
+                    missing
+                        .iter()
+                        .map(|member| format!("{}.{}", enum_name, member))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                fix: None,
+                severity: Severity::Error,
+            });
+        }
+        findings
+    }
+
+    /// Object and array literals that do not match the annotation of the
+    /// `let` they initialize. Each declaration is parsed on its own, so one
+    /// is checked even when a later part of the file does not parse.
+    fn literal_shapes(&self, strict: bool) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        for let_index in 0..self.tokens.len() {
+            if !matches!(self.kind(let_index), Some(TokenType::Let))
+                || !matches!(self.kind(let_index + 2), Some(TokenType::Colon))
+            {
+                continue;
+            }
+            let Some(equals) = self.statement_equals(let_index + 3) else {
+                continue;
+            };
+            let value = equals + 1;
+            if !matches!(
+                self.kind(value),
+                Some(TokenType::LeftBrace | TokenType::LeftBracket)
+            ) {
+                continue;
+            }
+            let Some(close) = self.closing(value) else {
+                continue;
+            };
+
+            let mut tokens = self.tokens[let_index..=close].to_vec();
+            let last = &self.tokens[close];
+            tokens.push(Token::eof(last.line, last.column + 1));
+            let Ok(program) = Parser::new(tokens).parse() else {
+                continue;
+            };
+            let Some(Stmt::Let {
+                name,
+                type_annotation: Some(annotation),
+                value: literal,
+            }) = program.statements.first()
+            else {
+                continue;
+            };
+
+            for issue in literal_check::check_literal(name, annotation, literal) {
+                let fields = &issue.path[1..];
+                let (target, fix) = match &issue.kind {
+                    // The literal, or the field, that should have it
+                    ShapeIssueKind::MissingField { .. } => {
+                        let parent = &fields[..fields.len() - 1];
+                        let target = if parent.is_empty() {
+                            Some(let_index + 1)
+                        } else {
+                            self.locate(value, parent)
+                        };
+                        (target, None)
+                    }
+                    ShapeIssueKind::UnknownField { suggestion } => {
+                        let target = self.locate(value, fields);
+                        let fix = target.zip(suggestion.as_ref()).map(|(key, suggestion)| {
+                            let replacement =
+                                if matches!(self.kind(key), Some(TokenType::String(_))) {
+                                    format!("\"{}\"", suggestion)
+                                } else {
+                                    suggestion.clone()
+                                };
+                            Fix {
+                                title: format!(
+                                    "rename field '{}' to '{}'",
+                                    self.tokens[key].lexeme.trim_matches('"'),
+                                    suggestion
+                                ),
+                                priority: PRIORITY_RENAME,
+                                edits: vec![Edit {
+                                    range: self.offset(key)..self.end_offset(key),
+                                    replacement,
+                                }],
+                            }
+                        });
+                        (target, fix)
+                    }
+                    ShapeIssueKind::Mismatch { .. } => (self.locate(value, fields), None),
+                };
+                let index = target.unwrap_or(let_index + 1);
+                findings.push(Finding {
+                    line: self.tokens[index].line,
+                    column: self.tokens[index].column,
+                    code: match issue.kind {
+                        ShapeIssueKind::MissingField { .. } => "missing-field",
+                        ShapeIssueKind::UnknownField { .. } => "unknown-field",
+                        ShapeIssueKind::Mismatch { .. } => "field-type",
+                    },
+                    message: issue.message(),
+                    fix,
+                    severity: issue.severity(strict),
+                });
+            }
+        }
+        findings
+    }
+
+    /// `let`s that start over on every pass through a loop or hide a
+    /// variable still read after their block, and assignments to the
+    /// variable of the surrounding `for` loop
+    fn variable_lifetimes(&self, program: &Program) -> Vec<Finding> {
+        lifetimes::find_issues(program, self.tokens)
+            .into_iter()
+            .map(|issue| {
+                let name = &issue.name;
+                let (code, message, fix) = match issue.kind {
+                    LifetimeIssueKind::LoopReset => (
+                        "loop-reset",
+                        format!(
+                            "'{}' is declared inside the loop, so it starts again from its \
+                             first value on every iteration; declare it before the loop to \
+                             add up across iterations",
+                            name
+                        ),
+                        None,
+                    ),
+                    LifetimeIssueKind::Shadowing => (
+                        "shadowing",
+                        format!(
+                            "'let' declares a new '{}' that only lives in this block; the \
+                             outer '{}' read after it keeps its value. Use '{} = ...' to \
+                             change it",
+                            name, name, name
+                        ),
+                        self.let_to_assignment(issue.token),
+                    ),
+                    LifetimeIssueKind::LoopVariable => (
+                        "loop-variable",
+                        format!(
+                            "assigning to loop variable '{}' has no effect on the next \
+                             iteration, which sets it again",
+                            name
+                        ),
+                        None,
+                    ),
+                };
+                Finding {
+                    line: self.tokens[issue.token].line,
+                    column: self.tokens[issue.token].column,
+                    code,
+                    message,
+                    fix,
+                    severity: Severity::Warning,
+                }
+            })
+            .collect()
+    }
+
+    /// Turn `let name: type = value` at the name token into `name = value`
+    fn let_to_assignment(&self, name_index: usize) -> Option<Fix> {
+        let let_index = name_index.checked_sub(1)?;
+        let equals = self.statement_equals(name_index + 1)?;
+        let name = self.name(name_index);
+        Some(Fix {
+            title: format!("assign to the outer '{}' instead of declaring one", name),
+            priority: PRIORITY_ASSIGNMENT,
+            edits: vec![Edit {
+                range: self.offset(let_index)..self.offset(equals),
+                replacement: format!("{} ", name),
+            }],
+        })
+    }
+
+    /// The `=` of the statement going on at `start`, outside any brackets
+    fn statement_equals(&self, start: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for index in start..self.tokens.len() {
+            match self.kind(index)? {
+                TokenType::LeftBrace | TokenType::LeftBracket | TokenType::LeftParen => depth += 1,
+                TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen => {
+                    depth = depth.checked_sub(1)?
+                }
+                TokenType::Equal if depth == 0 => return Some(index),
+                TokenType::Newline | TokenType::Eof if depth == 0 => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The bracket closing the one at `open`
+    fn closing(&self, open: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for index in open..self.tokens.len() {
+            match self.kind(index)? {
+                TokenType::LeftBrace | TokenType::LeftBracket | TokenType::LeftParen => depth += 1,
+                TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The token of the key or element `path` leads to, starting from the
+    /// literal that opens at `start`
+    fn locate(&self, start: usize, path: &[PathSegment]) -> Option<usize> {
+        let mut at = start;
+        let mut found = None;
+        for segment in path {
+            let close = self.closing(at)?;
+            let items = self.literal_items(at, close);
+            let item = match segment {
+                PathSegment::Index(index) => *items.get(*index)?,
+                PathSegment::Field(name) => items.into_iter().find(|&item| {
+                    matches!(self.kind(item + 1), Some(TokenType::Colon))
+                        && matches!(
+                            self.kind(item),
+                            Some(TokenType::Identifier(key) | TokenType::String(key))
+                                if key.as_ref() == name.as_str()
+                        )
+                })?,
+            };
+            found = Some(item);
+            at = match segment {
+                PathSegment::Index(_) => item,
+                // The value after `key:`
+                PathSegment::Field(_) => self.skip_newlines(item + 2),
+            };
+        }
+        found
+    }
+
+    /// First token of each item directly inside the brackets `open..close`
+    fn literal_items(&self, open: usize, close: usize) -> Vec<usize> {
+        let mut items = Vec::new();
+        let mut depth = 0usize;
+        let mut expecting = true;
+        for index in open + 1..close {
+            match self.kind(index) {
+                Some(TokenType::Newline) => continue,
+                Some(TokenType::Comma) if depth == 0 => {
+                    expecting = true;
+                    continue;
+                }
+                _ => {}
+            }
+            if expecting && depth == 0 {
+                items.push(index);
+                expecting = false;
+            }
+            match self.kind(index) {
+                Some(TokenType::LeftBrace | TokenType::LeftBracket | TokenType::LeftParen) => {
+                    depth += 1
+                }
+                Some(TokenType::RightBrace | TokenType::RightBracket | TokenType::RightParen) => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        items
+    }
+
+    fn skip_newlines(&self, mut index: usize) -> usize {
+        while matches!(self.kind(index), Some(TokenType::Newline)) {
+            index += 1;
+        }
+        index
+    }
+}
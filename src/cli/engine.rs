@@ -0,0 +1,159 @@
+//! Running Infra inside a host program.
+//!
+//! An [`Engine`] is one interpreter plus the native functions its host gave
+//! it; hosts get it from the library built with the `stdlib` feature, as
+//! `infra::Engine`. Building the standard library is the expensive part of starting one,
+//! so a host running many engines, on one thread or several, builds a
+//! library once and shares it:
+//!
+//! ```ignore
+//! let shared = StandardLibrary::new();
+//! let mut engine = Engine::builder().with_shared_stdlib(&shared).build();
+//! engine.register_native("host", "user_id", user_id);
+//! engine.evaluate("host.user_id()")?;
+//! ```
+//!
+//! Sharing costs a reference count. Functions an engine registers, and its
+//! dry-run setting, belong to that engine alone; the shared library and
+//! every other engine built from it never see them.
+//!
+//! An engine can also be given its own clock and entropy source, such as a
+//! [`ManualClock`] a test moves forward by hand:
+//!
+//! ```ignore
+//! let clock = ManualClock::new(1_767_225_600_000);
+//! let mut engine = Engine::builder().with_clock(clock.clone()).build();
+//! engine.execute("schedule.every(1000, tick)")?;
+//! engine.advance_clock(5000)?; // tick runs five times
+//! ```
+
+use crate::cli::Runner;
+use crate::core::{Result, Value};
+use crate::stdlib::sources::{self, ClockSource, EntropySource, Sources};
+use crate::stdlib::{NativeFunction, StandardLibrary};
+use std::rc::Rc;
+
+#[allow(dead_code)]
+pub struct Engine {
+    runner: Runner,
+    sources: Sources,
+}
+
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct EngineBuilder {
+    stdlib: Option<StandardLibrary>,
+    natives: Vec<(String, String, NativeFunction)>,
+    dry_run: Option<bool>,
+    sources: Sources,
+}
+
+#[allow(dead_code)]
+impl EngineBuilder {
+    /// Start from `stdlib` instead of a fresh library. Functions already
+    /// registered on it are kept; the engine's own go on top.
+    pub fn with_shared_stdlib(mut self, stdlib: &StandardLibrary) -> Self {
+        self.stdlib = Some(stdlib.clone());
+        self
+    }
+
+    /// Make `module.name` call `function` in this engine
+    pub fn with_native(mut self, module: &str, name: &str, function: NativeFunction) -> Self {
+        self.natives
+            .push((module.to_string(), name.to_string(), function));
+        self
+    }
+
+    /// Log file writes and process runs instead of performing them, in this
+    /// engine only
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = Some(dry_run);
+        self
+    }
+
+    /// Read the time from `clock` instead of the computer's clock
+    pub fn with_clock(mut self, clock: impl ClockSource + 'static) -> Self {
+        self.sources.clock = Rc::new(clock);
+        self
+    }
+
+    /// Take random bytes, such as those in temporary file names, from
+    /// `entropy`
+    pub fn with_entropy(mut self, entropy: impl EntropySource + 'static) -> Self {
+        self.sources.entropy = Rc::new(entropy);
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        let mut stdlib = self.stdlib.unwrap_or_default();
+        for (module, name, function) in self.natives {
+            stdlib.register(&module, &name, function);
+        }
+        if self.dry_run.is_some() {
+            stdlib.set_dry_run(self.dry_run);
+        }
+        Engine {
+            runner: Runner::with_stdlib(stdlib),
+            sources: self.sources,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// Make `module.name` call `function` in this engine, replacing a
+    /// standard library function of the same name
+    pub fn register_native(&mut self, module: &str, name: &str, function: NativeFunction) {
+        self.stdlib_mut().register(module, name, function);
+    }
+
+    /// Remove a function added with `register_native`; a standard library
+    /// function it replaced comes back. Returns whether there was one.
+    pub fn unregister_native(&mut self, module: &str, name: &str) -> bool {
+        self.stdlib_mut().unregister(module, name)
+    }
+
+    pub fn stdlib(&self) -> &StandardLibrary {
+        self.runner.get_interpreter().stdlib()
+    }
+
+    fn stdlib_mut(&mut self) -> &mut StandardLibrary {
+        self.runner.get_interpreter_mut().stdlib_mut()
+    }
+
+    pub fn execute(&mut self, code: &str) -> Result<()> {
+        self.with_sources(|runner| runner.execute_code(code))
+    }
+
+    /// Run code and return the value of its final expression
+    pub fn evaluate(&mut self, code: &str) -> Result<Value> {
+        self.with_sources(|runner| runner.evaluate_code(code))
+    }
+
+    /// Move the engine's clock forward by `ms`, running scheduled jobs and
+    /// timed calls as they come due. A clock that cannot be moved by hand,
+    /// like the default one, is waited on instead.
+    pub fn advance_clock(&mut self, ms: u64) -> Result<()> {
+        self.with_sources(|runner| runner.run_schedules(Some(sources::now_ms() + ms)))
+    }
+
+    /// Run `f` with this engine's clock and entropy source in effect
+    fn with_sources<T>(&mut self, f: impl FnOnce(&mut Runner) -> T) -> T {
+        let previous = sources::install(self.sources.clone());
+        let result = f(&mut self.runner);
+        sources::install(previous);
+        result
+    }
+
+    pub fn runner(&self) -> &Runner {
+        &self.runner
+    }
+
+    pub fn runner_mut(&mut self) -> &mut Runner {
+        &mut self.runner
+    }
+}
@@ -0,0 +1,277 @@
+//! `infra fix`: apply the machine-applicable fixes from `infra check`.
+//!
+//! Fixes are applied in passes until the checker has nothing left to fix,
+//! so removing an unused variable that was the last use of an import also
+//! removes the import, and a second run changes nothing.
+
+use crate::cli::check::{check_source, Fix};
+use crate::core::{InfraError, Result};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Stop after this many passes even if fixes keep appearing
+const MAX_PASSES: usize = 10;
+
+/// A fix as reported to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixNote {
+    pub line: usize,
+    pub title: String,
+    /// Why the fix was not applied
+    pub reason: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct FixOutcome {
+    pub original: String,
+    pub fixed: String,
+    pub applied: Vec<FixNote>,
+    pub skipped: Vec<FixNote>,
+}
+
+impl FixOutcome {
+    pub fn changed(&self) -> bool {
+        self.original != self.fixed
+    }
+}
+
+pub fn fix_file(path: &Path) -> Result<FixOutcome> {
+    let source = fs::read_to_string(path).map_err(|err| InfraError::IoError {
+        message: format!("Could not read file: {}", err),
+        operation: Some("fix".to_string()),
+        path: Some(path.display().to_string()),
+    })?;
+    Ok(fix_source(&source))
+}
+
+pub fn fix_source(source: &str) -> FixOutcome {
+    let mut text = source.to_string();
+    let mut applied = Vec::new();
+    let mut skipped: Vec<FixNote> = Vec::new();
+
+    for _ in 0..MAX_PASSES {
+        let report = check_source(&text);
+        let mut fixes: Vec<Fix> = Vec::new();
+
+        for fix in report
+            .findings
+            .into_iter()
+            .filter_map(|finding| finding.fix)
+        {
+            let line = line_of(&text, fix_start(&fix));
+            match report.parse_error_line {
+                Some(error_line) if line >= error_line => {
+                    let note = FixNote {
+                        line,
+                        title: fix.title,
+                        reason: Some(format!("the file does not parse from line {}", error_line)),
+                    };
+                    // Lines shift between passes; the title names the fix
+                    if !skipped.iter().any(|other| other.title == note.title) {
+                        skipped.push(note);
+                    }
+                }
+                _ => fixes.push(fix),
+            }
+        }
+        if fixes.is_empty() {
+            break;
+        }
+
+        // Lower priority wins; ties go to the earlier fix
+        fixes.sort_by_key(|fix| (fix.priority, fix_start(fix)));
+        let mut accepted: Vec<Fix> = Vec::new();
+        for fix in fixes {
+            let line = line_of(&text, fix_start(&fix));
+            match accepted.iter().find(|other| overlaps(&fix, other)) {
+                Some(other) => skipped.push(FixNote {
+                    line,
+                    title: fix.title,
+                    reason: Some(format!("overlaps: {}", other.title)),
+                }),
+                None => {
+                    applied.push(FixNote {
+                        line,
+                        title: fix.title.clone(),
+                        reason: None,
+                    });
+                    accepted.push(fix);
+                }
+            }
+        }
+
+        // Apply back to front so earlier offsets stay valid
+        let mut edits: Vec<_> = accepted.into_iter().flat_map(|fix| fix.edits).collect();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+        for edit in edits {
+            text.replace_range(edit.range, &edit.replacement);
+        }
+    }
+
+    FixOutcome {
+        original: source.to_string(),
+        fixed: text,
+        applied,
+        skipped,
+    }
+}
+
+fn fix_start(fix: &Fix) -> usize {
+    fix.edits
+        .iter()
+        .map(|edit| edit.range.start)
+        .min()
+        .unwrap_or(0)
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+fn overlaps(a: &Fix, b: &Fix) -> bool {
+    let touches = |x: &Range<usize>, y: &Range<usize>| {
+        // Two insertions at the same point would land in an arbitrary order
+        x.start < y.end && y.start < x.end || x.start == y.start
+    };
+    a.edits
+        .iter()
+        .any(|x| b.edits.iter().any(|y| touches(&x.range, &y.range)))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Lines with their terminators, the last one terminated even if the text is not
+fn diff_lines(text: &str) -> Vec<String> {
+    text.split_inclusive('\n')
+        .map(|line| {
+            if line.ends_with('\n') {
+                line.to_string()
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect()
+}
+
+/// Edit script turning `old` into `new`, from a longest common subsequence
+fn diff_ops(old: &[String], new: &[String]) -> Vec<DiffOp> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the LCS length of old_mid[i..] and new_mid[j..]
+    let mut lcs = vec![vec![0u32; new_mid.len() + 1]; old_mid.len() + 1];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![DiffOp::Equal; prefix];
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            ops.push(DiffOp::Equal);
+            i += 1;
+            j += 1;
+        } else if j == new_mid.len() || i < old_mid.len() && lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(DiffOp::Delete);
+            i += 1;
+        } else {
+            ops.push(DiffOp::Insert);
+            j += 1;
+        }
+    }
+    ops.extend(std::iter::repeat_n(DiffOp::Equal, suffix));
+    ops
+}
+
+/// A unified diff with three lines of context, empty when nothing changed
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 3;
+
+    let old_lines = diff_lines(old);
+    let new_lines = diff_lines(new);
+    let ops = diff_ops(&old_lines, &new_lines);
+    let changes: Vec<usize> = (0..ops.len())
+        .filter(|&k| ops[k] != DiffOp::Equal)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context would touch into hunks of op indices
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for &k in &changes {
+        let start = k.saturating_sub(CONTEXT);
+        let end = (k + 1 + CONTEXT).min(ops.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.end => hunk.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    // Line numbers (0-based) in old and new at each op index
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for op in &ops {
+        positions.push((old_line, new_line));
+        match op {
+            DiffOp::Equal => {
+                old_line += 1;
+                new_line += 1;
+            }
+            DiffOp::Delete => old_line += 1,
+            DiffOp::Insert => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    for hunk in hunks {
+        let (old_start, new_start) = positions[hunk.start];
+        let (old_end, new_end) = positions[hunk.end];
+        let old_count = old_end - old_start;
+        let new_count = new_end - new_start;
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            if old_count == 0 {
+                old_start
+            } else {
+                old_start + 1
+            },
+            old_count,
+            if new_count == 0 {
+                new_start
+            } else {
+                new_start + 1
+            },
+            new_count
+        ));
+        for k in hunk {
+            let (o, n) = positions[k];
+            match ops[k] {
+                DiffOp::Equal => out.push_str(&format!(" {}", old_lines[o])),
+                DiffOp::Delete => out.push_str(&format!("-{}", old_lines[o])),
+                DiffOp::Insert => out.push_str(&format!("+{}", new_lines[n])),
+            }
+        }
+    }
+    out
+}
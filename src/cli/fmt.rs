@@ -0,0 +1,18 @@
+//! `infra fmt`: print a file in canonical formatting.
+
+use crate::core::{InfraError, Result};
+use crate::frontend::formatter;
+use crate::frontend::printer::FormattingOptions;
+use std::fs;
+use std::path::Path;
+
+/// The file's source and its formatted text
+pub fn format_file(path: &Path, options: &FormattingOptions) -> Result<(String, String)> {
+    let source = fs::read_to_string(path).map_err(|err| InfraError::IoError {
+        message: format!("Could not read file: {}", err),
+        operation: Some("fmt".to_string()),
+        path: Some(path.display().to_string()),
+    })?;
+    let formatted = formatter::format_source(&source, options)?;
+    Ok((source, formatted))
+}
@@ -0,0 +1,543 @@
+//! Scope analysis behind the variable-lifetime findings of `infra check`:
+//! a `let` that starts over on every pass through a loop, a `let` in a
+//! block that hides a variable the code after the block still reads, and
+//! an assignment to the variable of the `for` loop it is in.
+//!
+//! Only `{ }` blocks open a scope, and a function body is the outermost
+//! scope looked at: a variable of the caller is not an outer binding, even
+//! though the function can see it when called.
+//!
+//! The analysis walks the parsed program. Statements carry no positions, so
+//! the name tokens are found by walking the tokens alongside: every `let`,
+//! `for` and assignment is met in the same order in both.
+
+use crate::core::ast::*;
+use crate::frontend::{Token, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifetimeIssueKind {
+    /// `let name = <constant>` in a loop body, after which the variable is
+    /// only ever accumulated into
+    LoopReset,
+    /// A `let` in a block hiding an outer variable that is read after the
+    /// block
+    Shadowing,
+    /// An assignment to the variable of the `for` loop it is in
+    LoopVariable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifetimeIssue {
+    pub kind: LifetimeIssueKind,
+    pub name: String,
+    /// Token of the declared or assigned name
+    pub token: usize,
+}
+
+pub fn find_issues(program: &Program, tokens: &[Token]) -> Vec<LifetimeIssue> {
+    let mut walker = Walker {
+        tokens,
+        cursor: 0,
+        frames: Vec::new(),
+        loop_vars: Vec::new(),
+        issues: Vec::new(),
+    };
+    walker.walk_list(&program.statements, true, Vec::new());
+    walker.issues
+}
+
+/// The statements of one scope being walked
+struct Frame<'p> {
+    stmts: &'p [Stmt],
+    /// The statement being walked
+    index: usize,
+    /// Names declared in the scope so far
+    bindings: Vec<String>,
+    /// A function body or the top level; outer frames are not looked at
+    function: bool,
+    /// Loops entered in this scope whose bodies are being walked
+    loops: Vec<&'p Stmt>,
+}
+
+struct Walker<'p, 't> {
+    tokens: &'t [Token<'t>],
+    /// Tokens before this one belong to statements already walked
+    cursor: usize,
+    frames: Vec<Frame<'p>>,
+    /// Variables of the `for` loops being walked, with the depth of the
+    /// frame their body starts
+    loop_vars: Vec<(String, usize)>,
+    issues: Vec<LifetimeIssue>,
+}
+
+impl<'p> Walker<'p, '_> {
+    fn walk_list(&mut self, stmts: &'p [Stmt], function: bool, bindings: Vec<String>) {
+        self.frames.push(Frame {
+            stmts,
+            index: 0,
+            bindings,
+            function,
+            loops: Vec::new(),
+        });
+        for (index, stmt) in stmts.iter().enumerate() {
+            self.frame().index = index;
+            self.statement(stmt);
+        }
+        self.frames.pop();
+    }
+
+    fn frame(&mut self) -> &mut Frame<'p> {
+        self.frames.last_mut().expect("a frame is always open")
+    }
+
+    fn bind(&mut self, name: &str) {
+        self.frame().bindings.push(name.to_string());
+    }
+
+    fn statement(&mut self, stmt: &'p Stmt) {
+        match stmt {
+            Stmt::Let { name, value, .. } => {
+                if let Some(token) = self.next_let() {
+                    self.declaration(name, value, token);
+                }
+                self.bind(name);
+            }
+            Stmt::LetTuple { pattern, .. } => {
+                self.next_let();
+                for name in pattern.names() {
+                    self.bind(name);
+                }
+            }
+            Stmt::Assignment {
+                target: AssignmentTarget::Identifier(name),
+                ..
+            } => {
+                if let Some(token) = self.next_assignment(name) {
+                    self.assignment(name, token);
+                }
+            }
+            Stmt::If {
+                then_stmt,
+                else_stmt,
+                ..
+            } => {
+                self.statement(then_stmt);
+                if let Some(else_stmt) = else_stmt {
+                    self.statement(else_stmt);
+                }
+            }
+            Stmt::While { body, .. } => {
+                self.frame().loops.push(stmt);
+                self.statement(body);
+                self.frame().loops.pop();
+            }
+            Stmt::For { var, body, .. } => self.loop_statement(stmt, &[var.as_str()], body),
+            Stmt::ForIn { pattern, body, .. } => self.loop_statement(stmt, &pattern.names(), body),
+            Stmt::Block(stmts) => self.walk_list(stmts, false, Vec::new()),
+            Stmt::Function {
+                name, params, body, ..
+            }
+            | Stmt::AsyncFunction {
+                name, params, body, ..
+            }
+            | Stmt::Export {
+                item:
+                    ExportItem::Function {
+                        name, params, body, ..
+                    },
+            } => {
+                self.bind(name);
+                self.function_body(body, params);
+            }
+            Stmt::Export {
+                item: ExportItem::Variable { name, .. },
+            } => {
+                self.next_let();
+                self.bind(name);
+            }
+            Stmt::Class { name, methods, .. } => {
+                self.bind(name);
+                for method in methods {
+                    self.function_body(&method.body, &method.params);
+                }
+            }
+            Stmt::Try {
+                try_block,
+                catch_var,
+                catch_block,
+            } => {
+                self.statement(try_block);
+                self.bind(catch_var);
+                self.statement(catch_block);
+            }
+            Stmt::Match { arms, default, .. } => {
+                for arm in arms {
+                    self.statement(&arm.body);
+                }
+                if let Some(default) = default {
+                    self.statement(default);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn function_body(&mut self, body: &'p Stmt, params: &[String]) {
+        // Loops around a function definition do not run its body
+        let loop_vars = std::mem::take(&mut self.loop_vars);
+        match body {
+            Stmt::Block(stmts) => self.walk_list(stmts, true, params.to_vec()),
+            other => self.walk_list(std::slice::from_ref(other), true, params.to_vec()),
+        }
+        self.loop_vars = loop_vars;
+    }
+
+    /// Index of the innermost function frame
+    fn function_frame(&self) -> usize {
+        self.frames
+            .iter()
+            .rposition(|frame| frame.function)
+            .unwrap_or(0)
+    }
+
+    fn declaration(&mut self, name: &str, value: &Expr, token: usize) {
+        let depth = self.frames.len() - 1;
+        let frame = &self.frames[depth];
+        if frame.function {
+            return;
+        }
+        let rest = &frame.stmts[frame.index + 1..];
+
+        let in_loop = self.frames[self.function_frame()..]
+            .iter()
+            .any(|frame| !frame.loops.is_empty());
+        if in_loop && is_constant(value) {
+            let uses = Uses::of_list(rest, name);
+            if uses.accumulations > 0 && uses.reads == 0 && uses.assignments == 0 {
+                self.issue(LifetimeIssueKind::LoopReset, name, token);
+                return;
+            }
+        }
+
+        let Some(outer) = (self.function_frame()..depth)
+            .rev()
+            .find(|&j| self.frames[j].bindings.iter().any(|bound| bound == name))
+        else {
+            return;
+        };
+        let read_later = self.frames[outer..depth].iter().any(|frame| {
+            Uses::of_list(&frame.stmts[frame.index + 1..], name).read()
+                || frame
+                    .loops
+                    .iter()
+                    .any(|&looped| Uses::of_list(std::slice::from_ref(looped), name).read())
+        });
+        if read_later {
+            self.issue(LifetimeIssueKind::Shadowing, name, token);
+        }
+    }
+
+    /// A `for` loop whose body sees `vars` as its loop variables
+    fn loop_statement(&mut self, stmt: &'p Stmt, vars: &[&str], body: &'p Stmt) {
+        self.next_token(|kind, _| matches!(kind, TokenType::For));
+        self.frame().loops.push(stmt);
+        let depth = self.frames.len();
+        self.loop_vars
+            .extend(vars.iter().map(|var| (var.to_string(), depth)));
+        self.statement(body);
+        self.loop_vars.truncate(self.loop_vars.len() - vars.len());
+        self.frame().loops.pop();
+    }
+
+    fn assignment(&mut self, name: &str, token: usize) {
+        let Some(&(_, depth)) = self.loop_vars.iter().rev().find(|(var, _)| var == name) else {
+            return;
+        };
+        // A `let` inside the loop declared a variable of its own
+        let shadowed = self.frames[depth.min(self.frames.len())..]
+            .iter()
+            .any(|frame| frame.bindings.iter().any(|bound| bound == name));
+        if !shadowed {
+            self.issue(LifetimeIssueKind::LoopVariable, name, token);
+        }
+    }
+
+    fn issue(&mut self, kind: LifetimeIssueKind, name: &str, token: usize) {
+        self.issues.push(LifetimeIssue {
+            kind,
+            name: name.to_string(),
+            token,
+        });
+    }
+
+    /// The first token from the cursor on that `matches`, moving the cursor
+    /// past it
+    fn next_token(&mut self, matches: impl Fn(&TokenType, usize) -> bool) -> Option<usize> {
+        let found = (self.cursor..self.tokens.len())
+            .find(|&index| matches(&self.tokens[index].token_type, index))?;
+        self.cursor = found + 1;
+        Some(found)
+    }
+
+    /// The name token of the next `let`
+    fn next_let(&mut self) -> Option<usize> {
+        let found = self.next_token(|kind, _| matches!(kind, TokenType::Let))?;
+        self.cursor = found + 2;
+        Some(found + 1)
+    }
+
+    /// The next `name =` that is not a declaration or a property
+    fn next_assignment(&mut self, name: &str) -> Option<usize> {
+        let tokens = self.tokens;
+        self.next_token(|kind, index| {
+            matches!(kind, TokenType::Identifier(identifier) if identifier.as_ref() == name)
+                && matches!(
+                    tokens.get(index + 1).map(|token| &token.token_type),
+                    Some(TokenType::Equal)
+                )
+                && !matches!(
+                    index
+                        .checked_sub(1)
+                        .map(|previous| &tokens[previous].token_type),
+                    Some(TokenType::Let | TokenType::Dot)
+                )
+        })
+    }
+}
+
+/// A literal, or an empty array or object
+fn is_constant(value: &Expr) -> bool {
+    match value {
+        Expr::Literal(_) => true,
+        Expr::Array(items) => items.is_empty(),
+        Expr::Object(fields) => fields.is_empty(),
+        Expr::Unary {
+            operator: UnaryOp::Minus,
+            operand,
+            ..
+        } => matches!(operand.as_ref(), Expr::Literal(_)),
+        _ => false,
+    }
+}
+
+/// How statements use one variable of the scope they run in. A `let` of
+/// the same name ends the count for the rest of its block, and function
+/// bodies are not counted.
+#[derive(Debug, Default)]
+struct Uses {
+    /// `name = name <op> ...`
+    accumulations: usize,
+    /// Other assignments
+    assignments: usize,
+    /// Reads outside accumulations
+    reads: usize,
+}
+
+impl Uses {
+    fn of_list(stmts: &[Stmt], name: &str) -> Self {
+        let mut uses = Self::default();
+        uses.list(stmts, name);
+        uses
+    }
+
+    fn read(&self) -> bool {
+        self.reads > 0 || self.accumulations > 0
+    }
+
+    /// Count `stmts`; false once a `let` of `name` hides the variable
+    fn list(&mut self, stmts: &[Stmt], name: &str) -> bool {
+        for stmt in stmts {
+            if !self.statement(stmt, name) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Count `stmt`; false when it declares `name` for what follows it
+    fn statement(&mut self, stmt: &Stmt, name: &str) -> bool {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr) | Stmt::Return(Some(expr)) => {
+                self.expr(expr, name)
+            }
+            Stmt::Let {
+                name: declared,
+                value,
+                ..
+            } => {
+                self.expr(value, name);
+                return declared != name;
+            }
+            Stmt::LetTuple { pattern, value, .. } => {
+                self.expr(value, name);
+                return !pattern.names().contains(&name);
+            }
+            Stmt::Assignment { target, value, .. } => match target {
+                AssignmentTarget::Identifier(target) if target == name => match value {
+                    Expr::Binary { left, right, .. } if matches!(left.as_ref(), Expr::Identifier { name: read, .. } if read == name) =>
+                    {
+                        self.accumulations += 1;
+                        self.expr(right, name);
+                    }
+                    _ => {
+                        self.assignments += 1;
+                        self.expr(value, name);
+                    }
+                },
+                AssignmentTarget::Identifier(_) => self.expr(value, name),
+                AssignmentTarget::Property { object, .. } => {
+                    self.expr(object, name);
+                    self.expr(value, name);
+                }
+                AssignmentTarget::Index { object, index } => {
+                    self.expr(object, name);
+                    self.expr(index, name);
+                    self.expr(value, name);
+                }
+            },
+            Stmt::If {
+                condition,
+                then_stmt,
+                else_stmt,
+            } => {
+                self.expr(condition, name);
+                self.statement(then_stmt, name);
+                if let Some(else_stmt) = else_stmt {
+                    self.statement(else_stmt, name);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.expr(condition, name);
+                self.statement(body, name);
+            }
+            Stmt::For {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                self.expr(start, name);
+                self.expr(end, name);
+                if var != name {
+                    self.statement(body, name);
+                }
+            }
+            Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expr(iterable, name);
+                if !pattern.names().contains(&name) {
+                    self.statement(body, name);
+                }
+            }
+            Stmt::Block(stmts) => {
+                self.list(stmts, name);
+            }
+            Stmt::Try {
+                try_block,
+                catch_var,
+                catch_block,
+            } => {
+                self.statement(try_block, name);
+                if catch_var != name {
+                    self.statement(catch_block, name);
+                }
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+            } => {
+                self.expr(subject, name);
+                for arm in arms {
+                    for pattern in &arm.patterns {
+                        self.expr(pattern, name);
+                    }
+                    self.statement(&arm.body, name);
+                }
+                if let Some(default) = default {
+                    self.statement(default, name);
+                }
+            }
+            Stmt::Function { name: declared, .. }
+            | Stmt::AsyncFunction { name: declared, .. }
+            | Stmt::Class { name: declared, .. } => return declared != name,
+            _ => {}
+        }
+        true
+    }
+
+    fn expr(&mut self, expr: &Expr, name: &str) {
+        match expr {
+            Expr::Identifier { name: read, .. } => {
+                if read == name {
+                    self.reads += 1;
+                }
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expr(left, name);
+                self.expr(right, name);
+            }
+            Expr::Unary { operand, .. } => self.expr(operand, name),
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.expr(condition, name);
+                self.expr(then_expr, name);
+                self.expr(else_expr, name);
+            }
+            Expr::Call { callee, args, .. }
+            | Expr::New {
+                class: callee,
+                args,
+            } => {
+                self.expr(callee, name);
+                for arg in args {
+                    self.expr(arg, name);
+                }
+            }
+            Expr::Array(items) | Expr::Tuple(items) => {
+                for item in items {
+                    self.expr(item, name);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.expr(object, name);
+                self.expr(index, name);
+            }
+            Expr::Object(fields) => {
+                for (_, value) in fields {
+                    self.expr(value, name);
+                }
+            }
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expr(expr, name);
+                    }
+                }
+            }
+            Expr::Property { object, .. } => self.expr(object, name),
+            Expr::Await { expression } => self.expr(expression, name),
+            Expr::Lambda {
+                params,
+                optional_params,
+                body,
+                ..
+            } => {
+                if !params.iter().any(|param| param == name) {
+                    for default in &optional_params.defaults {
+                        self.expr(default, name);
+                    }
+                    self.expr(body, name);
+                }
+            }
+            Expr::Literal(_) | Expr::ModuleAccess { .. } | Expr::This | Expr::Super { .. } => {}
+        }
+    }
+}
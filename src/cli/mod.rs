@@ -1,7 +1,23 @@
+pub mod analyze;
+
+pub mod check;
+
+pub mod engine;
+
+pub mod fix;
+
+pub mod fmt;
+
+pub mod lifetimes;
+
 pub mod runner;
 
 pub mod repl;
 
+pub mod test_runner;
+
+pub mod viewer;
+
 pub use runner::*;
 
 pub use repl::*;
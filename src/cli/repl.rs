@@ -1,77 +1,522 @@
+use crate::cli::viewer::{self, DEFAULT_VIEW_THRESHOLD};
 use crate::cli::Runner;
+use crate::core::{InfraError, Result, Value};
+use crate::frontend::{printer, Lexer, Parser, TokenType};
+use crate::stdlib::io as infra_io;
+use crate::utils::platform;
 
-use std::io::{self, Write};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+const PROMPT: &str = "infra> ";
+const CONTINUATION_PROMPT: &str = "   ... ";
+
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+/// Commands that also work without the leading ':'
+const BARE_COMMANDS: [&str; 5] = ["exit", "quit", "help", "clear", "env"];
+
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// One read from the REPL's input
+enum Line {
+    Text(String),
+    /// Ctrl+C at the prompt
+    Interrupted,
+    End,
+}
+
+/// Where the REPL reads its lines from
+trait LineSource {
+    /// Show `prompt` and read a line, without its line ending
+    fn read_line(&mut self, prompt: &str) -> Line;
+
+    /// Remember an entry so it can be recalled later in the session
+    fn add_history(&mut self, _entry: &str) {}
+}
+
+/// Something plain input can be read from a line at a time
+trait ReadLine {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize>;
+}
+
+impl<R: BufRead> ReadLine for R {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        BufRead::read_line(self, line)
+    }
+}
+
+/// Standard input, locked only while a line is read so that code run from
+/// the REPL can read it with io.read_line too
+struct SharedStdin;
+
+impl ReadLine for SharedStdin {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        io::stdin().read_line(line)
+    }
+}
+
+/// Plain line-based input, such as a pipe
+struct PlainInput<R>(R);
+
+impl<R: ReadLine> LineSource for PlainInput<R> {
+    fn read_line(&mut self, prompt: &str) -> Line {
+        print!("{}", prompt);
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        match self.0.read_line(&mut line) {
+            Ok(0) => Line::End,
+            Ok(_) => {
+                if let Some(rest) = line.strip_prefix(PASTE_START) {
+                    line = read_bracketed_paste(&mut self.0, rest);
+                }
+                Line::Text(line.trim_end_matches(['\n', '\r']).to_string())
+            }
+            Err(err) => {
+                eprintln!("Error reading input: {}", err);
+                Line::End
+            }
+        }
+    }
+}
+
+/// A terminal, with line editing and history
+struct EditorInput(DefaultEditor);
+
+impl LineSource for EditorInput {
+    fn read_line(&mut self, prompt: &str) -> Line {
+        match self.0.readline(prompt) {
+            Ok(line) => Line::Text(line),
+            Err(ReadlineError::Interrupted) => Line::Interrupted,
+            Err(ReadlineError::Eof) => Line::End,
+            Err(err) => {
+                eprintln!("Error reading input: {}", err);
+                Line::End
+            }
+        }
+    }
+
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.0.add_history_entry(entry);
+    }
+}
+
+/// Collect a bracketed paste; `first` is the rest of the line after the start marker
+fn read_bracketed_paste<R: ReadLine>(reader: &mut R, first: &str) -> String {
+    let mut code = String::new();
+    let mut line = first.to_string();
+
+    loop {
+        if let Some(end) = line.find(PASTE_END) {
+            code.push_str(&line[..end]);
+            code.push_str(&line[end + PASTE_END.len()..]);
+            return code;
+        }
+        code.push_str(&line);
+
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return code,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Whether `code` stops partway through a statement: inside a string or
+/// block comment, or where the parser still wanted more tokens
+fn is_incomplete(code: &str) -> bool {
+    let tokens = match Lexer::new(code).tokenize() {
+        Ok(tokens) => tokens,
+        Err(InfraError::LexError { message, .. }) => return message.starts_with("Unterminated"),
+        Err(_) => return false,
+    };
+    let last = tokens
+        .iter()
+        .rev()
+        .find(|token| !matches!(token.token_type, TokenType::Newline | TokenType::Eof))
+        .map(|token| (token.line, token.column));
+
+    match Parser::new(tokens).parse() {
+        // An error past the last token means the input ran out, not that it is wrong
+        Err(InfraError::ParseError { line, column, .. }) => {
+            last.is_some_and(|last| (line, column) > last)
+        }
+        _ => false,
+    }
+}
 
 pub struct Repl {
     runner: Runner,
+    /// Editor command for `:edit`; falls back to $VISUAL/$EDITOR when unset
+    editor: Option<String>,
+    /// The last input that failed to lex or parse, offered again by `:edit`
+    last_failed: Option<String>,
+    /// Results whose text is longer are summarized; None always prints them
+    view_threshold: Option<usize>,
 }
 
 impl Repl {
     pub fn new() -> Self {
         Self {
             runner: Runner::new(),
+            editor: None,
+            last_failed: None,
+            view_threshold: Some(DEFAULT_VIEW_THRESHOLD),
         }
     }
 
+    /// Use `editor` for `:edit` instead of the environment's editor
+    #[allow(dead_code)]
+    pub fn set_editor(&mut self, editor: &str) {
+        self.editor = Some(editor.to_string());
+    }
+
+    /// Summarize results whose text is longer than `threshold` bytes instead
+    /// of printing them; None prints every result in full
+    pub fn set_view_threshold(&mut self, threshold: Option<usize>) {
+        self.view_threshold = threshold;
+    }
+
     pub fn run(&mut self) {
         println!("Infra Programming Language v0.1.0");
         println!("Interactive REPL - Type 'exit', 'quit', or Ctrl+C to quit");
-        println!("Type 'help' for commands or 'clear' to reset environment");
+        println!("Type ':help' for commands or ':clear' to reset environment");
         println!();
 
-        loop {
-            print!("infra> ");
-            io::stdout().flush().unwrap();
+        // Line editing needs a terminal; anything else is read line by line
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            if let Ok(editor) = DefaultEditor::new() {
+                self.run_lines(&mut EditorInput(editor));
+                return;
+            }
+        }
+        self.run_lines(&mut PlainInput(SharedStdin));
+    }
+
+    /// Run the REPL loop over any line-based input
+    #[allow(dead_code)]
+    pub fn run_from<R: BufRead>(&mut self, reader: R) {
+        self.run_lines(&mut PlainInput(reader));
+    }
 
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-                Ok(_) => {
-                    let input = input.trim();
+    /// Read statements until the input ends or the user quits. Input that
+    /// stops partway through a statement is continued on the next line.
+    fn run_lines(&mut self, input: &mut dyn LineSource) {
+        let mut pending = String::new();
 
-                    if input.is_empty() {
-                        continue;
+        loop {
+            let prompt = if pending.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            let line = match input.read_line(prompt) {
+                Line::Text(line) => line,
+                Line::Interrupted if !pending.is_empty() => {
+                    pending.clear();
+                    continue;
+                }
+                Line::Interrupted => break,
+                Line::End => {
+                    // Report whatever was left unfinished
+                    if !pending.is_empty() {
+                        self.execute(&pending);
                     }
+                    break;
+                }
+            };
+
+            let command = line.trim();
+            if command.starts_with(':') || (pending.is_empty() && BARE_COMMANDS.contains(&command))
+            {
+                input.add_history(command);
+                if !pending.is_empty() {
+                    self.last_failed = Some(std::mem::take(&mut pending));
+                }
+                if !self.command(command, input) {
+                    break;
+                }
+                continue;
+            }
 
-                    match input {
-                        "exit" | "quit" => {
-                            println!("Goodbye!");
-                            break;
-                        }
-                        "help" => {
-                            self.show_help();
-                            continue;
-                        }
-                        "clear" => {
-                            self.runner.reset_interpreter();
-                            println!("Environment cleared.");
-                            continue;
-                        }
-                        "env" => {
-                            self.show_environment();
-                            continue;
-                        }
-                        _ => {
-                            if let Err(err) = self.runner.execute_code(input) {
-                                eprintln!("{}", err);
-                            }
-                        }
+            if pending.is_empty() && command.is_empty() {
+                continue;
+            }
+            pending.push_str(&line);
+            pending.push('\n');
+            if is_incomplete(&pending) {
+                continue;
+            }
+
+            let code = std::mem::take(&mut pending);
+            input.add_history(code.trim_end());
+            self.execute(&code);
+        }
+    }
+
+    /// Run a meta command; false means the user asked to quit
+    fn command(&mut self, command: &str, input: &mut dyn LineSource) -> bool {
+        match command {
+            "exit" | "quit" | ":exit" | ":quit" => {
+                println!("Goodbye!");
+                return false;
+            }
+            "help" | ":help" => self.show_help(),
+            "clear" | ":clear" => {
+                self.runner.reset_interpreter();
+                self.last_failed = None;
+                println!("Environment cleared.");
+            }
+            "env" | ":env" => self.show_environment(),
+            ":paste" => {
+                println!("Paste mode - enter a line containing only '.' to run");
+                let code = Self::read_until_dot(input);
+                self.execute_paste(&code);
+            }
+            ":edit" => self.edit(None),
+            ":view" => self.view(None),
+            ":load" => eprintln!("Usage: :load <file.infra>"),
+            _ => match command.split_once(' ') {
+                Some((":edit", name)) => self.edit(Some(name.trim())),
+                Some((":view", code)) => self.view(Some(code.trim())),
+                Some((":load", path)) => self.load(path.trim()),
+                _ => eprintln!("Unknown command '{}'; type ':help' for commands", command),
+            },
+        }
+        true
+    }
+
+    pub fn get_runner(&self) -> &Runner {
+        &self.runner
+    }
+
+    /// Collect lines for `:paste` until a line containing only '.'; Ctrl+C
+    /// drops the paste
+    fn read_until_dot(input: &mut dyn LineSource) -> String {
+        let mut code = String::new();
+
+        loop {
+            match input.read_line("") {
+                Line::Text(line) if line.trim_end() == "." => return code,
+                Line::Text(line) => {
+                    code.push_str(&line);
+                    code.push('\n');
+                }
+                Line::Interrupted => return String::new(),
+                Line::End => return code,
+            }
+        }
+    }
+
+    /// Run a pasted block as one unit, so errors report lines within the paste
+    fn execute_paste(&mut self, code: &str) {
+        if code.trim().is_empty() {
+            return;
+        }
+
+        self.execute(code);
+    }
+
+    /// Run code in the session, remembering it if it does not parse. A
+    /// final expression's value is echoed and bound to `_`.
+    fn execute(&mut self, code: &str) {
+        match self.runner.evaluate_code(code) {
+            Ok(value) => {
+                self.last_failed = None;
+                if value != Value::Null {
+                    println!("{}", self.echo(&value));
+                    self.set_last_result(value);
+                }
+            }
+            Err(err) => {
+                self.last_failed = match err {
+                    InfraError::LexError { .. } | InfraError::ParseError { .. } => {
+                        Some(code.to_string())
                     }
+                    _ => None,
+                };
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    /// How a result is echoed: in full, or summarized when its text is longer
+    /// than the view threshold
+    pub fn echo(&self, value: &Value) -> String {
+        self.view_threshold
+            .and_then(|threshold| viewer::summary(value, threshold))
+            .unwrap_or_else(|| value.to_string())
+    }
+
+    fn set_last_result(&mut self, value: Value) {
+        self.runner.get_interpreter_mut().define_global("_", value);
+    }
+
+    /// Open the value of `code`, or the last result, in the pager. The
+    /// viewed value is left bound to `_`.
+    fn view(&mut self, code: Option<&str>) {
+        let value = match code {
+            Some(code) => match self.runner.evaluate_code(code) {
+                Ok(value) => value,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
                 }
+            },
+            None => match self.runner.get_interpreter().get_environment().get("_") {
+                Ok(value) => value,
+                Err(_) => {
+                    eprintln!("Nothing to view yet; enter an expression or use ':view <expr>'");
+                    return;
+                }
+            },
+        };
+        self.set_last_result(value.clone());
+
+        // Without a terminal to page on, print it all
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            println!("{}", value);
+            return;
+        }
+        let path = code.unwrap_or("_");
+        let shown = viewer::Terminal::open()
+            .and_then(|mut terminal| viewer::view(&value, path, &mut terminal));
+        if let Err(err) = shown {
+            eprintln!("Cannot show the viewer: {}", err);
+        }
+    }
+
+    /// Run a file in the session, keeping what it defines
+    fn load(&mut self, path: &str) {
+        match self.runner.run_file(path) {
+            Ok(()) => println!("Loaded {}", path),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Open a snippet in the user's editor and run what they save.
+    ///
+    /// With a function name the file starts out as that function's source;
+    /// without one it holds the last input that failed to parse, if any.
+    fn edit(&mut self, name: Option<&str>) {
+        let initial = match name {
+            Some(name) => match self.function_source(name) {
+                Ok(source) => source,
                 Err(err) => {
-                    eprintln!("Error reading input: {}", err);
-                    break;
+                    eprintln!("{}", err);
+                    return;
                 }
+            },
+            None => self.last_failed.clone().unwrap_or_default(),
+        };
+
+        let path = match infra_io::create_temp_file("infra-edit-", ".infra", false) {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
             }
+        };
+
+        let edited = self.run_editor(&path, &initial);
+        infra_io::release_temp_path(&path);
+
+        match edited {
+            Ok(Some(code)) => self.execute_paste(&code),
+            Ok(None) => {}
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Let the user edit `initial` in `path`; `None` means the editor gave up
+    fn run_editor(&self, path: &str, initial: &str) -> Result<Option<String>> {
+        let io_error = |message: String| InfraError::IoError {
+            message,
+            operation: Some("edit".to_string()),
+            path: Some(path.to_string()),
+        };
+
+        std::fs::write(path, initial)
+            .map_err(|err| io_error(format!("Failed to write snippet: {}", err)))?;
+
+        let editor = self.editor_command();
+        let mut parts = editor.split_whitespace();
+        let program = parts.next().unwrap_or(DEFAULT_EDITOR);
+
+        let status = platform::command(program)
+            .args(parts)
+            .arg(path)
+            .status()
+            .map_err(|err| io_error(format!("Failed to start editor '{}': {}", editor, err)))?;
+
+        if !status.success() {
+            eprintln!("Editor exited with {}; nothing was run", status);
+            return Ok(None);
+        }
+
+        std::fs::read_to_string(path)
+            .map(Some)
+            .map_err(|err| io_error(format!("Failed to read snippet: {}", err)))
+    }
+
+    fn editor_command(&self) -> String {
+        self.editor
+            .clone()
+            .or_else(|| std::env::var("VISUAL").ok())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .filter(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+    }
+
+    /// Reconstruct the source of a function defined in the session
+    fn function_source(&self, name: &str) -> Result<String> {
+        match self.runner.get_interpreter().get_environment().get(name)? {
+            Value::Function {
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                ..
+            } => Ok(printer::format_function(
+                name,
+                &params,
+                &param_types,
+                &optional_params,
+                &return_type,
+                &body,
+            )),
+            other => Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: other.type_name().to_string(),
+                context: Some(":edit command".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Use ':edit' without a name to write a new snippet".to_string()),
+            }),
         }
     }
 
     fn show_help(&self) {
         println!("Available commands:");
-        println!("  help    - Show this help message");
-        println!("  clear   - Reset the environment (clear all variables)");
-        println!("  env     - Show current environment variables");
-        println!("  exit    - Exit the REPL");
-        println!("  quit    - Exit the REPL");
+        println!("  :help   - Show this help message");
+        println!("  :clear  - Reset the environment (clear all variables)");
+        println!("  :env    - List the variables defined in the session with their types");
+        println!("  :load <file>  - Run a file in the session, keeping its definitions");
+        println!("  :paste  - Enter paste mode (finish with a line containing only '.')");
+        println!("  :edit [name] - Write a snippet (or redefine function 'name') in $EDITOR");
+        println!("  :view [expr] - Page through a value (default: the last result, '_')");
+        println!("  exit    - Exit the REPL (also 'quit' or ':quit')");
+        println!();
+        println!("Unfinished statements continue on the next line at the '...' prompt;");
+        println!("Ctrl+C drops them. 'help', 'clear' and 'env' work without the ':' too.");
         println!();
         println!("Language syntax examples:");
         println!("  let x = 42");
@@ -83,15 +528,24 @@ impl Repl {
     }
 
     fn show_environment(&self) {
-        let env = self.runner.get_interpreter().get_environment();
-        let size = env.size();
-
-        if size == 0 {
+        let bindings = self.runner.get_interpreter().get_environment().bindings();
+        if bindings.is_empty() {
             println!("Environment is empty (no variables defined)");
-        } else {
-            println!("Environment contains {} variable(s)", size);
-            // Note: We'd need to expose the variables HashMap to show them
-            // This is a design decision - do we want to expose internal state?
+            return;
+        }
+
+        let width = bindings
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, value, annotation) in &bindings {
+            // A declared type wins over what the value happens to be now
+            let type_name = match annotation {
+                Some(ty) => printer::format_type(ty),
+                None => value.type_name().to_string(),
+            };
+            println!("  {:width$}  {}", name, type_name, width = width);
         }
     }
 }
@@ -1,25 +1,37 @@
+use crate::backend::bytecode::Compiler;
+use crate::backend::optimizer;
+use crate::backend::vm::VM;
 use crate::backend::Interpreter;
-use crate::core::{InfraError, Result};
+use crate::core::{InfraError, Result, Stmt, Value};
 use crate::frontend::{Lexer, Parser};
+use crate::stdlib::output::{self, OutputLimits};
+use crate::stdlib::{cancel, StandardLibrary};
 use std::fs;
 
 pub struct Runner {
     interpreter: Interpreter,
+    source: Option<String>,
 }
 
 impl Runner {
     pub fn new() -> Self {
         Self {
             interpreter: Interpreter::new(),
+            source: None,
+        }
+    }
+
+    /// A runner whose interpreter calls native functions from `stdlib`
+    #[allow(dead_code)]
+    pub fn with_stdlib(stdlib: StandardLibrary) -> Self {
+        Self {
+            interpreter: Interpreter::with_stdlib(stdlib),
+            source: None,
         }
     }
 
     pub fn run_file(&mut self, filename: &str) -> Result<()> {
-        let contents = fs::read_to_string(filename).map_err(|err| InfraError::IoError {
-            message: format!("Error reading file '{}': {}", filename, err),
-            operation: Some("read file".to_string()),
-            path: Some(filename.to_string()),
-        })?;
+        let contents = read_source(filename)?;
 
         // Set the current file path for module resolution
         let file_path = std::path::Path::new(filename)
@@ -27,10 +39,58 @@ impl Runner {
             .unwrap_or_else(|_| std::path::PathBuf::from(filename));
         self.interpreter.set_current_file(file_path);
 
-        self.execute_code(&contents)
+        let result = self
+            .execute_code(&contents)
+            .and_then(|()| self.run_schedules(None))
+            .map_err(|error| error.with_source(&contents));
+        self.source = Some(contents);
+        result
+    }
+
+    /// Compile a file to bytecode and run it on the VM instead of the
+    /// interpreter; with `dump`, print the bytecode's disassembly instead of
+    /// running it. With `optimize` the bytecode goes through the optimizer
+    /// first.
+    pub fn run_file_vm(&mut self, filename: &str, dump: bool, optimize: bool) -> Result<()> {
+        let contents = read_source(filename)?;
+
+        let result = Lexer::new(&contents)
+            .tokenize()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .and_then(|program| Compiler::new().compile(&program))
+            .map(|chunk| match optimize {
+                true => optimizer::optimize(chunk),
+                false => chunk,
+            })
+            .and_then(|chunk| {
+                if dump {
+                    print!("{}", chunk.disassemble(filename));
+                    Ok(())
+                } else {
+                    let _running = cancel::enter_running();
+                    VM::new().interpret(chunk)
+                }
+            })
+            .map_err(|error| error.with_source(&contents));
+        self.source = Some(contents);
+        result
+    }
+
+    /// The text of the file `run_file` last ran, for showing where an error
+    /// without a copy of its own happened
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Keep running scheduled jobs until they are all cancelled, or until
+    /// the scheduler's clock reaches `until`
+    pub fn run_schedules(&mut self, until: Option<u64>) -> Result<()> {
+        let _running = cancel::enter_running();
+        self.interpreter.run_schedules(until)
     }
 
     pub fn execute_code(&mut self, code: &str) -> Result<()> {
+        let _running = cancel::enter_running();
         let mut lexer = Lexer::new(code);
         let tokens = lexer.tokenize()?;
 
@@ -41,6 +101,36 @@ impl Runner {
         Ok(())
     }
 
+    /// Execute code and return the value of its final expression statement
+    /// (null if the code does not end with an expression)
+    pub fn evaluate_code(&mut self, code: &str) -> Result<Value> {
+        let _running = cancel::enter_running();
+        let mut lexer = Lexer::new(code);
+        let tokens = lexer.tokenize()?;
+
+        let mut parser = Parser::new(tokens);
+        let mut ast = parser.parse()?;
+
+        let last = match ast.statements.last() {
+            Some(Stmt::Expression(_)) => ast.statements.pop(),
+            _ => None,
+        };
+
+        self.interpreter.execute(&ast)?;
+
+        match last {
+            Some(Stmt::Expression(expr)) => self.interpreter.evaluate(&expr),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// Limit what `print` writes when stdout is not a terminal, starting a
+    /// fresh output budget (see `stdlib::output`)
+    #[allow(dead_code)]
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        output::set_limits(limits);
+    }
+
     pub fn reset_interpreter(&mut self) {
         self.interpreter.reset();
     }
@@ -59,3 +149,11 @@ impl Default for Runner {
         Self::new()
     }
 }
+
+fn read_source(filename: &str) -> Result<String> {
+    fs::read_to_string(filename).map_err(|err| InfraError::IoError {
+        message: format!("Error reading file '{}': {}", filename, err),
+        operation: Some("read file".to_string()),
+        path: Some(filename.to_string()),
+    })
+}
@@ -0,0 +1,412 @@
+//! `infra --test`: run every `*_test.infra` file under a directory.
+//!
+//! A test passes when its file runs without an uncaught error. Every test
+//! gets a fresh interpreter, so faults one test injects never reach another.
+//!
+//! Each run records, per test, the module files it loaded and their content
+//! hashes in `.infra-cache/test-deps.json`. With `--changed-since`, tests whose file and
+//! dependencies are unchanged are skipped. Whenever the cache cannot vouch for
+//! a test (no entry, last run failed, an import that could not be followed, a
+//! hash mismatch) the test runs.
+//!
+//! Each test's `assert.snapshot` calls are checked against
+//! `__snapshots__/<test>.snap` beside it (see `stdlib::snapshot`); the
+//! snapshot file counts as one of the test's dependencies.
+
+use crate::cli::Runner;
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use crate::stdlib::snapshot;
+use crate::utils::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const CACHE_DIR: &str = ".infra-cache";
+const CACHE_FILE: &str = "test-deps.json";
+const CACHE_VERSION: f64 = 1.0;
+const TEST_SUFFIX: &str = "_test.infra";
+
+/// What a selective run compares against
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangedSince {
+    /// Files whose hashes differ from the ones recorded by the last run
+    LastRun,
+    /// Files that differ from a git revision, plus untracked files
+    GitRef(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TestOptions {
+    pub root: PathBuf,
+    pub changed_since: Option<ChangedSince>,
+    /// Run everything even when `changed_since` is set
+    pub all: bool,
+    /// Rewrite snapshots that differ and remove obsolete ones
+    pub update_snapshots: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    /// Path relative to the test root
+    pub name: String,
+    pub outcome: TestOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+    /// Problems that made the run fall back to running more tests
+    pub warnings: Vec<String>,
+    /// Snapshots created, or rewritten under `update_snapshots`
+    pub snapshots_written: usize,
+    /// `file: key` of snapshots no test asserts any more; already removed
+    /// under `update_snapshots`
+    pub obsolete_snapshots: Vec<String>,
+}
+
+impl TestReport {
+    fn count(&self, outcome: fn(&TestOutcome) -> bool) -> usize {
+        self.results.iter().filter(|r| outcome(&r.outcome)).count()
+    }
+
+    pub fn passed(&self) -> usize {
+        self.count(|o| *o == TestOutcome::Passed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, TestOutcome::Failed(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|o| *o == TestOutcome::Skipped)
+    }
+}
+
+/// The recorded dependencies of one test
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    passed: bool,
+    complete: bool,
+    files: BTreeMap<String, String>, // Path relative to the root -> content hash
+}
+
+pub fn run_tests(options: &TestOptions) -> Result<TestReport> {
+    let root = options
+        .root
+        .canonicalize()
+        .map_err(|err| InfraError::IoError {
+            message: format!("Cannot open test directory: {}", err),
+            operation: Some("test".to_string()),
+            path: Some(options.root.display().to_string()),
+        })?;
+
+    let mut report = TestReport::default();
+    let cache_path = root.join(CACHE_DIR).join(CACHE_FILE);
+    let mut cache = match load_cache(&cache_path) {
+        Ok(cache) => cache,
+        Err(message) => {
+            report.warnings.push(message);
+            HashMap::new()
+        }
+    };
+
+    let selective = options.changed_since.clone().filter(|_| !options.all);
+    let git_changes = match &selective {
+        Some(ChangedSince::GitRef(reference)) => match git_changed_files(&root, reference) {
+            Ok(files) => Some(files),
+            Err(message) => {
+                report
+                    .warnings
+                    .push(format!("{}; running all tests", message));
+                None
+            }
+        },
+        _ => None,
+    };
+    // A git ref that cannot be compared against selects everything
+    let selective =
+        selective.filter(|since| *since == ChangedSince::LastRun || git_changes.is_some());
+
+    let tests = discover_tests(&root);
+    for test in &tests {
+        let name = relative_name(&root, test);
+        let unchanged = selective.is_some()
+            && cache
+                .get(&name)
+                .is_some_and(|entry| is_unchanged(&root, entry, git_changes.as_ref()));
+
+        if unchanged {
+            report.results.push(TestResult {
+                name,
+                outcome: TestOutcome::Skipped,
+            });
+            continue;
+        }
+
+        let (outcome, entry) = run_test(&root, test, options.update_snapshots, &mut report);
+        cache.insert(name.clone(), entry);
+        report.results.push(TestResult { name, outcome });
+    }
+
+    // Forget tests that no longer exist
+    let names: BTreeSet<&String> = report.results.iter().map(|r| &r.name).collect();
+    cache.retain(|name, _| names.contains(name));
+    if let Err(message) = save_cache(&cache_path, &cache) {
+        report.warnings.push(message);
+    }
+
+    Ok(report)
+}
+
+/// Test files under `root`, sorted, skipping hidden directories
+pub fn discover_tests(root: &Path) -> Vec<PathBuf> {
+    let mut tests = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if path.is_dir() {
+                if !name.starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if name.ends_with(TEST_SUFFIX) {
+                tests.push(path);
+            }
+        }
+    }
+
+    tests.sort();
+    tests
+}
+
+fn run_test(
+    root: &Path,
+    test: &Path,
+    update_snapshots: bool,
+    report: &mut TestReport,
+) -> (TestOutcome, CacheEntry) {
+    let mut files = BTreeMap::new();
+    if let Some(hash) = hash_file(test) {
+        files.insert(relative_name(root, test), hash);
+    }
+
+    let mut runner = Runner::new();
+    runner.get_interpreter_mut().track_module_dependencies();
+    snapshot::begin(test, update_snapshots);
+    let result = runner.run_file(&test.to_string_lossy());
+    match snapshot::finish(result.is_ok()) {
+        Ok(summary) => {
+            report.snapshots_written += summary.written.len();
+            let file = relative_name(root, &snapshot::snapshot_path(test));
+            report.obsolete_snapshots.extend(
+                summary
+                    .obsolete
+                    .iter()
+                    .map(|key| format!("{}: {}", file, key)),
+            );
+            if let Some(hash) = summary.path.as_deref().and_then(hash_file) {
+                files.insert(file, hash);
+            }
+        }
+        Err(message) => report.warnings.push(message),
+    }
+
+    let log = runner
+        .get_interpreter()
+        .module_dependencies()
+        .cloned()
+        .unwrap_or_default();
+    let mut complete = log.complete;
+    for file in &log.files {
+        match hash_file(file) {
+            Some(hash) => {
+                files.insert(relative_name(root, file), hash);
+            }
+            None => complete = false,
+        }
+    }
+
+    let outcome = match result {
+        Ok(()) => TestOutcome::Passed,
+        Err(err) => TestOutcome::Failed(err.to_string()),
+    };
+    let entry = CacheEntry {
+        passed: outcome == TestOutcome::Passed,
+        complete,
+        files,
+    };
+    (outcome, entry)
+}
+
+fn is_unchanged(root: &Path, entry: &CacheEntry, git_changes: Option<&BTreeSet<PathBuf>>) -> bool {
+    if !entry.passed || !entry.complete {
+        return false;
+    }
+
+    entry.files.iter().all(|(name, hash)| {
+        let path = root.join(name);
+        let same_content = hash_file(&path).as_ref() == Some(hash);
+        let changed_in_git = git_changes.is_some_and(|changes| {
+            path.canonicalize()
+                .is_ok_and(|path| changes.contains(&path))
+        });
+        same_content && !changed_in_git
+    })
+}
+
+/// Files that differ between the working tree and `reference`, and untracked
+/// files, as canonical paths
+fn git_changed_files(
+    root: &Path,
+    reference: &str,
+) -> std::result::Result<BTreeSet<PathBuf>, String> {
+    let git = |args: &[&str]| -> std::result::Result<String, String> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(root)
+            .output()
+            .map_err(|err| format!("Cannot run git: {}", err))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let top = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let diff = git(&["diff", "--name-only", reference, "--"])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| top.join(line).canonicalize().ok())
+        .collect())
+}
+
+/// A path relative to the test root with `/` separators, or the full path for
+/// files outside it
+fn relative_name(root: &Path, path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match path.strip_prefix(root) {
+        Ok(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// FNV-1a over the file contents; stable across builds, unlike std's hasher
+fn hash_file(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Some(format!("{:016x}", hash))
+}
+
+fn load_cache(path: &Path) -> std::result::Result<HashMap<String, CacheEntry>, String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        // No cache yet: every test runs
+        return Ok(HashMap::new());
+    };
+    let invalid = || format!("Ignoring invalid test cache {}", path.display());
+
+    let document = json::parse(&text).map_err(|_| invalid())?;
+    let document = document.as_object().ok_or_else(invalid)?;
+    if document.get("version").and_then(Value::as_number) != Some(CACHE_VERSION) {
+        // Written by a different version: start over
+        return Ok(HashMap::new());
+    }
+    let tests = document
+        .get("tests")
+        .and_then(Value::as_object)
+        .ok_or_else(invalid)?;
+
+    let mut cache = HashMap::new();
+    for (name, entry) in tests {
+        let entry = entry.as_object().ok_or_else(invalid)?;
+        let flag = |key: &str| {
+            entry
+                .get(key)
+                .and_then(Value::as_boolean)
+                .ok_or_else(invalid)
+        };
+        let files = entry
+            .get("files")
+            .and_then(Value::as_object)
+            .ok_or_else(invalid)?;
+
+        let mut hashes = BTreeMap::new();
+        for (file, hash) in files {
+            let hash = hash.as_string().ok_or_else(invalid)?;
+            hashes.insert(file.clone(), hash.clone());
+        }
+
+        cache.insert(
+            name.clone(),
+            CacheEntry {
+                passed: flag("passed")?,
+                complete: flag("complete")?,
+                files: hashes,
+            },
+        );
+    }
+
+    Ok(cache)
+}
+
+fn save_cache(path: &Path, cache: &HashMap<String, CacheEntry>) -> std::result::Result<(), String> {
+    let tests = cache
+        .iter()
+        .map(|(name, entry)| {
+            let files = entry
+                .files
+                .iter()
+                .map(|(file, hash)| (file.clone(), Value::String(hash.clone())))
+                .collect();
+            let fields = ObjectMap::from([
+                ("passed".to_string(), Value::Boolean(entry.passed)),
+                ("complete".to_string(), Value::Boolean(entry.complete)),
+                ("files".to_string(), Value::Object(files)),
+            ]);
+            (name.clone(), Value::Object(fields))
+        })
+        .collect();
+    let document = Value::Object(ObjectMap::from([
+        ("version".to_string(), Value::Number(CACHE_VERSION)),
+        ("tests".to_string(), Value::Object(tests)),
+    ]));
+
+    let text = document
+        .to_canonical_string()
+        .expect("the cache holds only plain values");
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    };
+    write().map_err(|err| format!("Could not write test cache {}: {}", path.display(), err))
+}
@@ -0,0 +1,565 @@
+//! Summaries and a pager for REPL results too big to read as text.
+//!
+//! A result whose text runs past the REPL's view threshold is echoed as one
+//! line describing its shape, `array[5000] of object{name, age, region}`,
+//! and `:view` opens it in a pager: a scrolling list of rows, laid out in
+//! columns when they are objects with the same fields, where Enter opens the
+//! selected row and `/` keeps only rows containing some text.
+//!
+//! The pager draws through a [`Screen`], so tests drive it with scripted
+//! keys and read back the frames it drew; [`Terminal`] is the real one, in
+//! raw mode on the alternate screen.
+
+use crate::core::Value;
+use crate::stdlib::output;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Default for `--view-threshold`
+pub const DEFAULT_VIEW_THRESHOLD: usize = 4 * 1024;
+
+/// Most field names a shape lists before eliding the rest
+const SHAPE_FIELDS: usize = 6;
+/// Widest a column gets; longer cells are cut
+const MAX_COLUMN_WIDTH: usize = 30;
+/// Most bytes of a value rendered for one cell
+const MAX_CELL_SIZE: usize = 256;
+
+/// The line the REPL echoes instead of `value` when its text is longer than
+/// `threshold` bytes
+pub fn summary(value: &Value, threshold: usize) -> Option<String> {
+    if output::render(value, Some(threshold)).truncated == 0 {
+        return None;
+    }
+    Some(format!("{} — use :view to inspect", shape(value)))
+}
+
+/// What a value holds, without its contents: `array[3] of number`,
+/// `object{name, age}`, `string[120]`
+pub fn shape(value: &Value) -> String {
+    match value {
+        Value::Array(items) => match items.first() {
+            None => "array[0]".to_string(),
+            Some(first) => {
+                let of = match common_fields(items) {
+                    Some(fields) => format!("object{}", field_list(&fields)),
+                    None if items.iter().all(|i| i.type_name() == first.type_name()) => {
+                        first.type_name().to_string()
+                    }
+                    None => "mixed".to_string(),
+                };
+                format!("array[{}] of {}", items.len(), of)
+            }
+        },
+        Value::Tuple(items) => format!("tuple[{}]", items.len()),
+        Value::Object(fields) => {
+            format!(
+                "object{}",
+                field_list(&fields.keys().cloned().collect::<Vec<_>>())
+            )
+        }
+        Value::String(s) => format!("string[{}]", s.chars().count()),
+        Value::Bytes(bytes) => format!("bytes[{}]", bytes.len()),
+        other => other.type_name().to_string(),
+    }
+}
+
+fn field_list(fields: &[String]) -> String {
+    let mut listed: Vec<&str> = fields
+        .iter()
+        .take(SHAPE_FIELDS)
+        .map(String::as_str)
+        .collect();
+    if fields.len() > SHAPE_FIELDS {
+        listed.push("…");
+    }
+    format!("{{{}}}", listed.join(", "))
+}
+
+/// The fields of `items` if every one is an object with the same fields, in
+/// the first one's order
+fn common_fields(items: &[Value]) -> Option<Vec<String>> {
+    let Some(Value::Object(first)) = items.first() else {
+        return None;
+    };
+    let fields: Vec<String> = first.keys().cloned().collect();
+    items
+        .iter()
+        .all(|item| match item {
+            Value::Object(other) => {
+                other.len() == fields.len() && fields.iter().all(|f| other.contains_key(f))
+            }
+            _ => false,
+        })
+        .then_some(fields)
+}
+
+/// A value as one line of at most `MAX_CELL_SIZE` bytes
+fn cell(value: &Value) -> String {
+    let rendered = output::render(value, Some(MAX_CELL_SIZE));
+    let mut text = rendered.text.replace(['\n', '\r', '\t'], " ");
+    if rendered.truncated > 0 {
+        text.push('…');
+    }
+    text
+}
+
+/// `text` cut or padded to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let count = text.chars().count();
+    if count <= width {
+        return format!("{}{}", text, " ".repeat(width - count));
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+/// A key the pager understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    /// Left arrow: back to the enclosing value
+    Left,
+    Backspace,
+    Esc,
+    Char(char),
+    /// The screen changed size; only redraws
+    Resize,
+}
+
+/// Where the pager draws and reads keys
+pub trait Screen {
+    /// Columns and rows
+    fn size(&self) -> (usize, usize);
+    fn draw(&mut self, frame: &[String]) -> io::Result<()>;
+    /// The next key, or None once there are no more
+    fn read_key(&mut self) -> io::Result<Option<Key>>;
+}
+
+/// Show `value`, named `path`, in a pager on `screen` until the user quits
+pub fn view(value: &Value, path: &str, screen: &mut dyn Screen) -> io::Result<()> {
+    let mut pager = Pager::new(value, path);
+    loop {
+        let (width, height) = screen.size();
+        pager.resize(height);
+        screen.draw(&pager.frame(width))?;
+        match screen.read_key()? {
+            Some(key) if pager.handle(key) => {}
+            _ => return Ok(()),
+        }
+    }
+}
+
+struct Row {
+    /// How the row is reached from its value: `[3]` or `.name`
+    step: String,
+    value: Value,
+    cells: Vec<String>,
+}
+
+/// One value being viewed; opening a row pushes another
+struct Level {
+    path: String,
+    shape: String,
+    /// Field names when the rows are shown as columns
+    columns: Option<Vec<String>>,
+    rows: Vec<Row>,
+    /// Rows shown, as indexes into `rows`, after filtering
+    visible: Vec<usize>,
+    filter: String,
+    /// Position in `visible`
+    selected: usize,
+    /// First position in `visible` on screen
+    top: usize,
+}
+
+impl Level {
+    fn new(value: &Value, path: String) -> Self {
+        let mut columns = None;
+        let rows = match value {
+            Value::Array(items) => {
+                columns = common_fields(items);
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| Row {
+                        step: format!("[{}]", i),
+                        value: item.clone(),
+                        cells: match (&columns, item) {
+                            (Some(fields), Value::Object(object)) => {
+                                fields.iter().map(|f| cell(&object[f.as_str()])).collect()
+                            }
+                            _ => vec![cell(item)],
+                        },
+                    })
+                    .collect()
+            }
+            Value::Tuple(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| Row {
+                    step: format!("[{}]", i),
+                    value: item.clone(),
+                    cells: vec![cell(item)],
+                })
+                .collect(),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(name, field)| Row {
+                    step: format!(".{}", name),
+                    value: field.clone(),
+                    cells: vec![cell(field)],
+                })
+                .collect(),
+            Value::String(text) => text
+                .lines()
+                .enumerate()
+                .map(|(i, line)| Row {
+                    step: format!(":{}", i + 1),
+                    value: Value::String(line.to_string()),
+                    cells: vec![line.to_string()],
+                })
+                .collect(),
+            other => vec![Row {
+                step: String::new(),
+                value: other.clone(),
+                cells: vec![cell(other)],
+            }],
+        };
+        let visible = (0..rows.len()).collect();
+        Self {
+            path,
+            shape: shape(value),
+            columns,
+            rows,
+            visible,
+            filter: String::new(),
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    fn apply_filter(&mut self) {
+        let needle = self.filter.to_lowercase();
+        self.visible = (0..self.rows.len())
+            .filter(|&i| {
+                let row = &self.rows[i];
+                needle.is_empty()
+                    || row.step.to_lowercase().contains(&needle)
+                    || row.cells.iter().any(|c| c.to_lowercase().contains(&needle))
+            })
+            .collect();
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    fn select(&mut self, position: usize) {
+        self.selected = position.min(self.visible.len().saturating_sub(1));
+    }
+}
+
+/// The pager's state, apart from any screen
+pub struct Pager {
+    levels: Vec<Level>,
+    /// Typing a filter after `/`
+    filtering: bool,
+    /// Rows that fit on the screen
+    page: usize,
+}
+
+impl Pager {
+    pub fn new(value: &Value, path: &str) -> Self {
+        Self {
+            levels: vec![Level::new(value, path.to_string())],
+            filtering: false,
+            page: 1,
+        }
+    }
+
+    /// Fit the rows to a screen `height` lines tall
+    pub fn resize(&mut self, height: usize) {
+        let level = self.level();
+        let chrome = if level.columns.is_some() { 3 } else { 2 };
+        self.page = height.saturating_sub(chrome).max(1);
+        self.scroll();
+    }
+
+    fn level(&self) -> &Level {
+        self.levels.last().expect("the pager always has a level")
+    }
+
+    fn level_mut(&mut self) -> &mut Level {
+        self.levels
+            .last_mut()
+            .expect("the pager always has a level")
+    }
+
+    /// Keep the selected row on screen
+    fn scroll(&mut self) {
+        let page = self.page;
+        let level = self.level_mut();
+        if level.selected < level.top {
+            level.top = level.selected;
+        } else if level.selected >= level.top + page {
+            level.top = level.selected + 1 - page;
+        }
+    }
+
+    /// Act on `key`; false means the user quit
+    pub fn handle(&mut self, key: Key) -> bool {
+        let page = self.page;
+        if self.filtering {
+            match key {
+                Key::Char(c) => {
+                    self.level_mut().filter.push(c);
+                    self.level_mut().apply_filter();
+                    return true;
+                }
+                Key::Backspace => {
+                    self.level_mut().filter.pop();
+                    self.level_mut().apply_filter();
+                    return true;
+                }
+                Key::Enter => {
+                    self.filtering = false;
+                    return true;
+                }
+                Key::Esc => {
+                    self.filtering = false;
+                    self.level_mut().filter.clear();
+                    self.level_mut().apply_filter();
+                    return true;
+                }
+                _ => {}
+            }
+        }
+
+        let level = self.level_mut();
+        match key {
+            Key::Char('q') => return false,
+            Key::Char('/') => self.filtering = true,
+            Key::Up | Key::Char('k') => level.select(level.selected.saturating_sub(1)),
+            Key::Down | Key::Char('j') => level.select(level.selected + 1),
+            Key::PageUp => level.select(level.selected.saturating_sub(page)),
+            Key::PageDown => level.select(level.selected + page),
+            Key::Home | Key::Char('g') => level.select(0),
+            Key::End | Key::Char('G') => level.select(usize::MAX),
+            Key::Enter => {
+                let row = level.visible.get(level.selected).map(|&i| &level.rows[i]);
+                if let Some(row) = row.filter(|row| {
+                    matches!(
+                        row.value,
+                        Value::Array(_) | Value::Tuple(_) | Value::Object(_)
+                    )
+                }) {
+                    let path = format!("{}{}", level.path, row.step);
+                    let child = Level::new(&row.value, path);
+                    self.levels.push(child);
+                }
+            }
+            Key::Left | Key::Backspace | Key::Esc if self.levels.len() > 1 => {
+                self.levels.pop();
+            }
+            _ => {}
+        }
+        self.scroll();
+        true
+    }
+
+    /// The lines to show on a screen `width` columns wide
+    pub fn frame(&self, width: usize) -> Vec<String> {
+        let level = self.level();
+        let mut lines = Vec::new();
+
+        let shown = level.visible.len();
+        let mut title = format!("{}  {}", level.path, level.shape);
+        if shown > 0 {
+            let last = (level.top + self.page).min(shown);
+            title.push_str(&format!("  rows {}-{} of {}", level.top + 1, last, shown));
+        } else {
+            title.push_str("  no rows");
+        }
+        if !level.filter.is_empty() {
+            title.push_str(&format!(
+                "  filter \"{}\" ({} of {})",
+                level.filter,
+                shown,
+                level.rows.len()
+            ));
+        }
+        lines.push(fit(&title, width));
+
+        let step_width = level
+            .rows
+            .iter()
+            .map(|row| row.step.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_COLUMN_WIDTH);
+        let cell_widths: Vec<usize> = match &level.columns {
+            Some(columns) => columns
+                .iter()
+                .enumerate()
+                .map(|(c, name)| {
+                    level
+                        .rows
+                        .iter()
+                        .map(|row| row.cells[c].chars().count())
+                        .chain([name.chars().count()])
+                        .max()
+                        .unwrap_or(0)
+                        .min(MAX_COLUMN_WIDTH)
+                })
+                .collect(),
+            None => vec![width],
+        };
+        let layout = |marker: &str, step: &str, cells: &[String]| {
+            let mut line = format!("{}{} ", marker, fit(step, step_width));
+            for (text, &cell_width) in cells.iter().zip(&cell_widths) {
+                line.push_str(&fit(text, cell_width));
+                line.push_str("  ");
+            }
+            fit(line.trim_end(), width)
+        };
+
+        if let Some(columns) = &level.columns {
+            lines.push(layout("  ", "", columns));
+        }
+        for position in level.top..(level.top + self.page).min(shown) {
+            let row = &level.rows[level.visible[position]];
+            let marker = if position == level.selected {
+                "> "
+            } else {
+                "  "
+            };
+            lines.push(layout(marker, &row.step, &row.cells));
+        }
+
+        let footer = if self.filtering {
+            format!("/{}", level.filter)
+        } else {
+            "↑↓ move  PgUp/PgDn page  Enter open  ← back  / filter  q quit".to_string()
+        };
+        lines.push(fit(&footer, width));
+        lines
+    }
+}
+
+/// The terminal, in raw mode on the alternate screen until dropped
+pub struct Terminal {
+    out: io::Stdout,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        if let Err(err) = execute!(out, terminal::EnterAlternateScreen, cursor::Hide) {
+            let _ = terminal::disable_raw_mode();
+            return Err(err);
+        }
+        Ok(Self { out })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Screen for Terminal {
+    fn size(&self) -> (usize, usize) {
+        terminal::size().map_or((80, 24), |(w, h)| (w as usize, h as usize))
+    }
+
+    fn draw(&mut self, frame: &[String]) -> io::Result<()> {
+        queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in frame.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(0, row as u16), Print(line))?;
+        }
+        self.out.flush()
+    }
+
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        loop {
+            let key = match event::read()? {
+                Event::Resize(..) => return Ok(Some(Key::Resize)),
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            // Raw mode keeps Ctrl+C from interrupting, so it quits instead
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(None);
+            }
+            let key = match key.code {
+                KeyCode::Up => Key::Up,
+                KeyCode::Down => Key::Down,
+                KeyCode::PageUp => Key::PageUp,
+                KeyCode::PageDown => Key::PageDown,
+                KeyCode::Home => Key::Home,
+                KeyCode::End => Key::End,
+                KeyCode::Enter | KeyCode::Right => Key::Enter,
+                KeyCode::Left => Key::Left,
+                KeyCode::Backspace => Key::Backspace,
+                KeyCode::Esc => Key::Esc,
+                KeyCode::Char(c) => Key::Char(c),
+                _ => continue,
+            };
+            return Ok(Some(key));
+        }
+    }
+}
+
+/// A screen that plays back keys and keeps every frame drawn, for tests
+#[allow(dead_code)]
+pub struct ScriptedScreen {
+    pub width: usize,
+    pub height: usize,
+    pub keys: VecDeque<Key>,
+    pub frames: Vec<Vec<String>>,
+}
+
+#[allow(dead_code)]
+impl ScriptedScreen {
+    pub fn new(width: usize, height: usize, keys: impl IntoIterator<Item = Key>) -> Self {
+        Self {
+            width,
+            height,
+            keys: keys.into_iter().collect(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn last_frame(&self) -> &[String] {
+        self.frames.last().map_or(&[], Vec::as_slice)
+    }
+}
+
+impl Screen for ScriptedScreen {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn draw(&mut self, frame: &[String]) -> io::Result<()> {
+        self.frames.push(frame.to_vec());
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        Ok(self.keys.pop_front())
+    }
+}
@@ -1,11 +1,14 @@
 use crate::core::Value;
+use std::rc::Rc;
 
 // Type system
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Number,
+    BigInt,
     String,
     Boolean,
+    Bytes,
     Array(Box<Type>),            // Array of specific type: [number]
     Object(Vec<(String, Type)>), // Object with typed properties: {name: string, age: number}
     Function {
@@ -13,37 +16,66 @@ pub enum Type {
         return_type: Box<Type>,
     },
     Union(Vec<Type>), // Union types: number | string
+    Tuple(Vec<Type>), // Fixed-size tuples: (number, string)
+    Named(String),    // A declared type by name; enums are the only kind so far
     Any,              // For untyped variables
     #[allow(dead_code)]
     Never, // Bottom type (for functions that never return)
 }
 
+/// Where an expression or statement is in its source, counted from 1. Expressions the
+/// interpreter builds itself, and code from modules and the prelude, have
+/// none: their errors are reported where the script's own code failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Literal(Value),
-    Identifier(String),
+    Identifier {
+        name: String,
+        span: Option<Span>,
+    },
     Binary {
         left: Box<Expr>,
         operator: BinaryOp,
         right: Box<Expr>,
+        span: Option<Span>, // The operator
     },
     Unary {
         operator: UnaryOp,
         operand: Box<Expr>,
+        span: Option<Span>,
     },
     Call {
         callee: Box<Expr>,
         args: Vec<Expr>,
+        span: Option<Span>, // Where the callee starts
     },
     Array(Vec<Expr>),
+    /// `(a, b)`, or `(a,)` with one element
+    Tuple(Vec<Expr>),
+    /// `"text ${expr} text"`
+    Interpolation(Vec<StringPart>),
+    /// `object[index]`, or `object?.[index]`
     Index {
         object: Box<Expr>,
         index: Box<Expr>,
+        /// Written `?.[`: null instead of an error when `object` is null
+        optional: bool,
+        span: Option<Span>, // The '['
     },
     Object(Vec<(String, Expr)>),
+    /// `object.property`, or `object?.property`
     Property {
         object: Box<Expr>,
         property: String,
+        /// Written `?.`: null instead of an error when `object` is null
+        optional: bool,
+        span: Option<Span>, // The '.'
     },
     ModuleAccess {
         module: String,
@@ -60,6 +92,51 @@ pub enum Expr {
         class: Box<Expr>,
         args: Vec<Expr>,
     },
+    /// `condition ? then_expr : else_expr`; only the chosen side is evaluated
+    Conditional {
+        condition: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    /// `function(x, y): x + y`; `name` is generated from the line it is on
+    Lambda {
+        name: String,
+        params: Vec<String>,
+        param_types: Vec<Option<Type>>,
+        optional_params: Rc<OptionalParams>,
+        return_type: Option<Type>,
+        body: Rc<Expr>,
+    },
+}
+
+/// A piece of an interpolated string
+#[derive(Debug, Clone)]
+pub enum StringPart {
+    Text(String),
+    Expr(Expr),
+}
+
+impl Expr {
+    /// A reference to `name` that does not come from source
+    pub fn identifier(name: impl Into<String>) -> Expr {
+        Expr::Identifier {
+            name: name.into(),
+            span: None,
+        }
+    }
+
+    /// Where this expression is reported when evaluating it fails
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Identifier { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Property { span, .. } => *span,
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -77,11 +154,14 @@ pub enum BinaryOp {
     GreaterEqual,
     And,
     Or,
+    /// `??`: the left side unless it is null, else the right side
+    Coalesce,
 }
 
 impl BinaryOp {
     pub fn precedence(&self) -> u8 {
         match self {
+            BinaryOp::Coalesce => 0,
             BinaryOp::Or => 1,
             BinaryOp::And => 2,
             BinaryOp::Equal | BinaryOp::NotEqual => 3,
@@ -102,13 +182,40 @@ pub enum UnaryOp {
     Minus,
 }
 
+/// The parameters a call may leave out: `= value` defaults for the last
+/// `defaults.len()` parameters before any rest parameter, and whether the
+/// last parameter is `...rest`, which collects the remaining arguments into
+/// an array
+#[derive(Debug, Clone, Default)]
+pub struct OptionalParams {
+    pub defaults: Vec<Expr>,
+    pub rest: bool,
+}
+
+impl OptionalParams {
+    /// How many of `param_count` parameters every call must pass
+    pub fn required(&self, param_count: usize) -> usize {
+        param_count - self.defaults.len() - usize::from(self.rest)
+    }
+
+    /// Whether `arg_count` arguments fit `param_count` parameters
+    pub fn accepts(&self, param_count: usize, arg_count: usize) -> bool {
+        arg_count >= self.required(param_count) && (self.rest || arg_count <= param_count)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defaults.is_empty() && !self.rest
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MethodDecl {
     pub name: String,
     pub params: Vec<String>,
     pub param_types: Vec<Option<Type>>, // Optional parameter types
-    pub return_type: Option<Type>,      // Optional return type
-    pub body: Box<Stmt>,
+    pub optional_params: Rc<OptionalParams>,
+    pub return_type: Option<Type>, // Optional return type
+    pub body: Rc<Stmt>,
 }
 
 #[derive(Debug, Clone)]
@@ -119,6 +226,13 @@ pub enum Stmt {
         type_annotation: Option<Type>, // Optional type: let x: number = 42
         value: Expr,
     },
+    /// `let (a, (b, c)) = value`: bind each name to its part of a tuple
+    LetTuple {
+        pattern: Pattern,
+        type_annotation: Option<Type>,
+        value: Expr,
+        span: Option<Span>, // The pattern
+    },
     If {
         condition: Expr,
         then_stmt: Box<Stmt>,
@@ -134,26 +248,47 @@ pub enum Stmt {
         end: Expr,
         body: Box<Stmt>,
     },
+    /// `for item in items:` over an array's or tuple's items, an object's
+    /// keys or a string's characters; `for (key, value) in pairs:` takes
+    /// each item apart as a destructuring `let` would
+    ForIn {
+        pattern: Pattern,
+        iterable: Expr,
+        body: Box<Stmt>,
+        span: Option<Span>, // The iterable
+    },
     Block(Vec<Stmt>),
     Print(Expr),
     Return(Option<Expr>),
+    Break {
+        span: Option<Span>,
+    },
+    Continue {
+        span: Option<Span>,
+    },
     Function {
         name: String,
         params: Vec<String>,
         param_types: Vec<Option<Type>>, // Optional parameter types
-        return_type: Option<Type>,      // Optional return type
-        body: Box<Stmt>,
+        optional_params: Rc<OptionalParams>,
+        return_type: Option<Type>, // Optional return type
+        body: Rc<Stmt>,
+        /// `@decorator` lines above the function, outermost first
+        decorators: Vec<Expr>,
     },
     AsyncFunction {
         name: String,
         params: Vec<String>,
         param_types: Vec<Option<Type>>, // Optional parameter types
-        return_type: Option<Type>,      // Optional return type
-        body: Box<Stmt>,
+        optional_params: Rc<OptionalParams>,
+        return_type: Option<Type>, // Optional return type
+        body: Rc<Stmt>,
     },
     Class {
         name: String,
         superclass: Option<String>,
+        /// `name = expr` lines, which every new instance starts with
+        fields: Vec<(String, Expr)>,
         methods: Vec<MethodDecl>,
     },
     Try {
@@ -164,6 +299,7 @@ pub enum Stmt {
     Assignment {
         target: AssignmentTarget,
         value: Expr,
+        span: Option<Span>, // Where the target is reported, as for the same expression
     },
     Import {
         module_path: String,
@@ -173,6 +309,41 @@ pub enum Stmt {
     Export {
         item: ExportItem,
     },
+    Enum {
+        name: String,
+        members: Vec<String>,
+    },
+    Match {
+        subject: Expr,
+        arms: Vec<MatchArm>,
+        default: Option<Box<Stmt>>, // The `else:` or `default:` arm
+    },
+}
+
+/// The names a destructuring `let` or `for` binds, shaped like the tuple it
+/// takes apart
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Name(String),
+    Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Every name bound, left to right
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Name(name) => vec![name.as_str()],
+            Pattern::Tuple(parts) => parts.iter().flat_map(Pattern::names).collect(),
+        }
+    }
+}
+
+/// `a, b: body`, or `case a, b: body`; taken when the subject equals any
+/// of the patterns
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub patterns: Vec<Expr>,
+    pub body: Stmt,
 }
 
 #[derive(Debug, Clone)]
@@ -201,8 +372,11 @@ pub enum ExportItem {
         name: String,
         params: Vec<String>,
         param_types: Vec<Option<Type>>, // Optional parameter types
-        return_type: Option<Type>,      // Optional return type
-        body: Box<Stmt>,
+        optional_params: Rc<OptionalParams>,
+        return_type: Option<Type>, // Optional return type
+        body: Rc<Stmt>,
+        /// `@decorator` lines above the function, outermost first
+        decorators: Vec<Expr>,
     },
     Variable {
         name: String,
@@ -214,12 +388,54 @@ pub enum ExportItem {
 #[derive(Debug)]
 pub struct Program {
     pub statements: Vec<Stmt>,
+    pub lines: Vec<usize>, // Source line of each top-level statement, when parsed
+    /// Whether each bracketed list (call arguments, array or object literal)
+    /// spanned several lines in the source, in the order the parser closed
+    /// them. Only the formatter reads this.
+    pub multiline_lists: Vec<bool>,
+    /// Where statements, class members and match arms start and where
+    /// braces close, in the order the parser reached them. Only the
+    /// formatter reads this, to put comments and blank lines back.
+    pub anchors: Vec<Anchor>,
+}
+
+/// A source line the formatter can place comments and blank lines by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Statement(usize),
+    Field(usize),
+    Method(usize),
+    /// A match arm, or its default
+    Arm(usize),
+    /// The `}` of a block, class body or match
+    Close(usize),
+    /// An item of a list or object literal spread over several lines
+    Item(usize),
+    /// The closing bracket of such a literal
+    Bracket(usize),
+}
+
+impl Anchor {
+    pub fn line(self) -> usize {
+        match self {
+            Anchor::Statement(line)
+            | Anchor::Field(line)
+            | Anchor::Method(line)
+            | Anchor::Arm(line)
+            | Anchor::Close(line)
+            | Anchor::Item(line)
+            | Anchor::Bracket(line) => line,
+        }
+    }
 }
 
 impl Program {
     pub fn new() -> Self {
         Self {
             statements: Vec::new(),
+            lines: Vec::new(),
+            multiline_lists: Vec::new(),
+            anchors: Vec::new(),
         }
     }
 
@@ -227,6 +443,17 @@ impl Program {
         self.statements.push(stmt);
     }
 
+    pub fn add_statement_at(&mut self, stmt: Stmt, line: usize) {
+        self.lines.resize(self.statements.len(), 0);
+        self.lines.push(line);
+        self.statements.push(stmt);
+    }
+
+    /// Source line of the top-level statement at `index`, if known
+    pub fn line_of(&self, index: usize) -> Option<usize> {
+        self.lines.get(index).copied().filter(|&line| line > 0)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.statements.is_empty()
     }
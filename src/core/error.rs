@@ -1,3 +1,4 @@
+use crate::core::{ObjectMap, Value};
 use std::fmt;
 
 #[allow(dead_code)]
@@ -46,6 +47,7 @@ pub enum InfraError {
         line: Option<usize>,
         column: Option<usize>,
         suggestion: Option<String>,
+        available_functions: Option<Vec<String>>, // Keys of the object a call looked in
     },
     ArgumentCountMismatch {
         expected: usize,
@@ -66,6 +68,16 @@ pub enum InfraError {
         available_properties: Option<Vec<String>>,
     },
     ReturnValue(Option<crate::core::Value>), // Renamed from Return
+    /// `break` unwinding to the innermost loop
+    Break {
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// `continue` unwinding to the innermost loop
+    Continue {
+        line: Option<usize>,
+        column: Option<usize>,
+    },
     IoError {
         message: String,
         operation: Option<String>,
@@ -76,6 +88,7 @@ pub enum InfraError {
         exception_type: Option<String>,
         line: Option<usize>,
         stack_trace: Vec<String>,
+        payload: Option<Box<crate::core::Value>>, // Error value thrown by the script, if any
     },
     ModuleError {
         module_name: String,
@@ -95,7 +108,18 @@ pub enum InfraError {
         message: String,
         operation: Option<String>,
     },
+    TimeoutError {
+        operation: Option<String>, // Operation in flight when the deadline passed
+        timeout_ms: u64,
+    },
     Generic(String), // General fallback error
+    /// An error that escaped a function call, with the calls it passed
+    /// through, innermost first. Runtime errors and exceptions keep theirs
+    /// in their own `stack_trace` instead.
+    Traced {
+        error: Box<InfraError>,
+        stack_trace: Vec<String>,
+    },
 }
 
 impl fmt::Display for InfraError {
@@ -130,27 +154,11 @@ impl fmt::Display for InfraError {
                 }
                 Ok(())
             }
-            InfraError::RuntimeError {
-                message,
-                line,
-                column,
-                stack_trace,
-                source_code: _,
-            } => {
-                if let (Some(l), Some(c)) = (line, column) {
-                    write!(f, "Runtime error at line {}, column {}: {}", l, c, message)?;
-                } else {
-                    write!(f, "Runtime error: {}", message)?;
-                }
-
-                if !stack_trace.is_empty() {
-                    write!(f, "\nStack trace:")?;
-                    for frame in stack_trace {
-                        write!(f, "\n  {}", frame)?;
-                    }
-                }
-                Ok(())
-            }
+            // As with the other variants, the position and the stack trace
+            // are left to the error reporter: this text is also what `catch`
+            // gives a script, and it should not change with where the error
+            // happened
+            InfraError::RuntimeError { message, .. } => write!(f, "Runtime error: {}", message),
             InfraError::TypeError {
                 expected,
                 found,
@@ -194,11 +202,15 @@ impl fmt::Display for InfraError {
                 line: _,
                 column: _,
                 suggestion,
+                available_functions,
             } => {
                 write!(f, "Runtime error: Undefined function '{}'", name)?;
                 if let Some(sugg) = suggestion {
                     write!(f, "\n  Did you mean '{}'?", sugg)?;
                 }
+                if let Some(names) = available_functions {
+                    write!(f, "\n  Available functions: {}", names.join(", "))?;
+                }
                 Ok(())
             }
             InfraError::ArgumentCountMismatch {
@@ -276,6 +288,8 @@ impl fmt::Display for InfraError {
                         .unwrap_or("null".to_string())
                 )
             }
+            InfraError::Break { .. } => write!(f, "'break' outside a loop"),
+            InfraError::Continue { .. } => write!(f, "'continue' outside a loop"),
             InfraError::IoError {
                 message,
                 operation,
@@ -293,23 +307,11 @@ impl fmt::Display for InfraError {
             InfraError::Exception {
                 message,
                 exception_type,
-                line: _,
-                stack_trace,
-            } => {
-                if let Some(exc_type) = exception_type {
-                    write!(f, "{}: {}", exc_type, message)?;
-                } else {
-                    write!(f, "Exception: {}", message)?;
-                }
-
-                if !stack_trace.is_empty() {
-                    write!(f, "\nStack trace:")?;
-                    for frame in stack_trace {
-                        write!(f, "\n  {}", frame)?;
-                    }
-                }
-                Ok(())
-            }
+                ..
+            } => match exception_type {
+                Some(exc_type) => write!(f, "{}: {}", exc_type, message),
+                None => write!(f, "Exception: {}", message),
+            },
             InfraError::ModuleError {
                 module_name,
                 reason,
@@ -352,13 +354,337 @@ impl fmt::Display for InfraError {
                     write!(f, "Memory error: {}", message)
                 }
             }
+            InfraError::TimeoutError {
+                operation,
+                timeout_ms,
+            } => {
+                if let Some(op) = operation {
+                    write!(
+                        f,
+                        "Timeout error: {} did not finish within {} ms",
+                        op, timeout_ms
+                    )
+                } else {
+                    write!(f, "Timeout error: deadline of {} ms passed", timeout_ms)
+                }
+            }
             InfraError::Generic(message) => {
                 write!(f, "Error: {}", message)
             }
+            InfraError::Traced { error, .. } => error.fmt(f),
         }
     }
 }
 
 impl std::error::Error for InfraError {}
 
+impl InfraError {
+    /// A `break` or `continue` that reached a function or the top level
+    /// without meeting a loop becomes an error at the statement, or on
+    /// `line` when its position is not known; other errors pass through
+    /// unchanged
+    pub fn outside_loop(self, line: Option<usize>) -> Self {
+        match &self {
+            InfraError::Break {
+                line: at_line,
+                column,
+            }
+            | InfraError::Continue {
+                line: at_line,
+                column,
+            } => InfraError::RuntimeError {
+                message: format!("{}; it can only be used inside a while or for loop", self),
+                line: at_line.or(line),
+                column: *column,
+                stack_trace: vec![],
+                source_code: None,
+            },
+            _ => self,
+        }
+    }
+
+    /// Give the error `stack_trace` unless it already has one. A runtime
+    /// error or exception keeps it in its own field; any other error a
+    /// script can catch is wrapped in `Traced`.
+    pub fn traced(mut self, frames: Vec<String>) -> Self {
+        if let InfraError::RuntimeError { stack_trace, .. }
+        | InfraError::Exception { stack_trace, .. } = &mut self
+        {
+            if stack_trace.is_empty() {
+                *stack_trace = frames;
+            }
+            return self;
+        }
+        if matches!(self, InfraError::Traced { .. }) || !self.is_catchable() || frames.is_empty() {
+            return self;
+        }
+        InfraError::Traced {
+            error: Box::new(self),
+            stack_trace: frames,
+        }
+    }
+
+    /// The error itself, without the calls it escaped
+    pub fn untraced(&self) -> &InfraError {
+        match self {
+            InfraError::Traced { error, .. } => error,
+            other => other,
+        }
+    }
+
+    /// The calls the error escaped, innermost first
+    pub fn stack_trace(&self) -> &[String] {
+        match self {
+            InfraError::RuntimeError { stack_trace, .. }
+            | InfraError::Exception { stack_trace, .. }
+            | InfraError::Traced { stack_trace, .. } => stack_trace,
+            _ => &[],
+        }
+    }
+
+    /// Place the error at `line` and `column` unless it already has a
+    /// position, so the innermost code that failed is the one reported
+    pub fn at(mut self, at_line: usize, at_column: usize) -> Self {
+        if let InfraError::Traced { error, stack_trace } = self {
+            return InfraError::Traced {
+                error: Box::new(error.at(at_line, at_column)),
+                stack_trace,
+            };
+        }
+        match &mut self {
+            InfraError::RuntimeError { line, column, .. }
+            | InfraError::TypeError { line, column, .. }
+            | InfraError::DivisionByZero { line, column }
+            | InfraError::UndefinedVariable { line, column, .. }
+            | InfraError::UndefinedFunction { line, column, .. }
+                if line.is_none() =>
+            {
+                *line = Some(at_line);
+                *column = Some(at_column);
+            }
+            InfraError::ArgumentCountMismatch { line, .. }
+            | InfraError::IndexOutOfBounds { line, .. }
+            | InfraError::PropertyNotFound { line, .. }
+            | InfraError::Exception { line, .. }
+            | InfraError::ClassError { line, .. } => {
+                line.get_or_insert(at_line);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// The text of the file the error happened in, for showing the lines
+    /// around it; only lexing, parsing and runtime errors carry one
+    pub fn source_code(&self) -> Option<&str> {
+        match self {
+            InfraError::LexError { source_code, .. }
+            | InfraError::ParseError { source_code, .. }
+            | InfraError::RuntimeError { source_code, .. } => source_code.as_deref(),
+            InfraError::Traced { error, .. } => error.source_code(),
+            _ => None,
+        }
+    }
+
+    /// Attach `source` to an error that can carry it and has none yet
+    pub fn with_source(mut self, source: &str) -> Self {
+        if let InfraError::Traced { error, stack_trace } = self {
+            return InfraError::Traced {
+                error: Box::new(error.with_source(source)),
+                stack_trace,
+            };
+        }
+        match &mut self {
+            InfraError::LexError { source_code, .. }
+            | InfraError::ParseError { source_code, .. }
+            | InfraError::RuntimeError { source_code, .. }
+                if source_code.is_none() =>
+            {
+                *source_code = Some(source.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Whether a script can handle this error: `try`/`catch` catches it and
+    /// an async function turns it into a rejected promise. Control flow and
+    /// Ctrl-C are not.
+    pub fn is_catchable(&self) -> bool {
+        matches!(
+            self.untraced(),
+            InfraError::Exception { .. }
+                | InfraError::RuntimeError { .. }
+                | InfraError::TypeError { .. }
+                | InfraError::DivisionByZero { .. }
+                | InfraError::IndexOutOfBounds { .. }
+                | InfraError::PropertyNotFound { .. }
+                | InfraError::UndefinedVariable { .. }
+                | InfraError::UndefinedFunction { .. }
+                | InfraError::ArgumentCountMismatch { .. }
+                | InfraError::ClassError { .. }
+                | InfraError::AsyncError { .. }
+                | InfraError::TimeoutError { .. }
+                | InfraError::IoError { .. }
+        )
+    }
+
+    /// What `catch` binds for this error. A value the script threw is given
+    /// back unchanged; anything else becomes an error value whose type is the
+    /// variant's name, whose message is the error's text, and whose fields
+    /// hold what the variant knows, e.g. `index` and `length` for
+    /// IndexOutOfBounds.
+    pub fn into_value(self) -> Value {
+        let text = self.to_string();
+        let mut fields = ObjectMap::new();
+        let mut field = |name: &str, value: Value| {
+            fields.insert(name.to_string(), value);
+        };
+        let (error_type, line, stack) = match self {
+            InfraError::Traced { error, stack_trace } => {
+                return match error.into_value() {
+                    Value::Error {
+                        error_type,
+                        message,
+                        fields,
+                        line,
+                        ..
+                    } => Value::Error {
+                        error_type,
+                        message,
+                        fields,
+                        line,
+                        stack: stack_trace,
+                    },
+                    other => other,
+                };
+            }
+            InfraError::Exception {
+                payload: Some(value),
+                ..
+            } => return *value,
+            InfraError::Exception {
+                exception_type,
+                line,
+                stack_trace,
+                ..
+            } => (
+                exception_type.unwrap_or_else(|| "Exception".to_string()),
+                line,
+                stack_trace,
+            ),
+            InfraError::RuntimeError {
+                line, stack_trace, ..
+            } => ("RuntimeError".to_string(), line, stack_trace),
+            InfraError::TypeError {
+                expected,
+                found,
+                context,
+                line,
+                ..
+            } => {
+                field("expected", Value::String(expected));
+                field("found", Value::String(found));
+                if let Some(context) = context {
+                    field("context", Value::String(context));
+                }
+                ("TypeError".to_string(), line, vec![])
+            }
+            InfraError::DivisionByZero { line, .. } => ("DivisionByZero".to_string(), line, vec![]),
+            InfraError::UndefinedVariable { name, line, .. } => {
+                field("name", Value::String(name));
+                ("UndefinedVariable".to_string(), line, vec![])
+            }
+            InfraError::UndefinedFunction { name, line, .. } => {
+                field("name", Value::String(name));
+                ("UndefinedFunction".to_string(), line, vec![])
+            }
+            InfraError::ArgumentCountMismatch {
+                expected,
+                found,
+                function_name,
+                line,
+            } => {
+                field("expected", Value::Number(expected as f64));
+                field("found", Value::Number(found as f64));
+                if let Some(function_name) = function_name {
+                    field("function", Value::String(function_name));
+                }
+                ("ArgumentCountMismatch".to_string(), line, vec![])
+            }
+            InfraError::IndexOutOfBounds {
+                index,
+                length,
+                line,
+                ..
+            } => {
+                field("index", Value::Number(index as f64));
+                field("length", Value::Number(length as f64));
+                ("IndexOutOfBounds".to_string(), line, vec![])
+            }
+            InfraError::PropertyNotFound {
+                property,
+                object_type,
+                line,
+                ..
+            } => {
+                field("property", Value::String(property));
+                if let Some(object_type) = object_type {
+                    field("object_type", Value::String(object_type));
+                }
+                ("PropertyNotFound".to_string(), line, vec![])
+            }
+            InfraError::ClassError {
+                class_name,
+                method_name,
+                line,
+                ..
+            } => {
+                if let Some(class_name) = class_name {
+                    field("class", Value::String(class_name));
+                }
+                if let Some(method_name) = method_name {
+                    field("method", Value::String(method_name));
+                }
+                ("ClassError".to_string(), line, vec![])
+            }
+            InfraError::IoError {
+                operation, path, ..
+            } => {
+                if let Some(operation) = operation {
+                    field("operation", Value::String(operation));
+                }
+                if let Some(path) = path {
+                    field("path", Value::String(path));
+                }
+                ("IoError".to_string(), None, vec![])
+            }
+            InfraError::AsyncError { operation, .. } => {
+                if let Some(operation) = operation {
+                    field("operation", Value::String(operation));
+                }
+                ("AsyncError".to_string(), None, vec![])
+            }
+            InfraError::TimeoutError {
+                operation,
+                timeout_ms,
+            } => {
+                if let Some(operation) = operation {
+                    field("operation", Value::String(operation));
+                }
+                field("timeout_ms", Value::Number(timeout_ms as f64));
+                ("TimeoutError".to_string(), None, vec![])
+            }
+            _ => ("Error".to_string(), None, vec![]),
+        };
+        Value::Error {
+            error_type,
+            message: text,
+            fields,
+            line,
+            stack,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, InfraError>;
@@ -2,10 +2,16 @@ pub mod ast;
 
 pub mod error;
 
+pub mod object_map;
+
 pub mod value;
 
+pub mod wire;
+
 pub use ast::*;
 
 pub use error::*;
 
+pub use object_map::ObjectMap;
+
 pub use value::*;
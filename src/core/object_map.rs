@@ -0,0 +1,194 @@
+//! The map behind `Value::Object`.
+//!
+//! Objects keep their keys in insertion order, like JavaScript objects and
+//! Python dicts, and every way of looking at an object (printing,
+//! `object.keys`, `object.entries`, serialization) walks them in that order:
+//!
+//! - an object literal keeps its source order; a key written twice keeps the
+//!   position of its first occurrence and the last value
+//! - inserting a new key appends it; assigning to an existing key keeps its
+//!   position
+//! - removing a key leaves the others in order
+//!
+//! Equality ignores order: `{a: 1, b: 2} == {b: 2, a: 1}`.
+//!
+//! Most objects are small records, where hashing the key costs more than
+//! comparing it against every entry. Up to [`SMALL_LEN`] entries a lookup
+//! scans `entries` and there is no index; the object that grows past that
+//! builds one, and keeps it even if keys are later removed.
+
+use crate::core::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+
+/// Most entries an object holds before it builds a key index
+pub const SMALL_LEN: usize = 8;
+
+#[derive(Clone, Default)]
+pub struct ObjectMap {
+    entries: Vec<(String, Value)>,
+    /// Position of each key in `entries`; None while the object is small
+    positions: Option<HashMap<String, usize>>,
+}
+
+impl ObjectMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        match &self.positions {
+            Some(positions) => positions.get(key).copied(),
+            None => self.entries.iter().position(|(k, _)| k == key),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let position = self.position(key)?;
+        Some(&self.entries[position].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let position = self.position(key)?;
+        Some(&mut self.entries[position].1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Whether lookups go through a key index rather than a scan
+    #[allow(dead_code)]
+    pub fn is_indexed(&self) -> bool {
+        self.positions.is_some()
+    }
+
+    /// Set `key`, appending it if it is new; an existing key keeps its
+    /// position. Returns the value it replaced.
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        if let Some(position) = self.position(&key) {
+            return Some(std::mem::replace(&mut self.entries[position].1, value));
+        }
+        match &mut self.positions {
+            Some(positions) => {
+                positions.insert(key.clone(), self.entries.len());
+            }
+            None if self.entries.len() == SMALL_LEN => {
+                let mut positions: HashMap<String, usize> = self
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(|(position, (k, _))| (k.clone(), position))
+                    .collect();
+                positions.insert(key.clone(), self.entries.len());
+                self.positions = Some(positions);
+            }
+            None => {}
+        }
+        self.entries.push((key, value));
+        None
+    }
+
+    /// Remove `key`; the keys after it move up and keep their order
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let position = self.position(key)?;
+        let (_, value) = self.entries.remove(position);
+        if let Some(positions) = &mut self.positions {
+            positions.remove(key);
+            for (key, _) in &self.entries[position..] {
+                if let Some(later) = positions.get_mut(key) {
+                    *later -= 1;
+                }
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl PartialEq for ObjectMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl fmt::Debug for ObjectMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Index<&str> for ObjectMap {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key)
+            .unwrap_or_else(|| panic!("no key '{}' in object", key))
+    }
+}
+
+impl FromIterator<(String, Value)> for ObjectMap {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut map = ObjectMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<const N: usize> From<[(String, Value); N]> for ObjectMap {
+    fn from(entries: [(String, Value); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl Extend<(String, Value)> for ObjectMap {
+    fn extend<I: IntoIterator<Item = (String, Value)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl IntoIterator for ObjectMap {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ObjectMap {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, Value)>,
+        fn(&'a (String, Value)) -> (&'a String, &'a Value),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
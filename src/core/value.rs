@@ -1,40 +1,241 @@
-use crate::core::ast::Stmt;
-use std::collections::HashMap;
+use crate::core::ast::{Expr, MethodDecl, OptionalParams, Stmt};
+use crate::core::ObjectMap;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Zero};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt;
 use std::ops::{Add, Div, Mul, Rem, Sub};
+use std::rc::Rc;
+
+thread_local! {
+    /// Instances being written out, innermost last
+    static WRITING: RefCell<Vec<*const Instance>> = const { RefCell::new(Vec::new()) };
+}
+
+/// `n` with at most `precision` decimal places, or in its shortest exact
+/// form for None
+fn format_number(n: f64, precision: Option<usize>) -> String {
+    match precision {
+        Some(digits) if n.is_finite() => {
+            let fixed = format!("{:.*}", digits, n);
+            let trimmed = if fixed.contains('.') {
+                fixed.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                &fixed
+            };
+            // Avoid "-0" for small negative numbers that round to zero
+            if trimmed == "-0" {
+                "0".to_string()
+            } else {
+                trimmed.to_string()
+            }
+        }
+        _ => n.to_string(),
+    }
+}
+
+/// Bytes shown in hex by Display before the rest is elided
+const DISPLAY_BYTES: usize = 32;
+
+/// An `enum` declaration. Each declaration is its own type: members of two
+/// enums never compare equal, even when both are named alike.
+#[derive(Debug, PartialEq)]
+pub struct EnumDef {
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+impl EnumDef {
+    pub fn member(self: &Rc<Self>, name: &str) -> Option<Value> {
+        let index = self.members.iter().position(|member| member == name)?;
+        Some(Value::EnumMember {
+            definition: self.clone(),
+            index,
+        })
+    }
+}
+
+/// A `class` declaration: the fields each instance starts with and the
+/// methods its instances answer to. Like an enum, each declaration is its
+/// own type.
+#[derive(Debug)]
+pub struct ClassDef {
+    pub name: String,
+    /// The class after `extends`, whose fields and methods this one inherits
+    pub superclass: Option<Rc<ClassDef>>,
+    /// `name = expr` lines, evaluated in order for every new instance
+    pub fields: Vec<(String, Expr)>,
+    pub methods: Vec<MethodDecl>,
+    /// What the class's methods closed over where it was declared
+    pub captured: Captures,
+}
+
+impl ClassDef {
+    /// The method `name` and the class that declares it: this one, or the
+    /// nearest superclass when this one does not
+    pub fn method(self: &Rc<Self>, name: &str) -> Option<(&Rc<ClassDef>, &MethodDecl)> {
+        self.lineage()
+            .find_map(|class| Some((class, class.methods.iter().find(|m| m.name == name)?)))
+    }
+
+    /// This class, then its superclass, and so on up to the class that
+    /// extends nothing
+    pub fn lineage(self: &Rc<Self>) -> impl Iterator<Item = &Rc<ClassDef>> {
+        std::iter::successors(Some(self), |class| class.superclass.as_ref())
+    }
+
+    /// Whether this class is `name` or extends it, however indirectly
+    pub fn is_a(self: &Rc<Self>, name: &str) -> bool {
+        self.lineage().any(|class| class.name == name)
+    }
+}
+
+/// An object made by calling a class. Instances are shared, not copied:
+/// every variable holding one sees changes made through the others.
+pub struct Instance {
+    pub class: Rc<ClassDef>,
+    pub fields: RefCell<ObjectMap>,
+}
+
+impl Instance {
+    /// Run `write` with this instance marked as being written out, or return
+    /// None without running it when it already is: a field holds the
+    /// instance itself, and writing it again would never end
+    pub(crate) fn writing<R>(&self, write: impl FnOnce() -> R) -> Option<R> {
+        let this = self as *const Instance;
+        if WRITING.with(|writing| writing.borrow().contains(&this)) {
+            return None;
+        }
+        WRITING.with(|writing| writing.borrow_mut().push(this));
+        let result = write();
+        WRITING.with(|writing| writing.borrow_mut().pop());
+        Some(result)
+    }
+}
+
+// The class by name only; its methods would bury the fields
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.writing(|| {
+            f.debug_struct("Instance")
+                .field("class", &self.class.name)
+                .field("fields", &self.fields)
+                .finish()
+        })
+        .unwrap_or_else(|| write!(f, "<cycle>"))
+    }
+}
+
+/// The variables a function closed over where it was defined. Each cell is
+/// the one the defining scope holds, so the function and that scope see each
+/// other's assignments, and copies of the function share the same state.
+#[derive(Clone, Default)]
+pub struct Captures(Rc<Vec<(String, Rc<RefCell<Value>>)>>);
+
+impl Captures {
+    pub fn new(cells: Vec<(String, Rc<RefCell<Value>>)>) -> Self {
+        Self(Rc::new(cells))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, Rc<RefCell<Value>>)> {
+        self.0.iter()
+    }
+}
+
+// Names only: a captured cell can hold the function that captured it
+impl fmt::Debug for Captures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
+    BigInt(BigInt),
     String(String),
     Boolean(bool),
     Null,
     Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    /// Fixed-size group of values, as `return (count, errors)` makes
+    Tuple(Rc<[Value]>),
+    Object(ObjectMap),
+    Bytes(Rc<Vec<u8>>),  // Shared so passing a buffer around never copies it
+    Enum(Rc<EnumDef>),   // The namespace an `enum` declaration binds
+    Class(Rc<ClassDef>), // What a `class` declaration binds; call it to construct
+    Instance(Rc<Instance>),
+    EnumMember {
+        definition: Rc<EnumDef>,
+        index: usize,
+    },
     Function {
         name: String,
         params: Vec<String>,
         param_types: Vec<Option<crate::core::ast::Type>>, // Parameter types
-        return_type: Option<crate::core::ast::Type>,      // Return type
-        body: Box<Stmt>,
+        optional_params: Rc<OptionalParams>,
+        return_type: Option<crate::core::ast::Type>, // Return type
+        body: Rc<Stmt>,                              // Shared so copies of the function stay cheap
+        captured: Captures,
+        is_async: bool, // Calls return a promise of what the body returns
+    },
+    NativeFunction {
+        module: String, // A standard library function used as a value, e.g. string.upper
+        function: String,
+    },
+    /// A function the bytecode compiler compiled; its code starts at `entry`
+    /// in the chunk it was compiled into
+    CompiledFunction {
+        name: String,
+        arity: usize,
+        entry: usize,
     },
     Promise {
+        id: usize, // Identifies the promise in the async module's bookkeeping
         value: Option<Box<Value>>,
         resolved: bool,
         rejected: bool,
         error: Option<String>,
     },
+    Error {
+        error_type: String,
+        message: String,
+        fields: ObjectMap,
+        line: Option<usize>,
+        stack: Vec<String>, // Innermost function first
+    },
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::BigInt(_), Value::BigInt(_) | Value::Number(_))
+            | (Value::Number(_), Value::BigInt(_)) => {
+                self.numeric_cmp(other) == Some(Ordering::Equal)
+            }
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Null, Value::Null) => true,
             (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
             (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Enum(a), Value::Enum(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (
+                Value::EnumMember {
+                    definition: d1,
+                    index: i1,
+                },
+                Value::EnumMember {
+                    definition: d2,
+                    index: i2,
+                },
+            ) => Rc::ptr_eq(d1, d2) && i1 == i2,
             (
                 Value::Function {
                     name: n1,
@@ -51,11 +252,36 @@ impl PartialEq for Value {
                 // We don't compare bodies for simplicity
                 n1 == n2 && p1 == p2
             }
-            (Value::Promise { .. }, Value::Promise { .. }) => {
-                // Promises are equal only if they are the same instance
-                // For simplicity, we'll say they're never equal
-                false
-            }
+            (
+                Value::Error {
+                    error_type: t1,
+                    message: m1,
+                    fields: f1,
+                    ..
+                },
+                Value::Error {
+                    error_type: t2,
+                    message: m2,
+                    fields: f2,
+                    ..
+                },
+            ) => t1 == t2 && m1 == m2 && f1 == f2,
+            (
+                Value::NativeFunction {
+                    module: m1,
+                    function: f1,
+                },
+                Value::NativeFunction {
+                    module: m2,
+                    function: f2,
+                },
+            ) => m1 == m2 && f1 == f2,
+            (
+                Value::CompiledFunction { entry: e1, .. },
+                Value::CompiledFunction { entry: e2, .. },
+            ) => e1 == e2,
+            // Promises are equal only if they are the same instance
+            (Value::Promise { id: a, .. }, Value::Promise { id: b, .. }) => a == b,
             _ => false,
         }
     }
@@ -65,13 +291,22 @@ impl Value {
     pub fn type_name(&self) -> &'static str {
         match self {
             Value::Number(_) => "number",
+            Value::BigInt(_) => "bigint",
             Value::String(_) => "string",
             Value::Boolean(_) => "boolean",
             Value::Null => "null",
             Value::Array(_) => "array",
+            Value::Tuple(_) => "tuple",
             Value::Object(_) => "object",
-            Value::Function { .. } => "function",
+            Value::Bytes(_) => "bytes",
+            Value::Enum(_) | Value::EnumMember { .. } => "enum",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Function { .. }
+            | Value::NativeFunction { .. }
+            | Value::CompiledFunction { .. } => "function",
             Value::Promise { .. } => "promise",
+            Value::Error { .. } => "error",
         }
     }
 
@@ -80,11 +315,20 @@ impl Value {
             Value::Boolean(b) => *b,
             Value::Null => false,
             Value::Number(n) => *n != 0.0,
+            Value::BigInt(n) => !n.is_zero(),
             Value::String(s) => !s.is_empty(),
             Value::Array(arr) => !arr.is_empty(),
+            Value::Tuple(_) => true,
             Value::Object(obj) => !obj.is_empty(),
-            Value::Function { .. } => true, // Functions are always truthy
+            Value::Bytes(bytes) => !bytes.is_empty(),
+            Value::Enum(_) | Value::EnumMember { .. } => true,
+            Value::Class(_) | Value::Instance(_) => true,
+            // Functions are always truthy
+            Value::Function { .. }
+            | Value::NativeFunction { .. }
+            | Value::CompiledFunction { .. } => true,
             Value::Promise { resolved, .. } => *resolved, // Promises are truthy if resolved
+            Value::Error { .. } => true,
         }
     }
 
@@ -107,6 +351,49 @@ impl Value {
         }
     }
 
+    /// Equality as the `==` operator and `match` see it: numbers within
+    /// `f64::EPSILON` of each other are equal, a bigint equals a number of
+    /// the same value, and arrays and tuples compare element by element.
+    /// Objects are equal when they hold the same keys with equal values, in
+    /// any order. An instance equals only itself, and functions are never
+    /// equal.
+    pub fn script_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => (l - r).abs() < f64::EPSILON,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Bytes(l), Value::Bytes(r)) => l == r,
+            (Value::EnumMember { .. }, Value::EnumMember { .. }) => self == other,
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::Null, Value::Null) => true,
+            (Value::Array(l), Value::Array(r)) => elements_eq(l, r),
+            (Value::Tuple(l), Value::Tuple(r)) => elements_eq(l, r),
+            (Value::Object(l), Value::Object(r)) => {
+                l.len() == r.len()
+                    && l.iter()
+                        .all(|(key, value)| r.get(key).is_some_and(|other| value.script_eq(other)))
+            }
+            (Value::BigInt(_), _) | (_, Value::BigInt(_)) => {
+                self.numeric_cmp(other) == Some(Ordering::Equal)
+            }
+            _ => false,
+        }
+    }
+
+    /// Compare two numeric values exactly. A bigint and a float compare by
+    /// their mathematical values, so no precision is lost above 2^53.
+    pub fn numeric_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::BigInt(a), Value::BigInt(b)) => Some(a.cmp(b)),
+            (Value::BigInt(a), Value::Number(b)) => compare_bigint_float(a, *b),
+            (Value::Number(a), Value::BigInt(b)) => {
+                compare_bigint_float(b, *a).map(Ordering::reverse)
+            }
+            _ => None,
+        }
+    }
+
     pub fn as_string(&self) -> Option<&String> {
         match self {
             Value::String(s) => Some(s),
@@ -139,29 +426,248 @@ impl Value {
         }
     }
 
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// The member's name, e.g. `active` for `Status.active`
+    pub fn enum_member_name(&self) -> Option<&str> {
+        match self {
+            Value::EnumMember { definition, index } => Some(&definition.members[*index]),
+            _ => None,
+        }
+    }
+
     pub fn is_object(&self) -> bool {
         matches!(self, Value::Object(_))
     }
 
-    pub fn as_object(&self) -> Option<&HashMap<String, Value>> {
+    pub fn as_object(&self) -> Option<&ObjectMap> {
         match self {
             Value::Object(obj) => Some(obj),
             _ => None,
         }
     }
 
-    pub fn as_object_mut(&mut self) -> Option<&mut HashMap<String, Value>> {
+    pub fn as_object_mut(&mut self) -> Option<&mut ObjectMap> {
         match self {
             Value::Object(obj) => Some(obj),
             _ => None,
         }
     }
+
+    /// Serialize to canonical JSON (object keys sorted, so equal objects
+    /// serialize the same whatever their key order), or `None` for values
+    /// that have no stable serialization such as functions and promises
+    pub fn to_canonical_string(&self) -> Option<String> {
+        let mut out = String::new();
+        self.write_json(&mut out, false, true)?;
+        Some(out)
+    }
+
+    /// Serialize to compact JSON with object keys in their order. Bigints are
+    /// written as strings so other tools cannot round them, unless
+    /// `raw_bigints` is set.
+    pub fn to_json_string(&self, raw_bigints: bool) -> Option<String> {
+        let mut out = String::new();
+        self.write_json(&mut out, raw_bigints, false)?;
+        Some(out)
+    }
+
+    fn write_json(&self, out: &mut String, raw_bigints: bool, sort_keys: bool) -> Option<()> {
+        match self {
+            Value::Number(n) => out.push_str(&n.to_string()),
+            Value::BigInt(n) if raw_bigints => out.push_str(&n.to_string()),
+            Value::BigInt(n) => write_json_string(&n.to_string(), out),
+            Value::String(s) => write_json_string(s, out),
+            Value::Boolean(b) => out.push_str(&b.to_string()),
+            Value::Null => out.push_str("null"),
+            // Members are written by name; Status.parse() reads them back
+            Value::EnumMember { definition, index } => {
+                write_json_string(&definition.members[*index], out)
+            }
+            Value::Array(items) => write_json_items(items, out, raw_bigints, sort_keys)?,
+            // JSON has no tuples; they are written as arrays
+            Value::Tuple(items) => write_json_items(items, out, raw_bigints, sort_keys)?,
+            Value::Object(obj) => write_json_fields(obj, out, raw_bigints, sort_keys)?,
+            // An instance is written as its fields
+            // An instance holding itself has no JSON form
+            Value::Instance(instance) => instance.writing(|| {
+                write_json_fields(&instance.fields.borrow(), out, raw_bigints, sort_keys)
+            })??,
+            Value::Error {
+                error_type,
+                message,
+                fields,
+                ..
+            } => {
+                out.push_str("{\"fields\":");
+                Value::Object(fields.clone()).write_json(out, raw_bigints, sort_keys)?;
+                out.push_str(",\"message\":");
+                write_json_string(message, out);
+                out.push_str(",\"type\":");
+                write_json_string(error_type, out);
+                out.push('}');
+            }
+            // Bytes have no JSON form; encode them with bytes.to_base64 first
+            Value::Bytes(_)
+            | Value::Enum(_)
+            | Value::Class(_)
+            | Value::Function { .. }
+            | Value::NativeFunction { .. }
+            | Value::CompiledFunction { .. }
+            | Value::Promise { .. } => return None,
+        }
+        Some(())
+    }
+}
+
+fn write_json_items(
+    items: &[Value],
+    out: &mut String,
+    raw_bigints: bool,
+    sort_keys: bool,
+) -> Option<()> {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        item.write_json(out, raw_bigints, sort_keys)?;
+    }
+    out.push(']');
+    Some(())
+}
+
+fn write_json_fields(
+    obj: &ObjectMap,
+    out: &mut String,
+    raw_bigints: bool,
+    sort_keys: bool,
+) -> Option<()> {
+    let mut keys: Vec<&String> = obj.keys().collect();
+    if sort_keys {
+        keys.sort();
+    }
+    out.push('{');
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_json_string(key, out);
+        out.push(':');
+        obj[key].write_json(out, raw_bigints, sort_keys)?;
+    }
+    out.push('}');
+    Some(())
+}
+
+/// Element-by-element `==` of two arrays or tuples
+fn elements_eq(left: &[Value], right: &[Value]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.script_eq(r))
+}
+
+fn compare_bigint_float(a: &BigInt, b: f64) -> Option<Ordering> {
+    if b.is_nan() {
+        return None;
+    }
+    if b.is_infinite() {
+        return Some(if b > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        });
+    }
+
+    // Every finite float's floor is an integer that BigInt holds exactly
+    let floor = b.floor();
+    match a.cmp(&BigInt::from_f64(floor)?) {
+        Ordering::Equal if floor < b => Some(Ordering::Less),
+        ordering => Some(ordering),
+    }
+}
+
+fn bigint_mixing_error(
+    operator: &str,
+    context: &str,
+    a: &Value,
+    b: &Value,
+) -> crate::core::error::InfraError {
+    crate::core::error::InfraError::TypeError {
+        expected: "matching numeric types".to_string(),
+        found: format!("{} {} {}", a.type_name(), operator, b.type_name()),
+        context: Some(context.to_string()),
+        line: None,
+        column: None,
+        hint: Some("Convert explicitly with number.to_bigint() or bigint.to_number()".to_string()),
+    }
+}
+
+pub(crate) fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_fields(
+    f: &mut fmt::Formatter<'_>,
+    obj: &ObjectMap,
+    precision: Option<usize>,
+) -> fmt::Result {
+    write!(f, "{{")?;
+    let mut first = true;
+    for (key, value) in obj.iter() {
+        if !first {
+            write!(f, ", ")?;
+        }
+        write!(f, "\"{}\": {}", key, value.display(precision))?;
+        first = false;
+    }
+    write!(f, "}}")
+}
+
+impl Value {
+    /// The value as `print` shows it, with numbers rounded to at most
+    /// `precision` decimal places; Display is this with no rounding
+    pub fn display(&self, precision: Option<usize>) -> Displayed<'_> {
+        Displayed {
+            value: self,
+            precision,
+        }
+    }
+}
+
+/// A value written with its numbers rounded, from [`Value::display`]
+pub struct Displayed<'a> {
+    value: &'a Value,
+    precision: Option<usize>,
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Value::Number(n) => write!(f, "{}", n),
+        self.display(None).fmt(f)
+    }
+}
+
+impl fmt::Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = self.precision;
+        match self.value {
+            Value::Number(n) => write!(f, "{}", format_number(*n, precision)),
+            Value::BigInt(n) => write!(f, "{}", n),
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Null => write!(f, "null"),
@@ -171,25 +677,55 @@ impl fmt::Display for Value {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{}", item)?;
+                    write!(f, "{}", item.display(precision))?;
                 }
                 write!(f, "]")
             }
-            Value::Object(obj) => {
-                write!(f, "{{")?;
-                let mut first = true;
-                for (key, value) in obj.iter() {
-                    if !first {
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "\"{}\": {}", key, value)?;
-                    first = false;
+                    write!(f, "{}", item.display(precision))?;
+                }
+                // A one-element tuple keeps its comma, as it is written
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Object(obj) => write_fields(f, obj, precision),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => instance
+                .writing(|| {
+                    write!(f, "{} ", instance.class.name)?;
+                    write_fields(f, &instance.fields.borrow(), precision)
+                })
+                .unwrap_or_else(|| write!(f, "<cycle>")),
+            Value::Bytes(bytes) => {
+                write!(f, "<bytes {}: ", bytes.len())?;
+                for byte in bytes.iter().take(DISPLAY_BYTES) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if bytes.len() > DISPLAY_BYTES {
+                    write!(f, "...")?;
                 }
-                write!(f, "}}")
+                write!(f, ">")
+            }
+            Value::Enum(definition) => write!(f, "<enum {}>", definition.name),
+            Value::EnumMember { definition, index } => {
+                write!(f, "{}.{}", definition.name, definition.members[*index])
             }
             Value::Function { name, params, .. } => {
                 write!(f, "<function {}({})>", name, params.join(", "))
             }
+            Value::NativeFunction { module, function } => {
+                write!(f, "<native function {}.{}>", module, function)
+            }
+            Value::CompiledFunction { name, arity, .. } => {
+                write!(f, "<compiled function {}/{}>", name, arity)
+            }
             Value::Promise {
                 resolved,
                 rejected,
@@ -208,6 +744,11 @@ impl fmt::Display for Value {
                     write!(f, "<Promise pending>")
                 }
             }
+            Value::Error {
+                error_type,
+                message,
+                ..
+            } => write!(f, "<error {}: {}>", error_type, message),
         }
     }
 }
@@ -218,9 +759,14 @@ impl Add for Value {
     fn add(self, other: Value) -> Self::Output {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok(Value::BigInt(a + b)),
             (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
             (Value::String(a), b) => Ok(Value::String(format!("{}{}", a, b))),
             (a, Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (
+                a @ (Value::BigInt(_) | Value::Number(_)),
+                b @ (Value::BigInt(_) | Value::Number(_)),
+            ) => Err(bigint_mixing_error("+", "addition operation", &a, &b)),
             (a, b) => Err(crate::core::error::InfraError::TypeError {
                 expected: "number or string".to_string(),
                 found: format!("{} + {}", a.type_name(), b.type_name()),
@@ -239,6 +785,11 @@ impl Sub for Value {
     fn sub(self, other: Value) -> Self::Output {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok(Value::BigInt(a - b)),
+            (
+                a @ (Value::BigInt(_) | Value::Number(_)),
+                b @ (Value::BigInt(_) | Value::Number(_)),
+            ) => Err(bigint_mixing_error("-", "subtraction operation", &a, &b)),
             (a, b) => Err(crate::core::error::InfraError::TypeError {
                 expected: "number".to_string(),
                 found: format!("{} - {}", a.type_name(), b.type_name()),
@@ -257,6 +808,11 @@ impl Mul for Value {
     fn mul(self, other: Value) -> Self::Output {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok(Value::BigInt(a * b)),
+            (
+                a @ (Value::BigInt(_) | Value::Number(_)),
+                b @ (Value::BigInt(_) | Value::Number(_)),
+            ) => Err(bigint_mixing_error("*", "multiplication operation", &a, &b)),
             (a, b) => Err(crate::core::error::InfraError::TypeError {
                 expected: "number".to_string(),
                 found: format!("{} * {}", a.type_name(), b.type_name()),
@@ -284,6 +840,21 @@ impl Div for Value {
                     Ok(Value::Number(a / b))
                 }
             }
+            // Integer division truncates toward zero
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if b.is_zero() {
+                    Err(crate::core::error::InfraError::DivisionByZero {
+                        line: None,
+                        column: None,
+                    })
+                } else {
+                    Ok(Value::BigInt(a / b))
+                }
+            }
+            (
+                a @ (Value::BigInt(_) | Value::Number(_)),
+                b @ (Value::BigInt(_) | Value::Number(_)),
+            ) => Err(bigint_mixing_error("/", "division operation", &a, &b)),
             (a, b) => Err(crate::core::error::InfraError::TypeError {
                 expected: "number".to_string(),
                 found: format!("{} / {}", a.type_name(), b.type_name()),
@@ -311,6 +882,21 @@ impl Rem for Value {
                     Ok(Value::Number(a % b))
                 }
             }
+            // Integer division truncates toward zero
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if b.is_zero() {
+                    Err(crate::core::error::InfraError::DivisionByZero {
+                        line: None,
+                        column: None,
+                    })
+                } else {
+                    Ok(Value::BigInt(a % b))
+                }
+            }
+            (
+                a @ (Value::BigInt(_) | Value::Number(_)),
+                b @ (Value::BigInt(_) | Value::Number(_)),
+            ) => Err(bigint_mixing_error("%", "modulo operation", &a, &b)),
             (a, b) => Err(crate::core::error::InfraError::TypeError {
                 expected: "number".to_string(),
                 found: format!("{} % {}", a.type_name(), b.type_name()),
@@ -0,0 +1,293 @@
+//! Binary encoding of Values for passing them between processes.
+//!
+//! A message is a version byte followed by one encoded value:
+//!
+//! ```text
+//! 0x00 null
+//! 0x01 false
+//! 0x02 true
+//! 0x03 number  f64 bits, little endian (NaN payloads and -0.0 survive)
+//! 0x04 string  length, UTF-8 bytes
+//! 0x05 array   count, values
+//! 0x06 object  count, (key length, key UTF-8 bytes, value) in the object's key order
+//! 0x07 bigint  sign (0 = non-negative, 1 = negative), length, magnitude bytes little endian
+//! 0x08 bytes   length, raw bytes
+//! 0x09 tuple   count, values
+//! ```
+//!
+//! Lengths and counts are unsigned LEB128. Functions, promises and error
+//! values cannot be encoded.
+
+use crate::core::{ObjectMap, Value};
+use num_bigint::{BigInt, Sign};
+use std::fmt;
+use std::rc::Rc;
+
+pub const WIRE_VERSION: u8 = 1;
+
+/// Deeper nesting is rejected so hostile input cannot overflow the stack
+const MAX_DEPTH: usize = 512;
+
+const TAG_NULL: u8 = 0x00;
+const TAG_FALSE: u8 = 0x01;
+const TAG_TRUE: u8 = 0x02;
+const TAG_NUMBER: u8 = 0x03;
+const TAG_STRING: u8 = 0x04;
+const TAG_ARRAY: u8 = 0x05;
+const TAG_OBJECT: u8 = 0x06;
+const TAG_BIGINT: u8 = 0x07;
+const TAG_BYTES: u8 = 0x08;
+const TAG_TUPLE: u8 = 0x09;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireError {
+    /// The value (or one nested in it) has no encoding
+    Unsupported(&'static str),
+    VersionMismatch {
+        found: u8,
+        expected: u8,
+    },
+    Truncated,
+    UnknownTag(u8),
+    InvalidUtf8,
+    TrailingBytes,
+    TooDeep,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Unsupported(type_name) => {
+                write!(f, "{} values cannot be serialized", type_name)
+            }
+            WireError::VersionMismatch { found, expected } => write!(
+                f,
+                "unsupported message version {} (expected {})",
+                found, expected
+            ),
+            WireError::Truncated => write!(f, "message is truncated"),
+            WireError::UnknownTag(tag) => write!(f, "unknown value tag 0x{:02x}", tag),
+            WireError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            WireError::TrailingBytes => write!(f, "unexpected bytes after the value"),
+            WireError::TooDeep => {
+                write!(f, "values are nested more than {} levels deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl Value {
+    /// Encode this value in the versioned wire format
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WireError> {
+        let mut out = vec![WIRE_VERSION];
+        encode(self, &mut out, 0)?;
+        Ok(out)
+    }
+
+    /// Decode a message produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Value, WireError> {
+        let (&version, rest) = bytes.split_first().ok_or(WireError::Truncated)?;
+        if version != WIRE_VERSION {
+            return Err(WireError::VersionMismatch {
+                found: version,
+                expected: WIRE_VERSION,
+            });
+        }
+
+        let mut reader = Reader {
+            bytes: rest,
+            pos: 0,
+        };
+        let value = reader.value(0)?;
+        if reader.pos != rest.len() {
+            return Err(WireError::TrailingBytes);
+        }
+        Ok(value)
+    }
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>, depth: usize) -> Result<(), WireError> {
+    if depth > MAX_DEPTH {
+        return Err(WireError::TooDeep);
+    }
+
+    match value {
+        Value::Null => out.push(TAG_NULL),
+        Value::Boolean(false) => out.push(TAG_FALSE),
+        Value::Boolean(true) => out.push(TAG_TRUE),
+        Value::Number(n) => {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&n.to_bits().to_le_bytes());
+        }
+        Value::String(s) => {
+            out.push(TAG_STRING);
+            write_str(s, out);
+        }
+        Value::Array(items) => {
+            out.push(TAG_ARRAY);
+            write_len(items.len(), out);
+            for item in items {
+                encode(item, out, depth + 1)?;
+            }
+        }
+        Value::Tuple(items) => {
+            out.push(TAG_TUPLE);
+            write_len(items.len(), out);
+            for item in items.iter() {
+                encode(item, out, depth + 1)?;
+            }
+        }
+        Value::Object(fields) => {
+            out.push(TAG_OBJECT);
+            write_len(fields.len(), out);
+            // The decoded object keeps this order
+            for (key, value) in fields {
+                write_str(key, out);
+                encode(value, out, depth + 1)?;
+            }
+        }
+        Value::Bytes(bytes) => {
+            out.push(TAG_BYTES);
+            write_len(bytes.len(), out);
+            out.extend_from_slice(bytes);
+        }
+        Value::BigInt(n) => {
+            out.push(TAG_BIGINT);
+            let (sign, magnitude) = n.to_bytes_le();
+            out.push(u8::from(sign == Sign::Minus));
+            write_len(magnitude.len(), out);
+            out.extend_from_slice(&magnitude);
+        }
+        other => return Err(WireError::Unsupported(other.type_name())),
+    }
+
+    Ok(())
+}
+
+fn write_len(mut len: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_str(s: &str, out: &mut Vec<u8>) {
+    write_len(s.len(), out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn value(&mut self, depth: usize) -> Result<Value, WireError> {
+        if depth > MAX_DEPTH {
+            return Err(WireError::TooDeep);
+        }
+
+        match self.byte()? {
+            TAG_NULL => Ok(Value::Null),
+            TAG_FALSE => Ok(Value::Boolean(false)),
+            TAG_TRUE => Ok(Value::Boolean(true)),
+            TAG_NUMBER => {
+                let bits = self.take(8)?.try_into().expect("took 8 bytes");
+                Ok(Value::Number(f64::from_bits(u64::from_le_bytes(bits))))
+            }
+            TAG_STRING => self.string().map(Value::String),
+            TAG_ARRAY => {
+                let count = self.count()?;
+                let mut items = Vec::with_capacity(count);
+                for _ in 0..count {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(items))
+            }
+            TAG_TUPLE => {
+                let count = self.count()?;
+                let mut items = Vec::with_capacity(count);
+                for _ in 0..count {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Tuple(items.into()))
+            }
+            TAG_OBJECT => {
+                let count = self.count()?;
+                let mut fields = ObjectMap::new();
+                for _ in 0..count {
+                    let key = self.string()?;
+                    fields.insert(key, self.value(depth + 1)?);
+                }
+                Ok(Value::Object(fields))
+            }
+            TAG_BIGINT => {
+                let sign = match self.byte()? {
+                    0 => Sign::Plus,
+                    1 => Sign::Minus,
+                    _ => return Err(WireError::UnknownTag(TAG_BIGINT)),
+                };
+                let len = self.len()?;
+                let magnitude = self.take(len)?;
+                Ok(Value::BigInt(BigInt::from_bytes_le(sign, magnitude)))
+            }
+            TAG_BYTES => {
+                let len = self.len()?;
+                Ok(Value::Bytes(Rc::new(self.take(len)?.to_vec())))
+            }
+            tag => Err(WireError::UnknownTag(tag)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, WireError> {
+        let byte = *self.bytes.get(self.pos).ok_or(WireError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        let end = self.pos.checked_add(len).ok_or(WireError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(WireError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn len(&mut self) -> Result<usize, WireError> {
+        let mut len: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= usize::BITS {
+                return Err(WireError::Truncated);
+            }
+            len |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(len);
+            }
+            shift += 7;
+        }
+    }
+
+    /// An element count; every element takes at least one byte, which bounds
+    /// the allocation for a corrupt count
+    fn count(&mut self) -> Result<usize, WireError> {
+        let count = self.len()?;
+        if count > self.bytes.len() - self.pos {
+            return Err(WireError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, WireError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| WireError::InvalidUtf8)
+    }
+}
@@ -0,0 +1,201 @@
+//! Source formatting, for `infra fmt` and the language server: the printer's
+//! canonical layout with the comments and blank lines of the source put back.
+//!
+//! The lexer keeps the comments, and the parser records anchors: the line
+//! each statement, class member, match arm and item of a list or object
+//! literal spread over several lines starts on, and the line of each closing
+//! brace or bracket. As the printer reaches an anchor, the comments above its
+//! line go on lines of their own before it, and those beside it end its first
+//! line. A literal the printer puts on one line passes over its anchors, and
+//! any other comment inside a statement that spans several lines goes on its
+//! own line after the statement. One blank line is kept wherever the source
+//! had any, and one is put around each top-level function and class, after
+//! any comments that continue a comment beside code on the lines under it.
+
+use crate::core::{Anchor, Program, Result};
+use crate::frontend::printer::{self, FormattingOptions};
+use crate::frontend::{Comment, Lexer, Parser};
+
+/// Format a whole source file
+pub fn format_source(source: &str, options: &FormattingOptions) -> Result<String> {
+    let (program, comments) = parse(source)?;
+    let trivia = Trivia::new(source, comments, &program.anchors);
+    Ok(printer::format_program_with(&program, options, trivia))
+}
+
+/// Format the top-level statements that the 1-based lines `first..=last`
+/// touch. Each statement takes the lines after it up to the next one, and
+/// the first one the lines above it as well. Returns the first and last line
+/// replaced and their new text, or `None` if the lines touch no statement.
+#[allow(dead_code)]
+pub fn format_lines(
+    source: &str,
+    first: usize,
+    last: usize,
+    options: &FormattingOptions,
+) -> Result<Option<(usize, usize, String)>> {
+    let (program, _) = parse(source)?;
+    let lines: Vec<&str> = source.lines().collect();
+    let starts: Vec<usize> = (0..program.statements.len())
+        .filter_map(|index| program.line_of(index))
+        .collect();
+
+    let mut touched = starts.iter().enumerate().filter_map(|(index, &start)| {
+        let next = starts.get(index + 1).copied().unwrap_or(lines.len() + 1);
+        let mut end = next - 1;
+        while end > start && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        let start = if index == 0 { 1 } else { start };
+        (start <= last && end >= first).then_some((start, end))
+    });
+    let Some((start, mut end)) = touched.next() else {
+        return Ok(None);
+    };
+    if let Some((_, last_end)) = touched.next_back() {
+        end = last_end;
+    }
+
+    let text = lines[start - 1..end].join("\n");
+    Ok(Some((start, end, format_source(&text, options)?)))
+}
+
+fn parse(source: &str) -> Result<(Program, Vec<Comment<'_>>)> {
+    let (tokens, comments) = Lexer::new(source).tokenize_with_comments()?;
+    let program = Parser::new(tokens).parse()?;
+    Ok((program, comments))
+}
+
+/// The comments and blank lines of a source, handed out as the printer
+/// reaches the anchors of its program
+pub(crate) struct Trivia<'a> {
+    lines: Vec<&'a str>,
+    comments: Vec<Comment<'a>>,
+    next_comment: usize,
+    anchors: &'a [Anchor],
+    next_anchor: usize,
+    /// Nothing has been written since a `{` or a header, or at all, so no
+    /// blank line goes next
+    opened: bool,
+    /// A blank line goes before whatever is written next
+    blank: bool,
+    /// The last source line of the comment just written beside code, and
+    /// of the comments on the lines right under it
+    trailing_line: Option<usize>,
+}
+
+impl<'a> Trivia<'a> {
+    pub(crate) fn new(source: &'a str, comments: Vec<Comment<'a>>, anchors: &'a [Anchor]) -> Self {
+        Self {
+            lines: source.lines().collect(),
+            comments,
+            next_comment: 0,
+            anchors,
+            next_anchor: 0,
+            opened: true,
+            blank: false,
+            trailing_line: None,
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<Anchor> {
+        self.anchors.get(self.next_anchor).copied()
+    }
+
+    /// Write the comments above the next anchor, indented by `indent`, and
+    /// a blank line before them and before what the anchor marks wherever
+    /// the source had one. Returns the comments beside the anchor, for the
+    /// end of the first line written for it.
+    pub(crate) fn anchor(&mut self, out: &mut String, indent: &str) -> Option<String> {
+        let anchor = self.peek()?;
+        self.next_anchor += 1;
+        let line = anchor.line();
+        self.comments_before(out, indent, line);
+        if matches!(anchor, Anchor::Close(_) | Anchor::Bracket(_)) {
+            self.blank = false;
+        } else {
+            self.separate(out, line);
+        }
+        self.opened = false;
+        self.trailing_line = None;
+
+        // A comment after code goes with the last anchor on its line, so
+        // `if ready: start()  # now` keeps it beside `start()`. One beside
+        // a list's closing bracket goes on its own line after the statement.
+        if matches!(anchor, Anchor::Bracket(_))
+            || self.peek().is_some_and(|next| next.line() == line)
+        {
+            return None;
+        }
+        let mut beside = Vec::new();
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.line == line)
+        {
+            beside.push(comment.text.trim_end());
+            self.next_comment += 1;
+        }
+        if !beside.is_empty() {
+            self.trailing_line = Some(line);
+        }
+        (!beside.is_empty()).then(|| beside.join(" "))
+    }
+
+    /// Pass over `count` anchors the printer laid out on one line, leaving
+    /// their comments for the next anchor
+    pub(crate) fn skip(&mut self, count: usize) {
+        self.next_anchor += count;
+    }
+
+    /// After a `{` or a header
+    pub(crate) fn open(&mut self) {
+        self.opened = true;
+    }
+
+    /// Put a blank line before whatever is written next, unless it opens
+    /// a block or the file
+    pub(crate) fn separate_next(&mut self) {
+        self.blank = true;
+    }
+
+    /// Write the comments after the last anchor
+    pub(crate) fn finish(&mut self, out: &mut String) {
+        self.comments_before(out, "", usize::MAX);
+    }
+
+    fn comments_before(&mut self, out: &mut String, indent: &str, line: usize) {
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.line < line)
+            .cloned()
+        {
+            // A comment on the line under a trailing one continues it, so
+            // a blank line due before what follows goes after it instead
+            if self.trailing_line == Some(comment.line - 1) {
+                self.trailing_line = Some(comment.line + comment.text.matches('\n').count());
+            } else {
+                self.trailing_line = None;
+                self.separate(out, comment.line);
+            }
+            out.push_str(indent);
+            out.push_str(comment.text.trim_end());
+            out.push('\n');
+            self.next_comment += 1;
+        }
+    }
+
+    /// Write a blank line before something from `line` if one is due
+    fn separate(&mut self, out: &mut String, line: usize) {
+        let blank_above = line
+            .checked_sub(2)
+            .and_then(|above| self.lines.get(above))
+            .is_some_and(|above| above.trim().is_empty());
+        if (self.blank || blank_above) && !self.opened && !out.is_empty() {
+            out.push('\n');
+        }
+        self.blank = false;
+        self.opened = false;
+    }
+}
@@ -1,34 +1,54 @@
 use crate::core::{InfraError, Result};
-use crate::frontend::{Token, TokenType};
+use crate::frontend::{Comment, StringPart, Token, TokenType};
+use std::borrow::Cow;
 
-pub struct Lexer {
-    input: Vec<char>,
+/// Lexer over a borrowed source string. Positions are byte offsets; token
+/// payloads borrow from the source unless they need processing (escapes).
+pub struct Lexer<'src> {
+    input: &'src str,
+    bytes: &'src [u8],
     position: usize,
     line: usize,
     column: usize,
     start_position: usize,
     start_line: usize,
     start_column: usize,
+    /// The comments skipped so far, when they are kept
+    comments: Option<Vec<Comment<'src>>>,
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
+impl<'src> Lexer<'src> {
+    pub fn new(input: &'src str) -> Self {
         Self {
-            input: input.chars().collect(),
+            input,
+            bytes: input.as_bytes(),
             position: 0,
             line: 1,
             column: 1,
             start_position: 0,
             start_line: 1,
             start_column: 1,
+            comments: None,
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>> {
+    /// A lexer over a piece of a larger source that starts at `line` and
+    /// `column` of it, so its tokens have positions in the whole
+    pub fn starting_at(input: &'src str, line: usize, column: usize) -> Self {
+        Self {
+            line,
+            column,
+            start_line: line,
+            start_column: column,
+            ..Self::new(input)
+        }
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<Token<'src>>> {
         let mut tokens = Vec::new();
 
         while !self.is_at_end() {
-            self.skip_whitespace();
+            self.skip_whitespace()?;
 
             if self.is_at_end() {
                 break;
@@ -43,13 +63,31 @@ impl Lexer {
         Ok(tokens)
     }
 
+    /// Tokenize, keeping the comments the tokens leave out
+    pub fn tokenize_with_comments(&mut self) -> Result<(Vec<Token<'src>>, Vec<Comment<'src>>)> {
+        self.comments = Some(Vec::new());
+        let tokens = self.tokenize()?;
+        Ok((tokens, self.comments.take().unwrap_or_default()))
+    }
+
+    /// Tokenize into tokens that own their text, for callers that need to keep
+    /// tokens around after the source is gone
+    #[allow(dead_code)]
+    pub fn tokenize_owned(&mut self) -> Result<Vec<Token<'static>>> {
+        Ok(self
+            .tokenize()?
+            .into_iter()
+            .map(Token::into_owned)
+            .collect())
+    }
+
     fn start_token(&mut self) {
         self.start_position = self.position;
         self.start_line = self.line;
         self.start_column = self.column;
     }
 
-    fn next_token(&mut self) -> Result<Token> {
+    fn next_token(&mut self) -> Result<Token<'src>> {
         let c = self.advance();
 
         let token_type = match c {
@@ -68,6 +106,7 @@ impl Lexer {
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    self.keep_comment();
                     // Return the next token after the comment
                     return self.next_token();
                 } else {
@@ -84,7 +123,13 @@ impl Lexer {
             ',' => TokenType::Comma,
             ':' => TokenType::Colon,
             ';' => TokenType::Semicolon,
+            '.' if self.peek() == '.' && self.peek_next() == '.' => {
+                self.advance();
+                self.advance();
+                TokenType::Ellipsis
+            }
             '.' => TokenType::Dot,
+            '@' => TokenType::At,
             '\n' => {
                 self.line += 1;
                 self.column = 1;
@@ -132,17 +177,50 @@ impl Lexer {
                     TokenType::Pipe // Single pipe for union types
                 }
             }
+            '?' => {
+                if self.match_char('?') {
+                    TokenType::QuestionQuestion
+                } else if self.match_char('.') {
+                    TokenType::QuestionDot
+                } else {
+                    TokenType::Question
+                }
+            }
+            '"' if self.peek() == '"' && self.peek_next() == '"' => self.raw_string()?,
             '"' => self.string()?,
-            _ if c.is_ascii_digit() => self.number(c)?,
-            _ if c.is_alphabetic() || c == '_' => self.identifier(c)?,
+            _ if c.is_ascii_digit() => self.number()?,
+            _ if c.is_alphabetic() || c == '_' => self.identifier()?,
             _ => return self.error(&format!("Unexpected character '{}'", c)),
         };
 
         Ok(self.make_token(token_type))
     }
 
-    fn string(&mut self) -> Result<TokenType> {
-        let mut value = String::new();
+    fn string(&mut self) -> Result<TokenType<'src>> {
+        let content_start = self.position;
+
+        // Fast path: no escapes or interpolation, so the literal borrows
+        // straight from the source
+        while !self.is_at_end() {
+            match self.bytes[self.position] {
+                b'"' => {
+                    let value = &self.input[content_start..self.position];
+                    self.advance(); // Consume closing "
+                    return Ok(TokenType::String(Cow::Borrowed(value)));
+                }
+                b'\\' => break,
+                b'$' if self.peek_next() == '{' => break,
+                _ => {
+                    if self.advance() == '\n' {
+                        self.line += 1;
+                        self.column = 1;
+                    }
+                }
+            }
+        }
+
+        let mut parts = Vec::new();
+        let mut value = self.input[content_start..self.position].to_string();
 
         while !self.is_at_end() && self.peek() != '"' {
             let c = self.advance();
@@ -152,18 +230,27 @@ impl Lexer {
             }
             // Handle escape sequences
             if c == '\\' && !self.is_at_end() {
-                let escaped = self.advance();
-                match escaped {
-                    'n' => value.push('\n'),
-                    't' => value.push('\t'),
-                    'r' => value.push('\r'),
-                    '\\' => value.push('\\'),
-                    '"' => value.push('"'),
-                    _ => {
-                        value.push('\\');
-                        value.push(escaped);
-                    }
+                value.push(self.escape()?);
+            } else if c == '$' && self.peek() == '{' {
+                let (line, column) = (self.line, self.column - 1);
+                self.advance(); // Consume {
+                let (code_start, code_line, code_column) = (self.position, self.line, self.column);
+                if !self.skip_interpolation() {
+                    return Err(InfraError::LexError {
+                        message: "Unterminated '${' in string".to_string(),
+                        line,
+                        column,
+                        source_code: None,
+                    });
                 }
+                if !value.is_empty() {
+                    parts.push(StringPart::Text(Cow::Owned(std::mem::take(&mut value))));
+                }
+                parts.push(StringPart::Code {
+                    source: Cow::Borrowed(&self.input[code_start..self.position - 1]),
+                    line: code_line,
+                    column: code_column,
+                });
             } else {
                 value.push(c);
             }
@@ -174,23 +261,175 @@ impl Lexer {
         }
 
         self.advance(); // Consume closing "
-        Ok(TokenType::String(value))
+        if parts.is_empty() {
+            return Ok(TokenType::String(Cow::Owned(value)));
+        }
+        if !value.is_empty() {
+            parts.push(StringPart::Text(Cow::Owned(value)));
+        }
+        Ok(TokenType::InterpolatedString(parts))
+    }
+
+    /// The character an escape stands for, with the backslash just consumed.
+    /// An escape that means nothing is an error at the backslash.
+    fn escape(&mut self) -> Result<char> {
+        let (line, column) = (self.line, self.column - 1);
+        let invalid = |message: String| {
+            Err(InfraError::LexError {
+                message,
+                line,
+                column,
+                source_code: None,
+            })
+        };
+        let escaped = self.advance();
+        match escaped {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'x' => {
+                let digits = self.hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => Ok(byte as char),
+                    Ok(_) if digits.len() == 2 => invalid(format!(
+                        "Escape '\\x{}' is not ASCII; use '\\u{{{}}}' for other characters",
+                        digits, digits
+                    )),
+                    _ => invalid("Expected two hex digits after '\\x'".to_string()),
+                }
+            }
+            'u' => {
+                if !self.match_char('{') {
+                    return invalid("Expected '{' after '\\u', as in '\\u{1F600}'".to_string());
+                }
+                let digits = self.hex_digits(6);
+                if digits.is_empty() || !self.match_char('}') {
+                    return invalid("Expected 1 to 6 hex digits and '}' in '\\u{...}'".to_string());
+                }
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(c) => Ok(c),
+                    None => invalid(format!("'\\u{{{}}}' is not a Unicode character", digits)),
+                }
+            }
+            '\n' => invalid("Unknown escape sequence '\\' at the end of a line".to_string()),
+            other => invalid(format!("Unknown escape sequence '\\{}'", other)),
+        }
+    }
+
+    /// Up to `max` hex digits, consumed
+    fn hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+        digits
+    }
+
+    /// `"""..."""`: everything up to the closing quotes, newlines included,
+    /// with no escapes or interpolation
+    fn raw_string(&mut self) -> Result<TokenType<'src>> {
+        self.advance();
+        self.advance(); // Consume the rest of the opening quotes
+        let content_start = self.position;
+        while !self.is_at_end() {
+            if self.input[self.position..].starts_with("\"\"\"") {
+                let value = &self.input[content_start..self.position];
+                for _ in 0..3 {
+                    self.advance();
+                }
+                return Ok(TokenType::String(Cow::Borrowed(value)));
+            }
+            if self.advance() == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+        self.error("Unterminated '\"\"\"' string")
     }
 
-    fn number(&mut self, first_digit: char) -> Result<TokenType> {
-        let mut value = String::new();
-        value.push(first_digit);
+    /// Move past the `}` that closes an interpolation, over nested braces and
+    /// string literals; false when the source ends first
+    fn skip_interpolation(&mut self) -> bool {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.advance() {
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return true,
+                '}' => depth -= 1,
+                '"' if !self.skip_string_literal() => return false,
+                _ => {}
+            }
+        }
+        false
+    }
 
+    /// Move past the rest of a string literal inside an interpolation,
+    /// including any interpolations of its own
+    fn skip_string_literal(&mut self) -> bool {
+        while !self.is_at_end() {
+            match self.advance() {
+                '"' => return true,
+                '\\' if !self.is_at_end() => {
+                    let escaped = self.advance();
+                    if escaped == '\n' {
+                        self.line += 1;
+                        self.column = 1;
+                    }
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    if !self.skip_interpolation() {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn number(&mut self) -> Result<TokenType<'src>> {
         while !self.is_at_end() && self.peek().is_ascii_digit() {
-            value.push(self.advance());
+            self.advance();
         }
 
         // Handle decimal point
+        let mut is_integer = true;
         if !self.is_at_end() && self.peek() == '.' && self.peek_next().is_ascii_digit() {
-            value.push(self.advance()); // consume '.'
+            is_integer = false;
+            self.advance(); // consume '.'
             while !self.is_at_end() && self.peek().is_ascii_digit() {
-                value.push(self.advance());
+                self.advance();
+            }
+        }
+
+        let value = &self.input[self.start_position..self.position];
+
+        // An `n` suffix makes a bigint literal, e.g. 123n
+        if !self.is_at_end()
+            && self.peek() == 'n'
+            && !(self.peek_next().is_alphanumeric() || self.peek_next() == '_')
+        {
+            self.advance(); // consume 'n'
+            if !is_integer {
+                return self.error(&format!("BigInt literal must be an integer: {}n", value));
             }
+            return Ok(TokenType::BigInt(Cow::Borrowed(value)));
         }
 
         match value.parse::<f64>() {
@@ -199,72 +438,41 @@ impl Lexer {
         }
     }
 
-    fn identifier(&mut self, first_char: char) -> Result<TokenType> {
-        let mut value = String::new();
-        value.push(first_char);
-
+    fn identifier(&mut self) -> Result<TokenType<'src>> {
         while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_') {
-            value.push(self.advance());
+            self.advance();
         }
 
-        let token_type = match value.as_str() {
-            "let" => TokenType::Let,
-            "if" => TokenType::If,
-            "else" => TokenType::Else,
-            "while" => TokenType::While,
-            "for" => TokenType::For,
-            "in" => TokenType::In,
-            "range" => TokenType::Range,
-            "true" => TokenType::True,
-            "false" => TokenType::False,
-            "null" => TokenType::Null,
-            "print" => TokenType::Print,
-            "return" => TokenType::Return,
-            "function" => TokenType::Function,
-            "def" => TokenType::Def,
-            "try" => TokenType::Try,
-            "catch" => TokenType::Catch,
-            "import" => TokenType::Import,
-            "export" => TokenType::Export,
-            "from" => TokenType::From,
-            "as" => TokenType::As,
-            "async" => TokenType::Async,
-            "await" => TokenType::Await,
-            "class" => TokenType::Class,
-            "extends" => TokenType::Extends,
-            "this" => TokenType::This,
-            "super" => TokenType::Super,
-            "init" => TokenType::Init,
-            "new" => TokenType::New,
-            // Type keywords
-            "number" => TokenType::NumberType,
-            "string" => TokenType::StringType,
-            "boolean" => TokenType::BooleanType,
-            _ => TokenType::Identifier(value),
-        };
-
-        Ok(token_type)
+        let value = &self.input[self.start_position..self.position];
+        Ok(keyword(value).unwrap_or(TokenType::Identifier(Cow::Borrowed(value))))
     }
 
-    fn skip_whitespace(&mut self) {
+    fn skip_whitespace(&mut self) -> Result<()> {
         while !self.is_at_end() {
             match self.peek() {
                 ' ' | '\r' | '\t' => {
                     self.advance();
                 }
                 '#' => {
-                    // Skip comments until newline
+                    // Skip comments until newline; this also skips a
+                    // `#!/usr/bin/env infra` line at the top of a script
+                    self.start_token();
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    self.keep_comment();
                 }
                 '/' => {
                     // Check for // style comments
-                    if self.position + 1 < self.input.len() && self.peek_next() == '/' {
+                    if self.peek_next() == '/' {
                         // Skip // comments until newline
+                        self.start_token();
                         while !self.is_at_end() && self.peek() != '\n' {
                             self.advance();
                         }
+                        self.keep_comment();
+                    } else if self.peek_next() == '*' {
+                        self.skip_block_comment()?;
                     } else {
                         break;
                     }
@@ -272,6 +480,44 @@ impl Lexer {
                 _ => break,
             }
         }
+        Ok(())
+    }
+
+    /// Skip a `/* ... */` comment, which may span lines and contain comments
+    /// of its own. An unclosed one is reported where it opens.
+    fn skip_block_comment(&mut self) -> Result<()> {
+        self.start_token();
+        let mut depth = 0;
+        while !self.is_at_end() {
+            if self.input[self.position..].starts_with("/*") {
+                self.advance();
+                self.advance();
+                depth += 1;
+            } else if self.input[self.position..].starts_with("*/") {
+                self.advance();
+                self.advance();
+                depth -= 1;
+                if depth == 0 {
+                    self.keep_comment();
+                    return Ok(());
+                }
+            } else if self.advance() == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+        self.error("Unterminated block comment")
+    }
+
+    /// Record the comment from the start of the current token to here
+    fn keep_comment(&mut self) {
+        if let Some(comments) = &mut self.comments {
+            comments.push(Comment {
+                text: &self.input[self.start_position..self.position],
+                line: self.start_line,
+                column: self.start_column,
+            });
+        }
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -284,37 +530,44 @@ impl Lexer {
     }
 
     fn advance(&mut self) -> char {
-        let c = self.input[self.position];
-        self.position += 1;
+        let c = self.peek();
+        self.position += c.len_utf8();
         self.column += 1;
         c
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.input[self.position]
+        match self.bytes.get(self.position) {
+            None => '\0',
+            Some(&b) if b.is_ascii() => b as char,
+            Some(_) => self.input[self.position..].chars().next().unwrap_or('\0'),
         }
     }
 
     fn peek_next(&self) -> char {
-        if self.position + 1 >= self.input.len() {
-            '\0'
-        } else {
-            self.input[self.position + 1]
+        if self.is_at_end() {
+            return '\0';
+        }
+        let next = self.position + self.peek().len_utf8();
+        match self.bytes.get(next) {
+            None => '\0',
+            Some(&b) if b.is_ascii() => b as char,
+            Some(_) => self.input[next..].chars().next().unwrap_or('\0'),
         }
     }
 
     fn is_at_end(&self) -> bool {
-        self.position >= self.input.len()
+        self.position >= self.bytes.len()
     }
 
-    fn make_token(&self, token_type: TokenType) -> Token {
-        let lexeme = self.input[self.start_position..self.position]
-            .iter()
-            .collect();
-        Token::new(token_type, self.start_line, self.start_column, lexeme)
+    fn make_token(&self, token_type: TokenType<'src>) -> Token<'src> {
+        let lexeme = &self.input[self.start_position..self.position];
+        Token::new(
+            token_type,
+            self.start_line,
+            self.start_column,
+            Cow::Borrowed(lexeme),
+        )
     }
 
     fn error<T>(&self, message: &str) -> Result<T> {
@@ -326,3 +579,45 @@ impl Lexer {
         })
     }
 }
+
+/// Keyword lookup on the identifier slice; no allocation either way
+fn keyword(word: &str) -> Option<TokenType<'static>> {
+    let token_type = match word {
+        "let" => TokenType::Let,
+        "if" => TokenType::If,
+        "else" => TokenType::Else,
+        "while" => TokenType::While,
+        "for" => TokenType::For,
+        "in" => TokenType::In,
+        "range" => TokenType::Range,
+        "true" => TokenType::True,
+        "false" => TokenType::False,
+        "null" => TokenType::Null,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "break" => TokenType::Break,
+        "continue" => TokenType::Continue,
+        "function" => TokenType::Function,
+        "def" => TokenType::Def,
+        "try" => TokenType::Try,
+        "catch" => TokenType::Catch,
+        "import" => TokenType::Import,
+        "export" => TokenType::Export,
+        "from" => TokenType::From,
+        "as" => TokenType::As,
+        "async" => TokenType::Async,
+        "await" => TokenType::Await,
+        "class" => TokenType::Class,
+        "extends" => TokenType::Extends,
+        "this" => TokenType::This,
+        "super" => TokenType::Super,
+        "init" => TokenType::Init,
+        "new" => TokenType::New,
+        // Type keywords
+        "number" => TokenType::NumberType,
+        "string" => TokenType::StringType,
+        "boolean" => TokenType::BooleanType,
+        _ => return None,
+    };
+    Some(token_type)
+}
@@ -1,6 +1,9 @@
+pub mod formatter;
 pub mod lexer;
 pub mod parser;
+pub mod printer;
 pub mod token;
+pub mod unused_imports;
 
 pub use lexer::*;
 pub use parser::*;
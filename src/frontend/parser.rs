@@ -1,14 +1,39 @@
 use crate::core::{ast::*, InfraError, Result, Value};
-use crate::frontend::{Token, TokenType};
-
-pub struct Parser {
-    tokens: Vec<Token>,
+use crate::frontend::{Lexer, StringPart as SourcePart, Token, TokenType};
+use std::rc::Rc;
+
+/// Parameter names, their optional types and the optional return type
+type FunctionSignature = (
+    Vec<String>,
+    Vec<Option<Type>>,
+    Rc<OptionalParams>,
+    Option<Type>,
+);
+
+pub struct Parser<'src> {
+    tokens: Vec<Token<'src>>,
     current: usize,
+    multiline_lists: Vec<bool>,
+    anchors: Vec<Anchor>,
+    positions: bool,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+impl<'src> Parser<'src> {
+    pub fn new(tokens: Vec<Token<'src>>) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            multiline_lists: Vec::new(),
+            anchors: Vec::new(),
+            positions: true,
+        }
+    }
+
+    /// Leave expressions without source positions, for code whose errors
+    /// should be reported where the script calls into it
+    pub fn without_positions(mut self) -> Self {
+        self.positions = false;
+        self
     }
 
     pub fn parse(&mut self) -> Result<Program> {
@@ -21,13 +46,17 @@ impl Parser {
                 continue;
             }
 
-            program.add_statement(self.statement()?);
+            let line = self.peek().line;
+            program.add_statement_at(self.statement()?, line);
         }
 
+        program.multiline_lists = std::mem::take(&mut self.multiline_lists);
+        program.anchors = std::mem::take(&mut self.anchors);
         Ok(program)
     }
 
     fn statement(&mut self) -> Result<Stmt> {
+        self.anchor(Anchor::Statement);
         match &self.peek().token_type {
             TokenType::Let => self.let_statement(),
             TokenType::If => self.if_statement(),
@@ -36,12 +65,29 @@ impl Parser {
             TokenType::Print => self.print_statement(),
             TokenType::LeftBrace => self.block_statement(),
             TokenType::Return => self.return_statement(),
+            TokenType::Break => {
+                let span = self.span();
+                self.loop_control_statement(Stmt::Break { span })
+            }
+            TokenType::Continue => {
+                let span = self.span();
+                self.loop_control_statement(Stmt::Continue { span })
+            }
             TokenType::Function | TokenType::Def => self.function_statement(),
-            TokenType::Async => self.async_function_statement(),
+            TokenType::At => self.decorated_function_statement(),
+            TokenType::Async if !self.next_is_dot() => self.async_function_statement(),
             TokenType::Class => self.class_statement(),
             TokenType::Try => self.try_statement(),
             TokenType::Import => self.import_statement(),
             TokenType::Export => self.export_statement(),
+            // `enum` and `match` are only keywords at the start of a statement,
+            // so existing uses such as regex.match() keep working
+            TokenType::Identifier(word) if word == "enum" && self.next_starts_operand() => {
+                self.enum_statement()
+            }
+            TokenType::Identifier(word) if word == "match" && self.next_starts_operand() => {
+                self.match_statement()
+            }
             _ => {
                 let expr = self.expression()?;
 
@@ -51,12 +97,21 @@ impl Parser {
                     let value = self.expression()?;
                     self.consume_newline_or_eof()?;
 
+                    let span = expr.span();
                     let target = match expr {
-                        Expr::Identifier(name) => AssignmentTarget::Identifier(name),
-                        Expr::Property { object, property } => {
-                            AssignmentTarget::Property { object, property }
-                        }
-                        Expr::Index { object, index } => AssignmentTarget::Index { object, index },
+                        Expr::Identifier { name, .. } => AssignmentTarget::Identifier(name),
+                        Expr::Property {
+                            object,
+                            property,
+                            optional: false,
+                            ..
+                        } => AssignmentTarget::Property { object, property },
+                        Expr::Index {
+                            object,
+                            index,
+                            optional: false,
+                            ..
+                        } => AssignmentTarget::Index { object, index },
                         _ => {
                             return Err(InfraError::ParseError {
                                 message: "Invalid assignment target".to_string(),
@@ -68,7 +123,11 @@ impl Parser {
                         }
                     };
 
-                    return Ok(Stmt::Assignment { target, value });
+                    return Ok(Stmt::Assignment {
+                        target,
+                        value,
+                        span,
+                    });
                 }
 
                 self.consume_newline_or_eof()?;
@@ -80,6 +139,24 @@ impl Parser {
     fn let_statement(&mut self) -> Result<Stmt> {
         self.advance(); // consume 'let'
 
+        if self.check(&TokenType::LeftParen) {
+            let span = self.span();
+            let pattern = self.pattern()?;
+            let type_annotation = self.parse_optional_type()?;
+            self.consume(
+                &TokenType::Equal,
+                "Expected '=' after destructuring pattern",
+            )?;
+            let value = self.expression()?;
+            self.consume_newline_or_eof()?;
+            return Ok(Stmt::LetTuple {
+                pattern,
+                type_annotation,
+                value,
+                span,
+            });
+        }
+
         let name = self.consume_identifier("Expected variable name after 'let'")?;
 
         // Parse optional type annotation: let x: number = 5
@@ -97,6 +174,45 @@ impl Parser {
         })
     }
 
+    /// A name, or names in parentheses to take a tuple apart: `(a, (b, c))`
+    fn pattern(&mut self) -> Result<Pattern> {
+        if !self.check(&TokenType::LeftParen) {
+            let name = self.consume_identifier("Expected a name in destructuring pattern")?;
+            return Ok(Pattern::Name(name));
+        }
+
+        let (line, column) = (self.peek().line, self.peek().column);
+        self.advance(); // consume '('
+        let mut parts = Vec::new();
+        let mut trailing_comma = false;
+        while !self.check(&TokenType::RightParen) {
+            parts.push(self.pattern()?);
+            trailing_comma = self.check(&TokenType::Comma);
+            if !trailing_comma {
+                break;
+            }
+            self.advance(); // consume ','
+        }
+        self.consume(
+            &TokenType::RightParen,
+            "Expected ')' after destructured names",
+        )?;
+
+        match parts.len() {
+            0 => self.error("Expected at least one name in destructuring pattern"),
+            1 if !trailing_comma => Err(InfraError::ParseError {
+                message: "A one-element tuple pattern needs a trailing comma".to_string(),
+                line,
+                column,
+                source_code: None,
+                hint: Some(
+                    "Write `let (name,) = ...` to take apart a one-element tuple".to_string(),
+                ),
+            }),
+            _ => Ok(Pattern::Tuple(parts)),
+        }
+    }
+
     fn if_statement(&mut self) -> Result<Stmt> {
         self.advance(); // consume 'if'
 
@@ -142,10 +258,30 @@ impl Parser {
     fn for_statement(&mut self) -> Result<Stmt> {
         self.advance(); // consume 'for'
 
-        let var = self.consume_identifier("Expected variable name in for loop")?;
+        let pattern = if self.check(&TokenType::LeftParen) {
+            self.pattern()?
+        } else {
+            Pattern::Name(self.consume_identifier("Expected variable name in for loop")?)
+        };
 
         self.consume(&TokenType::In, "Expected 'in' after for loop variable")?;
-        self.consume(&TokenType::Range, "Expected 'range' after 'in'")?;
+        if !self.check(&TokenType::Range) {
+            let span = self.span();
+            let iterable = self.expression()?;
+            self.consume(&TokenType::Colon, "Expected ':' after for loop iterable")?;
+            self.skip_optional_newline();
+            let body = Box::new(self.statement()?);
+            return Ok(Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                span,
+            });
+        }
+        let Pattern::Name(var) = pattern else {
+            return self.error("A range loop counts with one name, not a tuple pattern");
+        };
+        self.advance(); // consume 'range'
         self.consume(&TokenType::LeftParen, "Expected '(' after 'range'")?;
 
         let start = self.expression()?;
@@ -197,6 +333,13 @@ impl Parser {
         Ok(Stmt::Return(value))
     }
 
+    /// `break` or `continue`, alone on its line
+    fn loop_control_statement(&mut self, stmt: Stmt) -> Result<Stmt> {
+        self.advance();
+        self.consume_newline_or_eof()?;
+        Ok(stmt)
+    }
+
     fn block_statement(&mut self) -> Result<Stmt> {
         self.advance(); // consume '{'
 
@@ -210,6 +353,7 @@ impl Parser {
             statements.push(self.statement()?);
         }
 
+        self.anchor(Anchor::Close);
         self.consume(&TokenType::RightBrace, "Expected '}' after block")?;
 
         Ok(Stmt::Block(statements))
@@ -221,70 +365,57 @@ impl Parser {
         let name = self.consume_identifier("Expected function name")?;
 
         self.consume(&TokenType::LeftParen, "Expected '(' after function name")?;
-
-        let mut params = Vec::new();
-        let mut param_types = Vec::new();
-
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                let param = self.consume_identifier("Expected parameter name")?;
-                params.push(param);
-
-                // Parse optional parameter type: func(x: number, y: string)
-                let param_type = self.parse_optional_type()?;
-                param_types.push(param_type);
-
-                if !self.check(&TokenType::Comma) {
-                    break;
-                }
-                self.advance(); // consume ','
-            }
-        }
-
-        self.consume(&TokenType::RightParen, "Expected ')' after parameters")?;
-
-        // Parse optional return type: func() -> number:
-        let return_type = if self.check(&TokenType::Arrow) {
-            self.advance(); // consume '->'
-            Some(self.parse_type()?)
-        } else {
-            None
-        };
-
-        self.consume(&TokenType::Colon, "Expected ':' after function signature")?;
+        let (params, param_types, optional_params, return_type) = self.function_signature()?;
         self.skip_optional_newline();
 
-        let body = Box::new(self.statement()?);
+        let body = Rc::new(self.statement()?);
 
         Ok(Stmt::Function {
             name,
             params,
             param_types,
+            optional_params,
             return_type,
             body,
+            decorators: Vec::new(),
         })
     }
 
-    fn async_function_statement(&mut self) -> Result<Stmt> {
-        self.advance(); // consume 'async'
-        self.consume(&TokenType::Function, "Expected 'function' after 'async'")?;
-
-        let name = self.consume_identifier("Expected function name")?;
-
-        self.consume(&TokenType::LeftParen, "Expected '(' after function name")?;
-
+    /// Parameters after the opening '(', the optional return type and the
+    /// ':' that ends a function signature
+    fn function_signature(&mut self) -> Result<FunctionSignature> {
         let mut params = Vec::new();
         let mut param_types = Vec::new();
+        let mut optional = OptionalParams::default();
 
         if !self.check(&TokenType::RightParen) {
             loop {
+                let rest = self.check(&TokenType::Ellipsis);
+                if rest {
+                    self.advance(); // consume '...'
+                }
                 let param = self.consume_identifier("Expected parameter name")?;
-                params.push(param);
 
                 // Parse optional parameter type: func(x: number, y: string)
                 let param_type = self.parse_optional_type()?;
                 param_types.push(param_type);
 
+                if rest {
+                    optional.rest = true;
+                    if !self.check(&TokenType::RightParen) {
+                        return self.error("A rest parameter must be the last parameter");
+                    }
+                } else if self.check(&TokenType::Equal) {
+                    self.advance(); // consume '='
+                    optional.defaults.push(self.expression()?);
+                } else if !optional.defaults.is_empty() {
+                    return self.error(&format!(
+                        "Parameter '{}' needs a default value, as the ones before it have",
+                        param
+                    ));
+                }
+                params.push(param);
+
                 if !self.check(&TokenType::Comma) {
                     break;
                 }
@@ -303,14 +434,96 @@ impl Parser {
         };
 
         self.consume(&TokenType::Colon, "Expected ':' after function signature")?;
+        Ok((params, param_types, Rc::new(optional), return_type))
+    }
+
+    /// One or more `@decorator` lines, then the function (or exported
+    /// function) they apply to
+    fn decorated_function_statement(&mut self) -> Result<Stmt> {
+        let mut decorators = Vec::new();
+        while self.check(&TokenType::At) {
+            self.advance(); // consume '@'
+            decorators.push(self.decorator()?);
+            self.consume_newline_or_eof()?;
+            while self.check(&TokenType::Newline) {
+                self.advance();
+            }
+        }
+
+        let exported = self.check(&TokenType::Export)
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|t| matches!(t.token_type, TokenType::Function | TokenType::Def));
+        let mut stmt = match self.peek().token_type {
+            TokenType::Function | TokenType::Def => self.function_statement()?,
+            _ if exported => self.export_statement()?,
+            _ => return self.error("Expected a function declaration after decorators"),
+        };
+
+        match &mut stmt {
+            Stmt::Function {
+                decorators: slot, ..
+            }
+            | Stmt::Export {
+                item:
+                    ExportItem::Function {
+                        decorators: slot, ..
+                    },
+            } => *slot = decorators,
+            _ => unreachable!(),
+        }
+        Ok(stmt)
+    }
+
+    /// A decorator: a name, module function or property, optionally called
+    /// with arguments
+    fn decorator(&mut self) -> Result<Expr> {
+        let (line, column) = (self.peek().line, self.peek().column);
+        let expr = self.call()?;
+
+        fn is_reference(expr: &Expr) -> bool {
+            match expr {
+                Expr::Identifier { .. } | Expr::ModuleAccess { .. } => true,
+                Expr::Property { object, .. } => is_reference(object),
+                _ => false,
+            }
+        }
+        let valid = match &expr {
+            Expr::Call { callee, .. } => is_reference(callee),
+            other => is_reference(other),
+        };
+        if !valid {
+            return Err(InfraError::ParseError {
+                message:
+                    "A decorator must be a name, module function or property, optionally called"
+                        .to_string(),
+                line,
+                column,
+                source_code: None,
+                hint: Some("Use e.g. @memo.wrap or @timing.throttle(100)".to_string()),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn async_function_statement(&mut self) -> Result<Stmt> {
+        self.advance(); // consume 'async'
+        self.consume(&TokenType::Function, "Expected 'function' after 'async'")?;
+
+        let name = self.consume_identifier("Expected function name")?;
+
+        self.consume(&TokenType::LeftParen, "Expected '(' after function name")?;
+        let (params, param_types, optional_params, return_type) = self.function_signature()?;
         self.skip_optional_newline();
 
-        let body = Box::new(self.statement()?);
+        let body = Rc::new(self.statement()?);
 
         Ok(Stmt::AsyncFunction {
             name,
             params,
             param_types,
+            optional_params,
             return_type,
             body,
         })
@@ -336,70 +549,169 @@ impl Parser {
             &TokenType::LeftBrace,
             "Expected '{' after class declaration",
         )?;
-        self.skip_optional_newline();
+        self.skip_newlines();
 
+        let mut fields = Vec::new();
         let mut methods = Vec::new();
 
-        // Parse methods until closing brace
+        // Fields and methods until the closing brace
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            methods.push(self.method_declaration()?);
-            self.skip_optional_newline();
+            if self.is_field_declaration() {
+                self.anchor(Anchor::Field);
+                let field = self.consume_identifier("Expected field name")?;
+                self.advance(); // consume '='
+                let value = self.expression()?;
+                self.consume_newline_or_eof()?;
+                fields.push((field, value));
+            } else {
+                self.anchor(Anchor::Method);
+                methods.push(self.method_declaration()?);
+            }
+            self.skip_newlines();
         }
 
+        self.anchor(Anchor::Close);
         self.consume(&TokenType::RightBrace, "Expected '}' after class body")?;
 
         Ok(Stmt::Class {
             name,
             superclass,
+            fields,
             methods,
         })
     }
 
+    /// Whether a class body line is `name = expr` rather than a method
+    fn is_field_declaration(&self) -> bool {
+        matches!(self.peek().token_type, TokenType::Identifier(_))
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|t| t.token_type == TokenType::Equal)
+    }
+
+    /// `name(params)[-> type]: body`, optionally after `function` or `def`.
+    /// The constructor is the method named `init`.
     fn method_declaration(&mut self) -> Result<MethodDecl> {
-        let name = self.consume_identifier("Expected method name")?;
+        if matches!(self.peek().token_type, TokenType::Function | TokenType::Def) {
+            self.advance(); // consume 'function' or 'def'
+        }
+        let name = if self.check(&TokenType::Init) {
+            self.advance(); // consume 'init'
+            "init".to_string()
+        } else {
+            self.consume_identifier("Expected method name")?
+        };
 
         self.consume(&TokenType::LeftParen, "Expected '(' after method name")?;
+        let (params, param_types, optional_params, return_type) = self.function_signature()?;
+        self.skip_optional_newline();
 
-        let mut params = Vec::new();
-        let mut param_types = Vec::new();
+        let body = Rc::new(self.statement()?);
 
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                let param = self.consume_identifier("Expected parameter name")?;
-                params.push(param);
+        Ok(MethodDecl {
+            name,
+            params,
+            param_types,
+            optional_params,
+            return_type,
+            body,
+        })
+    }
 
-                // Parse optional parameter type: func(x: number, y: string)
-                let param_type = self.parse_optional_type()?;
-                param_types.push(param_type);
+    /// enum Status: pending, active, closed
+    fn enum_statement(&mut self) -> Result<Stmt> {
+        self.advance(); // consume 'enum'
 
-                if !self.check(&TokenType::Comma) {
-                    break;
+        let name = self.consume_identifier("Expected enum name after 'enum'")?;
+        self.consume(&TokenType::Colon, "Expected ':' after enum name")?;
+
+        let mut members: Vec<String> = Vec::new();
+        loop {
+            if let TokenType::Identifier(member) = &self.peek().token_type {
+                if members.iter().any(|existing| existing == member) {
+                    return self.error(&format!("Duplicate member '{}' in enum {}", member, name));
+                }
+                if member == "values" || member == "parse" {
+                    return self.error(&format!(
+                        "'{}' is reserved for {}.{}()",
+                        member, name, member
+                    ));
                 }
-                self.advance(); // consume ','
             }
+            members.push(self.consume_identifier("Expected enum member name")?);
+
+            if !self.check(&TokenType::Comma) {
+                break;
+            }
+            self.advance(); // consume ','
+            self.skip_optional_newline();
         }
+        self.consume_newline_or_eof()?;
 
-        self.consume(&TokenType::RightParen, "Expected ')' after parameters")?;
+        Ok(Stmt::Enum { name, members })
+    }
 
-        // Parse optional return type: func() -> number:
-        let return_type = if self.check(&TokenType::Arrow) {
-            self.advance(); // consume '->'
-            Some(self.parse_type()?)
-        } else {
-            None
-        };
+    /// match subject:
+    /// {
+    ///     Status.active: statement
+    ///     else: statement
+    /// }
+    fn match_statement(&mut self) -> Result<Stmt> {
+        self.advance(); // consume 'match'
 
-        self.consume(&TokenType::Colon, "Expected ':' after method signature")?;
+        let subject = self.expression()?;
+        self.consume(&TokenType::Colon, "Expected ':' after match subject")?;
         self.skip_optional_newline();
+        self.consume(&TokenType::LeftBrace, "Expected '{' to start match arms")?;
 
-        let body = Box::new(self.statement()?);
+        let mut arms = Vec::new();
+        let mut default = None;
+        loop {
+            self.skip_newlines();
+            if self.check(&TokenType::RightBrace) || self.is_at_end() {
+                break;
+            }
 
-        Ok(MethodDecl {
-            name,
-            params,
-            param_types,
-            return_type,
-            body,
+            self.anchor(Anchor::Arm);
+            if self.is_default_arm() {
+                if default.is_some() {
+                    return self.error("A match can only have one default arm");
+                }
+                self.advance(); // consume 'else' or 'default'
+                self.consume(&TokenType::Colon, "Expected ':' after the default arm")?;
+                self.skip_optional_newline();
+                default = Some(Box::new(self.statement()?));
+                continue;
+            }
+            if default.is_some() {
+                return self.error("The default arm must be the last arm of a match");
+            }
+
+            // `case` is only a keyword before an arm's first pattern
+            if matches!(&self.peek().token_type, TokenType::Identifier(word) if word == "case")
+                && (self.next_starts_operand()
+                    || matches!(self.peek_next_type(), Some(TokenType::Minus)))
+            {
+                self.advance(); // consume 'case'
+            }
+            let mut patterns = vec![self.expression()?];
+            while self.check(&TokenType::Comma) {
+                self.advance(); // consume ','
+                patterns.push(self.expression()?);
+            }
+            self.consume(&TokenType::Colon, "Expected ':' after match pattern")?;
+            self.skip_optional_newline();
+            let body = self.statement()?;
+            arms.push(MatchArm { patterns, body });
+        }
+        self.anchor(Anchor::Close);
+        self.consume(&TokenType::RightBrace, "Expected '}' after match arms")?;
+
+        Ok(Stmt::Match {
+            subject,
+            arms,
+            default,
         })
     }
 
@@ -515,7 +827,12 @@ impl Parser {
                 // export function name(params) { ... }
                 let func_stmt = self.function_statement()?;
                 if let Stmt::Function {
-                    name, params, body, ..
+                    name,
+                    params,
+                    optional_params,
+                    body,
+                    decorators,
+                    ..
                 } = func_stmt
                 {
                     let param_count = params.len();
@@ -524,8 +841,10 @@ impl Parser {
                             name,
                             params,
                             param_types: vec![None; param_count], // TODO: Parse parameter types
-                            return_type: None,                    // TODO: Parse return type
+                            optional_params,
+                            return_type: None, // TODO: Parse return type
                             body,
+                            decorators,
                         },
                     })
                 } else {
@@ -559,19 +878,62 @@ impl Parser {
     }
 
     fn expression(&mut self) -> Result<Expr> {
-        self.or()
+        self.conditional()
+    }
+
+    /// `cond ? a : b`. The else side is another conditional, so
+    /// `a ? b : c ? d : e` groups as `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.coalesce()?;
+        if !self.check(&TokenType::Question) {
+            return Ok(condition);
+        }
+        self.advance(); // consume '?'
+
+        let then_expr = self.expression()?;
+        self.consume(&TokenType::Colon, "Expected ':' after the '?' branch")?;
+        let else_expr = self.conditional()?;
+        Ok(Expr::Conditional {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        })
+    }
+
+    /// `a ?? b`, binding more loosely than `||`
+    fn coalesce(&mut self) -> Result<Expr> {
+        let mut expr = self.or()?;
+
+        while self.check(&TokenType::QuestionQuestion) {
+            let span = self.span();
+            self.advance();
+            // An operator ending a line continues the expression on the next
+            self.skip_newlines();
+            let right = self.or()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::Coalesce,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(expr)
     }
 
     fn or(&mut self) -> Result<Expr> {
         let mut expr = self.and()?;
 
         while self.check(&TokenType::Or) {
+            let span = self.span();
             self.advance();
+            self.skip_newlines();
             let right = self.and()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: BinaryOp::Or,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -582,12 +944,15 @@ impl Parser {
         let mut expr = self.equality()?;
 
         while self.check(&TokenType::And) {
+            let span = self.span();
             self.advance();
+            self.skip_newlines();
             let right = self.equality()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: BinaryOp::And,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -601,16 +966,19 @@ impl Parser {
             self.peek().token_type,
             TokenType::EqualEqual | TokenType::BangEqual
         ) {
+            let span = self.span();
             let operator = match self.advance().token_type {
                 TokenType::EqualEqual => BinaryOp::Equal,
                 TokenType::BangEqual => BinaryOp::NotEqual,
                 _ => unreachable!(),
             };
+            self.skip_newlines();
             let right = self.comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -624,6 +992,7 @@ impl Parser {
             self.peek().token_type,
             TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
         ) {
+            let span = self.span();
             let operator = match self.advance().token_type {
                 TokenType::Greater => BinaryOp::Greater,
                 TokenType::GreaterEqual => BinaryOp::GreaterEqual,
@@ -631,11 +1000,13 @@ impl Parser {
                 TokenType::LessEqual => BinaryOp::LessEqual,
                 _ => unreachable!(),
             };
+            self.skip_newlines();
             let right = self.term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -646,16 +1017,19 @@ impl Parser {
         let mut expr = self.factor()?;
 
         while matches!(self.peek().token_type, TokenType::Minus | TokenType::Plus) {
+            let span = self.span();
             let operator = match self.advance().token_type {
                 TokenType::Minus => BinaryOp::Subtract,
                 TokenType::Plus => BinaryOp::Add,
                 _ => unreachable!(),
             };
+            self.skip_newlines();
             let right = self.factor()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -669,17 +1043,20 @@ impl Parser {
             self.peek().token_type,
             TokenType::Slash | TokenType::Star | TokenType::Percent
         ) {
+            let span = self.span();
             let operator = match self.advance().token_type {
                 TokenType::Slash => BinaryOp::Divide,
                 TokenType::Star => BinaryOp::Multiply,
                 TokenType::Percent => BinaryOp::Modulo,
                 _ => unreachable!(),
             };
+            self.skip_newlines();
             let right = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -691,12 +1068,14 @@ impl Parser {
             self.peek().token_type,
             TokenType::Bang | TokenType::Minus | TokenType::Await | TokenType::New
         ) {
+            let span = self.span();
             let _operator = match self.advance().token_type {
                 TokenType::Bang => {
                     let operand = self.unary()?;
                     return Ok(Expr::Unary {
                         operator: UnaryOp::Not,
                         operand: Box::new(operand),
+                        span,
                     });
                 }
                 TokenType::Minus => {
@@ -704,6 +1083,7 @@ impl Parser {
                     return Ok(Expr::Unary {
                         operator: UnaryOp::Minus,
                         operand: Box::new(operand),
+                        span,
                     });
                 }
                 TokenType::Await => {
@@ -713,8 +1093,8 @@ impl Parser {
                     });
                 }
                 TokenType::New => {
-                    // Parse new Class()
-                    let class_expr = self.unary()?;
+                    // Parse new Class(); the class is a name, not a call
+                    let class_expr = self.primary()?;
 
                     // Check for constructor call
                     if !self.check(&TokenType::LeftParen) {
@@ -727,22 +1107,12 @@ impl Parser {
                         });
                     }
 
-                    let mut args = Vec::new();
-                    self.advance(); // consume '('
-
-                    if !self.check(&TokenType::RightParen) {
-                        loop {
-                            args.push(self.expression()?);
-                            if !self.check(&TokenType::Comma) {
-                                break;
-                            }
-                            self.advance(); // consume ','
-                        }
-                    }
-
-                    self.consume(
+                    let open_line = self.advance().line; // consume '('
+                    let args = self.list(
+                        open_line,
                         &TokenType::RightParen,
                         "Expected ')' after constructor arguments",
+                        Self::expression,
                     )?;
 
                     return Ok(Expr::New {
@@ -758,32 +1128,26 @@ impl Parser {
     }
 
     fn call(&mut self) -> Result<Expr> {
+        let start = self.span();
         let mut expr = self.primary()?;
 
         loop {
             if self.check(&TokenType::LeftParen) {
-                self.advance(); // consume '('
-
-                let mut args = Vec::new();
-
-                if !self.check(&TokenType::RightParen) {
-                    loop {
-                        args.push(self.expression()?);
-
-                        if !self.check(&TokenType::Comma) {
-                            break;
-                        }
-                        self.advance(); // consume ','
-                    }
-                }
-
-                self.consume(&TokenType::RightParen, "Expected ')' after arguments")?;
+                let open_line = self.advance().line; // consume '('
+                let args = self.list(
+                    open_line,
+                    &TokenType::RightParen,
+                    "Expected ')' after arguments",
+                    Self::expression,
+                )?;
 
                 expr = Expr::Call {
                     callee: Box::new(expr),
                     args,
+                    span: start,
                 };
             } else if self.check(&TokenType::LeftBracket) {
+                let span = self.span();
                 self.advance(); // consume '['
 
                 let index = self.expression()?;
@@ -793,14 +1157,44 @@ impl Parser {
                 expr = Expr::Index {
                     object: Box::new(expr),
                     index: Box::new(index),
+                    optional: false,
+                    span,
+                };
+            } else if self.check(&TokenType::QuestionDot) {
+                let span = self.span();
+                self.advance(); // consume '?.'
+
+                // `a?.[i]` indexes, `a?.name` reads a property; either is
+                // null when `a` is
+                expr = if self.check(&TokenType::LeftBracket) {
+                    self.advance(); // consume '['
+                    let index = self.expression()?;
+                    self.consume(&TokenType::RightBracket, "Expected ']' after array index")?;
+                    Expr::Index {
+                        object: Box::new(expr),
+                        index: Box::new(index),
+                        optional: true,
+                        span,
+                    }
+                } else {
+                    Expr::Property {
+                        object: Box::new(expr),
+                        property: self.consume_property_name()?,
+                        optional: true,
+                        span,
+                    }
                 };
             } else if self.check(&TokenType::Dot) {
+                let span = self.span();
                 self.advance(); // consume '.'
 
-                let property = self.consume_identifier("Expected property name after '.'")?;
+                let property = self.consume_property_name()?;
 
                 // Check if this is module access (simple identifier on the left)
-                if let Expr::Identifier(module_name) = &expr {
+                if let Expr::Identifier {
+                    name: module_name, ..
+                } = &expr
+                {
                     // For now, we'll assume all known module names should be treated as modules
                     // In a more sophisticated parser, we'd check against a known module list
                     if is_module_name(module_name) {
@@ -812,12 +1206,16 @@ impl Parser {
                         expr = Expr::Property {
                             object: Box::new(expr),
                             property,
+                            optional: false,
+                            span,
                         };
                     }
                 } else {
                     expr = Expr::Property {
                         object: Box::new(expr),
                         property,
+                        optional: false,
+                        span,
                     };
                 }
             } else {
@@ -828,84 +1226,184 @@ impl Parser {
         Ok(expr)
     }
 
+    /// Whether the token after the current one can start an operand, which
+    /// tells the statement keywords `enum` and `match` from plain names
+    fn next_starts_operand(&self) -> bool {
+        self.tokens.get(self.current + 1).is_some_and(|t| {
+            matches!(
+                t.token_type,
+                TokenType::Identifier(_)
+                    | TokenType::Number(_)
+                    | TokenType::String(_)
+                    | TokenType::InterpolatedString(_)
+                    | TokenType::True
+                    | TokenType::False
+                    | TokenType::Null
+                    | TokenType::This
+            )
+        })
+    }
+
+    fn peek_next_type(&self) -> Option<&TokenType<'src>> {
+        self.tokens.get(self.current + 1).map(|t| &t.token_type)
+    }
+
+    /// `else:`, or `default:` where an arm starts
+    fn is_default_arm(&self) -> bool {
+        match &self.peek().token_type {
+            TokenType::Else => true,
+            TokenType::Identifier(word) => {
+                word == "default" && matches!(self.peek_next_type(), Some(TokenType::Colon))
+            }
+            _ => false,
+        }
+    }
+
+    fn next_is_dot(&self) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|t| t.token_type == TokenType::Dot)
+    }
+
+    /// A string literal's text and `${...}` expressions, the expressions
+    /// parsed where they are in the source
+    fn interpolation(&self, parts: Vec<SourcePart<'src>>) -> Result<Expr> {
+        let mut pieces = Vec::with_capacity(parts.len());
+        for part in parts {
+            pieces.push(match part {
+                SourcePart::Text(text) => StringPart::Text(text.into_owned()),
+                SourcePart::Code {
+                    source,
+                    line,
+                    column,
+                } => {
+                    let tokens = Lexer::starting_at(&source, line, column).tokenize()?;
+                    let mut parser = Parser::new(tokens);
+                    parser.positions = self.positions;
+                    if parser.is_at_end() {
+                        return parser.error("Expected an expression inside '${}'");
+                    }
+                    let expr = parser.expression()?;
+                    if !parser.is_at_end() {
+                        return parser.error("Expected '}' after the interpolated expression");
+                    }
+                    StringPart::Expr(expr)
+                }
+            });
+        }
+        Ok(Expr::Interpolation(pieces))
+    }
+
     fn primary(&mut self) -> Result<Expr> {
+        // Some keywords double as module names: string.upper(), async.sleep()
+        if self.next_is_dot() {
+            let module = match self.peek().token_type {
+                TokenType::NumberType => Some("number"),
+                TokenType::StringType => Some("string"),
+                TokenType::Async => Some("async"),
+                _ => None,
+            };
+            if let Some(module) = module {
+                let span = self.span();
+                self.advance();
+                return Ok(Expr::Identifier {
+                    name: module.to_string(),
+                    span,
+                });
+            }
+        }
+
+        let span = self.span();
         let token = self.advance();
+        let line = token.line;
         match &token.token_type {
             TokenType::True => Ok(Expr::Literal(Value::Boolean(true))),
             TokenType::False => Ok(Expr::Literal(Value::Boolean(false))),
             TokenType::Null => Ok(Expr::Literal(Value::Null)),
             TokenType::Number(n) => Ok(Expr::Literal(Value::Number(*n))),
-            TokenType::String(s) => Ok(Expr::Literal(Value::String(s.clone()))),
-            TokenType::Identifier(name) => Ok(Expr::Identifier(name.clone())),
+            TokenType::BigInt(digits) => Ok(Expr::Literal(Value::BigInt(
+                digits.parse().expect("lexer only produces decimal digits"),
+            ))),
+            TokenType::String(s) => Ok(Expr::Literal(Value::String(s.to_string()))),
+            TokenType::InterpolatedString(parts) => {
+                let parts = parts.clone();
+                self.interpolation(parts)
+            }
+            TokenType::Identifier(name) => Ok(Expr::Identifier {
+                name: name.to_string(),
+                span,
+            }),
             TokenType::This => Ok(Expr::This),
+            TokenType::Function | TokenType::Def => {
+                self.consume(
+                    &TokenType::LeftParen,
+                    "Expected '(' after 'function' in a lambda",
+                )?;
+                let (params, param_types, optional_params, return_type) =
+                    self.function_signature()?;
+                let body = Rc::new(self.expression()?);
+                Ok(Expr::Lambda {
+                    name: format!("lambda@{}", line),
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                    body,
+                })
+            }
             TokenType::Super => {
                 // Parse super.method()
                 self.consume(&TokenType::Dot, "Expected '.' after super")?;
-                let method = self.consume_identifier("Expected method name after super.")?;
+                let method = self.consume_property_name()?;
                 Ok(Expr::Super { method })
             }
             TokenType::LeftParen => {
+                self.skip_newlines();
                 let expr = self.expression()?;
-                self.consume(&TokenType::RightParen, "Expected ')' after expression")?;
-                Ok(expr)
+                self.skip_newlines();
+                if !self.check(&TokenType::Comma) {
+                    self.consume(&TokenType::RightParen, "Expected ')' after expression")?;
+                    return Ok(expr);
+                }
+
+                // A comma makes a tuple: `(a, b)`, or `(a,)` with one element
+                self.advance(); // consume ','
+                let rest = self.list(
+                    line,
+                    &TokenType::RightParen,
+                    "Expected ')' after tuple elements",
+                    Self::expression,
+                )?;
+                Ok(Expr::Tuple(std::iter::once(expr).chain(rest).collect()))
             }
             TokenType::LeftBracket => {
                 // Array literal
-                let mut elements = Vec::new();
-
-                if !self.check(&TokenType::RightBracket) {
-                    loop {
-                        elements.push(self.expression()?);
-
-                        if !self.check(&TokenType::Comma) {
-                            break;
-                        }
-                        self.advance(); // consume ','
-                    }
-                }
-
-                self.consume(
+                let elements = self.anchored_list(
+                    line,
                     &TokenType::RightBracket,
                     "Expected ']' after array elements",
+                    Self::expression,
                 )?;
                 Ok(Expr::Array(elements))
             }
             TokenType::LeftBrace => {
                 // Object literal - we've already consumed the '{'
-                let mut properties = Vec::new();
-
-                if !self.check(&TokenType::RightBrace) {
-                    loop {
+                let properties = self.anchored_list(
+                    line,
+                    &TokenType::RightBrace,
+                    "Expected '}' after object properties",
+                    |parser| {
                         // Parse key (must be a string for now)
-                        let key = match &self.peek().token_type {
-                            TokenType::String(s) => {
-                                let key = s.clone();
-                                self.advance();
-                                key
-                            }
-                            TokenType::Identifier(name) => {
-                                let key = name.clone();
-                                self.advance();
-                                key
-                            }
-                            _ => return self.error("Expected property name"),
+                        let key = match &parser.peek().token_type {
+                            TokenType::String(s) => s.to_string(),
+                            TokenType::Identifier(name) => name.to_string(),
+                            _ => return parser.error("Expected property name"),
                         };
+                        parser.advance();
 
-                        self.consume(&TokenType::Colon, "Expected ':' after property name")?;
-
-                        let value = self.expression()?;
-                        properties.push((key, value));
-
-                        if !self.check(&TokenType::Comma) {
-                            break;
-                        }
-                        self.advance(); // consume ','
-                    }
-                }
-
-                self.consume(
-                    &TokenType::RightBrace,
-                    "Expected '}' after object properties",
+                        parser.consume(&TokenType::Colon, "Expected ':' after property name")?;
+                        Ok((key, parser.expression()?))
+                    },
                 )?;
                 Ok(Expr::Object(properties))
             }
@@ -981,19 +1479,33 @@ impl Parser {
                 self.consume(&TokenType::RightBrace, "Expected '}' after object type")?;
                 Ok(Type::Object(properties))
             }
+            TokenType::Identifier(name) if name == "bigint" => {
+                self.advance();
+                Ok(Type::BigInt)
+            }
+            TokenType::Identifier(name) if name == "bytes" => {
+                self.advance();
+                Ok(Type::Bytes)
+            }
             TokenType::LeftParen => {
-                // Function type: (param_types) -> return_type
+                // Function type: (param_types) -> return_type, or a tuple
+                // type: (number, string), (number,)
                 self.advance(); // consume '('
                 let mut param_types = Vec::new();
+                let mut trailing_comma = false;
 
                 if !self.check(&TokenType::RightParen) {
                     loop {
                         param_types.push(self.parse_type()?);
 
-                        if !self.check(&TokenType::Comma) {
+                        trailing_comma = self.check(&TokenType::Comma);
+                        if !trailing_comma {
                             break;
                         }
                         self.advance(); // consume ','
+                        if self.check(&TokenType::RightParen) {
+                            break;
+                        }
                     }
                 }
 
@@ -1001,6 +1513,12 @@ impl Parser {
                     &TokenType::RightParen,
                     "Expected ')' after function parameter types",
                 )?;
+                if !self.check(&TokenType::Arrow) && !param_types.is_empty() {
+                    if param_types.len() == 1 && !trailing_comma {
+                        return Ok(param_types.pop().unwrap());
+                    }
+                    return Ok(Type::Tuple(param_types));
+                }
                 self.consume(
                     &TokenType::Arrow,
                     "Expected '->' after function parameter types",
@@ -1012,6 +1530,11 @@ impl Parser {
                     return_type,
                 })
             }
+            TokenType::Identifier(name) => {
+                let name = name.to_string();
+                self.advance();
+                Ok(Type::Named(name))
+            }
             _ => {
                 // Default to Any type for unrecognized types
                 // This allows for graceful degradation
@@ -1033,7 +1556,7 @@ impl Parser {
     fn consume_identifier(&mut self, message: &str) -> Result<String> {
         match &self.peek().token_type {
             TokenType::Identifier(name) => {
-                let name = name.clone();
+                let name = name.to_string();
                 self.advance();
                 Ok(name)
             }
@@ -1041,10 +1564,29 @@ impl Parser {
         }
     }
 
+    /// Property names may also be keywords, e.g. `error.new` or `obj.type`
+    fn consume_property_name(&mut self) -> Result<String> {
+        let token = self.peek();
+        let is_word = !matches!(token.token_type, TokenType::String(_))
+            && token
+                .lexeme
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_');
+
+        if is_word {
+            let name = token.lexeme.to_string();
+            self.advance();
+            Ok(name)
+        } else {
+            self.error("Expected property name after '.'")
+        }
+    }
+
     fn consume_string(&mut self, message: &str) -> Result<String> {
         match &self.peek().token_type {
             TokenType::String(value) => {
-                let value = value.clone();
+                let value = value.to_string();
                 self.advance();
                 Ok(value)
             }
@@ -1061,6 +1603,74 @@ impl Parser {
         }
     }
 
+    /// Parse comma-separated items up to `close`, whose opening bracket was
+    /// on `open_line`. Items may be spread over several lines and the last
+    /// one may have a trailing comma. Records whether the list spanned
+    /// several lines, for the formatter.
+    fn list<T>(
+        &mut self,
+        open_line: usize,
+        close: &TokenType,
+        message: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.check(close) {
+                break;
+            }
+            items.push(item(self)?);
+            self.skip_newlines();
+            if !self.check(&TokenType::Comma) {
+                break;
+            }
+            self.advance(); // consume ','
+        }
+
+        let close_line = self.peek().line;
+        self.consume(close, message)?;
+        self.multiline_lists.push(close_line > open_line);
+        Ok(items)
+    }
+
+    /// Parse a list literal like [`Parser::list`], anchoring each item and
+    /// the closing bracket if it spans several lines, so the formatter can
+    /// keep the comments between its items
+    fn anchored_list<T>(
+        &mut self,
+        open_line: usize,
+        close: &TokenType,
+        message: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let first = self.anchors.len();
+        let items = self.list(open_line, close, message, |parser| {
+            parser.anchor(Anchor::Item);
+            item(parser)
+        })?;
+        if self.multiline_lists.last() == Some(&true) {
+            let line = self.tokens[self.current - 1].line;
+            self.anchors.push(Anchor::Bracket(line));
+        } else {
+            // A list on one line holds nothing that spans several
+            self.anchors.truncate(first);
+        }
+        Ok(items)
+    }
+
+    /// Record where the next token is, for the formatter
+    fn anchor(&mut self, anchor: fn(usize) -> Anchor) {
+        let line = self.peek().line;
+        self.anchors.push(anchor(line));
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.check(&TokenType::Newline) {
+            self.advance();
+        }
+    }
+
     fn skip_optional_newline(&mut self) {
         if self.check(&TokenType::Newline) {
             self.advance();
@@ -1086,18 +1696,27 @@ impl Parser {
         }
     }
 
-    fn advance(&mut self) -> &Token {
+    fn advance(&mut self) -> &Token<'src> {
         if !self.is_at_end() {
             self.current += 1;
         }
         &self.tokens[self.current - 1]
     }
 
+    /// Where the current token is, unless positions are left out
+    fn span(&self) -> Option<Span> {
+        let token = self.peek();
+        self.positions.then_some(Span {
+            line: token.line,
+            column: token.column,
+        })
+    }
+
     fn is_at_end(&self) -> bool {
         self.peek().is_eof()
     }
 
-    fn peek(&self) -> &Token {
+    fn peek(&self) -> &Token<'src> {
         &self.tokens[self.current]
     }
 
@@ -1114,6 +1733,33 @@ impl Parser {
 }
 
 // Helper function to check if an identifier is a known module name
-fn is_module_name(name: &str) -> bool {
-    matches!(name, "math" | "string" | "array" | "io")
+pub(crate) fn is_module_name(name: &str) -> bool {
+    matches!(
+        name,
+        "math"
+            | "string"
+            | "array"
+            | "object"
+            | "io"
+            | "memo"
+            | "error"
+            | "number"
+            | "process"
+            | "path"
+            | "bigint"
+            | "bytes"
+            | "ipc"
+            | "worker"
+            | "progress"
+            | "schedule"
+            | "timing"
+            | "async"
+            | "faults"
+            | "assert"
+            | "time"
+            | "units"
+            | "json"
+            | "random"
+            | "convert"
+    )
 }
@@ -0,0 +1,1076 @@
+//! Turns AST nodes back into Infra source.
+//!
+//! The output is meant to parse back into an equivalent tree rather than to
+//! reproduce the original text: redundant parentheses are dropped and
+//! multi-statement bodies always use braces. Comments and blank lines are
+//! not in the tree; the formatter hands them in as [`Trivia`] to put back.
+//!
+//! Bracketed lists (call arguments, array and object literals) go on one
+//! line when they fit in `max_width`, and one item per line with a trailing
+//! comma otherwise. With `preserve_line_breaks`, a list the user spread over
+//! several lines stays that way even when it would fit.
+
+use crate::core::ast::*;
+use crate::core::Value;
+use crate::frontend::formatter::Trivia;
+
+#[derive(Debug, Clone)]
+pub struct FormattingOptions {
+    pub max_width: usize,
+    /// Keep lists that spanned several lines in the source multi-line
+    pub preserve_line_breaks: bool,
+    /// Columns per level of indentation, and per tab when counting width
+    pub tab_size: usize,
+    /// Indent with tabs rather than `tab_size` spaces
+    pub use_tabs: bool,
+}
+
+impl Default for FormattingOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 100,
+            preserve_line_breaks: true,
+            tab_size: 4,
+            use_tabs: false,
+        }
+    }
+}
+
+/// Format a whole program, one top-level statement after another
+#[allow(dead_code)]
+pub fn format_program(program: &Program, options: &FormattingOptions) -> String {
+    let mut printer = Printer::new(options.clone(), &program.multiline_lists);
+    let mut out = String::new();
+    for stmt in &program.statements {
+        printer.write_stmt(&mut out, stmt, 0);
+    }
+    out
+}
+
+/// Format a whole program with the comments and blank lines of its source,
+/// and a blank line around each top-level function and class
+pub(crate) fn format_program_with(
+    program: &Program,
+    options: &FormattingOptions,
+    trivia: Trivia,
+) -> String {
+    let mut printer = Printer::new(options.clone(), &program.multiline_lists);
+    printer.trivia = Some(trivia);
+    let mut out = String::new();
+    let declaration = |stmt: &Stmt| {
+        matches!(
+            stmt,
+            Stmt::Function { .. }
+                | Stmt::AsyncFunction { .. }
+                | Stmt::Class { .. }
+                | Stmt::Export {
+                    item: ExportItem::Function { .. }
+                }
+        )
+    };
+    for (i, stmt) in program.statements.iter().enumerate() {
+        if i > 0 && (declaration(stmt) || declaration(&program.statements[i - 1])) {
+            printer.with_trivia(|trivia| trivia.separate_next());
+        }
+        printer.write_stmt(&mut out, stmt, 0);
+    }
+    printer.with_trivia(|trivia| trivia.finish(&mut out));
+    out
+}
+
+/// Reconstruct the definition of a function value's parts
+pub fn format_function(
+    name: &str,
+    params: &[String],
+    param_types: &[Option<Type>],
+    optional_params: &OptionalParams,
+    return_type: &Option<Type>,
+    body: &Stmt,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&signature(
+        "function ",
+        name,
+        params,
+        param_types,
+        optional_params,
+        return_type,
+    ));
+    Printer::flat().write_body(&mut out, body, 0);
+    out
+}
+
+/// The header of a function definition without its `:`, such as
+/// `function add(a: number, b = 1) -> number`; `keyword` ends in a space
+#[allow(dead_code)]
+pub fn format_signature(
+    keyword: &str,
+    name: &str,
+    params: &[String],
+    param_types: &[Option<Type>],
+    optional_params: &OptionalParams,
+    return_type: &Option<Type>,
+) -> String {
+    let mut header = signature(
+        keyword,
+        name,
+        params,
+        param_types,
+        optional_params,
+        return_type,
+    );
+    header.pop();
+    header
+}
+
+/// An expression on a single line
+pub fn format_expr(expr: &Expr) -> String {
+    Printer::flat().doc(expr).flat()
+}
+
+pub fn format_type(ty: &Type) -> String {
+    match ty {
+        Type::Number => "number".to_string(),
+        Type::BigInt => "bigint".to_string(),
+        Type::String => "string".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Bytes => "bytes".to_string(),
+        Type::Array(element) => format!("[{}]", format_type(element)),
+        Type::Object(properties) => {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|(name, ty)| format!("{}: {}", name, format_type(ty)))
+                .collect();
+            format!("{{{}}}", properties.join(", "))
+        }
+        Type::Function {
+            params,
+            return_type,
+        } => {
+            let params: Vec<String> = params.iter().map(format_type).collect();
+            format!("({}) -> {}", params.join(", "), format_type(return_type))
+        }
+        Type::Union(types) => {
+            let types: Vec<String> = types.iter().map(format_type).collect();
+            types.join(" | ")
+        }
+        Type::Tuple(types) => {
+            let types: Vec<String> = types.iter().map(format_type).collect();
+            parenthesized_list(&types)
+        }
+        Type::Named(name) => name.clone(),
+        Type::Any => "any".to_string(),
+        Type::Never => "never".to_string(),
+    }
+}
+
+/// The names of a destructuring `let`, as written: `(count, (low, high))`
+pub fn format_pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Name(name) => name.clone(),
+        Pattern::Tuple(parts) => {
+            let parts: Vec<String> = parts.iter().map(format_pattern).collect();
+            parenthesized_list(&parts)
+        }
+    }
+}
+
+/// `(a, b)`, or `(a,)` for one item so it does not read as plain parentheses
+fn parenthesized_list(items: &[String]) -> String {
+    match items {
+        [only] => format!("({},)", only),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+/// Formatted text that has not been laid out yet
+enum Doc {
+    Text(String),
+    Concat(Vec<Doc>),
+    /// Comma-separated items between brackets
+    List {
+        open: &'static str,
+        close: &'static str,
+        items: Vec<Doc>,
+        multiline: bool,
+        /// A list or object literal whose items and closing bracket the
+        /// parser anchored
+        anchored: bool,
+    },
+}
+
+fn text(text: impl Into<String>) -> Doc {
+    Doc::Text(text.into())
+}
+
+impl Doc {
+    /// The text with every list on one line
+    fn flat(&self) -> String {
+        let mut out = String::new();
+        self.write_flat(&mut out);
+        out
+    }
+
+    fn write_flat(&self, out: &mut String) {
+        match self {
+            Doc::Text(text) => out.push_str(text),
+            Doc::Concat(parts) => parts.iter().for_each(|part| part.write_flat(out)),
+            Doc::List {
+                open, close, items, ..
+            } => {
+                out.push_str(open);
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_flat(out);
+                }
+                out.push_str(close);
+            }
+        }
+    }
+
+    /// How many anchors the lists in the doc hold
+    fn anchors(&self) -> usize {
+        match self {
+            Doc::Text(_) => 0,
+            Doc::Concat(parts) => parts.iter().map(Doc::anchors).sum(),
+            Doc::List {
+                items, anchored, ..
+            } => {
+                let own = if *anchored { items.len() + 1 } else { 0 };
+                own + items.iter().map(Doc::anchors).sum::<usize>()
+            }
+        }
+    }
+
+    fn flat_width(&self) -> usize {
+        match self {
+            Doc::Text(text) => text.chars().count(),
+            Doc::Concat(parts) => parts.iter().map(Doc::flat_width).sum(),
+            Doc::List {
+                open, close, items, ..
+            } => {
+                let separators = items.len().saturating_sub(1) * 2;
+                open.len()
+                    + close.len()
+                    + separators
+                    + items.iter().map(Doc::flat_width).sum::<usize>()
+            }
+        }
+    }
+}
+
+struct Printer<'a> {
+    options: FormattingOptions,
+    /// `Program::multiline_lists`, consumed in the order the parser filled it
+    multiline: std::slice::Iter<'a, bool>,
+    trivia: Option<Trivia<'a>>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(options: FormattingOptions, multiline: &'a [bool]) -> Self {
+        Self {
+            options,
+            multiline: multiline.iter(),
+            trivia: None,
+        }
+    }
+
+    /// Never breaks a line
+    fn flat() -> Self {
+        Self::new(
+            FormattingOptions {
+                max_width: usize::MAX,
+                preserve_line_breaks: false,
+                ..FormattingOptions::default()
+            },
+            &[],
+        )
+    }
+
+    /// Build a list. Its items must already have been built: the parser
+    /// records a list once it has closed it, so flags are taken inner first.
+    fn list(&mut self, open: &'static str, close: &'static str, items: Vec<Doc>) -> Doc {
+        Doc::List {
+            open,
+            close,
+            items,
+            multiline: self.multiline.next().copied().unwrap_or(false),
+            anchored: false,
+        }
+    }
+
+    /// Build a list or object literal, which the parser anchored if it
+    /// spanned several lines
+    fn literal(&mut self, open: &'static str, close: &'static str, items: Vec<Doc>) -> Doc {
+        let mut list = self.list(open, close, items);
+        if let Doc::List {
+            multiline,
+            anchored,
+            ..
+        } = &mut list
+        {
+            *anchored = *multiline;
+        }
+        list
+    }
+
+    fn docs(&mut self, exprs: &[Expr]) -> Vec<Doc> {
+        exprs.iter().map(|expr| self.doc(expr)).collect()
+    }
+
+    fn doc(&mut self, expr: &Expr) -> Doc {
+        match expr {
+            Expr::Literal(value) => text(format_literal(value)),
+            Expr::Interpolation(parts) => text(format_interpolation(parts)),
+            Expr::Identifier { name, .. } => text(name.clone()),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let precedence = operator.precedence();
+                // Operators are left associative, so an equal-precedence right
+                // operand needs parentheses to keep its grouping
+                let left = self.operand(left, |p| p < precedence);
+                let right = self.operand(right, |p| p <= precedence);
+                Doc::Concat(vec![
+                    left,
+                    text(format!(" {} ", binary_symbol(operator))),
+                    right,
+                ])
+            }
+            Expr::Unary {
+                operator, operand, ..
+            } => {
+                let symbol = match operator {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Minus => "-",
+                };
+                Doc::Concat(vec![text(symbol), self.postfix_target(operand)])
+            }
+            Expr::Call { callee, args, .. } => {
+                let callee = self.postfix_target(callee);
+                let args = self.docs(args);
+                Doc::Concat(vec![callee, self.list("(", ")", args)])
+            }
+            Expr::Array(items) => {
+                let items = self.docs(items);
+                self.literal("[", "]", items)
+            }
+            Expr::Tuple(items) => {
+                let items = self.docs(items);
+                match <[Doc; 1]>::try_from(items) {
+                    // The comma is what makes it a tuple; the parser still
+                    // recorded the list, so take its flag
+                    Ok([only]) => {
+                        self.multiline.next();
+                        Doc::Concat(vec![text("("), only, text(",)")])
+                    }
+                    Err(items) => self.list("(", ")", items),
+                }
+            }
+            Expr::Index {
+                object,
+                index,
+                optional,
+                ..
+            } => Doc::Concat(vec![
+                self.postfix_target(object),
+                text(if *optional { "?.[" } else { "[" }),
+                self.doc(index),
+                text("]"),
+            ]),
+            Expr::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        Doc::Concat(vec![
+                            text(format!("{}: ", format_key(key))),
+                            self.doc(value),
+                        ])
+                    })
+                    .collect();
+                self.literal("{", "}", fields)
+            }
+            Expr::Property {
+                object,
+                property,
+                optional,
+                ..
+            } => Doc::Concat(vec![
+                self.postfix_target(object),
+                text(format!(
+                    "{}{}",
+                    if *optional { "?." } else { "." },
+                    property
+                )),
+            ]),
+            Expr::ModuleAccess { module, function } => text(format!("{}.{}", module, function)),
+            Expr::Await { expression } => {
+                Doc::Concat(vec![text("await "), self.postfix_target(expression)])
+            }
+            Expr::This => text("this"),
+            Expr::Super { method } => text(format!("super.{}", method)),
+            Expr::New { class, args } => {
+                let class = self.postfix_target(class);
+                let args = self.docs(args);
+                Doc::Concat(vec![text("new "), class, self.list("(", ")", args)])
+            }
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                // A conditional condition needs parentheses to stay grouped
+                // to the left; the branches parse as whole expressions
+                let condition = match condition.as_ref() {
+                    Expr::Conditional { .. } => {
+                        Doc::Concat(vec![text("("), self.doc(condition), text(")")])
+                    }
+                    _ => self.operand(condition, |_| false),
+                };
+                let then_expr = self.operand(then_expr, |_| false);
+                let else_expr = self.doc(else_expr);
+                Doc::Concat(vec![
+                    condition,
+                    text(" ? "),
+                    then_expr,
+                    text(" : "),
+                    else_expr,
+                ])
+            }
+            Expr::Lambda {
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                ..
+            } => {
+                let head = signature(
+                    "function",
+                    "",
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                );
+                Doc::Concat(vec![text(head + " "), self.doc(body)])
+            }
+        }
+    }
+
+    /// Format a binary operand, parenthesizing it when `needs_parens` says its
+    /// precedence would otherwise regroup it
+    fn operand(&mut self, expr: &Expr, needs_parens: impl Fn(u8) -> bool) -> Doc {
+        match expr {
+            Expr::Binary { operator, .. } if needs_parens(operator.precedence()) => {
+                Doc::Concat(vec![text("("), self.doc(expr), text(")")])
+            }
+            // A lambda's body or a conditional's else side would swallow the
+            // rest of the expression
+            Expr::Lambda { .. } | Expr::Conditional { .. } => {
+                Doc::Concat(vec![text("("), self.doc(expr), text(")")])
+            }
+            _ => self.doc(expr),
+        }
+    }
+
+    /// Format the target of a call, index, property access or prefix operator
+    fn postfix_target(&mut self, expr: &Expr) -> Doc {
+        match expr {
+            Expr::Binary { .. }
+            | Expr::Unary { .. }
+            | Expr::Await { .. }
+            | Expr::New { .. }
+            | Expr::Conditional { .. }
+            | Expr::Lambda { .. } => Doc::Concat(vec![text("("), self.doc(expr), text(")")]),
+            Expr::Literal(Value::Number(n)) if *n < 0.0 => text(format!("({})", format_expr(expr))),
+            _ => self.doc(expr),
+        }
+    }
+
+    /// Lay out `doc` at the end of `out`, followed by `suffix` more columns
+    /// of text on the same line
+    fn render(&mut self, out: &mut String, doc: &Doc, depth: usize, suffix: usize) {
+        match doc {
+            Doc::Text(text) => out.push_str(text),
+            Doc::Concat(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    let rest: usize = parts[i + 1..].iter().map(Doc::flat_width).sum();
+                    self.render(out, part, depth, rest + suffix);
+                }
+            }
+            Doc::List {
+                open,
+                close,
+                items,
+                multiline,
+                anchored,
+            } => {
+                let column = out
+                    .chars()
+                    .rev()
+                    .take_while(|&c| c != '\n')
+                    .map(|c| if c == '\t' { self.options.tab_size } else { 1 })
+                    .sum::<usize>();
+                let fits = column + doc.flat_width() + suffix <= self.options.max_width;
+                let keep_broken = *multiline && self.options.preserve_line_breaks;
+                if items.is_empty() || (fits && !keep_broken) {
+                    let anchors = doc.anchors();
+                    self.with_trivia(|trivia| trivia.skip(anchors));
+                    doc.write_flat(out);
+                    return;
+                }
+
+                let pad = self.indent(depth + 1);
+                out.push_str(open);
+                out.push('\n');
+                if *anchored {
+                    self.open();
+                }
+                for item in items {
+                    let trailing = if *anchored {
+                        self.anchor(out, depth + 1)
+                    } else {
+                        None
+                    };
+                    let start = out.len();
+                    out.push_str(&pad);
+                    self.render(out, item, depth + 1, 1);
+                    out.push_str(",\n");
+                    end_first_line(out, start, trailing);
+                }
+                if *anchored {
+                    self.anchor(out, depth + 1);
+                }
+                out.push_str(&self.indent(depth));
+                out.push_str(close);
+            }
+        }
+    }
+
+    fn indent(&self, depth: usize) -> String {
+        if self.options.use_tabs {
+            "\t".repeat(depth)
+        } else {
+            " ".repeat(self.options.tab_size * depth)
+        }
+    }
+
+    fn with_trivia<R: Default>(&mut self, f: impl FnOnce(&mut Trivia<'a>) -> R) -> R {
+        self.trivia.as_mut().map(f).unwrap_or_default()
+    }
+
+    /// Before what the next anchor marks, at `depth`: the comments above it,
+    /// and those beside it to end its first line with
+    fn anchor(&mut self, out: &mut String, depth: usize) -> Option<String> {
+        let indent = self.indent(depth);
+        self.with_trivia(|trivia| trivia.anchor(out, &indent))
+    }
+
+    /// After a `{` or a header: no blank line before what comes next
+    fn open(&mut self) {
+        self.with_trivia(|trivia| trivia.open());
+    }
+
+    /// The `}` closing a block at `depth`, after the comments inside it
+    fn close(&mut self, out: &mut String, depth: usize) {
+        let trailing = self.anchor(out, depth + 1);
+        let start = out.len();
+        self.line(out, depth, vec![text("}")]);
+        end_first_line(out, start, trailing);
+    }
+
+    /// Write `parts` indented to `depth`, without ending the line
+    fn start_line(&mut self, out: &mut String, depth: usize, parts: Vec<Doc>) {
+        out.push_str(&self.indent(depth));
+        self.render(out, &Doc::Concat(parts), depth, 0);
+    }
+
+    fn line(&mut self, out: &mut String, depth: usize, parts: Vec<Doc>) {
+        self.start_line(out, depth, parts);
+        out.push('\n');
+    }
+
+    /// One `@decorator` line each, in source order
+    fn write_decorators(&mut self, out: &mut String, decorators: &[Expr], depth: usize) {
+        for decorator in decorators {
+            let decorator = self.doc(decorator);
+            self.line(out, depth, vec![text("@"), decorator]);
+        }
+    }
+
+    fn write_stmt(&mut self, out: &mut String, stmt: &Stmt, depth: usize) {
+        let trailing = self.anchor(out, depth);
+        let start = out.len();
+        self.write_stmt_text(out, stmt, depth);
+        end_first_line(out, start, trailing);
+    }
+
+    fn write_stmt_text(&mut self, out: &mut String, stmt: &Stmt, depth: usize) {
+        match stmt {
+            Stmt::Expression(expr) => {
+                let expr = self.doc(expr);
+                self.line(out, depth, vec![expr]);
+            }
+            Stmt::Let {
+                name,
+                type_annotation,
+                value,
+            } => {
+                let value = self.doc(value);
+                let head = format!("let {}{} = ", name, annotation(type_annotation));
+                self.line(out, depth, vec![text(head), value]);
+            }
+            Stmt::LetTuple {
+                pattern,
+                type_annotation,
+                value,
+                ..
+            } => {
+                let value = self.doc(value);
+                let head = format!(
+                    "let {}{} = ",
+                    format_pattern(pattern),
+                    annotation(type_annotation)
+                );
+                self.line(out, depth, vec![text(head), value]);
+            }
+            Stmt::If {
+                condition,
+                then_stmt,
+                else_stmt,
+            } => {
+                let condition = self.doc(condition);
+                self.start_line(out, depth, vec![text("if "), condition, text(":")]);
+                self.write_body(out, then_stmt, depth);
+                if let Some(else_stmt) = else_stmt {
+                    self.start_line(out, depth, vec![text("else:")]);
+                    self.write_body(out, else_stmt, depth);
+                }
+            }
+            Stmt::While { condition, body } => {
+                let condition = self.doc(condition);
+                self.start_line(out, depth, vec![text("while "), condition, text(":")]);
+                self.write_body(out, body, depth);
+            }
+            Stmt::For {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                let start = self.doc(start);
+                let end = self.doc(end);
+                self.start_line(
+                    out,
+                    depth,
+                    vec![
+                        text(format!("for {} in range(", var)),
+                        start,
+                        text(", "),
+                        end,
+                        text("):"),
+                    ],
+                );
+                self.write_body(out, body, depth);
+            }
+            Stmt::ForIn {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                let iterable = self.doc(iterable);
+                self.start_line(
+                    out,
+                    depth,
+                    vec![
+                        text(format!("for {} in ", format_pattern(pattern))),
+                        iterable,
+                        text(":"),
+                    ],
+                );
+                self.write_body(out, body, depth);
+            }
+            Stmt::Block(statements) => {
+                self.line(out, depth, vec![text("{")]);
+                self.open();
+                for stmt in statements {
+                    self.write_stmt(out, stmt, depth + 1);
+                }
+                self.close(out, depth);
+            }
+            Stmt::Print(expr) => {
+                let expr = self.doc(expr);
+                self.line(out, depth, vec![text("print("), expr, text(")")]);
+            }
+            Stmt::Return(value) => match value {
+                Some(value) => {
+                    let value = self.doc(value);
+                    self.line(out, depth, vec![text("return "), value]);
+                }
+                None => self.line(out, depth, vec![text("return")]),
+            },
+            Stmt::Break { .. } => self.line(out, depth, vec![text("break")]),
+            Stmt::Continue { .. } => self.line(out, depth, vec![text("continue")]),
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                decorators,
+            } => {
+                self.write_decorators(out, decorators, depth);
+                let head = signature(
+                    "function ",
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                );
+                self.start_line(out, depth, vec![text(head)]);
+                self.write_body(out, body, depth);
+            }
+            Stmt::AsyncFunction {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+            } => {
+                let head = signature(
+                    "async function ",
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                );
+                self.start_line(out, depth, vec![text(head)]);
+                self.write_body(out, body, depth);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+            } => {
+                let head = match superclass {
+                    Some(superclass) => format!("class {} extends {}:", name, superclass),
+                    None => format!("class {}:", name),
+                };
+                self.line(out, depth, vec![text(head)]);
+                self.line(out, depth, vec![text("{")]);
+                self.open();
+                // Members in source order when it is known, fields first
+                // otherwise
+                let (mut field, mut method) = (0, 0);
+                while field < fields.len() || method < methods.len() {
+                    let method_next = field == fields.len()
+                        || (method < methods.len()
+                            && matches!(
+                                self.trivia.as_ref().and_then(Trivia::peek),
+                                Some(Anchor::Method(_))
+                            ));
+                    let trailing = self.anchor(out, depth + 1);
+                    let start = out.len();
+                    if method_next {
+                        let method_decl = &methods[method];
+                        method += 1;
+                        let head = signature(
+                            "",
+                            &method_decl.name,
+                            &method_decl.params,
+                            &method_decl.param_types,
+                            &method_decl.optional_params,
+                            &method_decl.return_type,
+                        );
+                        self.start_line(out, depth + 1, vec![text(head)]);
+                        self.write_body(out, &method_decl.body, depth + 1);
+                    } else {
+                        let (name, value) = &fields[field];
+                        field += 1;
+                        let value = self.doc(value);
+                        self.line(out, depth + 1, vec![text(format!("{} = ", name)), value]);
+                    }
+                    end_first_line(out, start, trailing);
+                }
+                self.close(out, depth);
+            }
+            Stmt::Try {
+                try_block,
+                catch_var,
+                catch_block,
+            } => {
+                self.start_line(out, depth, vec![text("try:")]);
+                self.write_body(out, try_block, depth);
+                self.start_line(out, depth, vec![text(format!("catch {}:", catch_var))]);
+                self.write_body(out, catch_block, depth);
+            }
+            Stmt::Assignment { target, value, .. } => {
+                let mut parts = match target {
+                    AssignmentTarget::Identifier(name) => vec![text(name.clone())],
+                    AssignmentTarget::Property { object, property } => {
+                        vec![self.postfix_target(object), text(format!(".{}", property))]
+                    }
+                    AssignmentTarget::Index { object, index } => vec![
+                        self.postfix_target(object),
+                        text("["),
+                        self.doc(index),
+                        text("]"),
+                    ],
+                };
+                parts.push(text(" = "));
+                parts.push(self.doc(value));
+                self.line(out, depth, parts);
+            }
+            Stmt::Import {
+                module_path,
+                items,
+                alias,
+            } => {
+                let path = format_literal(&Value::String(module_path.clone()));
+                let alias = alias
+                    .as_ref()
+                    .map(|alias| format!(" as {}", alias))
+                    .unwrap_or_default();
+                let line = match items {
+                    ImportItems::All if alias.is_empty() => format!("import * from {}", path),
+                    ImportItems::All => format!("import {}{}", path, alias),
+                    ImportItems::Named(names) => {
+                        let names: Vec<String> = names
+                            .iter()
+                            .map(|item| match &item.alias {
+                                Some(alias) => format!("{} as {}", item.name, alias),
+                                None => item.name.clone(),
+                            })
+                            .collect();
+                        format!("import {{{}}} from {}", names.join(", "), path)
+                    }
+                    ImportItems::Default(name) => {
+                        format!("import {}{} from {}", name, alias, path)
+                    }
+                };
+                self.line(out, depth, vec![text(line)]);
+            }
+            Stmt::Export { item } => match item {
+                ExportItem::Function {
+                    name,
+                    params,
+                    param_types,
+                    optional_params,
+                    return_type,
+                    body,
+                    decorators,
+                } => {
+                    self.write_decorators(out, decorators, depth);
+                    let head = signature(
+                        "export function ",
+                        name,
+                        params,
+                        param_types,
+                        optional_params,
+                        return_type,
+                    );
+                    self.start_line(out, depth, vec![text(head)]);
+                    self.write_body(out, body, depth);
+                }
+                ExportItem::Variable {
+                    name,
+                    type_annotation,
+                    value,
+                } => {
+                    let value = self.doc(value);
+                    let head = format!("export let {}{} = ", name, annotation(type_annotation));
+                    self.line(out, depth, vec![text(head), value]);
+                }
+            },
+            Stmt::Enum { name, members } => {
+                let line = format!("enum {}: {}", name, members.join(", "));
+                self.line(out, depth, vec![text(line)]);
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+            } => {
+                let subject = self.doc(subject);
+                self.line(out, depth, vec![text("match "), subject, text(":")]);
+                self.line(out, depth, vec![text("{")]);
+                self.open();
+                for arm in arms {
+                    let trailing = self.anchor(out, depth + 1);
+                    let start = out.len();
+                    let mut parts = Vec::new();
+                    for (i, pattern) in arm.patterns.iter().enumerate() {
+                        if i > 0 {
+                            parts.push(text(", "));
+                        }
+                        parts.push(self.doc(pattern));
+                    }
+                    parts.push(text(":"));
+                    self.start_line(out, depth + 1, parts);
+                    self.write_body(out, &arm.body, depth + 1);
+                    end_first_line(out, start, trailing);
+                }
+                if let Some(default) = default {
+                    let trailing = self.anchor(out, depth + 1);
+                    let start = out.len();
+                    self.start_line(out, depth + 1, vec![text("else:")]);
+                    self.write_body(out, default, depth + 1);
+                    end_first_line(out, start, trailing);
+                }
+                self.close(out, depth);
+            }
+        }
+    }
+
+    /// Write the body after a `header:` line. Single statements go on the next
+    /// line indented; blocks keep their braces at the header's indentation.
+    fn write_body(&mut self, out: &mut String, body: &Stmt, depth: usize) {
+        out.push('\n');
+        self.open();
+        match body {
+            Stmt::Block(_) => self.write_stmt(out, body, depth),
+            _ => self.write_stmt(out, body, depth + 1),
+        }
+    }
+}
+
+/// End the first line written to `out` from `start` with `comment`
+fn end_first_line(out: &mut String, start: usize, comment: Option<String>) {
+    if let Some(comment) = comment {
+        let end = out[start..].find('\n').map_or(out.len(), |i| start + i);
+        out.insert_str(end, &format!("  {}", comment));
+    }
+}
+
+fn signature(
+    keyword: &str,
+    name: &str,
+    params: &[String],
+    param_types: &[Option<Type>],
+    optional_params: &OptionalParams,
+    return_type: &Option<Type>,
+) -> String {
+    let first_default = optional_params.required(params.len());
+    let params: Vec<String> = params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let ty = param_types.get(i).cloned().flatten();
+            let annotated = format!("{}{}", param, annotation(&ty));
+            if i < first_default {
+                annotated
+            } else if let Some(default) = optional_params.defaults.get(i - first_default) {
+                format!("{} = {}", annotated, format_expr(default))
+            } else {
+                format!("...{}", annotated)
+            }
+        })
+        .collect();
+    let return_type = match return_type {
+        Some(ty) if *ty != Type::Any => format!(" -> {}", format_type(ty)),
+        _ => String::new(),
+    };
+    format!("{}{}({}){}:", keyword, name, params.join(", "), return_type)
+}
+
+/// `: type` for an annotation; `any` is what an omitted annotation means
+fn annotation(ty: &Option<Type>) -> String {
+    match ty {
+        Some(ty) if *ty != Type::Any => format!(": {}", format_type(ty)),
+        _ => String::new(),
+    }
+}
+
+fn format_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    if is_identifier {
+        key.to_string()
+    } else {
+        format_literal(&Value::String(key.to_string()))
+    }
+}
+
+fn format_literal(value: &Value) -> String {
+    match value {
+        Value::Number(n) => n.to_string(),
+        Value::BigInt(n) => format!("{}n", n),
+        Value::String(s) => {
+            let mut quoted = String::with_capacity(s.len() + 2);
+            quoted.push('"');
+            push_escaped(&mut quoted, s);
+            quoted.push('"');
+            quoted
+        }
+        Value::Boolean(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        // Only the scalar literals above come out of the parser
+        other => other.to_string(),
+    }
+}
+
+fn format_interpolation(parts: &[StringPart]) -> String {
+    let mut quoted = String::from('"');
+    for part in parts {
+        match part {
+            StringPart::Text(text) => push_escaped(&mut quoted, text),
+            StringPart::Expr(expr) => {
+                quoted.push_str("${");
+                quoted.push_str(&format_expr(expr));
+                quoted.push('}');
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `text` as it is written inside a string literal
+fn push_escaped(quoted: &mut String, text: &str) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            // Text that reads as an interpolation is written escaped
+            '$' if chars.peek() == Some(&'{') => quoted.push_str("\\$"),
+            _ => quoted.push(c),
+        }
+    }
+}
+
+fn binary_symbol(operator: &BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "+",
+        BinaryOp::Subtract => "-",
+        BinaryOp::Multiply => "*",
+        BinaryOp::Divide => "/",
+        BinaryOp::Modulo => "%",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::Less => "<",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::Greater => ">",
+        BinaryOp::GreaterEqual => ">=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+        BinaryOp::Coalesce => "??",
+    }
+}
@@ -1,11 +1,15 @@
 use crate::core::Value;
+use std::borrow::Cow;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum TokenType {
+pub enum TokenType<'src> {
     // Literals
     Number(f64),
-    String(String),
-    Identifier(String),
+    BigInt(Cow<'src, str>), // Digits of an `n`-suffixed integer literal
+    String(Cow<'src, str>), // Borrowed unless the literal contained escapes
+    /// A string literal with `${...}` in it
+    InterpolatedString(Vec<StringPart<'src>>),
+    Identifier(Cow<'src, str>),
 
     // Keywords
     Let,
@@ -20,6 +24,8 @@ pub enum TokenType {
     Null,
     Print,
     Return,
+    Break,
+    Continue,
     Function,
     Def,     // Alternative function keyword
     Try,     // New: try statement
@@ -59,7 +65,9 @@ pub enum TokenType {
     GreaterEqual,
     And,
     Or,
-    Pipe, // | for union types
+    Pipe,             // | for union types
+    QuestionQuestion, // ?? for null coalescing
+    Question,         // ? in `cond ? a : b`
 
     // Delimiters
     LeftParen,
@@ -72,19 +80,104 @@ pub enum TokenType {
     Colon,
     Semicolon,
     Dot,
+    QuestionDot, // ?. for null-safe access
+    Ellipsis,    // ... before a rest parameter
+    At,          // @ before a decorator line
     Newline,
 
     // End of file
     Eof,
 }
 
-impl TokenType {
+impl<'src> TokenType<'src> {
+    /// Detach the token type from the source it was lexed from
+    pub fn into_owned(self) -> TokenType<'static> {
+        match self {
+            TokenType::Number(n) => TokenType::Number(n),
+            TokenType::BigInt(s) => TokenType::BigInt(Cow::Owned(s.into_owned())),
+            TokenType::String(s) => TokenType::String(Cow::Owned(s.into_owned())),
+            TokenType::InterpolatedString(parts) => TokenType::InterpolatedString(
+                parts.into_iter().map(StringPart::into_owned).collect(),
+            ),
+            TokenType::Identifier(s) => TokenType::Identifier(Cow::Owned(s.into_owned())),
+            TokenType::Let => TokenType::Let,
+            TokenType::If => TokenType::If,
+            TokenType::Else => TokenType::Else,
+            TokenType::While => TokenType::While,
+            TokenType::For => TokenType::For,
+            TokenType::In => TokenType::In,
+            TokenType::Range => TokenType::Range,
+            TokenType::True => TokenType::True,
+            TokenType::False => TokenType::False,
+            TokenType::Null => TokenType::Null,
+            TokenType::Print => TokenType::Print,
+            TokenType::Return => TokenType::Return,
+            TokenType::Break => TokenType::Break,
+            TokenType::Continue => TokenType::Continue,
+            TokenType::Function => TokenType::Function,
+            TokenType::Def => TokenType::Def,
+            TokenType::Try => TokenType::Try,
+            TokenType::Catch => TokenType::Catch,
+            TokenType::Import => TokenType::Import,
+            TokenType::Export => TokenType::Export,
+            TokenType::From => TokenType::From,
+            TokenType::As => TokenType::As,
+            TokenType::Async => TokenType::Async,
+            TokenType::Await => TokenType::Await,
+            TokenType::Class => TokenType::Class,
+            TokenType::Extends => TokenType::Extends,
+            TokenType::This => TokenType::This,
+            TokenType::Super => TokenType::Super,
+            TokenType::Init => TokenType::Init,
+            TokenType::New => TokenType::New,
+            TokenType::Arrow => TokenType::Arrow,
+            TokenType::NumberType => TokenType::NumberType,
+            TokenType::StringType => TokenType::StringType,
+            TokenType::BooleanType => TokenType::BooleanType,
+            TokenType::Plus => TokenType::Plus,
+            TokenType::Minus => TokenType::Minus,
+            TokenType::Star => TokenType::Star,
+            TokenType::Slash => TokenType::Slash,
+            TokenType::Percent => TokenType::Percent,
+            TokenType::Equal => TokenType::Equal,
+            TokenType::EqualEqual => TokenType::EqualEqual,
+            TokenType::Bang => TokenType::Bang,
+            TokenType::BangEqual => TokenType::BangEqual,
+            TokenType::Less => TokenType::Less,
+            TokenType::LessEqual => TokenType::LessEqual,
+            TokenType::Greater => TokenType::Greater,
+            TokenType::GreaterEqual => TokenType::GreaterEqual,
+            TokenType::And => TokenType::And,
+            TokenType::Or => TokenType::Or,
+            TokenType::Pipe => TokenType::Pipe,
+            TokenType::QuestionQuestion => TokenType::QuestionQuestion,
+            TokenType::Question => TokenType::Question,
+            TokenType::LeftParen => TokenType::LeftParen,
+            TokenType::RightParen => TokenType::RightParen,
+            TokenType::LeftBrace => TokenType::LeftBrace,
+            TokenType::RightBrace => TokenType::RightBrace,
+            TokenType::LeftBracket => TokenType::LeftBracket,
+            TokenType::RightBracket => TokenType::RightBracket,
+            TokenType::Comma => TokenType::Comma,
+            TokenType::Colon => TokenType::Colon,
+            TokenType::Semicolon => TokenType::Semicolon,
+            TokenType::Dot => TokenType::Dot,
+            TokenType::QuestionDot => TokenType::QuestionDot,
+            TokenType::Ellipsis => TokenType::Ellipsis,
+            TokenType::At => TokenType::At,
+            TokenType::Newline => TokenType::Newline,
+            TokenType::Eof => TokenType::Eof,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn is_literal(&self) -> bool {
         matches!(
             self,
             TokenType::Number(_)
+                | TokenType::BigInt(_)
                 | TokenType::String(_)
+                | TokenType::InterpolatedString(_)
                 | TokenType::True
                 | TokenType::False
                 | TokenType::Null
@@ -107,6 +200,8 @@ impl TokenType {
                 | TokenType::Null
                 | TokenType::Print
                 | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
                 | TokenType::Function
                 | TokenType::Def
                 | TokenType::Try
@@ -146,6 +241,7 @@ impl TokenType {
                 | TokenType::And
                 | TokenType::Or
                 | TokenType::Pipe
+                | TokenType::QuestionQuestion
         )
     }
 
@@ -153,7 +249,8 @@ impl TokenType {
     pub fn to_literal_value(&self) -> Option<Value> {
         match self {
             TokenType::Number(n) => Some(Value::Number(*n)),
-            TokenType::String(s) => Some(Value::String(s.clone())),
+            TokenType::BigInt(digits) => digits.parse().ok().map(Value::BigInt),
+            TokenType::String(s) => Some(Value::String(s.to_string())),
             TokenType::True => Some(Value::Boolean(true)),
             TokenType::False => Some(Value::Boolean(false)),
             TokenType::Null => Some(Value::Null),
@@ -162,17 +259,51 @@ impl TokenType {
     }
 }
 
+/// A piece of an interpolated string literal
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart<'src> {
+    /// Text with its escapes already processed
+    Text(Cow<'src, str>),
+    /// The source between `${` and `}`, and where that source starts
+    Code {
+        source: Cow<'src, str>,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl StringPart<'_> {
+    pub fn into_owned(self) -> StringPart<'static> {
+        match self {
+            StringPart::Text(text) => StringPart::Text(Cow::Owned(text.into_owned())),
+            StringPart::Code {
+                source,
+                line,
+                column,
+            } => StringPart::Code {
+                source: Cow::Owned(source.into_owned()),
+                line,
+                column,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct Token {
-    pub token_type: TokenType,
+pub struct Token<'src> {
+    pub token_type: TokenType<'src>,
     pub line: usize,
     pub column: usize,
-    #[allow(dead_code)]
-    pub lexeme: String,
+    pub lexeme: Cow<'src, str>,
 }
 
-impl Token {
-    pub fn new(token_type: TokenType, line: usize, column: usize, lexeme: String) -> Self {
+impl<'src> Token<'src> {
+    pub fn new(
+        token_type: TokenType<'src>,
+        line: usize,
+        column: usize,
+        lexeme: Cow<'src, str>,
+    ) -> Self {
         Self {
             token_type,
             line,
@@ -182,10 +313,29 @@ impl Token {
     }
 
     pub fn eof(line: usize, column: usize) -> Self {
-        Self::new(TokenType::Eof, line, column, String::new())
+        Self::new(TokenType::Eof, line, column, Cow::Borrowed(""))
     }
 
     pub fn is_eof(&self) -> bool {
         matches!(self.token_type, TokenType::Eof)
     }
+
+    /// Detach the token from the source it was lexed from
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            token_type: self.token_type.into_owned(),
+            line: self.line,
+            column: self.column,
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+        }
+    }
+}
+
+/// A comment, with the position of its first character. The parser never
+/// sees comments; the formatter puts them back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment<'src> {
+    pub text: &'src str,
+    pub line: usize,
+    pub column: usize,
 }
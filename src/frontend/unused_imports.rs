@@ -0,0 +1,295 @@
+//! Imported names that nothing in a file refers to, and the edit that drops
+//! them. `infra fix` applies the edit and the language server offers it as a
+//! quick fix, so both remove the same imports in the same way.
+//!
+//! This works on the token stream, so files that do not parse are still
+//! checked. Names are matched by spelling across the whole file, which errs
+//! on the side of treating an import as used.
+
+use crate::frontend::{Lexer, StringPart, Token, TokenType};
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// An import statement with bindings that nothing uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedImport {
+    /// Where the first unused binding is, counted from 1
+    pub line: usize,
+    pub column: usize,
+    /// The unused names, as the file refers to them
+    pub names: Vec<String>,
+    /// Bytes of the source to replace: the braces of a named import, or
+    /// the whole line when no binding is left
+    pub range: Range<usize>,
+    /// The braces without the unused items, or nothing
+    pub replacement: String,
+}
+
+impl UnusedImport {
+    /// `unused import 'a'`, or `unused imports 'a', 'b'`
+    pub fn message(&self) -> String {
+        let names: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| format!("'{}'", name))
+            .collect();
+        format!(
+            "unused import{} {}",
+            if names.len() == 1 { "" } else { "s" },
+            names.join(", ")
+        )
+    }
+}
+
+/// Unused imports of `source`, counting any mention of a name outside the
+/// imports as a use. Source that does not lex has none.
+#[allow(dead_code)]
+pub fn find(source: &str) -> Vec<UnusedImport> {
+    let Ok(tokens) = Lexer::new(source).tokenize() else {
+        return Vec::new();
+    };
+    let used = mentioned_names(&tokens);
+    find_with(source, &tokens, |name| used.contains(name))
+}
+
+/// Unused imports of `source`, lexed as `tokens`, where `is_used` says
+/// whether anything refers to a name
+pub fn find_with(
+    source: &str,
+    tokens: &[Token],
+    is_used: impl Fn(&str) -> bool,
+) -> Vec<UnusedImport> {
+    let offsets = Offsets::new(source, tokens);
+    let mut found = Vec::new();
+
+    for statement in import_statements(tokens) {
+        let unused: Vec<usize> = statement
+            .bindings
+            .iter()
+            .copied()
+            .filter(|&binding| !is_used(name(tokens, binding)))
+            .collect();
+        if unused.is_empty() {
+            continue;
+        }
+
+        let (range, replacement) = match statement.braces {
+            Some((open, close)) if unused.len() < statement.bindings.len() => {
+                // Keep the remaining items as written, aliases included
+                let kept: Vec<&str> = import_items(tokens, &statement, open, close)
+                    .into_iter()
+                    .filter(|(binding, _, _)| !unused.contains(binding))
+                    .map(|(_, first, last)| &source[offsets.start(first)..offsets.end(last)])
+                    .collect();
+                (
+                    offsets.start(open)..offsets.end(close),
+                    format!("{{{}}}", kept.join(", ")),
+                )
+            }
+            _ => match offsets.line(statement.start) {
+                Some(range) => (range, String::new()),
+                None => continue,
+            },
+        };
+
+        let first = &tokens[unused[0]];
+        found.push(UnusedImport {
+            line: first.line,
+            column: first.column,
+            names: unused
+                .iter()
+                .map(|&binding| name(tokens, binding).to_string())
+                .collect(),
+            range,
+            replacement,
+        });
+    }
+    found
+}
+
+/// One `import` statement that binds names
+struct ImportStatement {
+    /// Token index of `import`
+    start: usize,
+    /// Token index of the newline or end of file after it
+    end: usize,
+    /// Token indices of the bound names
+    bindings: Vec<usize>,
+    /// Token indices of `{` and `}` for named imports
+    braces: Option<(usize, usize)>,
+}
+
+fn name<'t>(tokens: &'t [Token], index: usize) -> &'t str {
+    match tokens.get(index).map(|token| &token.token_type) {
+        Some(TokenType::Identifier(name)) => name,
+        _ => "",
+    }
+}
+
+/// The statements that bind names: `import {a, b as c} from`, `import m
+/// from` and `import m as n from`, but not `import * from`
+fn import_statements(tokens: &[Token]) -> Vec<ImportStatement> {
+    let mut statements = Vec::new();
+    let mut index = 0;
+
+    while index < tokens.len() {
+        if !matches!(tokens[index].token_type, TokenType::Import) {
+            index += 1;
+            continue;
+        }
+        let mut statement = ImportStatement {
+            start: index,
+            end: index,
+            bindings: Vec::new(),
+            braces: None,
+        };
+        let mut open = None;
+        let mut in_head = true;
+        index += 1;
+        while let Some(token) = tokens.get(index) {
+            match &token.token_type {
+                TokenType::Newline | TokenType::Eof => break,
+                TokenType::From => in_head = false,
+                TokenType::LeftBrace if in_head => open = Some(index),
+                TokenType::RightBrace if in_head => {
+                    statement.braces = open.map(|open| (open, index))
+                }
+                // The exported name; the alias after `as` is bound
+                TokenType::Identifier(_)
+                    if in_head
+                        && !matches!(
+                            tokens.get(index + 1).map(|next| &next.token_type),
+                            Some(TokenType::As)
+                        ) =>
+                {
+                    statement.bindings.push(index)
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        statement.end = index;
+        if !statement.bindings.is_empty() {
+            statements.push(statement);
+        }
+    }
+    statements
+}
+
+/// The items between `{` and `}` of a named import: the bound name's token
+/// and the first and last token of the whole item
+fn import_items(
+    tokens: &[Token],
+    statement: &ImportStatement,
+    open: usize,
+    close: usize,
+) -> Vec<(usize, usize, usize)> {
+    let mut items = Vec::new();
+    let mut first = open + 1;
+    for (index, token) in tokens.iter().enumerate().take(close + 1).skip(open + 1) {
+        if matches!(token.token_type, TokenType::Comma | TokenType::RightBrace) {
+            let binding = (first..index)
+                .rev()
+                .find(|i| statement.bindings.contains(i));
+            if let Some(binding) = binding {
+                items.push((binding, first, index - 1));
+            }
+            first = index + 1;
+        }
+    }
+    items
+}
+
+/// Names mentioned outside import statements, other than property names,
+/// including names read inside `${...}`
+fn mentioned_names(tokens: &[Token]) -> HashSet<String> {
+    let imports = import_statements(tokens);
+    let mut names = HashSet::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if imports
+            .iter()
+            .any(|import| (import.start..import.end).contains(&index))
+        {
+            continue;
+        }
+        match &token.token_type {
+            TokenType::Identifier(name)
+                if index == 0
+                    || !matches!(
+                        tokens[index - 1].token_type,
+                        TokenType::Dot | TokenType::QuestionDot
+                    ) =>
+            {
+                names.insert(name.to_string());
+            }
+            TokenType::InterpolatedString(parts) => interpolated_names(parts, &mut names),
+            _ => {}
+        }
+    }
+    names
+}
+
+fn interpolated_names(parts: &[StringPart], names: &mut HashSet<String>) {
+    for part in parts {
+        let StringPart::Code { source, .. } = part else {
+            continue;
+        };
+        if let Ok(tokens) = Lexer::new(source).tokenize() {
+            names.extend(mentioned_names(&tokens));
+        }
+    }
+}
+
+/// Byte offsets of tokens in the source they were lexed from
+struct Offsets<'a> {
+    source: &'a str,
+    tokens: &'a [Token<'a>],
+    /// Byte offset where each line starts; lines are 1-based
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Offsets<'a> {
+    fn new(source: &'a str, tokens: &'a [Token<'a>]) -> Self {
+        let mut line_starts = vec![0, 0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            tokens,
+            line_starts,
+        }
+    }
+
+    /// Byte offset of a token's first character
+    fn start(&self, index: usize) -> usize {
+        let token = &self.tokens[index];
+        let line_start = self
+            .line_starts
+            .get(token.line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source[line_start..]
+            .char_indices()
+            .nth(token.column.saturating_sub(1))
+            .map_or(self.source.len(), |(i, _)| line_start + i)
+    }
+
+    fn end(&self, index: usize) -> usize {
+        self.start(index) + self.tokens[index].lexeme.len()
+    }
+
+    /// The whole line of a token, including its newline, if nothing but
+    /// whitespace precedes the token on it
+    fn line(&self, index: usize) -> Option<Range<usize>> {
+        let line = self.tokens[index].line;
+        let start = self.line_starts[line];
+        if !self.source[start..self.start(index)].trim().is_empty() {
+            return None;
+        }
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        Some(start..end)
+    }
+}
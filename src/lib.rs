@@ -0,0 +1,36 @@
+//! The Infra frontend as a library, for tools such as the language server.
+//!
+//! The interpreter itself is the `infra` binary; with the `parsing` feature
+//! this crate exposes the lexer, parser and the AST they produce, and with
+//! `stdlib` the standard library's modules and functions as well, and an
+//! [`Engine`] for programs that embed the interpreter.
+
+#[cfg(feature = "parsing")]
+pub mod core;
+
+#[cfg(feature = "parsing")]
+pub mod frontend;
+
+#[cfg(feature = "stdlib")]
+pub mod stdlib;
+
+// The standard library calls back into the interpreter, which a library
+// user only reaches through it
+#[cfg(feature = "stdlib")]
+#[allow(dead_code, unused_imports)]
+mod backend;
+
+/// For its type inference, which tools use on expressions they do not run
+#[cfg(feature = "stdlib")]
+pub use backend::Evaluator;
+
+#[cfg(feature = "stdlib")]
+#[allow(dead_code, unused_imports)]
+mod cli;
+
+#[cfg(feature = "stdlib")]
+pub use cli::engine::{Engine, EngineBuilder};
+
+#[cfg(feature = "stdlib")]
+#[allow(dead_code, unused_imports)]
+mod utils;
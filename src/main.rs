@@ -1,4 +1,5 @@
 use std::env;
+use std::path::Path;
 use std::process;
 
 mod backend;
@@ -18,16 +19,123 @@ use cli::{Repl, Runner};
 use utils::{version_info, ErrorReporter};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    stdlib::cancel::install_interrupt_handler();
+
+    // Everything after `--` is the script's, even arguments that look like flags
+    let after_dashes = match args.iter().position(|arg| arg == "--") {
+        Some(pos) => {
+            let rest = args.split_off(pos + 1);
+            args.pop();
+            rest
+        }
+        None => Vec::new(),
+    };
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--keep-temp") {
+        args.remove(pos);
+        stdlib::io::set_keep_temp(true);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--dry-run") {
+        args.remove(pos);
+        stdlib::capability::set_dry_run(true);
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--strict") {
+        args.remove(pos);
+        backend::literal_check::set_strict(true);
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--no-prelude") {
+        args.remove(pos);
+        stdlib::prelude::set_enabled(false);
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--no-color") {
+        args.remove(pos);
+        stdlib::progress::set_no_color(true);
+    }
+
+    let mut output_limits = stdlib::output::OutputLimits::default();
+    if let Some(size) = take_flag_value(&mut args, "--max-print-size") {
+        output_limits.max_print_size = parse_size_flag("--max-print-size", &size);
+    }
+    if let Some(size) = take_flag_value(&mut args, "--max-output") {
+        output_limits.max_output = parse_size_flag("--max-output", &size);
+    }
+    stdlib::output::set_limits(output_limits);
+    let view_threshold = match take_flag_value(&mut args, "--view-threshold") {
+        Some(size) => parse_size_flag("--view-threshold", &size),
+        None => Some(cli::viewer::DEFAULT_VIEW_THRESHOLD),
+    };
+
+    if let Some(path) = take_flag_value(&mut args, "--record") {
+        stdlib::record::start_recording(Path::new(&path));
+    }
+    let matching = match take_flag_value(&mut args, "--replay-match").as_deref() {
+        None | Some("order") => stdlib::record::Matching::Order,
+        Some("key") => stdlib::record::Matching::Key,
+        Some(other) => {
+            eprintln!(
+                "Error: --replay-match expects order or key, not '{}'",
+                other
+            );
+            process::exit(1);
+        }
+    };
+    if let Some(path) = take_flag_value(&mut args, "--replay") {
+        if let Err(err) = stdlib::record::start_replay(Path::new(&path), matching) {
+            error_reporter().report_error(&err);
+            process::exit(1);
+        }
+    }
+
+    let optimize = if let Some(pos) = args.iter().position(|arg| arg == "--opt") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+    let backend = if let Some(pos) = args.iter().position(|arg| arg == "--dump-bytecode") {
+        args.remove(pos);
+        Backend::DumpBytecode
+    } else if let Some(pos) = args.iter().position(|arg| arg == "--vm") {
+        args.remove(pos);
+        Backend::Vm
+    } else if optimize {
+        // Only bytecode is optimized, so --opt alone means the VM
+        Backend::Vm
+    } else {
+        Backend::Interpreter
+    };
+
+    let allow_unhandled_rejections = if let Some(pos) = args
+        .iter()
+        .position(|arg| arg == "--allow-unhandled-rejections")
+    {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
 
     if args.len() < 2 {
         show_usage(&args[0]);
         process::exit(1);
     }
 
+    // The script sees what follows its file name or code, then what followed `--`
+    let first_script_arg = if matches!(args[1].as_str(), "-e" | "-c") {
+        3
+    } else {
+        2
+    };
+    let script_args = args.get(first_script_arg..).unwrap_or_default().iter();
+    stdlib::io::set_script_args(script_args.chain(&after_dashes).cloned().collect());
+
     match args[1].as_str() {
         "--repl" | "-r" => {
-            run_repl();
+            run_repl(view_threshold);
         }
         "--version" | "-v" => {
             println!("{}", version_info());
@@ -35,34 +143,506 @@ fn main() {
         "--help" | "-h" => {
             show_help(&args[0]);
         }
+        "analyze" => {
+            run_analyze(&args[2..]);
+        }
+        "fmt" => {
+            run_fmt(&args[2..]);
+        }
+        "check" => {
+            run_check(&args[2..]);
+        }
+        "fix" => {
+            run_fix(&args[2..]);
+        }
+        "--test" => {
+            run_tests(&args[2..]);
+        }
+        "-e" | "-c" => {
+            let Some(code) = args.get(2) else {
+                eprintln!("Error: {} requires an argument", args[1]);
+                process::exit(1);
+            };
+            run_fragment(code, args[1] == "-e", allow_unhandled_rejections);
+        }
+        filename if backend != Backend::Interpreter => {
+            run_file_vm(filename, backend == Backend::DumpBytecode, optimize);
+        }
         filename => {
-            run_file(filename);
+            run_file(filename, allow_unhandled_rejections);
         }
     }
 }
 
-fn run_file(filename: &str) {
+/// What runs a file
+#[derive(PartialEq)]
+enum Backend {
+    Interpreter,
+    /// The bytecode compiler and VM
+    Vm,
+    /// Print the compiled bytecode without running it
+    DumpBytecode,
+}
+
+/// Remove `flag` and the value after it from the command line
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == flag)?;
+    if pos + 1 >= args.len() {
+        eprintln!("Error: {} requires an argument", flag);
+        process::exit(1);
+    }
+    args.remove(pos);
+    Some(args.remove(pos))
+}
+
+/// A byte count for an output limit, exiting on anything unparsable
+fn parse_size_flag(flag: &str, size: &str) -> Option<usize> {
+    stdlib::output::parse_size(size).unwrap_or_else(|| {
+        eprintln!(
+            "Error: {} expects a size such as 4096, 64K or 1M (0 or none for no limit), not '{}'",
+            flag, size
+        );
+        process::exit(1);
+    })
+}
+
+fn run_file(filename: &str, allow_unhandled_rejections: bool) {
+    let mut runner = Runner::new();
+    let mut error_reporter = error_reporter();
+
+    let result = runner.run_file(filename);
+    cleanup_temp_paths();
+    save_recording(&mut error_reporter);
+
+    if let Err(err) = result {
+        let mut error_reporter = error_reporter.with_source(runner.source());
+        error_reporter.report_error(&err);
+        process::exit(1);
+    }
+    if report_promises(&mut error_reporter) && !allow_unhandled_rejections {
+        process::exit(1);
+    }
+}
+
+fn run_file_vm(filename: &str, dump: bool, optimize: bool) {
     let mut runner = Runner::new();
-    let mut error_reporter = ErrorReporter::new();
 
-    if let Err(err) = runner.run_file(filename) {
+    let result = runner.run_file_vm(filename, dump, optimize);
+    cleanup_temp_paths();
+
+    if let Err(err) = result {
+        let mut error_reporter = error_reporter().with_source(runner.source());
         error_reporter.report_error(&err);
         process::exit(1);
     }
 }
 
-fn run_repl() {
+fn run_fragment(code: &str, print_value: bool, allow_unhandled_rejections: bool) {
+    let mut runner = Runner::new();
+    let mut error_reporter = error_reporter();
+
+    let result = if print_value {
+        runner.evaluate_code(code).map(|value| {
+            let precision = runner.get_interpreter().display_precision();
+            stdlib::output::print_value(value.display(precision))
+        })
+    } else {
+        runner.execute_code(code)
+    };
+    cleanup_temp_paths();
+    save_recording(&mut error_reporter);
+
+    if let Err(err) = result {
+        let mut error_reporter = error_reporter.with_source(Some(code));
+        error_reporter.report_error(&err);
+        process::exit(1);
+    }
+    if report_promises(&mut error_reporter) && !allow_unhandled_rejections {
+        process::exit(1);
+    }
+}
+
+fn run_analyze(args: &[String]) {
+    let mut entry = None;
+    let mut format = "text";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => match iter.next().map(String::as_str) {
+                Some(f @ ("text" | "dot" | "json")) => format = f,
+                _ => {
+                    eprintln!("Error: --format expects one of: text, dot, json");
+                    process::exit(1);
+                }
+            },
+            path => entry = Some(path),
+        }
+    }
+
+    let Some(entry) = entry else {
+        eprintln!("Error: analyze requires an entry file");
+        process::exit(1);
+    };
+
+    match cli::analyze::analyze(std::path::Path::new(entry)) {
+        Ok(analysis) => match format {
+            "dot" => print!("{}", analysis.to_dot()),
+            "json" => println!("{}", analysis.to_json()),
+            _ => print!("{}", analysis.to_text()),
+        },
+        Err(err) => {
+            error_reporter().report_error(&err);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_fmt(args: &[String]) {
+    let mut file = None;
+    let mut write = false;
+    let mut check = false;
+    let mut options = frontend::printer::FormattingOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--collapse" => options.preserve_line_breaks = false,
+            "--write" => write = true,
+            "--check" => check = true,
+            "--tabs" => options.use_tabs = true,
+            "--max-width" => match iter.next().and_then(|width| width.parse().ok()) {
+                Some(width) => options.max_width = width,
+                None => {
+                    eprintln!("Error: --max-width expects a number of columns");
+                    process::exit(1);
+                }
+            },
+            "--tab-size" => match iter.next().and_then(|size| size.parse().ok()) {
+                Some(size) => options.tab_size = size,
+                None => {
+                    eprintln!("Error: --tab-size expects a number of columns");
+                    process::exit(1);
+                }
+            },
+            path => file = Some(path),
+        }
+    }
+
+    let Some(file) = file else {
+        eprintln!("Error: fmt requires a file");
+        process::exit(1);
+    };
+
+    let (source, formatted) = match cli::fmt::format_file(std::path::Path::new(file), &options) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            error_reporter().report_error(&err);
+            process::exit(1);
+        }
+    };
+
+    if check {
+        if source != formatted {
+            eprintln!("{} is not formatted", file);
+            process::exit(1);
+        }
+    } else if !write {
+        print!("{}", formatted);
+    } else if let Err(err) = std::fs::write(file, formatted) {
+        eprintln!("Error: could not write {}: {}", file, err);
+        process::exit(1);
+    }
+}
+
+fn run_check(files: &[String]) {
+    if files.is_empty() {
+        eprintln!("Error: check requires at least one file");
+        process::exit(1);
+    }
+
+    let mut found = false;
+    for file in files {
+        let report = match cli::check::check_file(std::path::Path::new(file)) {
+            Ok((_, report)) => report,
+            Err(err) => {
+                error_reporter().report_error(&err);
+                process::exit(1);
+            }
+        };
+        for finding in &report.findings {
+            let warning = finding.severity == backend::literal_check::Severity::Warning;
+            found |= !warning;
+            println!(
+                "{}:{}:{}: {}{} [{}]{}",
+                file,
+                finding.line,
+                finding.column,
+                if warning { "warning: " } else { "" },
+                finding.message,
+                finding.code,
+                if finding.fix.is_some() {
+                    " (fixable)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    if found {
+        process::exit(1);
+    }
+}
+
+fn run_fix(files: &[String]) {
+    if files.is_empty() {
+        eprintln!("Error: fix requires at least one file");
+        process::exit(1);
+    }
+    // --dry-run is taken off the command line before dispatch
+    let dry_run = stdlib::capability::is_dry_run();
+
+    let (mut applied, mut skipped) = (0, 0);
+    for file in files {
+        let outcome = match cli::fix::fix_file(std::path::Path::new(file)) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                error_reporter().report_error(&err);
+                process::exit(1);
+            }
+        };
+
+        for note in &outcome.applied {
+            if !dry_run {
+                println!("{}:{}: fixed: {}", file, note.line, note.title);
+            }
+        }
+        for note in &outcome.skipped {
+            let reason = note.reason.as_deref().unwrap_or("conflict");
+            println!(
+                "{}:{}: skipped: {} ({})",
+                file, note.line, note.title, reason
+            );
+        }
+        applied += outcome.applied.len();
+        skipped += outcome.skipped.len();
+
+        if !outcome.changed() {
+            continue;
+        }
+        if dry_run {
+            print!(
+                "{}",
+                cli::fix::unified_diff(file, &outcome.original, &outcome.fixed)
+            );
+        } else if let Err(err) = std::fs::write(file, &outcome.fixed) {
+            eprintln!("Error: could not write {}: {}", file, err);
+            process::exit(1);
+        }
+    }
+
+    println!(
+        "{} fix{} {}, {} skipped",
+        applied,
+        if applied == 1 { "" } else { "es" },
+        if dry_run {
+            "would be applied"
+        } else {
+            "applied"
+        },
+        skipped
+    );
+}
+
+fn run_tests(args: &[String]) {
+    use cli::test_runner::{self, ChangedSince, TestOptions, TestOutcome};
+
+    let mut options = TestOptions {
+        root: std::path::PathBuf::from("."),
+        changed_since: None,
+        all: false,
+        update_snapshots: false,
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--changed-since" => match iter.next().map(String::as_str) {
+                Some("last-run") => options.changed_since = Some(ChangedSince::LastRun),
+                Some(reference) => {
+                    options.changed_since = Some(ChangedSince::GitRef(reference.to_string()))
+                }
+                None => {
+                    eprintln!("Error: --changed-since expects a git ref or last-run");
+                    process::exit(1);
+                }
+            },
+            "--all" => options.all = true,
+            "--update-snapshots" => options.update_snapshots = true,
+            dir => options.root = std::path::PathBuf::from(dir),
+        }
+    }
+
+    let report = match test_runner::run_tests(&options) {
+        Ok(report) => report,
+        Err(err) => {
+            error_reporter().report_error(&err);
+            process::exit(1);
+        }
+    };
+    cleanup_temp_paths();
+
+    for warning in &report.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    for result in &report.results {
+        match &result.outcome {
+            TestOutcome::Passed => println!("PASS {}", result.name),
+            TestOutcome::Failed(message) => println!("FAIL {}: {}", result.name, message),
+            TestOutcome::Skipped => {}
+        }
+    }
+    println!(
+        "{} passed, {} failed, {} skipped as unchanged",
+        report.passed(),
+        report.failed(),
+        report.skipped()
+    );
+    match report.snapshots_written {
+        0 => {}
+        1 => println!("1 snapshot written"),
+        written => println!("{} snapshots written", written),
+    }
+    if !report.obsolete_snapshots.is_empty() {
+        if options.update_snapshots {
+            println!("Removed obsolete snapshots:");
+        } else {
+            println!("Obsolete snapshots (remove them with --update-snapshots):");
+        }
+        for snapshot in &report.obsolete_snapshots {
+            println!("  {}", snapshot);
+        }
+    }
+
+    if report.failed() > 0 {
+        process::exit(1);
+    }
+}
+
+fn error_reporter() -> ErrorReporter {
+    if stdlib::progress::no_color() {
+        ErrorReporter::new_no_color()
+    } else {
+        ErrorReporter::new()
+    }
+}
+
+fn cleanup_temp_paths() {
+    for path in stdlib::io::cleanup_temp_paths() {
+        eprintln!("Kept temporary path: {}", path);
+    }
+}
+
+/// Write the calls made so far when recording with --record
+fn save_recording(error_reporter: &mut ErrorReporter) {
+    if let Err(err) = stdlib::record::save() {
+        error_reporter.report_error(&err);
+    }
+}
+
+/// Report rejections nobody handled and promises that never settled.
+/// Returns true if there were unhandled rejections.
+fn report_promises(error_reporter: &mut ErrorReporter) -> bool {
+    let report = stdlib::async_mod::take_promise_report();
+
+    for site in &report.unhandled_rejections {
+        error_reporter.report_error(&core::InfraError::RuntimeError {
+            message: format!(
+                "Unhandled promise rejection: {} (promise created by {})",
+                site.error.as_deref().unwrap_or("unknown error"),
+                site.describe()
+            ),
+            line: site.line,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        });
+    }
+
+    if !report.pending.is_empty() {
+        eprintln!(
+            "Warning: {} promise(s) still pending at exit (missing await?):",
+            report.pending.len()
+        );
+        for site in &report.pending {
+            eprintln!("  {}", site.describe());
+        }
+    }
+
+    !report.unhandled_rejections.is_empty()
+}
+
+fn run_repl(view_threshold: Option<usize>) {
     let mut repl = Repl::new();
+    repl.set_view_threshold(view_threshold);
     repl.run();
+    cleanup_temp_paths();
 }
 
 fn show_usage(program_name: &str) {
     println!("{}", version_info());
-    println!("Usage: {} [OPTIONS] <file.infra>", program_name);
+    println!(
+        "Usage: {} [OPTIONS] <file.infra> [-- <script arguments>...]",
+        program_name
+    );
     println!("   or: {} --repl", program_name);
+    println!("   or: {} -e <expression> | -c <code>", program_name);
+    println!(
+        "   or: {} analyze <entry.infra> [--format text|dot|json]",
+        program_name
+    );
+    println!(
+        "   or: {} fmt <file.infra> [--collapse] [--max-width <n>] [--tab-size <n>] [--tabs] [--write | --check]",
+        program_name
+    );
+    println!("   or: {} check <file.infra>...", program_name);
+    println!("   or: {} fix [--dry-run] <file.infra>...", program_name);
+    println!(
+        "   or: {} --test [dir] [--changed-since <git-ref|last-run>] [--all] [--update-snapshots]",
+        program_name
+    );
     println!();
     println!("Options:");
     println!("  -r, --repl      Start interactive REPL");
+    println!("  -e <expr>       Evaluate an expression and print its value");
+    println!("  -c <code>       Execute code without printing a result");
+    println!("  --vm            Run the file on the bytecode VM (a subset of the language)");
+    println!("  --dump-bytecode Print the file's compiled bytecode without running it");
+    println!(
+        "  --opt           Optimize the bytecode first (implies --vm without --dump-bytecode)"
+    );
+    println!("  --keep-temp     Keep temporary files and print their paths");
+    println!("  --dry-run       Log file writes instead of performing them");
+    println!("  --strict        Treat unknown fields in annotated literals as errors");
+    println!("  --no-prelude    Don't make the prelude's helpers available without an import");
+    println!("  --record <file> Record file and process calls and their results");
+    println!("  --replay <file> Answer file and process calls from a recording");
+    println!("  --replay-match order|key");
+    println!("                  Replayed calls must come in recorded order (default)");
+    println!("                  or only match a recorded call's arguments");
+    println!("  --no-color      Disable colors and in-place progress bars");
+    println!("  --max-print-size <size>");
+    println!("                  Cut a single print after this much text (default 1M)");
+    println!("  --max-output <size>");
+    println!("                  Skip prints once a run has printed this much (default 256M)");
+    println!("                  Both only apply when stdout is not a terminal; 0 or none");
+    println!("                  removes the limit");
+    println!("  --view-threshold <size>");
+    println!("                  In the REPL, summarize results longer than this (default 4K)");
+    println!("                  and open them with :view; 0 or none always prints them");
+    println!("  --allow-unhandled-rejections");
+    println!("                  Report unhandled promise rejections without failing");
     println!("  -v, --version   Show version information");
     println!("  -h, --help      Show this help message");
 }
@@ -76,6 +656,11 @@ fn show_help(program_name: &str) {
         "  {} --repl            # Start interactive mode",
         program_name
     );
+    println!("  {} -e \"2 + 3\"          # Print 5", program_name);
+    println!(
+        "  {} tool.infra -- in.txt --fast  # io.args() is [\"in.txt\", \"--fast\"]",
+        program_name
+    );
     println!();
     println!("For more information, visit: https://github.com/infra-lang/infra");
 }
@@ -1,4 +1,5 @@
 use crate::core::{InfraError, Result, Value};
+use std::cmp::Ordering;
 
 /// Get array length
 #[allow(dead_code)]
@@ -244,134 +245,6 @@ pub fn join(args: &[Value]) -> Result<Value> {
     }
 }
 
-/// Map function over array elements (functional programming)
-/// Syntax: array.map(arr, function)
-/// Note: Since we can't pass functions as values yet, this is a placeholder
-/// for when function values are implemented
-#[allow(dead_code)]
-pub fn map(args: &[Value]) -> Result<Value> {
-    if args.len() != 2 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 2,
-            found: args.len(),
-            function_name: Some("array.map".to_string()),
-            line: None,
-        });
-    }
-
-    // For now, we'll implement a basic transformation that doubles numbers
-    // This is a placeholder until function values are implemented
-    match &args[0] {
-        Value::Array(arr) => {
-            let mapped: Result<Vec<Value>> = arr
-                .iter()
-                .map(|v| match v {
-                    Value::Number(n) => Ok(Value::Number(n * 2.0)), // Example: double numbers
-                    _ => Ok(v.clone()),                             // Keep other types unchanged
-                })
-                .collect();
-
-            match mapped {
-                Ok(result_arr) => Ok(Value::Array(result_arr)),
-                Err(e) => Err(e),
-            }
-        }
-        _ => Err(InfraError::TypeError {
-            expected: "array".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("array.map function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
-}
-
-/// Filter array elements based on a condition
-/// For now, filters numbers greater than the second argument
-#[allow(dead_code)]
-pub fn filter(args: &[Value]) -> Result<Value> {
-    if args.len() != 2 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 2,
-            found: args.len(),
-            function_name: Some("array.filter".to_string()),
-            line: None,
-        });
-    }
-
-    match (&args[0], &args[1]) {
-        (Value::Array(arr), Value::Number(threshold)) => {
-            let filtered: Vec<Value> = arr
-                .iter()
-                .filter(|v| match v {
-                    Value::Number(n) => n > threshold,
-                    _ => false, // Non-numbers are filtered out
-                })
-                .cloned()
-                .collect();
-
-            Ok(Value::Array(filtered))
-        }
-        _ => Err(InfraError::TypeError {
-            expected: "array and number".to_string(),
-            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
-            context: Some("array.filter function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
-}
-
-/// Reduce array to a single value (sum for numbers)
-#[allow(dead_code)]
-pub fn reduce(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
-            found: args.len(),
-            function_name: Some("array.reduce".to_string()),
-            line: None,
-        });
-    }
-
-    match &args[0] {
-        Value::Array(arr) => {
-            if arr.is_empty() {
-                return Ok(Value::Number(0.0));
-            }
-
-            // For now, sum all numbers in the array
-            let mut sum = 0.0;
-            for item in arr {
-                match item {
-                    Value::Number(n) => sum += n,
-                    _ => {
-                        return Err(InfraError::RuntimeError {
-                            message: "reduce currently only supports arrays of numbers".to_string(),
-                            line: None,
-                            column: None,
-                            stack_trace: vec![],
-                            source_code: None,
-                        })
-                    }
-                }
-            }
-
-            Ok(Value::Number(sum))
-        }
-        _ => Err(InfraError::TypeError {
-            expected: "array".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("array.reduce function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
-}
-
 /// Find first element matching a condition
 /// For now, finds first number greater than the second argument
 #[allow(dead_code)]
@@ -505,3 +378,155 @@ pub fn last(args: &[Value]) -> Result<Value> {
         }),
     }
 }
+
+/// array.slice(arr, start, end?): the elements from `start` up to, not
+/// including, `end` (default the length). Negative indexes count from the
+/// end, and bounds past either end are clamped.
+#[allow(dead_code)]
+pub fn slice(args: &[Value]) -> Result<Value> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 3,
+            found: args.len(),
+            function_name: Some("array.slice".to_string()),
+            line: None,
+        });
+    }
+
+    let bound = |value: &Value, len: usize| match value {
+        Value::Number(n) if n.fract() == 0.0 => {
+            let index = if *n < 0.0 { len as f64 + n } else { *n };
+            Ok(index.clamp(0.0, len as f64) as usize)
+        }
+        other => Err(InfraError::TypeError {
+            expected: "whole number".to_string(),
+            found: other.type_name().to_string(),
+            context: Some("array.slice function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    };
+
+    match &args[0] {
+        Value::Array(arr) => {
+            let start = bound(&args[1], arr.len())?;
+            let end = match args.get(2) {
+                Some(end) => bound(end, arr.len())?,
+                None => arr.len(),
+            };
+            Ok(Value::Array(arr[start..end.max(start)].to_vec()))
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "array".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("array.slice function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// array.concat(a, b): a new array of the elements of `a` followed by those
+/// of `b`
+#[allow(dead_code)]
+pub fn concat(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("array.concat".to_string()),
+            line: None,
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::Array(first), Value::Array(second)) => {
+            let mut combined = Vec::with_capacity(first.len() + second.len());
+            combined.extend(first.iter().cloned());
+            combined.extend(second.iter().cloned());
+            Ok(Value::Array(combined))
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "array and array".to_string(),
+            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+            context: Some("array.concat function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// array.index_of(arr, value): the index of the first element equal to
+/// `value` as `==` compares them, or -1
+#[allow(dead_code)]
+pub fn index_of(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("array.index_of".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::Array(arr) => Ok(Value::Number(
+            arr.iter()
+                .position(|item| item.script_eq(&args[1]))
+                .map_or(-1.0, |index| index as f64),
+        )),
+        _ => Err(InfraError::TypeError {
+            expected: "array".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("array.index_of function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Stable merge sort with a comparison that can fail. Unlike `slice::sort_by`
+/// it neither needs a consistent order nor panics on one, which a script's
+/// comparison cannot promise; the first error is returned as is.
+pub fn stable_sort_by<F>(items: Vec<Value>, mut compare: F) -> Result<Vec<Value>>
+where
+    F: FnMut(&Value, &Value) -> Result<Ordering>,
+{
+    let mut runs: Vec<Vec<Value>> = items.into_iter().map(|item| vec![item]).collect();
+    while runs.len() > 1 {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(2));
+        let mut pairs = runs.into_iter();
+        while let Some(left) = pairs.next() {
+            match pairs.next() {
+                Some(right) => merged.push(merge_runs(left, right, &mut compare)?),
+                None => merged.push(left),
+            }
+        }
+        runs = merged;
+    }
+    Ok(runs.pop().unwrap_or_default())
+}
+
+fn merge_runs<F>(left: Vec<Value>, right: Vec<Value>, compare: &mut F) -> Result<Vec<Value>>
+where
+    F: FnMut(&Value, &Value) -> Result<Ordering>,
+{
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Take from the right only when strictly smaller, keeping ties in order
+        if compare(a, b)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -1,30 +1,266 @@
-use crate::core::{InfraError, Result, Value};
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use crate::stdlib::{cancel, schedule};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io::Read;
 use std::thread;
 use std::time::Duration;
 
-/// Create a new promise that resolves with a value
-pub fn create_promise(args: &[Value]) -> Result<Value> {
-    // For now, create a promise that immediately resolves
-    // In a full implementation, this would create a pending promise
-    if args.is_empty() {
-        return Err(InfraError::RuntimeError {
-            message: "create_promise requires at least one argument".to_string(),
-            line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
+/// Bookkeeping for one promise created during the run
+struct PromiseRecord {
+    origin: &'static str,
+    line: Option<usize>,
+    state: PromiseState,
+    observed: bool,
+    /// The value a pending promise resolved with later; a promise settled
+    /// when it is made carries its outcome itself
+    value: Option<Value>,
+}
+
+enum PromiseState {
+    Pending,
+    Resolved,
+    Rejected(String),
+}
+
+/// A promise that needs attention when the run ends
+pub struct PromiseSite {
+    pub origin: &'static str,
+    pub line: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl PromiseSite {
+    /// Where the promise came from, e.g. "async.read_file at line 3"
+    pub fn describe(&self) -> String {
+        match self.line {
+            Some(line) => format!("{} at line {}", self.origin, line),
+            None => self.origin.to_string(),
+        }
+    }
+}
+
+/// Promises that were rejected without anyone observing the rejection, and
+/// promises nobody looked at that never settled
+pub struct PromiseReport {
+    pub unhandled_rejections: Vec<PromiseSite>,
+    pub pending: Vec<PromiseSite>,
+}
+
+thread_local! {
+    static PROMISES: RefCell<BTreeMap<usize, PromiseRecord>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_PROMISE_ID: Cell<usize> = const { Cell::new(0) };
+    static CREATION_LINE: Cell<Option<usize>> = const { Cell::new(None) };
+    /// Pending `async.sleep` promises: when each is due, and its id
+    static TIMERS: RefCell<Vec<(u64, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Set the source line attributed to promises created from now on
+pub fn set_creation_line(line: Option<usize>) {
+    CREATION_LINE.with(|l| l.set(line));
+}
+
+/// Forget every tracked promise and timer
+pub fn reset_promises() {
+    PROMISES.with(|promises| promises.borrow_mut().clear());
+    TIMERS.with(|timers| timers.borrow_mut().clear());
+}
+
+/// `promise` as it stands now: a copy made while it was pending does not
+/// see it settle, so this looks it up again
+pub fn current(promise: &Value) -> Value {
+    let Value::Promise { id, .. } = promise else {
+        return promise.clone();
+    };
+    PROMISES.with(|promises| match promises.borrow().get(id) {
+        Some(PromiseRecord {
+            state: PromiseState::Resolved,
+            value: Some(value),
+            ..
+        }) => Value::Promise {
+            id: *id,
+            value: Some(Box::new(value.clone())),
+            resolved: true,
+            rejected: false,
+            error: None,
+        },
+        _ => promise.clone(),
+    })
+}
+
+/// Run timers until `promise` settles, waiting for each on the current
+/// clock, and return it as it then stands. It comes back still pending
+/// when no timer is left that could settle it.
+pub fn wait_for(promise: &Value) -> Result<Value> {
+    loop {
+        let promise = current(promise);
+        if !is_pending(&promise) || !run_next_timer()? {
+            return Ok(promise);
+        }
+    }
+}
+
+fn is_pending(promise: &Value) -> bool {
+    matches!(
+        promise,
+        Value::Promise {
+            resolved: false,
+            rejected: false,
+            ..
+        }
+    )
+}
+
+/// Wait for the next timer and resolve every sleep due by then; false when
+/// there are no timers
+fn run_next_timer() -> Result<bool> {
+    let Some(due) = TIMERS.with(|timers| timers.borrow().iter().map(|(due, _)| *due).min()) else {
+        return Ok(false);
+    };
+    schedule::wait_until(due)?;
+    fire_timers(schedule::now_ms());
+    Ok(true)
+}
+
+/// Resolve the sleeps that are due at `now`
+fn fire_timers(now: u64) {
+    let due: Vec<usize> = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        let due = timers
+            .iter()
+            .filter(|(at, _)| *at <= now)
+            .map(|(_, id)| *id)
+            .collect();
+        timers.retain(|(at, _)| *at > now);
+        due
+    });
+    PROMISES.with(|promises| {
+        let mut promises = promises.borrow_mut();
+        for id in due {
+            if let Some(record) = promises.get_mut(&id) {
+                record.state = PromiseState::Resolved;
+                record.value = Some(Value::Null);
+            }
+        }
+    });
+}
+
+/// Record that a promise's outcome was looked at (awaited or handled)
+pub fn mark_observed(promise: &Value) {
+    if let Value::Promise { id, .. } = promise {
+        PROMISES.with(|promises| {
+            if let Some(record) = promises.borrow_mut().get_mut(id) {
+                record.observed = true;
+            }
         });
     }
+}
+
+/// Where a tracked promise was created
+pub fn site(promise: &Value) -> Option<PromiseSite> {
+    let Value::Promise { id, error, .. } = promise else {
+        return None;
+    };
+    PROMISES.with(|promises| {
+        promises.borrow().get(id).map(|record| PromiseSite {
+            origin: record.origin,
+            line: record.line,
+            error: error.clone(),
+        })
+    })
+}
+
+/// Collect unobserved rejections and unobserved pending promises, in creation
+/// order, and stop tracking everything
+pub fn take_promise_report() -> PromiseReport {
+    let records = PROMISES.with(|promises| std::mem::take(&mut *promises.borrow_mut()));
+    let mut report = PromiseReport {
+        unhandled_rejections: Vec::new(),
+        pending: Vec::new(),
+    };
+
+    for record in records.into_values() {
+        let site = |error| PromiseSite {
+            origin: record.origin,
+            line: record.line,
+            error,
+        };
+        match &record.state {
+            PromiseState::Rejected(error) if !record.observed => {
+                report.unhandled_rejections.push(site(Some(error.clone())))
+            }
+            // A race's losers were looked at; only unwatched ones are news
+            PromiseState::Pending if !record.observed => report.pending.push(site(None)),
+            _ => {}
+        }
+    }
+    report
+}
 
-    let value = args[0].clone();
+fn track(origin: &'static str, state: PromiseState) -> usize {
+    let id = NEXT_PROMISE_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+    let line = CREATION_LINE.with(|l| l.get());
+
+    PROMISES.with(|promises| {
+        promises.borrow_mut().insert(
+            id,
+            PromiseRecord {
+                origin,
+                line,
+                state,
+                observed: false,
+                value: None,
+            },
+        )
+    });
+    id
+}
 
-    // Create a resolved promise
-    Ok(Value::Promise {
-        value: Some(Box::new(value.clone())),
+/// Create a tracked promise that has already resolved
+pub fn resolved_promise(value: Value, origin: &'static str) -> Value {
+    Value::Promise {
+        id: track(origin, PromiseState::Resolved),
+        value: Some(Box::new(value)),
         resolved: true,
         rejected: false,
         error: None,
-    })
+    }
+}
+
+/// Create a tracked promise that has already been rejected
+pub fn rejected_promise(error: String, origin: &'static str) -> Value {
+    Value::Promise {
+        id: track(origin, PromiseState::Rejected(error.clone())),
+        value: None,
+        resolved: false,
+        rejected: true,
+        error: Some(error),
+    }
+}
+
+/// Create a tracked promise that has not settled
+pub fn pending_promise(origin: &'static str) -> Value {
+    Value::Promise {
+        id: track(origin, PromiseState::Pending),
+        value: None,
+        resolved: false,
+        rejected: false,
+        error: None,
+    }
+}
+
+/// Create a new promise that resolves with a value, or a pending promise
+pub fn create_promise(args: &[Value]) -> Result<Value> {
+    // With a value the promise resolves immediately; without one it stays
+    // pending, since nothing can resolve it later yet
+    match args.first() {
+        Some(value) => Ok(resolved_promise(value.clone(), "async.create_promise")),
+        None => Ok(pending_promise("async.create_promise")),
+    }
 }
 
 /// Create a promise that rejects with an error
@@ -45,16 +281,10 @@ pub fn create_rejected_promise(args: &[Value]) -> Result<Value> {
         "Promise rejected".to_string()
     };
 
-    // Create a rejected promise
-    Ok(Value::Promise {
-        value: None,
-        resolved: false,
-        rejected: true,
-        error: Some(error),
-    })
+    Ok(rejected_promise(error, "async.create_rejected_promise"))
 }
 
-/// Sleep for a specified number of milliseconds (async)
+/// A promise that resolves with null after the given number of milliseconds
 pub fn sleep(args: &[Value]) -> Result<Value> {
     if args.is_empty() {
         return Err(InfraError::RuntimeError {
@@ -78,17 +308,14 @@ pub fn sleep(args: &[Value]) -> Result<Value> {
         });
     };
 
-    // For now, simulate async sleep by blocking the thread
-    // In a full implementation, this would return a promise that resolves after the delay
-    thread::sleep(Duration::from_millis(duration));
-
-    // Return a resolved promise with null value
-    Ok(Value::Promise {
-        value: Some(Box::new(Value::Null)),
-        resolved: true,
-        rejected: false,
-        error: None,
-    })
+    // Resolved by the timer once the time has passed; awaiting it waits
+    // only as long as is left by then, so sleeps started together overlap
+    let promise = pending_promise("async.sleep");
+    if let Value::Promise { id, .. } = promise {
+        let due = schedule::now_ms().saturating_add(duration);
+        TIMERS.with(|timers| timers.borrow_mut().push((due, id)));
+    }
+    Ok(promise)
 }
 
 /// Read a file asynchronously (simplified version)
@@ -118,18 +345,11 @@ pub fn read_file_async(args: &[Value]) -> Result<Value> {
     // For now, use synchronous file reading and wrap it in a promise
     // In a full implementation, this would actually read the file asynchronously
     match std::fs::read_to_string(filename) {
-        Ok(content) => Ok(Value::Promise {
-            value: Some(Box::new(Value::String(content))),
-            resolved: true,
-            rejected: false,
-            error: None,
-        }),
-        Err(e) => Ok(Value::Promise {
-            value: None,
-            resolved: false,
-            rejected: true,
-            error: Some(format!("Failed to read file: {}", e)),
-        }),
+        Ok(content) => Ok(resolved_promise(Value::String(content), "async.read_file")),
+        Err(e) => Ok(rejected_promise(
+            format!("Failed to read file: {}", e),
+            "async.read_file",
+        )),
     }
 }
 
@@ -172,66 +392,243 @@ pub fn write_file_async(args: &[Value]) -> Result<Value> {
     // For now, use synchronous file writing and wrap it in a promise
     // In a full implementation, this would actually write the file asynchronously
     match std::fs::write(filename, content) {
-        Ok(_) => Ok(Value::Promise {
-            value: Some(Box::new(Value::Boolean(true))),
-            resolved: true,
-            rejected: false,
-            error: None,
-        }),
-        Err(e) => Ok(Value::Promise {
-            value: None,
-            resolved: false,
-            rejected: true,
-            error: Some(format!("Failed to write file: {}", e)),
-        }),
+        Ok(_) => Ok(resolved_promise(Value::Boolean(true), "async.write_file")),
+        Err(e) => Ok(rejected_promise(
+            format!("Failed to write file: {}", e),
+            "async.write_file",
+        )),
     }
 }
 
-/// Make an HTTP GET request asynchronously (simplified version)
+/// async.http_get(url, options?): a promise of the response, an object with
+/// `status`, `ok`, `headers` and `body`
 pub fn http_get_async(args: &[Value]) -> Result<Value> {
-    if args.is_empty() {
-        return Err(InfraError::RuntimeError {
-            message: "http_get_async requires one argument".to_string(),
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("async.http_get".to_string()),
             line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
         });
     }
+    let url = http_string(&args[0], "url", "async.http_get")?;
+    let timeout_ms = http_timeout(args.get(1), "async.http_get")?;
+    http_request("GET", url, None, Vec::new(), timeout_ms, "async.http_get")
+}
 
-    let url = if let Value::String(url_str) = &args[0] {
-        url_str
-    } else {
-        return Err(InfraError::RuntimeError {
-            message: "http_get_async argument must be a string".to_string(),
+/// async.http_post(url, body, headers?, options?): like `http_get`, sending
+/// `body` with the given request headers
+pub fn http_post_async(args: &[Value]) -> Result<Value> {
+    if args.len() < 2 || args.len() > 4 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("async.http_post".to_string()),
             line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
         });
+    }
+    let url = http_string(&args[0], "url", "async.http_post")?;
+    let body = http_string(&args[1], "body", "async.http_post")?;
+    let headers =
+        match args.get(2) {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Object(headers)) => headers
+                .iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => (name.clone(), value.clone()),
+                    other => (name.clone(), other.to_string()),
+                })
+                .collect(),
+            Some(other) => return Err(InfraError::TypeError {
+                expected: "headers object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("async.http_post() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some(
+                    "Use e.g. async.http_post(url, body, {\"Content-Type\": \"application/json\"})"
+                        .to_string(),
+                ),
+            }),
+        };
+    let timeout_ms = http_timeout(args.get(3), "async.http_post")?;
+    http_request(
+        "POST",
+        url,
+        Some(body.to_string()),
+        headers,
+        timeout_ms,
+        "async.http_post",
+    )
+}
+
+/// How long a request may take when the options do not say
+const HTTP_TIMEOUT_MS: u64 = 30_000;
+
+fn http_string<'a>(value: &'a Value, what: &str, function_name: &str) -> Result<&'a str> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(InfraError::TypeError {
+            expected: format!("{} string", what),
+            found: other.type_name().to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// The `timeout_ms` of an options object
+fn http_timeout(options: Option<&Value>, function_name: &str) -> Result<u64> {
+    let timeout = match options {
+        None | Some(Value::Null) => return Ok(HTTP_TIMEOUT_MS),
+        Some(Value::Object(options)) => options.get("timeout_ms"),
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "options object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some(format!("{}() function", function_name)),
+                line: None,
+                column: None,
+                hint: Some("Use e.g. {timeout_ms: 5000}".to_string()),
+            })
+        }
     };
+    match timeout {
+        None | Some(Value::Null) => Ok(HTTP_TIMEOUT_MS),
+        Some(Value::Number(ms)) if *ms > 0.0 && ms.is_finite() => Ok(ms.ceil() as u64),
+        Some(other) => Err(InfraError::TypeError {
+            expected: "positive number of milliseconds".to_string(),
+            found: other.to_string(),
+            context: Some(format!("{}() option 'timeout_ms'", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
 
-    // For now, simulate HTTP request with a mock response
-    // In a full implementation, this would make an actual HTTP request
-    let response_body = format!("Mock HTTP response for {}", url);
+/// A response as it comes off the wire, before it becomes a script value
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
 
-    // Create a response object
-    let mut response_map = std::collections::HashMap::new();
-    response_map.insert("status".to_string(), Value::Number(200.0));
-    response_map.insert("body".to_string(), Value::String(response_body));
-    response_map.insert("ok".to_string(), Value::Boolean(true));
+/// Make a request and settle a promise with its response. An error status
+/// still resolves, with `ok` false; failing to get a response at all, or a
+/// body that is not UTF-8 text, rejects.
+fn http_request(
+    method: &'static str,
+    url: &str,
+    body: Option<String>,
+    headers: Vec<(String, String)>,
+    timeout_ms: u64,
+    origin: &'static str,
+) -> Result<Value> {
+    let target = url.to_string();
+    let outcome = cancel::run_blocking(origin, move || {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(timeout_ms))
+            .build();
+        let mut request = agent.request(method, &target);
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        let result = match &body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+        let response = match result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(error)) => {
+                // The error's own text repeats the URL; say it once
+                let mut detail = error.kind().to_string();
+                if let Some(message) = error.message() {
+                    detail = format!("{}: {}", detail, message);
+                }
+                if let Some(cause) = std::error::Error::source(&error) {
+                    detail = format!("{} ({})", detail, cause);
+                }
+                return Err(match error.kind() {
+                    ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => {
+                        format!("invalid URL '{}': {}", target, detail)
+                    }
+                    _ => format!("{} {} failed: {}", method, target, detail),
+                });
+            }
+        };
+        let status = response.status();
+        let mut names = response.headers_names();
+        names.dedup();
+        let headers = names
+            .into_iter()
+            .map(|name| {
+                let value = response.all(&name).join(", ");
+                (name, value)
+            })
+            .collect();
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| format!("{} {} failed reading the body: {}", method, target, e))?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    })?;
 
-    Ok(Value::Promise {
-        value: Some(Box::new(Value::Object(response_map))),
-        resolved: true,
-        rejected: false,
-        error: None,
-    })
+    let response = match outcome {
+        Ok(response) => response,
+        Err(message) => return Ok(rejected_promise(message, origin)),
+    };
+    let Ok(body) = String::from_utf8(response.body) else {
+        return Ok(rejected_promise(
+            format!("{} {}: the response body is not UTF-8 text", method, url),
+            origin,
+        ));
+    };
+    let mut headers = ObjectMap::new();
+    for (name, value) in response.headers {
+        headers.insert(name.to_ascii_lowercase(), Value::String(value));
+    }
+    Ok(resolved_promise(
+        http_response(response.status, headers, body),
+        origin,
+    ))
+}
+
+/// The object a request's promise resolves with
+pub fn http_response(status: u16, headers: ObjectMap, body: String) -> Value {
+    let mut response = ObjectMap::new();
+    response.insert("status".to_string(), Value::Number(status as f64));
+    response.insert(
+        "ok".to_string(),
+        Value::Boolean((200..300).contains(&status)),
+    );
+    response.insert("headers".to_string(), Value::Object(headers));
+    response.insert("body".to_string(), Value::String(body));
+    Value::Object(response)
+}
+
+/// The promises passed to `async.all` or `async.race`: an array of them,
+/// or each as its own argument
+fn promise_list(args: &[Value]) -> &[Value] {
+    let promises = match args {
+        [Value::Array(items)] => items.as_slice(),
+        _ => args,
+    };
+    promises.iter().for_each(mark_observed);
+    promises
 }
 
-/// Race multiple promises and return the first one that resolves
+/// The first of the promises to settle, running timers until one does
 pub fn race(args: &[Value]) -> Result<Value> {
-    if args.is_empty() {
+    let promises = promise_list(args);
+    if promises.is_empty() {
         return Err(InfraError::RuntimeError {
             message: "race requires at least one promise".to_string(),
             line: None,
@@ -240,44 +637,57 @@ pub fn race(args: &[Value]) -> Result<Value> {
             source_code: None,
         });
     }
-
-    // For now, just return the first promise
-    // In a full implementation, this would race multiple promises
-    Ok(args[0].clone())
+    loop {
+        let settled = promises
+            .iter()
+            .map(current)
+            .find(|promise| !is_pending(promise));
+        if let Some(promise) = settled {
+            return Ok(promise);
+        }
+        if !run_next_timer()? {
+            // Nothing can settle any of them
+            return Ok(promises[0].clone());
+        }
+    }
 }
 
-/// Wait for all promises to resolve
+/// A promise of every promise's value, in order, once all have resolved.
+/// It rejects as soon as one of them rejects, without waiting for the rest.
+/// Values that are not promises are taken as they are.
 pub fn all(args: &[Value]) -> Result<Value> {
-    if args.is_empty() {
-        return Err(InfraError::RuntimeError {
-            message: "all requires at least one promise".to_string(),
-            line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
+    let promises = promise_list(args);
+    loop {
+        let outcomes: Vec<Value> = promises.iter().map(current).collect();
+        let rejection = outcomes.iter().find_map(|outcome| match outcome {
+            Value::Promise {
+                rejected: true,
+                error,
+                ..
+            } => Some(
+                error
+                    .clone()
+                    .unwrap_or_else(|| "Promise rejected".to_string()),
+            ),
+            _ => None,
         });
-    }
-
-    // For now, just collect all promises into an array
-    // In a full implementation, this would wait for all promises to resolve
-    let mut results = Vec::new();
-    for promise in args {
-        if let Value::Promise {
-            value, resolved, ..
-        } = promise
-        {
-            if *resolved {
-                results.push(value.clone().map(|boxed| *boxed).unwrap_or(Value::Null));
-            } else {
-                // For now, just use null for unresolved promises
-                results.push(Value::Null);
-            }
-        } else {
-            results.push(promise.clone());
+        if let Some(error) = rejection {
+            return Ok(rejected_promise(error, "async.all"));
+        }
+        if !outcomes.iter().any(is_pending) {
+            let results = outcomes
+                .into_iter()
+                .map(|outcome| match outcome {
+                    Value::Promise { value, .. } => value.map_or(Value::Null, |boxed| *boxed),
+                    value => value,
+                })
+                .collect();
+            return Ok(resolved_promise(Value::Array(results), "async.all"));
+        }
+        if !run_next_timer()? {
+            return Ok(pending_promise("async.all"));
         }
     }
-
-    Ok(Value::Array(results))
 }
 
 /// Create a timeout promise
@@ -308,49 +718,8 @@ pub fn timeout(args: &[Value]) -> Result<Value> {
     thread::sleep(Duration::from_millis(duration));
 
     // Return a rejected promise with timeout error
-    Ok(Value::Promise {
-        value: None,
-        resolved: false,
-        rejected: true,
-        error: Some("Timeout exceeded".to_string()),
-    })
-}
-
-/// Add callback to a promise (simplified version)
-pub fn then(args: &[Value]) -> Result<Value> {
-    if args.len() < 2 {
-        return Err(InfraError::RuntimeError {
-            message: "then requires two arguments: promise and callback".to_string(),
-            line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
-        });
-    }
-
-    let promise = &args[0];
-    let _callback = &args[1];
-
-    // For now, just check if promise is resolved and apply callback
-    if let Value::Promise {
-        resolved, value, ..
-    } = promise
-    {
-        if *resolved {
-            // Apply callback to the resolved value
-            // For now, just return the value (simplified)
-            Ok(value.clone().map(|boxed| *boxed).unwrap_or(Value::Null))
-        } else {
-            // Return unresolved promise
-            Ok(promise.clone())
-        }
-    } else {
-        Err(InfraError::RuntimeError {
-            message: "then first argument must be a promise".to_string(),
-            line: None,
-            column: None,
-            stack_trace: vec![],
-            source_code: None,
-        })
-    }
+    Ok(rejected_promise(
+        "Timeout exceeded".to_string(),
+        "async.timeout",
+    ))
 }
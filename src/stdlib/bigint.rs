@@ -0,0 +1,109 @@
+use crate::core::{InfraError, Result, Value};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+/// Largest integer every float between it and zero can represent exactly (2^53)
+const MAX_SAFE_INTEGER: i64 = 1 << 53;
+
+/// Create a bigint from a decimal string or an integral number
+#[allow(dead_code)]
+pub fn new(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("bigint.new".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::String(text) => parse_decimal(text).map(Value::BigInt),
+        Value::Number(n) => from_number(*n, "bigint.new").map(Value::BigInt),
+        Value::BigInt(n) => Ok(Value::BigInt(n.clone())),
+        other => Err(InfraError::TypeError {
+            expected: "string or number".to_string(),
+            found: other.type_name().to_string(),
+            context: Some("bigint.new() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Convert a bigint to a number, failing if precision would be lost
+#[allow(dead_code)]
+pub fn to_number(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("bigint.to_number".to_string()),
+            line: None,
+        });
+    }
+
+    let Value::BigInt(n) = &args[0] else {
+        return Err(InfraError::TypeError {
+            expected: "bigint".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("bigint.to_number() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        });
+    };
+
+    match n.to_i64() {
+        Some(i) if i.abs() <= MAX_SAFE_INTEGER => Ok(Value::Number(i as f64)),
+        _ => Err(InfraError::RuntimeError {
+            message: format!(
+                "bigint.to_number: {} is outside the exactly representable range (±2^53)",
+                n
+            ),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }),
+    }
+}
+
+/// Convert an integral number to a bigint, rejecting fractions and non-finite values
+pub fn from_number(n: f64, function_name: &str) -> Result<BigInt> {
+    if n.is_finite() && n.fract() == 0.0 {
+        if let Some(big) = BigInt::from_f64(n) {
+            return Ok(big);
+        }
+    }
+
+    Err(InfraError::RuntimeError {
+        message: format!("{}: {} is not an integer", function_name, n),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    })
+}
+
+fn parse_decimal(text: &str) -> Result<BigInt> {
+    let trimmed = text.trim();
+    let digits = trimmed.strip_prefix(['-', '+']).unwrap_or(trimmed);
+
+    // BigInt's own parser also accepts '_' separators, which we do not
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(big) = trimmed.parse::<BigInt>() {
+            return Ok(big);
+        }
+    }
+
+    Err(InfraError::TypeError {
+        expected: "decimal integer string".to_string(),
+        found: format!("\"{}\"", text),
+        context: Some("bigint.new() function".to_string()),
+        line: None,
+        column: None,
+        hint: None,
+    })
+}
@@ -0,0 +1,294 @@
+use crate::core::{InfraError, Result, Value};
+use crate::utils::base64;
+use std::rc::Rc;
+
+fn check_args(args: &[Value], min: usize, max: usize, function_name: &str) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: if args.len() < min { min } else { max },
+            found: args.len(),
+            function_name: Some(format!("bytes.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("bytes.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn runtime_error(function_name: &str, message: String) -> InfraError {
+    InfraError::RuntimeError {
+        message: format!("bytes.{}: {}", function_name, message),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
+
+fn bytes_arg<'a>(value: &'a Value, function_name: &str) -> Result<&'a [u8]> {
+    value
+        .as_bytes()
+        .ok_or_else(|| type_error("bytes", value, function_name))
+}
+
+fn string_arg<'a>(value: &'a Value, function_name: &str) -> Result<&'a str> {
+    value
+        .as_string()
+        .map(String::as_str)
+        .ok_or_else(|| type_error("string", value, function_name))
+}
+
+fn bytes_value(bytes: Vec<u8>) -> Value {
+    Value::Bytes(Rc::new(bytes))
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Latin1,
+    Ascii,
+}
+
+/// The encoding named by an optional argument; UTF-8 when omitted
+fn encoding_arg(value: Option<&Value>, function_name: &str) -> Result<Encoding> {
+    let Some(value) = value else {
+        return Ok(Encoding::Utf8);
+    };
+    match string_arg(value, function_name)?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "utf-8" | "utf8" => Ok(Encoding::Utf8),
+        "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+        "ascii" => Ok(Encoding::Ascii),
+        other => Err(runtime_error(
+            function_name,
+            format!(
+                "unknown encoding '{}' (expected utf-8, latin1 or ascii)",
+                other
+            ),
+        )),
+    }
+}
+
+/// A non-negative integer argument
+fn offset_arg(value: &Value, function_name: &str) -> Result<usize> {
+    match value {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        Value::Number(n) => Err(runtime_error(
+            function_name,
+            format!("{} is not a valid byte offset", n),
+        )),
+        other => Err(type_error("number", other, function_name)),
+    }
+}
+
+/// Parse hexadecimal text, two digits per byte
+#[allow(dead_code)]
+pub fn from_hex(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "from_hex")?;
+    let text = string_arg(&args[0], "from_hex")?;
+
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    let mut high = None;
+    for (offset, c) in text.chars().enumerate() {
+        let digit = c.to_digit(16).ok_or_else(|| {
+            runtime_error(
+                "from_hex",
+                format!("invalid hex digit '{}' at offset {}", c, offset),
+            )
+        })? as u8;
+        match high.take() {
+            Some(high) => bytes.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+
+    if high.is_some() {
+        return Err(runtime_error(
+            "from_hex",
+            format!("odd number of hex digits ({})", text.chars().count()),
+        ));
+    }
+    Ok(bytes_value(bytes))
+}
+
+/// Encode a string: bytes.from_string(s, encoding = "utf-8")
+#[allow(dead_code)]
+pub fn from_string(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 2, "from_string")?;
+    let text = string_arg(&args[0], "from_string")?;
+    let encoding = encoding_arg(args.get(1), "from_string")?;
+
+    let limit = match encoding {
+        Encoding::Utf8 => return Ok(bytes_value(text.as_bytes().to_vec())),
+        Encoding::Latin1 => 0xff,
+        Encoding::Ascii => 0x7f,
+    };
+    let mut bytes = Vec::with_capacity(text.len());
+    for (offset, c) in text.chars().enumerate() {
+        if c as u32 > limit {
+            return Err(runtime_error(
+                "from_string",
+                format!("'{}' at character {} cannot be encoded", c, offset),
+            ));
+        }
+        bytes.push(c as u8);
+    }
+    Ok(bytes_value(bytes))
+}
+
+#[allow(dead_code)]
+pub fn from_base64(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "from_base64")?;
+    let text = string_arg(&args[0], "from_base64")?;
+    base64::decode(text)
+        .map(bytes_value)
+        .map_err(|message| runtime_error("from_base64", message))
+}
+
+/// Build a buffer from an array of numbers in 0..=255
+#[allow(dead_code)]
+pub fn from_array(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "from_array")?;
+    let items = args[0]
+        .as_array()
+        .ok_or_else(|| type_error("array", &args[0], "from_array"))?;
+
+    let mut bytes = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        match item {
+            Value::Number(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => {
+                bytes.push(*n as u8)
+            }
+            other => {
+                return Err(runtime_error(
+                    "from_array",
+                    format!("element {} ({}) is not a byte in 0..255", i, other),
+                ))
+            }
+        }
+    }
+    Ok(bytes_value(bytes))
+}
+
+#[allow(dead_code)]
+pub fn to_hex(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "to_hex")?;
+    let bytes = bytes_arg(&args[0], "to_hex")?;
+
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        text.push_str(&format!("{:02x}", byte));
+    }
+    Ok(Value::String(text))
+}
+
+#[allow(dead_code)]
+pub fn to_base64(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "to_base64")?;
+    let bytes = bytes_arg(&args[0], "to_base64")?;
+    Ok(Value::String(base64::encode(bytes)))
+}
+
+/// Decode to a string: bytes.to_string(b, encoding = "utf-8")
+#[allow(dead_code)]
+pub fn to_string(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 2, "to_string")?;
+    let bytes = bytes_arg(&args[0], "to_string")?;
+    let encoding = encoding_arg(args.get(1), "to_string")?;
+
+    let text = match encoding {
+        Encoding::Utf8 => std::str::from_utf8(bytes)
+            .map_err(|err| {
+                runtime_error(
+                    "to_string",
+                    format!("invalid UTF-8 at byte offset {}", err.valid_up_to()),
+                )
+            })?
+            .to_string(),
+        Encoding::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+        Encoding::Ascii => {
+            if let Some(offset) = bytes.iter().position(|byte| !byte.is_ascii()) {
+                return Err(runtime_error(
+                    "to_string",
+                    format!(
+                        "byte 0x{:02x} at offset {} is not ASCII",
+                        bytes[offset], offset
+                    ),
+                ));
+            }
+            bytes.iter().map(|&byte| byte as char).collect()
+        }
+    };
+    Ok(Value::String(text))
+}
+
+#[allow(dead_code)]
+pub fn to_array(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "to_array")?;
+    let bytes = bytes_arg(&args[0], "to_array")?;
+    Ok(Value::Array(
+        bytes
+            .iter()
+            .map(|&byte| Value::Number(byte as f64))
+            .collect(),
+    ))
+}
+
+#[allow(dead_code)]
+pub fn length(args: &[Value]) -> Result<Value> {
+    check_args(args, 1, 1, "length")?;
+    let bytes = bytes_arg(&args[0], "length")?;
+    Ok(Value::Number(bytes.len() as f64))
+}
+
+/// bytes.slice(b, start, end = length), end exclusive
+#[allow(dead_code)]
+pub fn slice(args: &[Value]) -> Result<Value> {
+    check_args(args, 2, 3, "slice")?;
+    let bytes = bytes_arg(&args[0], "slice")?;
+    let start = offset_arg(&args[1], "slice")?;
+    let end = match args.get(2) {
+        Some(end) => offset_arg(end, "slice")?,
+        None => bytes.len(),
+    };
+
+    if start > end || end > bytes.len() {
+        return Err(runtime_error(
+            "slice",
+            format!(
+                "range {}..{} is out of bounds for {} bytes",
+                start,
+                end,
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(bytes_value(bytes[start..end].to_vec()))
+}
+
+/// Join buffers, given as arguments or as one array
+#[allow(dead_code)]
+pub fn concat(args: &[Value]) -> Result<Value> {
+    let parts = match args {
+        [Value::Array(parts)] => parts.as_slice(),
+        parts => parts,
+    };
+
+    let mut bytes = Vec::new();
+    for part in parts {
+        bytes.extend_from_slice(bytes_arg(part, "concat")?);
+    }
+    Ok(bytes_value(bytes))
+}
@@ -0,0 +1,269 @@
+//! Cancellation for long-running work: `process.with_timeout` deadlines and
+//! Ctrl-C interrupts share the checks below.
+//!
+//! Interpreted code checks between statements, so loops and calls stop at the
+//! next statement boundary. Blocking stdlib operations come in two kinds:
+//!
+//! - abortable: `process.exec` polls its child and kills it when cancelled;
+//! - best effort: `io.read_file` runs on a worker thread when a deadline is
+//!   active. The script stops waiting when the deadline passes, but the read
+//!   itself cannot be stopped and finishes (or stays stuck) in the background.
+
+use crate::core::{InfraError, Result};
+use std::cell::RefCell;
+use std::io::Read;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often blocked operations look at the deadline and interrupt flag
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static DEADLINES: RefCell<Vec<Deadline>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    expires: Instant,
+    timeout_ms: u64,
+}
+
+/// Keeps a deadline in effect until dropped
+pub struct DeadlineGuard {
+    _private: (),
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        DEADLINES.with(|d| d.borrow_mut().pop());
+    }
+}
+
+/// Put a deadline `timeout_ms` from now in effect for the guard's lifetime.
+/// Deadlines nest; the earliest one wins.
+pub fn push_deadline(timeout_ms: u64) -> DeadlineGuard {
+    let deadline = Deadline {
+        expires: Instant::now() + Duration::from_millis(timeout_ms),
+        timeout_ms,
+    };
+    DEADLINES.with(|d| d.borrow_mut().push(deadline));
+    DeadlineGuard { _private: () }
+}
+
+fn earliest_deadline() -> Option<Deadline> {
+    DEADLINES.with(|d| d.borrow().iter().copied().min_by_key(|d| d.expires))
+}
+
+/// Fail if the script was interrupted or a deadline has passed.
+/// `operation` names the operation in flight for the error message.
+pub fn check(operation: Option<&str>) -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(InfraError::Generic("Interrupted".to_string()));
+    }
+
+    match earliest_deadline() {
+        Some(deadline) if Instant::now() >= deadline.expires => Err(InfraError::TimeoutError {
+            operation: operation.map(str::to_string),
+            timeout_ms: deadline.timeout_ms,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Run a blocking operation so that a deadline can stop the wait for it.
+///
+/// Without a deadline the work runs inline. With one, it runs on a worker
+/// thread that is abandoned if the deadline passes first.
+pub fn run_blocking<T: Send + 'static>(
+    operation: &str,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T> {
+    if earliest_deadline().is_none() {
+        return Ok(work());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+
+    loop {
+        check(Some(operation))?;
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(value) => return Ok(value),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(InfraError::RuntimeError {
+                    message: format!("{} failed unexpectedly", operation),
+                    line: None,
+                    column: None,
+                    stack_trace: vec![],
+                    source_code: None,
+                })
+            }
+        }
+    }
+}
+
+/// Output of a child process that ran to completion
+pub struct ChildOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Wait for a child process, killing it if the script is cancelled first
+pub fn wait_child(mut child: Child, operation: &str) -> Result<ChildOutput> {
+    // Drain the pipes on their own threads so a chatty child cannot block
+    let stdout = child.stdout.take().map(read_to_end);
+    let stderr = child.stderr.take().map(read_to_end);
+
+    let status = loop {
+        if let Err(err) = check(Some(operation)) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => {
+                return Err(InfraError::IoError {
+                    message: format!("Failed to wait for process: {}", err),
+                    operation: Some(operation.to_string()),
+                    path: None,
+                })
+            }
+        }
+    };
+
+    let collect = |reader: Option<thread::JoinHandle<String>>| {
+        reader
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+    Ok(ChildOutput {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+fn read_to_end(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// Marks script code as running, so Ctrl-C interrupts it instead of exiting.
+/// Dropping the guard clears any pending interrupt.
+pub struct RunningGuard {
+    _private: (),
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+        INTERRUPTED.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn enter_running() -> RunningGuard {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    RUNNING.store(true, Ordering::SeqCst);
+    RunningGuard { _private: () }
+}
+
+/// Called on Ctrl-C. The first press while a script runs cancels it at the
+/// next check; a second press, or one while nothing runs, exits right away.
+fn on_interrupt() {
+    if RUNNING.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    exit_now(130);
+}
+
+/// Route Ctrl-C through the interrupt flag
+pub fn install_interrupt_handler() {
+    platform_signals::install();
+}
+
+#[cfg(unix)]
+mod platform_signals {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn handle(_signum: c_int) {
+        super::on_interrupt();
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only touches atomics or exits the process
+        unsafe {
+            signal(SIGINT, handle);
+        }
+    }
+
+    pub fn exit(code: c_int) -> ! {
+        extern "C" {
+            fn _exit(code: c_int) -> !;
+        }
+        // SAFETY: _exit is async-signal-safe, unlike std::process::exit
+        unsafe { _exit(code) }
+    }
+}
+
+#[cfg(windows)]
+mod platform_signals {
+    const CTRL_C_EVENT: u32 = 0;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn handle(event: u32) -> i32 {
+        if event != CTRL_C_EVENT {
+            return 0;
+        }
+        super::on_interrupt();
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: the handler runs on its own thread and only touches atomics
+        unsafe {
+            SetConsoleCtrlHandler(handle, 1);
+        }
+    }
+
+    pub fn exit(code: i32) -> ! {
+        std::process::exit(code)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform_signals {
+    pub fn install() {}
+
+    pub fn exit(code: i32) -> ! {
+        std::process::exit(code)
+    }
+}
+
+fn exit_now(code: i32) -> ! {
+    platform_signals::exit(code)
+}
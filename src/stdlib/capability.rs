@@ -0,0 +1,142 @@
+use crate::core::Value;
+use crate::stdlib::{async_mod, process};
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static DRY_RUN: Cell<bool> = const { Cell::new(false) };
+    static DRY_RUN_LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A stdlib function whose effect reaches outside the interpreter
+struct Mutation {
+    module: &'static str,
+    function: &'static str,
+    /// Describe the intended effect, or None if the arguments are invalid
+    /// (the real function then runs and reports the error without side effects)
+    describe: fn(&[Value]) -> Option<String>,
+    /// Value returned in place of performing the effect
+    result: fn() -> Value,
+}
+
+/// Every mutating stdlib operation, in one place
+const MUTATIONS: &[Mutation] = &[
+    Mutation {
+        module: "io",
+        function: "write_file",
+        describe: describe_write,
+        result: || Value::Null,
+    },
+    Mutation {
+        module: "io",
+        function: "write_bytes",
+        describe: describe_write,
+        result: || Value::Null,
+    },
+    Mutation {
+        module: "io",
+        function: "append_file",
+        describe: describe_write,
+        result: || Value::Null,
+    },
+    Mutation {
+        module: "io",
+        function: "delete_file",
+        describe: describe_path,
+        result: || Value::Null,
+    },
+    Mutation {
+        module: "io",
+        function: "mkdir",
+        describe: describe_path,
+        result: || Value::Null,
+    },
+    Mutation {
+        module: "async",
+        function: "write_file",
+        describe: describe_write,
+        result: || async_mod::resolved_promise(Value::Boolean(true), "async.write_file"),
+    },
+    Mutation {
+        module: "async",
+        function: "http_post",
+        describe: describe_post,
+        result: || {
+            let response = async_mod::http_response(200, Default::default(), String::new());
+            async_mod::resolved_promise(response, "async.http_post")
+        },
+    },
+    Mutation {
+        module: "process",
+        function: "exec",
+        describe: describe_exec,
+        result: || process::exec_result(0, String::new(), String::new()),
+    },
+];
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.with(|d| d.set(enabled));
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.with(|d| d.get())
+}
+
+/// For a dry run, log a mutating call instead of running it and return the
+/// value to use in its place
+pub fn intercept(module: &str, function: &str, args: &[Value]) -> Option<Value> {
+    let mutation = MUTATIONS
+        .iter()
+        .find(|m| m.module == module && m.function == function)?;
+    let description = (mutation.describe)(args)?;
+
+    let line = format!("would have: {}.{} {}", module, function, description);
+    eprintln!("[dry-run] {}", line);
+    DRY_RUN_LOG.with(|log| log.borrow_mut().push(line));
+
+    Some((mutation.result)())
+}
+
+/// Take the mutations logged so far in dry-run mode
+#[allow(dead_code)]
+pub fn take_dry_run_log() -> Vec<String> {
+    DRY_RUN_LOG.with(|log| std::mem::take(&mut *log.borrow_mut()))
+}
+
+fn describe_write(args: &[Value]) -> Option<String> {
+    match args {
+        [Value::String(path), Value::String(content)] => {
+            Some(format!("path={} bytes={}", path, content.len()))
+        }
+        [Value::String(path), Value::Bytes(content)] => {
+            Some(format!("path={} bytes={}", path, content.len()))
+        }
+        _ => None,
+    }
+}
+
+fn describe_path(args: &[Value]) -> Option<String> {
+    match args {
+        [Value::String(path)] => Some(format!("path={}", path)),
+        _ => None,
+    }
+}
+
+fn describe_post(args: &[Value]) -> Option<String> {
+    match args {
+        [Value::String(url), Value::String(body), ..] => {
+            Some(format!("url={} bytes={}", url, body.len()))
+        }
+        _ => None,
+    }
+}
+
+fn describe_exec(args: &[Value]) -> Option<String> {
+    let (program, args) = process::exec_args(args).ok()?;
+    Some(format!(
+        "command={}",
+        std::iter::once(program)
+            .chain(args)
+            .collect::<Vec<_>>()
+            .join(" ")
+    ))
+}
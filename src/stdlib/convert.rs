@@ -0,0 +1,113 @@
+//! Conversions between value types, callable without a module prefix:
+//! `str`, `int`, `float`, `bool`, `type` and `len`.
+//!
+//! They are registered as the `convert` module and listed among the
+//! library's globals, so `int("42")` and `convert.int("42")` are the same
+//! call.
+
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::number;
+
+/// str(value): `value` as `print` would show it
+#[allow(dead_code)]
+pub fn str(args: &[Value]) -> Result<Value> {
+    check_count(args, "str")?;
+    Ok(Value::String(args[0].to_string()))
+}
+
+/// int(value): a number or numeric string with its fraction dropped
+#[allow(dead_code)]
+pub fn int(args: &[Value]) -> Result<Value> {
+    check_count(args, "int")?;
+    Ok(Value::Number(to_number(&args[0], "int")?.trunc()))
+}
+
+/// float(value): a number, or a numeric string as a number
+#[allow(dead_code)]
+pub fn float(args: &[Value]) -> Result<Value> {
+    check_count(args, "float")?;
+    Ok(Value::Number(to_number(&args[0], "float")?))
+}
+
+/// bool(value): whether `value` counts as true in an `if`
+#[allow(dead_code)]
+pub fn bool(args: &[Value]) -> Result<Value> {
+    check_count(args, "bool")?;
+    Ok(Value::Boolean(args[0].is_truthy()))
+}
+
+/// type(value): the name of `value`'s type, such as "number" or "array",
+/// or its class's name for an instance
+#[allow(dead_code)]
+pub fn type_of(args: &[Value]) -> Result<Value> {
+    check_count(args, "type")?;
+    let name = match &args[0] {
+        Value::Instance(instance) => instance.class.name.clone(),
+        other => other.type_name().to_string(),
+    };
+    Ok(Value::String(name))
+}
+
+/// len(value): the characters in a string, the items in an array or tuple,
+/// the keys of an object or the bytes in bytes
+#[allow(dead_code)]
+pub fn len(args: &[Value]) -> Result<Value> {
+    check_count(args, "len")?;
+    let length = match &args[0] {
+        Value::String(text) => text.chars().count(),
+        Value::Array(items) => items.len(),
+        Value::Tuple(items) => items.len(),
+        Value::Object(fields) => fields.len(),
+        Value::Bytes(bytes) => bytes.len(),
+        other => {
+            return Err(InfraError::TypeError {
+                expected: "string, array, tuple, object or bytes".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("len() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+    Ok(Value::Number(length as f64))
+}
+
+fn to_number(value: &Value, function_name: &str) -> Result<f64> {
+    match value {
+        Value::Number(n) if n.is_finite() => Ok(*n),
+        Value::Boolean(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        Value::String(text) => number::parse_invariant(text, function_name).map_err(|mut error| {
+            if let InfraError::TypeError {
+                hint: hint @ None, ..
+            } = &mut error
+            {
+                *hint = Some(format!(
+                    "Check the input: {}() reads numbers written like \"42\" or \"-3.5\"",
+                    function_name
+                ));
+            }
+            error
+        }),
+        other => Err(InfraError::TypeError {
+            expected: "finite number, numeric string or boolean".to_string(),
+            found: other.to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+fn check_count(args: &[Value], function_name: &str) -> Result<()> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some(function_name.to_string()),
+            line: None,
+        });
+    }
+    Ok(())
+}
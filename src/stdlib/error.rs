@@ -0,0 +1,127 @@
+use crate::core::ObjectMap;
+use crate::core::{InfraError, Result, Value};
+
+/// Create an error value: error.new(type, message, fields?)
+#[allow(dead_code)]
+pub fn new(args: &[Value]) -> Result<Value> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("error.new".to_string()),
+            line: None,
+        });
+    }
+
+    let fields = match args.get(2) {
+        None | Some(Value::Null) => ObjectMap::new(),
+        Some(Value::Object(fields)) => fields.clone(),
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("error.new() fields".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+
+    match (&args[0], &args[1]) {
+        (Value::String(error_type), Value::String(message)) => Ok(Value::Error {
+            error_type: error_type.clone(),
+            message: message.clone(),
+            fields,
+            line: None,
+            stack: vec![],
+        }),
+        _ => Err(InfraError::TypeError {
+            expected: "two strings".to_string(),
+            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+            context: Some("error.new() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Get the type string of an error value
+#[allow(dead_code)]
+pub fn error_type(args: &[Value]) -> Result<Value> {
+    let (error_type, ..) = expect_error(args, "error.type")?;
+    Ok(Value::String(error_type.clone()))
+}
+
+/// Get the message of an error value
+#[allow(dead_code)]
+pub fn message(args: &[Value]) -> Result<Value> {
+    let (_, message, ..) = expect_error(args, "error.message")?;
+    Ok(Value::String(message.clone()))
+}
+
+/// Get the custom fields of an error value
+#[allow(dead_code)]
+pub fn fields(args: &[Value]) -> Result<Value> {
+    let (_, _, fields, _) = expect_error(args, "error.fields")?;
+    Ok(Value::Object(fields.clone()))
+}
+
+/// Get the call stack captured when an error value was created
+#[allow(dead_code)]
+pub fn stack(args: &[Value]) -> Result<Value> {
+    let (.., stack) = expect_error(args, "error.stack")?;
+    Ok(Value::Array(
+        stack
+            .iter()
+            .map(|frame| Value::String(frame.clone()))
+            .collect(),
+    ))
+}
+
+/// Check whether a value is an error value
+#[allow(dead_code)]
+pub fn is_error(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("error.is_error".to_string()),
+            line: None,
+        });
+    }
+
+    Ok(Value::Boolean(matches!(args[0], Value::Error { .. })))
+}
+
+type ErrorParts<'a> = (&'a String, &'a String, &'a ObjectMap, &'a Vec<String>);
+
+fn expect_error<'a>(args: &'a [Value], function_name: &str) -> Result<ErrorParts<'a>> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some(function_name.to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::Error {
+            error_type,
+            message,
+            fields,
+            stack,
+            ..
+        } => Ok((error_type, message, fields, stack)),
+        other => Err(InfraError::TypeError {
+            expected: "error".to_string(),
+            found: other.type_name().to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: Some("Create error values with error.new(type, message)".to_string()),
+        }),
+    }
+}
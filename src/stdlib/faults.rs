@@ -0,0 +1,275 @@
+//! Failure injection for testing error handling:
+//! `faults.inject("io.write_file", {error: "disk full", after_calls: 2})`.
+//!
+//! An injected fault makes a standard library function fail without being
+//! attempted, with the same `IoError` a real failure raises, so a script's
+//! error handling cannot tell the two apart. A fault can wait for a number of
+//! successful calls, fail a limited number of times, fail at random, or only
+//! affect calls whose arguments a predicate accepts.
+//!
+//! Faults belong to one evaluator, so tests running side by side never see
+//! each other's. Deciding whether a call matches can call back into script
+//! code, so the `faults` functions and the check itself live in the
+//! evaluator; this module keeps the state.
+
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use crate::stdlib::sources;
+
+const DEFAULT_ERROR: &str = "injected fault";
+
+/// One `faults.inject` call
+struct Fault {
+    module: String,
+    function: String,
+    error: String,
+    /// Matching calls that succeed before the fault starts failing them
+    after_calls: u64,
+    /// Most failures to inject; None is no limit
+    times: Option<u64>,
+    /// Chance that a matching call fails; None is every call
+    probability: Option<f64>,
+    /// Predicate given the call's arguments; None matches every call
+    when: Option<Value>,
+    /// Matching calls seen so far
+    calls: u64,
+    /// Failures injected so far
+    failures: u64,
+}
+
+impl Fault {
+    fn operation(&self) -> String {
+        format!("{}.{}", self.module, self.function)
+    }
+}
+
+/// The faults injected into one evaluator
+#[derive(Default)]
+pub struct Faults {
+    faults: Vec<Fault>,
+    /// xorshift state for `probability`, seeded on first use
+    rng: u64,
+}
+
+impl Faults {
+    pub fn is_empty(&self) -> bool {
+        self.faults.is_empty()
+    }
+
+    /// faults.inject(operation, options?): add a fault for `operation`, a
+    /// standard library function for which `is_function` holds
+    pub fn inject(
+        &mut self,
+        args: &[Value],
+        is_function: impl Fn(&str, &str) -> bool,
+    ) -> Result<()> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: args.len(),
+                function_name: Some("faults.inject".to_string()),
+                line: None,
+            });
+        }
+
+        let operation = match &args[0] {
+            Value::String(operation) => operation,
+            other => return Err(type_error("string", other, "inject")),
+        };
+        let (module, function) = operation
+            .split_once('.')
+            .filter(|(module, function)| is_function(module, function))
+            .ok_or_else(|| InfraError::RuntimeError {
+                message: format!(
+                    "faults.inject: '{}' is not a standard library function; name one like \"io.write_file\"",
+                    operation
+                ),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            })?;
+
+        let mut fault = Fault {
+            module: module.to_string(),
+            function: function.to_string(),
+            error: DEFAULT_ERROR.to_string(),
+            after_calls: 0,
+            times: None,
+            probability: None,
+            when: None,
+            calls: 0,
+            failures: 0,
+        };
+        let options = match args.get(1) {
+            None | Some(Value::Null) => ObjectMap::new(),
+            Some(Value::Object(options)) => options.clone(),
+            Some(other) => return Err(type_error("object of options", other, "inject")),
+        };
+        for (key, value) in &options {
+            match (key.as_str(), value) {
+                ("error", Value::String(error)) => fault.error = error.clone(),
+                ("after_calls", value) => fault.after_calls = count_option(key, value)?,
+                ("times", value) => fault.times = Some(count_option(key, value)?),
+                ("probability", Value::Number(p)) if (0.0..=1.0).contains(p) => {
+                    fault.probability = Some(*p)
+                }
+                ("seed", value) => self.rng = count_option(key, value)?.max(1),
+                ("when", Value::Function { .. } | Value::NativeFunction { .. }) => {
+                    fault.when = Some(value.clone())
+                }
+                ("error" | "probability" | "when", other) => {
+                    let expected = match key.as_str() {
+                        "error" => "string",
+                        "probability" => "number from 0 to 1",
+                        _ => "function",
+                    };
+                    return Err(InfraError::TypeError {
+                        expected: expected.to_string(),
+                        found: other.to_string(),
+                        context: Some(format!("faults.inject() option '{}'", key)),
+                        line: None,
+                        column: None,
+                        hint: None,
+                    });
+                }
+                _ => {
+                    return Err(InfraError::RuntimeError {
+                        message: format!(
+                            "Unknown faults.inject option '{}'; expected error, after_calls, times, probability, seed or when",
+                            key
+                        ),
+                        line: None,
+                        column: None,
+                        stack_trace: vec![],
+                        source_code: None,
+                    })
+                }
+            }
+        }
+
+        self.faults.push(fault);
+        Ok(())
+    }
+
+    /// faults.clear(operation?): remove every fault, or those for one
+    /// operation; returns how many were removed
+    pub fn clear(&mut self, args: &[Value]) -> Result<Value> {
+        let before = self.faults.len();
+        match args {
+            [] => self.faults.clear(),
+            [Value::String(operation)] => self.faults.retain(|f| f.operation() != *operation),
+            [other] => return Err(type_error("string", other, "clear")),
+            _ => {
+                return Err(InfraError::ArgumentCountMismatch {
+                    expected: 1,
+                    found: args.len(),
+                    function_name: Some("faults.clear".to_string()),
+                    line: None,
+                })
+            }
+        }
+        Ok(Value::Number((before - self.faults.len()) as f64))
+    }
+
+    /// faults.active(): the injected faults with how often each has matched
+    /// and failed
+    pub fn active(&self) -> Value {
+        let optional = |value: Option<f64>| value.map_or(Value::Null, Value::Number);
+        Value::Array(
+            self.faults
+                .iter()
+                .map(|fault| {
+                    let mut fields = ObjectMap::new();
+                    fields.insert("operation".to_string(), Value::String(fault.operation()));
+                    fields.insert("error".to_string(), Value::String(fault.error.clone()));
+                    fields.insert(
+                        "after_calls".to_string(),
+                        Value::Number(fault.after_calls as f64),
+                    );
+                    fields.insert("times".to_string(), optional(fault.times.map(|t| t as f64)));
+                    fields.insert("probability".to_string(), optional(fault.probability));
+                    fields.insert(
+                        "when".to_string(),
+                        fault.when.clone().unwrap_or(Value::Null),
+                    );
+                    fields.insert("calls".to_string(), Value::Number(fault.calls as f64));
+                    fields.insert("failures".to_string(), Value::Number(fault.failures as f64));
+                    Value::Object(fields)
+                })
+                .collect(),
+        )
+    }
+
+    /// Faults for `module.function`, by index, with their predicates
+    pub fn candidates(&self, module: &str, function: &str) -> Vec<(usize, Option<Value>)> {
+        self.faults
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.module == module && f.function == function)
+            .map(|(index, f)| (index, f.when.clone()))
+            .collect()
+    }
+
+    /// Count a call that fault `index` matches and return the error to fail
+    /// it with, if this is one of the calls to fail
+    pub fn trip(&mut self, index: usize, args: &[Value]) -> Option<InfraError> {
+        let roll = self.faults[index].probability.map(|_| self.next_unit());
+        let fault = &mut self.faults[index];
+        fault.calls += 1;
+        if fault.calls <= fault.after_calls
+            || fault.times.is_some_and(|times| fault.failures >= times)
+            || roll
+                .zip(fault.probability)
+                .is_some_and(|(roll, p)| roll >= p)
+        {
+            return None;
+        }
+
+        fault.failures += 1;
+        let path = match args.first() {
+            Some(Value::String(path)) => Some(path.clone()),
+            _ => None,
+        };
+        Some(InfraError::IoError {
+            message: fault.error.clone(),
+            operation: Some(fault.operation()),
+            path,
+        })
+    }
+
+    /// Uniform in [0, 1)
+    fn next_unit(&mut self) -> f64 {
+        if self.rng == 0 {
+            self.rng = sources::random_u64() | 1;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn count_option(key: &str, value: &Value) -> Result<u64> {
+    match value {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as u64),
+        other => Err(InfraError::TypeError {
+            expected: "non-negative whole number".to_string(),
+            found: other.to_string(),
+            context: Some(format!("faults.inject() option '{}'", key)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("faults.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
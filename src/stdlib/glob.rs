@@ -0,0 +1,664 @@
+//! Glob patterns: `io.glob` finds the paths under a root that match one,
+//! `io.match_glob` tests a single path.
+//!
+//! A pattern is compiled once into path segments and matched without
+//! regular expressions:
+//!
+//! - `*` matches any run of characters within a segment, `?` any one
+//! - `**` as a whole segment matches any number of segments, even none
+//! - `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match one character in, or
+//!   not in, a set
+//! - `{a,b}` matches either alternative; alternatives may nest
+//! - `\` makes the next character literal
+//!
+//! Paths always use `/` between segments, on every platform. `io.glob`
+//! patterns are relative to the root and may not leave it with `..`.
+
+use crate::core::{InfraError, Result, Value};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    Star,
+    /// `**`, only kept when it is a whole segment
+    AnyPath,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Separator,
+    Alternatives(Vec<Vec<Token>>),
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => *expected == c,
+            Token::AnyChar => true,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| (low..=high).contains(&c)) != *negated
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// `**`
+    AnyPath,
+    Pattern(Vec<Token>),
+}
+
+/// A compiled glob pattern
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// One list of segments per brace alternative
+    alternatives: Vec<Vec<Segment>>,
+    /// The pattern starts with `/`
+    absolute: bool,
+    /// Some alternative has a `..` segment
+    climbs: bool,
+}
+
+impl Glob {
+    pub fn compile(pattern: &str) -> Result<Glob> {
+        let mut parser = PatternParser {
+            pattern,
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let tokens = parser.sequence(false)?;
+
+        let mut alternatives = Vec::new();
+        for expanded in expand(&tokens) {
+            let mut segments = Vec::new();
+            for part in expanded.split(|token| *token == Token::Separator) {
+                match part {
+                    // Repeated separators and `.` segments change nothing
+                    [] | [Token::Char('.')] => {}
+                    // `**/**` matches what one `**` does
+                    [Token::AnyPath] if segments.last() == Some(&Segment::AnyPath) => {}
+                    [Token::AnyPath] => segments.push(Segment::AnyPath),
+                    _ => segments.push(Segment::Pattern(
+                        part.iter()
+                            .map(|token| match token {
+                                // `{**,x}y` only puts `**` inside a segment
+                                Token::AnyPath => Token::Star,
+                                other => other.clone(),
+                            })
+                            .collect(),
+                    )),
+                }
+            }
+            alternatives.push(segments);
+        }
+
+        let climbs = alternatives
+            .iter()
+            .flatten()
+            .any(|segment| *segment == Segment::Pattern(vec![Token::Char('.'), Token::Char('.')]));
+        Ok(Glob {
+            alternatives,
+            absolute: pattern.starts_with('/') || has_drive_prefix(pattern),
+            climbs,
+        })
+    }
+
+    /// Whether `path` matches; `\` separates segments too on Windows
+    pub fn is_match(&self, path: &str) -> bool {
+        let normalized = if cfg!(windows) {
+            path.replace('\\', "/")
+        } else {
+            path.to_string()
+        };
+        if (normalized.starts_with('/') || has_drive_prefix(&normalized)) != self.absolute {
+            return false;
+        }
+        let segments: Vec<&str> = normalized
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .collect();
+        self.matches_segments(&segments)
+    }
+
+    fn matches_segments(&self, path: &[&str]) -> bool {
+        self.alternatives
+            .iter()
+            .any(|segments| match_path(segments, path))
+    }
+
+    /// Whether some path below the directory `dir` could match
+    fn could_match_below(&self, dir: &[&str]) -> bool {
+        self.alternatives
+            .iter()
+            .any(|segments| match_below(segments, dir))
+    }
+}
+
+/// A drive-letter path such as `C:/x`, which is absolute on Windows
+fn has_drive_prefix(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+struct PatternParser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl PatternParser<'_> {
+    fn error(&self, position: usize, problem: &str) -> InfraError {
+        InfraError::RuntimeError {
+            message: format!(
+                "Invalid glob pattern '{}': {} at position {}",
+                self.pattern,
+                problem,
+                position + 1
+            ),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }
+    }
+
+    /// Tokens up to the end of the pattern, or inside braces up to the next
+    /// `,` or `}`
+    fn sequence(&mut self, in_braces: bool) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            let token = match c {
+                ',' | '}' if in_braces => break,
+                '}' => return Err(self.error(self.pos, "'}' without a matching '{'")),
+                '{' => self.alternatives()?,
+                '[' => self.class()?,
+                '*' if self.chars.get(self.pos + 1) == Some(&'*') => {
+                    let before = self.pos.checked_sub(1).map(|i| self.chars[i]);
+                    let after = self.chars.get(self.pos + 2);
+                    if !matches!(before, None | Some('/' | '{' | ','))
+                        || !matches!(after, None | Some('/' | '}' | ','))
+                    {
+                        return Err(self.error(self.pos, "'**' must be a whole path segment"));
+                    }
+                    self.pos += 2;
+                    tokens.push(Token::AnyPath);
+                    continue;
+                }
+                '*' => Token::Star,
+                '?' => Token::AnyChar,
+                '/' => Token::Separator,
+                '\\' => {
+                    self.pos += 1;
+                    match self.chars.get(self.pos) {
+                        Some(&escaped) => Token::Char(escaped),
+                        None => {
+                            return Err(self.error(self.pos - 1, "'\\' at the end of the pattern"))
+                        }
+                    }
+                }
+                other => Token::Char(other),
+            };
+            self.pos += 1;
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    /// `{a,b,...}`, leaving `pos` on the closing brace
+    fn alternatives(&mut self) -> Result<Token> {
+        let open = self.pos;
+        let mut alternatives = Vec::new();
+        loop {
+            self.pos += 1;
+            alternatives.push(self.sequence(true)?);
+            match self.chars.get(self.pos) {
+                Some(',') => {}
+                Some('}') => return Ok(Token::Alternatives(alternatives)),
+                _ => return Err(self.error(open, "unclosed '{'")),
+            }
+        }
+    }
+
+    /// `[...]`, leaving `pos` on the closing bracket
+    fn class(&mut self) -> Result<Token> {
+        let open = self.pos;
+        self.pos += 1;
+        let negated = matches!(self.chars.get(self.pos), Some('!' | '^'));
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = Vec::new();
+        loop {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return Err(self.error(open, "unclosed '['"));
+            };
+            // A `]` right after the opening is a member, not the end
+            if c == ']' && !ranges.is_empty() {
+                break;
+            }
+            let low = if c == '\\' {
+                self.pos += 1;
+                *self
+                    .chars
+                    .get(self.pos)
+                    .ok_or_else(|| self.error(open, "unclosed '['"))?
+            } else {
+                c
+            };
+            let high = match (self.chars.get(self.pos + 1), self.chars.get(self.pos + 2)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    if high < low {
+                        return Err(
+                            self.error(self.pos, &format!("range '{}-{}' is reversed", low, high))
+                        );
+                    }
+                    self.pos += 2;
+                    high
+                }
+                _ => low,
+            };
+            ranges.push((low, high));
+            self.pos += 1;
+        }
+        if ranges
+            .iter()
+            .any(|&(low, high)| (low..=high).contains(&'/'))
+        {
+            return Err(self.error(open, "'/' cannot be part of a '[...]' set"));
+        }
+        Ok(Token::Class { negated, ranges })
+    }
+}
+
+/// Every brace-free token list `tokens` stands for
+fn expand(tokens: &[Token]) -> Vec<Vec<Token>> {
+    let mut expanded = vec![Vec::new()];
+    for token in tokens {
+        match token {
+            Token::Alternatives(alternatives) => {
+                let options: Vec<Vec<Token>> =
+                    alternatives.iter().flat_map(|alt| expand(alt)).collect();
+                expanded = expanded
+                    .iter()
+                    .flat_map(|prefix| {
+                        options.iter().map(move |option| {
+                            let mut joined = prefix.clone();
+                            joined.extend(option.iter().cloned());
+                            joined
+                        })
+                    })
+                    .collect();
+            }
+            other => expanded
+                .iter_mut()
+                .for_each(|prefix| prefix.push(other.clone())),
+        }
+    }
+    expanded
+}
+
+fn match_path(segments: &[Segment], path: &[&str]) -> bool {
+    match_from(segments, path, 0, 0, &mut HashSet::new())
+}
+
+/// Whether `path[p..]` matches `segments[s..]`. Each `**` tries every number
+/// of segments, so the pairs found not to match are kept in `failed` and
+/// several `**` never retry the same split.
+fn match_from(
+    segments: &[Segment],
+    path: &[&str],
+    s: usize,
+    p: usize,
+    failed: &mut HashSet<(usize, usize)>,
+) -> bool {
+    if failed.contains(&(s, p)) {
+        return false;
+    }
+    let matched = match segments.get(s) {
+        None => p == path.len(),
+        Some(Segment::AnyPath) => {
+            (p..=path.len()).any(|skip| match_from(segments, path, s + 1, skip, failed))
+        }
+        Some(Segment::Pattern(tokens)) => {
+            p < path.len()
+                && match_segment(tokens, path[p])
+                && match_from(segments, path, s + 1, p + 1, failed)
+        }
+    };
+    if !matched {
+        failed.insert((s, p));
+    }
+    matched
+}
+
+fn match_below(segments: &[Segment], dir: &[&str]) -> bool {
+    match (segments.split_first(), dir.split_first()) {
+        (None, _) => false,
+        (Some((Segment::AnyPath, _)), _) | (Some(_), None) => true,
+        (Some((Segment::Pattern(tokens), rest)), Some((first, tail))) => {
+            match_segment(tokens, first) && match_below(rest, tail)
+        }
+    }
+}
+
+/// Match one segment, backtracking to the last `*` on a mismatch
+fn match_segment(tokens: &[Token], text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut s) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while s < text.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                star = Some((t, s));
+                t += 1;
+                continue;
+            }
+            Some(token) if token.matches(text[s]) => {
+                t += 1;
+                s += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match star {
+            Some((star_t, star_s)) => {
+                t = star_t + 1;
+                s = star_s + 1;
+                star = Some((star_t, star_s + 1));
+            }
+            None => return false,
+        }
+    }
+    tokens[t..].iter().all(|token| *token == Token::Star)
+}
+
+fn compile_all(patterns: &[String]) -> Result<Vec<Glob>> {
+    patterns
+        .iter()
+        .map(|pattern| Glob::compile(pattern))
+        .collect()
+}
+
+/// io.match_glob(pattern, path): whether `path` matches `pattern`
+#[allow(dead_code)]
+pub fn match_glob(args: &[Value]) -> Result<Value> {
+    match args {
+        [Value::String(pattern), Value::String(path)] => {
+            Ok(Value::Boolean(Glob::compile(pattern)?.is_match(path)))
+        }
+        [pattern, path] => Err(type_error(
+            "string and string",
+            &format!("{} and {}", pattern.type_name(), path.type_name()),
+            "match_glob",
+        )),
+        _ => Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("io.match_glob".to_string()),
+            line: None,
+        }),
+    }
+}
+
+struct GlobOptions {
+    root: String,
+    ignore: Vec<String>,
+    follow_symlinks: bool,
+    warnings: bool,
+}
+
+fn options(value: Option<&Value>) -> Result<GlobOptions> {
+    let mut options = GlobOptions {
+        root: ".".to_string(),
+        ignore: Vec::new(),
+        follow_symlinks: false,
+        warnings: false,
+    };
+    let fields = match value {
+        None | Some(Value::Null) => return Ok(options),
+        Some(Value::Object(fields)) => fields,
+        Some(other) => return Err(type_error("object of options", other.type_name(), "glob")),
+    };
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("root", Value::String(root)) => options.root = root.clone(),
+            ("ignore", Value::Array(patterns)) => {
+                options.ignore = patterns
+                    .iter()
+                    .map(|pattern| match pattern {
+                        Value::String(pattern) => Ok(pattern.clone()),
+                        other => Err(option_error(key, "array of strings", other)),
+                    })
+                    .collect::<Result<_>>()?
+            }
+            ("follow_symlinks", Value::Boolean(follow)) => options.follow_symlinks = *follow,
+            ("warnings", Value::Boolean(warnings)) => options.warnings = *warnings,
+            ("root", other) => return Err(option_error(key, "string", other)),
+            ("ignore", other) => return Err(option_error(key, "array of strings", other)),
+            ("follow_symlinks" | "warnings", other) => {
+                return Err(option_error(key, "boolean", other))
+            }
+            _ => {
+                return Err(InfraError::RuntimeError {
+                    message: format!(
+                        "Unknown io.glob option '{}'; expected root, ignore, follow_symlinks or warnings",
+                        key
+                    ),
+                    line: None,
+                    column: None,
+                    stack_trace: vec![],
+                    source_code: None,
+                })
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// io.glob(pattern, options?): the paths under `root` (default `.`) that
+/// match `pattern`, sorted. Options:
+///
+/// - `root`: the directory to search; returned paths start with it unless it is `.`
+/// - `ignore`: patterns for paths to leave out; an ignored directory is not searched
+/// - `follow_symlinks`: search linked directories that stay inside the root
+/// - `warnings`: return `(paths, warnings)` instead of printing why
+///   directories were skipped
+#[allow(dead_code)]
+pub fn glob(args: &[Value]) -> Result<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("io.glob".to_string()),
+            line: None,
+        });
+    }
+    let pattern = match &args[0] {
+        Value::String(pattern) => pattern,
+        other => return Err(type_error("string", other.type_name(), "glob")),
+    };
+    let options = options(args.get(1))?;
+
+    let globs = compile_all(std::slice::from_ref(pattern))?;
+    let ignores = compile_all(&options.ignore)?;
+    for (glob, pattern) in globs
+        .iter()
+        .zip([pattern])
+        .chain(ignores.iter().zip(&options.ignore))
+    {
+        if glob.absolute || glob.climbs {
+            return Err(InfraError::RuntimeError {
+                message: format!(
+                    "io.glob pattern '{}' leaves the root; patterns are relative to the root and cannot use '..'",
+                    pattern
+                ),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            });
+        }
+    }
+
+    let root = PathBuf::from(&options.root);
+    if !root.is_dir() {
+        return Err(InfraError::IoError {
+            message: "The glob root is not a directory".to_string(),
+            operation: Some("io.glob".to_string()),
+            path: Some(options.root.clone()),
+        });
+    }
+    let mut walk = Walk {
+        globs: &globs,
+        ignores: &ignores,
+        follow_symlinks: options.follow_symlinks,
+        canonical_root: fs::canonicalize(&root).unwrap_or_else(|_| root.clone()),
+        visited: HashSet::new(),
+        found: BTreeSet::new(),
+        warnings: Vec::new(),
+    };
+    walk.visited.insert(walk.canonical_root.clone());
+    walk.directory(&root, &mut Vec::new());
+
+    let prefix = options.root.trim_end_matches('/');
+    let paths = Value::Array(
+        walk.found
+            .into_iter()
+            .map(|path| {
+                Value::String(if prefix == "." {
+                    path
+                } else {
+                    format!("{}/{}", prefix, path)
+                })
+            })
+            .collect(),
+    );
+    if options.warnings {
+        let warnings = walk.warnings.into_iter().map(Value::String).collect();
+        return Ok(Value::Tuple(vec![paths, Value::Array(warnings)].into()));
+    }
+    for warning in &walk.warnings {
+        eprintln!("Warning: io.glob {}", warning);
+    }
+    Ok(paths)
+}
+
+struct Walk<'a> {
+    globs: &'a [Glob],
+    ignores: &'a [Glob],
+    follow_symlinks: bool,
+    canonical_root: PathBuf,
+    /// Directories searched, so linked cycles are searched once
+    visited: HashSet<PathBuf>,
+    /// Matches relative to the root
+    found: BTreeSet<String>,
+    warnings: Vec<String>,
+}
+
+impl Walk<'_> {
+    /// Search `dir`, which is `relative` below the root
+    fn directory(&mut self, dir: &Path, relative: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.warnings
+                    .push(format!("skipped '{}': {}", dir.display(), error));
+                return;
+            }
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let Ok(name) = entry.file_name().into_string() else {
+                self.warnings.push(format!(
+                    "skipped '{}': the name is not valid UTF-8",
+                    entry.path().display()
+                ));
+                continue;
+            };
+            relative.push(name);
+            let segments: Vec<&str> = relative.iter().map(String::as_str).collect();
+
+            if !self
+                .ignores
+                .iter()
+                .any(|glob| glob.matches_segments(&segments))
+            {
+                if self
+                    .globs
+                    .iter()
+                    .any(|glob| glob.matches_segments(&segments))
+                {
+                    self.found.insert(relative.join("/"));
+                }
+                let descend = self
+                    .globs
+                    .iter()
+                    .any(|glob| glob.could_match_below(&segments))
+                    && self.is_searchable(&entry);
+                if descend {
+                    self.directory(&entry.path(), relative);
+                }
+            }
+            relative.pop();
+        }
+    }
+
+    /// Whether `entry` is a directory to search: a linked one only when
+    /// following links, once, and inside the root
+    fn is_searchable(&mut self, entry: &fs::DirEntry) -> bool {
+        let Ok(file_type) = entry.file_type() else {
+            return false;
+        };
+        if !file_type.is_symlink() {
+            return file_type.is_dir();
+        }
+        let path = entry.path();
+        if !self.follow_symlinks || !path.is_dir() {
+            return false;
+        }
+        let Ok(target) = fs::canonicalize(&path) else {
+            return false;
+        };
+        if !target.starts_with(&self.canonical_root) {
+            self.warnings.push(format!(
+                "did not follow '{}': it leads outside the root",
+                path.display()
+            ));
+            return false;
+        }
+        self.visited.insert(target)
+    }
+}
+
+fn option_error(key: &str, expected: &str, found: &Value) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("io.glob() option '{}'", key)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn type_error(expected: &str, found: &str, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.to_string(),
+        context: Some(format!("io.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
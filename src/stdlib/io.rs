@@ -1,5 +1,20 @@
 use crate::core::{InfraError, Result, Value};
+use crate::stdlib::{cancel, progress, sources};
+use std::cell::{Cell, RefCell};
 use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+thread_local! {
+    /// Temporary paths created by this interpreter, with their `keep` flag
+    static TEMP_PATHS: RefCell<Vec<(PathBuf, bool)>> = const { RefCell::new(Vec::new()) };
+    /// When set (via `--keep-temp`), no temporary path is ever deleted
+    static KEEP_TEMP: Cell<bool> = const { Cell::new(false) };
+    static TEMP_COUNTER: Cell<u64> = const { Cell::new(0) };
+    /// The command line arguments given to the script, for `io.args()`
+    static SCRIPT_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
 
 /// Read file contents as string
 #[allow(dead_code)]
@@ -14,7 +29,10 @@ pub fn read_file(args: &[Value]) -> Result<Value> {
     }
 
     match &args[0] {
-        Value::String(filename) => match fs::read_to_string(filename) {
+        Value::String(filename) => match cancel::run_blocking("io.read_file", {
+            let filename = filename.clone();
+            move || fs::read_to_string(filename)
+        })? {
             Ok(content) => Ok(Value::String(content)),
             Err(e) => Err(InfraError::IoError {
                 message: format!("Failed to read file '{}': {}", filename, e),
@@ -33,6 +51,69 @@ pub fn read_file(args: &[Value]) -> Result<Value> {
     }
 }
 
+/// Read a file as an array of lines, without line terminators
+#[allow(dead_code)]
+pub fn read_lines(args: &[Value]) -> Result<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("io.read_lines".to_string()),
+            line: None,
+        });
+    }
+
+    let keep_cr = match args.get(1) {
+        Some(Value::Object(options)) => options
+            .get("keep_cr")
+            .map(|v| v.is_truthy())
+            .unwrap_or(false),
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "options object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("io.read_lines() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Pass {keep_cr: true} to keep carriage returns".to_string()),
+            })
+        }
+        None => false,
+    };
+
+    let content = read_file(&args[..1])?;
+    let Value::String(content) = content else {
+        unreachable!("read_file returns a string");
+    };
+
+    Ok(Value::Array(
+        split_lines(&content, keep_cr)
+            .into_iter()
+            .map(|line| Value::String(line.to_string()))
+            .collect(),
+    ))
+}
+
+/// Split text on '\n'. A trailing '\r' is part of a CRLF terminator and is
+/// dropped unless `keep_cr` is set; a final terminator does not start a new line.
+pub fn split_lines(content: &str, keep_cr: bool) -> Vec<&str> {
+    let content = content.strip_suffix('\n').unwrap_or(content);
+    if content.is_empty() {
+        return Vec::new();
+    }
+
+    content
+        .split('\n')
+        .map(|line| {
+            if keep_cr {
+                line
+            } else {
+                line.strip_suffix('\r').unwrap_or(line)
+            }
+        })
+        .collect()
+}
+
 /// Write string content to file
 #[allow(dead_code)]
 pub fn write_file(args: &[Value]) -> Result<Value> {
@@ -65,6 +146,73 @@ pub fn write_file(args: &[Value]) -> Result<Value> {
     }
 }
 
+/// Read a file into a bytes buffer
+#[allow(dead_code)]
+pub fn read_bytes(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("io.read_bytes".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::String(filename) => match cancel::run_blocking("io.read_bytes", {
+            let filename = filename.clone();
+            move || fs::read(filename)
+        })? {
+            Ok(content) => Ok(Value::Bytes(Rc::new(content))),
+            Err(e) => Err(InfraError::IoError {
+                message: format!("Failed to read file '{}': {}", filename, e),
+                operation: Some("io.read_bytes".to_string()),
+                path: Some(filename.clone()),
+            }),
+        },
+        _ => Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("io.read_bytes() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Write a bytes buffer to a file, replacing its contents
+#[allow(dead_code)]
+pub fn write_bytes(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("io.write_bytes".to_string()),
+            line: None,
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::String(filename), Value::Bytes(content)) => {
+            fs::write(filename, content.as_slice()).map_err(|e| InfraError::IoError {
+                message: format!("Failed to write file '{}': {}", filename, e),
+                operation: Some("io.write_bytes".to_string()),
+                path: Some(filename.clone()),
+            })?;
+            Ok(Value::Null)
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "string and bytes".to_string(),
+            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+            context: Some("io.write_bytes() function".to_string()),
+            line: None,
+            column: None,
+            hint: Some("Convert text with bytes.from_string()".to_string()),
+        }),
+    }
+}
+
 /// Check if file exists
 #[allow(dead_code)]
 pub fn exists(args: &[Value]) -> Result<Value> {
@@ -90,7 +238,282 @@ pub fn exists(args: &[Value]) -> Result<Value> {
     }
 }
 
-/// Throw an exception that can be caught by try/catch
+/// Add string content to the end of a file, creating it if it is missing
+#[allow(dead_code)]
+pub fn append_file(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("io.append_file".to_string()),
+            line: None,
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::String(filename), Value::String(content)) => {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(filename)
+                .and_then(|mut file| file.write_all(content.as_bytes()))
+                .map_err(|e| io_error("append to file", "io.append_file", filename, e))?;
+            Ok(Value::Null)
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "two strings".to_string(),
+            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+            context: Some("io.append_file() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Delete a file; a missing file is an error
+#[allow(dead_code)]
+pub fn delete_file(args: &[Value]) -> Result<Value> {
+    let filename = string_arg(args, "io.delete_file")?;
+    fs::remove_file(filename)
+        .map_err(|e| io_error("delete file", "io.delete_file", filename, e))?;
+    Ok(Value::Null)
+}
+
+/// Create a directory and any missing parents; one that exists is left alone
+#[allow(dead_code)]
+pub fn mkdir(args: &[Value]) -> Result<Value> {
+    let dirname = string_arg(args, "io.mkdir")?;
+    fs::create_dir_all(dirname)
+        .map_err(|e| io_error("create directory", "io.mkdir", dirname, e))?;
+    Ok(Value::Null)
+}
+
+/// The names of a directory's entries, sorted
+#[allow(dead_code)]
+pub fn list_dir(args: &[Value]) -> Result<Value> {
+    let dirname = string_arg(args, "io.list_dir")?;
+    let mut names = fs::read_dir(dirname)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<std::io::Result<Vec<String>>>()
+        })
+        .map_err(|e| io_error("list directory", "io.list_dir", dirname, e))?;
+    names.sort();
+    Ok(Value::Array(names.into_iter().map(Value::String).collect()))
+}
+
+/// Set what `io.args()` returns: the arguments after the script's name
+pub fn set_script_args(args: Vec<String>) {
+    SCRIPT_ARGS.with(|script_args| *script_args.borrow_mut() = args);
+}
+
+/// io.args(): the script's command line arguments, as strings
+#[allow(dead_code)]
+pub fn script_args(args: &[Value]) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 0,
+            found: args.len(),
+            function_name: Some("io.args".to_string()),
+            line: None,
+        });
+    }
+
+    Ok(SCRIPT_ARGS.with(|script_args| {
+        Value::Array(
+            script_args
+                .borrow()
+                .iter()
+                .cloned()
+                .map(Value::String)
+                .collect(),
+        )
+    }))
+}
+
+/// io.env(name): the environment variable's value, or null if it is not set
+#[allow(dead_code)]
+pub fn env(args: &[Value]) -> Result<Value> {
+    let name = string_arg(args, "io.env")?;
+    Ok(std::env::var_os(name).map_or(Value::Null, |value| {
+        Value::String(value.to_string_lossy().into_owned())
+    }))
+}
+
+/// io.set_env(name, value): set an environment variable for the script and
+/// the processes it starts; a null value removes it
+#[allow(dead_code)]
+pub fn set_env(args: &[Value]) -> Result<Value> {
+    let (name, value) = match args {
+        [Value::String(name), value @ (Value::String(_) | Value::Null)] => (name, value),
+        [name, value] => {
+            return Err(InfraError::TypeError {
+                expected: "string and string or null".to_string(),
+                found: format!("{} and {}", name.type_name(), value.type_name()),
+                context: Some("io.set_env() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+        _ => {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: args.len(),
+                function_name: Some("io.set_env".to_string()),
+                line: None,
+            })
+        }
+    };
+
+    // The standard library panics on these rather than returning an error
+    let invalid = |what: &str| InfraError::RuntimeError {
+        message: format!("io.set_env: {}", what),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    };
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(invalid(&format!(
+            "invalid variable name '{}'",
+            name.escape_debug()
+        )));
+    }
+    match value {
+        Value::String(value) if value.contains('\0') => {
+            return Err(invalid("values cannot contain a NUL character"))
+        }
+        Value::String(value) => std::env::set_var(name, value),
+        _ => std::env::remove_var(name),
+    }
+    Ok(Value::Null)
+}
+
+/// io.read_line(): the next line of standard input without its line
+/// ending, or null once the input has ended
+#[allow(dead_code)]
+pub fn read_line(args: &[Value]) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 0,
+            found: args.len(),
+            function_name: Some("io.read_line".to_string()),
+            line: None,
+        });
+    }
+    read_stdin_line("io.read_line")
+}
+
+/// io.input(prompt?): print the prompt without ending the line, then read a
+/// line as io.read_line does
+#[allow(dead_code)]
+pub fn input(args: &[Value]) -> Result<Value> {
+    match args {
+        [] => {}
+        [Value::String(prompt)] => progress::print_prompt(prompt),
+        [other] => {
+            return Err(InfraError::TypeError {
+                expected: "string".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("io.input() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+        _ => {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: args.len(),
+                function_name: Some("io.input".to_string()),
+                line: None,
+            })
+        }
+    }
+    read_stdin_line("io.input")
+}
+
+/// io.read_all(): the rest of standard input, as when data is piped in
+#[allow(dead_code)]
+pub fn read_all(args: &[Value]) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 0,
+            found: args.len(),
+            function_name: Some("io.read_all".to_string()),
+            line: None,
+        });
+    }
+
+    let read = cancel::run_blocking("io.read_all", || {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map(|_| text)
+    })?;
+    read.map(Value::String)
+        .map_err(|e| stdin_error("io.read_all", e))
+}
+
+/// Read a line of standard input. Stdin is locked only for the read, so the
+/// REPL, which reads its own lines from it, can run scripts that use it.
+fn read_stdin_line(operation: &str) -> Result<Value> {
+    let read = cancel::run_blocking(operation, || {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|n| (n, line))
+    })?;
+    match read {
+        Ok((0, _)) => Ok(Value::Null),
+        Ok((_, line)) => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            Ok(Value::String(
+                line.strip_suffix('\r').unwrap_or(line).to_string(),
+            ))
+        }
+        Err(e) => Err(stdin_error(operation, e)),
+    }
+}
+
+fn stdin_error(operation: &str, error: std::io::Error) -> InfraError {
+    InfraError::IoError {
+        message: format!("Failed to read standard input: {}", error),
+        operation: Some(operation.to_string()),
+        path: None,
+    }
+}
+
+/// The single string argument of `function_name`
+fn string_arg<'a>(args: &'a [Value], function_name: &str) -> Result<&'a str> {
+    match args {
+        [Value::String(text)] => Ok(text),
+        [other] => Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: other.type_name().to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+        _ => Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some(function_name.to_string()),
+            line: None,
+        }),
+    }
+}
+
+fn io_error(action: &str, operation: &str, path: &str, error: std::io::Error) -> InfraError {
+    InfraError::IoError {
+        message: format!("Failed to {} '{}': {}", action, path, error),
+        operation: Some(operation.to_string()),
+        path: Some(path.to_string()),
+    }
+}
+
+/// Throw an exception that can be caught by try/catch. `catch` receives the
+/// thrown value unchanged, whatever it is.
 #[allow(dead_code)]
 pub fn throw_exception(args: &[Value]) -> Result<Value> {
     if args.len() != 1 {
@@ -102,20 +525,205 @@ pub fn throw_exception(args: &[Value]) -> Result<Value> {
         });
     }
 
+    let payload = Some(Box::new(args[0].clone()));
     match &args[0] {
-        Value::String(message) => Err(InfraError::Exception {
+        Value::Error {
+            error_type,
+            message,
+            line,
+            stack,
+            ..
+        } => Err(InfraError::Exception {
             message: message.clone(),
+            exception_type: Some(error_type.clone()),
+            line: *line,
+            stack_trace: stack.clone(),
+            payload,
+        }),
+        other => Err(InfraError::Exception {
+            message: other.to_string(),
             exception_type: None,
             line: None,
             stack_trace: vec![],
+            payload,
         }),
-        _ => Err(InfraError::TypeError {
-            expected: "string".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("throw_exception() function".to_string()),
+    }
+}
+
+/// Keep every temporary path instead of deleting it on cleanup
+pub fn set_keep_temp(keep: bool) {
+    KEEP_TEMP.with(|k| k.set(keep));
+}
+
+/// Delete all registered temporary paths, returning the ones that were kept
+pub fn cleanup_temp_paths() -> Vec<String> {
+    let keep_all = KEEP_TEMP.with(|k| k.get());
+    let paths = TEMP_PATHS.with(|t| std::mem::take(&mut *t.borrow_mut()));
+
+    let mut retained = Vec::new();
+    for (path, keep) in paths {
+        if keep || keep_all {
+            retained.push(path.display().to_string());
+        } else {
+            remove_temp_path(&path);
+        }
+    }
+    retained
+}
+
+/// Delete a single registered temporary path right away (unless it must be kept)
+pub fn release_temp_path(path: &str) {
+    let keep_all = KEEP_TEMP.with(|k| k.get());
+    let path = PathBuf::from(path);
+
+    TEMP_PATHS.with(|t| {
+        let mut paths = t.borrow_mut();
+        if let Some(pos) = paths.iter().position(|(p, _)| *p == path) {
+            let (_, keep) = paths[pos];
+            if !keep && !keep_all {
+                paths.remove(pos);
+                remove_temp_path(&path);
+            }
+        }
+    });
+}
+
+/// Create a temporary directory and register it for cleanup
+pub fn create_temp_dir(prefix: &str, keep: bool) -> Result<String> {
+    create_temp_path(prefix, "", keep, "temp_dir", |path| fs::create_dir(path))
+}
+
+/// Create an empty temporary file and register it for cleanup
+pub fn create_temp_file(prefix: &str, suffix: &str, keep: bool) -> Result<String> {
+    create_temp_path(prefix, suffix, keep, "temp_file", |path| {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(|_| ())
+    })
+}
+
+fn remove_temp_path(path: &Path) {
+    // Cleanup is best effort: the script may already have removed the path itself
+    if path.is_dir() {
+        let _ = fs::remove_dir_all(path);
+    } else {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn create_temp_path(
+    prefix: &str,
+    suffix: &str,
+    keep: bool,
+    operation: &str,
+    create: impl Fn(&Path) -> std::io::Result<()>,
+) -> Result<String> {
+    let nonce = (sources::random_u64() >> 32) as u32;
+
+    for _ in 0..100 {
+        let counter = TEMP_COUNTER.with(|c| {
+            c.set(c.get() + 1);
+            c.get()
+        });
+        let name = format!(
+            "{}{}-{}-{}{}",
+            prefix,
+            std::process::id(),
+            nonce,
+            counter,
+            suffix
+        );
+        let path = std::env::temp_dir().join(name);
+
+        match create(&path) {
+            Ok(()) => {
+                TEMP_PATHS.with(|t| t.borrow_mut().push((path.clone(), keep)));
+                return Ok(path.display().to_string());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(InfraError::IoError {
+                    message: format!("Failed to create temporary path: {}", e),
+                    operation: Some(operation.to_string()),
+                    path: Some(path.display().to_string()),
+                })
+            }
+        }
+    }
+
+    Err(InfraError::IoError {
+        message: "Failed to find an unused temporary path".to_string(),
+        operation: Some(operation.to_string()),
+        path: None,
+    })
+}
+
+/// Split `(string?, string?, options?)` style arguments into strings and the `keep` option
+fn temp_args(
+    args: &[Value],
+    max_strings: usize,
+    function_name: &str,
+) -> Result<(Vec<String>, bool)> {
+    let mut strings = Vec::new();
+    let mut keep = false;
+
+    for (i, arg) in args.iter().enumerate() {
+        match arg {
+            Value::String(s) if strings.len() < max_strings => strings.push(s.clone()),
+            Value::Object(options) if i == args.len() - 1 => {
+                keep = options.get("keep").map(|v| v.is_truthy()).unwrap_or(false);
+            }
+            _ => {
+                return Err(InfraError::TypeError {
+                    expected: "string or options object".to_string(),
+                    found: arg.type_name().to_string(),
+                    context: Some(format!("{}() function", function_name)),
+                    line: None,
+                    column: None,
+                    hint: Some("Options must be the last argument, e.g. {keep: true}".to_string()),
+                })
+            }
+        }
+    }
+
+    Ok((strings, keep))
+}
+
+/// Create an empty temporary file that is deleted when the script exits
+#[allow(dead_code)]
+pub fn temp_file(args: &[Value]) -> Result<Value> {
+    if args.len() > 3 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 3,
+            found: args.len(),
+            function_name: Some("io.temp_file".to_string()),
             line: None,
-            column: None,
-            hint: None,
-        }),
+        });
     }
+
+    let (strings, keep) = temp_args(args, 2, "io.temp_file")?;
+    let prefix = strings.first().map(String::as_str).unwrap_or("infra-");
+    let suffix = strings.get(1).map(String::as_str).unwrap_or("");
+
+    create_temp_file(prefix, suffix, keep).map(Value::String)
+}
+
+/// Create a temporary directory that is deleted (recursively) when the script exits
+#[allow(dead_code)]
+pub fn temp_dir(args: &[Value]) -> Result<Value> {
+    if args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("io.temp_dir".to_string()),
+            line: None,
+        });
+    }
+
+    let (strings, keep) = temp_args(args, 1, "io.temp_dir")?;
+    let prefix = strings.first().map(String::as_str).unwrap_or("infra-");
+
+    create_temp_dir(prefix, keep).map(Value::String)
 }
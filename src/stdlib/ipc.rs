@@ -0,0 +1,80 @@
+use crate::core::wire::WireError;
+use crate::core::{InfraError, Result, Value};
+use crate::utils::base64;
+use std::rc::Rc;
+
+/// Encode a value in the binary wire format
+#[allow(dead_code)]
+pub fn encode(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("ipc.encode".to_string()),
+            line: None,
+        });
+    }
+
+    let bytes = to_bytes(&args[0], "ipc.encode")?;
+    Ok(Value::Bytes(Rc::new(bytes)))
+}
+
+/// Decode a value produced by ipc.encode. Base64 text is accepted too, as
+/// ipc.encode returned it before there was a bytes value.
+#[allow(dead_code)]
+pub fn decode(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("ipc.decode".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::Bytes(bytes) => from_bytes(bytes, "ipc.decode"),
+        Value::String(text) => {
+            let bytes =
+                base64::decode(text).map_err(|message| decode_error(message, "ipc.decode"))?;
+            from_bytes(&bytes, "ipc.decode")
+        }
+        other => Err(InfraError::TypeError {
+            expected: "bytes".to_string(),
+            found: other.type_name().to_string(),
+            context: Some("ipc.decode() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Encode a value, reporting values without an encoding as type errors
+pub fn to_bytes(value: &Value, function_name: &str) -> Result<Vec<u8>> {
+    value.to_bytes().map_err(|err| match err {
+        WireError::Unsupported(type_name) => InfraError::TypeError {
+            expected: "null, boolean, number, bigint, string, bytes, array or object".to_string(),
+            found: type_name.to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: Some("Functions, promises and error values cannot be serialized".to_string()),
+        },
+        other => decode_error(other.to_string(), function_name),
+    })
+}
+
+pub fn from_bytes(bytes: &[u8], function_name: &str) -> Result<Value> {
+    Value::from_bytes(bytes).map_err(|err| decode_error(err.to_string(), function_name))
+}
+
+fn decode_error(message: String, function_name: &str) -> InfraError {
+    InfraError::RuntimeError {
+        message: format!("{}: {}", function_name, message),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
@@ -0,0 +1,242 @@
+//! JSON documents to and from values: `json.parse(text)` and
+//! `json.stringify(value, indent?)`.
+//!
+//! Objects keep their key order both ways. Tuples are written as arrays,
+//! enum members by name, class instances as their fields and bigints as
+//! strings, as everywhere else values become JSON; functions, promises,
+//! bytes, enums and classes have no JSON form.
+
+use crate::core::value::write_json_string;
+use crate::core::value::Instance;
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use crate::utils::json;
+use std::rc::Rc;
+
+/// Deepest indent json.stringify accepts, as in other languages
+const MAX_INDENT: usize = 10;
+
+/// json.parse(text): the value the JSON document in `text` describes
+#[allow(dead_code)]
+pub fn parse(args: &[Value]) -> Result<Value> {
+    check_count(args, 1, 1, "parse")?;
+    let Value::String(text) = &args[0] else {
+        return Err(type_error("string", &args[0], "parse"));
+    };
+
+    json::parse(text).map_err(|message| InfraError::RuntimeError {
+        message: format!("json.parse: {}", message),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    })
+}
+
+/// json.stringify(value, indent?): `value` as compact JSON, or spread over
+/// lines with `indent` spaces (or the given string) per level
+#[allow(dead_code)]
+pub fn stringify(args: &[Value]) -> Result<Value> {
+    check_count(args, 1, 2, "stringify")?;
+    let indent = match args.get(1) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => {
+            " ".repeat((*n as usize).min(MAX_INDENT))
+        }
+        Some(Value::String(s)) => s.chars().take(MAX_INDENT).collect(),
+        Some(other) => return Err(type_error("whole number or string", other, "stringify")),
+    };
+
+    let mut writer = Writer {
+        out: String::new(),
+        indent,
+        path: "$".to_string(),
+        open: Vec::new(),
+    };
+    writer.value(&args[0], 0)?;
+    Ok(Value::String(writer.out))
+}
+
+struct Writer {
+    out: String,
+    indent: String,
+    /// Where in the value being written we are, as `$.config.ports[2]`
+    path: String,
+    /// Instances between the top of the value and `path`
+    open: Vec<Rc<Instance>>,
+}
+
+impl Writer {
+    fn value(&mut self, value: &Value, depth: usize) -> Result<()> {
+        match value {
+            Value::Number(n) if n.is_finite() => self.out.push_str(&n.to_string()),
+            Value::BigInt(n) => write_json_string(&n.to_string(), &mut self.out),
+            Value::String(s) => write_json_string(s, &mut self.out),
+            Value::Boolean(b) => self.out.push_str(&b.to_string()),
+            Value::Null => self.out.push_str("null"),
+            Value::EnumMember { definition, index } => {
+                write_json_string(&definition.members[*index], &mut self.out)
+            }
+            Value::Array(items) => self.items(items, depth)?,
+            Value::Tuple(items) => self.items(items, depth)?,
+            Value::Object(obj) => self.object(obj, depth)?,
+            // An instance is written as its fields
+            Value::Instance(instance) => {
+                if self.open.iter().any(|open| Rc::ptr_eq(open, instance)) {
+                    return Err(self.cycle());
+                }
+                self.open.push(instance.clone());
+                let written = self.object(&instance.fields.borrow(), depth);
+                self.open.pop();
+                written?
+            }
+            Value::Error {
+                error_type,
+                message,
+                fields,
+                ..
+            } => {
+                let fields_value = Value::Object(fields.clone());
+                let message = Value::String(message.clone());
+                let error_type = Value::String(error_type.clone());
+                self.fields(
+                    &[
+                        ("fields", &fields_value),
+                        ("message", &message),
+                        ("type", &error_type),
+                    ],
+                    depth,
+                )?;
+            }
+            Value::Number(_) => return Err(self.unserializable("finite number", value)),
+            Value::Bytes(_)
+            | Value::Enum(_)
+            | Value::Class(_)
+            | Value::Function { .. }
+            | Value::NativeFunction { .. }
+            | Value::CompiledFunction { .. }
+            | Value::Promise { .. } => {
+                return Err(self.unserializable("JSON-compatible value", value))
+            }
+        }
+        Ok(())
+    }
+
+    fn items(&mut self, items: &[Value], depth: usize) -> Result<()> {
+        self.out.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(depth + 1);
+            let outer = self.path.len();
+            self.path.push_str(&format!("[{}]", i));
+            self.value(item, depth + 1)?;
+            self.path.truncate(outer);
+        }
+        if !items.is_empty() {
+            self.newline(depth);
+        }
+        self.out.push(']');
+        Ok(())
+    }
+
+    fn object(&mut self, obj: &ObjectMap, depth: usize) -> Result<()> {
+        let fields: Vec<(&str, &Value)> = obj
+            .iter()
+            .map(|(key, value)| (key.as_str(), value))
+            .collect();
+        self.fields(&fields, depth)
+    }
+
+    fn fields(&mut self, fields: &[(&str, &Value)], depth: usize) -> Result<()> {
+        self.out.push('{');
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(depth + 1);
+            write_json_string(key, &mut self.out);
+            self.out.push(':');
+            if !self.indent.is_empty() {
+                self.out.push(' ');
+            }
+
+            let outer = self.path.len();
+            if is_plain_key(key) {
+                self.path.push_str(&format!(".{}", key));
+            } else {
+                self.path.push('[');
+                write_json_string(key, &mut self.path);
+                self.path.push(']');
+            }
+            self.value(value, depth + 1)?;
+            self.path.truncate(outer);
+        }
+        if !fields.is_empty() {
+            self.newline(depth);
+        }
+        self.out.push('}');
+        Ok(())
+    }
+
+    /// Start a line `depth` levels in, when writing indented JSON
+    fn newline(&mut self, depth: usize) {
+        if !self.indent.is_empty() {
+            self.out.push('\n');
+            self.out.push_str(&self.indent.repeat(depth));
+        }
+    }
+
+    /// The instance at `path` is one of those it is inside
+    fn cycle(&self) -> InfraError {
+        InfraError::TypeError {
+            expected: "value without cycles".to_string(),
+            found: "instance that contains itself".to_string(),
+            context: Some(format!("json.stringify() at {}", self.path)),
+            line: None,
+            column: None,
+            hint: Some("Write an id in place of the reference back".to_string()),
+        }
+    }
+
+    fn unserializable(&self, expected: &str, found: &Value) -> InfraError {
+        InfraError::TypeError {
+            expected: expected.to_string(),
+            found: found.type_name().to_string(),
+            context: Some(format!("json.stringify() at {}", self.path)),
+            line: None,
+            column: None,
+            hint: Some("Leave it out or convert it to a string first".to_string()),
+        }
+    }
+}
+
+/// Whether `key` can follow a `.` in a path
+fn is_plain_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn check_count(args: &[Value], min: usize, max: usize, function_name: &str) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: if args.len() < min { min } else { max },
+            found: args.len(),
+            function_name: Some(format!("json.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("json.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
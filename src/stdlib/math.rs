@@ -1,185 +1,214 @@
+//! Numbers in, numbers out: rounding, powers, logarithms, trigonometry and
+//! the constants `math.pi()` and `math.e()`.
+//!
+//! Arguments outside a function's domain, such as `math.log(-1)`, are
+//! runtime errors rather than NaN, so a bad value stops the script where it
+//! first appears instead of spreading through later arithmetic.
+
 use crate::core::{InfraError, Result, Value};
 
+/// math.pi(): the ratio of a circle's circumference to its diameter
+#[allow(dead_code)]
+pub fn pi(args: &[Value]) -> Result<Value> {
+    check_count(args, 0, "pi")?;
+    Ok(Value::Number(std::f64::consts::PI))
+}
+
+/// math.e(): the base of natural logarithms
+#[allow(dead_code)]
+pub fn e(args: &[Value]) -> Result<Value> {
+    check_count(args, 0, "e")?;
+    Ok(Value::Number(std::f64::consts::E))
+}
+
 /// Square root function
 #[allow(dead_code)]
 pub fn sqrt(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
-            found: args.len(),
-            function_name: Some("math_sqrt".to_string()),
-            line: None,
-        });
-    }
-
-    match &args[0] {
-        Value::Number(n) => {
-            if *n < 0.0 {
-                Err(InfraError::RuntimeError {
-                    message: "Cannot take square root of negative number".to_string(),
-                    line: None,
-                    column: None,
-                    stack_trace: vec![],
-                    source_code: None,
-                })
-            } else {
-                Ok(Value::Number(n.sqrt()))
-            }
-        }
-        _ => Err(InfraError::TypeError {
-            expected: "number".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("math_sqrt() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    let n = unary(args, "sqrt")?;
+    if n < 0.0 {
+        return Err(domain_error(
+            "sqrt",
+            n,
+            "Cannot take the square root of a negative number",
+        ));
+    }
+    Ok(Value::Number(n.sqrt()))
 }
 
 /// Absolute value function
 #[allow(dead_code)]
 pub fn abs(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
-            found: args.len(),
-            function_name: Some("math_abs".to_string()),
-            line: None,
-        });
-    }
-
-    match &args[0] {
-        Value::Number(n) => Ok(Value::Number(n.abs())),
-        _ => Err(InfraError::TypeError {
-            expected: "number".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("math_abs() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    Ok(Value::Number(unary(args, "abs")?.abs()))
 }
 
-/// Maximum of two numbers
+/// Largest of the numbers given, or of the numbers in a single array
 #[allow(dead_code)]
 pub fn max(args: &[Value]) -> Result<Value> {
-    if args.len() != 2 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 2,
-            found: args.len(),
-            function_name: Some("math_max".to_string()),
-            line: None,
-        });
-    }
-
-    match (&args[0], &args[1]) {
-        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.max(*b))),
-        _ => Err(InfraError::TypeError {
-            expected: "two numbers".to_string(),
-            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
-            context: Some("math_max() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    let numbers = numbers(args, "max")?;
+    Ok(Value::Number(
+        numbers.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    ))
 }
 
-/// Minimum of two numbers
+/// Smallest of the numbers given, or of the numbers in a single array
 #[allow(dead_code)]
 pub fn min(args: &[Value]) -> Result<Value> {
-    if args.len() != 2 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 2,
-            found: args.len(),
-            function_name: Some("math_min".to_string()),
-            line: None,
-        });
-    }
-
-    match (&args[0], &args[1]) {
-        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.min(*b))),
-        _ => Err(InfraError::TypeError {
-            expected: "two numbers".to_string(),
-            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
-            context: Some("math_min() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    let numbers = numbers(args, "min")?;
+    Ok(Value::Number(
+        numbers.into_iter().fold(f64::INFINITY, f64::min),
+    ))
 }
 
 /// Power function (base^exponent)
 #[allow(dead_code)]
 pub fn pow(args: &[Value]) -> Result<Value> {
-    if args.len() != 2 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 2,
-            found: args.len(),
-            function_name: Some("math_pow".to_string()),
-            line: None,
-        });
-    }
-
-    match (&args[0], &args[1]) {
-        (Value::Number(base), Value::Number(exp)) => Ok(Value::Number(base.powf(*exp))),
-        _ => Err(InfraError::TypeError {
-            expected: "two numbers".to_string(),
-            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
-            context: Some("math_pow() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    let (base, exponent) = binary(args, "pow")?;
+    Ok(Value::Number(base.powf(exponent)))
 }
 
 /// Floor function
 #[allow(dead_code)]
 pub fn floor(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
-            found: args.len(),
-            function_name: Some("math_floor".to_string()),
-            line: None,
-        });
-    }
-
-    match &args[0] {
-        Value::Number(n) => Ok(Value::Number(n.floor())),
-        _ => Err(InfraError::TypeError {
-            expected: "number".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("math_floor() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
-    }
+    Ok(Value::Number(unary(args, "floor")?.floor()))
 }
 
 /// Ceiling function
 #[allow(dead_code)]
 pub fn ceil(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
-        return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
-            found: args.len(),
-            function_name: Some("math_ceil".to_string()),
-            line: None,
-        });
-    }
+    Ok(Value::Number(unary(args, "ceil")?.ceil()))
+}
 
-    match &args[0] {
-        Value::Number(n) => Ok(Value::Number(n.ceil())),
-        _ => Err(InfraError::TypeError {
+/// Round function
+#[allow(dead_code)]
+pub fn round(args: &[Value]) -> Result<Value> {
+    Ok(Value::Number(unary(args, "round")?.round()))
+}
+
+/// math.clamp(x, lo, hi): `x`, moved into the range from `lo` to `hi`
+#[allow(dead_code)]
+pub fn clamp(args: &[Value]) -> Result<Value> {
+    check_count(args, 3, "clamp")?;
+    let x = number(&args[0], "clamp")?;
+    let lo = number(&args[1], "clamp")?;
+    let hi = number(&args[2], "clamp")?;
+    if lo.is_nan() || hi.is_nan() || lo > hi {
+        return Err(runtime_error(format!(
+            "math.clamp: lo ({}) must not be greater than hi ({})",
+            Value::Number(lo),
+            Value::Number(hi)
+        )));
+    }
+    Ok(Value::Number(x.clamp(lo, hi)))
+}
+
+/// Sine of an angle in radians
+#[allow(dead_code)]
+pub fn sin(args: &[Value]) -> Result<Value> {
+    Ok(Value::Number(unary(args, "sin")?.sin()))
+}
+
+/// Cosine of an angle in radians
+#[allow(dead_code)]
+pub fn cos(args: &[Value]) -> Result<Value> {
+    Ok(Value::Number(unary(args, "cos")?.cos()))
+}
+
+/// Tangent of an angle in radians
+#[allow(dead_code)]
+pub fn tan(args: &[Value]) -> Result<Value> {
+    Ok(Value::Number(unary(args, "tan")?.tan()))
+}
+
+/// math.atan2(y, x): the angle in radians from the x axis to the point
+/// (x, y), from -pi to pi
+#[allow(dead_code)]
+pub fn atan2(args: &[Value]) -> Result<Value> {
+    let (y, x) = binary(args, "atan2")?;
+    Ok(Value::Number(y.atan2(x)))
+}
+
+/// Natural logarithm
+#[allow(dead_code)]
+pub fn log(args: &[Value]) -> Result<Value> {
+    let n = unary(args, "log")?;
+    if n <= 0.0 {
+        return Err(domain_error(
+            "log",
+            n,
+            "Cannot take the logarithm of a number that is not positive",
+        ));
+    }
+    Ok(Value::Number(n.ln()))
+}
+
+/// Base-10 logarithm
+#[allow(dead_code)]
+pub fn log10(args: &[Value]) -> Result<Value> {
+    let n = unary(args, "log10")?;
+    if n <= 0.0 {
+        return Err(domain_error(
+            "log10",
+            n,
+            "Cannot take the logarithm of a number that is not positive",
+        ));
+    }
+    Ok(Value::Number(n.log10()))
+}
+
+/// e raised to the given power
+#[allow(dead_code)]
+pub fn exp(args: &[Value]) -> Result<Value> {
+    Ok(Value::Number(unary(args, "exp")?.exp()))
+}
+
+/// The one number a function of one argument takes
+fn unary(args: &[Value], function_name: &str) -> Result<f64> {
+    check_count(args, 1, function_name)?;
+    number(&args[0], function_name)
+}
+
+fn binary(args: &[Value], function_name: &str) -> Result<(f64, f64)> {
+    check_count(args, 2, function_name)?;
+    Ok((
+        number(&args[0], function_name)?,
+        number(&args[1], function_name)?,
+    ))
+}
+
+/// The numbers passed to max or min: one or more numbers, or a single
+/// non-empty array of them
+fn numbers(args: &[Value], function_name: &str) -> Result<Vec<f64>> {
+    let values = match args {
+        [] => {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: 0,
+                function_name: Some(format!("math.{}", function_name)),
+                line: None,
+            })
+        }
+        [Value::Array(items)] if items.is_empty() => {
+            return Err(runtime_error(format!(
+                "math.{}: the array is empty",
+                function_name
+            )))
+        }
+        [Value::Array(items)] => items.as_slice(),
+        values => values,
+    };
+    values
+        .iter()
+        .map(|value| number(value, function_name))
+        .collect()
+}
+
+fn number(value: &Value, function_name: &str) -> Result<f64> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        other => Err(InfraError::TypeError {
             expected: "number".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("math_ceil() function".to_string()),
+            found: other.type_name().to_string(),
+            context: Some(format!("math.{}() function", function_name)),
             line: None,
             column: None,
             hint: None,
@@ -187,27 +216,34 @@ pub fn ceil(args: &[Value]) -> Result<Value> {
     }
 }
 
-/// Round function
-#[allow(dead_code)]
-pub fn round(args: &[Value]) -> Result<Value> {
-    if args.len() != 1 {
+fn check_count(args: &[Value], expected: usize, function_name: &str) -> Result<()> {
+    if args.len() != expected {
         return Err(InfraError::ArgumentCountMismatch {
-            expected: 1,
+            expected,
             found: args.len(),
-            function_name: Some("math_round".to_string()),
+            function_name: Some(format!("math.{}", function_name)),
             line: None,
         });
     }
+    Ok(())
+}
 
-    match &args[0] {
-        Value::Number(n) => Ok(Value::Number(n.round())),
-        _ => Err(InfraError::TypeError {
-            expected: "number".to_string(),
-            found: args[0].type_name().to_string(),
-            context: Some("math_round() function".to_string()),
-            line: None,
-            column: None,
-            hint: None,
-        }),
+/// `n` is outside the numbers `function_name` is defined for
+fn domain_error(function_name: &str, n: f64, problem: &str) -> InfraError {
+    runtime_error(format!(
+        "{}: math.{}({}) has no real result",
+        problem,
+        function_name,
+        Value::Number(n)
+    ))
+}
+
+fn runtime_error(message: String) -> InfraError {
+    InfraError::RuntimeError {
+        message,
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
     }
 }
@@ -0,0 +1,438 @@
+//! Memoized functions: `memo.wrap(fn, options?)` returns a function that
+//! caches results by argument, in memory and optionally on disk.
+//!
+//! Each evaluator keeps its own caches, so engines running side by side
+//! never answer each other's calls; the `memo` functions live in the
+//! evaluator and this module keeps the tables.
+
+use crate::core::{ast::*, InfraError, ObjectMap, Result, Value};
+use crate::frontend::printer;
+use crate::utils::{json, VERSION};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+const DEFAULT_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// Cache state for one `memo.wrap` call
+struct MemoTable {
+    function: Value,
+    function_hash: String,
+    persist: Option<PathBuf>,
+    max_entries: usize,
+    max_bytes: usize,
+    entries: HashMap<String, Value>,
+    order: VecDeque<String>,
+    bytes: usize,
+    hits: usize,
+    misses: usize,
+}
+
+/// The caches of one evaluator's memoized functions, by id
+#[derive(Default)]
+pub struct MemoTables {
+    tables: Vec<MemoTable>,
+}
+
+/// Result of looking up a memoized call
+pub enum MemoLookup {
+    Hit(Value),
+    Miss {
+        function: Value,
+        id: usize,
+        key: String,
+    },
+}
+
+impl MemoTables {
+    /// Wrap a function so its results are cached by argument
+    pub fn wrap(&mut self, args: &[Value]) -> Result<Value> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: args.len(),
+                function_name: Some("memo.wrap".to_string()),
+                line: None,
+            });
+        }
+
+        let (name, params, param_types, optional_params, return_type, body) = match &args[0] {
+            Value::Function {
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+                ..
+            } => (
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body,
+            ),
+            other => {
+                return Err(InfraError::TypeError {
+                    expected: "function".to_string(),
+                    found: other.type_name().to_string(),
+                    context: Some("memo.wrap() function".to_string()),
+                    line: None,
+                    column: None,
+                    hint: None,
+                })
+            }
+        };
+
+        let mut persist = None;
+        let mut max_entries = DEFAULT_MAX_ENTRIES;
+        let mut max_bytes = DEFAULT_MAX_BYTES;
+
+        if let Some(options) = args.get(1) {
+            let options = options.as_object().ok_or_else(|| InfraError::TypeError {
+                expected: "options object".to_string(),
+                found: options.type_name().to_string(),
+                context: Some("memo.wrap() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Use e.g. {persist: \".infra-cache\", max_entries: 100}".to_string()),
+            })?;
+
+            if let Some(dir) = options.get("persist") {
+                match dir {
+                    Value::String(dir) => persist = Some(PathBuf::from(dir)),
+                    Value::Null => {}
+                    other => return Err(option_type_error("persist", "string", other)),
+                }
+            }
+            if let Some(limit) = options.get("max_entries") {
+                max_entries = limit_option("max_entries", limit)?;
+            }
+            if let Some(limit) = options.get("max_bytes") {
+                max_bytes = limit_option("max_bytes", limit)?;
+            }
+        }
+
+        // The function's source is the hash, so persisted results are dropped
+        // when the function changes but not when it only moves in its file
+        let function_hash = format!(
+            "{:016x}",
+            fnv1a(&printer::format_function(
+                name,
+                params,
+                param_types,
+                optional_params,
+                return_type,
+                body
+            ))
+        );
+
+        self.tables.push(MemoTable {
+            function: args[0].clone(),
+            function_hash,
+            persist,
+            max_entries,
+            max_bytes,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+        });
+        let id = self.tables.len() - 1;
+
+        // The wrapper is an ordinary function whose body forwards to memo.__call
+        let mut call_args = vec![Expr::Literal(Value::Number(id as f64))];
+        call_args.extend(params.iter().map(Expr::identifier));
+
+        Ok(Value::Function {
+            name: name.clone(),
+            params: params.clone(),
+            param_types: param_types.clone(),
+            optional_params: Rc::clone(optional_params),
+            return_type: return_type.clone(),
+            body: Rc::new(Stmt::Return(Some(Expr::Call {
+                callee: Box::new(Expr::ModuleAccess {
+                    module: "memo".to_string(),
+                    function: "__call".to_string(),
+                }),
+                args: call_args,
+                span: None,
+            }))),
+            // The original keeps its own captures; the wrapper needs none
+            captured: Default::default(),
+            is_async: false,
+        })
+    }
+
+    /// Clear the cache of one memoized function, or of all of them
+    pub fn clear(&mut self, args: &[Value]) -> Result<Value> {
+        if args.len() > 1 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: args.len(),
+                function_name: Some("memo.clear".to_string()),
+                line: None,
+            });
+        }
+
+        let only = match args.first() {
+            Some(function) => Some(memo_id(function, "memo.clear")?),
+            None => None,
+        };
+
+        for (id, table) in self.tables.iter_mut().enumerate() {
+            if only.is_none_or(|only| only == id) {
+                table.clear();
+            }
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Get hit/miss statistics for a memoized function
+    pub fn stats(&self, args: &[Value]) -> Result<Value> {
+        if args.len() != 1 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: args.len(),
+                function_name: Some("memo.stats".to_string()),
+                line: None,
+            });
+        }
+
+        let id = memo_id(&args[0], "memo.stats")?;
+        let table = self.table(id, "memo.stats")?;
+
+        let mut stats = ObjectMap::new();
+        stats.insert("hits".to_string(), Value::Number(table.hits as f64));
+        stats.insert("misses".to_string(), Value::Number(table.misses as f64));
+        stats.insert(
+            "entries".to_string(),
+            Value::Number(table.entries.len() as f64),
+        );
+        Ok(Value::Object(stats))
+    }
+
+    /// Look up a memoized call made through a wrapper function
+    pub fn lookup(&mut self, args: &[Value]) -> Result<MemoLookup> {
+        let id = match args.first() {
+            Some(Value::Number(n)) if *n >= 0.0 && (*n as usize) < self.tables.len() => *n as usize,
+            _ => {
+                return Err(InfraError::RuntimeError {
+                    message: "memo.__call is internal to memo.wrap".to_string(),
+                    line: None,
+                    column: None,
+                    stack_trace: vec![],
+                    source_code: None,
+                })
+            }
+        };
+
+        let call_args = Value::Array(args[1..].to_vec());
+        let key = call_args
+        .to_canonical_string()
+        .ok_or_else(|| InfraError::TypeError {
+            expected: "serializable arguments".to_string(),
+            found: describe_unserializable(&args[1..]),
+            context: Some("call to a memo.wrap() function".to_string()),
+            line: None,
+            column: None,
+            hint: Some(
+                "Memoized functions can only take numbers, strings, booleans, null, arrays and objects"
+                    .to_string(),
+            ),
+        })?;
+
+        let table = &mut self.tables[id];
+
+        if let Some(value) = table.entries.get(&key).cloned() {
+            table.hits += 1;
+            return Ok(MemoLookup::Hit(value));
+        }
+
+        if let Some(value) = table.read_persisted(&key) {
+            table.hits += 1;
+            table.insert(key, value.clone());
+            return Ok(MemoLookup::Hit(value));
+        }
+
+        table.misses += 1;
+        Ok(MemoLookup::Miss {
+            function: table.function.clone(),
+            id,
+            key,
+        })
+    }
+
+    /// Record the result of a call that missed the cache
+    pub fn store(&mut self, id: usize, key: String, value: &Value) {
+        if let Some(table) = self.tables.get_mut(id) {
+            table.write_persisted(&key, value);
+            table.insert(key, value.clone());
+        }
+    }
+
+    /// The table of a function wrapped by this evaluator; a wrapper made by
+    /// another engine has no table here
+    fn table(&self, id: usize, function_name: &str) -> Result<&MemoTable> {
+        self.tables.get(id).ok_or_else(|| InfraError::RuntimeError {
+            message: format!(
+                "{}: the function was memoized by another interpreter",
+                function_name
+            ),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        })
+    }
+}
+
+impl MemoTable {
+    fn insert(&mut self, key: String, value: Value) {
+        let size = entry_size(&key, &value);
+        if size > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+
+        while self.entries.len() >= self.max_entries || self.bytes + size > self.max_bytes {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.bytes -= entry_size(&oldest, &old);
+            }
+        }
+
+        self.bytes += size;
+        self.order.push_back(key.clone());
+        self.entries.insert(key, value);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+        self.hits = 0;
+        self.misses = 0;
+
+        if let Some(dir) = &self.persist {
+            if let Ok(files) = fs::read_dir(dir) {
+                for file in files.flatten() {
+                    let name = file.file_name();
+                    if name.to_string_lossy().starts_with(&self.function_hash) {
+                        let _ = fs::remove_file(file.path());
+                    }
+                }
+            }
+        }
+    }
+
+    fn cache_file(&self, key: &str) -> Option<PathBuf> {
+        self.persist
+            .as_ref()
+            .map(|dir| dir.join(format!("{}-{:016x}.json", self.function_hash, fnv1a(key))))
+    }
+
+    /// Read a persisted entry; anything unreadable, corrupt or written by a
+    /// different Infra version is treated as a miss
+    fn read_persisted(&self, key: &str) -> Option<Value> {
+        let contents = fs::read_to_string(self.cache_file(key)?).ok()?;
+        let entry = json::parse(&contents).ok()?;
+        let entry = entry.as_object()?;
+
+        if entry.get("version")?.as_string()? != VERSION || entry.get("key")?.as_string()? != key {
+            return None;
+        }
+        entry.get("value").cloned()
+    }
+
+    fn write_persisted(&self, key: &str, value: &Value) {
+        let Some(path) = self.cache_file(key) else {
+            return;
+        };
+
+        let mut entry = ObjectMap::new();
+        entry.insert("version".to_string(), Value::String(VERSION.to_string()));
+        entry.insert("key".to_string(), Value::String(key.to_string()));
+        entry.insert("value".to_string(), value.clone());
+
+        // Results that cannot be serialized are only cached in memory, and a
+        // cache directory that cannot be written is not an error
+        if let Some(contents) = Value::Object(entry).to_canonical_string() {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(path, contents);
+        }
+    }
+}
+
+/// Find the cache id of a function returned by memo.wrap
+fn memo_id(function: &Value, function_name: &str) -> Result<usize> {
+    if let Value::Function { body, .. } = function {
+        if let Stmt::Return(Some(Expr::Call { callee, args, .. })) = body.as_ref() {
+            if let (
+                Expr::ModuleAccess { module, function },
+                Some(Expr::Literal(Value::Number(id))),
+            ) = (callee.as_ref(), args.first())
+            {
+                if module == "memo" && function == "__call" {
+                    return Ok(*id as usize);
+                }
+            }
+        }
+    }
+
+    Err(InfraError::TypeError {
+        expected: "function returned by memo.wrap".to_string(),
+        found: function.type_name().to_string(),
+        context: Some(format!("{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    })
+}
+
+fn limit_option(name: &str, value: &Value) -> Result<usize> {
+    match value {
+        Value::Number(n) if *n >= 0.0 => Ok(*n as usize),
+        other => Err(option_type_error(name, "non-negative number", other)),
+    }
+}
+
+fn option_type_error(name: &str, expected: &str, found: &Value) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("memo.wrap() option '{}'", name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn describe_unserializable(args: &[Value]) -> String {
+    args.iter()
+        .find(|arg| arg.to_canonical_string().is_none())
+        .map(|arg| format!("{} argument", arg.type_name()))
+        .unwrap_or_else(|| "unserializable argument".to_string())
+}
+
+fn entry_size(key: &str, value: &Value) -> usize {
+    key.len() + value.to_string().len()
+}
+
+/// 64-bit FNV-1a, used because it is stable across Rust versions
+fn fnv1a(data: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
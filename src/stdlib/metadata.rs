@@ -0,0 +1,966 @@
+//! What each registered function takes and does, for tools that describe
+//! calls without making them, such as the language server's hover.
+//!
+//! Arities are the ones scripts see: for `array.filter` and `array.reduce`
+//! that includes the forms the evaluator handles before the registered
+//! function is reached. A test checks that every registered function has an
+//! entry here and every entry is registered.
+
+use std::fmt;
+
+/// How many arguments a function accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` for any number from `min` up
+    pub max: Option<usize>,
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        match (self.min, self.max) {
+            (0, None) => write!(f, "any number of arguments"),
+            (min, None) => write!(f, "at least {} argument{}", min, plural(min)),
+            (min, Some(max)) if min == max => write!(f, "{} argument{}", min, plural(min)),
+            (min, Some(max)) => write!(f, "{} to {} arguments", min, max),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub arity: Arity,
+    /// One sentence, without a final full stop
+    pub summary: &'static str,
+}
+
+const fn exactly(n: usize) -> Arity {
+    Arity {
+        min: n,
+        max: Some(n),
+    }
+}
+
+const fn between(min: usize, max: usize) -> Arity {
+    Arity {
+        min,
+        max: Some(max),
+    }
+}
+
+const fn at_least(min: usize) -> Arity {
+    Arity { min, max: None }
+}
+
+const fn function(
+    module: &'static str,
+    name: &'static str,
+    arity: Arity,
+    summary: &'static str,
+) -> FunctionInfo {
+    FunctionInfo {
+        module,
+        name,
+        arity,
+        summary,
+    }
+}
+
+/// Every built-in function, by module in registration order
+pub const FUNCTIONS: &[FunctionInfo] = &[
+    function(
+        "math",
+        "sqrt",
+        exactly(1),
+        "Square root of a non-negative number",
+    ),
+    function("math", "abs", exactly(1), "Absolute value"),
+    function(
+        "math",
+        "max",
+        at_least(1),
+        "Largest of the numbers given, or of the numbers in one array",
+    ),
+    function(
+        "math",
+        "min",
+        at_least(1),
+        "Smallest of the numbers given, or of the numbers in one array",
+    ),
+    function("math", "pow", exactly(2), "`base` raised to `exponent`"),
+    function(
+        "math",
+        "floor",
+        exactly(1),
+        "Largest whole number not above the number",
+    ),
+    function(
+        "math",
+        "ceil",
+        exactly(1),
+        "Smallest whole number not below the number",
+    ),
+    function(
+        "math",
+        "round",
+        exactly(1),
+        "Nearest whole number, halves rounded away from zero",
+    ),
+    function(
+        "math",
+        "clamp",
+        exactly(3),
+        "The number limited to the range from `lo` to `hi`",
+    ),
+    function("math", "sin", exactly(1), "Sine of an angle in radians"),
+    function("math", "cos", exactly(1), "Cosine of an angle in radians"),
+    function("math", "tan", exactly(1), "Tangent of an angle in radians"),
+    function(
+        "math",
+        "atan2",
+        exactly(2),
+        "Angle in radians of the point (`x`, `y`), given as `y` then `x`",
+    ),
+    function(
+        "math",
+        "log",
+        exactly(1),
+        "Natural logarithm of a positive number",
+    ),
+    function(
+        "math",
+        "log10",
+        exactly(1),
+        "Base 10 logarithm of a positive number",
+    ),
+    function("math", "exp", exactly(1), "e raised to the number"),
+    function(
+        "math",
+        "pi",
+        exactly(0),
+        "The ratio of a circle's circumference to its diameter",
+    ),
+    function("math", "e", exactly(0), "The base of natural logarithms"),
+    function(
+        "string",
+        "length",
+        exactly(1),
+        "Number of characters in the string",
+    ),
+    function(
+        "string",
+        "split",
+        exactly(2),
+        "The parts of the string between each delimiter",
+    ),
+    function(
+        "string",
+        "join",
+        exactly(2),
+        "The strings of an array joined with a delimiter",
+    ),
+    function(
+        "string",
+        "upper",
+        exactly(1),
+        "The string in uppercase, the same under every locale",
+    ),
+    function(
+        "string",
+        "lower",
+        exactly(1),
+        "The string in lowercase, the same under every locale",
+    ),
+    function(
+        "string",
+        "trim",
+        exactly(1),
+        "The string without leading and trailing whitespace",
+    ),
+    function(
+        "string",
+        "contains",
+        exactly(2),
+        "Whether the string contains a substring",
+    ),
+    function(
+        "string",
+        "substring",
+        exactly(3),
+        "The characters from `start` up to, not including, `end`",
+    ),
+    function(
+        "string",
+        "replace",
+        exactly(3),
+        "The string with every occurrence of a substring replaced",
+    ),
+    function(
+        "string",
+        "starts_with",
+        exactly(2),
+        "Whether the string starts with a prefix",
+    ),
+    function(
+        "string",
+        "ends_with",
+        exactly(2),
+        "Whether the string ends with a suffix",
+    ),
+    function(
+        "string",
+        "repeat",
+        exactly(2),
+        "The string repeated `n` times",
+    ),
+    function(
+        "string",
+        "pad_left",
+        exactly(2),
+        "The string padded with spaces on the left to a length",
+    ),
+    function(
+        "string",
+        "pad_right",
+        exactly(2),
+        "The string padded with spaces on the right to a length",
+    ),
+    function(
+        "string",
+        "casefold",
+        exactly(1),
+        "The string case folded, for caseless matching",
+    ),
+    function(
+        "string",
+        "equals_ignore_case",
+        exactly(2),
+        "Whether two strings are equal once both are case folded",
+    ),
+    function(
+        "string",
+        "compare",
+        between(2, 3),
+        "-1, 0 or 1 as the first string sorts before, with or after the second",
+    ),
+    function(
+        "array",
+        "length",
+        exactly(1),
+        "Number of elements in the array",
+    ),
+    function(
+        "array",
+        "push",
+        exactly(2),
+        "A new array with a value added at the end",
+    ),
+    function(
+        "array",
+        "pop",
+        exactly(1),
+        "A new array without its last element",
+    ),
+    function(
+        "array",
+        "sort",
+        exactly(1),
+        "A new array of the numbers or strings in order",
+    ),
+    function(
+        "array",
+        "reverse",
+        exactly(1),
+        "A new array with the elements in reverse order",
+    ),
+    function(
+        "array",
+        "join",
+        exactly(2),
+        "The elements joined into a string with a separator",
+    ),
+    function(
+        "array",
+        "filter",
+        exactly(2),
+        "A new array of the elements a predicate accepts",
+    ),
+    function(
+        "array",
+        "reduce",
+        between(2, 3),
+        "The result of `reducer(acc, item)` over each element",
+    ),
+    function(
+        "array",
+        "find",
+        exactly(2),
+        "The first number greater than the second argument, or null",
+    ),
+    function(
+        "array",
+        "contains",
+        exactly(2),
+        "Whether the array has an element equal to the value",
+    ),
+    function(
+        "array",
+        "first",
+        exactly(1),
+        "The first element, or null for an empty array",
+    ),
+    function(
+        "array",
+        "last",
+        exactly(1),
+        "The last element, or null for an empty array",
+    ),
+    function(
+        "array",
+        "slice",
+        between(2, 3),
+        "The elements from `start` up to, not including, `end`",
+    ),
+    function(
+        "array",
+        "concat",
+        exactly(2),
+        "A new array of the elements of both arrays",
+    ),
+    function(
+        "array",
+        "index_of",
+        exactly(2),
+        "The index of the first element equal to the value, or -1",
+    ),
+    function("object", "keys", exactly(1), "The object's keys, in order"),
+    function(
+        "object",
+        "values",
+        exactly(1),
+        "The object's values, in key order",
+    ),
+    function(
+        "object",
+        "entries",
+        exactly(1),
+        "The object's [key, value] pairs, in key order",
+    ),
+    function("object", "has", exactly(2), "Whether the object has a key"),
+    function("object", "remove", exactly(2), "A new object without a key"),
+    function(
+        "object",
+        "merge",
+        exactly(2),
+        "A new object with the keys of both, the right one winning",
+    ),
+    function(
+        "io",
+        "read_file",
+        exactly(1),
+        "The contents of a file as a string",
+    ),
+    function(
+        "io",
+        "read_lines",
+        between(1, 2),
+        "The lines of a file, without line terminators",
+    ),
+    function(
+        "io",
+        "write_file",
+        exactly(2),
+        "Replace a file's contents with a string",
+    ),
+    function(
+        "io",
+        "read_bytes",
+        exactly(1),
+        "The contents of a file as bytes",
+    ),
+    function(
+        "io",
+        "write_bytes",
+        exactly(2),
+        "Replace a file's contents with bytes",
+    ),
+    function(
+        "io",
+        "exists",
+        exactly(1),
+        "Whether a file or directory exists",
+    ),
+    function(
+        "io",
+        "append_file",
+        exactly(2),
+        "Add a string to the end of a file, creating it if missing",
+    ),
+    function(
+        "io",
+        "delete_file",
+        exactly(1),
+        "Delete a file; a missing file is an error",
+    ),
+    function(
+        "io",
+        "mkdir",
+        exactly(1),
+        "Create a directory and any missing parents",
+    ),
+    function(
+        "io",
+        "list_dir",
+        exactly(1),
+        "The names of a directory's entries, sorted",
+    ),
+    function(
+        "io",
+        "args",
+        exactly(0),
+        "The script's command line arguments, as strings",
+    ),
+    function(
+        "io",
+        "env",
+        exactly(1),
+        "An environment variable's value, or null if it is not set",
+    ),
+    function(
+        "io",
+        "set_env",
+        exactly(2),
+        "Set an environment variable; null removes it",
+    ),
+    function(
+        "io",
+        "read_line",
+        exactly(0),
+        "The next line of standard input, or null once it has ended",
+    ),
+    function(
+        "io",
+        "input",
+        between(0, 1),
+        "Print a prompt, then read a line of standard input",
+    ),
+    function("io", "read_all", exactly(0), "The rest of standard input"),
+    function(
+        "io",
+        "throw",
+        exactly(1),
+        "Throw a value for `catch` to receive unchanged",
+    ),
+    function(
+        "io",
+        "temp_file",
+        between(0, 3),
+        "Create an empty temporary file, deleted when the script exits",
+    ),
+    function(
+        "io",
+        "temp_dir",
+        between(0, 2),
+        "Create a temporary directory, deleted when the script exits",
+    ),
+    function(
+        "io",
+        "glob",
+        between(1, 2),
+        "The paths that match a glob pattern, sorted",
+    ),
+    function(
+        "io",
+        "match_glob",
+        exactly(2),
+        "Whether a path matches a glob pattern",
+    ),
+    function(
+        "async",
+        "create_promise",
+        between(0, 1),
+        "A promise resolved with a value, or a pending one",
+    ),
+    function(
+        "async",
+        "create_rejected_promise",
+        exactly(1),
+        "A promise rejected with an error",
+    ),
+    function(
+        "async",
+        "sleep",
+        exactly(1),
+        "A promise that resolves with null after some milliseconds",
+    ),
+    function(
+        "async",
+        "read_file",
+        exactly(1),
+        "A promise of a file's contents",
+    ),
+    function(
+        "async",
+        "write_file",
+        exactly(2),
+        "A promise that resolves once a file is written",
+    ),
+    function(
+        "async",
+        "http_get",
+        between(1, 2),
+        "A promise of the response to a GET request",
+    ),
+    function(
+        "async",
+        "http_post",
+        between(2, 4),
+        "A promise of the response to a POST request",
+    ),
+    function(
+        "async",
+        "race",
+        at_least(0),
+        "The first of the promises to settle",
+    ),
+    function(
+        "async",
+        "all",
+        at_least(0),
+        "A promise of every promise's value, in order",
+    ),
+    function(
+        "async",
+        "timeout",
+        exactly(1),
+        "A promise that rejects after some milliseconds",
+    ),
+    function(
+        "memo",
+        "wrap",
+        between(1, 2),
+        "The function with its results cached by argument",
+    ),
+    function(
+        "memo",
+        "clear",
+        between(0, 1),
+        "Clear the cache of one memoized function, or of all",
+    ),
+    function(
+        "memo",
+        "stats",
+        exactly(1),
+        "The cache hits and misses of a memoized function",
+    ),
+    function(
+        "error",
+        "new",
+        between(2, 3),
+        "An error value with a type, message and optional fields",
+    ),
+    function("error", "type", exactly(1), "The type of an error value"),
+    function(
+        "error",
+        "message",
+        exactly(1),
+        "The message of an error value",
+    ),
+    function(
+        "error",
+        "fields",
+        exactly(1),
+        "The custom fields of an error value",
+    ),
+    function(
+        "error",
+        "stack",
+        exactly(1),
+        "The call stack captured when an error value was created",
+    ),
+    function(
+        "error",
+        "is_error",
+        exactly(1),
+        "Whether a value is an error value",
+    ),
+    function(
+        "number",
+        "parse",
+        exactly(1),
+        "The number in a string, with a dot as decimal separator",
+    ),
+    function(
+        "number",
+        "parse_locale",
+        exactly(2),
+        "The number in a string with the given separators",
+    ),
+    function(
+        "number",
+        "set_display_precision",
+        exactly(1),
+        "Decimal places used when printing numbers; null resets",
+    ),
+    function(
+        "number",
+        "to_precision",
+        exactly(2),
+        "The number written with a count of significant digits",
+    ),
+    function(
+        "number",
+        "to_bigint",
+        exactly(1),
+        "A whole number as a bigint",
+    ),
+    function(
+        "process",
+        "dry_run",
+        exactly(0),
+        "Whether the script is running in dry-run mode",
+    ),
+    function(
+        "process",
+        "exec",
+        between(1, 2),
+        "Run a program to completion: {status, stdout, stderr}",
+    ),
+    function(
+        "path",
+        "join",
+        at_least(1),
+        "Path components joined; an absolute one replaces those before it",
+    ),
+    function(
+        "path",
+        "normalize",
+        exactly(1),
+        "The path with `.`, `..` and repeated separators collapsed",
+    ),
+    function(
+        "path",
+        "dirname",
+        exactly(1),
+        "The path without its last component",
+    ),
+    function(
+        "path",
+        "basename",
+        exactly(1),
+        "The last component of the path",
+    ),
+    function(
+        "path",
+        "extension",
+        exactly(1),
+        "The extension of the last component, without the dot",
+    ),
+    function(
+        "path",
+        "is_absolute",
+        exactly(1),
+        "Whether the path is absolute on this platform",
+    ),
+    function(
+        "bigint",
+        "new",
+        exactly(1),
+        "A bigint from a decimal string or a whole number",
+    ),
+    function(
+        "bigint",
+        "to_number",
+        exactly(1),
+        "A bigint as a number, if no precision is lost",
+    ),
+    function(
+        "bytes",
+        "from_hex",
+        exactly(1),
+        "Bytes from hexadecimal text, two digits per byte",
+    ),
+    function(
+        "bytes",
+        "from_string",
+        between(1, 2),
+        "A string encoded as bytes, UTF-8 by default",
+    ),
+    function("bytes", "from_base64", exactly(1), "Bytes from base64 text"),
+    function(
+        "bytes",
+        "from_array",
+        exactly(1),
+        "Bytes from an array of numbers from 0 to 255",
+    ),
+    function(
+        "bytes",
+        "to_hex",
+        exactly(1),
+        "The bytes as hexadecimal text",
+    ),
+    function("bytes", "to_base64", exactly(1), "The bytes as base64 text"),
+    function(
+        "bytes",
+        "to_string",
+        between(1, 2),
+        "The bytes decoded as a string, UTF-8 by default",
+    ),
+    function(
+        "bytes",
+        "to_array",
+        exactly(1),
+        "The bytes as an array of numbers",
+    ),
+    function("bytes", "length", exactly(1), "Number of bytes"),
+    function(
+        "bytes",
+        "slice",
+        between(2, 3),
+        "The bytes from `start` up to, not including, `end`",
+    ),
+    function(
+        "bytes",
+        "concat",
+        at_least(0),
+        "The buffers joined, given as arguments or as one array",
+    ),
+    function(
+        "ipc",
+        "encode",
+        exactly(1),
+        "A value in the binary wire format",
+    ),
+    function(
+        "ipc",
+        "decode",
+        exactly(1),
+        "The value `ipc.encode` encoded",
+    ),
+    function(
+        "worker",
+        "spawn",
+        exactly(1),
+        "Start a script in a child process; returns its worker id",
+    ),
+    function(
+        "worker",
+        "send",
+        between(1, 2),
+        "Send a value to a worker, or to the parent from inside one",
+    ),
+    function(
+        "worker",
+        "recv",
+        between(0, 1),
+        "Wait for a value from a worker, or from the parent inside one",
+    ),
+    function(
+        "worker",
+        "wait",
+        exactly(1),
+        "Close a worker's input and wait for its exit code",
+    ),
+    function(
+        "progress",
+        "bar",
+        between(1, 2),
+        "Start a progress bar counting up to a total",
+    ),
+    function(
+        "progress",
+        "spinner",
+        exactly(1),
+        "Start a spinner for work of unknown length",
+    ),
+    function(
+        "progress",
+        "advance",
+        between(1, 2),
+        "Move a bar forward, by 1 by default",
+    ),
+    function(
+        "progress",
+        "set_message",
+        exactly(2),
+        "Show a message next to a bar's label",
+    ),
+    function("progress", "finish", exactly(1), "Remove a bar or spinner"),
+    function(
+        "schedule",
+        "every",
+        exactly(2),
+        "Call a function every so many milliseconds",
+    ),
+    function(
+        "schedule",
+        "cron",
+        exactly(2),
+        "Call a function at the times a cron expression matches",
+    ),
+    function(
+        "schedule",
+        "cancel",
+        exactly(1),
+        "Stop a job; returns whether it was still scheduled",
+    ),
+    function(
+        "schedule",
+        "now",
+        exactly(0),
+        "The scheduler's current time in milliseconds",
+    ),
+    function(
+        "timing",
+        "debounce",
+        exactly(2),
+        "Call a function once calls stop for so many milliseconds",
+    ),
+    function(
+        "timing",
+        "throttle",
+        between(2, 3),
+        "Call a function at most once every so many milliseconds",
+    ),
+    function(
+        "timing",
+        "cancel",
+        exactly(1),
+        "Drop the waiting call of a wrapper",
+    ),
+    function(
+        "assert",
+        "snapshot",
+        between(1, 2),
+        "Compare a value with its stored snapshot",
+    ),
+    function(
+        "time",
+        "parse_duration",
+        exactly(1),
+        "The milliseconds in a duration such as \"2h 30m\"",
+    ),
+    function(
+        "time",
+        "format_duration",
+        between(1, 2),
+        "Milliseconds written as a duration such as \"2h 30m\"",
+    ),
+    function(
+        "time",
+        "now",
+        exactly(0),
+        "Milliseconds since 1970-01-01 00:00 UTC",
+    ),
+    function(
+        "time",
+        "elapsed",
+        exactly(1),
+        "Milliseconds since an earlier `time.now()` reading",
+    ),
+    function(
+        "time",
+        "format",
+        between(1, 2),
+        "The UTC date and time of a moment, written with a pattern",
+    ),
+    function(
+        "time",
+        "parse",
+        between(1, 2),
+        "The moment a date and time names, read as UTC",
+    ),
+    function(
+        "units",
+        "parse_size",
+        exactly(1),
+        "The bytes in a size such as \"1.5 GB\"",
+    ),
+    function(
+        "units",
+        "format_size",
+        between(1, 2),
+        "Bytes written as a size such as \"1.5 GB\"",
+    ),
+    function(
+        "json",
+        "parse",
+        exactly(1),
+        "The value a JSON document describes",
+    ),
+    function(
+        "json",
+        "stringify",
+        between(1, 2),
+        "A value as JSON, compact or indented",
+    ),
+    function(
+        "random",
+        "random",
+        exactly(0),
+        "A number from 0 up to, not including, 1",
+    ),
+    function(
+        "random",
+        "int",
+        exactly(2),
+        "A whole number from `min` to `max`, both included",
+    ),
+    function(
+        "random",
+        "choice",
+        exactly(1),
+        "One of the elements of an array",
+    ),
+    function(
+        "random",
+        "shuffle",
+        exactly(1),
+        "A new array of the elements in a random order",
+    ),
+    function(
+        "random",
+        "seed",
+        exactly(1),
+        "Make the numbers that follow the same on every run",
+    ),
+    function(
+        "convert",
+        "str",
+        exactly(1),
+        "The value as `print` would show it",
+    ),
+    function(
+        "convert",
+        "int",
+        exactly(1),
+        "A number or numeric string with its fraction dropped",
+    ),
+    function(
+        "convert",
+        "float",
+        exactly(1),
+        "A number, or a numeric string as a number",
+    ),
+    function(
+        "convert",
+        "bool",
+        exactly(1),
+        "Whether the value counts as true in an `if`",
+    ),
+    function(
+        "convert",
+        "type",
+        exactly(1),
+        "The name of the value's type, or its class's name",
+    ),
+    function(
+        "convert",
+        "len",
+        exactly(1),
+        "The length of a string, array, tuple, object or bytes",
+    ),
+];
@@ -1,59 +1,256 @@
 pub mod array;
 pub mod async_mod;
+pub mod bigint;
+pub mod bytes;
+pub mod cancel;
+pub mod capability;
+pub mod convert;
+pub mod error;
+pub mod faults;
+pub mod glob;
 pub mod io;
+pub mod ipc;
+pub mod json;
 pub mod math;
+pub mod memo;
+// Read by tools through the library, not by the interpreter
+#[allow(dead_code)]
+pub mod metadata;
+pub mod number;
+pub mod object;
+pub mod output;
+pub mod path;
+pub mod prelude;
+pub mod process;
+pub mod progress;
+pub mod random;
+pub mod record;
+pub mod schedule;
+pub mod snapshot;
+pub mod sources;
 pub mod string;
+pub mod time;
+pub mod timing;
+pub mod units;
+pub mod worker;
 
 use crate::core::{Result, Value};
+use metadata::FunctionInfo;
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 
-/// Standard library module that provides built-in functions
+/// Functions by module, then by name
+type ModuleTable = HashMap<String, HashMap<String, NativeFunction>>;
+
+/// The built-in functions and any the host added, as seen by one interpreter.
+///
+/// The built-in modules are built once per process and shared, so a new
+/// library (and with it a new interpreter) costs a reference count, and a
+/// host can hand one library to many interpreters on different threads.
+/// Each clone has its own overlay: functions registered on it shadow
+/// built-ins of the same name and are invisible to every other clone.
+#[derive(Clone)]
 pub struct StandardLibrary {
-    modules: HashMap<String, HashMap<String, NativeFunction>>,
+    core: Arc<ModuleTable>,
+    overlay: ModuleTable,
+    /// Dry-run mode for this library only, in place of `--dry-run`
+    dry_run: Option<bool>,
 }
 
 /// Native function type
 pub type NativeFunction = fn(&[Value]) -> Result<Value>;
 
+/// Functions scripts call without a module prefix, as (name, module,
+/// function): the conversions, and the math functions people expect to
+/// find without one
+const GLOBALS: &[(&str, &str, &str)] = &[
+    ("str", "convert", "str"),
+    ("int", "convert", "int"),
+    ("float", "convert", "float"),
+    ("bool", "convert", "bool"),
+    ("type", "convert", "type"),
+    ("len", "convert", "len"),
+    ("abs", "math", "abs"),
+    ("max", "math", "max"),
+    ("min", "math", "min"),
+    ("round", "math", "round"),
+    ("floor", "math", "floor"),
+    ("ceil", "math", "ceil"),
+];
+
 impl StandardLibrary {
     pub fn new() -> Self {
-        let mut stdlib = Self {
-            modules: HashMap::new(),
+        static CORE: OnceLock<Arc<ModuleTable>> = OnceLock::new();
+        let core = CORE.get_or_init(|| {
+            let mut builtins = Builtins {
+                modules: HashMap::new(),
+            };
+            builtins.register_all_modules();
+            Arc::new(builtins.modules)
+        });
+
+        Self {
+            core: Arc::clone(core),
+            overlay: HashMap::new(),
+            dry_run: None,
+        }
+    }
+
+    /// Add `module.name` to this library only, replacing a built-in or
+    /// earlier registration of the same name
+    #[allow(dead_code)]
+    pub fn register(&mut self, module: &str, name: &str, function: NativeFunction) {
+        self.overlay
+            .entry(module.to_string())
+            .or_default()
+            .insert(name.to_string(), function);
+    }
+
+    /// Remove a function registered with `register`; a built-in it shadowed
+    /// becomes visible again. Returns whether there was one.
+    #[allow(dead_code)]
+    pub fn unregister(&mut self, module: &str, name: &str) -> bool {
+        let Some(functions) = self.overlay.get_mut(module) else {
+            return false;
         };
+        let removed = functions.remove(name).is_some();
+        if functions.is_empty() {
+            self.overlay.remove(module);
+        }
+        removed
+    }
 
-        stdlib.register_all_modules();
-        stdlib
+    /// Run mutating calls as a dry run (or not) in this library only,
+    /// whatever `--dry-run` says; `None` follows `--dry-run` again
+    #[allow(dead_code)]
+    pub fn set_dry_run(&mut self, dry_run: Option<bool>) {
+        self.dry_run = dry_run;
     }
 
-    /// Register all standard library modules
-    fn register_all_modules(&mut self) {
-        self.register_math_module();
-        self.register_string_module();
-        self.register_array_module();
-        self.register_io_module();
-        self.register_async_module();
+    /// Whether mutating calls made through this library are a dry run
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.unwrap_or_else(capability::is_dry_run)
     }
 
     /// Get a function from a module
     pub fn get_function(&self, module: &str, function: &str) -> Option<&NativeFunction> {
-        self.modules.get(module)?.get(function)
+        self.overlay
+            .get(module)
+            .and_then(|functions| functions.get(function))
+            .or_else(|| self.core.get(module)?.get(function))
+    }
+
+    /// The function a script calls as plain `name(...)`, such as `len` or
+    /// `abs`, as a value; None when `name` is not a global
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let &(_, module, function) = GLOBALS.iter().find(|(global, _, _)| *global == name)?;
+        self.get_function(module, function)?;
+        Some(Value::NativeFunction {
+            module: module.to_string(),
+            function: function.to_string(),
+        })
+    }
+
+    /// Names `get_global` resolves
+    pub fn get_globals(&self) -> Vec<&'static str> {
+        GLOBALS
+            .iter()
+            .filter(|(_, module, function)| self.get_function(module, function).is_some())
+            .map(|(name, _, _)| *name)
+            .collect()
     }
 
     /// Check if a module exists
     pub fn has_module(&self, module: &str) -> bool {
-        self.modules.contains_key(module)
+        self.overlay.contains_key(module) || self.core.contains_key(module)
     }
 
     /// Get all available modules
     pub fn get_modules(&self) -> Vec<&str> {
-        self.modules.keys().map(|s| s.as_str()).collect()
+        let mut modules: Vec<&str> = self.core.keys().map(|s| s.as_str()).collect();
+        modules.extend(
+            self.overlay
+                .keys()
+                .filter(|module| !self.core.contains_key(*module))
+                .map(|s| s.as_str()),
+        );
+        modules
+    }
+
+    /// What a built-in function takes and does; `None` for a function a
+    /// host registered, even over a built-in
+    #[allow(dead_code)]
+    pub fn describe(&self, module: &str, function: &str) -> Option<&'static FunctionInfo> {
+        if self
+            .overlay
+            .get(module)
+            .is_some_and(|functions| functions.contains_key(function))
+        {
+            return None;
+        }
+        metadata::FUNCTIONS
+            .iter()
+            .find(|info| info.module == module && info.name == function)
     }
 
     /// Get all functions in a module
     pub fn get_module_functions(&self, module: &str) -> Option<Vec<&str>> {
-        self.modules
-            .get(module)
-            .map(|funcs| funcs.keys().map(|s| s.as_str()).collect())
+        let core = self.core.get(module);
+        let overlay = self.overlay.get(module);
+        if core.is_none() && overlay.is_none() {
+            return None;
+        }
+        let mut functions: Vec<&str> = core
+            .into_iter()
+            .flat_map(|funcs| funcs.keys())
+            .map(|s| s.as_str())
+            .collect();
+        functions.extend(
+            overlay
+                .into_iter()
+                .flat_map(|funcs| funcs.keys())
+                .filter(|name| core.is_none_or(|funcs| !funcs.contains_key(*name)))
+                .map(|s| s.as_str()),
+        );
+        Some(functions)
+    }
+}
+
+impl Default for StandardLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the shared table of built-in modules
+struct Builtins {
+    modules: ModuleTable,
+}
+
+impl Builtins {
+    /// Register all standard library modules
+    fn register_all_modules(&mut self) {
+        self.register_math_module();
+        self.register_string_module();
+        self.register_array_module();
+        self.register_object_module();
+        self.register_io_module();
+        self.register_async_module();
+        self.register_error_module();
+        self.register_number_module();
+        self.register_process_module();
+        self.register_path_module();
+        self.register_bigint_module();
+        self.register_bytes_module();
+        self.register_ipc_module();
+        self.register_worker_module();
+        self.register_progress_module();
+        self.register_schedule_module();
+        self.register_assert_module();
+        self.register_time_module();
+        self.register_units_module();
+        self.register_json_module();
+        self.register_convert_module();
     }
 
     // Module registration methods
@@ -67,6 +264,16 @@ impl StandardLibrary {
         math_funcs.insert("floor".to_string(), math::floor as NativeFunction);
         math_funcs.insert("ceil".to_string(), math::ceil as NativeFunction);
         math_funcs.insert("round".to_string(), math::round as NativeFunction);
+        math_funcs.insert("clamp".to_string(), math::clamp as NativeFunction);
+        math_funcs.insert("sin".to_string(), math::sin as NativeFunction);
+        math_funcs.insert("cos".to_string(), math::cos as NativeFunction);
+        math_funcs.insert("tan".to_string(), math::tan as NativeFunction);
+        math_funcs.insert("atan2".to_string(), math::atan2 as NativeFunction);
+        math_funcs.insert("log".to_string(), math::log as NativeFunction);
+        math_funcs.insert("log10".to_string(), math::log10 as NativeFunction);
+        math_funcs.insert("exp".to_string(), math::exp as NativeFunction);
+        math_funcs.insert("pi".to_string(), math::pi as NativeFunction);
+        math_funcs.insert("e".to_string(), math::e as NativeFunction);
 
         self.modules.insert("math".to_string(), math_funcs);
     }
@@ -91,6 +298,12 @@ impl StandardLibrary {
         string_funcs.insert("repeat".to_string(), string::repeat as NativeFunction);
         string_funcs.insert("pad_left".to_string(), string::pad_left as NativeFunction);
         string_funcs.insert("pad_right".to_string(), string::pad_right as NativeFunction);
+        string_funcs.insert("casefold".to_string(), string::casefold as NativeFunction);
+        string_funcs.insert(
+            "equals_ignore_case".to_string(),
+            string::equals_ignore_case as NativeFunction,
+        );
+        string_funcs.insert("compare".to_string(), string::compare as NativeFunction);
 
         self.modules.insert("string".to_string(), string_funcs);
     }
@@ -104,23 +317,52 @@ impl StandardLibrary {
         array_funcs.insert("reverse".to_string(), array::reverse as NativeFunction);
         array_funcs.insert("join".to_string(), array::join as NativeFunction);
         // New functional programming methods
-        array_funcs.insert("map".to_string(), array::map as NativeFunction);
-        array_funcs.insert("filter".to_string(), array::filter as NativeFunction);
-        array_funcs.insert("reduce".to_string(), array::reduce as NativeFunction);
         array_funcs.insert("find".to_string(), array::find as NativeFunction);
         array_funcs.insert("contains".to_string(), array::contains as NativeFunction);
         array_funcs.insert("first".to_string(), array::first as NativeFunction);
         array_funcs.insert("last".to_string(), array::last as NativeFunction);
+        array_funcs.insert("slice".to_string(), array::slice as NativeFunction);
+        array_funcs.insert("concat".to_string(), array::concat as NativeFunction);
+        array_funcs.insert("index_of".to_string(), array::index_of as NativeFunction);
 
         self.modules.insert("array".to_string(), array_funcs);
     }
 
+    fn register_object_module(&mut self) {
+        let mut object_funcs = HashMap::new();
+        object_funcs.insert("keys".to_string(), object::keys as NativeFunction);
+        object_funcs.insert("values".to_string(), object::values as NativeFunction);
+        object_funcs.insert("entries".to_string(), object::entries as NativeFunction);
+        object_funcs.insert("has".to_string(), object::has as NativeFunction);
+        object_funcs.insert("remove".to_string(), object::remove as NativeFunction);
+        object_funcs.insert("merge".to_string(), object::merge as NativeFunction);
+
+        self.modules.insert("object".to_string(), object_funcs);
+    }
+
     fn register_io_module(&mut self) {
         let mut io_funcs = HashMap::new();
         io_funcs.insert("read_file".to_string(), io::read_file as NativeFunction);
+        io_funcs.insert("read_lines".to_string(), io::read_lines as NativeFunction);
         io_funcs.insert("write_file".to_string(), io::write_file as NativeFunction);
+        io_funcs.insert("read_bytes".to_string(), io::read_bytes as NativeFunction);
+        io_funcs.insert("write_bytes".to_string(), io::write_bytes as NativeFunction);
         io_funcs.insert("exists".to_string(), io::exists as NativeFunction);
+        io_funcs.insert("append_file".to_string(), io::append_file as NativeFunction);
+        io_funcs.insert("delete_file".to_string(), io::delete_file as NativeFunction);
+        io_funcs.insert("mkdir".to_string(), io::mkdir as NativeFunction);
+        io_funcs.insert("list_dir".to_string(), io::list_dir as NativeFunction);
+        io_funcs.insert("args".to_string(), io::script_args as NativeFunction);
+        io_funcs.insert("env".to_string(), io::env as NativeFunction);
+        io_funcs.insert("set_env".to_string(), io::set_env as NativeFunction);
+        io_funcs.insert("read_line".to_string(), io::read_line as NativeFunction);
+        io_funcs.insert("input".to_string(), io::input as NativeFunction);
+        io_funcs.insert("read_all".to_string(), io::read_all as NativeFunction);
         io_funcs.insert("throw".to_string(), io::throw_exception as NativeFunction);
+        io_funcs.insert("temp_file".to_string(), io::temp_file as NativeFunction);
+        io_funcs.insert("temp_dir".to_string(), io::temp_dir as NativeFunction);
+        io_funcs.insert("glob".to_string(), glob::glob as NativeFunction);
+        io_funcs.insert("match_glob".to_string(), glob::match_glob as NativeFunction);
 
         self.modules.insert("io".to_string(), io_funcs);
     }
@@ -148,16 +390,198 @@ impl StandardLibrary {
             "http_get".to_string(),
             async_mod::http_get_async as NativeFunction,
         );
+        async_funcs.insert(
+            "http_post".to_string(),
+            async_mod::http_post_async as NativeFunction,
+        );
         async_funcs.insert("race".to_string(), async_mod::race as NativeFunction);
         async_funcs.insert("all".to_string(), async_mod::all as NativeFunction);
         async_funcs.insert("timeout".to_string(), async_mod::timeout as NativeFunction);
-        async_funcs.insert("then".to_string(), async_mod::then as NativeFunction);
         self.modules.insert("async".to_string(), async_funcs);
     }
-}
 
-impl Default for StandardLibrary {
-    fn default() -> Self {
-        Self::new()
+    fn register_error_module(&mut self) {
+        let mut error_funcs = HashMap::new();
+        error_funcs.insert("new".to_string(), error::new as NativeFunction);
+        error_funcs.insert("type".to_string(), error::error_type as NativeFunction);
+        error_funcs.insert("message".to_string(), error::message as NativeFunction);
+        error_funcs.insert("fields".to_string(), error::fields as NativeFunction);
+        error_funcs.insert("stack".to_string(), error::stack as NativeFunction);
+        error_funcs.insert("is_error".to_string(), error::is_error as NativeFunction);
+
+        self.modules.insert("error".to_string(), error_funcs);
+    }
+
+    fn register_number_module(&mut self) {
+        let mut number_funcs = HashMap::new();
+        number_funcs.insert("parse".to_string(), number::parse as NativeFunction);
+        number_funcs.insert(
+            "parse_locale".to_string(),
+            number::parse_locale as NativeFunction,
+        );
+        number_funcs.insert(
+            "to_precision".to_string(),
+            number::to_precision as NativeFunction,
+        );
+        number_funcs.insert("to_bigint".to_string(), number::to_bigint as NativeFunction);
+
+        self.modules.insert("number".to_string(), number_funcs);
+    }
+
+    fn register_process_module(&mut self) {
+        let mut process_funcs = HashMap::new();
+        process_funcs.insert("dry_run".to_string(), process::dry_run as NativeFunction);
+        process_funcs.insert("exec".to_string(), process::exec as NativeFunction);
+
+        self.modules.insert("process".to_string(), process_funcs);
+    }
+
+    fn register_path_module(&mut self) {
+        let mut path_funcs = HashMap::new();
+        path_funcs.insert("join".to_string(), path::join as NativeFunction);
+        path_funcs.insert("normalize".to_string(), path::normalize as NativeFunction);
+        path_funcs.insert("dirname".to_string(), path::dirname as NativeFunction);
+        path_funcs.insert("basename".to_string(), path::basename as NativeFunction);
+        path_funcs.insert("extension".to_string(), path::extension as NativeFunction);
+        path_funcs.insert(
+            "is_absolute".to_string(),
+            path::is_absolute as NativeFunction,
+        );
+
+        self.modules.insert("path".to_string(), path_funcs);
+    }
+
+    fn register_bigint_module(&mut self) {
+        let mut bigint_funcs = HashMap::new();
+        bigint_funcs.insert("new".to_string(), bigint::new as NativeFunction);
+        bigint_funcs.insert("to_number".to_string(), bigint::to_number as NativeFunction);
+
+        self.modules.insert("bigint".to_string(), bigint_funcs);
+    }
+
+    fn register_bytes_module(&mut self) {
+        let mut bytes_funcs = HashMap::new();
+        bytes_funcs.insert("from_hex".to_string(), bytes::from_hex as NativeFunction);
+        bytes_funcs.insert(
+            "from_string".to_string(),
+            bytes::from_string as NativeFunction,
+        );
+        bytes_funcs.insert(
+            "from_base64".to_string(),
+            bytes::from_base64 as NativeFunction,
+        );
+        bytes_funcs.insert(
+            "from_array".to_string(),
+            bytes::from_array as NativeFunction,
+        );
+        bytes_funcs.insert("to_hex".to_string(), bytes::to_hex as NativeFunction);
+        bytes_funcs.insert("to_base64".to_string(), bytes::to_base64 as NativeFunction);
+        bytes_funcs.insert("to_string".to_string(), bytes::to_string as NativeFunction);
+        bytes_funcs.insert("to_array".to_string(), bytes::to_array as NativeFunction);
+        bytes_funcs.insert("length".to_string(), bytes::length as NativeFunction);
+        bytes_funcs.insert("slice".to_string(), bytes::slice as NativeFunction);
+        bytes_funcs.insert("concat".to_string(), bytes::concat as NativeFunction);
+
+        self.modules.insert("bytes".to_string(), bytes_funcs);
+    }
+
+    fn register_ipc_module(&mut self) {
+        let mut ipc_funcs = HashMap::new();
+        ipc_funcs.insert("encode".to_string(), ipc::encode as NativeFunction);
+        ipc_funcs.insert("decode".to_string(), ipc::decode as NativeFunction);
+
+        self.modules.insert("ipc".to_string(), ipc_funcs);
+    }
+
+    fn register_worker_module(&mut self) {
+        let mut worker_funcs = HashMap::new();
+        worker_funcs.insert("spawn".to_string(), worker::spawn as NativeFunction);
+        worker_funcs.insert("send".to_string(), worker::send as NativeFunction);
+        worker_funcs.insert("recv".to_string(), worker::recv as NativeFunction);
+        worker_funcs.insert("wait".to_string(), worker::wait as NativeFunction);
+
+        self.modules.insert("worker".to_string(), worker_funcs);
+    }
+
+    fn register_progress_module(&mut self) {
+        let mut progress_funcs = HashMap::new();
+        progress_funcs.insert("bar".to_string(), progress::bar as NativeFunction);
+        progress_funcs.insert("spinner".to_string(), progress::spinner as NativeFunction);
+        progress_funcs.insert("advance".to_string(), progress::advance as NativeFunction);
+        progress_funcs.insert(
+            "set_message".to_string(),
+            progress::set_message as NativeFunction,
+        );
+        progress_funcs.insert("finish".to_string(), progress::finish as NativeFunction);
+
+        self.modules.insert("progress".to_string(), progress_funcs);
+    }
+
+    /// The other schedule functions, and all of timing's and random's, keep
+    /// their jobs, wrappers and generator in the evaluator
+    fn register_schedule_module(&mut self) {
+        let mut schedule_funcs = HashMap::new();
+        schedule_funcs.insert("now".to_string(), schedule::now as NativeFunction);
+
+        self.modules.insert("schedule".to_string(), schedule_funcs);
+    }
+
+    fn register_assert_module(&mut self) {
+        let mut assert_funcs = HashMap::new();
+        assert_funcs.insert("snapshot".to_string(), snapshot::snapshot as NativeFunction);
+
+        self.modules.insert("assert".to_string(), assert_funcs);
+    }
+
+    fn register_time_module(&mut self) {
+        let mut time_funcs = HashMap::new();
+        time_funcs.insert(
+            "parse_duration".to_string(),
+            time::parse_duration as NativeFunction,
+        );
+        time_funcs.insert(
+            "format_duration".to_string(),
+            time::format_duration as NativeFunction,
+        );
+        time_funcs.insert("now".to_string(), time::now as NativeFunction);
+        time_funcs.insert("elapsed".to_string(), time::elapsed as NativeFunction);
+        time_funcs.insert("format".to_string(), time::format as NativeFunction);
+        time_funcs.insert("parse".to_string(), time::parse as NativeFunction);
+
+        self.modules.insert("time".to_string(), time_funcs);
+    }
+
+    fn register_units_module(&mut self) {
+        let mut units_funcs = HashMap::new();
+        units_funcs.insert(
+            "parse_size".to_string(),
+            units::parse_size as NativeFunction,
+        );
+        units_funcs.insert(
+            "format_size".to_string(),
+            units::format_size as NativeFunction,
+        );
+
+        self.modules.insert("units".to_string(), units_funcs);
+    }
+
+    fn register_json_module(&mut self) {
+        let mut json_funcs = HashMap::new();
+        json_funcs.insert("parse".to_string(), json::parse as NativeFunction);
+        json_funcs.insert("stringify".to_string(), json::stringify as NativeFunction);
+
+        self.modules.insert("json".to_string(), json_funcs);
+    }
+
+    fn register_convert_module(&mut self) {
+        let mut convert_funcs = HashMap::new();
+        convert_funcs.insert("str".to_string(), convert::str as NativeFunction);
+        convert_funcs.insert("int".to_string(), convert::int as NativeFunction);
+        convert_funcs.insert("float".to_string(), convert::float as NativeFunction);
+        convert_funcs.insert("bool".to_string(), convert::bool as NativeFunction);
+        convert_funcs.insert("type".to_string(), convert::type_of as NativeFunction);
+        convert_funcs.insert("len".to_string(), convert::len as NativeFunction);
+
+        self.modules.insert("convert".to_string(), convert_funcs);
     }
 }
@@ -0,0 +1,235 @@
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::bigint;
+
+/// Parse a number using the invariant format (dot decimal separator only)
+#[allow(dead_code)]
+pub fn parse(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("number.parse".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::String(text) => parse_invariant(text, "number.parse").map(Value::Number),
+        _ => Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("number.parse() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Parse a locale-formatted number: number.parse_locale(text, {decimal: ",", thousands: "."})
+#[allow(dead_code)]
+pub fn parse_locale(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("number.parse_locale".to_string()),
+            line: None,
+        });
+    }
+
+    let (text, options) =
+        match (&args[0], &args[1]) {
+            (Value::String(text), Value::Object(options)) => (text, options),
+            _ => return Err(InfraError::TypeError {
+                expected: "string and options object".to_string(),
+                found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+                context: Some("number.parse_locale() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some(
+                    "Use e.g. number.parse_locale(\"1.234,5\", {decimal: \",\", thousands: \".\"})"
+                        .to_string(),
+                ),
+            }),
+        };
+
+    let separator = |name: &str, default: &str| -> Result<String> {
+        match options.get(name) {
+            None => Ok(default.to_string()),
+            Some(Value::String(s)) if s.chars().count() <= 1 => Ok(s.clone()),
+            Some(other) => Err(InfraError::TypeError {
+                expected: "single-character string".to_string(),
+                found: other.to_string(),
+                context: Some(format!("number.parse_locale() option '{}'", name)),
+                line: None,
+                column: None,
+                hint: None,
+            }),
+        }
+    };
+    let decimal = separator("decimal", ".")?;
+    let thousands = separator("thousands", "")?;
+
+    if !decimal.is_empty() && decimal == thousands {
+        return Err(InfraError::RuntimeError {
+            message: "Decimal and thousands separators must differ".to_string(),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        });
+    }
+
+    let mut normalized = text.trim().to_string();
+    if !thousands.is_empty() {
+        normalized = normalized.replace(&thousands, "");
+    }
+    if !decimal.is_empty() && decimal != "." {
+        if normalized.contains('.') {
+            return Err(not_a_number(text, "number.parse_locale", None));
+        }
+        normalized = normalized.replace(&decimal, ".");
+    }
+
+    parse_invariant(&normalized, "number.parse_locale")
+        .map(Value::Number)
+        .map_err(|_| not_a_number(text, "number.parse_locale", None))
+}
+
+/// The decimal places `number.set_display_precision(digits)` asks printed
+/// numbers to be rounded to, or None for null; the evaluator keeps the setting
+pub fn display_precision(args: &[Value]) -> Result<Option<usize>> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("number.set_display_precision".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::Null => Ok(None),
+        Value::Number(n) if *n >= 0.0 && *n <= 100.0 && n.fract() == 0.0 => Ok(Some(*n as usize)),
+        _ => Err(InfraError::TypeError {
+            expected: "integer between 0 and 100, or null".to_string(),
+            found: args[0].to_string(),
+            context: Some("number.set_display_precision() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Format a number with the given count of significant digits
+#[allow(dead_code)]
+pub fn to_precision(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("number.to_precision".to_string()),
+            line: None,
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::Number(n), Value::Number(digits))
+            if *digits >= 1.0 && *digits <= 100.0 && digits.fract() == 0.0 =>
+        {
+            Ok(Value::String(format_significant(*n, *digits as usize)))
+        }
+        _ => Err(InfraError::TypeError {
+            expected: "number and digit count between 1 and 100".to_string(),
+            found: format!("{} and {}", args[0], args[1]),
+            context: Some("number.to_precision() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Convert an integral number to a bigint
+#[allow(dead_code)]
+pub fn to_bigint(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("number.to_bigint".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::Number(n) => bigint::from_number(*n, "number.to_bigint").map(Value::BigInt),
+        other => Err(InfraError::TypeError {
+            expected: "number".to_string(),
+            found: other.type_name().to_string(),
+            context: Some("number.to_bigint() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// `text` in plain decimal notation, or a TypeError naming `function_name`
+pub(crate) fn parse_invariant(text: &str, function_name: &str) -> Result<f64> {
+    let trimmed = text.trim();
+
+    // Only plain decimal notation: no locale separators, "inf", "NaN", hex, ...
+    let valid = !trimmed.is_empty()
+        && trimmed.chars().any(|c| c.is_ascii_digit())
+        && trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+
+    match trimmed.parse::<f64>() {
+        Ok(n) if valid => Ok(n),
+        _ => {
+            let hint = if trimmed.contains(',') {
+                Some(
+                    "number.parse only accepts '.' as the decimal separator; \
+                     use number.parse_locale(text, {decimal: \",\"}) for locale-formatted input"
+                        .to_string(),
+                )
+            } else {
+                None
+            };
+            Err(not_a_number(text, function_name, hint))
+        }
+    }
+}
+
+fn not_a_number(text: &str, function_name: &str, hint: Option<String>) -> InfraError {
+    InfraError::TypeError {
+        expected: "numeric string".to_string(),
+        found: format!("\"{}\"", text),
+        context: Some(format!("{}() function", function_name)),
+        line: None,
+        column: None,
+        hint,
+    }
+}
+
+fn format_significant(n: f64, digits: usize) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+
+    // Let the exponent formatter do the rounding, then decide on the notation
+    let scientific = format!("{:.*e}", digits - 1, n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if exponent < -6 || exponent >= digits as i32 {
+        format!("{}e{}", mantissa, exponent)
+    } else {
+        let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+        format!("{:.*}", decimals, n)
+    }
+}
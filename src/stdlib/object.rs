@@ -0,0 +1,102 @@
+use crate::core::{InfraError, ObjectMap, Result, Value};
+
+/// Keys of an object, in the object's key order
+#[allow(dead_code)]
+pub fn keys(args: &[Value]) -> Result<Value> {
+    let obj = single_object(args, "keys")?;
+    Ok(Value::Array(
+        obj.keys().map(|key| Value::String(key.clone())).collect(),
+    ))
+}
+
+/// Values of an object, in the object's key order
+#[allow(dead_code)]
+pub fn values(args: &[Value]) -> Result<Value> {
+    let obj = single_object(args, "values")?;
+    Ok(Value::Array(obj.values().cloned().collect()))
+}
+
+/// [key, value] pairs of an object, in the object's key order
+#[allow(dead_code)]
+pub fn entries(args: &[Value]) -> Result<Value> {
+    let obj = single_object(args, "entries")?;
+    Ok(Value::Array(
+        obj.iter()
+            .map(|(key, value)| Value::Array(vec![Value::String(key.clone()), value.clone()]))
+            .collect(),
+    ))
+}
+
+/// Whether an object has a key
+#[allow(dead_code)]
+pub fn has(args: &[Value]) -> Result<Value> {
+    check_count(args, 2, "has")?;
+    let obj = object_arg(&args[0], "has")?;
+    match &args[1] {
+        Value::String(key) => Ok(Value::Boolean(obj.contains_key(key))),
+        other => Err(type_error("string", other, "has")),
+    }
+}
+
+/// Object without a key (returns new object); the other keys keep their order
+#[allow(dead_code)]
+pub fn remove(args: &[Value]) -> Result<Value> {
+    check_count(args, 2, "remove")?;
+    let mut obj = object_arg(&args[0], "remove")?.clone();
+    let key = match &args[1] {
+        Value::String(key) => key,
+        other => return Err(type_error("string", other, "remove")),
+    };
+    obj.remove(key);
+    Ok(Value::Object(obj))
+}
+
+/// Both objects' keys in one (returns new object). The left object's keys
+/// come first in their order, then the keys only the right one has in its
+/// order; a key both have takes the right value but keeps its left position.
+#[allow(dead_code)]
+pub fn merge(args: &[Value]) -> Result<Value> {
+    check_count(args, 2, "merge")?;
+    let mut merged = object_arg(&args[0], "merge")?.clone();
+    let right = object_arg(&args[1], "merge")?;
+    merged.extend(
+        right
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    Ok(Value::Object(merged))
+}
+
+fn single_object<'a>(args: &'a [Value], function_name: &str) -> Result<&'a ObjectMap> {
+    check_count(args, 1, function_name)?;
+    object_arg(&args[0], function_name)
+}
+
+fn object_arg<'a>(value: &'a Value, function_name: &str) -> Result<&'a ObjectMap> {
+    value
+        .as_object()
+        .ok_or_else(|| type_error("object", value, function_name))
+}
+
+fn check_count(args: &[Value], count: usize, function_name: &str) -> Result<()> {
+    if args.len() != count {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: count,
+            found: args.len(),
+            function_name: Some(format!("object.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("object.{} function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
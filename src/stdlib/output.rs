@@ -0,0 +1,222 @@
+//! Guards on what `print` writes when stdout is not a terminal.
+//!
+//! A script that prints a huge value, or keeps printing in a runaway loop,
+//! should not flood a CI log or hang whatever collects it. So outside a
+//! terminal:
+//!
+//! - one print writes at most `max_print_size` bytes of the rendered value,
+//!   then a marker saying how much was cut
+//! - once a run has printed `max_output` bytes, further prints are skipped,
+//!   with a single warning on stderr
+//!
+//! Values are rendered through a [`LimitedWriter`], which stops keeping text
+//! at the limit and only counts the rest, so cutting a huge nested array
+//! never builds its full text. On a terminal nothing is limited.
+
+use crate::stdlib::progress;
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::io::{self, IsTerminal};
+
+/// Default for `--max-print-size`
+pub const DEFAULT_MAX_PRINT_SIZE: usize = 1024 * 1024;
+/// Default for `--max-output`
+pub const DEFAULT_MAX_OUTPUT: usize = 256 * 1024 * 1024;
+
+/// Limits on print output; `None` means unlimited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputLimits {
+    /// Most bytes of rendered text a single print writes
+    pub max_print_size: Option<usize>,
+    /// Most bytes all prints of a run write together
+    pub max_output: Option<usize>,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            max_print_size: Some(DEFAULT_MAX_PRINT_SIZE),
+            max_output: Some(DEFAULT_MAX_OUTPUT),
+        }
+    }
+}
+
+#[derive(Default)]
+struct OutputState {
+    limits: OutputLimits,
+    /// Bytes printed so far in this run
+    written: usize,
+    /// Prints skipped after the budget ran out
+    skipped: usize,
+    /// Overrides terminal detection (tests)
+    interactive: Option<bool>,
+}
+
+thread_local! {
+    static STATE: RefCell<OutputState> = RefCell::new(OutputState::default());
+}
+
+/// Use `limits` from now on and start a fresh output budget
+pub fn set_limits(limits: OutputLimits) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.limits = limits;
+        state.written = 0;
+        state.skipped = 0;
+    });
+}
+
+/// Treat stdout as a terminal or not regardless of what it is, or detect it
+/// again with `None`, for tests
+#[allow(dead_code)]
+pub fn set_interactive(interactive: Option<bool>) {
+    STATE.with(|state| state.borrow_mut().interactive = interactive);
+}
+
+/// How many prints were skipped because the output budget ran out
+#[allow(dead_code)]
+pub fn skipped_prints() -> usize {
+    STATE.with(|state| state.borrow().skipped)
+}
+
+/// Print `value` on a line of its own, within the output limits
+pub fn print_value(value: impl fmt::Display) {
+    let (limits, interactive) = STATE.with(|state| {
+        let state = state.borrow();
+        let interactive = state
+            .interactive
+            .unwrap_or_else(|| io::stdout().is_terminal());
+        (state.limits, interactive)
+    });
+    if interactive {
+        progress::print_line(&value.to_string());
+        return;
+    }
+
+    let rendered = render(value, limits.max_print_size);
+    let mut line = rendered.text;
+    if rendered.truncated > 0 {
+        line.push_str(&format!(
+            "... [truncated, {} more bytes; rerun with --max-print-size to raise]",
+            rendered.truncated
+        ));
+    }
+
+    let allowed = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let size = line.len() + 1; // and the newline
+        let within = limits
+            .max_output
+            .is_none_or(|budget| state.written + size <= budget);
+        if within && state.skipped == 0 {
+            state.written += size;
+            return true;
+        }
+        state.skipped += 1;
+        if state.skipped == 1 {
+            eprintln!(
+                "Warning: output limit of {} bytes reached; further prints are skipped (rerun with --max-output to raise)",
+                limits.max_output.unwrap_or_default()
+            );
+        }
+        false
+    });
+    if allowed {
+        progress::print_line(&line);
+    }
+}
+
+/// A value's display text, cut to a size limit
+#[derive(Debug)]
+pub struct Rendered {
+    pub text: String,
+    /// Bytes of the full text left out
+    pub truncated: usize,
+}
+
+/// Render `value` as `print` shows it, keeping at most `limit` bytes
+pub fn render(value: impl fmt::Display, limit: Option<usize>) -> Rendered {
+    let mut writer = LimitedWriter::new(limit);
+    let _ = write!(writer, "{}", value);
+    writer.finish()
+}
+
+/// `fmt::Write` target that keeps the first `limit` bytes written, cut at a
+/// character boundary, and only counts everything after them
+pub struct LimitedWriter {
+    text: String,
+    limit: Option<usize>,
+    truncated: usize,
+}
+
+impl LimitedWriter {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            text: String::new(),
+            limit,
+            truncated: 0,
+        }
+    }
+
+    /// Bytes of text held so far, which never exceeds the limit
+    #[allow(dead_code)]
+    pub fn retained(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn finish(self) -> Rendered {
+        Rendered {
+            text: self.text,
+            truncated: self.truncated,
+        }
+    }
+}
+
+impl Write for LimitedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let Some(limit) = self.limit else {
+            self.text.push_str(s);
+            return Ok(());
+        };
+        // Once anything was cut, everything after it is too
+        let room = if self.truncated > 0 {
+            0
+        } else {
+            limit - self.text.len()
+        };
+        if s.len() <= room {
+            self.text.push_str(s);
+            return Ok(());
+        }
+
+        let mut cut = room;
+        while !s.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        self.text.push_str(&s[..cut]);
+        self.truncated += s.len() - cut;
+        Ok(())
+    }
+}
+
+/// Parse a size such as `4096`, `64K`, `1M` or `2G` (powers of 1024); `0`
+/// and `none` mean no limit
+pub fn parse_size(text: &str) -> Option<Option<usize>> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    let (digits, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((at, _)) => text.split_at(at),
+        None => (text, ""),
+    };
+    let scale: usize = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let size = digits.parse::<usize>().ok()?.checked_mul(scale)?;
+    Some((size > 0).then_some(size))
+}
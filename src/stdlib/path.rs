@@ -0,0 +1,153 @@
+//! Path manipulation built on `std::path`.
+//!
+//! Inputs are parsed with the platform's rules, so on Windows both `\` and `/`
+//! separate components while elsewhere only `/` does. Outputs always use `/`,
+//! which every platform's file APIs accept, so scripts see the same strings
+//! everywhere.
+
+use crate::core::{InfraError, Result, Value};
+use std::path::{Component, Path, PathBuf};
+
+/// Join path components; an absolute component replaces everything before it
+#[allow(dead_code)]
+pub fn join(args: &[Value]) -> Result<Value> {
+    if args.is_empty() {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: 0,
+            function_name: Some("path.join".to_string()),
+            line: None,
+        });
+    }
+
+    let mut joined = PathBuf::new();
+    for arg in args {
+        joined.push(string_arg(arg, "path.join")?);
+    }
+    Ok(Value::String(render(&joined)))
+}
+
+/// Collapse `.`, `..` and repeated separators without touching the file system
+#[allow(dead_code)]
+pub fn normalize(args: &[Value]) -> Result<Value> {
+    let path = single_path(args, "path.normalize")?;
+
+    let mut parts: Vec<Component> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(Component::Normal(_)) => {
+                    parts.pop();
+                }
+                // ".." directly under the root stays at the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => parts.push(component),
+            },
+            _ => parts.push(component),
+        }
+    }
+
+    let normalized: PathBuf = parts.iter().collect();
+    let rendered = render(&normalized);
+    Ok(Value::String(if rendered.is_empty() {
+        ".".to_string()
+    } else {
+        rendered
+    }))
+}
+
+/// The path without its last component ("." for a bare name)
+#[allow(dead_code)]
+pub fn dirname(args: &[Value]) -> Result<Value> {
+    let path = single_path(args, "path.dirname")?;
+
+    let dir = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => ".".to_string(),
+        Some(parent) => render(parent),
+        None => render(path),
+    };
+    Ok(Value::String(dir))
+}
+
+/// The last component of the path
+#[allow(dead_code)]
+pub fn basename(args: &[Value]) -> Result<Value> {
+    let path = single_path(args, "path.basename")?;
+
+    Ok(Value::String(
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    ))
+}
+
+/// The extension of the last component, without the dot
+#[allow(dead_code)]
+pub fn extension(args: &[Value]) -> Result<Value> {
+    let path = single_path(args, "path.extension")?;
+
+    Ok(Value::String(
+        path.extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    ))
+}
+
+/// Check whether the path is absolute on this platform
+#[allow(dead_code)]
+pub fn is_absolute(args: &[Value]) -> Result<Value> {
+    let path = single_path(args, "path.is_absolute")?;
+    Ok(Value::Boolean(path.is_absolute()))
+}
+
+/// Render a path with `/` between components
+fn render(path: &Path) -> String {
+    let mut out = String::new();
+
+    for component in path.components() {
+        match component {
+            // Drive letters and UNC prefixes on Windows
+            Component::Prefix(prefix) => {
+                out.push_str(&prefix.as_os_str().to_string_lossy().replace('\\', "/"))
+            }
+            Component::RootDir => out.push('/'),
+            other => {
+                // A bare drive prefix ("C:x") is drive-relative, so no separator
+                if !out.is_empty() && !out.ends_with('/') && !out.ends_with(':') {
+                    out.push('/');
+                }
+                out.push_str(&other.as_os_str().to_string_lossy());
+            }
+        }
+    }
+
+    out
+}
+
+fn single_path<'a>(args: &'a [Value], function_name: &str) -> Result<&'a Path> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some(function_name.to_string()),
+            line: None,
+        });
+    }
+
+    string_arg(&args[0], function_name).map(Path::new)
+}
+
+fn string_arg<'a>(arg: &'a Value, function_name: &str) -> Result<&'a str> {
+    match arg {
+        Value::String(s) => Ok(s),
+        other => Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: other.type_name().to_string(),
+            context: Some(format!("{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
@@ -0,0 +1,94 @@
+//! The prelude: helpers written in Infra (`prelude.infra`) and compiled into
+//! the binary, callable from any script without an import.
+//!
+//! It is only parsed the first time a name is not found in the script's own
+//! scope, so scripts that never use it pay nothing at startup. Its exports
+//! are plain functions, run by the same evaluator and subject to the same
+//! capability checks as the script's own; `import {zip} from "std:prelude"`
+//! brings them in explicitly, even under `--no-prelude`.
+
+use crate::backend::module_system::ModuleSystem;
+use crate::core::{InfraError, Result, Value};
+use crate::frontend::{Lexer, Parser};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// Source of the prelude, as compiled into the binary
+pub const SOURCE: &str = include_str!("prelude.infra");
+
+/// Module path of the prelude for an explicit import
+pub const MODULE_PATH: &str = "std:prelude";
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+    static EXPORTS: RefCell<Option<HashMap<String, Value>>> = const { RefCell::new(None) };
+}
+
+/// Make the prelude's names available without an import (the default) or
+/// not, as with `--no-prelude`
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|flag| flag.set(enabled));
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// The prelude export `name` for a name not defined by the script, or None
+/// when there is no such export or the prelude is disabled
+pub fn lookup(name: &str) -> Result<Option<Value>> {
+    if !is_enabled() {
+        return Ok(None);
+    }
+    load_once()?;
+    Ok(EXPORTS.with(|cached| cached.borrow().as_ref()?.get(name).cloned()))
+}
+
+/// Everything the prelude exports
+pub fn exports() -> Result<HashMap<String, Value>> {
+    load_once()?;
+    Ok(EXPORTS.with(|cached| cached.borrow().clone().unwrap_or_default()))
+}
+
+fn load_once() -> Result<()> {
+    if EXPORTS.with(|cached| cached.borrow().is_none()) {
+        let exports = load(SOURCE)?;
+        EXPORTS.with(|cached| *cached.borrow_mut() = Some(exports));
+    }
+    Ok(())
+}
+
+/// Parse prelude source into its exports. Any failure is a bug in the
+/// interpreter rather than the script, and is reported against the
+/// prelude's own lines.
+pub fn load(source: &str) -> Result<HashMap<String, Value>> {
+    let program = Lexer::new(source)
+        .tokenize()
+        .and_then(|tokens| Parser::new(tokens).without_positions().parse())
+        .map_err(|error| {
+            let (line, message) = match &error {
+                InfraError::LexError { line, message, .. }
+                | InfraError::ParseError { line, message, .. } => (Some(*line), message.clone()),
+                other => (None, other.to_string()),
+            };
+            internal_error(line, &message)
+        })?;
+
+    ModuleSystem::new()
+        .extract_exports_from_program(&program)
+        .map_err(|error| internal_error(None, &error.to_string()))
+}
+
+fn internal_error(line: Option<usize>, message: &str) -> InfraError {
+    let location = match line {
+        Some(line) => format!("prelude.infra line {}", line),
+        None => "prelude.infra".to_string(),
+    };
+    InfraError::ModuleError {
+        module_name: MODULE_PATH.to_string(),
+        reason: format!(
+            "internal error in the embedded prelude at {}: {} (run with --no-prelude to work around it)",
+            location, message
+        ),
+    }
+}
@@ -0,0 +1,95 @@
+use crate::core::ObjectMap;
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::{cancel, capability};
+use crate::utils::platform;
+use std::process::Stdio;
+
+/// Check whether the script is running in dry-run mode
+#[allow(dead_code)]
+pub fn dry_run(args: &[Value]) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 0,
+            found: args.len(),
+            function_name: Some("process.dry_run".to_string()),
+            line: None,
+        });
+    }
+
+    Ok(Value::Boolean(capability::is_dry_run()))
+}
+
+/// Run a program to completion and return {status, stdout, stderr}.
+/// Inside process.with_timeout the program is killed when the deadline passes.
+#[allow(dead_code)]
+pub fn exec(args: &[Value]) -> Result<Value> {
+    let (program, program_args) = exec_args(args)?;
+
+    let child = platform::command(&program)
+        .args(&program_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| InfraError::IoError {
+            message: format!("Failed to start '{}': {}", program, err),
+            operation: Some("process.exec".to_string()),
+            path: None,
+        })?;
+
+    let output = cancel::wait_child(child, "process.exec")?;
+    Ok(exec_result(
+        output.status.code().unwrap_or(-1),
+        output.stdout,
+        output.stderr,
+    ))
+}
+
+/// Split `(program, args?)` into the program and its argument strings
+pub fn exec_args(args: &[Value]) -> Result<(String, Vec<String>)> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("process.exec".to_string()),
+            line: None,
+        });
+    }
+
+    let type_error = |found: &Value| InfraError::TypeError {
+        expected: "program name and array of string arguments".to_string(),
+        found: found.type_name().to_string(),
+        context: Some("process.exec() function".to_string()),
+        line: None,
+        column: None,
+        hint: Some("e.g. process.exec(\"git\", [\"status\"])".to_string()),
+    };
+
+    let Value::String(program) = &args[0] else {
+        return Err(type_error(&args[0]));
+    };
+
+    let mut program_args = Vec::new();
+    match args.get(1) {
+        Some(Value::Array(items)) => {
+            for item in items {
+                match item {
+                    Value::String(s) => program_args.push(s.clone()),
+                    other => return Err(type_error(other)),
+                }
+            }
+        }
+        Some(other) => return Err(type_error(other)),
+        None => {}
+    }
+
+    Ok((program.clone(), program_args))
+}
+
+pub fn exec_result(status: i32, stdout: String, stderr: String) -> Value {
+    let mut result = ObjectMap::new();
+    result.insert("status".to_string(), Value::Number(status as f64));
+    result.insert("stdout".to_string(), Value::String(stdout));
+    result.insert("stderr".to_string(), Value::String(stderr));
+    Value::Object(result)
+}
@@ -0,0 +1,500 @@
+//! Progress bars and spinners on stderr.
+//!
+//! On a terminal the active bars are redrawn in place, one line each, with
+//! the newest at the bottom. Anywhere else (piped stderr, `--no-color`) they
+//! degrade to plain log lines every 10% or 5 seconds. `print` goes through
+//! `print_line` so the bars are cleared before program output and redrawn
+//! after it.
+
+use crate::core::{InfraError, Result, Value};
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 24;
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+/// Plain mode logs at least this often while something is in progress
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+/// Terminal redraws are skipped when nothing visible changed within this time
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Erase the current line and return to its start
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static STATE: RefCell<ProgressState> = RefCell::new(ProgressState::default());
+}
+
+/// Where progress output goes and how it is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Terminal,
+    Plain,
+}
+
+struct Bar {
+    id: usize,
+    label: String,
+    message: Option<String>,
+    total: Option<f64>, // None for spinners
+    position: f64,
+    started: Instant,
+    frame: usize,
+    logged_step: usize, // Last 10% step written in plain mode
+    logged_at: Instant,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    bars: Vec<Bar>,
+    next_id: usize,
+    drawn_lines: usize,
+    drawn_at: Option<Instant>,
+    mode: Option<Mode>,        // Overrides detection (tests)
+    captured: Option<Vec<u8>>, // Collects output instead of stderr (tests)
+}
+
+/// Draw progress as plain log lines even on a terminal (`--no-color`)
+pub fn set_no_color(no_color: bool) {
+    NO_COLOR.store(no_color, Ordering::SeqCst);
+}
+
+pub fn no_color() -> bool {
+    NO_COLOR.load(Ordering::SeqCst)
+}
+
+/// Send progress output, drawn in `mode`, and printed lines to a buffer,
+/// for tests
+#[allow(dead_code)]
+pub fn capture(mode: Mode) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        *state = ProgressState::default();
+        state.mode = Some(mode);
+        state.captured = Some(Vec::new());
+    });
+}
+
+/// Return what was captured since the last call
+#[allow(dead_code)]
+pub fn take_captured() -> String {
+    STATE.with(|state| {
+        let bytes = state
+            .borrow_mut()
+            .captured
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// Print a line of program output to stdout without mixing it into the bars
+pub fn print_line(text: &str) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(buffer) = &mut state.captured {
+            buffer.extend_from_slice(format!("{}\n", text).as_bytes());
+            return;
+        }
+        if state.drawn_lines == 0 {
+            println!("{}", text);
+            return;
+        }
+
+        state.clear();
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", text);
+        let _ = stdout.flush();
+        drop(stdout);
+        state.redraw();
+    })
+}
+
+/// Print a prompt that input is typed after, on the line the bars were on;
+/// they are drawn again below the answer by their next update
+pub fn print_prompt(text: &str) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(buffer) = &mut state.captured {
+            buffer.extend_from_slice(text.as_bytes());
+            return;
+        }
+
+        state.clear();
+        let mut stdout = io::stdout().lock();
+        let _ = write!(stdout, "{}", text);
+        let _ = stdout.flush();
+    })
+}
+
+impl ProgressState {
+    fn mode(&self) -> Mode {
+        self.mode.unwrap_or_else(|| {
+            if io::stderr().is_terminal() && !no_color() {
+                Mode::Terminal
+            } else {
+                Mode::Plain
+            }
+        })
+    }
+
+    fn write(&mut self, text: &str) {
+        match &mut self.captured {
+            Some(buffer) => buffer.extend_from_slice(text.as_bytes()),
+            None => {
+                let mut stderr = io::stderr().lock();
+                let _ = stderr.write_all(text.as_bytes());
+                let _ = stderr.flush();
+            }
+        }
+    }
+
+    /// Erase the drawn bars, leaving the cursor where the first one started
+    fn clear(&mut self) {
+        if self.drawn_lines == 0 {
+            return;
+        }
+        let mut out = String::new();
+        for line in 0..self.drawn_lines {
+            if line > 0 {
+                out.push_str("\x1b[1A");
+            }
+            out.push_str(CLEAR_LINE);
+        }
+        self.drawn_lines = 0;
+        self.write(&out);
+    }
+
+    /// Redraw every active bar in place (terminal mode only)
+    fn redraw(&mut self) {
+        if self.mode() != Mode::Terminal {
+            return;
+        }
+
+        // Draw over the previous frame; lines of finished bars are blanked
+        let mut out = String::new();
+        if self.drawn_lines > 1 {
+            out.push_str(&format!("\x1b[{}A", self.drawn_lines - 1));
+        }
+        let lines = self.bars.len().max(self.drawn_lines);
+        for i in 0..lines {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(CLEAR_LINE);
+            if let Some(bar) = self.bars.get(i) {
+                out.push_str(&bar.render());
+            }
+        }
+        // Leave the cursor at the end of the last bar
+        let blanked = lines - self.bars.len().max(1);
+        if blanked > 0 {
+            out.push_str(&format!("\x1b[{}A", blanked));
+        }
+
+        self.drawn_lines = self.bars.len();
+        self.drawn_at = Some(Instant::now());
+        self.write(&out);
+    }
+
+    /// Redraw on a terminal, or write a log line when one is due in plain mode
+    fn update(&mut self, id: usize, force: bool) {
+        match self.mode() {
+            Mode::Terminal => {
+                let recent = self
+                    .drawn_at
+                    .is_some_and(|at| at.elapsed() < REDRAW_INTERVAL);
+                if force || !recent {
+                    self.redraw();
+                }
+            }
+            Mode::Plain => {
+                let Some(bar) = self.bars.iter_mut().find(|bar| bar.id == id) else {
+                    return;
+                };
+                let step = bar.step();
+                if step > bar.logged_step || bar.logged_at.elapsed() >= LOG_INTERVAL {
+                    bar.logged_step = step;
+                    bar.logged_at = Instant::now();
+                    let line = format!("{}\n", bar.log_line());
+                    self.write(&line);
+                }
+            }
+        }
+    }
+
+    fn bar_mut(&mut self, id: usize, function_name: &str) -> Result<&mut Bar> {
+        self.bars
+            .iter_mut()
+            .find(|bar| bar.id == id)
+            .ok_or_else(|| InfraError::RuntimeError {
+                message: format!("{}: no active progress bar with id {}", function_name, id),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            })
+    }
+}
+
+impl Bar {
+    fn fraction(&self) -> f64 {
+        match self.total {
+            Some(total) if total > 0.0 => (self.position / total).clamp(0.0, 1.0),
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    /// Completed tenths, for plain-mode logging
+    fn step(&self) -> usize {
+        match self.total {
+            Some(_) => (self.fraction() * 10.0).floor() as usize,
+            None => 0,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.message {
+            Some(message) => format!("{} ({})", self.label, message),
+            None => self.label.clone(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let Some(total) = self.total else {
+            let frame = SPINNER_FRAMES[self.frame % SPINNER_FRAMES.len()];
+            return format!("{} {}", frame, self.describe());
+        };
+
+        let filled = (self.fraction() * BAR_WIDTH as f64).round() as usize;
+        let mut line = format!(
+            "{} [{}{}] {:>3}% {}/{}",
+            self.describe(),
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            (self.fraction() * 100.0).floor(),
+            format_count(self.position),
+            format_count(total)
+        );
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 && self.position > 0.0 {
+            let rate = self.position / elapsed;
+            line.push_str(&format!(" {:.1}/s", rate));
+            if self.position < total {
+                let eta = (total - self.position) / rate;
+                line.push_str(&format!(" ETA {}", format_duration(eta)));
+            }
+        }
+        line
+    }
+
+    fn log_line(&self) -> String {
+        match self.total {
+            Some(total) => format!(
+                "{}: {}/{} ({}%)",
+                self.describe(),
+                format_count(self.position),
+                format_count(total),
+                (self.fraction() * 100.0).floor()
+            ),
+            None => format!(
+                "{}: still working ({})",
+                self.describe(),
+                format_duration(self.started.elapsed().as_secs_f64())
+            ),
+        }
+    }
+}
+
+fn format_count(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{}", n as i64)
+    } else {
+        format!("{:.1}", n)
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn start(label: String, total: Option<f64>) -> Value {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.next_id += 1;
+        let id = state.next_id;
+        let now = Instant::now();
+        state.bars.push(Bar {
+            id,
+            label,
+            message: None,
+            total,
+            position: 0.0,
+            started: now,
+            frame: 0,
+            logged_step: 0,
+            logged_at: now,
+        });
+
+        match state.mode() {
+            Mode::Terminal => state.redraw(),
+            Mode::Plain => {
+                let bar = state.bars.last().expect("just pushed");
+                let line = match total {
+                    Some(_) => format!("{}\n", bar.log_line()),
+                    None => format!("{}...\n", bar.describe()),
+                };
+                state.write(&line);
+            }
+        }
+        Value::Number(id as f64)
+    })
+}
+
+fn expect_args(args: &[Value], min: usize, max: usize, function_name: &str) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: if args.len() < min { min } else { max },
+            found: args.len(),
+            function_name: Some(function_name.to_string()),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn handle(value: &Value, function_name: &str) -> Result<usize> {
+    match value {
+        Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => Ok(*n as usize),
+        other => Err(type_error("progress handle", other, function_name)),
+    }
+}
+
+/// Start a bar counting up to `total`; options: label
+#[allow(dead_code)]
+pub fn bar(args: &[Value]) -> Result<Value> {
+    expect_args(args, 1, 2, "progress.bar")?;
+    let total = match &args[0] {
+        Value::Number(n) if *n >= 0.0 && n.is_finite() => *n,
+        other => return Err(type_error("non-negative number", other, "progress.bar")),
+    };
+
+    let label = match args.get(1) {
+        None | Some(Value::Null) => "Progress".to_string(),
+        Some(Value::Object(options)) => match options.get("label") {
+            None | Some(Value::Null) => "Progress".to_string(),
+            Some(Value::String(label)) => label.clone(),
+            Some(other) => return Err(type_error("string label", other, "progress.bar")),
+        },
+        Some(other) => return Err(type_error("options object", other, "progress.bar")),
+    };
+
+    Ok(start(label, Some(total)))
+}
+
+/// Start a spinner for work of unknown length
+#[allow(dead_code)]
+pub fn spinner(args: &[Value]) -> Result<Value> {
+    expect_args(args, 1, 1, "progress.spinner")?;
+    let Value::String(label) = &args[0] else {
+        return Err(type_error("string", &args[0], "progress.spinner"));
+    };
+    Ok(start(label.clone(), None))
+}
+
+/// Move a bar forward by `n` (default 1); spinners just turn
+#[allow(dead_code)]
+pub fn advance(args: &[Value]) -> Result<Value> {
+    expect_args(args, 1, 2, "progress.advance")?;
+    let id = handle(&args[0], "progress.advance")?;
+    let amount = match args.get(1) {
+        None => 1.0,
+        Some(Value::Number(n)) if n.is_finite() => *n,
+        Some(other) => return Err(type_error("number", other, "progress.advance")),
+    };
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let bar = state.bar_mut(id, "progress.advance")?;
+        bar.frame += 1;
+        if let Some(total) = bar.total {
+            bar.position = (bar.position + amount).clamp(0.0, total);
+        }
+        state.update(id, false);
+        Ok(Value::Null)
+    })
+}
+
+/// Show a message next to the label
+#[allow(dead_code)]
+pub fn set_message(args: &[Value]) -> Result<Value> {
+    expect_args(args, 2, 2, "progress.set_message")?;
+    let id = handle(&args[0], "progress.set_message")?;
+    let message = match &args[1] {
+        Value::Null => None,
+        Value::String(message) => Some(message.clone()),
+        other => return Err(type_error("string", other, "progress.set_message")),
+    };
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.bar_mut(id, "progress.set_message")?.message = message;
+        state.update(id, true);
+        Ok(Value::Null)
+    })
+}
+
+/// Remove a bar: its line is cleared on a terminal, and a final line is
+/// logged in plain mode
+#[allow(dead_code)]
+pub fn finish(args: &[Value]) -> Result<Value> {
+    expect_args(args, 1, 1, "progress.finish")?;
+    let id = handle(&args[0], "progress.finish")?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.bar_mut(id, "progress.finish")?;
+        let index = state
+            .bars
+            .iter()
+            .position(|bar| bar.id == id)
+            .expect("bar exists");
+        let bar = state.bars.remove(index);
+
+        match state.mode() {
+            Mode::Terminal if state.bars.is_empty() => state.clear(),
+            Mode::Terminal => state.redraw(),
+            Mode::Plain => {
+                let line = format!(
+                    "{}: done in {}\n",
+                    bar.describe(),
+                    format_duration(bar.started.elapsed().as_secs_f64())
+                );
+                state.write(&line);
+            }
+        }
+        Ok(Value::Null)
+    })
+}
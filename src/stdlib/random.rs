@@ -0,0 +1,153 @@
+//! Random numbers: `random.random()`, `random.int(min, max)`,
+//! `random.choice(array)`, `random.shuffle(array)` and `random.seed(n)`.
+//!
+//! Numbers come from a splitmix64 generator kept per evaluator, so engines
+//! running side by side draw their own sequences. Until a script calls
+//! `random.seed`, the generator is seeded from [`sources`] on first use, so
+//! a host that fixes the entropy source fixes these numbers too. After
+//! `random.seed(n)` the sequence depends on `n` alone. Not for secrets.
+
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::sources;
+use crate::stdlib::time::MAX_SAFE_INTEGER;
+
+/// One evaluator's generator
+#[derive(Default)]
+pub struct Random {
+    /// Generator state; `None` until seeded
+    state: Option<u64>,
+}
+
+impl Random {
+    /// random.random(): a number in [0, 1)
+    pub fn random(&mut self, args: &[Value]) -> Result<Value> {
+        check_count(args, 0, "random")?;
+        Ok(Value::Number(self.next_unit()))
+    }
+
+    /// random.int(min, max): a whole number from `min` to `max`, both included
+    pub fn int(&mut self, args: &[Value]) -> Result<Value> {
+        check_count(args, 2, "int")?;
+        let min = whole_number(&args[0], "int")?;
+        let max = whole_number(&args[1], "int")?;
+        if min > max {
+            return Err(runtime_error(format!(
+                "random.int: min ({}) is greater than max ({})",
+                min, max
+            )));
+        }
+        let span = (max - min) as u64 + 1;
+        Ok(Value::Number((min + self.below(span) as i64) as f64))
+    }
+
+    /// random.choice(array): one of the elements of `array`
+    pub fn choice(&mut self, args: &[Value]) -> Result<Value> {
+        check_count(args, 1, "choice")?;
+        let items = array_arg(&args[0], "choice")?;
+        if items.is_empty() {
+            return Err(runtime_error(
+                "random.choice: cannot choose from an empty array".to_string(),
+            ));
+        }
+        Ok(items[self.below(items.len() as u64) as usize].clone())
+    }
+
+    /// random.shuffle(array): a new array with the elements of `array` in a
+    /// random order
+    pub fn shuffle(&mut self, args: &[Value]) -> Result<Value> {
+        check_count(args, 1, "shuffle")?;
+        let mut items = array_arg(&args[0], "shuffle")?.to_vec();
+        // Fisher-Yates: every order is equally likely
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+        Ok(Value::Array(items))
+    }
+
+    /// random.seed(n): make the numbers that follow the same on every run
+    /// seeded with `n`
+    pub fn seed(&mut self, args: &[Value]) -> Result<Value> {
+        check_count(args, 1, "seed")?;
+        let seed = whole_number(&args[0], "seed")?;
+        self.state = Some(seed as u64);
+        Ok(Value::Null)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let current = self.state.unwrap_or_else(sources::random_u64);
+        let next = current.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state = Some(next);
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1), with all 53 bits of a number's precision
+    fn next_unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [0, `span`). Draws that fall in the incomplete last block of
+    /// `span` values are redrawn, so no result is more likely than another.
+    fn below(&mut self, span: u64) -> u64 {
+        let limit = u64::MAX - u64::MAX % span;
+        loop {
+            let draw = self.next_u64();
+            if draw < limit {
+                return draw % span;
+            }
+        }
+    }
+}
+
+fn whole_number(value: &Value, function_name: &str) -> Result<i64> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER => Ok(*n as i64),
+        other => Err(InfraError::TypeError {
+            expected: "whole number".to_string(),
+            found: other.to_string(),
+            context: Some(format!("random.{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+fn array_arg<'a>(value: &'a Value, function_name: &str) -> Result<&'a [Value]> {
+    match value {
+        Value::Array(items) => Ok(items),
+        other => Err(InfraError::TypeError {
+            expected: "array".to_string(),
+            found: other.type_name().to_string(),
+            context: Some(format!("random.{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+fn check_count(args: &[Value], expected: usize, function_name: &str) -> Result<()> {
+    if args.len() != expected {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected,
+            found: args.len(),
+            function_name: Some(format!("random.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn runtime_error(message: String) -> InfraError {
+    InfraError::RuntimeError {
+        message,
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
@@ -0,0 +1,513 @@
+//! Record and replay of a script's calls to the outside world.
+//!
+//! `infra --record session.json script.infra` runs the script normally and
+//! logs every call to an operation in `RECORDED` with its arguments and its
+//! result, in order. `infra --replay session.json script.infra` answers
+//! those calls from the log instead of touching files, running commands or
+//! making HTTP requests, so a run against production can be debugged
+//! without production. Writes are answered from the log too, which makes
+//! them no-ops as in dry-run mode. A call the log cannot answer stops the
+//! run with a description of where the script diverged.
+//!
+//! The log is versioned JSON in the canonical serialization; bytes are
+//! written as `{"$bytes": "<base64>"}` and a settled promise as
+//! `{"$promise": {"value": ...}}` or `{"$promise": {"error": "..."}}`.
+
+use crate::core::ObjectMap;
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::async_mod;
+use crate::utils::{base64, json, VERSION};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Version of the recording format; recordings of another version are
+/// rejected rather than misread
+pub const FORMAT_VERSION: f64 = 1.0;
+
+/// Operations whose calls are recorded: everything that reads from or
+/// changes files and processes, and reads of the environment and stdin
+const RECORDED: &[(&str, &str)] = &[
+    ("io", "env"),
+    ("io", "read_line"),
+    ("io", "input"),
+    ("io", "read_all"),
+    ("io", "read_file"),
+    ("io", "read_lines"),
+    ("io", "read_bytes"),
+    ("io", "exists"),
+    ("io", "glob"),
+    ("io", "list_dir"),
+    ("io", "write_file"),
+    ("io", "write_bytes"),
+    ("io", "append_file"),
+    ("io", "delete_file"),
+    ("io", "mkdir"),
+    ("process", "exec"),
+    ("async", "http_get"),
+    ("async", "http_post"),
+];
+
+/// Recorded operations that return a promise. The recording keeps how the
+/// promise settled; a replayed call gets a new promise settled the same way
+const PROMISED: &[&str] = &["async.http_get", "async.http_post"];
+
+/// How replayed calls are matched to the recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Matching {
+    /// Each call must be the next one recorded
+    Order,
+    /// Each call is answered by the first unused recorded call with the same
+    /// operation and arguments, wherever it is
+    Key,
+}
+
+struct Call {
+    op: String,
+    args: Vec<Value>,
+    outcome: std::result::Result<Value, InfraError>,
+}
+
+enum Session {
+    Off,
+    Recording {
+        path: PathBuf,
+        calls: Vec<Call>,
+    },
+    Replaying {
+        path: PathBuf,
+        calls: Vec<Call>,
+        used: Vec<bool>,
+        /// Calls made so far, counting from 1 in messages
+        made: usize,
+        matching: Matching,
+    },
+}
+
+thread_local! {
+    static SESSION: RefCell<Session> = const { RefCell::new(Session::Off) };
+}
+
+/// Record calls from now on; `save` writes them to `path`
+pub fn start_recording(path: &Path) {
+    SESSION.with(|session| {
+        *session.borrow_mut() = Session::Recording {
+            path: path.to_path_buf(),
+            calls: Vec::new(),
+        }
+    });
+}
+
+/// Answer recorded operations from the recording at `path` from now on
+pub fn start_replay(path: &Path, matching: Matching) -> Result<()> {
+    let calls = load(path)?;
+    SESSION.with(|session| {
+        *session.borrow_mut() = Session::Replaying {
+            path: path.to_path_buf(),
+            used: vec![false; calls.len()],
+            calls,
+            made: 0,
+            matching,
+        }
+    });
+    Ok(())
+}
+
+/// Command-line flags that put a child infra process in this session's mode.
+/// The child records to, or replays from, its own file next to this one,
+/// named with `suffix`, so the two logs never interleave
+pub fn child_flags(suffix: &str) -> Vec<String> {
+    let child_path = |path: &Path| format!("{}.{}", path.display(), suffix);
+    SESSION.with(|session| match &*session.borrow() {
+        Session::Off => Vec::new(),
+        Session::Recording { path, .. } => vec!["--record".to_string(), child_path(path)],
+        Session::Replaying { path, matching, .. } => {
+            let matching = match matching {
+                Matching::Order => "order",
+                Matching::Key => "key",
+            };
+            vec![
+                "--replay".to_string(),
+                child_path(path),
+                "--replay-match".to_string(),
+                matching.to_string(),
+            ]
+        }
+    })
+}
+
+fn is_recorded(module: &str, function: &str) -> bool {
+    RECORDED.iter().any(|(m, f)| *m == module && *f == function)
+}
+
+/// In replay mode, the recorded result of a recorded operation, or an error
+/// if the recording has no matching call; None otherwise
+pub fn replay(module: &str, function: &str, args: &[Value]) -> Option<Result<Value>> {
+    if !is_recorded(module, function) {
+        return None;
+    }
+    SESSION.with(|session| {
+        let mut session = session.borrow_mut();
+        let Session::Replaying {
+            path,
+            calls,
+            used,
+            made,
+            matching,
+        } = &mut *session
+        else {
+            return None;
+        };
+
+        *made += 1;
+        let op = format!("{}.{}", module, function);
+        let found = match matching {
+            Matching::Order => Some(*made - 1).filter(|&index| {
+                calls
+                    .get(index)
+                    .is_some_and(|call| call.op == op && call.args == args)
+            }),
+            Matching::Key => (0..calls.len())
+                .find(|&index| !used[index] && calls[index].op == op && calls[index].args == args),
+        };
+
+        Some(match found {
+            Some(index) => {
+                used[index] = true;
+                let outcome = calls[index].outcome.clone();
+                match PROMISED.iter().find(|origin| **origin == op) {
+                    Some(origin) => outcome.map(|value| revive(value, origin)),
+                    None => outcome,
+                }
+            }
+            None => Err(divergence(path, calls, used, *made, *matching, &op, args)),
+        })
+    })
+}
+
+/// In record mode, add a call to a recorded operation and what it returned
+pub fn record(module: &str, function: &str, args: &[Value], result: &Result<Value>) {
+    if !is_recorded(module, function) {
+        return;
+    }
+    SESSION.with(|session| {
+        if let Session::Recording { calls, .. } = &mut *session.borrow_mut() {
+            calls.push(Call {
+                op: format!("{}.{}", module, function),
+                args: args.to_vec(),
+                outcome: result.clone(),
+            });
+        }
+    });
+}
+
+/// In record mode, write the calls recorded so far to the recording file
+pub fn save() -> Result<()> {
+    SESSION.with(|session| {
+        let session = session.borrow();
+        let Session::Recording { path, calls } = &*session else {
+            return Ok(());
+        };
+
+        let calls = calls.iter().map(call_to_value).collect();
+        let mut document = ObjectMap::new();
+        document.insert("version".to_string(), Value::Number(FORMAT_VERSION));
+        document.insert("infra".to_string(), Value::String(VERSION.to_string()));
+        document.insert("calls".to_string(), Value::Array(calls));
+
+        let contents = Value::Object(document)
+            .to_canonical_string()
+            .ok_or_else(|| recording_error(path, "a recorded value cannot be serialized"))?;
+        fs::write(path, contents + "\n").map_err(|error| InfraError::IoError {
+            message: format!("Failed to write recording: {}", error),
+            operation: Some("record".to_string()),
+            path: Some(path.display().to_string()),
+        })
+    })
+}
+
+fn call_to_value(call: &Call) -> Value {
+    let mut entry = ObjectMap::new();
+    entry.insert("op".to_string(), Value::String(call.op.clone()));
+    entry.insert(
+        "args".to_string(),
+        Value::Array(call.args.iter().map(encode).collect()),
+    );
+    match &call.outcome {
+        Ok(value) => {
+            entry.insert("result".to_string(), encode(value));
+        }
+        Err(error) => {
+            entry.insert("error".to_string(), error_to_value(error));
+        }
+    }
+    Value::Object(entry)
+}
+
+/// I/O errors keep their fields so a replayed failure reads the same; any
+/// other error is replayed as a runtime error with the same message
+fn error_to_value(error: &InfraError) -> Value {
+    let mut fields = ObjectMap::new();
+    let optional = |value: &Option<String>| value.clone().map_or(Value::Null, Value::String);
+    match error {
+        InfraError::IoError {
+            message,
+            operation,
+            path,
+        } => {
+            fields.insert("kind".to_string(), Value::String("io".to_string()));
+            fields.insert("message".to_string(), Value::String(message.clone()));
+            fields.insert("operation".to_string(), optional(operation));
+            fields.insert("path".to_string(), optional(path));
+        }
+        other => {
+            fields.insert("kind".to_string(), Value::String("runtime".to_string()));
+            fields.insert("message".to_string(), Value::String(other.to_string()));
+        }
+    }
+    Value::Object(fields)
+}
+
+fn error_from_value(value: &Value) -> Option<InfraError> {
+    let fields = value.as_object()?;
+    let message = fields.get("message")?.as_string()?.to_string();
+    let optional = |name: &str| fields.get(name)?.as_string().cloned();
+    match fields.get("kind")?.as_string()?.as_str() {
+        "io" => Some(InfraError::IoError {
+            message,
+            operation: optional("operation"),
+            path: optional("path"),
+        }),
+        _ => Some(InfraError::RuntimeError {
+            message,
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        }),
+    }
+}
+
+/// Bytes, tuples and settled promises have no JSON form of their own
+fn encode(value: &Value) -> Value {
+    match value {
+        Value::Promise {
+            value,
+            resolved,
+            rejected,
+            error,
+            ..
+        } if *resolved || *rejected => {
+            let mut settled = ObjectMap::new();
+            if *rejected {
+                let error = error.clone().unwrap_or_default();
+                settled.insert("error".to_string(), Value::String(error));
+            } else {
+                let value = value.as_deref().map_or(Value::Null, encode);
+                settled.insert("value".to_string(), value);
+            }
+            let mut tagged = ObjectMap::new();
+            tagged.insert("$promise".to_string(), Value::Object(settled));
+            Value::Object(tagged)
+        }
+        Value::Tuple(items) => {
+            let mut tagged = ObjectMap::new();
+            tagged.insert(
+                "$tuple".to_string(),
+                Value::Array(items.iter().map(encode).collect()),
+            );
+            Value::Object(tagged)
+        }
+        Value::Bytes(bytes) => {
+            let mut tagged = ObjectMap::new();
+            tagged.insert("$bytes".to_string(), Value::String(base64::encode(bytes)));
+            Value::Object(tagged)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(encode).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), encode(value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn decode(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) if fields.len() == 1 => match fields.get("$bytes") {
+            Some(Value::String(text)) => match base64::decode(text) {
+                Ok(bytes) => Value::Bytes(Rc::new(bytes)),
+                Err(_) => value.clone(),
+            },
+            _ if fields.contains_key("$tuple") => match &fields["$tuple"] {
+                Value::Array(items) => Value::Tuple(items.iter().map(decode).collect()),
+                _ => value.clone(),
+            },
+            _ => Value::Object(fields.iter().map(|(k, v)| (k.clone(), decode(v))).collect()),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(decode).collect()),
+        Value::Object(fields) => {
+            Value::Object(fields.iter().map(|(k, v)| (k.clone(), decode(v))).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// A recorded promise as a new promise, settled as the recorded one was.
+/// Promises are only made when a call is replayed, so that they are
+/// tracked from the line that makes the call
+fn revive(value: Value, origin: &'static str) -> Value {
+    let Value::Object(fields) = &value else {
+        return value;
+    };
+    let Some(Value::Object(settled)) = fields.get("$promise").filter(|_| fields.len() == 1) else {
+        return value;
+    };
+    match (settled.get("value"), settled.get("error")) {
+        (_, Some(Value::String(error))) => async_mod::rejected_promise(error.clone(), origin),
+        (Some(resolved), _) => async_mod::resolved_promise(resolved.clone(), origin),
+        _ => value,
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<Call>> {
+    let contents = fs::read_to_string(path).map_err(|error| InfraError::IoError {
+        message: format!("Failed to read recording: {}", error),
+        operation: Some("replay".to_string()),
+        path: Some(path.display().to_string()),
+    })?;
+    let document = json::parse(&contents).map_err(|error| recording_error(path, &error))?;
+    let document = document
+        .as_object()
+        .ok_or_else(|| recording_error(path, "expected a JSON object"))?;
+
+    match document.get("version") {
+        Some(Value::Number(version)) if *version == FORMAT_VERSION => {}
+        other => {
+            return Err(recording_error(
+                path,
+                &format!(
+                    "format version {} is not supported (expected {})",
+                    other.map_or("missing".to_string(), Value::to_string),
+                    FORMAT_VERSION
+                ),
+            ))
+        }
+    }
+
+    let Some(Value::Array(entries)) = document.get("calls") else {
+        return Err(recording_error(path, "missing the list of calls"));
+    };
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            call_from_value(entry)
+                .ok_or_else(|| recording_error(path, &format!("call {} is malformed", index + 1)))
+        })
+        .collect()
+}
+
+fn call_from_value(entry: &Value) -> Option<Call> {
+    let entry = entry.as_object()?;
+    let op = entry.get("op")?.as_string()?.to_string();
+    let Value::Array(args) = entry.get("args")? else {
+        return None;
+    };
+    let outcome = match (entry.get("result"), entry.get("error")) {
+        (Some(result), None) => Ok(decode(result)),
+        (None, Some(error)) => Err(error_from_value(error)?),
+        _ => return None,
+    };
+    Some(Call {
+        op,
+        args: args.iter().map(decode).collect(),
+        outcome,
+    })
+}
+
+fn recording_error(path: &Path, reason: &str) -> InfraError {
+    InfraError::Generic(format!(
+        "Invalid recording '{}': {}",
+        path.display(),
+        reason
+    ))
+}
+
+/// Why the recording has no answer for call number `made`, with the
+/// arguments that differ from the closest recorded call
+fn divergence(
+    path: &Path,
+    calls: &[Call],
+    used: &[bool],
+    made: usize,
+    matching: Matching,
+    op: &str,
+    args: &[Value],
+) -> InfraError {
+    let call = describe_call(op, args);
+    let closest = match matching {
+        Matching::Order => calls.get(made - 1),
+        Matching::Key => calls
+            .iter()
+            .zip(used)
+            .find(|(recorded, used)| !**used && recorded.op == op)
+            .map(|(recorded, _)| recorded),
+    };
+
+    let mut message = format!(
+        "Replay of '{}' diverged at call {}: {}",
+        path.display(),
+        made,
+        call
+    );
+    match closest {
+        None if matching == Matching::Order => message.push_str(&format!(
+            " was not recorded; the recording ends after {} calls",
+            calls.len()
+        )),
+        None => message.push_str(" is not in the recording"),
+        Some(recorded) if recorded.op != op => message.push_str(&format!(
+            " was made where the recording has {}",
+            describe_call(&recorded.op, &recorded.args)
+        )),
+        Some(recorded) => {
+            message.push_str(" has different arguments than recorded");
+            let count = recorded.args.len().max(args.len());
+            for index in 0..count {
+                let expected = recorded.args.get(index);
+                let found = args.get(index);
+                if expected != found {
+                    message.push_str(&format!(
+                        "\n  argument {}:\n  - {}\n  + {}",
+                        index + 1,
+                        expected.map_or("(none)".to_string(), show),
+                        found.map_or("(none)".to_string(), show)
+                    ));
+                }
+            }
+        }
+    }
+    InfraError::Generic(message)
+}
+
+fn describe_call(op: &str, args: &[Value]) -> String {
+    let args: Vec<String> = args.iter().map(show).collect();
+    format!("{}({})", op, args.join(", "))
+}
+
+/// An argument as it would be written in a script, cut short if long
+fn show(value: &Value) -> String {
+    const LIMIT: usize = 80;
+    let text = encode(value)
+        .to_canonical_string()
+        .unwrap_or_else(|| value.to_string());
+    if text.chars().count() > LIMIT {
+        format!("{}...", text.chars().take(LIMIT).collect::<String>())
+    } else {
+        text
+    }
+}
@@ -0,0 +1,490 @@
+//! Recurring jobs: `schedule.every(ms, fn)` and `schedule.cron(expr, fn)`.
+//!
+//! Each evaluator keeps its own job table, so engines on one thread never
+//! run each other's jobs. Running a job calls back into script code, so the
+//! evaluator runs them, and the runner keeps a script alive after its last
+//! statement while jobs remain.
+//! A job is never re-entered: a run that comes due while the previous one is
+//! still going is skipped with a warning. Cron expressions are matched
+//! against UTC.
+//!
+//! Time comes from the current clock source (see `sources`). On a virtual
+//! clock tests can simulate hours instantly: waiting for a job, like
+//! awaiting `async.sleep`, advances the clock instead of blocking.
+
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::sources::{self, ManualClock, SystemClock};
+use crate::stdlib::{cancel, progress};
+use crate::utils::ErrorReporter;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+/// Longest real sleep between checks for Ctrl-C while waiting for a job
+const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+const MS_PER_MINUTE: u64 = 60_000;
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+/// How far ahead a cron expression is searched for a match: five years
+/// covers every leap day
+const CRON_HORIZON_MINUTES: u64 = 5 * 366 * MINUTES_PER_DAY;
+
+struct Job {
+    function: Value,
+    trigger: Trigger,
+    next_fire: u64,
+    running: bool,
+}
+
+enum Trigger {
+    Every(u64),
+    Cron(CronExpr),
+}
+
+impl Trigger {
+    /// The first fire time strictly after `fire`
+    fn following(&self, fire: u64) -> u64 {
+        match self {
+            Trigger::Every(interval) => fire + interval,
+            // An expression that matched once matches again within the horizon
+            Trigger::Cron(expr) => expr.next_after(fire).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+thread_local! {
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Milliseconds since the Unix epoch, from the current clock source
+pub fn now_ms() -> u64 {
+    sources::now_ms()
+}
+
+/// Run on a virtual clock starting at `start` ms, or on the real one again
+#[allow(dead_code)]
+pub fn set_virtual_time(start: Option<u64>) {
+    match start {
+        Some(start) => sources::set_clock(Rc::new(ManualClock::new(start))),
+        None => sources::set_clock(Rc::new(SystemClock)),
+    }
+}
+
+/// Move the virtual clock forward. Returns false, doing nothing, when the
+/// real clock is in use.
+pub fn advance_virtual_time(ms: u64) -> bool {
+    sources::advance(ms)
+}
+
+/// Block until `deadline`, or jump the virtual clock there. Ctrl-C stops
+/// the wait.
+pub fn wait_until(deadline: u64) -> Result<()> {
+    loop {
+        cancel::check(None)?;
+        let now = now_ms();
+        if now >= deadline || advance_virtual_time(deadline - now) {
+            return Ok(());
+        }
+        thread::sleep(WAIT_SLICE.min(Duration::from_millis(deadline - now)));
+    }
+}
+
+fn warn(message: String) {
+    eprintln!("Warning: {}", message);
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(message));
+}
+
+/// Warnings printed since the last call, oldest first
+#[allow(dead_code)]
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.with(|warnings| std::mem::take(&mut *warnings.borrow_mut()))
+}
+
+/// The jobs of one evaluator
+pub struct Jobs {
+    jobs: BTreeMap<usize, Job>,
+    next_id: usize,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self {
+            jobs: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl Jobs {
+    /// When the next job comes due
+    pub fn next_due(&self) -> Option<u64> {
+        self.jobs.values().map(|job| job.next_fire).min()
+    }
+
+    /// Jobs due at `now`, in the order they came due, marked as running.
+    /// Due jobs whose previous run has not finished are skipped instead.
+    pub fn take_due(&mut self, now: u64) -> Vec<(usize, Value)> {
+        let mut due: Vec<(u64, usize)> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.next_fire <= now)
+            .map(|(id, job)| (job.next_fire, *id))
+            .collect();
+        due.sort();
+
+        let mut started = Vec::new();
+        for (_, id) in due {
+            let job = self.jobs.get_mut(&id).expect("due job exists");
+            if job.running {
+                warn(format!(
+                    "schedule: job {} is still running; skipping overlapping run",
+                    id
+                ));
+                while job.next_fire <= now {
+                    job.next_fire = job.trigger.following(job.next_fire);
+                }
+                continue;
+            }
+            job.running = true;
+            started.push((id, job.function.clone()));
+        }
+        started
+    }
+
+    /// Record that job `id`, started at `started`, finished at `now`. Fire
+    /// times that passed before it started are caught up by this one run;
+    /// those that passed while it ran are skipped with a warning.
+    pub fn finish(&mut self, id: usize, started: u64, now: u64) {
+        // The job may have cancelled itself
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return;
+        };
+        job.running = false;
+
+        let mut next = job.next_fire;
+        while next <= started {
+            next = job.trigger.following(next);
+        }
+        let mut skipped = 0;
+        while next <= now {
+            skipped += 1;
+            next = job.trigger.following(next);
+        }
+        job.next_fire = next;
+
+        if skipped > 0 {
+            warn(format!(
+                "schedule: job {} ran for {}ms; skipped {} overlapping run(s)",
+                id,
+                now - started,
+                skipped
+            ));
+        }
+    }
+
+    /// schedule.every(ms, fn): call `fn` every `ms` milliseconds; returns a
+    /// handle for schedule.cancel
+    pub fn every(&mut self, args: &[Value]) -> Result<Value> {
+        check_args(args, 2, "every")?;
+        let interval = match &args[0] {
+            Value::Number(ms) if *ms >= 1.0 && ms.is_finite() => *ms as u64,
+            Value::Number(ms) => {
+                return Err(runtime_error(
+                    "every",
+                    format!("interval must be at least 1ms, got {}", ms),
+                ))
+            }
+            other => return Err(type_error("number", other, "every")),
+        };
+        self.add(
+            &args[1],
+            Trigger::Every(interval),
+            now_ms() + interval,
+            "every",
+        )
+    }
+
+    /// schedule.cron(expr, fn): call `fn` at the times matched by a 5-field
+    /// cron expression
+    pub fn cron(&mut self, args: &[Value]) -> Result<Value> {
+        check_args(args, 2, "cron")?;
+        let Value::String(text) = &args[0] else {
+            return Err(type_error("string", &args[0], "cron"));
+        };
+        let expr = CronExpr::parse(text).map_err(|message| runtime_error("cron", message))?;
+        let Some(next_fire) = expr.next_after(now_ms()) else {
+            return Err(runtime_error(
+                "cron",
+                format!("cron expression '{}' never matches a date", text),
+            ));
+        };
+        self.add(&args[1], Trigger::Cron(expr), next_fire, "cron")
+    }
+
+    /// schedule.cancel(handle): stop a job; returns whether it was still
+    /// scheduled
+    pub fn cancel(&mut self, args: &[Value]) -> Result<Value> {
+        check_args(args, 1, "cancel")?;
+        let id = match &args[0] {
+            Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => *n as usize,
+            other => return Err(type_error("schedule handle", other, "cancel")),
+        };
+        Ok(Value::Boolean(self.jobs.remove(&id).is_some()))
+    }
+
+    fn add(
+        &mut self,
+        function: &Value,
+        trigger: Trigger,
+        next_fire: u64,
+        function_name: &str,
+    ) -> Result<Value> {
+        if !matches!(function, Value::Function { .. }) {
+            return Err(type_error("function", function, function_name));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.insert(
+            id,
+            Job {
+                function: function.clone(),
+                trigger,
+                next_fire,
+                running: false,
+            },
+        );
+        Ok(Value::Number(id as f64))
+    }
+}
+
+/// Report a job that failed; the scheduler carries on with the next run
+pub fn report_failure(id: usize, error: &InfraError) {
+    warn(format!("schedule: job {} failed", id));
+    let mut reporter = if progress::no_color() {
+        ErrorReporter::new_no_color()
+    } else {
+        ErrorReporter::new()
+    };
+    reporter.report_error(error);
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("schedule.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn runtime_error(function_name: &str, message: String) -> InfraError {
+    InfraError::RuntimeError {
+        message: format!("schedule.{}: {}", function_name, message),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
+
+fn check_args(args: &[Value], count: usize, function_name: &str) -> Result<()> {
+    if args.len() != count {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: count,
+            found: args.len(),
+            function_name: Some(format!("schedule.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+/// The scheduler's current time in milliseconds since the Unix epoch
+#[allow(dead_code)]
+pub fn now(args: &[Value]) -> Result<Value> {
+    check_args(args, 0, "now")?;
+    Ok(Value::Number(now_ms() as f64))
+}
+
+/// The set of values one cron field matches, as a bit mask
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    bits: u64,
+    /// Written as `*`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A standard cron expression: minute, hour, day of month, month and day
+/// of week
+#[derive(Debug, Clone)]
+pub struct CronExpr {
+    minute: Field,
+    hour: Field,
+    day_of_month: Field,
+    month: Field,
+    day_of_week: Field,
+}
+
+impl CronExpr {
+    /// Parse an expression; errors name the offending field
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "invalid cron expression '{}': expected 5 fields (minute hour day-of-month month day-of-week), found {}",
+                text,
+                fields.len()
+            ));
+        }
+
+        let field = |index: usize, name: &str, min: u32, max: u32, names: &[&str], offset: u32| {
+            parse_field(fields[index], min, max, names, offset).map_err(|problem| {
+                format!(
+                    "invalid cron expression '{}': {} field '{}' {}",
+                    text, name, fields[index], problem
+                )
+            })
+        };
+
+        let mut day_of_week = field(4, "day-of-week", 0, 7, DAY_NAMES, 0)?;
+        // 7 is another name for Sunday
+        if day_of_week.matches(7) {
+            day_of_week.bits = (day_of_week.bits & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minute: field(0, "minute", 0, 59, &[], 0)?,
+            hour: field(1, "hour", 0, 23, &[], 0)?,
+            day_of_month: field(2, "day-of-month", 1, 31, &[], 0)?,
+            month: field(3, "month", 1, 12, MONTH_NAMES, 1)?,
+            day_of_week,
+        })
+    }
+
+    /// When both day fields are restricted a day matching either one
+    /// counts, as in every common cron implementation
+    fn day_matches(&self, day: u32, weekday: u32) -> bool {
+        match (self.day_of_month.any, self.day_of_week.any) {
+            (false, false) => self.day_of_month.matches(day) || self.day_of_week.matches(weekday),
+            _ => self.day_of_month.matches(day) && self.day_of_week.matches(weekday),
+        }
+    }
+
+    /// The first matching minute strictly after `after_ms`, in ms
+    pub fn next_after(&self, after_ms: u64) -> Option<u64> {
+        let mut minute = after_ms / MS_PER_MINUTE + 1;
+        let limit = minute + CRON_HORIZON_MINUTES;
+
+        while minute < limit {
+            let days = minute / MINUTES_PER_DAY;
+            let (month, day) = month_and_day(days as i64);
+            // 1970-01-01 was a Thursday
+            let weekday = ((days + 4) % 7) as u32;
+            if !self.month.matches(month) || !self.day_matches(day, weekday) {
+                minute = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            if !self.hour.matches((minute % MINUTES_PER_DAY / 60) as u32) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if !self.minute.matches((minute % 60) as u32) {
+                minute += 1;
+                continue;
+            }
+            return Some(minute * MS_PER_MINUTE);
+        }
+        None
+    }
+}
+
+/// Parse one field: `*`, a value, a range `a-b`, any of those followed by
+/// a step `/n`, or a comma-separated list of them. Names count from
+/// `name_offset`, so `jan` is 1 and `sun` is 0.
+fn parse_field(
+    text: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    name_offset: u32,
+) -> std::result::Result<Field, String> {
+    let value = |part: &str| -> std::result::Result<u32, String> {
+        let lower = part.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + name_offset,
+            None => part
+                .parse()
+                .map_err(|_| format!("has '{}', which is not a number or name", part))?,
+        };
+        if value < min || value > max {
+            return Err(format!("is out of range {}-{}", min, max));
+        }
+        Ok(value)
+    };
+
+    let mut field = Field {
+        bits: 0,
+        any: text == "*",
+    };
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("has an invalid step '{}'", step)),
+            },
+            None => (part, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` runs from 5 to the end of the range
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let single = value(range)?;
+                (single, single)
+            }
+        };
+        if start > end {
+            return Err(format!("has an inverted range {}-{}", start, end));
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            field.bits |= 1 << value;
+        }
+    }
+    Ok(field)
+}
+
+/// Month and day of month for a count of days since 1970-01-01, using
+/// Howard Hinnant's civil-from-days algorithm
+fn month_and_day(days: i64) -> (u32, u32) {
+    let shifted = days + 719_468;
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    (month as u32, day as u32)
+}
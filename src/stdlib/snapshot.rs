@@ -0,0 +1,426 @@
+//! `assert.snapshot(value, label?)`: compare a value against the rendering
+//! stored the last time the test ran.
+//!
+//! Snapshots live next to the test in `__snapshots__/<test>.snap`, one
+//! section per `assert.snapshot` call, keyed by the test's name and the
+//! call's label (or `#1`, `#2`, ... for unlabeled calls, in the order they
+//! run). A snapshot seen for the first time is written and passes; after
+//! that a different value fails with a line diff against the stored one.
+//! `infra --test --update-snapshots` rewrites changed snapshots instead of
+//! failing and removes those no call asserts any more.
+//!
+//! The rendering is meant to be reviewed in a diff: one array item or
+//! object field per line, object keys sorted (equal objects render the
+//! same whatever their key order), strings quoted and escaped.
+//!
+//! Snapshots only exist inside `infra --test`, which starts a session for
+//! each test file and finishes it when the file has run.
+
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOT_DIR: &str = "__snapshots__";
+const HEADER_PREFIX: &str = "--- ";
+
+thread_local! {
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// The snapshots of the test file being run
+struct Session {
+    /// Test file name without its extension, e.g. `report_test`
+    test: String,
+    path: PathBuf,
+    update: bool,
+    /// Sections in file order; new ones are appended
+    entries: Vec<(String, String)>,
+    /// Problem reading the file; every assertion fails with it
+    load_error: Option<String>,
+    seen: HashSet<String>,
+    unlabeled: usize,
+    written: Vec<String>,
+}
+
+/// What a finished session changed
+#[derive(Debug, Default)]
+pub struct SnapshotSummary {
+    /// Keys written, new or (under `--update-snapshots`) changed
+    pub written: Vec<String>,
+    /// Keys in the file that no call asserted; removed under
+    /// `--update-snapshots`
+    pub obsolete: Vec<String>,
+    /// The snapshot file, when it exists after the session
+    pub path: Option<PathBuf>,
+}
+
+/// Where the snapshots of `test` are stored
+pub fn snapshot_path(test: &Path) -> PathBuf {
+    let stem = test
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    test.with_file_name(SNAPSHOT_DIR)
+        .join(format!("{}.snap", stem))
+}
+
+/// Start collecting snapshots for the test file `test`
+pub fn begin(test: &Path, update: bool) {
+    let path = snapshot_path(test);
+    let (entries, load_error) = match fs::read_to_string(&path) {
+        Ok(text) => (parse(&text), None),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (Vec::new(), None),
+        Err(err) => (
+            Vec::new(),
+            Some(format!("Cannot read {}: {}", path.display(), err)),
+        ),
+    };
+    let test = test
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    SESSION.with(|session| {
+        *session.borrow_mut() = Some(Session {
+            test,
+            path,
+            update,
+            entries,
+            load_error,
+            seen: HashSet::new(),
+            unlabeled: 0,
+            written: Vec::new(),
+        })
+    });
+}
+
+/// End the session and save what changed. Obsolete snapshots are only
+/// known when the test ran to the end, so `completed` says whether it did.
+pub fn finish(completed: bool) -> std::result::Result<SnapshotSummary, String> {
+    let Some(mut session) = SESSION.with(|session| session.borrow_mut().take()) else {
+        return Ok(SnapshotSummary::default());
+    };
+    if session.load_error.is_some() {
+        return Ok(SnapshotSummary::default());
+    }
+
+    let obsolete: Vec<String> = if completed {
+        session
+            .entries
+            .iter()
+            .map(|(key, _)| key.clone())
+            .filter(|key| !session.seen.contains(key))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let removing = session.update && !obsolete.is_empty();
+    if removing {
+        session.entries.retain(|(key, _)| !obsolete.contains(key));
+    }
+
+    if !session.written.is_empty() || removing {
+        save(&session.path, &session.test, &session.entries)?;
+    }
+    Ok(SnapshotSummary {
+        written: session.written,
+        obsolete,
+        path: session.path.exists().then_some(session.path),
+    })
+}
+
+/// assert.snapshot(value, label?)
+pub fn snapshot(args: &[Value]) -> Result<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("assert.snapshot".to_string()),
+            line: None,
+        });
+    }
+    let label = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(label)) if !label.is_empty() && !label.contains('\n') => {
+            Some(label.clone())
+        }
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "single-line label string".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("assert.snapshot() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+    let rendered = render(&args[0]);
+
+    SESSION.with(|session| {
+        let mut session = session.borrow_mut();
+        let Some(session) = session.as_mut() else {
+            return Err(runtime_error(
+                "assert.snapshot only works in tests run with `infra --test`".to_string(),
+            ));
+        };
+        if let Some(message) = &session.load_error {
+            return Err(runtime_error(message.clone()));
+        }
+
+        let key = match label {
+            Some(label) => format!("{} {}", session.test, label),
+            None => {
+                session.unlabeled += 1;
+                format!("{} #{}", session.test, session.unlabeled)
+            }
+        };
+        if !session.seen.insert(key.clone()) {
+            return Err(runtime_error(format!(
+                "Snapshot '{}' is asserted twice; give each assert.snapshot call its own label",
+                key
+            )));
+        }
+
+        match session.entries.iter_mut().find(|(k, _)| *k == key) {
+            None => {
+                session.entries.push((key.clone(), rendered));
+                session.written.push(key);
+            }
+            Some((_, stored)) if *stored == rendered => {}
+            Some((_, stored)) if session.update => {
+                *stored = rendered;
+                session.written.push(key);
+            }
+            Some((_, stored)) => {
+                return Err(InfraError::Exception {
+                    message: format!(
+                        "snapshot '{}' does not match {}\n{}\
+                         Run `infra --test --update-snapshots` if the new value is right",
+                        key,
+                        session.path.display(),
+                        diff(stored, &rendered)
+                    ),
+                    exception_type: Some("AssertionError".to_string()),
+                    line: None,
+                    stack_trace: vec![],
+                    payload: None,
+                })
+            }
+        }
+        Ok(Value::Null)
+    })
+}
+
+/// The text a value is stored as
+pub fn render(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, 0, &mut out);
+    out
+}
+
+fn write_object(fields: &ObjectMap, depth: usize, out: &mut String) {
+    let mut keys: Vec<&String> = fields.keys().collect();
+    keys.sort();
+    let fields: Vec<(&String, &Value)> = keys.into_iter().map(|key| (key, &fields[key])).collect();
+    write_fields(&fields, depth, out);
+}
+
+fn write_value(value: &Value, depth: usize, out: &mut String) {
+    match value {
+        Value::String(s) => write_string(s, out),
+        Value::BigInt(n) => out.push_str(&format!("{}n", n)),
+        Value::Array(items) => write_items("[", "]", items, depth, out),
+        Value::Tuple(items) => write_items("(", ")", items, depth, out),
+        Value::Object(fields) => write_object(fields, depth, out),
+        Value::Instance(instance) => {
+            let written = instance.writing(|| {
+                out.push_str(&instance.class.name);
+                out.push(' ');
+                write_object(&instance.fields.borrow(), depth, out);
+            });
+            if written.is_none() {
+                out.push_str("<cycle>");
+            }
+        }
+        Value::Bytes(bytes) => {
+            out.push_str("bytes(");
+            for byte in bytes.iter() {
+                out.push_str(&format!("{:02x}", byte));
+            }
+            out.push(')');
+        }
+        Value::Enum(definition) => out.push_str(&format!("enum {}", definition.name)),
+        Value::Class(class) => out.push_str(&format!("class {}", class.name)),
+        Value::EnumMember { definition, index } => out.push_str(&format!(
+            "{}.{}",
+            definition.name, definition.members[*index]
+        )),
+        Value::Function { name, .. } => out.push_str(&format!("<function {}>", name)),
+        Value::NativeFunction { module, function } => {
+            out.push_str(&format!("<function {}.{}>", module, function))
+        }
+        Value::CompiledFunction { name, .. } => out.push_str(&format!("<function {}>", name)),
+        Value::Promise { .. } => out.push_str("<promise>"),
+        Value::Error {
+            error_type,
+            message,
+            fields,
+            ..
+        } => {
+            out.push_str(error_type);
+            out.push('(');
+            write_string(message, out);
+            out.push(')');
+            if !fields.is_empty() {
+                out.push(' ');
+                write_value(&Value::Object(fields.clone()), depth, out);
+            }
+        }
+        Value::Number(_) | Value::Boolean(_) | Value::Null => out.push_str(&value.to_string()),
+    }
+}
+
+fn write_items(open: &str, close: &str, items: &[Value], depth: usize, out: &mut String) {
+    out.push_str(open);
+    if !items.is_empty() {
+        for (i, item) in items.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            indent(depth + 1, out);
+            write_value(item, depth + 1, out);
+        }
+        out.push('\n');
+        indent(depth, out);
+    }
+    out.push_str(close);
+}
+
+fn write_fields(fields: &[(&String, &Value)], depth: usize, out: &mut String) {
+    out.push('{');
+    if !fields.is_empty() {
+        for (i, (key, value)) in fields.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            indent(depth + 1, out);
+            let plain = key
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if plain {
+                out.push_str(key);
+            } else {
+                write_string(key, out);
+            }
+            out.push_str(": ");
+            write_value(value, depth + 1, out);
+        }
+        out.push('\n');
+        indent(depth, out);
+    }
+    out.push('}');
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push_str(
+        &Value::String(s.to_string())
+            .to_json_string(false)
+            .expect("strings always serialize"),
+    );
+}
+
+fn indent(depth: usize, out: &mut String) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+/// The lines of `stored` and `actual`, marking those only one has with `-`
+/// and `+`
+pub fn diff(stored: &str, actual: &str) -> String {
+    let old: Vec<&str> = stored.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence, filled from the end
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::from("--- stored\n+++ actual\n");
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!("  {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            out.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Sections of a snapshot file: a `--- key` line, then the rendering up to
+/// the next one. Rendered values never start a line with `--- `, since
+/// nested lines are indented and strings are quoted.
+fn parse(text: &str) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if let Some(key) = line.strip_prefix(HEADER_PREFIX) {
+            entries.push((key.to_string(), String::new()));
+        } else if let Some((_, body)) = entries.last_mut() {
+            if !body.is_empty() {
+                body.push('\n');
+            }
+            body.push_str(line);
+        }
+    }
+    for (_, body) in &mut entries {
+        body.truncate(body.trim_end_matches('\n').len());
+    }
+    entries
+}
+
+fn save(path: &Path, test: &str, entries: &[(String, String)]) -> std::result::Result<(), String> {
+    let failed = |err: std::io::Error| format!("Could not write {}: {}", path.display(), err);
+    if entries.is_empty() {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(failed(err)),
+            _ => Ok(()),
+        };
+    }
+
+    let mut text = format!(
+        "# Snapshots for {}, checked by assert.snapshot.\n\
+         # Rewrite them with `infra --test --update-snapshots`.\n",
+        test
+    );
+    for (key, body) in entries {
+        text.push_str(&format!("\n{}{}\n{}\n", HEADER_PREFIX, key, body));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+    fs::write(path, text).map_err(failed)
+}
+
+fn runtime_error(message: String) -> InfraError {
+    InfraError::RuntimeError {
+        message,
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
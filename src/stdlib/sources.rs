@@ -0,0 +1,182 @@
+//! Where the standard library gets the time and random bytes.
+//!
+//! Every function that reads the clock goes through `now_ms` or
+//! `monotonic_ms`, and every one that needs unpredictable bytes goes
+//! through `fill_bytes`, so a host can swap either for its own: a virtual
+//! clock it advances by hand, or a recorded entropy stream. The sources in
+//! effect belong to the current thread; an `Engine` installs its own for the
+//! length of each call into it, so engines on one thread keep their own
+//! clocks.
+//!
+//! Sources are only asked when a function needs them, never when an
+//! interpreter or engine is built.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub trait ClockSource {
+    /// Milliseconds since the Unix epoch
+    fn now_ms(&self) -> u64;
+
+    /// Milliseconds since an arbitrary fixed point; never goes backwards
+    fn monotonic_ms(&self) -> u64;
+
+    /// Move the clock forward by `ms` instead of waiting for it. Returns
+    /// false for clocks that only follow real time.
+    fn advance(&self, _ms: u64) -> bool {
+        false
+    }
+}
+
+pub trait EntropySource {
+    fn fill_bytes(&self, buf: &mut [u8]);
+}
+
+/// The computer's clock
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+
+    fn monotonic_ms(&self) -> u64 {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_millis() as u64
+    }
+}
+
+/// A clock that only moves when told to. Clones share one time, so a host
+/// can keep a handle to the clock it gave an engine.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>,
+}
+
+#[allow(dead_code)]
+impl ManualClock {
+    /// A clock reading `start` ms since the Unix epoch
+    pub fn new(start: u64) -> Self {
+        Self {
+            now: Rc::new(Cell::new(start)),
+        }
+    }
+
+    pub fn set(&self, ms: u64) {
+        self.now.set(ms);
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now.get()
+    }
+
+    fn monotonic_ms(&self) -> u64 {
+        self.now.get()
+    }
+
+    fn advance(&self, ms: u64) -> bool {
+        self.now.set(self.now.get() + ms);
+        true
+    }
+}
+
+/// Bytes mixed from the clock, the thread and a counter with splitmix64.
+/// Unpredictable enough for temporary names and fault probabilities; not
+/// for secrets.
+pub struct SystemEntropy;
+
+impl EntropySource for SystemEntropy {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        thread_local! {
+            static STATE: Cell<u64> = const { Cell::new(0) };
+        }
+        STATE.with(|state| {
+            if state.get() == 0 {
+                let nanos = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64);
+                let thread = format!("{:?}", std::thread::current().id());
+                let seed = thread
+                    .bytes()
+                    .fold(nanos ^ std::process::id() as u64, |h, b| {
+                        h.rotate_left(5) ^ b as u64
+                    });
+                state.set(seed | 1);
+            }
+            for chunk in buf.chunks_mut(8) {
+                let next = state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+                state.set(next);
+                let mut z = next;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                z ^= z >> 31;
+                chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+            }
+        })
+    }
+}
+
+/// A clock and an entropy source that are used together
+#[derive(Clone)]
+pub struct Sources {
+    pub clock: Rc<dyn ClockSource>,
+    pub entropy: Rc<dyn EntropySource>,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            clock: Rc::new(SystemClock),
+            entropy: Rc::new(SystemEntropy),
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Sources> = RefCell::new(Sources::default());
+}
+
+/// Make `sources` the current thread's; returns the ones they replace
+pub fn install(sources: Sources) -> Sources {
+    CURRENT.with(|current| std::mem::replace(&mut *current.borrow_mut(), sources))
+}
+
+/// Use `clock` from now on, keeping the entropy source
+pub fn set_clock(clock: Rc<dyn ClockSource>) {
+    CURRENT.with(|current| current.borrow_mut().clock = clock);
+}
+
+fn clock() -> Rc<dyn ClockSource> {
+    CURRENT.with(|current| current.borrow().clock.clone())
+}
+
+pub fn now_ms() -> u64 {
+    clock().now_ms()
+}
+
+#[allow(dead_code)]
+pub fn monotonic_ms() -> u64 {
+    clock().monotonic_ms()
+}
+
+/// Move the current clock forward; false when it follows real time
+pub fn advance(ms: u64) -> bool {
+    clock().advance(ms)
+}
+
+pub fn fill_bytes(buf: &mut [u8]) {
+    let entropy = CURRENT.with(|current| current.borrow().entropy.clone());
+    entropy.fill_bytes(buf);
+}
+
+pub fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
@@ -1,4 +1,5 @@
 use crate::core::{InfraError, Result, Value};
+use std::cmp::Ordering;
 
 /// Get string length
 #[allow(dead_code)]
@@ -101,7 +102,9 @@ pub fn join(args: &[Value]) -> Result<Value> {
     }
 }
 
-/// Convert string to uppercase
+/// Convert string to uppercase. Uses the Unicode default mapping, the same
+/// under every locale: `i` becomes `I`, never the Turkish dotted `İ`, and `ß`
+/// becomes `SS`.
 #[allow(dead_code)]
 pub fn upper(args: &[Value]) -> Result<Value> {
     if args.len() != 1 {
@@ -126,7 +129,8 @@ pub fn upper(args: &[Value]) -> Result<Value> {
     }
 }
 
-/// Convert string to lowercase
+/// Convert string to lowercase. Uses the Unicode default mapping, the same
+/// under every locale: `I` becomes `i`, never the Turkish dotless `ı`.
 #[allow(dead_code)]
 pub fn lower(args: &[Value]) -> Result<Value> {
     if args.len() != 1 {
@@ -455,3 +459,227 @@ pub fn pad_right(args: &[Value]) -> Result<Value> {
         }),
     }
 }
+
+/// Fold case for caseless matching: Unicode full case folding, so `ß`, `ẞ`
+/// and `SS` all fold to `ss` and final `ς` folds to `σ`
+#[allow(dead_code)]
+pub fn casefold(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("string_casefold".to_string()),
+            line: None,
+        });
+    }
+
+    match &args[0] {
+        Value::String(s) => Ok(Value::String(fold(s))),
+        _ => Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("string_casefold() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Whether two strings are equal once both are case folded
+#[allow(dead_code)]
+pub fn equals_ignore_case(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("string_equals_ignore_case".to_string()),
+            line: None,
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::String(a), Value::String(b)) => Ok(Value::Boolean(fold(a) == fold(b))),
+        _ => Err(InfraError::TypeError {
+            expected: "two strings".to_string(),
+            found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+            context: Some("string_equals_ignore_case() function".to_string()),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// Compare two strings, returning -1, 0 or 1; made to be passed to
+/// `array.sort_by`. Options: `numeric` compares runs of digits by their
+/// value, so `file2` sorts before `file10`; `case_insensitive` compares
+/// case-folded text. Strings that compare equal under the options but differ
+/// are ordered by their text, so 0 means the compared text is identical.
+#[allow(dead_code)]
+pub fn compare(args: &[Value]) -> Result<Value> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("string_compare".to_string()),
+            line: None,
+        });
+    }
+
+    let (a, b) = match (&args[0], &args[1]) {
+        (Value::String(a), Value::String(b)) => (a, b),
+        _ => {
+            return Err(InfraError::TypeError {
+                expected: "two strings".to_string(),
+                found: format!("{} and {}", args[0].type_name(), args[1].type_name()),
+                context: Some("string_compare() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+    let options = match args.get(2) {
+        Some(options) => CompareOptions::from_value(options)?,
+        None => CompareOptions::default(),
+    };
+
+    let ordering = if options.case_insensitive {
+        compare_text(&fold(a), &fold(b), options.numeric)
+    } else {
+        compare_text(a, b, options.numeric)
+    };
+    Ok(Value::Number(match ordering {
+        Ordering::Less => -1.0,
+        Ordering::Equal => 0.0,
+        Ordering::Greater => 1.0,
+    }))
+}
+
+#[derive(Default)]
+struct CompareOptions {
+    numeric: bool,
+    case_insensitive: bool,
+}
+
+impl CompareOptions {
+    fn from_value(value: &Value) -> Result<Self> {
+        let Some(fields) = value.as_object() else {
+            return Err(InfraError::TypeError {
+                expected: "object of options".to_string(),
+                found: value.type_name().to_string(),
+                context: Some("string_compare() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Pass options like {numeric: true, case_insensitive: true}".to_string()),
+            });
+        };
+
+        let mut options = CompareOptions::default();
+        for (key, value) in fields {
+            let slot = match key.as_str() {
+                "numeric" => &mut options.numeric,
+                "case_insensitive" => &mut options.case_insensitive,
+                _ => {
+                    return Err(InfraError::RuntimeError {
+                        message: format!(
+                        "Unknown string.compare option '{}'; expected numeric or case_insensitive",
+                        key
+                    ),
+                        line: None,
+                        column: None,
+                        stack_trace: vec![],
+                        source_code: None,
+                    })
+                }
+            };
+            *slot = match value {
+                Value::Boolean(flag) => *flag,
+                other => {
+                    return Err(InfraError::TypeError {
+                        expected: "boolean".to_string(),
+                        found: other.type_name().to_string(),
+                        context: Some(format!("string_compare() option '{}'", key)),
+                        line: None,
+                        column: None,
+                        hint: None,
+                    })
+                }
+            };
+        }
+        Ok(options)
+    }
+}
+
+/// Full case folding. Taking the uppercase and then the lowercase of each
+/// character expands `ß` and the ligatures, and maps variant forms such as
+/// `ς`, `ſ` and `µ` to their ordinary letter. The second round catches `ẞ`,
+/// whose lowercase `ß` still folds further. Dotless `ı` has no folding and is
+/// kept apart from `i`.
+fn fold(text: &str) -> String {
+    text.chars().flat_map(fold_char).collect()
+}
+
+fn fold_char(c: char) -> Vec<char> {
+    if c == 'ı' {
+        return vec![c];
+    }
+    let once: Vec<char> = c.to_uppercase().flat_map(char::to_lowercase).collect();
+    if once.len() == 1 && once[0] != c && once[0].to_uppercase().count() > 1 {
+        return once[0]
+            .to_uppercase()
+            .flat_map(char::to_lowercase)
+            .collect();
+    }
+    once
+}
+
+/// Order two strings by character, or with `numeric` by runs of ASCII digits
+/// compared as whole numbers, falling back to plain character order when
+/// they tie
+fn compare_text(a: &str, b: &str, numeric: bool) -> Ordering {
+    if !numeric {
+        return a.cmp(b);
+    }
+    natural_order(a, b).then_with(|| a.cmp(b))
+}
+
+fn natural_order(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (run_a, rest_a) = split_digits(a);
+            let (run_b, rest_b) = split_digits(b);
+            let ordering = compare_digit_runs(run_a, run_b);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (rest_a, rest_b);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// Compare digit runs by value without parsing them, so runs of any length
+/// compare exactly: ignoring leading zeros, a longer run is a larger number,
+/// and runs of equal length compare digit by digit
+fn compare_digit_runs(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
@@ -0,0 +1,579 @@
+//! Durations written the way people write them: `time.parse_duration("2h30m")`
+//! and `time.format_duration(ms)`.
+//!
+//! A duration is a number of milliseconds. Text is one or more segments of a
+//! number and a unit (`ms`, `s`, `m`, `h` or `d`), optionally separated by
+//! spaces; numbers may have a fraction (`1.5h`). A number on its own is
+//! milliseconds, and `H:MM:SS` (with an optional `.mmm`) is read as a clock
+//! reading, so both forms `format_duration` writes parse back.
+//!
+//! Moments are milliseconds since 1970-01-01 00:00 UTC, as `time.now()`
+//! reads them from the clock in [`sources`]. `time.format` and `time.parse`
+//! convert them to and from text with a pattern of `%Y` (four-digit year),
+//! `%m`, `%d`, `%H`, `%M`, `%S` (two digits each), `%L` (three-digit
+//! milliseconds) and `%%`; everything else in a pattern is literal text.
+//! Both work in UTC.
+
+use crate::core::{InfraError, ObjectMap, Result, Value};
+use crate::stdlib::sources;
+
+/// The largest whole number a number holds exactly
+pub(crate) const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// The pattern time.format and time.parse use when given none: ISO 8601
+const DEFAULT_PATTERN: &str = "%Y-%m-%dT%H:%M:%S.%LZ";
+
+/// Pattern directives in the order of [`Fields`], with their widths
+const DIRECTIVES: [(char, usize); 7] = [
+    ('Y', 4),
+    ('m', 2),
+    ('d', 2),
+    ('H', 2),
+    ('M', 2),
+    ('S', 2),
+    ('L', 3),
+];
+
+/// Year, month, day, hour, minute, second and millisecond
+type Fields = [i64; 7];
+
+const UNITS: [(&str, f64); 5] = [
+    ("d", 86_400_000.0),
+    ("h", 3_600_000.0),
+    ("m", 60_000.0),
+    ("s", 1000.0),
+    ("ms", 1.0),
+];
+
+/// time.parse_duration(text): the milliseconds in `text`, rounded to a whole
+/// millisecond
+#[allow(dead_code)]
+pub fn parse_duration(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("time.parse_duration".to_string()),
+            line: None,
+        });
+    }
+    let Value::String(text) = &args[0] else {
+        return Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("time.parse_duration() function".to_string()),
+            line: None,
+            column: None,
+            hint: Some("Use e.g. time.parse_duration(\"2h30m\")".to_string()),
+        });
+    };
+
+    let ms = if text.contains(':') {
+        parse_clock(text)?
+    } else {
+        parse_segments(text)?
+    };
+    if ms > MAX_SAFE_INTEGER {
+        return Err(invalid(
+            "duration",
+            text,
+            0..text.len(),
+            "too large to count in whole milliseconds",
+        ));
+    }
+    Ok(Value::Number(ms.round()))
+}
+
+/// time.format_duration(ms, options?): "2h 30m", or "2:30:00" with
+/// `{compact: false}`
+#[allow(dead_code)]
+pub fn format_duration(args: &[Value]) -> Result<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("time.format_duration".to_string()),
+            line: None,
+        });
+    }
+    let ms = match &args[0] {
+        Value::Number(ms) if *ms >= 0.0 && *ms <= MAX_SAFE_INTEGER => ms.round() as u64,
+        other => {
+            return Err(InfraError::TypeError {
+                expected: "non-negative number of milliseconds".to_string(),
+                found: other.to_string(),
+                context: Some("time.format_duration() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+    let compact = match args.get(1) {
+        None | Some(Value::Null) => true,
+        Some(Value::Object(options)) => {
+            bool_option(options, "compact", true, "time.format_duration")?
+        }
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "options object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("time.format_duration() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Use e.g. time.format_duration(ms, {compact: false})".to_string()),
+            })
+        }
+    };
+
+    let text = if compact {
+        let mut parts = Vec::new();
+        let mut rest = ms;
+        for (unit, size) in UNITS {
+            let size = size as u64;
+            if rest >= size {
+                parts.push(format!("{}{}", rest / size, unit));
+                rest %= size;
+            }
+        }
+        if parts.is_empty() {
+            "0s".to_string()
+        } else {
+            parts.join(" ")
+        }
+    } else {
+        let (hours, rest) = (ms / 3_600_000, ms % 3_600_000);
+        let (minutes, rest) = (rest / 60_000, rest % 60_000);
+        let (seconds, millis) = (rest / 1000, rest % 1000);
+        let mut text = format!("{}:{:02}:{:02}", hours, minutes, seconds);
+        if millis > 0 {
+            text.push_str(&format!(".{:03}", millis));
+        }
+        text
+    };
+    Ok(Value::String(text))
+}
+
+/// time.now(): milliseconds since 1970-01-01 00:00 UTC
+#[allow(dead_code)]
+pub fn now(args: &[Value]) -> Result<Value> {
+    check_count(args, 0, 0, "now")?;
+    Ok(Value::Number(sources::now_ms() as f64))
+}
+
+/// time.elapsed(start_ms): milliseconds since `start_ms`, an earlier
+/// time.now() reading
+#[allow(dead_code)]
+pub fn elapsed(args: &[Value]) -> Result<Value> {
+    check_count(args, 1, 1, "elapsed")?;
+    let start = moment(&args[0], "elapsed")?;
+    Ok(Value::Number(sources::now_ms() as f64 - start as f64))
+}
+
+/// time.format(ms, pattern?): the UTC date and time `ms` falls on, written
+/// out with `pattern`
+#[allow(dead_code)]
+pub fn format(args: &[Value]) -> Result<Value> {
+    check_count(args, 1, 2, "format")?;
+    let ms = moment(&args[0], "format")?;
+    let pattern = pattern_arg(args, "format")?;
+    let pieces = pieces(pattern)?;
+
+    let (year, month, day) = civil_from_days(ms.div_euclid(MS_PER_DAY));
+    let in_day = ms.rem_euclid(MS_PER_DAY);
+    let fields: Fields = [
+        year,
+        month,
+        day,
+        in_day / 3_600_000,
+        in_day / 60_000 % 60,
+        in_day / 1000 % 60,
+        in_day % 1000,
+    ];
+
+    let mut text = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Literal(c) => text.push(c),
+            Piece::Field(i) => {
+                text.push_str(&format!("{:0width$}", fields[i], width = DIRECTIVES[i].1))
+            }
+        }
+    }
+    Ok(Value::String(text))
+}
+
+/// time.parse(text, pattern?): the moment `text` names, read as UTC with
+/// `pattern`. Fields the pattern leaves out default to 1970-01-01 00:00.
+#[allow(dead_code)]
+pub fn parse(args: &[Value]) -> Result<Value> {
+    check_count(args, 1, 2, "parse")?;
+    let Value::String(text) = &args[0] else {
+        return Err(type_error("string", &args[0], "parse"));
+    };
+    let pattern = pattern_arg(args, "parse")?;
+    let pieces = pieces(pattern)?;
+
+    let mut fields: Fields = [1970, 1, 1, 0, 0, 0, 0];
+    let mut spans = [0..0, 0..0, 0..0, 0..0, 0..0, 0..0, 0..0];
+    let mut at = 0;
+    for piece in pieces {
+        match piece {
+            Piece::Literal(c) => {
+                if !text[at..].starts_with(c) {
+                    let problem = format!("expected '{}' here, as in \"{}\"", c, pattern);
+                    return Err(invalid("date", text, at..at + 1, &problem));
+                }
+                at += c.len_utf8();
+            }
+            Piece::Field(i) => {
+                let (directive, width) = DIRECTIVES[i];
+                let digits = text.get(at..at + width).unwrap_or(&text[at..]);
+                if digits.len() < width || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    let problem = format!("expected {} digits for %{}", width, directive);
+                    return Err(invalid("date", text, at..at + width, &problem));
+                }
+                fields[i] = digits.parse().unwrap_or(0);
+                spans[i] = at..at + width;
+                at += width;
+            }
+        }
+    }
+    if at < text.len() {
+        return Err(invalid(
+            "date",
+            text,
+            at..text.len(),
+            &format!("unexpected text after \"{}\"", pattern),
+        ));
+    }
+
+    let [year, month, day, hour, minute, second, millis] = fields;
+    let checks = [
+        (
+            1,
+            (1..=12).contains(&month),
+            "month must be 01 to 12".to_string(),
+        ),
+        (
+            2,
+            day >= 1 && day <= days_in_month(year, month),
+            format!("day must be 01 to {:02}", days_in_month(year, month)),
+        ),
+        (3, hour < 24, "hour must be 00 to 23".to_string()),
+        (4, minute < 60, "minute must be 00 to 59".to_string()),
+        (5, second < 60, "second must be 00 to 59".to_string()),
+    ];
+    for (i, ok, problem) in checks {
+        if !ok {
+            return Err(invalid("date", text, spans[i].clone(), &problem));
+        }
+    }
+
+    let days = days_from_civil(year, month, day);
+    let ms = days * MS_PER_DAY + hour * 3_600_000 + minute * 60_000 + second * 1000 + millis;
+    Ok(Value::Number(ms as f64))
+}
+
+/// `2h 30m`, `90s`, `1.5d`, or a bare number of milliseconds
+fn parse_segments(text: &str) -> Result<f64> {
+    let bytes = text.as_bytes();
+    let mut total = 0.0;
+    let mut segments = 0;
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == bytes.len() {
+            break;
+        }
+        let start = i;
+        if bytes[i] == b'-' {
+            return Err(invalid(
+                "duration",
+                text,
+                start..start + 1,
+                "negative durations are not allowed",
+            ));
+        }
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+            i += 1;
+        }
+        let number = &text[start..i];
+        let unit_start = i;
+        while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        let unit = &text[unit_start..i];
+
+        let Some(value) = parse_number(number) else {
+            let end = i.max(start + 1).min(text.len());
+            return Err(invalid("duration", text, start..end, "expected a number"));
+        };
+        if unit.is_empty() {
+            // Only a number standing alone means milliseconds; "1h30" is
+            // more likely a missing unit than 30ms
+            let alone = segments == 0 && text[i..].trim().is_empty();
+            if !alone {
+                return Err(invalid(
+                    "duration",
+                    text,
+                    start..i,
+                    "missing unit (ms, s, m, h or d)",
+                ));
+            }
+            total += value;
+        } else {
+            let Some((_, size)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+                return Err(invalid(
+                    "duration",
+                    text,
+                    start..i,
+                    &format!("unknown unit '{}' (use ms, s, m, h or d)", unit),
+                ));
+            };
+            total += value * size;
+        }
+        segments += 1;
+    }
+    if segments == 0 {
+        return Err(invalid("duration", text, 0..0, "empty duration"));
+    }
+    Ok(total)
+}
+
+/// `H:MM:SS` or `H:MM:SS.mmm`, as `format_duration` writes with
+/// `{compact: false}`
+fn parse_clock(text: &str) -> Result<f64> {
+    let trimmed = text.trim();
+    let offset = text.len() - text.trim_start().len();
+    if trimmed.starts_with('-') {
+        return Err(invalid(
+            "duration",
+            text,
+            offset..offset + 1,
+            "negative durations are not allowed",
+        ));
+    }
+    let fields: Vec<&str> = trimmed.split(':').collect();
+    if fields.len() != 3 {
+        return Err(invalid(
+            "duration",
+            text,
+            offset..offset + trimmed.len(),
+            "expected hours:minutes:seconds",
+        ));
+    }
+    let mut start = offset;
+    let mut total = 0.0;
+    for (i, field) in fields.iter().enumerate() {
+        let span = start..start + field.len().max(1);
+        let value = match parse_number(field) {
+            Some(value) if i == 2 || !field.contains('.') => value,
+            _ => return Err(invalid("duration", text, span, "expected a number")),
+        };
+        if i > 0 && value >= 60.0 {
+            return Err(invalid("duration", text, span, "must be less than 60"));
+        }
+        total = total * 60.0 + value;
+        start += field.len() + 1;
+    }
+    Ok(total * 1000.0)
+}
+
+/// Digits with at most one '.', and at least one digit
+pub(crate) fn parse_number(text: &str) -> Option<f64> {
+    let valid = text.chars().any(|c| c.is_ascii_digit())
+        && text.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && text.matches('.').count() <= 1;
+    if valid {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// `options[name]` as a boolean, or `default` when it is missing
+pub(crate) fn bool_option(
+    options: &ObjectMap,
+    name: &str,
+    default: bool,
+    function_name: &str,
+) -> Result<bool> {
+    match options.get(name) {
+        None | Some(Value::Null) => Ok(default),
+        Some(Value::Boolean(value)) => Ok(*value),
+        Some(other) => Err(InfraError::TypeError {
+            expected: "boolean".to_string(),
+            found: other.type_name().to_string(),
+            context: Some(format!("{}() option '{}'", function_name, name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+/// An error naming what is wrong with `text`, with the bytes in `span`
+/// underlined
+pub(crate) fn invalid(
+    what: &str,
+    text: &str,
+    span: std::ops::Range<usize>,
+    problem: &str,
+) -> InfraError {
+    let boundary = |mut at: usize| {
+        at = at.min(text.len());
+        while !text.is_char_boundary(at) {
+            at += 1;
+        }
+        at
+    };
+    let (start, end) = (boundary(span.start), boundary(span.end));
+    let before = text[..start].chars().count();
+    let width = text[start..end.max(start)].chars().count().max(1);
+    InfraError::RuntimeError {
+        message: format!(
+            "Invalid {} \"{}\": {}\n  {}\n  {}{}",
+            what,
+            text,
+            problem,
+            text,
+            " ".repeat(before),
+            "^".repeat(width)
+        ),
+        line: None,
+        column: None,
+        stack_trace: vec![],
+        source_code: None,
+    }
+}
+
+enum Piece {
+    Literal(char),
+    /// Index into [`DIRECTIVES`]
+    Field(usize),
+}
+
+/// `pattern` split into literal characters and directives
+fn pieces(pattern: &str) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.char_indices();
+    while let Some((at, c)) = chars.next() {
+        if c != '%' {
+            pieces.push(Piece::Literal(c));
+            continue;
+        }
+        match chars.next() {
+            Some((_, '%')) => pieces.push(Piece::Literal('%')),
+            Some((_, d)) => match DIRECTIVES.iter().position(|(directive, _)| *directive == d) {
+                Some(i) => pieces.push(Piece::Field(i)),
+                None => {
+                    return Err(invalid(
+                        "pattern",
+                        pattern,
+                        at..at + 1 + d.len_utf8(),
+                        "unknown directive; use %Y, %m, %d, %H, %M, %S, %L or %%",
+                    ))
+                }
+            },
+            None => {
+                return Err(invalid(
+                    "pattern",
+                    pattern,
+                    at..at + 1,
+                    "a lone '%' must be written %%",
+                ))
+            }
+        }
+    }
+    Ok(pieces)
+}
+
+/// The pattern in `args[1]`, or the ISO 8601 one when it is missing
+fn pattern_arg<'a>(args: &'a [Value], function_name: &str) -> Result<&'a str> {
+    match args.get(1) {
+        None | Some(Value::Null) => Ok(DEFAULT_PATTERN),
+        Some(Value::String(pattern)) => Ok(pattern),
+        Some(other) => Err(type_error("pattern string", other, function_name)),
+    }
+}
+
+/// A moment in whole milliseconds
+fn moment(value: &Value, function_name: &str) -> Result<i64> {
+    match value {
+        Value::Number(ms) if ms.abs() <= MAX_SAFE_INTEGER => Ok(ms.floor() as i64),
+        other => Err(type_error(
+            "milliseconds since 1970, as time.now() returns",
+            other,
+            function_name,
+        )),
+    }
+}
+
+/// Year, month and day of the day `days` after 1970-01-01, in the
+/// proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so February's leap day comes last
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days from 1970-01-01 to the given date; the inverse of [`civil_from_days`]
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn check_count(args: &[Value], min: usize, max: usize, function_name: &str) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: if args.len() < min { min } else { max },
+            found: args.len(),
+            function_name: Some(format!("time.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("time.{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
@@ -0,0 +1,329 @@
+//! Rate-limited function wrappers: `timing.debounce(fn, ms)` and
+//! `timing.throttle(fn, ms, options)`.
+//!
+//! Like memo.wrap, a wrapper is an ordinary function whose body forwards to
+//! `timing.__call`. Calls that have to wait are kept, with the time they
+//! come due, in the evaluator's `Wrappers`; the runner fires them from the
+//! same loop as scheduled jobs, on the scheduler's clock, so a script stays
+//! alive until they have run and tests on the virtual clock need no real
+//! waiting. Invoking the wrapped function calls back into script code, so
+//! the evaluator makes the calls these functions return.
+//!
+//! A call that comes due while the script is still busy runs late: at the
+//! next call to the same wrapper, or once the script has finished.
+
+use crate::core::{ast::*, InfraError, Result, Value};
+use std::rc::Rc;
+
+/// A call to make now: the wrapped function and its arguments
+pub type Invocation = (Value, Vec<Value>);
+
+enum Mode {
+    /// Call once `wait` ms have passed without another call
+    Debounce,
+    /// Call at most once per `wait` ms, at the start and/or end of the window
+    Throttle { leading: bool, trailing: bool },
+}
+
+/// State for one `timing.debounce` or `timing.throttle` call
+struct Wrapper {
+    function: Value,
+    wait: u64,
+    mode: Mode,
+    /// Arguments of the call waiting to be made
+    pending: Option<Vec<Value>>,
+    /// When the pending call is made, or for a throttle when its window ends
+    due: Option<u64>,
+}
+
+impl Wrapper {
+    /// What happens at `now` if the wrapper's time has come
+    fn expire(&mut self, now: u64) -> Option<Invocation> {
+        if self.due.is_none_or(|due| due > now) {
+            return None;
+        }
+        self.due = None;
+        let args = self.pending.take()?;
+        if matches!(self.mode, Mode::Throttle { .. }) {
+            // The trailing call opens the next window
+            self.due = Some(now + self.wait);
+        }
+        Some((self.function.clone(), args))
+    }
+
+    fn call(&mut self, args: Vec<Value>, now: u64) -> Vec<Invocation> {
+        let mut invocations: Vec<Invocation> = self.expire(now).into_iter().collect();
+        match self.mode {
+            Mode::Debounce => {
+                self.pending = Some(args);
+                self.due = Some(now + self.wait);
+            }
+            Mode::Throttle { leading, trailing } => {
+                if self.due.is_none() {
+                    self.due = Some(now + self.wait);
+                    if leading {
+                        invocations.push((self.function.clone(), args));
+                        return invocations;
+                    }
+                }
+                if trailing {
+                    self.pending = Some(args);
+                }
+            }
+        }
+        invocations
+    }
+}
+
+/// The debounce and throttle wrappers of one evaluator, by id
+#[derive(Default)]
+pub struct Wrappers {
+    wrappers: Vec<Wrapper>,
+}
+
+impl Wrappers {
+    /// When the next waiting call comes due, or a throttle window ends
+    pub fn next_due(&self) -> Option<u64> {
+        self.wrappers.iter().filter_map(|w| w.due).min()
+    }
+
+    /// Calls due at `now`, in the order they came due
+    pub fn take_due(&mut self, now: u64) -> Vec<Invocation> {
+        let mut due: Vec<(u64, usize)> = self
+            .wrappers
+            .iter()
+            .enumerate()
+            .filter_map(|(id, wrapper)| Some((wrapper.due?, id)))
+            .filter(|(due, _)| *due <= now)
+            .collect();
+        due.sort();
+        due.into_iter()
+            .filter_map(|(_, id)| self.wrappers[id].expire(now))
+            .collect()
+    }
+
+    /// A call made through a wrapper function: the calls to make right away.
+    /// A call that was due first comes before a leading throttle call.
+    pub fn call(&mut self, args: &[Value], now: u64) -> Result<Vec<Invocation>> {
+        let wrapper = match args.first() {
+            Some(Value::Number(n)) if *n >= 0.0 => self.wrappers.get_mut(*n as usize),
+            _ => None,
+        };
+        let Some(wrapper) = wrapper else {
+            return Err(InfraError::RuntimeError {
+                message: "timing.__call is internal to timing.debounce and timing.throttle"
+                    .to_string(),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            });
+        };
+        Ok(wrapper.call(args[1..].to_vec(), now))
+    }
+
+    /// Take the waiting call of a wrapper so it can be made now; a
+    /// throttle's window ends with it
+    pub fn take_flush(&mut self, args: &[Value]) -> Result<Option<Invocation>> {
+        check_args(args, 1, "flush")?;
+        let wrapper = self.wrapper(&args[0], "flush")?;
+        let Some(args) = wrapper.pending.take() else {
+            return Ok(None);
+        };
+        wrapper.due = None;
+        Ok(Some((wrapper.function.clone(), args)))
+    }
+
+    /// timing.debounce(fn, ms): delay calls to `fn` until `ms` milliseconds
+    /// pass without another call, then call it once with the latest
+    /// arguments
+    pub fn debounce(&mut self, args: &[Value]) -> Result<Value> {
+        check_args(args, 2, "debounce")?;
+        let wait = wait_ms(&args[1], "debounce")?;
+        self.wrap(&args[0], wait, Mode::Debounce, "debounce")
+    }
+
+    /// timing.throttle(fn, ms, options?): call `fn` at most once every `ms`
+    /// milliseconds. Options: `leading` (default true) calls at the start
+    /// of a window, `trailing` (default false) makes the last call of a
+    /// window when it ends.
+    pub fn throttle(&mut self, args: &[Value]) -> Result<Value> {
+        if args.len() < 2 || args.len() > 3 {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 3,
+                found: args.len(),
+                function_name: Some("timing.throttle".to_string()),
+                line: None,
+            });
+        }
+        let wait = wait_ms(&args[1], "throttle")?;
+        let mode = throttle_mode(args.get(2))?;
+        self.wrap(&args[0], wait, mode, "throttle")
+    }
+
+    /// timing.cancel(wrapper): drop the waiting call of a wrapper; returns
+    /// whether there was one
+    pub fn cancel(&mut self, args: &[Value]) -> Result<Value> {
+        check_args(args, 1, "cancel")?;
+        let wrapper = self.wrapper(&args[0], "cancel")?;
+        let had_pending = wrapper.pending.take().is_some();
+        wrapper.due = None;
+        Ok(Value::Boolean(had_pending))
+    }
+
+    fn wrap(
+        &mut self,
+        function: &Value,
+        wait: u64,
+        mode: Mode,
+        function_name: &str,
+    ) -> Result<Value> {
+        let Value::Function {
+            name,
+            params,
+            param_types,
+            optional_params,
+            ..
+        } = function
+        else {
+            return Err(InfraError::TypeError {
+                expected: "function".to_string(),
+                found: function.type_name().to_string(),
+                context: Some(format!("timing.{}() function", function_name)),
+                line: None,
+                column: None,
+                hint: None,
+            });
+        };
+
+        self.wrappers.push(Wrapper {
+            function: function.clone(),
+            wait,
+            mode,
+            pending: None,
+            due: None,
+        });
+        let id = self.wrappers.len() - 1;
+
+        // The wrapper is an ordinary function whose body forwards to timing.__call
+        let mut call_args = vec![Expr::Literal(Value::Number(id as f64))];
+        call_args.extend(params.iter().map(Expr::identifier));
+
+        Ok(Value::Function {
+            name: name.clone(),
+            params: params.clone(),
+            param_types: param_types.clone(),
+            optional_params: Rc::clone(optional_params),
+            // Calls return null, whatever the original returns
+            return_type: None,
+            body: Rc::new(Stmt::Return(Some(Expr::Call {
+                callee: Box::new(Expr::ModuleAccess {
+                    module: "timing".to_string(),
+                    function: "__call".to_string(),
+                }),
+                args: call_args,
+                span: None,
+            }))),
+            // Calls reach the original through the table, captures and all
+            captured: Default::default(),
+            is_async: false,
+        })
+    }
+
+    /// The wrapper behind a function returned by debounce or throttle
+    fn wrapper(&mut self, function: &Value, function_name: &str) -> Result<&mut Wrapper> {
+        if let Value::Function { body, .. } = function {
+            if let Stmt::Return(Some(Expr::Call { callee, args, .. })) = body.as_ref() {
+                if let (
+                    Expr::ModuleAccess { module, function },
+                    Some(Expr::Literal(Value::Number(id))),
+                ) = (callee.as_ref(), args.first())
+                {
+                    if module == "timing" && function == "__call" {
+                        if let Some(wrapper) = self.wrappers.get_mut(*id as usize) {
+                            return Ok(wrapper);
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(InfraError::TypeError {
+            expected: "function returned by timing.debounce or timing.throttle".to_string(),
+            found: function.type_name().to_string(),
+            context: Some(format!("timing.{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        })
+    }
+}
+
+/// The leading and trailing options of timing.throttle
+fn throttle_mode(options: Option<&Value>) -> Result<Mode> {
+    let mut leading = true;
+    let mut trailing = false;
+    if let Some(options) = options {
+        let options = options.as_object().ok_or_else(|| InfraError::TypeError {
+            expected: "options object".to_string(),
+            found: options.type_name().to_string(),
+            context: Some("timing.throttle() function".to_string()),
+            line: None,
+            column: None,
+            hint: Some("Use e.g. {leading: true, trailing: false}".to_string()),
+        })?;
+        for (name, flag) in [("leading", &mut leading), ("trailing", &mut trailing)] {
+            match options.get(name) {
+                Some(Value::Boolean(value)) => *flag = *value,
+                Some(Value::Null) | None => {}
+                Some(other) => {
+                    return Err(InfraError::TypeError {
+                        expected: "boolean".to_string(),
+                        found: other.type_name().to_string(),
+                        context: Some(format!("timing.throttle() option '{}'", name)),
+                        line: None,
+                        column: None,
+                        hint: None,
+                    })
+                }
+            }
+        }
+    }
+    if !leading && !trailing {
+        return Err(InfraError::RuntimeError {
+            message: "timing.throttle: at least one of leading and trailing must be true"
+                .to_string(),
+            line: None,
+            column: None,
+            stack_trace: vec![],
+            source_code: None,
+        });
+    }
+    Ok(Mode::Throttle { leading, trailing })
+}
+
+fn wait_ms(value: &Value, function_name: &str) -> Result<u64> {
+    match value {
+        Value::Number(ms) if *ms >= 0.0 && ms.is_finite() => Ok(*ms as u64),
+        other => Err(InfraError::TypeError {
+            expected: "non-negative number of milliseconds".to_string(),
+            found: other.to_string(),
+            context: Some(format!("timing.{}() function", function_name)),
+            line: None,
+            column: None,
+            hint: None,
+        }),
+    }
+}
+
+fn check_args(args: &[Value], count: usize, function_name: &str) -> Result<()> {
+    if args.len() != count {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: count,
+            found: args.len(),
+            function_name: Some(format!("timing.{}", function_name)),
+            line: None,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! Sizes in bytes written the way people write them:
+//! `units.parse_size("1.5GiB")` and `units.format_size(bytes)`.
+//!
+//! Binary units (`KiB`, `MiB`, ...) step by 1024 and decimal ones (`KB`,
+//! `MB`, ...) by 1000; unit names are not case sensitive and may follow the
+//! number after a space. A number on its own is bytes.
+
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::time::{bool_option, invalid, parse_number, MAX_SAFE_INTEGER};
+
+const PREFIXES: [&str; 5] = ["K", "M", "G", "T", "P"];
+
+/// units.parse_size(text): the bytes in `text`, rounded to a whole byte
+#[allow(dead_code)]
+pub fn parse_size(args: &[Value]) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("units.parse_size".to_string()),
+            line: None,
+        });
+    }
+    let Value::String(text) = &args[0] else {
+        return Err(InfraError::TypeError {
+            expected: "string".to_string(),
+            found: args[0].type_name().to_string(),
+            context: Some("units.parse_size() function".to_string()),
+            line: None,
+            column: None,
+            hint: Some("Use e.g. units.parse_size(\"1.5GiB\")".to_string()),
+        });
+    };
+
+    if text.trim().is_empty() {
+        return Err(invalid("size", text, 0..0, "empty size"));
+    }
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len();
+    if text[start..].starts_with('-') {
+        return Err(invalid(
+            "size",
+            text,
+            start..start + 1,
+            "negative sizes are not allowed",
+        ));
+    }
+    let number_end = text[start..end]
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or(end, |at| start + at);
+    let Some(number) = parse_number(&text[start..number_end]) else {
+        return Err(invalid("size", text, start..end, "expected a number"));
+    };
+    let unit_start =
+        number_end + (text[number_end..end].len() - text[number_end..end].trim_start().len());
+    let unit = &text[unit_start..end];
+    let Some(multiplier) = unit_size(unit) else {
+        return Err(invalid(
+            "size",
+            text,
+            unit_start..end,
+            &format!(
+                "unknown unit '{}' (use B, KB, MB, GB, TB, PB or KiB, MiB, GiB, TiB, PiB)",
+                unit
+            ),
+        ));
+    };
+
+    let bytes = number * multiplier;
+    if bytes > MAX_SAFE_INTEGER {
+        return Err(invalid(
+            "size",
+            text,
+            start..end,
+            "too large to count in whole bytes",
+        ));
+    }
+    Ok(Value::Number(bytes.round()))
+}
+
+/// units.format_size(bytes, options?): "1.5 GB", or "1.4 GiB" with
+/// `{binary: true}`; `precision` is the most decimal places shown
+#[allow(dead_code)]
+pub fn format_size(args: &[Value]) -> Result<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 2,
+            found: args.len(),
+            function_name: Some("units.format_size".to_string()),
+            line: None,
+        });
+    }
+    let bytes = match &args[0] {
+        Value::Number(bytes) if *bytes >= 0.0 && *bytes <= MAX_SAFE_INTEGER => bytes.round(),
+        other => {
+            return Err(InfraError::TypeError {
+                expected: "non-negative number of bytes".to_string(),
+                found: other.to_string(),
+                context: Some("units.format_size() function".to_string()),
+                line: None,
+                column: None,
+                hint: None,
+            })
+        }
+    };
+    let (binary, precision) = match args.get(1) {
+        None | Some(Value::Null) => (false, 1),
+        Some(Value::Object(options)) => {
+            let binary = bool_option(options, "binary", false, "units.format_size")?;
+            let precision = match options.get("precision") {
+                None | Some(Value::Null) => 1,
+                Some(Value::Number(n)) if (0.0..=10.0).contains(n) && n.fract() == 0.0 => {
+                    *n as usize
+                }
+                Some(other) => {
+                    return Err(InfraError::TypeError {
+                        expected: "integer between 0 and 10".to_string(),
+                        found: other.to_string(),
+                        context: Some("units.format_size() option 'precision'".to_string()),
+                        line: None,
+                        column: None,
+                        hint: None,
+                    })
+                }
+            };
+            (binary, precision)
+        }
+        Some(other) => {
+            return Err(InfraError::TypeError {
+                expected: "options object".to_string(),
+                found: other.type_name().to_string(),
+                context: Some("units.format_size() function".to_string()),
+                line: None,
+                column: None,
+                hint: Some("Use e.g. units.format_size(bytes, {binary: true})".to_string()),
+            })
+        }
+    };
+
+    let base: f64 = if binary { 1024.0 } else { 1000.0 };
+    if bytes < base {
+        return Ok(Value::String(format!("{} B", bytes)));
+    }
+    let mut power = 0;
+    while power + 1 < PREFIXES.len() && bytes >= base.powi(power as i32 + 2) {
+        power += 1;
+    }
+    let mut shown = round_to(bytes / base.powi(power as i32 + 1), precision);
+    // 1023.96 KiB rounds to "1024 KiB", which is better said as "1 MiB"
+    if shown >= base && power + 1 < PREFIXES.len() {
+        power += 1;
+        shown = round_to(bytes / base.powi(power as i32 + 1), precision);
+    }
+    let mut number = format!("{:.*}", precision, shown);
+    if number.contains('.') {
+        number.truncate(number.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    let unit = format!("{}{}B", PREFIXES[power], if binary { "i" } else { "" });
+    Ok(Value::String(format!("{} {}", number, unit)))
+}
+
+/// Bytes in one `unit`; an empty unit is bytes
+fn unit_size(unit: &str) -> Option<f64> {
+    let upper = unit.to_ascii_uppercase();
+    if upper.is_empty() || upper == "B" {
+        return Some(1.0);
+    }
+    let (prefix, base) = if let Some(prefix) = upper.strip_suffix("IB") {
+        (prefix, 1024.0_f64)
+    } else {
+        (upper.strip_suffix('B')?, 1000.0)
+    };
+    let power = PREFIXES.iter().position(|p| *p == prefix)?;
+    Some(base.powi(power as i32 + 1))
+}
+
+fn round_to(n: f64, places: usize) -> f64 {
+    let scale = 10f64.powi(places as i32);
+    (n * scale).round() / scale
+}
@@ -0,0 +1,271 @@
+//! Experimental worker processes: `worker.spawn` runs a script in a child
+//! infra process and the two sides exchange values with `worker.send` and
+//! `worker.recv`.
+//!
+//! Messages travel as lines over the child's stdin and stdout: a record
+//! separator (0x1E), the base64 of the wire encoding, and a newline. Any other
+//! line the child prints is passed through to the parent's stdout, so `print`
+//! keeps working inside workers.
+
+use crate::core::{InfraError, Result, Value};
+use crate::stdlib::{capability, ipc, record};
+use crate::utils::base64;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const FRAME_START: char = '\u{1e}';
+
+struct WorkerProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+thread_local! {
+    static WORKERS: RefCell<HashMap<usize, WorkerProcess>> = RefCell::new(HashMap::new());
+    static NEXT_WORKER_ID: Cell<usize> = const { Cell::new(1) };
+}
+
+/// Start a script in a child process and return its worker id
+#[allow(dead_code)]
+pub fn spawn(args: &[Value]) -> Result<Value> {
+    let script = match args {
+        [Value::String(script)] => script,
+        [other] => return Err(type_error("string", other, "worker.spawn")),
+        _ => {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 1,
+                found: args.len(),
+                function_name: Some("worker.spawn".to_string()),
+                line: None,
+            })
+        }
+    };
+
+    let io_error = |message: String| InfraError::IoError {
+        message,
+        operation: Some("worker.spawn".to_string()),
+        path: Some(script.clone()),
+    };
+
+    let id = NEXT_WORKER_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+
+    // The child runs in the parent's mode: a dry run stays dry, and a
+    // recorded or replayed session covers the worker's calls too
+    let mut command = Command::new(
+        std::env::current_exe()
+            .map_err(|err| io_error(format!("Cannot locate the infra executable: {}", err)))?,
+    );
+    if capability::is_dry_run() {
+        command.arg("--dry-run");
+    }
+    let mut child = command
+        .args(record::child_flags(&format!("worker{}", id)))
+        .arg(script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| io_error(format!("Failed to start worker: {}", err)))?;
+
+    let stdin = child.stdin.take();
+    let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    WORKERS.with(|workers| {
+        workers.borrow_mut().insert(
+            id,
+            WorkerProcess {
+                child,
+                stdin,
+                stdout,
+            },
+        )
+    });
+
+    Ok(Value::Number(id as f64))
+}
+
+/// `worker.send(id, value)` sends to a worker; inside a worker,
+/// `worker.send(value)` sends to the parent
+#[allow(dead_code)]
+pub fn send(args: &[Value]) -> Result<Value> {
+    match args {
+        [value] => {
+            let frame = encode_frame(value, "worker.send")?;
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(frame.as_bytes())
+                .and_then(|_| stdout.flush())
+                .map_err(|err| pipe_error("worker.send", err))?;
+        }
+        [id, value] => {
+            let frame = encode_frame(value, "worker.send")?;
+            with_worker(id, "worker.send", |worker| {
+                let stdin = worker
+                    .stdin
+                    .as_mut()
+                    .ok_or_else(|| closed_error("worker.send"))?;
+                stdin
+                    .write_all(frame.as_bytes())
+                    .and_then(|_| stdin.flush())
+                    .map_err(|err| pipe_error("worker.send", err))
+            })?;
+        }
+        _ => {
+            return Err(InfraError::ArgumentCountMismatch {
+                expected: 2,
+                found: args.len(),
+                function_name: Some("worker.send".to_string()),
+                line: None,
+            })
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+/// `worker.recv(id)` waits for a message from a worker; inside a worker,
+/// `worker.recv()` waits for one from the parent
+#[allow(dead_code)]
+pub fn recv(args: &[Value]) -> Result<Value> {
+    match args {
+        [] => read_frame(&mut io::stdin().lock(), false),
+        [id] => with_worker(id, "worker.recv", |worker| {
+            read_frame(&mut worker.stdout, true)
+        }),
+        _ => Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("worker.recv".to_string()),
+            line: None,
+        }),
+    }
+}
+
+/// Close a worker's input and wait for it to exit, returning its exit code
+#[allow(dead_code)]
+pub fn wait(args: &[Value]) -> Result<Value> {
+    let [id] = args else {
+        return Err(InfraError::ArgumentCountMismatch {
+            expected: 1,
+            found: args.len(),
+            function_name: Some("worker.wait".to_string()),
+            line: None,
+        });
+    };
+
+    let key = worker_id(id)?;
+    let status = with_worker(id, "worker.wait", |worker| {
+        worker.stdin = None;
+        // Forward anything the worker still prints
+        let mut line = String::new();
+        while worker.stdout.read_line(&mut line).unwrap_or(0) > 0 {
+            print!("{}", line);
+            line.clear();
+        }
+        worker
+            .child
+            .wait()
+            .map_err(|err| pipe_error("worker.wait", err))
+    })?;
+
+    WORKERS.with(|workers| workers.borrow_mut().remove(&key));
+    Ok(Value::Number(status.code().unwrap_or(-1) as f64))
+}
+
+fn encode_frame(value: &Value, function_name: &str) -> Result<String> {
+    let bytes = ipc::to_bytes(value, function_name)?;
+    Ok(format!("{}{}\n", FRAME_START, base64::encode(&bytes)))
+}
+
+/// Read lines until a message arrives; other lines are printed when
+/// `forward_output` is set (the parent reading a worker's stdout)
+fn read_frame(reader: &mut impl BufRead, forward_output: bool) -> Result<Value> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|err| pipe_error("worker.recv", err))?;
+        if read == 0 {
+            return Err(closed_error("worker.recv"));
+        }
+
+        match line.strip_prefix(FRAME_START) {
+            Some(frame) => {
+                let bytes = base64::decode(frame.trim_end()).map_err(|message| {
+                    InfraError::RuntimeError {
+                        message: format!("worker.recv: corrupt message: {}", message),
+                        line: None,
+                        column: None,
+                        stack_trace: vec![],
+                        source_code: None,
+                    }
+                })?;
+                return ipc::from_bytes(&bytes, "worker.recv");
+            }
+            None if forward_output => print!("{}", line),
+            None => {}
+        }
+    }
+}
+
+fn worker_id(id: &Value) -> Result<usize> {
+    match id {
+        Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => Ok(*n as usize),
+        other => Err(type_error("worker id", other, "worker")),
+    }
+}
+
+fn with_worker<T>(
+    id: &Value,
+    function_name: &str,
+    f: impl FnOnce(&mut WorkerProcess) -> Result<T>,
+) -> Result<T> {
+    let id = worker_id(id)?;
+    WORKERS.with(|workers| {
+        let mut workers = workers.borrow_mut();
+        let worker = workers
+            .get_mut(&id)
+            .ok_or_else(|| InfraError::RuntimeError {
+                message: format!("{}: no running worker with id {}", function_name, id),
+                line: None,
+                column: None,
+                stack_trace: vec![],
+                source_code: None,
+            })?;
+        f(worker)
+    })
+}
+
+fn type_error(expected: &str, found: &Value, function_name: &str) -> InfraError {
+    InfraError::TypeError {
+        expected: expected.to_string(),
+        found: found.type_name().to_string(),
+        context: Some(format!("{}() function", function_name)),
+        line: None,
+        column: None,
+        hint: None,
+    }
+}
+
+fn pipe_error(operation: &str, err: io::Error) -> InfraError {
+    InfraError::IoError {
+        message: err.to_string(),
+        operation: Some(operation.to_string()),
+        path: None,
+    }
+}
+
+fn closed_error(operation: &str) -> InfraError {
+    InfraError::IoError {
+        message: "the other side closed the connection".to_string(),
+        operation: Some(operation.to_string()),
+        path: None,
+    }
+}
@@ -1,10 +1,11 @@
 use crate::core::InfraError;
-use std::io::Write;
+use std::io::{self, IsTerminal, Write};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 pub struct ErrorReporter {
     had_error: bool,
     colored: bool,
+    source: Option<String>,
 }
 
 impl ErrorReporter {
@@ -12,6 +13,7 @@ impl ErrorReporter {
         Self {
             had_error: false,
             colored: true,
+            source: None,
         }
     }
 
@@ -19,18 +21,30 @@ impl ErrorReporter {
         Self {
             had_error: false,
             colored: false,
+            source: None,
         }
     }
 
+    /// Show the lines around errors from `source` when an error does not
+    /// carry its own copy of the code it happened in
+    pub fn with_source(mut self, source: Option<&str>) -> Self {
+        self.source = source.map(str::to_string);
+        self
+    }
+
     pub fn report_error(&mut self, error: &InfraError) {
         self.had_error = true;
 
-        let mut stderr = StandardStream::stderr(if self.colored {
+        // termcolor drives the Windows console API on terminals without ANSI
+        // support; redirected output gets no color at all
+        let mut stderr = StandardStream::stderr(if self.colored && io::stderr().is_terminal() {
             ColorChoice::Auto
         } else {
             ColorChoice::Never
         });
 
+        let source = error.source_code().or(self.source.as_deref());
+
         match error {
             InfraError::LexError {
                 message,
@@ -40,6 +54,7 @@ impl ErrorReporter {
             } => {
                 self.report_at(
                     &mut stderr,
+                    source,
                     *line,
                     *column,
                     "Lexical Error",
@@ -56,6 +71,7 @@ impl ErrorReporter {
             } => {
                 self.report_at(
                     &mut stderr,
+                    source,
                     *line,
                     *column,
                     "Parse Error",
@@ -74,7 +90,15 @@ impl ErrorReporter {
                 source_code: _,
             } => {
                 if let (Some(l), Some(c)) = (line, column) {
-                    self.report_at(&mut stderr, *l, *c, "Runtime Error", message, Color::Red);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        *c,
+                        "Runtime Error",
+                        message,
+                        Color::Red,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Runtime Error", message, Color::Red);
                 }
@@ -98,7 +122,15 @@ impl ErrorReporter {
                 };
 
                 if let (Some(l), Some(c)) = (line, column) {
-                    self.report_at(&mut stderr, *l, *c, "Type Error", &message, Color::Magenta);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        *c,
+                        "Type Error",
+                        &message,
+                        Color::Magenta,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Type Error", &message, Color::Magenta);
                 }
@@ -111,6 +143,7 @@ impl ErrorReporter {
                 if let (Some(l), Some(c)) = (line, column) {
                     self.report_at(
                         &mut stderr,
+                        source,
                         *l,
                         *c,
                         "Runtime Error",
@@ -130,6 +163,7 @@ impl ErrorReporter {
                 if let (Some(l), Some(c)) = (line, column) {
                     self.report_at(
                         &mut stderr,
+                        source,
                         *l,
                         *c,
                         "Runtime Error",
@@ -154,10 +188,12 @@ impl ErrorReporter {
                 line,
                 column,
                 suggestion,
+                available_functions,
             } => {
                 if let (Some(l), Some(c)) = (line, column) {
                     self.report_at(
                         &mut stderr,
+                        source,
                         *l,
                         *c,
                         "Runtime Error",
@@ -176,6 +212,9 @@ impl ErrorReporter {
                 if let Some(sugg) = suggestion {
                     self.print_suggestion(&mut stderr, sugg);
                 }
+                if let Some(names) = available_functions {
+                    self.print_available(&mut stderr, "functions", names);
+                }
             }
             InfraError::ArgumentCountMismatch {
                 expected,
@@ -193,7 +232,15 @@ impl ErrorReporter {
                 };
 
                 if let Some(l) = line {
-                    self.report_at(&mut stderr, *l, 0, "Runtime Error", &message, Color::Red);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        0,
+                        "Runtime Error",
+                        &message,
+                        Color::Red,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Runtime Error", &message, Color::Red);
                 }
@@ -217,7 +264,15 @@ impl ErrorReporter {
                 };
 
                 if let Some(l) = line {
-                    self.report_at(&mut stderr, *l, 0, "Runtime Error", &message, Color::Red);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        0,
+                        "Runtime Error",
+                        &message,
+                        Color::Red,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Runtime Error", &message, Color::Red);
                 }
@@ -235,13 +290,21 @@ impl ErrorReporter {
                 };
 
                 if let Some(l) = line {
-                    self.report_at(&mut stderr, *l, 0, "Runtime Error", &message, Color::Red);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        0,
+                        "Runtime Error",
+                        &message,
+                        Color::Red,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Runtime Error", &message, Color::Red);
                 }
 
                 if let Some(props) = available_properties {
-                    self.print_available_properties(&mut stderr, props);
+                    self.print_available(&mut stderr, "properties", props);
                 }
             }
             InfraError::ReturnValue(value) => {
@@ -262,6 +325,9 @@ impl ErrorReporter {
                     );
                 }
             }
+            InfraError::Break { .. } | InfraError::Continue { .. } => {
+                self.print_error(&mut stderr, "Runtime Error", &error.to_string(), Color::Red);
+            }
             InfraError::IoError {
                 message,
                 operation,
@@ -282,11 +348,12 @@ impl ErrorReporter {
                 exception_type,
                 line,
                 stack_trace,
+                payload: _,
             } => {
                 let error_type = exception_type.as_deref().unwrap_or("Exception");
 
                 if let Some(l) = line {
-                    self.report_at(&mut stderr, *l, 0, error_type, message, Color::Red);
+                    self.report_at(&mut stderr, source, *l, 0, error_type, message, Color::Red);
                 } else {
                     self.print_error(&mut stderr, error_type, message, Color::Red);
                 }
@@ -330,7 +397,15 @@ impl ErrorReporter {
                 };
 
                 if let Some(l) = line {
-                    self.report_at(&mut stderr, *l, 0, "Class Error", &error_msg, Color::Red);
+                    self.report_at(
+                        &mut stderr,
+                        source,
+                        *l,
+                        0,
+                        "Class Error",
+                        &error_msg,
+                        Color::Red,
+                    );
                 } else {
                     self.print_error(&mut stderr, "Class Error", &error_msg, Color::Red);
                 }
@@ -344,9 +419,26 @@ impl ErrorReporter {
 
                 self.print_error(&mut stderr, "Memory Error", &error_msg, Color::Red);
             }
+            InfraError::TimeoutError {
+                operation,
+                timeout_ms,
+            } => {
+                let error_msg = if let Some(op) = operation {
+                    format!("{} did not finish within {} ms", op, timeout_ms)
+                } else {
+                    format!("deadline of {} ms passed", timeout_ms)
+                };
+
+                self.print_error(&mut stderr, "Timeout Error", &error_msg, Color::Red);
+            }
             InfraError::Generic(message) => {
                 self.print_error(&mut stderr, "Error", message, Color::Red);
             }
+            // The error as it happened, then the calls it escaped
+            InfraError::Traced { error, stack_trace } => {
+                self.report_error(error);
+                self.print_stack_trace(&mut stderr, stack_trace);
+            }
         }
 
         stderr.flush().unwrap();
@@ -360,9 +452,11 @@ impl ErrorReporter {
         self.had_error = false;
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn report_at(
         &self,
         writer: &mut StandardStream,
+        source: Option<&str>,
         line: usize,
         column: usize,
         error_type: &str,
@@ -380,6 +474,10 @@ impl ErrorReporter {
         } else {
             writeln!(writer, " [line {}]: {}", line, message).unwrap();
         }
+
+        if let Some(source) = source {
+            write_snippet(writer, source, line, column, color).unwrap();
+        }
     }
 
     fn print_error(
@@ -413,16 +511,11 @@ impl ErrorReporter {
         writer.reset().unwrap();
     }
 
-    fn print_available_properties(&self, writer: &mut StandardStream, properties: &[String]) {
+    fn print_available(&self, writer: &mut StandardStream, kind: &str, names: &[String]) {
         writer
             .set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))
             .unwrap();
-        writeln!(
-            writer,
-            "  📋 Available properties: {}",
-            properties.join(", ")
-        )
-        .unwrap();
+        writeln!(writer, "  📋 Available {}: {}", kind, names.join(", ")).unwrap();
         writer.reset().unwrap();
     }
 
@@ -446,3 +539,54 @@ impl Default for ErrorReporter {
         Self::new()
     }
 }
+
+/// The line `line` of `source` with one line of context either side and a
+/// caret under `column`, rustc style. A column of 0 gets no caret, one past
+/// the end of the line points just after it, and a line that is not in
+/// `source` writes nothing.
+pub(crate) fn write_snippet(
+    writer: &mut impl WriteColor,
+    source: &str,
+    line: usize,
+    column: usize,
+    color: Color,
+) -> io::Result<()> {
+    let lines: Vec<&str> = source.lines().collect();
+    if line == 0 || line > lines.len() {
+        return Ok(());
+    }
+    let first = (line - 1).max(1);
+    let last = (line + 1).min(lines.len());
+    let width = last.to_string().len();
+
+    for number in first..=last {
+        let text = lines[number - 1].trim_end();
+        write_gutter(writer, &number.to_string(), width)?;
+        if text.is_empty() {
+            writeln!(writer)?;
+        } else {
+            writeln!(writer, " {}", text)?;
+        }
+
+        if number == line && column > 0 {
+            // Tabs are kept so the caret lines up however wide they are shown
+            let indent: String = text
+                .chars()
+                .take(column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            write_gutter(writer, "", width)?;
+            write!(writer, " {}", indent)?;
+            writer.set_color(ColorSpec::new().set_bold(true).set_fg(Some(color)))?;
+            writeln!(writer, "^")?;
+            writer.reset()?;
+        }
+    }
+    Ok(())
+}
+
+fn write_gutter(writer: &mut impl WriteColor, number: &str, width: usize) -> io::Result<()> {
+    writer.set_color(ColorSpec::new().set_dimmed(true))?;
+    write!(writer, "  {:>width$} |", number, width = width)?;
+    writer.reset()
+}
@@ -0,0 +1,236 @@
+use crate::core::ObjectMap;
+use crate::core::Value;
+
+/// Deeper nesting is rejected so hostile input cannot overflow the stack
+const MAX_DEPTH: usize = 512;
+
+/// Parse a JSON document into a Value
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = JsonParser {
+        chars: input.chars().collect(),
+        pos: 0,
+        depth: 0,
+    };
+
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("Unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Arrays and objects open around the current position
+    depth: usize,
+}
+
+impl JsonParser {
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.literal("true", Value::Boolean(true)),
+            Some('f') => self.literal("false", Value::Boolean(false)),
+            Some('n') => self.literal("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(self.error(&format!("Unexpected character '{}'", c))),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    /// Parse an array or object one level deeper than the current position
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!(
+                "Values are nested more than {} levels deep",
+                MAX_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1; // consume '{'
+        let mut obj = ObjectMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(obj));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected string key"));
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            obj.insert(key, value);
+
+            self.skip_whitespace();
+            // Errors point at the character found, not the one after it
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(obj));
+                }
+                _ => return Err(self.error("Expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1; // consume '['
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            // Errors point at the character found, not the one after it
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1; // consume opening quote
+        let mut result = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => result.push(self.unicode_escape()?),
+                    _ => return Err(self.error("Invalid escape sequence")),
+                },
+                Some(c) => result.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if (0xD800..0xDC00).contains(&high) {
+            // Surrogate pair
+            if self.next() != Some('\\') || self.next() != Some('u') {
+                return Err(self.error("Expected low surrogate"));
+            }
+            let low = self.hex4()?;
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+            return char::from_u32(code).ok_or_else(|| self.error("Invalid surrogate pair"));
+        }
+        char::from_u32(high).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("Invalid number '{}'", text)))
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error(&format!("Expected '{}'", word)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    /// `message` placed at the current position by line, column and byte
+    /// offset, so it can be found in the document whichever one tools count
+    fn error(&self, message: &str) -> String {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = 1 + before.iter().filter(|&&c| c == '\n').count();
+        let column = 1 + before.iter().rev().take_while(|&&c| c != '\n').count();
+        let byte: usize = before.iter().map(|c| c.len_utf8()).sum();
+        format!(
+            "{} at line {}, column {} (byte {})",
+            message, line, column, byte
+        )
+    }
+}
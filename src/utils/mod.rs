@@ -1,4 +1,7 @@
+pub mod base64;
 pub mod error_reporter;
+pub mod json;
+pub mod platform;
 pub mod version;
 
 pub use error_reporter::*;
@@ -0,0 +1,52 @@
+//! Platform differences that the CLI has to paper over.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Build a command that starts `program` the way a shell would.
+///
+/// Windows only starts `.exe` files directly; batch scripts (`code.cmd`,
+/// `build.bat`) have to go through `cmd.exe`, so those are wrapped in
+/// `cmd /C`.
+pub fn command(program: &str) -> Command {
+    if cfg!(windows) {
+        let search_dirs: Vec<PathBuf> = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect())
+            .unwrap_or_default();
+
+        if is_batch_script(program, &search_dirs) {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(program);
+            return command;
+        }
+    }
+
+    Command::new(program)
+}
+
+/// Check whether `program` refers to a batch script, either by its extension
+/// or because the first match on the search path is a `.cmd` or `.bat` file
+pub fn is_batch_script(program: &str, search_dirs: &[PathBuf]) -> bool {
+    let path = Path::new(program);
+    if let Some(ext) = path.extension() {
+        let ext = ext.to_string_lossy().to_ascii_lowercase();
+        return ext == "cmd" || ext == "bat";
+    }
+
+    let dirs: Vec<PathBuf> = if path.components().count() > 1 {
+        // A path with directories is not looked up on the search path
+        vec![PathBuf::new()]
+    } else {
+        search_dirs.to_vec()
+    };
+
+    for dir in dirs {
+        for ext in ["exe", "com", "cmd", "bat"] {
+            if dir.join(path).with_extension(ext).is_file() {
+                return ext == "cmd" || ext == "bat";
+            }
+        }
+    }
+
+    false
+}
@@ -0,0 +1,1148 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn infra() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_infra"))
+}
+
+/// Run `command` with `input` piped to its stdin
+fn with_stdin(command: &mut Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn eval_flag_prints_expression_value() {
+    let output = infra().args(["-e", "(2 + 3) * 4"]).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "20\n");
+}
+
+#[test]
+fn code_flag_executes_statements() {
+    let output = infra()
+        .args(["-c", "let name = \"infra\"\nprint(name)"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "infra\n");
+}
+
+#[test]
+fn for_in_loops_over_items_keys_and_characters() {
+    let code = "let item = \"outer\"\n\
+                for item in [3, 1, 2]:\n    print(item)\n\
+                for key in {b: 1, a: 2}:\n    print(key)\n\
+                for c in \"hé!\":\n{\n    if c == \"!\":\n        break\n    print(c)\n}\n\
+                print(item)\n";
+    let output = infra().args(["-c", code]).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3\n1\n2\nb\na\nh\né\nouter\n"
+    );
+
+    let output = infra()
+        .args(["-c", "for n in 42:\n    print(n)"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("expected array, tuple, object or string, found number (in for loop iterable)"));
+}
+
+#[test]
+fn code_flag_reports_errors_with_exit_code() {
+    let output = infra().args(["-c", "print(missing)"]).output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn runtime_errors_point_at_the_failing_expression() {
+    let stderr = |code: &str| {
+        let output = infra().args(["-c", code]).output().unwrap();
+        assert!(!output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    assert_eq!(
+        stderr("let a = 1\n\nlet b = a / 0\n"),
+        "Runtime Error [line 3, column 11]: Division by zero\n  2 |\n  3 | let b = a / 0\n    |           ^\n"
+    );
+    // Inside a function, the body's line rather than the call's
+    assert!(
+        stderr("function f(x): {\n    return x + missing\n}\nprint(f(1))\n").starts_with(
+            "Runtime Error [line 2, column 16]: Undefined variable 'missing'\n  1 | function f(x): {\n  2 |     return x + missing\n"
+        )
+    );
+    assert!(stderr("let s = \"a\" - 2").starts_with("Type Error [line 1, column 13]:"));
+    // Native functions fail where they are called
+    assert!(
+        stderr("let n = 1\nlet s = math.sqrt(\"x\")").starts_with("Type Error [line 2, column 9]:")
+    );
+    assert!(stderr("let t = [1]\nprint(t[4])\n")
+        .starts_with("Runtime Error [line 2]: Array index 4 out of bounds"));
+}
+
+#[test]
+fn statement_errors_point_at_the_statement() {
+    let stderr = |code: &str| {
+        let output = infra().args(["-c", code]).output().unwrap();
+        assert!(!output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    // Assignments are placed where their target is, as reading it would be
+    assert_eq!(
+        stderr("let a = 1\nzz = 3\n"),
+        "Runtime Error [line 2, column 1]: Undefined variable 'zz'\n  1 | let a = 1\n  2 | zz = 3\n    | ^\n"
+    );
+    assert_eq!(
+        stderr("let x = [1]\nx[5] = 3\n"),
+        "Runtime Error [line 2, column 2]: Cannot assign to x[5]: index 5 is out of bounds for x (length 1)\n  1 | let x = [1]\n  2 | x[5] = 3\n    |  ^\n"
+    );
+    assert_eq!(
+        stderr("let o = {a: 1}\no.a.b = 2\n"),
+        "Runtime Error [line 2, column 4]: Cannot assign to o.a.b: o.a is a number, not an object\n  1 | let o = {a: 1}\n  2 | o.a.b = 2\n    |    ^\n"
+    );
+    // A loop over something that is not a collection points at it
+    assert!(stderr("for v in 5:\n    print(v)\n").starts_with(
+        "Type Error [line 1, column 10]: expected array, tuple, object or string, found number (in for loop iterable)\n  1 | for v in 5:\n    |          ^\n"
+    ));
+    // Destructuring points at the pattern that does not fit
+    assert!(stderr("let p = 0\nlet (a, b) = (1, 2, 3)\n").starts_with(
+        "Type Error [line 2, column 5]: expected tuple of 2 values, found tuple of 3 values (in destructuring let (a, b))\n  1 | let p = 0\n  2 | let (a, b) = (1, 2, 3)\n    |     ^\n"
+    ));
+    // A stray break or continue points at itself, even inside a function
+    assert_eq!(
+        stderr("let y = 1\nbreak\n"),
+        "Runtime Error [line 2, column 1]: 'break' outside a loop; it can only be used inside a while or for loop\n  1 | let y = 1\n  2 | break\n    | ^\n"
+    );
+    assert!(stderr("function f():\n    continue\nf()\n").starts_with(
+        "Runtime Error [line 2, column 5]: 'continue' outside a loop; it can only be used inside a while or for loop\n  1 | function f():\n  2 |     continue\n    |     ^\n  3 | f()\n"
+    ));
+}
+
+#[test]
+fn errors_in_files_show_the_failing_line() {
+    let dir = std::env::temp_dir().join(format!("infra-snippet-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("main.infra");
+    std::fs::write(
+        &script,
+        "let total = 0\nlet items = [1, 2]\ntotal = total + items.size\n",
+    )
+    .unwrap();
+
+    let output = infra().arg(&script).env("NO_COLOR", "1").output().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains('\x1b'));
+    assert!(stderr.contains(
+        "  2 | let items = [1, 2]\n  3 | total = total + items.size\n    |                      ^\n"
+    ));
+}
+
+#[test]
+fn scripts_see_their_arguments_and_environment() {
+    let fixture = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/args/echo.infra"
+    );
+
+    // Flags after `--` are the script's, not the interpreter's
+    let output = infra()
+        .args([fixture, "--", "input.txt", "--fast", "--opt"])
+        .env("INFRA_GREETING", "hi")
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[\"input.txt\",\"--fast\",\"--opt\"]\nhi\nbye\nnull\n"
+    );
+
+    let output = infra()
+        .arg(fixture)
+        .env_remove("INFRA_GREETING")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[]\nnull\nbye\nnull\n"
+    );
+}
+
+#[test]
+fn scripts_read_piped_stdin() {
+    let example = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/sum_stdin.infra");
+    let output = with_stdin(infra().arg(example), "1\n2.5\r\n\n4");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Read 3 numbers, total 7.5\n"
+    );
+
+    let code = "let name = io.input(\"Name? \")\n\
+                print(\"hi \" + name)\n\
+                print(json.stringify(io.read_all()))\n\
+                print(io.read_line())";
+    let output = with_stdin(infra().args(["-c", code]), "Ada\nrest\nof it\n");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Name? hi Ada\n\"rest\\nof it\\n\"\nnull\n"
+    );
+}
+
+#[test]
+fn repl_code_reads_the_lines_after_it() {
+    let output = with_stdin(
+        infra().arg("--repl"),
+        "let answer = io.read_line()\n42\nprint(answer)\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.ends_with("infra> infra> 42\ninfra> "), "{}", stdout);
+}
+
+#[test]
+fn dry_run_logs_writes_without_touching_files() {
+    let fixture = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/dry_run/deploy.infra"
+    );
+    let dir = std::env::temp_dir().join(format!("infra-dry-run-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let output = infra()
+        .args(["--dry-run", fixture])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "planned release 1.4.0\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[dry-run] would have: io.write_file path=app.conf bytes=28\n\
+         [dry-run] would have: io.write_file path=RELEASE bytes=5\n"
+    );
+    assert!(!dir.join("app.conf").exists());
+    assert!(!dir.join("RELEASE").exists());
+
+    let output = infra().arg(fixture).current_dir(&dir).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "released 1.4.0\n");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("RELEASE")).unwrap(),
+        "1.4.0"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn promise_fixture(name: &str) -> String {
+    format!(
+        "{}/tests/fixtures/promises/{}.infra",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+#[test]
+fn unhandled_rejection_fails_the_run() {
+    let output = infra().arg(promise_fixture("unhandled")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+    assert!(stderr.contains("Unhandled promise rejection: Failed to read file"));
+    assert!(stderr.contains("promise created by async.read_file at line 2"));
+
+    let output = infra()
+        .args([
+            "--allow-unhandled-rejections",
+            &promise_fixture("unhandled"),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unhandled promise rejection"));
+}
+
+#[test]
+fn handled_rejections_stay_silent() {
+    let output = infra().arg(promise_fixture("handled")).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "defaults\ncaught\n"
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn pending_promises_are_reported_at_exit() {
+    let output = infra().arg(promise_fixture("pending")).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Warning: 1 promise(s) still pending at exit (missing await?):\n  \
+         async.create_promise at line 2\n"
+    );
+}
+
+#[test]
+fn worker_exchanges_nested_values() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/worker");
+    let output = infra()
+        .arg("parent.infra")
+        .current_dir(dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "child got build\nchild\n[1, 2.5, null]\n12345678901234567891\n0\n"
+    );
+}
+
+#[test]
+fn worker_runs_in_the_parents_mode() {
+    let dir = std::env::temp_dir().join(format!("infra-worker-mode-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("child.infra"),
+        "io.write_file(\"out.txt\", worker.recv())\nworker.send(io.read_file(\"out.txt\"))\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("parent.infra"),
+        "let child = worker.spawn(\"child.infra\")\n\
+         worker.send(child, \"written\")\n\
+         print(worker.recv(child))\n\
+         worker.wait(child)\n",
+    )
+    .unwrap();
+
+    let dry = infra()
+        .args(["--dry-run", "parent.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&dry.stderr)
+        .contains("[dry-run] would have: io.write_file path=out.txt bytes=7\n"));
+    assert!(!dir.join("out.txt").exists());
+
+    let recorded = infra()
+        .args(["--record", "session.json", "parent.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(recorded.status.success(), "{:?}", recorded);
+    assert_eq!(String::from_utf8_lossy(&recorded.stdout), "written\n");
+    assert!(dir.join("session.json.worker1").exists());
+
+    std::fs::remove_file(dir.join("out.txt")).unwrap();
+    let replayed = infra()
+        .args(["--replay", "session.json", "parent.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(replayed.status.success(), "{:?}", replayed);
+    assert_eq!(replayed.stdout, recorded.stdout);
+    assert!(!dir.join("out.txt").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Copy a fixture directory so a test can edit it
+fn copy_fixture(name: &str, dest: &std::path::Path) {
+    let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let mut dirs = vec![std::path::PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        std::fs::create_dir_all(dest.join(&dir)).unwrap();
+        for entry in std::fs::read_dir(src.join(&dir)).unwrap() {
+            let entry = entry.unwrap();
+            let relative = dir.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                dirs.push(relative);
+            } else {
+                std::fs::copy(entry.path(), dest.join(&relative)).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn changed_since_last_run_reruns_only_affected_tests() {
+    let dir = std::env::temp_dir().join(format!("infra-selective-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_fixture("selective_tests", &dir);
+
+    let run = |extra: &[&str]| {
+        let output = infra()
+            .args(["--test", "--changed-since", "last-run"])
+            .args(extra)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let append = |file: &str| {
+        let path = dir.join(file);
+        let source = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, source + "# edited\n").unwrap();
+    };
+
+    // Cold cache: everything runs
+    assert_eq!(
+        run(&[]),
+        "PASS greeting_test.infra\nPASS math_test.infra\n\
+         2 passed, 0 failed, 0 skipped as unchanged\n"
+    );
+    assert_eq!(run(&[]), "0 passed, 0 failed, 2 skipped as unchanged\n");
+
+    // A helper only one test imports
+    append("helpers/greeting.infra");
+    assert_eq!(
+        run(&[]),
+        "PASS greeting_test.infra\n1 passed, 0 failed, 1 skipped as unchanged\n"
+    );
+
+    // A module both tests import, directly and through shared.infra
+    append("shared.infra");
+    assert_eq!(
+        run(&[]),
+        "PASS greeting_test.infra\nPASS math_test.infra\n\
+         2 passed, 0 failed, 0 skipped as unchanged\n"
+    );
+    append("constants.infra");
+    assert_eq!(
+        run(&[]),
+        "PASS greeting_test.infra\nPASS math_test.infra\n\
+         2 passed, 0 failed, 0 skipped as unchanged\n"
+    );
+
+    assert_eq!(
+        run(&["--all"]),
+        "PASS greeting_test.infra\nPASS math_test.infra\n\
+         2 passed, 0 failed, 0 skipped as unchanged\n"
+    );
+
+    // A corrupt cache is ignored rather than trusted
+    std::fs::write(dir.join(".infra-cache/test-deps.json"), "{").unwrap();
+    assert!(run(&[]).ends_with("2 passed, 0 failed, 0 skipped as unchanged\n"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_runner_resets_injected_faults_between_tests() {
+    let dir = std::env::temp_dir().join(format!("infra-faults-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_fixture("faults", &dir);
+
+    let output = infra().arg("--test").current_dir(&dir).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "PASS inject_test.infra\nPASS write_test.infra\n\
+         2 passed, 0 failed, 0 skipped as unchanged\n"
+    );
+    assert!(!dir.join("never-written.txt").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshots_are_created_compared_updated_and_pruned() {
+    let dir = std::env::temp_dir().join(format!("infra-snapshots-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_fixture("snapshots", &dir);
+    let test = dir.join("report_test.infra");
+    let snap = dir.join("__snapshots__/report_test.snap");
+
+    let run = |extra: &[&str]| {
+        let output = infra()
+            .arg("--test")
+            .args(extra)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    };
+    let edit = |from: &str, to: &str| {
+        let source = std::fs::read_to_string(&test).unwrap();
+        std::fs::write(&test, source.replace(from, to)).unwrap();
+    };
+
+    // First run writes the snapshots and passes
+    let (passed, stdout) = run(&[]);
+    assert!(passed, "{}", stdout);
+    assert!(stdout.ends_with(
+        "1 passed, 0 failed, 0 skipped as unchanged
+2 snapshots written
+"
+    ));
+    let stored = "# Snapshots for report_test, checked by assert.snapshot.\n\
+                  # Rewrite them with `infra --test --update-snapshots`.\n\
+                  \n\
+                  --- report_test #1\n\
+                  {\n  name: \"nightly\",\n  passed: 12,\n  tags: [\n    \"db\",\n    \"api\"\n  ]\n}\n\
+                  \n\
+                  --- report_test keys\n\
+                  [\n  \"name\",\n  \"passed\",\n  \"tags\"\n]\n";
+    assert_eq!(std::fs::read_to_string(&snap).unwrap(), stored);
+    assert_eq!(
+        run(&[]),
+        (
+            true,
+            "PASS report_test.infra\n1 passed, 0 failed, 0 skipped as unchanged\n".to_string()
+        )
+    );
+
+    // A changed value fails with a diff and leaves the file alone
+    edit("passed: 12", "passed: 13");
+    let (passed, stdout) = run(&[]);
+    assert!(!passed);
+    assert!(
+        stdout.contains("AssertionError: snapshot 'report_test #1' does not match"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("--- stored\n+++ actual\n  {\n    name: \"nightly\",\n-   passed: 12,\n+   passed: 13,\n    tags: ["), "{}", stdout);
+    assert_eq!(std::fs::read_to_string(&snap).unwrap(), stored);
+
+    let (passed, stdout) = run(&["--update-snapshots"]);
+    assert!(passed);
+    assert!(stdout.ends_with("1 snapshot written\n"), "{}", stdout);
+    assert!(std::fs::read_to_string(&snap)
+        .unwrap()
+        .contains("passed: 13,"));
+
+    // A snapshot no call asserts is reported, then removed on update
+    edit("assert.snapshot(object.keys(report), \"keys\")\n", "");
+    let (passed, stdout) = run(&[]);
+    assert!(passed);
+    assert!(stdout.ends_with(
+        "Obsolete snapshots (remove them with --update-snapshots):\n  \
+         __snapshots__/report_test.snap: report_test keys\n"
+    ));
+    assert!(std::fs::read_to_string(&snap)
+        .unwrap()
+        .contains("--- report_test keys"));
+    let (passed, stdout) = run(&["--update-snapshots"]);
+    assert!(passed);
+    assert!(stdout.ends_with(
+        "Removed obsolete snapshots:\n  __snapshots__/report_test.snap: report_test keys\n"
+    ));
+    let pruned = std::fs::read_to_string(&snap).unwrap();
+    assert!(!pruned.contains("keys"));
+    assert!(pruned.contains("--- report_test #1"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Format a fixture, check it against the expected output, then check that
+/// formatting the expected output changes nothing
+fn assert_formats_stably(name: &str) {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fmt");
+    let expected = std::fs::read_to_string(format!("{}/{}.expected.infra", dir, name)).unwrap();
+
+    for input in [
+        format!("{}.infra", name),
+        format!("{}.expected.infra", name),
+    ] {
+        let output = infra()
+            .args(["fmt", &input])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            expected,
+            "formatting {}",
+            input
+        );
+    }
+}
+
+#[test]
+fn fmt_keeps_multiline_literals_multiline() {
+    assert_formats_stably("multiline_object");
+}
+
+#[test]
+fn fmt_breaks_calls_that_exceed_the_width() {
+    assert_formats_stably("long_call");
+}
+
+#[test]
+fn fmt_keeps_comments_and_separates_functions() {
+    assert_formats_stably("comments");
+}
+
+#[test]
+fn fmt_check_fails_only_for_unformatted_files() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fmt");
+    let check = |file: &str| {
+        infra()
+            .args(["fmt", file, "--check"])
+            .current_dir(dir)
+            .output()
+            .unwrap()
+    };
+
+    let formatted = check("comments.expected.infra");
+    assert!(formatted.status.success());
+    assert!(formatted.stdout.is_empty());
+
+    let unformatted = check("comments.infra");
+    assert_eq!(unformatted.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&unformatted.stderr),
+        "comments.infra is not formatted\n"
+    );
+}
+
+#[test]
+fn fmt_collapse_joins_lists_that_fit() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fmt");
+    let output = infra()
+        .args(["fmt", "multiline_object.expected.infra", "--collapse"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "let config = {name: \"server\", port: 8080, tags: [\"a\", \"b\"]}\n\
+         let point = {x: 1, y: 2}\n\
+         print(string.join([config.name, \"ready\"], \" \"))\n"
+    );
+}
+
+/// A fresh copy of the fix fixtures, so `infra fix` can write to it
+fn fix_fixture_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("infra-fix-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_fixture("fix", &dir);
+    dir
+}
+
+fn run_fix(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = infra().args(args).current_dir(dir).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn fix_applies_every_fixable_finding() {
+    let dir = fix_fixture_dir("apply");
+
+    let check = infra()
+        .args(["check", "main.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(check.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&check.stdout),
+        "main.infra:1:16: unused import 'shout' [unused-import] (fixable)\n\
+         main.infra:3:5: variable 'greeting' is never used [unused-variable] (fixable)\n\
+         main.infra:7:7: 'totl' is not defined; did you mean 'total'? [unknown-name] (fixable)\n"
+    );
+
+    assert_eq!(
+        run_fix(&dir, &["fix", "main.infra"]),
+        "main.infra:1: fixed: remove unused import 'shout'\n\
+         main.infra:3: fixed: remove unused variable 'greeting'\n\
+         main.infra:7: fixed: rename 'totl' to 'total'\n\
+         3 fixes applied, 0 skipped\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.infra")).unwrap(),
+        "import {greet} from \"./util\"\n\
+         \n\
+         let total = 3\n\
+         \n\
+         print(greet(\"world\"))\n\
+         print(total + 1)\n"
+    );
+
+    let run = infra()
+        .arg("main.infra")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "Hello, world\n4\n");
+
+    // A second run has nothing left to do
+    assert_eq!(
+        run_fix(&dir, &["fix", "main.infra"]),
+        "0 fixes applied, 0 skipped\n"
+    );
+    let check = infra()
+        .args(["check", "main.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(check.status.success());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fix_skips_conflicting_and_unparseable_fixes() {
+    let dir = fix_fixture_dir("conflict");
+
+    assert_eq!(
+        run_fix(&dir, &["fix", "conflict.infra", "broken.infra"]),
+        "conflict.infra:2: fixed: remove unused variable 'copy'\n\
+         conflict.infra:2: skipped: rename 'totl' to 'total' \
+         (overlaps: remove unused variable 'copy')\n\
+         broken.infra:1: fixed: remove unused variable 'unused'\n\
+         broken.infra:4: skipped: remove unused variable 'later' \
+         (the file does not parse from line 3)\n\
+         2 fixes applied, 2 skipped\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("conflict.infra")).unwrap(),
+        "let total = 10\nprint(total)\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("broken.infra")).unwrap(),
+        "print(\"ok\")\nlet = 5\nlet later = 2\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fix_dry_run_prints_a_diff_without_writing() {
+    let dir = fix_fixture_dir("dry-run");
+    let original = std::fs::read_to_string(dir.join("main.infra")).unwrap();
+
+    assert_eq!(
+        run_fix(&dir, &["--dry-run", "fix", "main.infra"]),
+        "--- a/main.infra\n\
+         +++ b/main.infra\n\
+         @@ -1,7 +1,6 @@\n\
+         -import {greet, shout} from \"./util\"\n\
+         +import {greet} from \"./util\"\n \
+         \n\
+         -let greeting = \"hello\"\n \
+         let total = 3\n \
+         \n \
+         print(greet(\"world\"))\n\
+         -print(totl + 1)\n\
+         +print(total + 1)\n\
+         3 fixes would be applied, 0 skipped\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.infra")).unwrap(),
+        original
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_warns_about_variable_lifetimes_in_loops_and_blocks() {
+    let dir = std::env::temp_dir().join(format!("infra-lifetimes-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_fixture("lifetimes", &dir);
+
+    let check = infra()
+        .args(["check", "found.infra", "quiet.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    // Warnings alone do not fail the check
+    assert!(check.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&check.stdout),
+        "found.infra:8:13: warning: 'total' is declared inside the loop, so it starts again \
+         from its first value on every iteration; declare it before the loop to add up across \
+         iterations [loop-reset]\n\
+         found.infra:19:13: warning: 'let' declares a new 'best' that only lives in this block; \
+         the outer 'best' read after it keeps its value. Use 'best = ...' to change it \
+         [shadowing] (fixable)\n\
+         found.infra:27:9: warning: 'let' declares a new 'status' that only lives in this block; \
+         the outer 'status' read after it keeps its value. Use 'status = ...' to change it \
+         [shadowing] (fixable)\n\
+         found.infra:34:9: warning: 'let' declares a new 'i' that only lives in this block; \
+         the outer 'i' read after it keeps its value. Use 'i = ...' to change it \
+         [shadowing] (fixable)\n\
+         found.infra:40:9: warning: assigning to loop variable 'n' has no effect on the next \
+         iteration, which sets it again [loop-variable]\n"
+    );
+
+    // The fix turns each shadowing declaration into an assignment, which
+    // also ends the while loop
+    assert_eq!(
+        run_fix(&dir, &["fix", "found.infra"]),
+        "found.infra:19: fixed: assign to the outer 'best' instead of declaring one\n\
+         found.infra:27: fixed: assign to the outer 'status' instead of declaring one\n\
+         found.infra:34: fixed: assign to the outer 'i' instead of declaring one\n\
+         3 fixes applied, 0 skipped\n"
+    );
+    let fixed = std::fs::read_to_string(dir.join("found.infra")).unwrap();
+    assert!(fixed.contains("\n        best = price\n"));
+    assert!(fixed.contains("\n    i = i + 1\n"));
+    let run = infra()
+        .arg("found.infra")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "5\nrunning\n0\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A deploy report that reads files and runs a command, standing in for a
+/// script that talks to live systems
+fn record_fixture(dir: &std::path::Path) -> String {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join("hosts.txt"), "web-1\nweb-2\n").unwrap();
+    format!(
+        "let hosts = io.read_lines(\"hosts.txt\")\n\
+         let status = process.exec({:?}, [\"-e\", \"6 * 7\"])\n\
+         print(\"hosts: \" + array.join(hosts, \", \"))\n\
+         print(\"healthy: \" + string.trim(status.stdout))\n\
+         print(io.exists(\"maintenance\"))\n\
+         io.write_file(\"report.txt\", hosts[0])\n",
+        env!("CARGO_BIN_EXE_infra")
+    )
+}
+
+#[test]
+fn replay_reproduces_a_recorded_run_without_the_world() {
+    let dir = std::env::temp_dir().join(format!("infra-record-{}", std::process::id()));
+    let script = record_fixture(&dir);
+    std::fs::write(dir.join("report.infra"), &script).unwrap();
+
+    let recorded = infra()
+        .args(["--record", "session.json", "report.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(recorded.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&recorded.stdout),
+        "hosts: web-1, web-2\nhealthy: 42\nfalse\n"
+    );
+
+    // The inputs are gone and the write is not repeated
+    std::fs::remove_file(dir.join("hosts.txt")).unwrap();
+    std::fs::remove_file(dir.join("report.txt")).unwrap();
+    let replayed = infra()
+        .args(["--replay", "session.json", "report.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(replayed.status.success());
+    assert_eq!(replayed.stdout, recorded.stdout);
+    assert!(replayed.stderr.is_empty());
+    assert!(!dir.join("report.txt").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replay_makes_no_changes_to_files() {
+    let dir = std::env::temp_dir().join(format!("infra-replay-writes-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("log.txt"), "start\n").unwrap();
+    std::fs::write(dir.join("stale.txt"), "old").unwrap();
+    std::fs::write(
+        dir.join("cleanup.infra"),
+        "io.append_file(\"log.txt\", \"cleaned\\n\")\n\
+         io.delete_file(\"stale.txt\")\n\
+         io.mkdir(\"archive\")\n\
+         print(array.sort(io.list_dir(\".\")))\n",
+    )
+    .unwrap();
+
+    let recorded = infra()
+        .args(["--record", "session.json", "cleanup.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(recorded.status.success(), "{:?}", recorded);
+
+    // Put the files back as they were before the recorded run
+    std::fs::write(dir.join("log.txt"), "start\n").unwrap();
+    std::fs::write(dir.join("stale.txt"), "old").unwrap();
+    std::fs::remove_dir(dir.join("archive")).unwrap();
+    let replayed = infra()
+        .args(["--replay", "session.json", "cleanup.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(replayed.status.success(), "{:?}", replayed);
+    assert_eq!(replayed.stdout, recorded.stdout);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("log.txt")).unwrap(),
+        "start\n"
+    );
+    assert!(dir.join("stale.txt").exists());
+    assert!(!dir.join("archive").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replay_answers_http_requests_from_the_recording() {
+    use std::io::{BufRead, BufReader};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // Answer the one request the recorded run makes, then stop listening
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\nhealthy")
+            .unwrap();
+    });
+
+    let dir = std::env::temp_dir().join(format!("infra-replay-http-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("probe.infra"),
+        format!(
+            "let health = await async.http_get(\"http://127.0.0.1:{}/health\")\n\
+             print(health.status + \" \" + health.body)\n\
+             try:\n    await async.http_post(\"nope://deploy\", \"go\")\n\
+             catch e:\n    print(e.message)\n",
+            port
+        ),
+    )
+    .unwrap();
+
+    let recorded = infra()
+        .args(["--record", "session.json", "probe.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(recorded.status.success(), "{:?}", recorded);
+    server.join().unwrap();
+    assert!(String::from_utf8_lossy(&recorded.stdout).starts_with("200 healthy\n"));
+
+    // Nothing is listening now, so only the recording can answer
+    let replayed = infra()
+        .args(["--replay", "session.json", "probe.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(replayed.status.success(), "{:?}", replayed);
+    assert_eq!(replayed.stdout, recorded.stdout);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replay_reports_where_a_changed_script_diverges() {
+    let dir = std::env::temp_dir().join(format!("infra-diverge-{}", std::process::id()));
+    let script = record_fixture(&dir);
+    std::fs::write(dir.join("report.infra"), &script).unwrap();
+    let recorded = infra()
+        .args(["--record", "session.json", "report.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(recorded.status.success());
+
+    let changed = script.replace("\"maintenance\"", "\"drain\"");
+    std::fs::write(dir.join("report.infra"), changed).unwrap();
+    for matching in ["order", "key"] {
+        let output = infra()
+            .args(["--replay-match", matching, "--replay", "session.json"])
+            .arg("report.infra")
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(
+                "Replay of 'session.json' diverged at call 3: io.exists(\"drain\") \
+                 has different arguments than recorded\n  argument 1:\n  \
+                 - \"maintenance\"\n  + \"drain\""
+            ),
+            "{}",
+            stderr
+        );
+    }
+
+    // A call the recording never saw
+    std::fs::write(
+        dir.join("report.infra"),
+        script + "let extra = io.read_file(\"hosts.txt\")\n",
+    )
+    .unwrap();
+    let output = infra()
+        .args(["--replay", "session.json", "report.infra"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "diverged at call 5: io.read_file(\"hosts.txt\") was not recorded; \
+         the recording ends after 4 calls"
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn vm_fixture(name: &str) -> String {
+    format!(
+        "{}/tests/fixtures/vm/{}.infra",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+#[test]
+fn vm_flag_prints_what_the_interpreter_prints() {
+    for name in ["loops", "scopes", "function"] {
+        let interpreted = infra().arg(vm_fixture(name)).output().unwrap();
+        for flag in ["--vm", "--opt"] {
+            let compiled = infra().args([flag, &vm_fixture(name)]).output().unwrap();
+
+            assert!(
+                compiled.status.success(),
+                "{}",
+                String::from_utf8_lossy(&compiled.stderr)
+            );
+            assert_eq!(
+                compiled.stdout, interpreted.stdout,
+                "running {} with {}",
+                name, flag
+            );
+        }
+    }
+
+    let output = infra()
+        .args(["--vm", &vm_fixture("unsupported")])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("[line 2]: Calling 'len' is not supported in VM mode yet"));
+}
+
+#[test]
+fn vm_reports_runaway_recursion_as_stack_overflow() {
+    let output = infra()
+        .args(["--vm", &vm_fixture("runaway")])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains("[line 2]: Stack overflow: more than 10000 nested calls"));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn vm_errors_point_at_the_line_that_failed() {
+    for flag in ["--vm", "--opt"] {
+        let output = infra()
+            .args([flag, &vm_fixture("type_error")])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "over\n");
+        assert!(
+            stderr.contains("[line 6]: Can only compare numbers"),
+            "{} gave {}",
+            flag,
+            stderr
+        );
+        assert!(stderr.contains("  6 |         if limit < count: print(\"over\")"));
+    }
+}
+
+#[test]
+fn dump_bytecode_lists_instructions_without_running() {
+    let output = infra()
+        .args(["--dump-bytecode", &vm_fixture("loops")])
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(listing.starts_with("== "));
+    assert!(
+        listing.contains("0000    1 LOAD_CONST          0 (10)\n0001    | STORE_VAR           0\n")
+    );
+    assert!(listing.contains("| JUMP_IF_FALSE      17\n"));
+    assert!(listing.contains("LOAD_CONST          4 (\"sum \")\n"));
+    assert!(listing.ends_with("| HALT\n"));
+    assert!(!listing.contains("sum 55"));
+}
@@ -0,0 +1,81 @@
+//! Embedding the interpreter through the library's `Engine`.
+//!
+//!     cargo test --features stdlib --test engine
+
+use infra::core::{InfraError, Result, Value};
+use infra::stdlib::sources::{ClockSource, EntropySource, ManualClock};
+use infra::Engine;
+use std::cell::Cell;
+
+/// 2026-01-01T00:00:00Z
+const START: u64 = 1_767_225_600_000;
+
+fn user_id(args: &[Value]) -> Result<Value> {
+    match args {
+        [Value::String(name)] => Ok(Value::Number(name.len() as f64)),
+        _ => Err(InfraError::Generic("user_id takes a name".to_string())),
+    }
+}
+
+/// Bytes counting up from zero
+struct Counting(Cell<u8>);
+
+impl EntropySource for Counting {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        for byte in buf {
+            *byte = self.0.get();
+            self.0.set(self.0.get().wrapping_add(1));
+        }
+    }
+}
+
+#[test]
+fn a_host_registers_functions_and_evaluates_code() {
+    let mut engine = Engine::builder()
+        .with_native("host", "user_id", user_id)
+        .build();
+
+    engine.execute("let id = host.user_id(\"ada\")").unwrap();
+    assert_eq!(engine.evaluate("id * 2").unwrap(), Value::Number(6.0));
+
+    assert!(engine.unregister_native("host", "user_id"));
+    assert!(engine.evaluate("host.user_id(\"ada\")").is_err());
+}
+
+#[test]
+fn a_manual_clock_runs_jobs_only_when_the_host_moves_it() {
+    let clock = ManualClock::new(START);
+    let mut engine = Engine::builder().with_clock(clock.clone()).build();
+    engine
+        .execute(
+            "let ticks = 0\n\
+             function tick(): ticks = ticks + 1\n\
+             schedule.every(1000, tick)\n",
+        )
+        .unwrap();
+
+    assert_eq!(
+        engine.evaluate("schedule.now()").unwrap(),
+        Value::Number(START as f64)
+    );
+    engine.advance_clock(2500).unwrap();
+    assert_eq!(engine.evaluate("ticks").unwrap(), Value::Number(2.0));
+    assert_eq!(clock.now_ms(), START + 2500);
+}
+
+#[test]
+fn an_entropy_source_makes_temporary_names_predictable() {
+    let mut engine = Engine::builder()
+        .with_entropy(Counting(Cell::new(0)))
+        .build();
+    let path = engine
+        .evaluate("io.temp_dir(\"embed-\")")
+        .unwrap()
+        .to_string();
+    std::fs::remove_dir(&path).unwrap();
+
+    // `embed-<pid>-<random>-<count>`, the random part from the high half of
+    // the little-endian word made of bytes 0 to 7
+    let name = path.rsplit(std::path::MAIN_SEPARATOR).next().unwrap();
+    assert_eq!(name.split('-').nth(2), Some("117835012"));
+}